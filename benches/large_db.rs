@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use deltachat::chat;
+use deltachat::chatlist::Chatlist;
+use deltachat::context::Context;
+use deltachat::stock_str::StockStrings;
+use deltachat::synthetic_db::{self, SyntheticDb};
+use deltachat::Events;
+use tempfile::tempdir;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    // Unlike the other benchmarks, this one does not need an existing database,
+    // a database with 100k messages is generated instead.
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempdir().unwrap();
+    let dbfile = dir.path().join("db.sqlite");
+    let context = rt.block_on(async {
+        let context = Context::new(&dbfile, 100, Events::new(), StockStrings::new())
+            .await
+            .unwrap();
+        synthetic_db::populate(&context, &SyntheticDb::default())
+            .await
+            .unwrap();
+        context
+    });
+    let chat_id = rt.block_on(async {
+        Chatlist::try_load(&context, 0, None, None)
+            .await
+            .unwrap()
+            .get_chat_id(0)
+            .unwrap()
+    });
+
+    let mut group = c.benchmark_group("Large database");
+    group.bench_function("chatlist:try_load (Get Chatlist)", |b| {
+        b.to_async(&rt).iter(|| async {
+            Chatlist::try_load(black_box(&context), 0, None, None)
+                .await
+                .unwrap()
+        })
+    });
+    group.bench_function("chat::get_chat_msgs (Load messages from 1 chat)", |b| {
+        b.to_async(&rt).iter(|| async {
+            chat::get_chat_msgs(black_box(&context), black_box(chat_id))
+                .await
+                .unwrap()
+        })
+    });
+    group.bench_function("context.search_msgs (Search all messages)", |b| {
+        b.to_async(&rt)
+            .iter(|| async { context.search_msgs(None, black_box("lorem")).await.unwrap() })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
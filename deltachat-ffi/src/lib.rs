@@ -25,15 +25,15 @@
 use anyhow::Context as _;
 use deltachat::chat::{ChatId, ChatVisibility, MessageListOptions, MuteDuration, ProtectionStatus};
 use deltachat::constants::DC_MSG_ID_LAST_SPECIAL;
-use deltachat::contact::{Contact, ContactId, Origin};
+use deltachat::contact::{Contact, ContactId, Origin, RemoteContentDecision};
 use deltachat::context::Context;
 use deltachat::ephemeral::Timer as EphemeralTimer;
 use deltachat::imex::BackupProvider;
 use deltachat::key::preconfigure_keypair;
 use deltachat::message::MsgId;
-use deltachat::net::read_url_blob;
+use deltachat::net::{read_url_blob, SystemProxy};
 use deltachat::qr_code_generator::{generate_backup_qr, get_securejoin_qr_svg};
-use deltachat::reaction::{get_msg_reactions, send_reaction, Reactions};
+use deltachat::reaction::{add_reaction, get_msg_reactions, send_reaction, Reactions};
 use deltachat::stock_str::StockMessage;
 use deltachat::stock_str::StockStrings;
 use deltachat::webxdc::StatusUpdateSerial;
@@ -65,6 +65,12 @@
 const DC_GCM_ADDDAYMARKER: u32 = 0x01;
 const DC_GCM_INFO_ONLY: u32 = 0x02;
 
+/// Version of the C interface, must match `DC_ABI_VERSION` in `deltachat.h`.
+///
+/// Increased whenever functions, constants or events are added,
+/// so bindings can detect a library that is older than their header.
+const DC_ABI_VERSION: libc::c_int = 3;
+
 // dc_context_t
 
 /// Struct representing the deltachat context.
@@ -329,6 +335,27 @@ fn spawn<T>(fut: T) -> JoinHandle<T::Output>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_stock_translations(
+    context: *mut dc_context_t,
+    json: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || json.is_null() {
+        eprintln!("ignoring careless call to dc_set_stock_translations()");
+        return 0;
+    }
+    let ctx = &*context;
+    let json = to_string_lossy(json);
+
+    block_on(async move {
+        let translations = serde_json::from_str(&json).context("Invalid JSON")?;
+        ctx.set_stock_translations(translations).await
+    })
+    .context("Failed to set stock translations")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_config_from_qr(
     context: *mut dc_context_t,
@@ -447,6 +474,118 @@ fn render_info(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_complete_oauth2_flow(
+    context: *mut dc_context_t,
+    addr: *const libc::c_char,
+    code: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || addr.is_null() || code.is_null() {
+        eprintln!("ignoring careless call to dc_complete_oauth2_flow()");
+        return 0;
+    }
+    let ctx = &*context;
+    let addr = to_string_lossy(addr);
+    let code = to_string_lossy(code);
+
+    block_on(oauth2::complete_oauth2_flow(ctx, &addr, &code))
+        .context("dc_complete_oauth2_flow failed")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_change_password(
+    context: *mut dc_context_t,
+    password: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || password.is_null() {
+        eprintln!("ignoring careless call to dc_change_password()");
+        return 0;
+    }
+    let ctx = &*context;
+    let password = to_string_lossy(password);
+
+    block_on(ctx.change_password(&password))
+        .context("dc_change_password failed")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_export_provisioning(
+    context: *mut dc_context_t,
+    passphrase: *const libc::c_char,
+    include_secret_key: libc::c_int,
+) -> *mut libc::c_char {
+    if context.is_null() || passphrase.is_null() {
+        eprintln!("ignoring careless call to dc_export_provisioning()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let passphrase = to_string_lossy(passphrase);
+
+    match block_on(imex::export_provisioning(
+        ctx,
+        &passphrase,
+        include_secret_key != 0,
+    ))
+    .context("dc_export_provisioning failed")
+    .log_err(ctx)
+    {
+        Ok(blob) => blob.strdup(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_import_provisioning(
+    context: *mut dc_context_t,
+    blob: *const libc::c_char,
+    passphrase: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || blob.is_null() || passphrase.is_null() {
+        eprintln!("ignoring careless call to dc_import_provisioning()");
+        return 0;
+    }
+    let ctx = &*context;
+    let blob = to_string_lossy(blob);
+    let passphrase = to_string_lossy(passphrase);
+
+    block_on(imex::import_provisioning(ctx, &blob, &passphrase))
+        .context("dc_import_provisioning failed")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_import_eml_file(
+    context: *mut dc_context_t,
+    path: *const libc::c_char,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() || path.is_null() {
+        eprintln!("ignoring careless call to dc_import_eml_file()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    match block_on(imex::receive_eml_file(ctx, as_path(path)))
+        .context("dc_import_eml_file failed")
+        .log_err(ctx)
+    {
+        Ok(msg_ids) => {
+            let arr = dc_array_t::from(
+                msg_ids
+                    .iter()
+                    .map(|msg_id| msg_id.to_u32())
+                    .collect::<Vec<u32>>(),
+            );
+            Box::into_raw(Box::new(arr))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
 fn spawn_configure(ctx: Context) {
     spawn(async move {
         ctx.configure()
@@ -549,6 +688,7 @@ fn spawn_configure(ctx: Context) {
         EventType::ChatModified(_) => 2020,
         EventType::ChatEphemeralTimerModified { .. } => 2021,
         EventType::ContactsChanged(_) => 2030,
+        EventType::ContactsImportProgress(_) => 2031,
         EventType::LocationChanged(_) => 2035,
         EventType::ConfigureProgress { .. } => 2041,
         EventType::ImexProgress(_) => 2051,
@@ -557,8 +697,16 @@ fn spawn_configure(ctx: Context) {
         EventType::SecurejoinJoinerProgress { .. } => 2061,
         EventType::ConnectivityChanged => 2100,
         EventType::SelfavatarChanged => 2110,
+        EventType::ConfigChanged(_) => 2111,
         EventType::WebxdcStatusUpdate { .. } => 2120,
         EventType::WebxdcInstanceDeleted { .. } => 2121,
+        EventType::WebxdcRealtimeData { .. } => 2122,
+        EventType::RemoteContentBlocked { .. } => 2123,
+        EventType::BackgroundFetchDone => 2210,
+        EventType::FetchProgress { .. } => 2211,
+        EventType::HistoryImportProgress(_) => 2212,
+        EventType::JobFailed { .. } => 2200,
+        EventType::DatabaseRepaired { .. } => 2213,
     }
 }
 
@@ -584,7 +732,9 @@ fn spawn_configure(ctx: Context) {
         | EventType::Error(_)
         | EventType::ConnectivityChanged
         | EventType::SelfavatarChanged
+        | EventType::ConfigChanged(_)
         | EventType::IncomingMsgBunch { .. }
+        | EventType::BackgroundFetchDone
         | EventType::ErrorSelfNotInGroup(_) => 0,
         EventType::MsgsChanged { chat_id, .. }
         | EventType::ReactionsChanged { chat_id, .. }
@@ -600,16 +750,26 @@ fn spawn_configure(ctx: Context) {
             let id = id.unwrap_or_default();
             id.to_u32() as libc::c_int
         }
-        EventType::ConfigureProgress { progress, .. } | EventType::ImexProgress(progress) => {
-            *progress as libc::c_int
-        }
+        EventType::ConfigureProgress { progress, .. }
+        | EventType::ImexProgress(progress)
+        | EventType::ContactsImportProgress(progress)
+        | EventType::HistoryImportProgress(progress) => *progress as libc::c_int,
+        EventType::FetchProgress { fetched, .. } => *fetched as libc::c_int,
         EventType::ImexFileWritten(_) => 0,
         EventType::SecurejoinInviterProgress { contact_id, .. }
         | EventType::SecurejoinJoinerProgress { contact_id, .. } => {
             contact_id.to_u32() as libc::c_int
         }
         EventType::WebxdcStatusUpdate { msg_id, .. } => msg_id.to_u32() as libc::c_int,
-        EventType::WebxdcInstanceDeleted { msg_id, .. } => msg_id.to_u32() as libc::c_int,
+        EventType::WebxdcInstanceDeleted { msg_id, .. }
+        | EventType::WebxdcRealtimeData { msg_id, .. }
+        | EventType::RemoteContentBlocked { msg_id, .. }
+        | EventType::JobFailed { msg_id, .. } => msg_id.to_u32() as libc::c_int,
+        EventType::DatabaseRepaired {
+            missing_files,
+            chats,
+            orphans,
+        } => (missing_files + chats + orphans) as libc::c_int,
     }
 }
 
@@ -636,6 +796,8 @@ fn spawn_configure(ctx: Context) {
         | EventType::Error(_)
         | EventType::ErrorSelfNotInGroup(_)
         | EventType::ContactsChanged(_)
+        | EventType::ContactsImportProgress(_)
+        | EventType::HistoryImportProgress(_)
         | EventType::LocationChanged(_)
         | EventType::ConfigureProgress { .. }
         | EventType::ImexProgress(_)
@@ -644,7 +806,11 @@ fn spawn_configure(ctx: Context) {
         | EventType::ConnectivityChanged
         | EventType::WebxdcInstanceDeleted { .. }
         | EventType::IncomingMsgBunch { .. }
-        | EventType::SelfavatarChanged => 0,
+        | EventType::SelfavatarChanged
+        | EventType::ConfigChanged(_)
+        | EventType::BackgroundFetchDone
+        | EventType::JobFailed { .. }
+        | EventType::DatabaseRepaired { .. } => 0,
         EventType::ChatModified(_) => 0,
         EventType::MsgsChanged { msg_id, .. }
         | EventType::ReactionsChanged { msg_id, .. }
@@ -656,6 +822,9 @@ fn spawn_configure(ctx: Context) {
         EventType::SecurejoinInviterProgress { progress, .. }
         | EventType::SecurejoinJoinerProgress { progress, .. } => *progress as libc::c_int,
         EventType::ChatEphemeralTimerModified { timer, .. } => timer.to_u32() as libc::c_int,
+        EventType::FetchProgress { total, .. } => *total as libc::c_int,
+        EventType::WebxdcRealtimeData { data, .. } => data.len() as libc::c_int,
+        EventType::RemoteContentBlocked { contact_id, .. } => contact_id.to_u32() as libc::c_int,
         EventType::WebxdcStatusUpdate {
             status_update_serial,
             ..
@@ -698,6 +867,9 @@ fn spawn_configure(ctx: Context) {
         | EventType::MsgDeleted { .. }
         | EventType::ChatModified(_)
         | EventType::ContactsChanged(_)
+        | EventType::ContactsImportProgress(_)
+        | EventType::FetchProgress { .. }
+        | EventType::HistoryImportProgress(_)
         | EventType::LocationChanged(_)
         | EventType::ImexProgress(_)
         | EventType::SecurejoinInviterProgress { .. }
@@ -706,6 +878,9 @@ fn spawn_configure(ctx: Context) {
         | EventType::SelfavatarChanged
         | EventType::WebxdcStatusUpdate { .. }
         | EventType::WebxdcInstanceDeleted { .. }
+        | EventType::RemoteContentBlocked { .. }
+        | EventType::BackgroundFetchDone
+        | EventType::DatabaseRepaired { .. }
         | EventType::ChatEphemeralTimerModified { .. } => ptr::null_mut(),
         EventType::ConfigureProgress { comment, .. } => {
             if let Some(comment) = comment {
@@ -718,6 +893,14 @@ fn spawn_configure(ctx: Context) {
             let data2 = file.to_c_string().unwrap_or_default();
             data2.into_raw()
         }
+        EventType::JobFailed { error, .. } => error.to_c_string().unwrap_or_default().into_raw(),
+        EventType::WebxdcRealtimeData { data, .. } => {
+            // Binary data, the size is returned by dc_event_get_data2_int().
+            let ptr = libc::malloc(data.len());
+            libc::memcpy(ptr, data.as_ptr() as *mut libc::c_void, data.len());
+            ptr as *mut libc::c_char
+        }
+        EventType::ConfigChanged(key) => key.as_ref().to_c_string().unwrap_or_default().into_raw(),
         EventType::IncomingMsgBunch { msg_ids } => serde_json::to_string(msg_ids)
             .unwrap_or_default()
             .to_c_string()
@@ -736,6 +919,37 @@ fn spawn_configure(ctx: Context) {
     (*event).id
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_event_get_json(event: *mut dc_event_t) -> *mut libc::c_char {
+    if event.is_null() {
+        eprintln!("ignoring careless call to dc_event_get_json()");
+        return "".strdup();
+    }
+
+    // Binary data2 is not a string, it is contained in the serialized event.
+    let data2_str = if matches!((*event).typ, EventType::WebxdcRealtimeData { .. }) {
+        ptr::null_mut()
+    } else {
+        dc_event_get_data2_str(event)
+    };
+    let data2 = if data2_str.is_null() {
+        serde_json::Value::from(dc_event_get_data2_int(event))
+    } else {
+        let data2 = to_string_lossy(data2_str);
+        dc_str_unref(data2_str);
+        serde_json::Value::from(data2)
+    };
+    serde_json::json!({
+        "id": dc_event_get_id(event),
+        "account_id": dc_event_get_account_id(event),
+        "data1": dc_event_get_data1_int(event),
+        "data2": data2,
+        "event": serde_json::to_value(&(*event).typ).unwrap_or_default(),
+    })
+    .to_string()
+    .strdup()
+}
+
 pub type dc_event_emitter_t = EventEmitter;
 
 #[no_mangle]
@@ -801,6 +1015,167 @@ fn spawn_configure(ctx: Context) {
     block_on(async move { ctx.maybe_network().await })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_background_fetch(
+    context: *mut dc_context_t,
+    timeout_seconds: u64,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_background_fetch()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(ctx.background_fetch(Duration::from_secs(timeout_seconds)))
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_push_device_token(
+    context: *mut dc_context_t,
+    token: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_push_device_token()");
+        return 0;
+    }
+    let ctx = &*context;
+    let token = to_opt_string_lossy(token).unwrap_or_default();
+
+    block_on(ctx.set_push_device_token(&token))
+        .context("Failed to set push device token")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+unsafe fn to_system_proxy(
+    host: *const libc::c_char,
+    port: libc::c_int,
+    user: *const libc::c_char,
+    password: *const libc::c_char,
+) -> Option<SystemProxy> {
+    let host = to_opt_string_lossy(host).filter(|host| !host.is_empty())?;
+    let user_password = to_opt_string_lossy(user)
+        .filter(|user| !user.is_empty())
+        .map(|user| (user, to_opt_string_lossy(password).unwrap_or_default()));
+    Some(SystemProxy {
+        host,
+        port: u16::try_from(port).unwrap_or_default(),
+        user_password,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_system_proxy(
+    context: *mut dc_context_t,
+    host: *const libc::c_char,
+    port: libc::c_int,
+    user: *const libc::c_char,
+    password: *const libc::c_char,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_system_proxy()");
+        return;
+    }
+    let ctx = &*context;
+    ctx.set_system_proxy(to_system_proxy(host, port, user, password));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_extra_folder_watched(
+    context: *mut dc_context_t,
+    folder: *const libc::c_char,
+    watched: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() || folder.is_null() {
+        eprintln!("ignoring careless call to dc_set_extra_folder_watched()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(ctx.set_extra_folder_watched(&to_string_lossy(folder), watched != 0))
+        .context("Failed to set additional folder")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub extern "C" fn dc_get_abi_version() -> libc::c_int {
+    DC_ABI_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_metrics_json(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_metrics_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    ctx.get_metrics_json()
+        .log_err(ctx)
+        .unwrap_or_default()
+        .strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_quota_usage_percentage(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_quota_usage_percentage()");
+        return -1;
+    }
+    let ctx = &*context;
+
+    block_on(ctx.get_quota_usage_percentage())
+        .and_then(|percentage| libc::c_int::try_from(percentage).ok())
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_reset_metrics(context: *mut dc_context_t) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_reset_metrics()");
+        return;
+    }
+    let ctx = &*context;
+
+    ctx.reset_metrics()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_last_log_lines(
+    context: *mut dc_context_t,
+    count: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_last_log_lines()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    ctx.get_last_log_lines(count as usize).join("\n").strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_pending_jobs_json(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_pending_jobs_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match ctx.list_pending_jobs().await {
+            Ok(jobs) => serde_json::to_string(&jobs).unwrap_or_default().strdup(),
+            Err(err) => {
+                error!(ctx, "Failed to list pending jobs: {err:#}");
+                "".strdup()
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_preconfigure_keypair(
     context: *mut dc_context_t,
@@ -1022,6 +1397,26 @@ fn spawn_configure(ctx: Context) {
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_add_reaction(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    reaction: *const libc::c_char,
+) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_add_reaction()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        add_reaction(ctx, MsgId::new(msg_id), &to_string_lossy(reaction))
+            .await
+            .map(|msg_id| msg_id.to_u32())
+            .unwrap_or_log_default(ctx, "Failed to add reaction")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_reactions(
     context: *mut dc_context_t,
@@ -1088,6 +1483,71 @@ fn spawn_configure(ctx: Context) {
     .strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_send_webxdc_realtime_advertisement(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_send_webxdc_realtime_advertisement()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(peer_channels::send_webxdc_realtime_advertisement(
+        ctx,
+        MsgId::new(msg_id),
+    ))
+    .context("Failed to send realtime advertisement")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_send_webxdc_realtime_data(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    data: *const u8,
+    size: libc::size_t,
+) -> libc::c_int {
+    if context.is_null() || (data.is_null() && size > 0) {
+        eprintln!("ignoring careless call to dc_send_webxdc_realtime_data()");
+        return 0;
+    }
+    let ctx = &*context;
+    let data = if size > 0 {
+        std::slice::from_raw_parts(data, size).to_vec()
+    } else {
+        Vec::new()
+    };
+
+    block_on(peer_channels::send_webxdc_realtime_data(
+        ctx,
+        MsgId::new(msg_id),
+        data,
+    ))
+    .context("Failed to send realtime data")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_leave_webxdc_realtime(context: *mut dc_context_t, msg_id: u32) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_leave_webxdc_realtime()");
+        return;
+    }
+    let ctx = &*context;
+
+    block_on(peer_channels::leave_webxdc_realtime(
+        ctx,
+        MsgId::new(msg_id),
+    ))
+    .context("Failed to leave realtime channel")
+    .log_err(ctx)
+    .ok();
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_draft(
     context: *mut dc_context_t,
@@ -1318,6 +1778,20 @@ fn spawn_configure(ctx: Context) {
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_fresh_msg_count(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_fresh_msg_count()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(ctx.get_fresh_msg_count())
+        .context("Failed to get fresh message count")
+        .log_err(ctx)
+        .unwrap_or_default() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_next_msgs(context: *mut dc_context_t) -> *mut dc_array::dc_array_t {
     if context.is_null() {
@@ -1421,6 +1895,41 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_export_chat_transcript(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    html: libc::c_int,
+    timestamp_start: i64,
+    timestamp_end: i64,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || path.is_null() {
+        eprintln!("ignoring careless call to dc_export_chat_transcript()");
+        return 0;
+    }
+    let ctx = &*context;
+    let format = if html != 0 {
+        chat::TranscriptFormat::Html
+    } else {
+        chat::TranscriptFormat::Text
+    };
+    let range = if timestamp_start == 0 && timestamp_end == 0 {
+        None
+    } else {
+        Some(timestamp_start..=timestamp_end)
+    };
+    let path = to_string_lossy(path);
+
+    block_on(async move {
+        let mut file = tokio::fs::File::create(&path).await?;
+        chat::export_transcript(ctx, ChatId::new(chat_id), format, range, &mut file).await
+    })
+    .context("Failed to export chat transcript")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 #[allow(deprecated)]
 pub unsafe extern "C" fn dc_get_next_media(
@@ -1551,6 +2060,24 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_mark_chat_not_spam(context: *mut dc_context_t, chat_id: u32) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_mark_chat_not_spam()");
+        return;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .mark_not_spam(ctx)
+            .await
+            .context("Failed to mark chat as not spam")
+            .log_err(ctx)
+            .ok();
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_chat_contacts(
     context: *mut dc_context_t,
@@ -1575,6 +2102,30 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_admins(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_admins()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let arr = dc_array_t::from(
+            chat::get_chat_admins(ctx, ChatId::new(chat_id))
+                .await
+                .unwrap_or_log_default(ctx, "Failed get_chat_admins")
+                .iter()
+                .map(|id| id.to_u32())
+                .collect::<Vec<u32>>(),
+        );
+        Box::into_raw(Box::new(arr))
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_search_msgs(
     context: *mut dc_context_t,
@@ -1605,6 +2156,54 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn dc_search_msgs_ex(
+    context: *mut dc_context_t,
+    query: *const libc::c_char,
+    chat_id: u32,
+    contact_id: u32,
+    timestamp_after: i64,
+    timestamp_before: i64,
+    msg_type: libc::c_int,
+    has_attachment: libc::c_int,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_search_msgs_ex()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let filter = context::SearchFilter {
+        chat_id: Some(ChatId::new(chat_id)).filter(|chat_id| !chat_id.is_unset()),
+        from_id: if contact_id == 0 {
+            None
+        } else {
+            Some(ContactId::new(contact_id))
+        },
+        after: Some(timestamp_after).filter(|timestamp| *timestamp != 0),
+        before: Some(timestamp_before).filter(|timestamp| *timestamp != 0),
+        viewtype: if msg_type == 0 {
+            None
+        } else {
+            Some(from_prim(msg_type).expect(&format!("invalid msg_type = {msg_type}")))
+        },
+        has_attachment: has_attachment != 0,
+    };
+    let query = to_opt_string_lossy(query).unwrap_or_default();
+
+    block_on(async move {
+        let arr = dc_array_t::from(
+            ctx.search_msgs_ex(&query, &filter)
+                .await
+                .unwrap_or_log_default(ctx, "Failed search_msgs_ex")
+                .iter()
+                .map(|msg_id| msg_id.to_u32())
+                .collect::<Vec<u32>>(),
+        );
+        Box::into_raw(Box::new(arr))
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_chat(context: *mut dc_context_t, chat_id: u32) -> *mut dc_chat_t {
     if context.is_null() {
@@ -1652,6 +2251,23 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_create_channel(
+    context: *mut dc_context_t,
+    name: *const libc::c_char,
+) -> u32 {
+    if context.is_null() || name.is_null() {
+        eprintln!("ignoring careless call to dc_create_channel()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(chat::create_channel(ctx, &to_string_lossy(name)))
+        .context("Failed to create channel")
+        .log_err(ctx)
+        .map(|id| id.to_u32())
+        .unwrap_or(0)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_create_broadcast_list(context: *mut dc_context_t) -> u32 {
     if context.is_null() {
@@ -1732,6 +2348,30 @@ fn from_prim<S, T>(s: S) -> Option<T>
     .is_ok() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_admin(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    contact_id: u32,
+    admin: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_admin()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(chat::set_chat_admin(
+        ctx,
+        ChatId::new(chat_id),
+        ContactId::new(contact_id),
+        admin != 0,
+    ))
+    .context("Failed to set chat admin")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_chat_name(
     context: *mut dc_context_t,
@@ -1753,6 +2393,25 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_unsubscribe_chat(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> libc::c_int {
+    if context.is_null() || chat_id <= constants::DC_CHAT_ID_LAST_SPECIAL.to_u32() {
+        eprintln!("ignoring careless call to dc_unsubscribe_chat()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::unsubscribe(ctx, ChatId::new(chat_id))
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(ctx, "Failed to unsubscribe")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_chat_profile_image(
     context: *mut dc_context_t,
@@ -1831,50 +2490,170 @@ fn from_prim<S, T>(s: S) -> Option<T>
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_get_chat_ephemeral_timer(
+pub unsafe extern "C" fn dc_get_chat_encryption_info_json(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_encryption_info_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let chat = match chat::Chat::load_from_db(ctx, ChatId::new(chat_id)).await {
+            Ok(chat) => chat,
+            Err(err) => {
+                error!(
+                    ctx,
+                    "dc_get_chat_encryption_info_json() failed to load chat: {err:#}"
+                );
+                return ptr::null_mut();
+            }
+        };
+        let info = match chat.get_encryption_info(ctx).await {
+            Ok(info) => info,
+            Err(err) => {
+                error!(ctx, "dc_get_chat_encryption_info_json() failed: {err:#}");
+                return ptr::null_mut();
+            }
+        };
+        let members: Vec<_> = info
+            .members
+            .into_iter()
+            .map(|member| {
+                serde_json::json!({
+                    "contact_id": member.contact_id.to_u32(),
+                    "addr": member.addr,
+                    "fingerprint": member.fingerprint,
+                    "verified": member.verified,
+                    "prefer_encrypt": member.prefer_encrypt.to_string(),
+                })
+            })
+            .collect();
+        let encryption = match info.encryption {
+            chat::ChatEncryption::Yes => "yes",
+            chat::ChatEncryption::Partially => "partially",
+            chat::ChatEncryption::No => "no",
+        };
+        serde_json::json!({
+            "members": members,
+            "encryption": encryption,
+        })
+        .to_string()
+        .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ephemeral_timer(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ephemeral_timer()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    // Timer value 0 is returned in the rare case of a database error,
+    // but it is not dangerous since it is only meant to be used as a
+    // default when changing the value. Such errors should not be
+    // ignored when ephemeral timer value is used to construct
+    // message headers.
+    block_on(async move { ChatId::new(chat_id).get_ephemeral_timer(ctx).await })
+        .context("Failed to get ephemeral timer")
+        .log_err(ctx)
+        .unwrap_or_default()
+        .to_u32()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_timer(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    timer: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_timer(ctx, EphemeralTimer::from_u32(timer))
+            .await
+            .context("Failed to set ephemeral timer")
+            .log_err(ctx)
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_self_addr(
     context: *mut dc_context_t,
     chat_id: u32,
-) -> u32 {
+    addr: *const libc::c_char,
+) -> libc::c_int {
     if context.is_null() {
-        eprintln!("ignoring careless call to dc_get_chat_ephemeral_timer()");
+        eprintln!("ignoring careless call to dc_set_chat_self_addr()");
         return 0;
     }
     let ctx = &*context;
+    let addr = to_opt_string_lossy(addr);
 
-    // Timer value 0 is returned in the rare case of a database error,
-    // but it is not dangerous since it is only meant to be used as a
-    // default when changing the value. Such errors should not be
-    // ignored when ephemeral timer value is used to construct
-    // message headers.
-    block_on(async move { ChatId::new(chat_id).get_ephemeral_timer(ctx).await })
-        .context("Failed to get ephemeral timer")
-        .log_err(ctx)
-        .unwrap_or_default()
-        .to_u32()
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_self_addr(ctx, addr.as_deref())
+            .await
+            .context("Failed to set self address")
+            .log_err(ctx)
+            .is_ok() as libc::c_int
+    })
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_set_chat_ephemeral_timer(
+pub unsafe extern "C" fn dc_set_chat_ui_settings(
     context: *mut dc_context_t,
     chat_id: u32,
-    timer: u32,
+    settings: *const libc::c_char,
 ) -> libc::c_int {
     if context.is_null() {
-        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer()");
+        eprintln!("ignoring careless call to dc_set_chat_ui_settings()");
         return 0;
     }
     let ctx = &*context;
+    let settings = to_opt_string_lossy(settings);
 
     block_on(async move {
         ChatId::new(chat_id)
-            .set_ephemeral_timer(ctx, EphemeralTimer::from_u32(timer))
+            .set_ui_settings(ctx, settings.as_deref())
             .await
-            .context("Failed to set ephemeral timer")
+            .context("Failed to set UI settings")
             .log_err(ctx)
             .is_ok() as libc::c_int
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ui_settings(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ui_settings()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(ChatId::new(chat_id).get_ui_settings(ctx))
+        .log_err(ctx)
+        .ok()
+        .flatten()
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_info(
     context: *mut dc_context_t,
@@ -1891,6 +2670,26 @@ fn from_prim<S, T>(s: S) -> Option<T>
         .strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_notification_json(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_notification_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(notification::get_notification(ctx, MsgId::new(msg_id)))
+        .log_err(ctx)
+        .ok()
+        .flatten()
+        .and_then(|notification| serde_json::to_string(&notification).log_err(ctx).ok())
+        .unwrap_or_default()
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_html(
     context: *mut dc_context_t,
@@ -1907,6 +2706,24 @@ fn from_prim<S, T>(s: S) -> Option<T>
         .strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_sanitized_html(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    load_remote: libc::c_int,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_sanitized_html()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(MsgId::new(msg_id).get_sanitized_html(ctx, load_remote != 0))
+        .unwrap_or_log_default(ctx, "Failed get_msg_sanitized_html")
+        .map(|sanitized| sanitized.html)
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_mime_headers(
     context: *mut dc_context_t,
@@ -1929,6 +2746,28 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_export_eml_file(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || path.is_null() {
+        eprintln!("ignoring careless call to dc_export_eml_file()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(message::export_eml_file(
+        ctx,
+        MsgId::new(msg_id),
+        as_path(path),
+    ))
+    .context("dc_export_eml_file failed")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_delete_msgs(
     context: *mut dc_context_t,
@@ -1948,6 +2787,20 @@ fn from_prim<S, T>(s: S) -> Option<T>
         .ok();
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_undo(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_undo()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(undo::undo(ctx))
+        .context("failed dc_undo() call")
+        .log_err(ctx)
+        .unwrap_or_default() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_forward_msgs(
     context: *mut dc_context_t,
@@ -2217,6 +3070,43 @@ fn from_prim<S, T>(s: S) -> Option<T>
     });
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_contact_remote_content(
+    context: *mut dc_context_t,
+    contact_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_contact_remote_content()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(ContactId::new(contact_id).get_remote_content_decision(ctx))
+        .context("Can't get remote content decision")
+        .log_err(ctx)
+        .unwrap_or_default() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_contact_remote_content(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    decision: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_contact_remote_content()");
+        return 0;
+    }
+    let ctx = &*context;
+    let Some(decision) = RemoteContentDecision::from_i32(decision) else {
+        eprintln!("ignoring dc_set_contact_remote_content() with invalid decision");
+        return 0;
+    };
+    block_on(ContactId::new(contact_id).set_remote_content_decision(ctx, decision))
+        .context("Can't set remote content decision")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_contact_encrinfo(
     context: *mut dc_context_t,
@@ -2387,6 +3277,19 @@ fn spawn_imex(ctx: Context, what: imex::ImexMode, param1: String, passphrase: Op
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_rotate_key(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_rotate_key()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(ctx.rotate_key())
+        .context("Failed to rotate key")
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_stop_ongoing_process(context: *mut dc_context_t) {
     if context.is_null() {
@@ -2397,6 +3300,16 @@ fn spawn_imex(ctx: Context, what: imex::ImexMode, param1: String, passphrase: Op
     block_on(ctx.stop_ongoing());
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_cancel_history_import(context: *mut dc_context_t) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_cancel_history_import()");
+        return;
+    }
+    let ctx = &*context;
+    ctx.cancel_history_import();
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_check_qr(
     context: *mut dc_context_t,
@@ -2457,6 +3370,61 @@ fn spawn_imex(ctx: Context, what: imex::ImexMode, param1: String, passphrase: Op
         .strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_securejoin_link(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_securejoin_link()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+    let chat_id = if chat_id == 0 {
+        None
+    } else {
+        Some(ChatId::new(chat_id))
+    };
+
+    block_on(securejoin::get_securejoin_link(ctx, chat_id))
+        .unwrap_or_else(|_| "".to_string())
+        .strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_withdraw_securejoin_qr(
+    context: *mut dc_context_t,
+    qr: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || qr.is_null() {
+        eprintln!("ignoring careless call to dc_withdraw_securejoin_qr()");
+        return 0;
+    }
+    let ctx = &*context;
+    let qr = to_string_lossy(qr);
+
+    block_on(securejoin::withdraw_securejoin_qr(ctx, &qr))
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_revive_securejoin_qr(
+    context: *mut dc_context_t,
+    qr: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || qr.is_null() {
+        eprintln!("ignoring careless call to dc_revive_securejoin_qr()");
+        return 0;
+    }
+    let ctx = &*context;
+    let qr = to_string_lossy(qr);
+
+    block_on(securejoin::revive_securejoin_qr(ctx, &qr))
+        .log_err(ctx)
+        .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_join_securejoin(
     context: *mut dc_context_t,
@@ -2562,7 +3530,7 @@ fn spawn_imex(ctx: Context, what: imex::ImexMode, param1: String, passphrase: Op
     let contact_id = if contact_id == 0 {
         None
     } else {
-        Some(contact_id)
+        Some(contact_id.into())
     };
 
     block_on(async move {
@@ -2713,7 +3681,7 @@ fn spawn_imex(ctx: Context, what: imex::ImexMode, param1: String, passphrase: Op
         return 0;
     }
 
-    (*array).get_location(index).msg_id
+    (*array).get_location(index).msg_id.into()
 }
 #[no_mangle]
 pub unsafe extern "C" fn dc_array_get_marker(
@@ -2986,6 +3954,31 @@ pub struct ChatWrapper {
         .strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_get_last_error(chat: *mut dc_chat_t) -> *mut libc::c_char {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_get_last_error()");
+        return "".strdup();
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.get_last_error().unwrap_or_default().strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_get_self_addr(chat: *mut dc_chat_t) -> *mut libc::c_char {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_get_self_addr()");
+        return "".strdup();
+    }
+    let ffi_chat = &*chat;
+    let ctx = &*ffi_chat.context;
+    block_on(ffi_chat.chat.get_self_addr(ctx))
+        .context("Failed to get self address")
+        .log_err(ctx)
+        .unwrap_or_default()
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_get_profile_image(chat: *mut dc_chat_t) -> *mut libc::c_char {
     if chat.is_null() {
@@ -3019,6 +4012,22 @@ pub struct ChatWrapper {
     block_on(ffi_chat.chat.get_color(ctx)).unwrap_or_log_default(ctx, "Failed get_color")
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_get_fallback_avatar_svg(
+    chat: *mut dc_chat_t,
+) -> *mut libc::c_char {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_get_fallback_avatar_svg()");
+        return "".strdup();
+    }
+    let ffi_chat = &*chat;
+    let ctx = &*ffi_chat.context;
+
+    block_on(ffi_chat.chat.get_fallback_avatar_svg(ctx))
+        .unwrap_or_log_default(ctx, "Failed get_fallback_avatar_svg")
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_get_visibility(chat: *mut dc_chat_t) -> libc::c_int {
     if chat.is_null() {
@@ -3043,6 +4052,26 @@ pub struct ChatWrapper {
     ffi_chat.chat.is_contact_request() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_is_channel(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_is_channel()");
+        return 0;
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.is_channel() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_is_spam(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_is_spam()");
+        return 0;
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.is_spam() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_is_unpromoted(chat: *mut dc_chat_t) -> libc::c_int {
     if chat.is_null() {
@@ -3311,6 +4340,16 @@ pub struct MessageWrapper {
     ffi_msg.message.get_received_timestamp()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_server_timestamp(msg: *mut dc_msg_t) -> i64 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_server_timestamp()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.get_server_timestamp()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_sort_timestamp(msg: *mut dc_msg_t) -> i64 {
     if msg.is_null() {
@@ -3331,6 +4370,19 @@ pub struct MessageWrapper {
     ffi_msg.message.get_text().strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_markup(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_markup()");
+        return "".strdup();
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+    serde_json::to_string(&ffi_msg.message.get_markup())
+        .unwrap_or_log_default(ctx, "dc_msg_get_markup() failed to serialise to json")
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_subject(msg: *mut dc_msg_t) -> *mut libc::c_char {
     if msg.is_null() {
@@ -3480,13 +4532,33 @@ pub struct MessageWrapper {
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_msg_get_showpadlock(msg: *mut dc_msg_t) -> libc::c_int {
+pub unsafe extern "C" fn dc_msg_is_animated(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_is_animated()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.is_animated().into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_showpadlock(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_showpadlock()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.get_showpadlock() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_dkim_status(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
-        eprintln!("ignoring careless call to dc_msg_get_showpadlock()");
+        eprintln!("ignoring careless call to dc_msg_get_dkim_status()");
         return 0;
     }
     let ffi_msg = &*msg;
-    ffi_msg.message.get_showpadlock() as libc::c_int
+    ffi_msg.message.get_dkim_status() as libc::c_int
 }
 
 #[no_mangle]
@@ -3499,6 +4571,16 @@ pub struct MessageWrapper {
     ffi_msg.message.is_bot() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_is_auto_reply(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_is_auto_reply()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.is_auto_reply() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_ephemeral_timer(msg: *mut dc_msg_t) -> u32 {
     if msg.is_null() {
@@ -3637,6 +4719,37 @@ pub struct MessageWrapper {
     ffi_msg.message.get_info_type() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_info_contact_id(msg: *mut dc_msg_t) -> u32 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_info_contact_id()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+    block_on(ffi_msg.message.get_info_contact_id(ctx))
+        .context("dc_msg_get_info_contact_id failed")
+        .log_err(ctx)
+        .ok()
+        .flatten()
+        .map(|contact_id| contact_id.to_u32())
+        .unwrap_or_default()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_info_actor_id(msg: *mut dc_msg_t) -> u32 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_info_actor_id()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg
+        .message
+        .get_info_actor_id()
+        .map(|contact_id| contact_id.to_u32())
+        .unwrap_or_default()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_is_increation(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -3751,6 +4864,16 @@ pub struct MessageWrapper {
         .set_override_sender_name(to_opt_string_lossy(name))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_set_delivery_ttl(msg: *mut dc_msg_t, seconds: u32) {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_set_delivery_ttl()");
+        return;
+    }
+    let ffi_msg = &mut *msg;
+    ffi_msg.message.set_delivery_ttl(seconds)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_set_file(
     msg: *mut dc_msg_t,
@@ -3768,6 +4891,23 @@ pub struct MessageWrapper {
     )
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_set_file_reference(
+    msg: *mut dc_msg_t,
+    file: *const libc::c_char,
+    filemime: *const libc::c_char,
+) {
+    if msg.is_null() || file.is_null() {
+        eprintln!("ignoring careless call to dc_msg_set_file_reference()");
+        return;
+    }
+    let ffi_msg = &mut *msg;
+    ffi_msg.message.set_file_reference(
+        to_string_lossy(file),
+        to_opt_string_lossy(filemime).as_deref(),
+    )
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_set_dimension(
     msg: *mut dc_msg_t,
@@ -4064,6 +5204,23 @@ pub struct ContactWrapper {
     ffi_contact.contact.get_color()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_fallback_avatar_svg(
+    contact: *mut dc_contact_t,
+) -> *mut libc::c_char {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_fallback_avatar_svg()");
+        return "".strdup();
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+    ffi_contact
+        .contact
+        .get_fallback_avatar_svg()
+        .unwrap_or_log_default(ctx, "Failed get_fallback_avatar_svg")
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_contact_get_status(contact: *mut dc_contact_t) -> *mut libc::c_char {
     if contact.is_null() {
@@ -4074,6 +5231,22 @@ pub struct ContactWrapper {
     ffi_contact.contact.get_status().strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_last_error(
+    contact: *mut dc_contact_t,
+) -> *mut libc::c_char {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_last_error()");
+        return "".strdup();
+    }
+    let ffi_contact = &*contact;
+    ffi_contact
+        .contact
+        .get_last_error()
+        .unwrap_or_default()
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_contact_get_last_seen(contact: *mut dc_contact_t) -> i64 {
     if contact.is_null() {
@@ -4119,6 +5292,44 @@ pub struct ContactWrapper {
         .unwrap_or_default() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_fingerprint(
+    contact: *mut dc_contact_t,
+) -> *mut libc::c_char {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_fingerprint()");
+        return ptr::null_mut();
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+    block_on(ffi_contact.contact.get_fingerprint(ctx))
+        .context("failed to get fingerprint")
+        .log_err(ctx)
+        .unwrap_or_default()
+        .strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_mark_verified(
+    contact: *mut dc_contact_t,
+    fingerprint: *const libc::c_char,
+) -> libc::c_int {
+    if contact.is_null() || fingerprint.is_null() {
+        eprintln!("ignoring careless call to dc_contact_mark_verified()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+    block_on(
+        ffi_contact
+            .contact
+            .mark_verified(ctx, &to_string_lossy(fingerprint)),
+    )
+    .context("failed to mark contact as verified")
+    .log_err(ctx)
+    .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_contact_get_verifier_addr(
     contact: *mut dc_contact_t,
@@ -4261,6 +5472,24 @@ pub struct ContactWrapper {
     reactions.get(ContactId::new(contact_id)).as_str().strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_reactions_get_emojis_json(
+    reactions: *mut dc_reactions_t,
+) -> *mut libc::c_char {
+    if reactions.is_null() {
+        eprintln!("ignoring careless call to dc_reactions_get_emojis_json()");
+        return "".strdup();
+    }
+
+    let reactions = &*reactions;
+    let emojis: Vec<serde_json::Value> = reactions
+        .emoji_sorted_by_frequency()
+        .into_iter()
+        .map(|(emoji, count)| serde_json::json!({ "emoji": emoji, "count": count }))
+        .collect();
+    serde_json::Value::Array(emojis).to_string().strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_reactions_unref(reactions: *mut dc_reactions_t) {
     if reactions.is_null() {
@@ -4537,28 +5766,13 @@ fn convert_and_prune_message_ids(msg_ids: *const u32, msg_cnt: libc::c_int) -> V
     let addr = to_string_lossy(addr);
 
     let ctx = &*context;
-    let socks5_enabled = block_on(async move {
-        ctx.get_config_bool(config::Config::Socks5Enabled)
-            .await
-            .context("Can't get config")
-            .log_err(ctx)
-    });
 
-    match socks5_enabled {
-        Ok(socks5_enabled) => {
-            match block_on(provider::get_provider_info_by_addr(
-                ctx,
-                addr.as_str(),
-                socks5_enabled,
-            ))
-            .log_err(ctx)
-            .unwrap_or_default()
-            {
-                Some(provider) => provider,
-                None => ptr::null_mut(),
-            }
-        }
-        Err(_) => ptr::null_mut(),
+    match block_on(provider::get_info(ctx, addr.as_str()))
+        .log_err(ctx)
+        .unwrap_or_default()
+    {
+        Some(provider) => provider,
+        None => ptr::null_mut(),
     }
 }
 
@@ -4596,6 +5810,30 @@ fn convert_and_prune_message_ids(msg_ids: *const u32, msg_cnt: libc::c_int) -> V
     provider.status as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_provider_get_app_password_required(
+    provider: *const dc_provider_t,
+) -> libc::c_int {
+    if provider.is_null() {
+        eprintln!("ignoring careless call to dc_provider_get_app_password_required()");
+        return 0;
+    }
+    let provider = &*provider;
+    provider.opt.app_password_required as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_provider_get_oauth2_available(
+    provider: *const dc_provider_t,
+) -> libc::c_int {
+    if provider.is_null() {
+        eprintln!("ignoring careless call to dc_provider_get_oauth2_available()");
+        return 0;
+    }
+    let provider = &*provider;
+    provider.oauth2_authorizer.is_some() as libc::c_int
+}
+
 #[no_mangle]
 #[allow(clippy::needless_return)]
 pub unsafe extern "C" fn dc_provider_unref(provider: *mut dc_provider_t) {
@@ -4939,6 +6177,28 @@ fn new(accounts: Accounts) -> Self {
     block_on(async move { accounts.read().await.all_work_done().await as libc::c_int })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_get_fresh_msg_count(
+    accounts: *mut dc_accounts_t,
+) -> libc::c_int {
+    if accounts.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_get_fresh_msg_count()");
+        return 0;
+    }
+    let accounts = &*accounts;
+    block_on(async move {
+        accounts
+            .read()
+            .await
+            .get_fresh_msg_count()
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("failed to get fresh message count: {err:#}");
+                0
+            }) as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_accounts_start_io(accounts: *mut dc_accounts_t) {
     if accounts.is_null() {
@@ -4972,6 +6232,147 @@ fn new(accounts: Accounts) -> Self {
     block_on(async move { accounts.read().await.maybe_network().await });
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_background_fetch(
+    accounts: *mut dc_accounts_t,
+    timeout_seconds: u64,
+) {
+    if accounts.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_background_fetch()");
+        return;
+    }
+
+    let accounts = &*accounts;
+    block_on(async move {
+        accounts
+            .read()
+            .await
+            .background_fetch(Duration::from_secs(timeout_seconds))
+            .await
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_get_connectivity_html(
+    accounts: *mut dc_accounts_t,
+) -> *mut libc::c_char {
+    if accounts.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_get_connectivity_html()");
+        return "".strdup();
+    }
+
+    let accounts = &*accounts;
+    block_on(async move {
+        let accounts = accounts.read().await;
+        match accounts.get_connectivity_html().await {
+            Ok(html) => html.strdup(),
+            Err(err) => {
+                accounts.emit_event(EventType::Error(format!(
+                    "Failed to get connectivity html: {err:#}"
+                )));
+                "".strdup()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_set_push_device_token(
+    accounts: *mut dc_accounts_t,
+    token: *const libc::c_char,
+) {
+    if accounts.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_set_push_device_token()");
+        return;
+    }
+
+    let accounts = &*accounts;
+    let token = to_opt_string_lossy(token).unwrap_or_default();
+    block_on(async move {
+        let accounts = accounts.read().await;
+        if let Err(err) = accounts.set_push_device_token(&token).await {
+            accounts.emit_event(EventType::Error(format!(
+                "Failed to set push device token: {err:#}"
+            )));
+        }
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_set_system_proxy(
+    accounts: *mut dc_accounts_t,
+    host: *const libc::c_char,
+    port: libc::c_int,
+    user: *const libc::c_char,
+    password: *const libc::c_char,
+) {
+    if accounts.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_set_system_proxy()");
+        return;
+    }
+
+    let accounts = &*accounts;
+    let proxy = to_system_proxy(host, port, user, password);
+    block_on(async move {
+        accounts.write().await.set_system_proxy(proxy);
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_set_offline(
+    accounts: *mut dc_accounts_t,
+    offline: libc::c_int,
+) -> libc::c_int {
+    if accounts.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_set_offline()");
+        return 0;
+    }
+
+    let accounts = &*accounts;
+    block_on(async move {
+        let mut accounts = accounts.write().await;
+        match accounts.set_offline(offline != 0).await {
+            Ok(()) => 1,
+            Err(err) => {
+                accounts.emit_event(EventType::Error(format!(
+                    "Failed to set offline mode: {err:#}"
+                )));
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_accounts_handle_push_payload(
+    accounts: *mut dc_accounts_t,
+    payload: *const libc::c_char,
+    timeout_seconds: u64,
+) -> u32 {
+    if accounts.is_null() || payload.is_null() {
+        eprintln!("ignoring careless call to dc_accounts_handle_push_payload()");
+        return 0;
+    }
+
+    let accounts = &*accounts;
+    let payload = to_string_lossy(payload);
+    block_on(async move {
+        let accounts = accounts.read().await;
+        match accounts
+            .handle_push_payload(&payload, Duration::from_secs(timeout_seconds))
+            .await
+        {
+            Ok(id) => id,
+            Err(err) => {
+                accounts.emit_event(EventType::Error(format!(
+                    "Failed to handle push payload: {err:#}"
+                )));
+                0
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_accounts_maybe_network_lost(accounts: *mut dc_accounts_t) {
     if accounts.is_null() {
@@ -52,6 +52,8 @@ pub fn get_text1(&self) -> Option<&str> {
                 Qr::Backup { .. } => None,
                 Qr::WebrtcInstance { domain, .. } => Some(domain),
                 Qr::Addr { draft, .. } => draft.as_deref(),
+                Qr::Proxy { host, .. } => Some(host),
+                Qr::WebxdcShare { url } => Some(url),
                 Qr::Url { url } => Some(url),
                 Qr::Text { text } => Some(text),
                 Qr::WithdrawVerifyContact { .. } => None,
@@ -59,6 +61,7 @@ pub fn get_text1(&self) -> Option<&str> {
                 Qr::ReviveVerifyContact { .. } => None,
                 Qr::ReviveVerifyGroup { grpname, .. } => Some(grpname),
                 Qr::Login { address, .. } => Some(address),
+                Qr::Provisioning => None,
             },
             Self::Error(err) => Some(err),
         }
@@ -104,6 +107,8 @@ pub fn get_state(&self) -> LotState {
                 Qr::Backup { .. } => LotState::QrBackup,
                 Qr::WebrtcInstance { .. } => LotState::QrWebrtcInstance,
                 Qr::Addr { .. } => LotState::QrAddr,
+                Qr::Proxy { .. } => LotState::QrProxy,
+                Qr::WebxdcShare { .. } => LotState::QrWebxdcShare,
                 Qr::Url { .. } => LotState::QrUrl,
                 Qr::Text { .. } => LotState::QrText,
                 Qr::WithdrawVerifyContact { .. } => LotState::QrWithdrawVerifyContact,
@@ -111,6 +116,7 @@ pub fn get_state(&self) -> LotState {
                 Qr::ReviveVerifyContact { .. } => LotState::QrReviveVerifyContact,
                 Qr::ReviveVerifyGroup { .. } => LotState::QrReviveVerifyGroup,
                 Qr::Login { .. } => LotState::QrLogin,
+                Qr::Provisioning => LotState::QrProvisioning,
             },
             Self::Error(_err) => LotState::QrError,
         }
@@ -129,6 +135,8 @@ pub fn get_id(&self) -> u32 {
                 Qr::Backup { .. } => Default::default(),
                 Qr::WebrtcInstance { .. } => Default::default(),
                 Qr::Addr { contact_id, .. } => contact_id.to_u32(),
+                Qr::Proxy { .. } => Default::default(),
+                Qr::WebxdcShare { .. } => Default::default(),
                 Qr::Url { .. } => Default::default(),
                 Qr::Text { .. } => Default::default(),
                 Qr::WithdrawVerifyContact { contact_id, .. } => contact_id.to_u32(),
@@ -136,6 +144,7 @@ pub fn get_id(&self) -> u32 {
                 Qr::ReviveVerifyContact { contact_id, .. } => contact_id.to_u32(),
                 Qr::ReviveVerifyGroup { .. } => Default::default(),
                 Qr::Login { .. } => Default::default(),
+                Qr::Provisioning => Default::default(),
             },
             Self::Error(_) => Default::default(),
         }
@@ -180,6 +189,9 @@ pub enum LotState {
     /// text1=domain, text2=instance pattern
     QrWebrtcInstance = 260,
 
+    /// text1=host
+    QrProxy = 270,
+
     /// id=contact
     QrAddr = 320,
 
@@ -189,6 +201,9 @@ pub enum LotState {
     /// text1=URL
     QrUrl = 332,
 
+    /// text1=URL
+    QrWebxdcShare = 334,
+
     /// text1=error string
     QrError = 400,
 
@@ -205,6 +220,8 @@ pub enum LotState {
     /// text1=email_address
     QrLogin = 520,
 
+    QrProvisioning = 530,
+
     // Message States
     MsgInFresh = 10,
     MsgInNoticed = 13,
@@ -4,10 +4,11 @@
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 pub use deltachat::accounts::Accounts;
+use deltachat::audit::get_msg_audit_trail;
 use deltachat::chat::{
-    self, add_contact_to_chat, forward_msgs, get_chat_media, get_chat_msgs, get_chat_msgs_ex,
-    marknoticed_chat, remove_contact_from_chat, Chat, ChatId, ChatItem, MessageListOptions,
-    ProtectionStatus,
+    self, add_contact_to_chat, forward_msgs, get_chat_media, get_chat_media_count,
+    get_chat_media_index, get_chat_media_page, get_chat_msgs, get_chat_msgs_ex, marknoticed_chat,
+    remove_contact_from_chat, Chat, ChatId, ChatItem, MessageListOptions, ProtectionStatus,
 };
 use deltachat::chatlist::Chatlist;
 use deltachat::config::Config;
@@ -21,6 +22,8 @@
 use deltachat::message::{
     self, delete_msgs, markseen_msgs, Message, MessageState, MsgId, Viewtype,
 };
+use deltachat::oauth2;
+use deltachat::peer_channels;
 use deltachat::provider::get_provider_info;
 use deltachat::qr::{self, Qr};
 use deltachat::qr_code_generator::{generate_backup_qr, get_securejoin_qr_svg};
@@ -39,17 +42,23 @@
 use num_traits::FromPrimitive;
 use types::account::Account;
 use types::chat::FullChat;
-use types::contact::ContactObject;
+use types::contact::{ContactObject, ContactStatusSummaryObject};
 use types::events::Event;
 use types::http::HttpResponse;
-use types::message::{MessageData, MessageObject, MessageReadReceipt};
+use types::login_check::LoginCheck;
+use types::message::{
+    MessageAuditEntry, MessageData, MessageNotification, MessageObject, MessageReadReceipt,
+    MessageSanitizedHtml, MessageSearchFilter,
+};
 use types::provider_info::ProviderInfo;
 use types::reactions::JSONRPCReactions;
+use types::stock_strings::StockTranslation;
 use types::webxdc::WebxdcMessageInfo;
 
 use self::types::message::MessageLoadResult;
 use self::types::{
-    chat::{BasicChat, JSONRPCChatVisibility, MuteDuration},
+    chat::{BasicChat, ChatEncryptionInfo, JSONRPCChatVisibility, MuteDuration},
+    contact::JSONRPCRemoteContentDecision,
     location::JsonrpcLocation,
     message::{
         JSONRPCMessageListItem, MessageNotificationInfo, MessageSearchResult, MessageViewtype,
@@ -299,6 +308,31 @@ async fn get_provider_info(
         Ok(ProviderInfo::from_dc_type(provider_info))
     }
 
+    /// Returns URL that should be opened in the browser
+    /// if OAuth 2 is supported for this address.
+    async fn get_oauth2_url(
+        &self,
+        account_id: u32,
+        addr: String,
+        redirect_uri: String,
+    ) -> Result<Option<String>> {
+        let ctx = self.get_context(account_id).await?;
+        oauth2::get_oauth2_url(&ctx, &addr, &redirect_uri).await
+    }
+
+    /// Completes OAuth 2 authorization with the `code` passed to the redirect URI.
+    ///
+    /// Call `configure()` afterwards.
+    async fn complete_oauth2_flow(
+        &self,
+        account_id: u32,
+        addr: String,
+        code: String,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        oauth2::complete_oauth2_flow(&ctx, &addr, &code).await
+    }
+
     /// Checks if the context is already configured.
     async fn is_configured(&self, account_id: u32) -> Result<bool> {
         let ctx = self.get_context(account_id).await?;
@@ -311,6 +345,15 @@ async fn get_info(&self, account_id: u32) -> Result<BTreeMap<&'static str, Strin
         ctx.get_info().await
     }
 
+    /// Returns up to `count` most recent log lines of the account, oldest first.
+    ///
+    /// Which lines are recorded is controlled by the `log_filter` config key.
+    /// This is meant to be attached to bug reports.
+    async fn get_last_log_lines(&self, account_id: u32, count: u32) -> Result<Vec<String>> {
+        let ctx = self.get_context(account_id).await?;
+        Ok(ctx.get_last_log_lines(count.try_into()?))
+    }
+
     /// Sets the given configuration key.
     async fn set_config(&self, account_id: u32, key: String, value: Option<String>) -> Result<()> {
         let ctx = self.get_context(account_id).await?;
@@ -368,6 +411,24 @@ async fn batch_get_config(
         Ok(result)
     }
 
+    /// Returns the additional IMAP folders to fetch messages from.
+    async fn get_extra_folders(&self, account_id: u32) -> Result<Vec<String>> {
+        let ctx = self.get_context(account_id).await?;
+        ctx.get_extra_folders().await
+    }
+
+    /// Enables or disables fetching messages from an additional IMAP folder,
+    /// e.g. a folder where messages are sorted into by server-side filters.
+    async fn set_extra_folder_watched(
+        &self,
+        account_id: u32,
+        folder: String,
+        watched: bool,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ctx.set_extra_folder_watched(&folder, watched).await
+    }
+
     async fn set_stock_strings(&self, strings: HashMap<u32, String>) -> Result<()> {
         let accounts = self.accounts.read().await;
         for (stock_id, stock_message) in strings {
@@ -380,6 +441,72 @@ async fn set_stock_strings(&self, strings: HashMap<u32, String>) -> Result<()> {
         Ok(())
     }
 
+    /// Replaces all stock string translations.
+    ///
+    /// `translations` are in the order of preference, e.g. `pt-BR` followed by `pt`.
+    /// Strings missing in all of them use the English default.
+    async fn set_stock_translations(&self, translations: Vec<StockTranslation>) -> Result<()> {
+        let translations = translations
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<Vec<_>>>()?;
+        self.accounts
+            .read()
+            .await
+            .set_stock_translations(translations)
+            .await
+    }
+
+    /// Tests IMAP and SMTP login with the currently set parameters
+    /// without changing the configuration.
+    ///
+    /// Can be used to offer a "test connection" button in the settings.
+    async fn check_login(&self, account_id: u32) -> Result<LoginCheck> {
+        let ctx = self.get_context(account_id).await?;
+        Ok(ctx.check_login().await?.into())
+    }
+
+    /// Changes the password of the configured account
+    /// after testing it with the configured IMAP and SMTP servers.
+    async fn change_password(&self, account_id: u32, password: String) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ctx.change_password(&password).await
+    }
+
+    /// Exports the login configuration of the account as provisioning string
+    /// encrypted with `passphrase`, optionally including the secret key.
+    async fn export_provisioning(
+        &self,
+        account_id: u32,
+        passphrase: String,
+        include_secret_key: bool,
+    ) -> Result<String> {
+        let ctx = self.get_context(account_id).await?;
+        imex::export_provisioning(&ctx, &passphrase, include_secret_key).await
+    }
+
+    /// Configures an unconfigured account from a provisioning string
+    /// without autoconfiguration.
+    async fn import_provisioning(
+        &self,
+        account_id: u32,
+        blob: String,
+        passphrase: String,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        imex::import_provisioning(&ctx, &blob, &passphrase).await
+    }
+
+    /// Imports an `.eml` file into the chats as if the email was received.
+    ///
+    /// Returns the IDs of the added messages,
+    /// which is empty if the message is already in the database.
+    async fn import_eml_file(&self, account_id: u32, path: String) -> Result<Vec<u32>> {
+        let ctx = self.get_context(account_id).await?;
+        let msg_ids = imex::receive_eml_file(&ctx, path.as_ref()).await?;
+        Ok(msg_ids.iter().map(|msg_id| msg_id.to_u32()).collect())
+    }
+
     /// Configures this account with the currently set parameters.
     /// Setup the credential config before calling this.
     async fn configure(&self, account_id: u32) -> Result<()> {
@@ -403,6 +530,14 @@ async fn stop_ongoing_process(&self, account_id: u32) -> Result<()> {
         Ok(())
     }
 
+    /// Cancel the running import of the mail history,
+    /// see the `import_history_days` config option.
+    async fn cancel_history_import(&self, account_id: u32) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ctx.cancel_history_import();
+        Ok(())
+    }
+
     async fn export_self_keys(
         &self,
         account_id: u32,
@@ -467,6 +602,21 @@ async fn get_fresh_msg_cnt(&self, account_id: u32, chat_id: u32) -> Result<usize
         ChatId::new(chat_id).get_fresh_msg_cnt(&ctx).await
     }
 
+    /// Get the number of fresh messages to show as a badge counter, e.g. on the app icon.
+    ///
+    /// Which messages are counted is configured by the `badge_count_muted`,
+    /// `badge_count_requests` and `badge_count_device_msgs` config options.
+    /// If `account_id` is `null`, the total of all accounts is returned.
+    async fn get_fresh_msg_count(&self, account_id: Option<u32>) -> Result<usize> {
+        match account_id {
+            Some(account_id) => {
+                let ctx = self.get_context(account_id).await?;
+                ctx.get_fresh_msg_count().await
+            }
+            None => self.accounts.read().await.get_fresh_msg_count().await,
+        }
+    }
+
     /// Gets messages to be processed by the bot and returns their IDs.
     ///
     /// Only messages with database ID higher than `last_msg_id` config value
@@ -543,6 +693,15 @@ async fn continue_autocrypt_key_transfer(
         deltachat::imex::continue_key_transfer(&ctx, MsgId::new(message_id), &setup_code).await
     }
 
+    /// Replaces the own key with a newly generated one
+    /// and sends key-transition notices to recently seen contacts.
+    /// The old key is kept to decrypt messages encrypted to it.
+    /// Fails if other devices using the account are known.
+    async fn rotate_key(&self, account_id: u32) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ctx.rotate_key().await
+    }
+
     // ---------------------------------------------
     //   chat list
     // ---------------------------------------------
@@ -628,6 +787,13 @@ async fn accept_chat(&self, account_id: u32, chat_id: u32) -> Result<()> {
         ChatId::new(chat_id).accept(&ctx).await
     }
 
+    /// Moves a contact request classified as spam back to the normal chatlist
+    /// and its messages out of the Spam folder.
+    async fn mark_chat_not_spam(&self, account_id: u32, chat_id: u32) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ChatId::new(chat_id).mark_not_spam(&ctx).await
+    }
+
     async fn block_chat(&self, account_id: u32, chat_id: u32) -> Result<()> {
         let ctx = self.get_context(account_id).await?;
         ChatId::new(chat_id).block(&ctx).await
@@ -666,6 +832,19 @@ async fn get_chat_encryption_info(&self, account_id: u32, chat_id: u32) -> Resul
         ChatId::new(chat_id).get_encryption_info(&ctx).await
     }
 
+    /// Get the structured encryption status of a chat:
+    /// key, fingerprint, verification and encryption preference of each member
+    /// and whether messages sent to the chat will be encrypted.
+    async fn get_chat_encryption_status(
+        &self,
+        account_id: u32,
+        chat_id: u32,
+    ) -> Result<ChatEncryptionInfo> {
+        let ctx = self.get_context(account_id).await?;
+        let chat = Chat::load_from_db(&ctx, ChatId::new(chat_id)).await?;
+        Ok(chat.get_encryption_info(&ctx).await?.into())
+    }
+
     /// Get QR code (text and SVG) that will offer an Setup-Contact or Verified-Group invitation.
     /// The QR code is compatible to the OPENPGP4FPR format
     /// so that a basic fingerprint comparison also works e.g. with OpenKeychain.
@@ -695,6 +874,45 @@ async fn get_chat_securejoin_qr_code_svg(
         ))
     }
 
+    /// Get an invite link offering the same invitation as the QR code
+    /// returned by `get_chat_securejoin_qr_code_svg()`,
+    /// e.g. `https://i.delta.chat/#FINGERPRINT&a=...`.
+    ///
+    /// When the link is pasted or opened in the app,
+    /// pass it to `check_qr()` and `secure_join()` as a scanned QR code.
+    async fn get_chat_securejoin_link(
+        &self,
+        account_id: u32,
+        chat_id: Option<u32>,
+    ) -> Result<String> {
+        let ctx = self.get_context(account_id).await?;
+        securejoin::get_securejoin_link(&ctx, chat_id.map(ChatId::new)).await
+    }
+
+    /// Withdraw a QR code or invite link returned by `get_chat_securejoin_qr_code_svg()`
+    /// or `get_chat_securejoin_link()`.
+    ///
+    /// Handshakes started with a withdrawn code are ignored.
+    async fn withdraw_securejoin_qr(&self, account_id: u32, qr: String) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        securejoin::withdraw_securejoin_qr(&ctx, &qr).await
+    }
+
+    /// Enable a code withdrawn by `withdraw_securejoin_qr()` again.
+    async fn revive_securejoin_qr(&self, account_id: u32, qr: String) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        securejoin::revive_securejoin_qr(&ctx, &qr).await
+    }
+
+    /// Render a fallback avatar for a chat without profile image as an SVG image.
+    ///
+    /// The avatar shows the initial letter of the chat name on the chat color.
+    async fn get_chat_fallback_avatar_svg(&self, account_id: u32, chat_id: u32) -> Result<String> {
+        let ctx = self.get_context(account_id).await?;
+        let chat = Chat::load_from_db(&ctx, ChatId::new(chat_id)).await?;
+        chat.get_fallback_avatar_svg(&ctx).await
+    }
+
     /// Continue a Setup-Contact or Verified-Group-Invite protocol
     /// started on another device with `get_chat_securejoin_qr_code_svg()`.
     /// This function is typically called when `check_qr()` returns
@@ -782,6 +1000,39 @@ async fn get_chat_contacts(&self, account_id: u32, chat_id: u32) -> Result<Vec<u
         Ok(contacts.iter().map(|id| id.to_u32()).collect::<Vec<u32>>())
     }
 
+    /// Get the contact IDs of the admins of a group.
+    ///
+    /// If the list is empty, the group does not use roles
+    /// and all members may add and remove members and change name and avatar of the group.
+    async fn get_chat_admins(&self, account_id: u32, chat_id: u32) -> Result<Vec<u32>> {
+        let ctx = self.get_context(account_id).await?;
+        let admins = chat::get_chat_admins(&ctx, ChatId::new(chat_id)).await?;
+        Ok(admins.iter().map(|id| id.to_u32()).collect::<Vec<u32>>())
+    }
+
+    /// Grant or revoke admin rights of a group member.
+    ///
+    /// A group does not use roles until the first admin is set,
+    /// then the user setting it becomes an admin as well.
+    ///
+    /// Sends out #DC_EVENT_CHAT_MODIFIED and #DC_EVENT_MSGS_CHANGED if a status message was sent.
+    async fn set_chat_admin(
+        &self,
+        account_id: u32,
+        chat_id: u32,
+        contact_id: u32,
+        admin: bool,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        chat::set_chat_admin(
+            &ctx,
+            ChatId::new(chat_id),
+            ContactId::new(contact_id),
+            admin,
+        )
+        .await
+    }
+
     /// Create a new group chat.
     ///
     /// After creation,
@@ -812,6 +1063,18 @@ async fn create_group_chat(&self, account_id: u32, name: String, protect: bool)
             .map(|id| id.to_u32())
     }
 
+    /// Create a new read-only channel where only the user may post.
+    ///
+    /// Other members cannot send to the channel,
+    /// messages they send nevertheless are shown in the 1:1 chat with the sender.
+    /// Only the user may add and remove members and change the channel.
+    async fn create_channel(&self, account_id: u32, name: String) -> Result<u32> {
+        let ctx = self.get_context(account_id).await?;
+        chat::create_channel(&ctx, &name)
+            .await
+            .map(|id| id.to_u32())
+    }
+
     /// Create a new broadcast list.
     ///
     /// Broadcast lists are similar to groups on the sending device,
@@ -839,6 +1102,14 @@ async fn set_chat_name(&self, account_id: u32, chat_id: u32, new_name: String) -
         chat::set_chat_name(&ctx, ChatId::new(chat_id), &new_name).await
     }
 
+    /// Unsubscribe from a mailing list using its List-Unsubscribe header.
+    ///
+    /// On success, an info message is added to the chat and #DC_EVENT_CHAT_MODIFIED is emitted.
+    async fn unsubscribe_chat(&self, account_id: u32, chat_id: u32) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        chat::unsubscribe(&ctx, ChatId::new(chat_id)).await
+    }
+
     /// Set group profile image.
     ///
     /// If the group is already _promoted_ (any message was sent to the group),
@@ -896,6 +1167,50 @@ async fn get_chat_ephemeral_timer(&self, account_id: u32, chat_id: u32) -> Resul
             .to_u32())
     }
 
+    /// Sets the address messages to the chat are sent from,
+    /// the primary address or one of the `alias_addrs`.
+    /// `null` resets to the default.
+    async fn set_chat_self_addr(
+        &self,
+        account_id: u32,
+        chat_id: u32,
+        addr: Option<String>,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ChatId::new(chat_id)
+            .set_self_addr(&ctx, addr.as_deref())
+            .await
+    }
+
+    /// Returns the address messages to the chat are sent from.
+    async fn get_chat_self_addr(&self, account_id: u32, chat_id: u32) -> Result<String> {
+        let ctx = self.get_context(account_id).await?;
+        let chat = Chat::load_from_db(&ctx, ChatId::new(chat_id)).await?;
+        chat.get_self_addr(&ctx).await
+    }
+
+    /// Sets opaque settings of the UI for the chat, e.g. the wallpaper,
+    /// at most 4096 bytes. `null` removes the settings.
+    ///
+    /// The settings are synchronized to other devices if `send_sync_msgs` is enabled.
+    async fn set_chat_ui_settings(
+        &self,
+        account_id: u32,
+        chat_id: u32,
+        settings: Option<String>,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ChatId::new(chat_id)
+            .set_ui_settings(&ctx, settings.as_deref())
+            .await
+    }
+
+    /// Returns the settings set by `set_chat_ui_settings()`.
+    async fn get_chat_ui_settings(&self, account_id: u32, chat_id: u32) -> Result<Option<String>> {
+        let ctx = self.get_context(account_id).await?;
+        ChatId::new(chat_id).get_ui_settings(&ctx).await
+    }
+
     // for now only text messages, because we only used text messages in desktop thusfar
     async fn add_device_message(
         &self,
@@ -1072,6 +1387,32 @@ async fn get_message_html(&self, account_id: u32, message_id: u32) -> Result<Opt
         MsgId::new(message_id).get_html(&ctx).await
     }
 
+    /// Returns the HTML of the message sanitized to be safe to display.
+    ///
+    /// Remote content is loaded as configured by `remote_content`;
+    /// if the UI shall ask, `load_remote` is the answer of the user.
+    async fn get_message_sanitized_html(
+        &self,
+        account_id: u32,
+        message_id: u32,
+        load_remote: bool,
+    ) -> Result<Option<MessageSanitizedHtml>> {
+        let ctx = self.get_context(account_id).await?;
+        Ok(MsgId::new(message_id)
+            .get_sanitized_html(&ctx, load_remote)
+            .await?
+            .map(Into::into))
+    }
+
+    /// Exports a message as `.eml` file in RFC 5322 format.
+    ///
+    /// End-to-end encrypted messages are exported decrypted
+    /// and marked with the header `Chat-Decrypted: 1`.
+    async fn export_eml_file(&self, account_id: u32, message_id: u32, path: String) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        message::export_eml_file(&ctx, MsgId::new(message_id), path.as_ref()).await
+    }
+
     /// get multiple messages in one call,
     /// if loading one message fails the error is stored in the result object in it's place.
     ///
@@ -1116,6 +1457,15 @@ async fn delete_messages(&self, account_id: u32, message_ids: Vec<u32>) -> Resul
         delete_msgs(&ctx, &msgs).await
     }
 
+    /// Reverse the latest deletion of messages or chats or removal of a group member.
+    ///
+    /// Only possible while the `undo_timeout` config option is set and the timeout did not expire.
+    /// Returns false if there is no operation that can be undone.
+    async fn undo(&self, account_id: u32) -> Result<bool> {
+        let ctx = self.get_context(account_id).await?;
+        deltachat::undo::undo(&ctx).await
+    }
+
     /// Get an informational text for a single message. The text is multiline and may
     /// contain e.g. the raw text of the message.
     ///
@@ -1126,6 +1476,22 @@ async fn get_message_info(&self, account_id: u32, message_id: u32) -> Result<Str
         MsgId::new(message_id).get_info(&ctx).await
     }
 
+    /// Returns the notification to show for an incoming message,
+    /// localized and respecting the `notify_privacy` config option.
+    ///
+    /// `null` is returned if no notification should be shown,
+    /// e.g. because the message is already seen or the chat is muted.
+    async fn get_message_notification(
+        &self,
+        account_id: u32,
+        message_id: u32,
+    ) -> Result<Option<MessageNotification>> {
+        let ctx = self.get_context(account_id).await?;
+        let notification =
+            deltachat::notification::get_notification(&ctx, MsgId::new(message_id)).await?;
+        Ok(notification.map(Into::into))
+    }
+
     /// Returns contacts that sent read receipts and the time of reading.
     async fn get_message_read_receipts(
         &self,
@@ -1144,6 +1510,24 @@ async fn get_message_read_receipts(
         Ok(receipts)
     }
 
+    /// Returns the IMAP and SMTP operations recorded for a message, oldest first.
+    async fn get_message_audit_trail(
+        &self,
+        account_id: u32,
+        message_id: u32,
+    ) -> Result<Vec<MessageAuditEntry>> {
+        let ctx = self.get_context(account_id).await?;
+        let trail = get_msg_audit_trail(&ctx, MsgId::new(message_id))
+            .await?
+            .into_iter()
+            .map(|entry| MessageAuditEntry {
+                timestamp: entry.timestamp,
+                operation: entry.operation,
+            })
+            .collect();
+        Ok(trail)
+    }
+
     /// Asks the core to start downloading a message fully.
     /// This function is typically called when the user hits the "Download" button
     /// that is shown by the UI in case `download_state` is `'Available'` or `'Failure'`
@@ -1186,6 +1570,22 @@ async fn search_messages(
             .collect::<Vec<u32>>())
     }
 
+    /// Searches for messages containing the query and matching the filter.
+    ///
+    /// The query may be empty if the filter restricts the search,
+    /// e.g. to find all messages with attachments from a contact.
+    /// Results are ordered and limited like for `search_messages`.
+    async fn search_messages_ex(
+        &self,
+        account_id: u32,
+        query: String,
+        filter: MessageSearchFilter,
+    ) -> Result<Vec<u32>> {
+        let ctx = self.get_context(account_id).await?;
+        let messages = ctx.search_msgs_ex(&query, &filter.into()).await?;
+        Ok(messages.iter().map(|msg_id| msg_id.to_u32()).collect())
+    }
+
     async fn message_ids_to_search_results(
         &self,
         account_id: u32,
@@ -1207,6 +1607,33 @@ async fn message_ids_to_search_results(
     //  contact
     // ---------------------------------------------
 
+    /// Render a fallback avatar for a contact without profile image as an SVG image.
+    ///
+    /// The avatar shows the initial letter of the display name on the contact color.
+    async fn get_contact_fallback_avatar_svg(
+        &self,
+        account_id: u32,
+        contact_id: u32,
+    ) -> Result<String> {
+        let ctx = self.get_context(account_id).await?;
+        let contact =
+            deltachat::contact::Contact::get_by_id(&ctx, ContactId::new(contact_id)).await?;
+        contact.get_fallback_avatar_svg()
+    }
+
+    /// Get when a contact was last active and whether their recent messages
+    /// were encrypted and verified.
+    async fn get_contact_status_summary(
+        &self,
+        account_id: u32,
+        contact_id: u32,
+    ) -> Result<ContactStatusSummaryObject> {
+        let ctx = self.get_context(account_id).await?;
+        let contact =
+            deltachat::contact::Contact::get_by_id(&ctx, ContactId::new(contact_id)).await?;
+        Ok(contact.get_status_summary(&ctx).await?.into())
+    }
+
     /// Get a single contact options by ID.
     async fn get_contact(&self, account_id: u32, contact_id: u32) -> Result<ContactObject> {
         let ctx = self.get_context(account_id).await?;
@@ -1362,6 +1789,63 @@ async fn get_contact_encryption_info(
         Contact::get_encrinfo(&ctx, ContactId::new(contact_id)).await
     }
 
+    /// Get the fingerprint of the key used to encrypt messages to a contact,
+    /// formatted for comparison by the user.
+    /// For the self contact, the fingerprint of the own key is returned.
+    ///
+    /// Returns `null` if no key is known for the contact.
+    async fn get_contact_fingerprint(
+        &self,
+        account_id: u32,
+        contact_id: u32,
+    ) -> Result<Option<String>> {
+        let ctx = self.get_context(account_id).await?;
+        let contact = Contact::get_by_id(&ctx, ContactId::new(contact_id)).await?;
+        contact.get_fingerprint(&ctx).await
+    }
+
+    /// Mark a contact as verified after the user compared the fingerprints
+    /// returned by `get_contact_fingerprint` on both devices, e.g. over the phone.
+    ///
+    /// Fails if the fingerprint does not match the contact's key.
+    async fn mark_contact_verified(
+        &self,
+        account_id: u32,
+        contact_id: u32,
+        fingerprint: String,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        let contact = Contact::get_by_id(&ctx, ContactId::new(contact_id)).await?;
+        contact.mark_verified(&ctx, &fingerprint).await
+    }
+
+    /// Returns whether remote content of HTML messages from the contact is loaded.
+    async fn get_contact_remote_content_decision(
+        &self,
+        account_id: u32,
+        contact_id: u32,
+    ) -> Result<JSONRPCRemoteContentDecision> {
+        let ctx = self.get_context(account_id).await?;
+        Ok(ContactId::new(contact_id)
+            .get_remote_content_decision(&ctx)
+            .await?
+            .into())
+    }
+
+    /// Sets whether remote content of HTML messages from the contact
+    /// is loaded by `get_message_sanitized_html()`.
+    async fn set_contact_remote_content_decision(
+        &self,
+        account_id: u32,
+        contact_id: u32,
+        decision: JSONRPCRemoteContentDecision,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        ContactId::new(contact_id)
+            .set_remote_content_decision(&ctx, decision.into())
+            .await
+    }
+
     /// Check if an e-mail address belongs to a known and unblocked contact.
     /// To get a list of all known and unblocked contacts, use contacts_get_contacts().
     ///
@@ -1399,19 +1883,123 @@ async fn get_chat_media(
         or_message_type3: Option<MessageViewtype>,
     ) -> Result<Vec<u32>> {
         let ctx = self.get_context(account_id).await?;
-
-        let chat_id = match chat_id {
-            None | Some(0) => None,
-            Some(id) => Some(ChatId::new(id)),
-        };
-        let msg_type = message_type.into();
-        let or_msg_type2 = or_message_type2.map_or(Viewtype::Unknown, |v| v.into());
-        let or_msg_type3 = or_message_type3.map_or(Viewtype::Unknown, |v| v.into());
+        let (chat_id, msg_type, or_msg_type2, or_msg_type3) =
+            media_query_args(chat_id, message_type, or_message_type2, or_message_type3);
 
         let media = get_chat_media(&ctx, chat_id, msg_type, or_msg_type2, or_msg_type3).await?;
         Ok(media.iter().map(|msg_id| msg_id.to_u32()).collect())
     }
 
+    /// Returns a page of message IDs of the given types in a chat.
+    ///
+    /// The order is the same as for `get_chat_media`,
+    /// `offset` is the index of the first returned message in the full list.
+    /// Use `get_chat_media_count` to get the size of the full list.
+    #[allow(clippy::too_many_arguments)]
+    async fn get_chat_media_page(
+        &self,
+        account_id: u32,
+        chat_id: Option<u32>,
+        message_type: MessageViewtype,
+        or_message_type2: Option<MessageViewtype>,
+        or_message_type3: Option<MessageViewtype>,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<u32>> {
+        let ctx = self.get_context(account_id).await?;
+        let (chat_id, msg_type, or_msg_type2, or_msg_type3) =
+            media_query_args(chat_id, message_type, or_message_type2, or_message_type3);
+
+        let media = get_chat_media_page(
+            &ctx,
+            chat_id,
+            msg_type,
+            or_msg_type2,
+            or_msg_type3,
+            usize::try_from(offset)?,
+            usize::try_from(limit)?,
+        )
+        .await?;
+        Ok(media.iter().map(|msg_id| msg_id.to_u32()).collect())
+    }
+
+    /// Returns the number of messages of the given types in a chat.
+    async fn get_chat_media_count(
+        &self,
+        account_id: u32,
+        chat_id: Option<u32>,
+        message_type: MessageViewtype,
+        or_message_type2: Option<MessageViewtype>,
+        or_message_type3: Option<MessageViewtype>,
+    ) -> Result<u32> {
+        let ctx = self.get_context(account_id).await?;
+        let (chat_id, msg_type, or_msg_type2, or_msg_type3) =
+            media_query_args(chat_id, message_type, or_message_type2, or_message_type3);
+
+        let count =
+            get_chat_media_count(&ctx, chat_id, msg_type, or_msg_type2, or_msg_type3).await?;
+        Ok(u32::try_from(count)?)
+    }
+
+    /// Writes a readable transcript of a chat to the file at `path`,
+    /// as plain text or as a standalone HTML document with embedded images and avatars.
+    ///
+    /// If `timestamp_range` is set, only messages sent within the range are exported.
+    async fn export_chat_transcript(
+        &self,
+        account_id: u32,
+        chat_id: u32,
+        html: bool,
+        timestamp_range: Option<(i64, i64)>,
+        path: String,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        let format = if html {
+            chat::TranscriptFormat::Html
+        } else {
+            chat::TranscriptFormat::Text
+        };
+        let mut file = fs::File::create(&path).await?;
+        chat::export_transcript(
+            &ctx,
+            ChatId::new(chat_id),
+            format,
+            timestamp_range.map(|(start, end)| start..=end),
+            &mut file,
+        )
+        .await
+    }
+
+    /// Returns the index of a message in the list returned by `get_chat_media`.
+    ///
+    /// Typically used by fullscreen viewers to load the previous and next media
+    /// with `get_chat_media_page`.
+    /// Returns `null` if the message is not part of the list.
+    async fn get_chat_media_index(
+        &self,
+        account_id: u32,
+        chat_id: Option<u32>,
+        msg_id: u32,
+        message_type: MessageViewtype,
+        or_message_type2: Option<MessageViewtype>,
+        or_message_type3: Option<MessageViewtype>,
+    ) -> Result<Option<u32>> {
+        let ctx = self.get_context(account_id).await?;
+        let (chat_id, msg_type, or_msg_type2, or_msg_type3) =
+            media_query_args(chat_id, message_type, or_message_type2, or_message_type3);
+
+        let index = get_chat_media_index(
+            &ctx,
+            chat_id,
+            MsgId::new(msg_id),
+            msg_type,
+            or_msg_type2,
+            or_msg_type3,
+        )
+        .await?;
+        Ok(index.map(u32::try_from).transpose()?)
+    }
+
     /// Search next/previous message based on a given message and a list of types.
     /// Typically used to implement the "next" and "previous" buttons
     /// in a gallery or in a media player.
@@ -1577,6 +2165,66 @@ async fn maybe_network(&self) -> Result<()> {
         Ok(())
     }
 
+    /// Turn the offline mode on or off for all accounts.
+    ///
+    /// While offline, no IMAP or SMTP connections are opened.
+    /// When going online again, new messages are fetched
+    /// and queued messages are sent immediately.
+    /// The mode is remembered and also applied to accounts added later.
+    async fn set_offline(&self, offline: bool) -> Result<()> {
+        self.accounts.write().await.set_offline(offline).await
+    }
+
+    /// Fetch new messages and send queued messages for all accounts,
+    /// giving up after `timeout_in_seconds`.
+    ///
+    /// Returns when all accounts are done or the timeout is reached,
+    /// `BackgroundFetchDone` event is emitted with context ID 0 at this point.
+    async fn background_fetch(&self, timeout_in_seconds: f64) -> Result<()> {
+        ensure!(
+            timeout_in_seconds.is_finite() && timeout_in_seconds >= 0.0,
+            "invalid timeout"
+        );
+        self.accounts
+            .read()
+            .await
+            .background_fetch(std::time::Duration::from_secs_f64(timeout_in_seconds))
+            .await;
+        Ok(())
+    }
+
+    /// Set the push device token for all accounts
+    /// and register it with the notification proxy set in the `push_proxy_url` config option.
+    ///
+    /// Should be called on every app start as the token may change.
+    async fn set_push_device_token(&self, token: String) -> Result<()> {
+        self.accounts
+            .read()
+            .await
+            .set_push_device_token(&token)
+            .await
+    }
+
+    /// Fetch new messages of the account a push payload is meant for,
+    /// giving up after `timeout_in_seconds`.
+    ///
+    /// Returns the ID of the account,
+    /// `BackgroundFetchDone` event is emitted for this account when done.
+    async fn handle_push_payload(&self, payload: String, timeout_in_seconds: f64) -> Result<u32> {
+        ensure!(
+            timeout_in_seconds.is_finite() && timeout_in_seconds >= 0.0,
+            "invalid timeout"
+        );
+        self.accounts
+            .read()
+            .await
+            .handle_push_payload(
+                &payload,
+                std::time::Duration::from_secs_f64(timeout_in_seconds),
+            )
+            .await
+    }
+
     /// Get the current connectivity, i.e. whether the device is connected to the IMAP server.
     /// One of:
     /// - DC_CONNECTIVITY_NOT_CONNECTED (1000-1999): Show e.g. the string "Not connected" or a red dot
@@ -1610,6 +2258,14 @@ async fn get_connectivity_html(&self, account_id: u32) -> Result<String> {
         ctx.get_connectivity_html().await
     }
 
+    /// Get an overview of the connectivity of all accounts,
+    /// with one section per account as returned by `get_connectivity_html`.
+    ///
+    /// If this page changes, a #DC_EVENT_CONNECTIVITY_CHANGED will be emitted.
+    async fn get_all_accounts_connectivity_html(&self) -> Result<String> {
+        self.accounts.read().await.get_connectivity_html().await
+    }
+
     // ---------------------------------------------
     //                  locations
     // ---------------------------------------------
@@ -1627,7 +2283,7 @@ async fn get_locations(
         let locations = location::get_range(
             &ctx,
             chat_id.map(ChatId::new),
-            contact_id,
+            contact_id.map(ContactId::new),
             timestamp_begin,
             timestamp_end,
         )
@@ -1666,6 +2322,35 @@ async fn get_webxdc_status_updates(
         .await
     }
 
+    /// Joins the realtime channel of a webxdc instance and advertises it to the chat members.
+    async fn send_webxdc_realtime_advertisement(
+        &self,
+        account_id: u32,
+        instance_msg_id: u32,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        peer_channels::send_webxdc_realtime_advertisement(&ctx, MsgId::new(instance_msg_id)).await
+    }
+
+    /// Sends data to the members of the realtime channel of a webxdc instance.
+    ///
+    /// Other members get the data with the `WebxdcRealtimeData` event.
+    async fn send_webxdc_realtime_data(
+        &self,
+        account_id: u32,
+        instance_msg_id: u32,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        peer_channels::send_webxdc_realtime_data(&ctx, MsgId::new(instance_msg_id), data).await
+    }
+
+    /// Leaves the realtime channel of a webxdc instance.
+    async fn leave_webxdc_realtime(&self, account_id: u32, instance_msg_id: u32) -> Result<()> {
+        let ctx = self.get_context(account_id).await?;
+        peer_channels::leave_webxdc_realtime(&ctx, MsgId::new(instance_msg_id)).await
+    }
+
     /// Get info from a webxdc message
     async fn get_webxdc_info(
         &self,
@@ -1792,7 +2477,12 @@ async fn send_msg(&self, account_id: u32, chat_id: u32, data: MessageData) -> Re
         } else {
             Viewtype::Text
         });
-        message.set_text(data.text.unwrap_or_default());
+        if let Some(spans) = data.markup {
+            let spans: Vec<_> = spans.into_iter().map(Into::into).collect();
+            message.set_text_with_markup(&spans);
+        } else {
+            message.set_text(data.text.unwrap_or_default());
+        }
         if data.html.is_some() {
             message.set_html(data.html);
         }
@@ -1805,6 +2495,9 @@ async fn send_msg(&self, account_id: u32, chat_id: u32, data: MessageData) -> Re
         if let Some((latitude, longitude)) = data.location {
             message.set_location(latitude, longitude);
         }
+        if let Some(delivery_ttl) = data.delivery_ttl {
+            message.set_delivery_ttl(delivery_ttl);
+        }
         if let Some(id) = data.quoted_message_id {
             message
                 .set_quote(
@@ -2115,6 +2808,25 @@ async fn get_config(
     }
 }
 
+/// Converts the arguments of the `get_chat_media*` methods to core types.
+fn media_query_args(
+    chat_id: Option<u32>,
+    message_type: MessageViewtype,
+    or_message_type2: Option<MessageViewtype>,
+    or_message_type3: Option<MessageViewtype>,
+) -> (Option<ChatId>, Viewtype, Viewtype, Viewtype) {
+    let chat_id = match chat_id {
+        None | Some(0) => None,
+        Some(id) => Some(ChatId::new(id)),
+    };
+    (
+        chat_id,
+        message_type.into(),
+        or_message_type2.map_or(Viewtype::Unknown, |v| v.into()),
+        or_message_type3.map_or(Viewtype::Unknown, |v| v.into()),
+    )
+}
+
 /// Whether a QR code for a BackupProvider is currently available.
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
@@ -31,13 +31,18 @@ pub struct FullChat {
     fresh_message_counter: usize,
     // is_group - please check over chat.type in frontend instead
     is_contact_request: bool,
+    is_spam: bool,
     is_device_chat: bool,
+    /// True if the chat is a read-only channel where only the owner may post.
+    is_channel: bool,
     self_in_group: bool,
     is_muted: bool,
     ephemeral_timer: u32, //TODO look if there are more important properties in newer core versions
     can_send: bool,
     was_seen_recently: bool,
     mailing_list_address: Option<String>,
+    /// Last error sending a message to the chat, cleared on success.
+    last_error: Option<String>,
 }
 
 impl FullChat {
@@ -100,13 +105,16 @@ pub async fn try_from_dc_chat_id(context: &Context, chat_id: u32) -> Result<Self
             color,
             fresh_message_counter,
             is_contact_request: chat.is_contact_request(),
+            is_spam: chat.is_spam(),
             is_device_chat: chat.is_device_talk(),
+            is_channel: chat.is_channel(),
             self_in_group: contact_ids.contains(&ContactId::SELF),
             is_muted: chat.is_muted(),
             ephemeral_timer,
             can_send,
             was_seen_recently,
             mailing_list_address,
+            last_error: chat.get_last_error().map(|s| s.to_string()),
         })
     }
 }
@@ -135,6 +143,7 @@ pub struct BasicChat {
     color: String,
     is_contact_request: bool,
     is_device_chat: bool,
+    is_channel: bool,
     is_muted: bool,
 }
 
@@ -161,6 +170,7 @@ pub async fn try_from_dc_chat_id(context: &Context, chat_id: u32) -> Result<Self
             color,
             is_contact_request: chat.is_contact_request(),
             is_device_chat: chat.is_device_talk(),
+            is_channel: chat.is_channel(),
             is_muted: chat.is_muted(),
         })
     }
@@ -209,3 +219,53 @@ pub fn into_core_type(self) -> ChatVisibility {
         }
     }
 }
+
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename = "ChatEncryption")]
+pub enum JSONRPCChatEncryption {
+    Yes,
+    Partially,
+    No,
+}
+
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberEncryptionInfo {
+    contact_id: u32,
+    addr: String,
+    /// Hex fingerprint of the member's key, `None` if no key is known.
+    fingerprint: Option<String>,
+    verified: bool,
+    /// One of "mutual", "nopreference" or "reset".
+    prefer_encrypt: String,
+}
+
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatEncryptionInfo {
+    members: Vec<MemberEncryptionInfo>,
+    encryption: JSONRPCChatEncryption,
+}
+
+impl From<chat::ChatEncryptionInfo> for ChatEncryptionInfo {
+    fn from(info: chat::ChatEncryptionInfo) -> Self {
+        ChatEncryptionInfo {
+            members: info
+                .members
+                .into_iter()
+                .map(|member| MemberEncryptionInfo {
+                    contact_id: member.contact_id.to_u32(),
+                    addr: member.addr,
+                    fingerprint: member.fingerprint,
+                    verified: member.verified,
+                    prefer_encrypt: member.prefer_encrypt.to_string(),
+                })
+                .collect(),
+            encryption: match info.encryption {
+                chat::ChatEncryption::Yes => JSONRPCChatEncryption::Yes,
+                chat::ChatEncryption::Partially => JSONRPCChatEncryption::Partially,
+                chat::ChatEncryption::No => JSONRPCChatEncryption::No,
+            },
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
-use deltachat::contact::VerifiedStatus;
+use deltachat::contact::{RemoteContentDecision, VerifiedStatus};
 use deltachat::context::Context;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use typescript_type_def::TypeDef;
 
 use super::color_int_to_hex_string;
@@ -27,6 +27,8 @@ pub struct ContactObject {
     /// the contact's last seen timestamp
     last_seen: i64,
     was_seen_recently: bool,
+    /// Last error sending a message to the contact, cleared on success.
+    last_error: Option<String>,
 }
 
 impl ContactObject {
@@ -68,6 +70,72 @@ pub async fn try_from_dc_contact(
             verifier_id,
             last_seen: contact.last_seen(),
             was_seen_recently: contact.was_seen_recently(),
+            last_error: contact.get_last_error().map(|s| s.to_string()),
         })
     }
 }
+
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename = "ContactStatusSummary", rename_all = "camelCase")]
+pub struct ContactStatusSummaryObject {
+    /// timestamp of the last message from the contact, 0 if never seen
+    last_seen: i64,
+    /// IMAP folder the last incoming message from the contact arrived in
+    last_seen_folder: Option<String>,
+    /// timestamp of the last encrypted message from the contact
+    last_encrypted: i64,
+    /// timestamp of the last unencrypted message from the contact
+    last_unencrypted: i64,
+    /// timestamp of the last message signed with the verified key of the contact
+    last_verified: i64,
+    /// whether the last message from the contact was encrypted
+    is_encrypted: bool,
+    /// whether the last message from the contact was signed with their verified key
+    is_verified: bool,
+}
+
+impl From<deltachat::contact::ContactStatusSummary> for ContactStatusSummaryObject {
+    fn from(summary: deltachat::contact::ContactStatusSummary) -> Self {
+        ContactStatusSummaryObject {
+            is_encrypted: summary.is_encrypted(),
+            is_verified: summary.is_verified(),
+            last_seen: summary.last_seen,
+            last_seen_folder: summary.last_seen_folder,
+            last_encrypted: summary.last_encrypted,
+            last_unencrypted: summary.last_unencrypted,
+            last_verified: summary.last_verified,
+        }
+    }
+}
+
+/// Whether remote content of HTML messages from a contact is loaded.
+#[derive(Serialize, Deserialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename = "RemoteContentDecision")]
+pub enum JSONRPCRemoteContentDecision {
+    /// The `remote_content` config option applies.
+    Undecided,
+    /// Always load remote content unless `remote_content` is set to never load it.
+    Allow,
+    /// Never load remote content automatically.
+    Block,
+}
+
+impl From<RemoteContentDecision> for JSONRPCRemoteContentDecision {
+    fn from(decision: RemoteContentDecision) -> Self {
+        match decision {
+            RemoteContentDecision::Undecided => JSONRPCRemoteContentDecision::Undecided,
+            RemoteContentDecision::Allow => JSONRPCRemoteContentDecision::Allow,
+            RemoteContentDecision::Block => JSONRPCRemoteContentDecision::Block,
+        }
+    }
+}
+
+impl From<JSONRPCRemoteContentDecision> for RemoteContentDecision {
+    fn from(decision: JSONRPCRemoteContentDecision) -> Self {
+        match decision {
+            JSONRPCRemoteContentDecision::Undecided => RemoteContentDecision::Undecided,
+            JSONRPCRemoteContentDecision::Allow => RemoteContentDecision::Allow,
+            JSONRPCRemoteContentDecision::Block => RemoteContentDecision::Block,
+        }
+    }
+}
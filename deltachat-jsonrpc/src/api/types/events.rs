@@ -208,6 +208,13 @@ pub enum EventType {
         contact_id: Option<u32>,
     },
 
+    /// Inform about the progress of importing a large address book.
+    ///
+    /// @param data1 (usize) 1-999=progress in permille, 1000=done
+    ContactsImportProgress {
+        progress: usize,
+    },
+
     /// Location of one or more contact has changed.
     ///
     /// @param data1 (u32) contact_id of the contact for which the location has changed.
@@ -227,6 +234,10 @@ pub enum EventType {
 
         /// Progress comment or error, something to display to the user.
         comment: Option<String>,
+
+        /// Step of the online autoconfiguration the event is about, if any:
+        /// `Autoconfig`, `Autodiscover`, `Srv` or `Ispdb`.
+        step: Option<String>,
     },
 
     /// Inform about the import/export progress started by imex().
@@ -290,6 +301,13 @@ pub enum EventType {
 
     SelfavatarChanged,
 
+    /// A setting changed, either by a call to `setConfig()`
+    /// or as a result of a message from another device.
+    ConfigChanged {
+        /// Name of the changed setting, e.g. `displayname`.
+        key: String,
+    },
+
     #[serde(rename_all = "camelCase")]
     WebxdcStatusUpdate {
         msg_id: u32,
@@ -301,6 +319,78 @@ pub enum EventType {
     WebxdcInstanceDeleted {
         msg_id: u32,
     },
+
+    /// Data received over the realtime channel of a webxdc instance.
+    #[serde(rename_all = "camelCase")]
+    WebxdcRealtimeData {
+        msg_id: u32,
+        /// ID of the chat member who sent the data.
+        contact_id: u32,
+        data: Vec<u8>,
+    },
+
+    /// Remote content of an HTML message was not loaded by `getMessageSanitizedHtml()`.
+    ///
+    /// The UI may ask the user whether to load it once
+    /// or to set the remote content decision for the sender.
+    #[serde(rename_all = "camelCase")]
+    RemoteContentBlocked {
+        msg_id: u32,
+        contact_id: u32,
+    },
+
+    /// Background fetch started by `backgroundFetch()` is done,
+    /// either because all messages were fetched and sent or because the timeout was reached.
+    ///
+    /// Emitted for each account and additionally with context ID 0
+    /// when all accounts are done.
+    BackgroundFetchDone,
+
+    /// Inform about the progress of downloading many messages,
+    /// e.g. after being offline for a long time.
+    ///
+    /// Sent for each message if there are more new messages in a folder
+    /// than fit into one batch, and when existing messages are fetched,
+    /// e.g. to import the mail history.
+    FetchProgress {
+        /// Number of new messages downloaded or skipped so far.
+        fetched: usize,
+
+        /// Total number of new messages.
+        total: usize,
+    },
+
+    /// Inform about the progress of importing the mail history after configuring,
+    /// see the `import_history_days` config option.
+    ///
+    /// Progress within a folder is reported by `FetchProgress`.
+    ///
+    /// @param data1 (usize) 0=error or cancelled, 1-999=progress in permille, 1000=done
+    HistoryImportProgress {
+        progress: usize,
+    },
+
+    /// A queued job was given up and removed from the queue,
+    /// either because it failed permanently or exceeded its retry limit.
+    #[serde(rename_all = "camelCase")]
+    JobFailed {
+        /// Kind of the job, e.g. `SendMsg`, `Download` or `SendMdn`.
+        job_kind: String,
+        msg_id: u32,
+        error: String,
+    },
+
+    /// Inconsistent entries were found and repaired when opening the database
+    /// or during housekeeping.
+    #[serde(rename_all = "camelCase")]
+    DatabaseRepaired {
+        /// Number of messages whose file is missing, they are marked with an error.
+        missing_files: usize,
+        /// Number of repaired or deleted 1:1 chats without members.
+        chats: usize,
+        /// Number of removed reactions and jobs referring to deleted messages.
+        orphans: usize,
+    },
 }
 
 impl From<CoreEventType> for EventType {
@@ -370,12 +460,19 @@ fn from(event: CoreEventType) -> Self {
             CoreEventType::ContactsChanged(contact) => ContactsChanged {
                 contact_id: contact.map(|c| c.to_u32()),
             },
+            CoreEventType::ContactsImportProgress(progress) => ContactsImportProgress { progress },
             CoreEventType::LocationChanged(contact) => LocationChanged {
                 contact_id: contact.map(|c| c.to_u32()),
             },
-            CoreEventType::ConfigureProgress { progress, comment } => {
-                ConfigureProgress { progress, comment }
-            }
+            CoreEventType::ConfigureProgress {
+                progress,
+                comment,
+                step,
+            } => ConfigureProgress {
+                progress,
+                comment,
+                step: step.map(|step| step.to_string()),
+            },
             CoreEventType::ImexProgress(progress) => ImexProgress { progress },
             CoreEventType::ImexFileWritten(path) => ImexFileWritten {
                 path: path.to_str().unwrap_or_default().to_owned(),
@@ -396,6 +493,9 @@ fn from(event: CoreEventType) -> Self {
             },
             CoreEventType::ConnectivityChanged => ConnectivityChanged,
             CoreEventType::SelfavatarChanged => SelfavatarChanged,
+            CoreEventType::ConfigChanged(key) => ConfigChanged {
+                key: key.to_string(),
+            },
             CoreEventType::WebxdcStatusUpdate {
                 msg_id,
                 status_update_serial,
@@ -406,6 +506,40 @@ fn from(event: CoreEventType) -> Self {
             CoreEventType::WebxdcInstanceDeleted { msg_id } => WebxdcInstanceDeleted {
                 msg_id: msg_id.to_u32(),
             },
+            CoreEventType::WebxdcRealtimeData {
+                msg_id,
+                contact_id,
+                data,
+            } => WebxdcRealtimeData {
+                msg_id: msg_id.to_u32(),
+                contact_id: contact_id.to_u32(),
+                data,
+            },
+            CoreEventType::RemoteContentBlocked { msg_id, contact_id } => RemoteContentBlocked {
+                msg_id: msg_id.to_u32(),
+                contact_id: contact_id.to_u32(),
+            },
+            CoreEventType::BackgroundFetchDone => BackgroundFetchDone,
+            CoreEventType::FetchProgress { fetched, total } => FetchProgress { fetched, total },
+            CoreEventType::HistoryImportProgress(progress) => HistoryImportProgress { progress },
+            CoreEventType::JobFailed {
+                kind,
+                msg_id,
+                error,
+            } => JobFailed {
+                job_kind: kind.to_string(),
+                msg_id: msg_id.to_u32(),
+                error,
+            },
+            CoreEventType::DatabaseRepaired {
+                missing_files,
+                chats,
+                orphans,
+            } => DatabaseRepaired {
+                missing_files,
+                chats,
+                orphans,
+            },
         }
     }
 }
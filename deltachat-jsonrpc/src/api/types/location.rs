@@ -39,7 +39,7 @@ fn from(location: Location) -> Self {
             accuracy,
             timestamp,
             contact_id: contact_id.to_u32(),
-            msg_id,
+            msg_id: msg_id.to_u32(),
             chat_id: chat_id.to_u32(),
             marker,
         }
@@ -0,0 +1,20 @@
+use serde::Serialize;
+use typescript_type_def::TypeDef;
+
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginCheck {
+    /// IMAP login error, `null` if IMAP login succeeded.
+    pub imap_error: Option<String>,
+    /// SMTP login error, `null` if SMTP login succeeded.
+    pub smtp_error: Option<String>,
+}
+
+impl From<deltachat::LoginCheck> for LoginCheck {
+    fn from(check: deltachat::LoginCheck) -> Self {
+        LoginCheck {
+            imap_error: check.imap_error,
+            smtp_error: check.smtp_error,
+        }
+    }
+}
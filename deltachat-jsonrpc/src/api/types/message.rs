@@ -1,13 +1,18 @@
 use anyhow::{Context as _, Result};
 use deltachat::chat::Chat;
+use deltachat::chat::ChatId;
 use deltachat::chat::ChatItem;
 use deltachat::chat::ChatVisibility;
 use deltachat::contact::Contact;
+use deltachat::contact::ContactId;
 use deltachat::context::Context;
+use deltachat::context::SearchFilter;
 use deltachat::download;
+use deltachat::markup;
 use deltachat::message::Message;
 use deltachat::message::MsgId;
 use deltachat::message::Viewtype;
+use deltachat::notification::Notification;
 use deltachat::reaction::get_msg_reactions;
 use num_traits::cast::ToPrimitive;
 use serde::{Deserialize, Serialize};
@@ -35,6 +40,8 @@ pub struct MessageObject {
     parent_id: Option<u32>,
 
     text: String,
+    /// Ranges of `text` with markup, e.g. bold or italic text.
+    markup: Vec<MarkupRange>,
     has_location: bool,
     has_html: bool,
     view_type: MessageViewtype,
@@ -46,6 +53,7 @@ pub struct MessageObject {
     timestamp: i64,
     sort_timestamp: i64,
     received_timestamp: i64,
+    server_timestamp: i64,
     has_deviating_timestamp: bool,
 
     // summary - use/create another function if you need it
@@ -58,12 +66,21 @@ pub struct MessageObject {
     /// True if the message was sent by a bot.
     is_bot: bool,
 
+    /// True if the message is an automatic reply, e.g. a vacation notice.
+    is_auto_reply: bool,
+
     /// when is_info is true this describes what type of system message it is
     system_message_type: SystemMessageType,
+    /// Contact added or removed by a membership change system message.
+    info_contact_id: Option<u32>,
+    /// Contact who performed the group change described by a system message.
+    info_actor_id: Option<u32>,
 
     duration: i32,
     dimensions_height: i32,
     dimensions_width: i32,
+    /// True if the image has more than one frame, e.g. an animated GIF or sticker.
+    is_animated: bool,
 
     videochat_type: Option<u32>,
     videochat_url: Option<String>,
@@ -181,6 +198,7 @@ pub async fn from_msg_id(context: &Context, msg_id: MsgId) -> Result<Self> {
             quote,
             parent_id,
             text: message.get_text(),
+            markup: message.get_markup().into_iter().map(Into::into).collect(),
             has_location: message.has_location(),
             has_html: message.has_html(),
             view_type: message.get_viewtype().into(),
@@ -193,6 +211,7 @@ pub async fn from_msg_id(context: &Context, msg_id: MsgId) -> Result<Self> {
             timestamp: message.get_timestamp(),
             sort_timestamp: message.get_sort_timestamp(),
             received_timestamp: message.get_received_timestamp(),
+            server_timestamp: message.get_server_timestamp(),
             has_deviating_timestamp: message.has_deviating_timestamp(),
 
             subject: message.get_subject().to_owned(),
@@ -201,11 +220,20 @@ pub async fn from_msg_id(context: &Context, msg_id: MsgId) -> Result<Self> {
             is_info: message.is_info(),
             is_forwarded: message.is_forwarded(),
             is_bot: message.is_bot(),
+            is_auto_reply: message.is_auto_reply(),
             system_message_type: message.get_info_type().into(),
+            info_contact_id: message
+                .get_info_contact_id(context)
+                .await?
+                .map(|contact_id| contact_id.to_u32()),
+            info_actor_id: message
+                .get_info_actor_id()
+                .map(|contact_id| contact_id.to_u32()),
 
             duration: message.get_duration(),
             dimensions_height: message.get_height(),
             dimensions_width: message.get_width(),
+            is_animated: message.is_animated(),
 
             videochat_type: match message.get_videochat_type() {
                 Some(vct) => Some(
@@ -313,6 +341,75 @@ fn from(viewtype: MessageViewtype) -> Self {
     }
 }
 
+#[derive(Serialize, Deserialize, TypeDef, schemars::JsonSchema)]
+pub enum MarkupStyle {
+    Bold,
+    Italic,
+    Strikethrough,
+    Code,
+}
+
+impl From<markup::MarkupStyle> for MarkupStyle {
+    fn from(style: markup::MarkupStyle) -> Self {
+        match style {
+            markup::MarkupStyle::Bold => MarkupStyle::Bold,
+            markup::MarkupStyle::Italic => MarkupStyle::Italic,
+            markup::MarkupStyle::Strikethrough => MarkupStyle::Strikethrough,
+            markup::MarkupStyle::Code => MarkupStyle::Code,
+        }
+    }
+}
+
+impl From<MarkupStyle> for markup::MarkupStyle {
+    fn from(style: MarkupStyle) -> Self {
+        match style {
+            MarkupStyle::Bold => markup::MarkupStyle::Bold,
+            MarkupStyle::Italic => markup::MarkupStyle::Italic,
+            MarkupStyle::Strikethrough => markup::MarkupStyle::Strikethrough,
+            MarkupStyle::Code => markup::MarkupStyle::Code,
+        }
+    }
+}
+
+/// Range of a message text with markup.
+///
+/// `start` and `end` count Unicode code points.
+/// The markers are the characters right before `start` and at `end`.
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkupRange {
+    pub style: MarkupStyle,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl From<markup::MarkupRange> for MarkupRange {
+    fn from(range: markup::MarkupRange) -> Self {
+        MarkupRange {
+            style: range.style.into(),
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+/// Part of a message text with an optional style.
+#[derive(Deserialize, Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkupSpan {
+    pub text: String,
+    pub style: Option<MarkupStyle>,
+}
+
+impl From<MarkupSpan> for markup::MarkupSpan {
+    fn from(span: MarkupSpan) -> Self {
+        markup::MarkupSpan {
+            text: span.text,
+            style: span.style.map(Into::into),
+        }
+    }
+}
+
 #[derive(Serialize, TypeDef, schemars::JsonSchema)]
 pub enum DownloadState {
     Done,
@@ -353,6 +450,9 @@ pub enum SystemMessageType {
     ChatProtectionEnabled,
     ChatProtectionDisabled,
 
+    /// Group admins changed.
+    GroupAdminsChanged,
+
     /// Self-sent-message that contains only json used for multi-device-sync;
     /// if possible, we attach that to other messages as for locations.
     MultiDeviceSync,
@@ -362,8 +462,14 @@ pub enum SystemMessageType {
     // These messages are not shown in the chat.
     WebxdcStatusUpdate,
 
+    /// Hidden message advertising a realtime channel of a webxdc instance.
+    WebxdcRealtimeAdvertisement,
+
     /// Webxdc info added with `info` set in `send_webxdc_status_update()`.
     WebxdcInfoMessage,
+
+    /// Hidden message announcing a new key of the sender.
+    KeyTransition,
 }
 
 impl From<deltachat::mimeparser::SystemMessage> for SystemMessageType {
@@ -382,9 +488,14 @@ fn from(system_message_type: deltachat::mimeparser::SystemMessage) -> Self {
             SystemMessage::EphemeralTimerChanged => SystemMessageType::EphemeralTimerChanged,
             SystemMessage::ChatProtectionEnabled => SystemMessageType::ChatProtectionEnabled,
             SystemMessage::ChatProtectionDisabled => SystemMessageType::ChatProtectionDisabled,
+            SystemMessage::GroupAdminsChanged => SystemMessageType::GroupAdminsChanged,
             SystemMessage::MultiDeviceSync => SystemMessageType::MultiDeviceSync,
             SystemMessage::WebxdcStatusUpdate => SystemMessageType::WebxdcStatusUpdate,
+            SystemMessage::WebxdcRealtimeAdvertisement => {
+                SystemMessageType::WebxdcRealtimeAdvertisement
+            }
             SystemMessage::WebxdcInfoMessage => SystemMessageType::WebxdcInfoMessage,
+            SystemMessage::KeyTransition => SystemMessageType::KeyTransition,
         }
     }
 }
@@ -538,12 +649,48 @@ fn from(item: ChatItem) -> Self {
 #[serde(rename_all = "camelCase")]
 pub struct MessageData {
     pub text: Option<String>,
+    /// Text with markup, used instead of `text` if set.
+    pub markup: Option<Vec<MarkupSpan>>,
     pub html: Option<String>,
     pub viewtype: Option<MessageViewtype>,
     pub file: Option<String>,
     pub location: Option<(f64, f64)>,
     pub override_sender_name: Option<String>,
     pub quoted_message_id: Option<u32>,
+    /// Seconds after which the message is marked as failed
+    /// instead of being sent if it could not be delivered yet.
+    pub delivery_ttl: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSearchFilter {
+    /// Only search in this chat.
+    pub chat_id: Option<u32>,
+    /// Only return messages from this contact.
+    pub from_id: Option<u32>,
+    /// Only return messages with a timestamp at or after this time.
+    pub after: Option<i64>,
+    /// Only return messages with a timestamp before this time.
+    pub before: Option<i64>,
+    /// Only return messages of this type.
+    pub viewtype: Option<MessageViewtype>,
+    /// Only return messages with an attached file.
+    #[serde(default)]
+    pub has_attachment: bool,
+}
+
+impl From<MessageSearchFilter> for SearchFilter {
+    fn from(filter: MessageSearchFilter) -> Self {
+        SearchFilter {
+            chat_id: filter.chat_id.map(ChatId::new),
+            from_id: filter.from_id.map(ContactId::new),
+            after: filter.after,
+            before: filter.before,
+            viewtype: filter.viewtype.map(Into::into),
+            has_attachment: filter.has_attachment,
+        }
+    }
 }
 
 #[derive(Serialize, TypeDef, schemars::JsonSchema)]
@@ -552,3 +699,54 @@ pub struct MessageReadReceipt {
     pub contact_id: u32,
     pub timestamp: i64,
 }
+
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageAuditEntry {
+    pub timestamp: i64,
+    pub operation: String,
+}
+
+/// HTML of a message made safe to display.
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSanitizedHtml {
+    pub html: String,
+    /// True if remote content was removed, the UI may offer to load it.
+    pub blocked_remote_content: bool,
+}
+
+/// Notification to show for an incoming message.
+#[derive(Serialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageNotification {
+    pub chat_id: u32,
+    pub msg_id: u32,
+    /// Usually the chat name.
+    pub title: String,
+    /// Usually the message summary, prefixed by the sender name in groups.
+    pub body: String,
+    /// True if the message mentions the user in a group, the UI may highlight the notification.
+    pub is_mention: bool,
+}
+
+impl From<Notification> for MessageNotification {
+    fn from(notification: Notification) -> Self {
+        MessageNotification {
+            chat_id: notification.chat_id.to_u32(),
+            msg_id: notification.msg_id.to_u32(),
+            title: notification.title,
+            body: notification.body,
+            is_mention: notification.is_mention,
+        }
+    }
+}
+
+impl From<deltachat::html::SanitizedHtml> for MessageSanitizedHtml {
+    fn from(sanitized: deltachat::html::SanitizedHtml) -> Self {
+        MessageSanitizedHtml {
+            html: sanitized.html,
+            blocked_remote_content: sanitized.blocked_remote_content,
+        }
+    }
+}
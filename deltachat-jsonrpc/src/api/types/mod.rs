@@ -5,10 +5,12 @@
 pub mod events;
 pub mod http;
 pub mod location;
+pub mod login_check;
 pub mod message;
 pub mod provider_info;
 pub mod qr;
 pub mod reactions;
+pub mod stock_strings;
 pub mod webxdc;
 
 pub fn color_int_to_hex_string(color: u32) -> String {
@@ -9,6 +9,10 @@ pub struct ProviderInfo {
     pub before_login_hint: String,
     pub overview_page: String,
     pub status: u32, // in reality this is an enum, but for simplicity and because it gets converted into a number anyway, we use an u32 here.
+    /// True if an app password has to be created in the web interface of the provider to log in.
+    pub app_password_required: bool,
+    /// True if the provider supports logging in using OAuth2.
+    pub oauth2_available: bool,
 }
 
 impl ProviderInfo {
@@ -17,6 +21,8 @@ pub fn from_dc_type(provider: Option<&Provider>) -> Option<Self> {
             before_login_hint: p.before_login_hint.to_owned(),
             overview_page: p.overview_page.to_owned(),
             status: p.status.to_u32().unwrap(),
+            app_password_required: p.opt.app_password_required,
+            oauth2_available: p.oauth2_authorizer.is_some(),
         })
     }
 }
@@ -43,6 +43,14 @@ pub enum QrObject {
         contact_id: u32,
         draft: Option<String>,
     },
+    Proxy {
+        url: String,
+        host: String,
+        port: u16,
+    },
+    WebxdcShare {
+        url: String,
+    },
     Url {
         url: String,
     },
@@ -80,6 +88,7 @@ pub enum QrObject {
     Login {
         address: String,
     },
+    Provisioning,
 }
 
 impl From<Qr> for QrObject {
@@ -143,6 +152,8 @@ fn from(qr: Qr) -> Self {
                 let contact_id = contact_id.to_u32();
                 QrObject::Addr { contact_id, draft }
             }
+            Qr::Proxy { url, host, port } => QrObject::Proxy { url, host, port },
+            Qr::WebxdcShare { url } => QrObject::WebxdcShare { url },
             Qr::Url { url } => QrObject::Url { url },
             Qr::Text { text } => QrObject::Text { text },
             Qr::WithdrawVerifyContact {
@@ -214,6 +225,7 @@ fn from(qr: Qr) -> Self {
                 }
             }
             Qr::Login { address, .. } => QrObject::Login { address },
+            Qr::Provisioning => QrObject::Provisioning,
         }
     }
 }
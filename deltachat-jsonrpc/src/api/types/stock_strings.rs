@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use deltachat::stock_str::PluralCategory;
+use serde::Deserialize;
+use typescript_type_def::TypeDef;
+
+#[derive(Deserialize, TypeDef, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StockTranslation {
+    /// BCP 47 language tag, e.g. `de` or `pt-BR`, selecting the plural rules.
+    pub lang: String,
+    /// Map from stock string ID to the translation.
+    #[serde(default)]
+    pub strings: HashMap<u32, String>,
+    /// Map from stock string ID to the plural forms of the translation,
+    /// keyed by `zero`, `one`, `two`, `few`, `many` or `other`.
+    #[serde(default)]
+    pub plurals: HashMap<u32, HashMap<String, String>>,
+}
+
+impl TryFrom<StockTranslation> for deltachat::stock_str::StockTranslation {
+    type Error = anyhow::Error;
+
+    fn try_from(translation: StockTranslation) -> Result<Self> {
+        let mut plurals = HashMap::new();
+        for (id, forms) in translation.plurals {
+            let mut categories = HashMap::new();
+            for (category, form) in forms {
+                let category = match category.as_str() {
+                    "zero" => PluralCategory::Zero,
+                    "one" => PluralCategory::One,
+                    "two" => PluralCategory::Two,
+                    "few" => PluralCategory::Few,
+                    "many" => PluralCategory::Many,
+                    "other" => PluralCategory::Other,
+                    _ => bail!("Invalid plural category {category:?}"),
+                };
+                categories.insert(category, form);
+            }
+            plurals.insert(id, categories);
+        }
+        Ok(Self {
+            lang: translation.lang,
+            strings: translation.strings,
+            plurals,
+        })
+    }
+}
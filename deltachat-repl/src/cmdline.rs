@@ -22,7 +22,6 @@
 use deltachat::peerstate::*;
 use deltachat::qr::*;
 use deltachat::reaction::send_reaction;
-use deltachat::receive_imf::*;
 use deltachat::sql;
 use deltachat::tools::*;
 use deltachat::{config, provider};
@@ -99,10 +98,8 @@ async fn reset_tables(context: &Context, bits: i32) {
 }
 
 async fn poke_eml_file(context: &Context, filename: impl AsRef<Path>) -> Result<()> {
-    let data = read_file(context, filename).await?;
-
-    if let Err(err) = receive_imf(context, &data, false).await {
-        println!("receive_imf errored: {err:?}");
+    if let Err(err) = receive_eml_file(context, filename.as_ref()).await {
+        println!("receive_eml_file errored: {err:?}");
     }
     Ok(())
 }
@@ -253,7 +250,7 @@ async fn log_msg(context: &Context, prefix: impl AsRef<str>, msg: &Message) {
 async fn log_msglist(context: &Context, msglist: &[MsgId]) -> Result<()> {
     let mut lines_out = 0;
     for &msg_id in msglist {
-        if msg_id == MsgId::new(DC_MSG_ID_DAYMARKER) {
+        if msg_id == MsgId::DAYMARKER {
             println!(
                 "--------------------------------------------------------------------------------"
             );
@@ -433,6 +430,7 @@ pub async fn cmdline(context: Context, line: &str, chat_id: &mut ChatId) -> Resu
                  ======================================Misc.==\n\
                  getqr [<chat-id>]\n\
                  getqrsvg [<chat-id>]\n\
+                 getqrpng [<chat-id>]\n\
                  getbadqr\n\
                  checkqr <qr-content>\n\
                  joinqr <qr-content>\n\
@@ -685,7 +683,7 @@ pub async fn cmdline(context: Context, line: &str, chat_id: &mut ChatId) -> Resu
                 .into_iter()
                 .map(|x| match x {
                     ChatItem::Message { msg_id } => msg_id,
-                    ChatItem::DayMarker { .. } => MsgId::new(DC_MSG_ID_DAYMARKER),
+                    ChatItem::DayMarker { .. } => MsgId::DAYMARKER,
                 })
                 .collect();
 
@@ -848,7 +846,7 @@ pub async fn cmdline(context: Context, line: &str, chat_id: &mut ChatId) -> Resu
         "getlocations" => {
             ensure!(sel_chat.is_some(), "No chat selected.");
 
-            let contact_id: Option<u32> = arg1.parse().ok();
+            let contact_id: Option<ContactId> = arg1.parse::<u32>().ok().map(ContactId::new);
             let locations = location::get_range(
                 &context,
                 Some(sel_chat.as_ref().unwrap().get_id()),
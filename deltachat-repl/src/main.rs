@@ -18,7 +18,7 @@
 use deltachat::config;
 use deltachat::context::*;
 use deltachat::oauth2::*;
-use deltachat::qr_code_generator::get_securejoin_qr_svg;
+use deltachat::qr_code_generator::{get_securejoin_qr_png, get_securejoin_qr_svg};
 use deltachat::securejoin::*;
 use deltachat::stock_str::StockStrings;
 use deltachat::{EventType, Events};
@@ -92,7 +92,9 @@ fn receive_event(event: EventType) {
                 yellow.paint(format!("Received LOCATION_CHANGED(contact={contact:?})"))
             );
         }
-        EventType::ConfigureProgress { progress, comment } => {
+        EventType::ConfigureProgress {
+            progress, comment, ..
+        } => {
             if let Some(comment) = comment {
                 info!(
                     "{}",
@@ -242,9 +244,10 @@ fn complete(
     "unblock",
     "listblocked",
 ];
-const MISC_COMMANDS: [&str; 11] = [
+const MISC_COMMANDS: [&str; 12] = [
     "getqr",
     "getqrsvg",
+    "getqrpng",
     "getbadqr",
     "checkqr",
     "joinqr",
@@ -466,6 +469,20 @@ async fn handle_cmd(
                 }
             }
         }
+        "getqrpng" => {
+            ctx.start_io().await;
+            let group = arg1.parse::<u32>().ok().map(ChatId::new);
+            let file = dirs::home_dir().unwrap_or_default().join("qr.png");
+            match get_securejoin_qr_png(&ctx, group).await {
+                Ok(png) => {
+                    fs::write(&file, png).await?;
+                    println!("QR code png written to: {file:#?}");
+                }
+                Err(err) => {
+                    bail!("Failed to get QR code png: {}", err);
+                }
+            }
+        }
         "joinqr" => {
             ctx.start_io().await;
             if !arg0.is_empty() {
@@ -151,6 +151,7 @@ def get_chatlist(
         for_forwarding: bool = False,
         no_specials: bool = False,
         alldone_hint: bool = False,
+        spam_only: bool = False,
         snapshot: bool = False,
     ) -> Union[List[Chat], List[AttrDict]]:
         """Return list of chats.
@@ -163,6 +164,7 @@ def get_chatlist(
         :param no_specials: if True archive link is not added to the list.
         :param alldone_hint: if True the "all done hint" special chat will be added to the list
                              as needed.
+        :param spam_only: if True only contact requests classified as spam are returned.
         :param snapshot: If True return a list of chat snapshots instead of Chat instances.
         """
         flags = 0
@@ -174,6 +176,8 @@ def get_chatlist(
             flags |= ChatlistFlag.NO_SPECIALS
         if alldone_hint:
             flags |= ChatlistFlag.ADD_ALLDONE_HINT
+        if spam_only:
+            flags |= ChatlistFlag.SPAM_ONLY
 
         entries = self._rpc.get_chatlist_entries(self.id, flags, query, contact and contact.id)
         if not snapshot:
@@ -267,3 +271,8 @@ def export_backup(self, path, passphrase: str = "") -> None:
     def import_backup(self, path, passphrase: str = "") -> None:
         """Import backup."""
         self._rpc.import_backup(self.id, str(path), passphrase)
+
+    def import_eml_file(self, path) -> List[Message]:
+        """Import an .eml file into the chats and return the added messages."""
+        msg_ids = self._rpc.import_eml_file(self.id, str(path))
+        return [Message(self, msg_id) for msg_id in msg_ids]
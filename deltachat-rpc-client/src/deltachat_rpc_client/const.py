@@ -13,6 +13,7 @@ class ChatlistFlag(IntEnum):
     NO_SPECIALS = 0x02
     ADD_ALLDONE_HINT = 0x04
     FOR_FORWARDING = 0x08
+    SPAM_ONLY = 0x10
 
 
 class SpecialContactId(IntEnum):
@@ -49,6 +50,7 @@ class EventType(str, Enum):
     CHAT_MODIFIED = "ChatModified"
     CHAT_EPHEMERAL_TIMER_MODIFIED = "ChatEphemeralTimerModified"
     CONTACTS_CHANGED = "ContactsChanged"
+    CONTACTS_IMPORT_PROGRESS = "ContactsImportProgress"
     LOCATION_CHANGED = "LocationChanged"
     CONFIGURE_PROGRESS = "ConfigureProgress"
     IMEX_PROGRESS = "ImexProgress"
@@ -57,8 +59,16 @@ class EventType(str, Enum):
     SECUREJOIN_JOINER_PROGRESS = "SecurejoinJoinerProgress"
     CONNECTIVITY_CHANGED = "ConnectivityChanged"
     SELFAVATAR_CHANGED = "SelfavatarChanged"
+    CONFIG_CHANGED = "ConfigChanged"
     WEBXDC_STATUS_UPDATE = "WebxdcStatusUpdate"
     WEBXDC_INSTANCE_DELETED = "WebxdcInstanceDeleted"
+    WEBXDC_REALTIME_DATA = "WebxdcRealtimeData"
+    REMOTE_CONTENT_BLOCKED = "RemoteContentBlocked"
+    BACKGROUND_FETCH_DONE = "BackgroundFetchDone"
+    FETCH_PROGRESS = "FetchProgress"
+    HISTORY_IMPORT_PROGRESS = "HistoryImportProgress"
+    JOB_FAILED = "JobFailed"
+    DATABASE_REPAIRED = "DatabaseRepaired"
 
 
 class ChatType(IntEnum):
@@ -118,7 +128,9 @@ class SystemMessageType(str, Enum):
     LOCATION_ONLY = "LocationOnly"
     CHAT_PROTECTION_ENABLED = "ChatProtectionEnabled"
     CHAT_PROTECTION_DISABLED = "ChatProtectionDisabled"
+    GROUP_ADMINS_CHANGED = "GroupAdminsChanged"
     WEBXDC_STATUS_UPDATE = "WebxdcStatusUpdate"
+    WEBXDC_REALTIME_ADVERTISEMENT = "WebxdcRealtimeAdvertisement"
     EPHEMERAL_TIMER_CHANGED = "EphemeralTimerChanged"
     MULTI_DEVICE_SYNC = "MultiDeviceSync"
     WEBXDC_INFO_MESSAGE = "WebxdcInfoMessage"
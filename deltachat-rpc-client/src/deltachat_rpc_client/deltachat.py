@@ -47,3 +47,13 @@ def get_system_info(self) -> AttrDict:
     def set_translations(self, translations: Dict[str, str]) -> None:
         """Set stock translation strings."""
         self.rpc.set_stock_strings(translations)
+
+    def set_stock_translations(self, translations: List[dict]) -> None:
+        """Replace all stock translations.
+
+        Each translation is a dict with the language tag `lang`,
+        `strings` mapping stock string IDs to translations
+        and `plurals` mapping stock string IDs to plural forms,
+        in the order of preference.
+        """
+        self.rpc.set_stock_translations(translations)
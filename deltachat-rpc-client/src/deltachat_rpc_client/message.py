@@ -46,6 +46,10 @@ def mark_seen(self) -> None:
         """Mark the message as seen."""
         self._rpc.markseen_msgs(self.account.id, [self.id])
 
+    def export_eml_file(self, path) -> None:
+        """Export the message as .eml file."""
+        self._rpc.export_eml_file(self.account.id, self.id, str(path))
+
     def send_webxdc_status_update(self, update: Union[dict, str], description: str) -> None:
         """Send a webxdc status update. This message must be a webxdc."""
         if not isinstance(update, str):
@@ -0,0 +1,13 @@
+use bolero::check;
+
+use deltachat::fuzzing::{autocrypt_header, FuzzError};
+
+fn main() {
+    check!().for_each(|data: &[u8]| {
+        if let Ok(input) = std::str::from_utf8(data) {
+            if let Err(FuzzError::Panic(message)) = autocrypt_header(input) {
+                panic!("{message}");
+            }
+        }
+    });
+}
@@ -0,0 +1,11 @@
+use bolero::check;
+
+use deltachat::fuzzing::{mime_headers, FuzzError};
+
+fn main() {
+    check!().for_each(|data: &[u8]| {
+        if let Err(FuzzError::Panic(message)) = mime_headers(data) {
+            panic!("{message}");
+        }
+    });
+}
@@ -0,0 +1,29 @@
+use bolero::check;
+
+use deltachat::context::Context;
+use deltachat::fuzzing::{qr, FuzzError};
+use deltachat::stock_str::StockStrings;
+use deltachat::Events;
+
+fn main() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let context = rt.block_on(async {
+        Context::new(
+            &dir.path().join("db.sqlite"),
+            1,
+            Events::new(),
+            StockStrings::new(),
+        )
+        .await
+        .unwrap()
+    });
+
+    check!().for_each(|data: &[u8]| {
+        if let Ok(input) = std::str::from_utf8(data) {
+            if let Err(FuzzError::Panic(message)) = rt.block_on(qr(&context, input)) {
+                panic!("{message}");
+            }
+        }
+    });
+}
@@ -1,6 +1,7 @@
 // Generated!
 
 module.exports = {
+  DC_ABI_VERSION: 1,
   DC_CERTCK_ACCEPT_INVALID_CERTIFICATES: 3,
   DC_CERTCK_AUTO: 0,
   DC_CERTCK_STRICT: 1,
@@ -28,14 +29,18 @@ module.exports = {
   DC_DOWNLOAD_DONE: 0,
   DC_DOWNLOAD_FAILURE: 20,
   DC_DOWNLOAD_IN_PROGRESS: 1000,
+  DC_EVENT_BACKGROUND_FETCH_DONE: 2210,
   DC_EVENT_CHAT_EPHEMERAL_TIMER_MODIFIED: 2021,
   DC_EVENT_CHAT_MODIFIED: 2020,
   DC_EVENT_CONFIGURE_PROGRESS: 2041,
+  DC_EVENT_CONFIG_CHANGED: 2111,
   DC_EVENT_CONNECTIVITY_CHANGED: 2100,
   DC_EVENT_CONTACTS_CHANGED: 2030,
+  DC_EVENT_CONTACTS_IMPORT_PROGRESS: 2031,
   DC_EVENT_DELETED_BLOB_FILE: 151,
   DC_EVENT_ERROR: 400,
   DC_EVENT_ERROR_SELF_NOT_IN_GROUP: 410,
+  DC_EVENT_FETCH_PROGRESS: 2211,
   DC_EVENT_IMAP_CONNECTED: 102,
   DC_EVENT_IMAP_INBOX_IDLE: 106,
   DC_EVENT_IMAP_MESSAGE_DELETED: 104,
@@ -45,6 +50,7 @@ module.exports = {
   DC_EVENT_INCOMING_MSG: 2005,
   DC_EVENT_INCOMING_MSG_BUNCH: 2006,
   DC_EVENT_INFO: 100,
+  DC_EVENT_JOB_FAILED: 2200,
   DC_EVENT_LOCATION_CHANGED: 2035,
   DC_EVENT_MSGS_CHANGED: 2000,
   DC_EVENT_MSGS_NOTICED: 2008,
@@ -67,6 +73,7 @@ module.exports = {
   DC_GCL_ARCHIVED_ONLY: 1,
   DC_GCL_FOR_FORWARDING: 8,
   DC_GCL_NO_SPECIALS: 2,
+  DC_GCL_SPAM_ONLY: 16,
   DC_GCL_VERIFIED_ONLY: 1,
   DC_GCM_ADDDAYMARKER: 1,
   DC_GCM_INFO_ONLY: 2,
@@ -76,8 +83,10 @@ module.exports = {
   DC_IMEX_IMPORT_SELF_KEYS: 2,
   DC_INFO_AUTOCRYPT_SETUP_MESSAGE: 6,
   DC_INFO_EPHEMERAL_TIMER_CHANGED: 10,
+  DC_INFO_GROUP_ADMINS_CHANGED: 13,
   DC_INFO_GROUP_IMAGE_CHANGED: 3,
   DC_INFO_GROUP_NAME_CHANGED: 2,
+  DC_INFO_KEY_TRANSITION: 33,
   DC_INFO_LOCATIONSTREAMING_ENABLED: 8,
   DC_INFO_LOCATION_ONLY: 9,
   DC_INFO_MEMBER_ADDED_TO_GROUP: 4,
@@ -121,6 +130,7 @@ module.exports = {
   DC_QR_FPR_OK: 210,
   DC_QR_FPR_WITHOUT_ADDR: 230,
   DC_QR_LOGIN: 520,
+  DC_QR_PROVISIONING: 530,
   DC_QR_REVIVE_VERIFYCONTACT: 510,
   DC_QR_REVIVE_VERIFYGROUP: 512,
   DC_QR_TEXT: 330,
@@ -167,6 +177,7 @@ module.exports = {
   DC_STR_CONTACT_NOT_VERIFIED: 36,
   DC_STR_CONTACT_SETUP_CHANGED: 37,
   DC_STR_CONTACT_VERIFIED: 35,
+  DC_STR_DAMAGED_MSG_BODY: 173,
   DC_STR_DEVICE_MESSAGES: 68,
   DC_STR_DEVICE_MESSAGES_HINT: 70,
   DC_STR_DOWNLOAD_AVAILABILITY: 100,
@@ -214,6 +225,8 @@ module.exports = {
   DC_STR_FINGERPRINTS: 30,
   DC_STR_FORWARDED: 97,
   DC_STR_GIF: 23,
+  DC_STR_GROUP_ADMINS_CHANGED_BY_OTHER: 178,
+  DC_STR_GROUP_ADMINS_CHANGED_BY_YOU: 177,
   DC_STR_GROUP_IMAGE_CHANGED_BY_OTHER: 127,
   DC_STR_GROUP_IMAGE_CHANGED_BY_YOU: 126,
   DC_STR_GROUP_IMAGE_DELETED_BY_OTHER: 135,
@@ -224,6 +237,7 @@ module.exports = {
   DC_STR_GROUP_NAME_CHANGED_BY_YOU: 124,
   DC_STR_IMAGE: 9,
   DC_STR_INCOMING_MESSAGES: 103,
+  DC_STR_LAST_ERROR: 174,
   DC_STR_LAST_MSG_SENT_SUCCESSFULLY: 111,
   DC_STR_LOCATION: 66,
   DC_STR_LOCATION_ENABLED_BY_OTHER: 137,
@@ -246,6 +260,8 @@ module.exports = {
   DC_STR_OUTGOING_MESSAGES: 104,
   DC_STR_PARTIAL_DOWNLOAD_MSG_BODY: 99,
   DC_STR_PART_OF_TOTAL_USED: 116,
+  DC_STR_PUSH_NOTIFICATIONS: 175,
+  DC_STR_PUSH_NOTIFICATIONS_DISABLED: 176,
   DC_STR_QUOTA_EXCEEDING_MSG_BODY: 98,
   DC_STR_READRCPT: 31,
   DC_STR_READRCPT_MAILBODY: 32,
@@ -267,6 +283,7 @@ module.exports = {
   DC_STR_SYNC_MSG_BODY: 102,
   DC_STR_SYNC_MSG_SUBJECT: 101,
   DC_STR_UNKNOWN_SENDER_FOR_CHAT: 72,
+  DC_STR_UNSUBSCRIBED_FROM_MAILINGLIST: 172,
   DC_STR_UPDATE_REMINDER_MSG_BODY: 86,
   DC_STR_UPDATING: 109,
   DC_STR_VIDEO: 10,
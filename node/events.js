@@ -26,6 +26,7 @@ module.exports = {
   2020: 'DC_EVENT_CHAT_MODIFIED',
   2021: 'DC_EVENT_CHAT_EPHEMERAL_TIMER_MODIFIED',
   2030: 'DC_EVENT_CONTACTS_CHANGED',
+  2031: 'DC_EVENT_CONTACTS_IMPORT_PROGRESS',
   2035: 'DC_EVENT_LOCATION_CHANGED',
   2041: 'DC_EVENT_CONFIGURE_PROGRESS',
   2051: 'DC_EVENT_IMEX_PROGRESS',
@@ -34,6 +35,10 @@ module.exports = {
   2061: 'DC_EVENT_SECUREJOIN_JOINER_PROGRESS',
   2100: 'DC_EVENT_CONNECTIVITY_CHANGED',
   2110: 'DC_EVENT_SELFAVATAR_CHANGED',
+  2111: 'DC_EVENT_CONFIG_CHANGED',
   2120: 'DC_EVENT_WEBXDC_STATUS_UPDATE',
-  2121: 'DC_EVENT_WEBXDC_INSTANCE_DELETED'
+  2121: 'DC_EVENT_WEBXDC_INSTANCE_DELETED',
+  2200: 'DC_EVENT_JOB_FAILED',
+  2210: 'DC_EVENT_BACKGROUND_FETCH_DONE',
+  2211: 'DC_EVENT_FETCH_PROGRESS'
 }
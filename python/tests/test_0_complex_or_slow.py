@@ -303,6 +303,33 @@ def test_fetch_existing_msgs_group_and_single(acfactory, lp):
     assert len(private_messages) == 0
 
 
+def test_import_history(acfactory, lp):
+    ac1, ac2 = acfactory.get_online_accounts(2)
+
+    lp.sec("receive messages")
+    chat = ac2.create_chat(ac1)
+    chat.send_text("hello from the past")
+    ac1._evtracker.wait_next_incoming_message()
+    chat.send_text("another one")
+    ac1._evtracker.wait_next_incoming_message()
+
+    lp.sec("Clone online account and let it import the history")
+    ac1_clone = acfactory.new_online_configuring_account(cloned_from=ac1)
+    ac1_clone.set_config("import_history_days", "7")
+    acfactory.wait_configured(ac1_clone)
+    ac1_clone.start_io()
+    while True:
+        ev = ac1_clone._evtracker.get_matching("DC_EVENT_HISTORY_IMPORT_PROGRESS")
+        assert ev.data1 > 0
+        if ev.data1 == 1000:
+            break
+
+    contact = ac1_clone.get_contact_by_addr(ac2.get_config("addr"))
+    texts = [msg.text for msg in contact.create_chat().get_messages()]
+    assert "hello from the past" in texts
+    assert "another one" in texts
+
+
 def test_undecipherable_group(acfactory, lp):
     """Test how group messages that cannot be decrypted are
     handled.
@@ -186,12 +186,30 @@ def process_data(data, file):
     out_ids += ids
 
 
+def load_overrides():
+    path = Path(__file__).parent / "provider-db-overrides.yaml"
+    with open(path) as f:
+        return yaml.load(f, Loader=yaml.SafeLoader) or {}
+
+
+def apply_overrides(data, file):
+    override = overrides.pop(file2id(file), None)
+    if override is None:
+        return
+    for key in override:
+        if key == "opt":
+            data["opt"] = {**data.get("opt", {}), **override["opt"]}
+        else:
+            data[key] = override[key]
+
+
 def process_file(file):
     print("processing file: {}".format(file), file=sys.stderr)
     with open(file) as f:
         # load_all() loads "---"-separated yamls -
         # by coincidence, this is also the frontmatter separator :)
         data = next(yaml.load_all(f, Loader=yaml.SafeLoader))
+        apply_overrides(data, file)
         process_data(data, file)
 
 
@@ -218,7 +236,10 @@ def process_dir(dir):
         "use once_cell::sync::Lazy;\n\n"
     )
 
+    overrides = load_overrides()
     process_dir(Path(sys.argv[1]))
+    if overrides:
+        raise TypeError("overrides for unknown providers: " + ", ".join(overrides))
 
     out_all += "pub(crate) static PROVIDER_DATA: Lazy<HashMap<&'static str, &'static Provider>> = Lazy::new(|| HashMap::from([\n"
     out_all += out_domains
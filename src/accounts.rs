@@ -4,6 +4,7 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context as _, Result};
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
@@ -14,6 +15,9 @@
 
 use crate::context::Context;
 use crate::events::{Event, EventEmitter, EventType, Events};
+use crate::log::{LogExt, LogLevel};
+use crate::net::SystemProxy;
+use crate::scheduler::connectivity::{CONNECTIVITY_HTML_HEAD, CONNECTIVITY_HTML_TAIL};
 use crate::stock_str::StockStrings;
 
 /// Account manager, that can handle multiple accounts in a single place.
@@ -32,6 +36,10 @@ pub struct Accounts {
     /// This way changing a translation for one context automatically
     /// changes it for all other contexts.
     pub(crate) stockstrings: StockStrings,
+
+    /// Proxy from the system settings set for all accounts,
+    /// see [`Accounts::set_system_proxy`].
+    pub(crate) system_proxy: Option<SystemProxy>,
 }
 
 impl Accounts {
@@ -79,6 +87,7 @@ async fn open(dir: PathBuf, writable: bool) -> Result<Self> {
             accounts,
             events,
             stockstrings,
+            system_proxy: None,
         })
     }
 
@@ -122,6 +131,8 @@ pub async fn add_account(&mut self) -> Result<u32> {
             self.stockstrings.clone(),
         )
         .await?;
+        ctx.set_system_proxy(self.system_proxy.clone());
+        self.apply_offline(&ctx).await?;
         self.accounts.insert(account_config.id, ctx);
 
         Ok(account_config.id)
@@ -138,6 +149,7 @@ pub async fn add_closed_account(&mut self) -> Result<u32> {
             self.stockstrings.clone(),
         )
         .await?;
+        ctx.set_system_proxy(self.system_proxy.clone());
         self.accounts.insert(account_config.id, ctx);
 
         Ok(account_config.id)
@@ -214,6 +226,8 @@ pub async fn migrate_account(&mut self, dbfile: PathBuf) -> Result<u32> {
                     self.stockstrings.clone(),
                 )
                 .await?;
+                ctx.set_system_proxy(self.system_proxy.clone());
+                self.apply_offline(&ctx).await?;
                 self.accounts.insert(account_config.id, ctx);
                 Ok(account_config.id)
             }
@@ -237,6 +251,46 @@ pub fn get_all(&self) -> Vec<u32> {
         self.accounts.keys().copied().collect()
     }
 
+    /// Get an overview of the connectivity of all accounts,
+    /// with one section per account as returned by [`Context::get_connectivity_html`].
+    ///
+    /// Accounts which are not started show the reason instead of the details,
+    /// so the page can be shown before IO is started.
+    pub async fn get_connectivity_html(&self) -> Result<String> {
+        let mut ret = CONNECTIVITY_HTML_HEAD.to_string();
+        for (id, account) in &self.accounts {
+            let title = match account
+                .get_config(crate::config::Config::ConfiguredAddr)
+                .await?
+            {
+                Some(addr) => addr,
+                None => format!("Account {id}"),
+            };
+            ret += &format!("<h2>{}</h2>", escaper::encode_minimal(&title));
+            match account.get_connectivity_html_body().await {
+                Ok(body) => ret += &body,
+                Err(err) => {
+                    ret += &format!(
+                        "<ul><li><span class=\"red dot\"></span> {}</li></ul>",
+                        escaper::encode_minimal(&format!("{err:#}"))
+                    )
+                }
+            }
+        }
+        ret += CONNECTIVITY_HTML_TAIL;
+        Ok(ret)
+    }
+
+    /// Returns the number of fresh messages of all accounts to show as a badge,
+    /// the sum of [`Context::get_fresh_msg_count`] of the accounts.
+    pub async fn get_fresh_msg_count(&self) -> Result<usize> {
+        let mut count = 0;
+        for account in self.accounts.values() {
+            count += account.get_fresh_msg_count().await?;
+        }
+        Ok(count)
+    }
+
     /// This is meant especially for iOS, because iOS needs to tell the system when its background work is done.
     ///
     /// Returns whether all accounts finished their background work.
@@ -259,6 +313,7 @@ pub async fn all_work_done(&self) -> bool {
     /// Starts background tasks such as IMAP and SMTP loops for all accounts.
     pub async fn start_io(&self) {
         for account in self.accounts.values() {
+            self.apply_offline(account).await.log_err(account).ok();
             account.start_io().await;
         }
     }
@@ -273,6 +328,34 @@ pub async fn stop_io(&self) {
         }
     }
 
+    /// Turns the offline mode on or off for all accounts,
+    /// see [`crate::config::Config::Offline`].
+    ///
+    /// The mode is saved in the account manager configuration
+    /// and also applied to accounts added later.
+    /// Closed accounts are switched when IO is started for them.
+    pub async fn set_offline(&mut self, offline: bool) -> Result<()> {
+        self.config.set_offline(offline).await?;
+        for account in self.accounts.values() {
+            if account.is_open().await {
+                account
+                    .set_config_bool(crate::config::Config::Offline, offline)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the offline mode set with [`Accounts::set_offline`] to an account.
+    async fn apply_offline(&self, account: &Context) -> Result<()> {
+        if self.config.is_offline() && account.is_open().await {
+            account
+                .set_config_bool(crate::config::Config::Offline, true)
+                .await?;
+        }
+        Ok(())
+    }
+
     /// Notifies all accounts that the network may have become available.
     pub async fn maybe_network(&self) {
         for account in self.accounts.values() {
@@ -280,6 +363,25 @@ pub async fn maybe_network(&self) {
         }
     }
 
+    /// Performs a background fetch for all accounts in parallel with a timeout.
+    ///
+    /// See [`Context::background_fetch`] for details.
+    /// Each account emits [`EventType::BackgroundFetchDone`] when it is done,
+    /// additionally the event is emitted with account ID 0
+    /// when all accounts are done or the timeout is reached.
+    pub async fn background_fetch(&self, timeout: Duration) {
+        let futures = self.accounts.values().map(|account| async move {
+            account
+                .background_fetch(timeout)
+                .await
+                .context("Background fetch failed")
+                .log_err(account)
+                .ok();
+        });
+        join_all(futures).await;
+        self.emit_event(EventType::BackgroundFetchDone);
+    }
+
     /// Notifies all accounts that the network connection may have been lost.
     pub async fn maybe_network_lost(&self) {
         for account in self.accounts.values() {
@@ -292,6 +394,35 @@ pub fn emit_event(&self, event: EventType) {
         self.events.emit(Event { id: 0, typ: event })
     }
 
+    /// Returns true if an entry of the given level is logged.
+    ///
+    /// Account manager has no log filter of its own, debug entries are dropped.
+    #[doc(hidden)]
+    pub fn log_enabled(&self, level: LogLevel, _module_path: &str) -> bool {
+        level >= LogLevel::Info
+    }
+
+    /// Emits a log entry as an event.
+    ///
+    /// This is called by the logging macros and should not be used directly.
+    #[doc(hidden)]
+    pub fn log_entry(
+        &self,
+        level: LogLevel,
+        _module_path: &str,
+        file: &str,
+        line: u32,
+        msg: String,
+    ) {
+        let event = match level {
+            LogLevel::Debug => return,
+            LogLevel::Info => EventType::Info(format!("{file}:{line}: {msg}")),
+            LogLevel::Warning => EventType::Warning(format!("{file}:{line}: {msg}")),
+            LogLevel::Error => EventType::Error(msg),
+        };
+        self.emit_event(event);
+    }
+
     /// Returns event emitter.
     pub fn get_event_emitter(&self) -> EventEmitter {
         self.events.get_emitter()
@@ -326,6 +457,10 @@ struct InnerConfig {
     pub selected_account: u32,
     pub next_id: u32,
     pub accounts: Vec<AccountConfig>,
+
+    /// Whether all accounts stay offline, see [`Accounts::set_offline`].
+    #[serde(default)]
+    pub offline: bool,
 }
 
 impl Drop for Config {
@@ -344,6 +479,7 @@ async fn new_nosync(file: PathBuf, lock: bool) -> Result<Self> {
             accounts: Vec::new(),
             selected_account: 0,
             next_id: 1,
+            offline: false,
         };
         if !lock {
             let cfg = Self {
@@ -533,6 +669,17 @@ pub async fn remove_account(&mut self, id: u32) -> Result<()> {
         self.sync().await
     }
 
+    /// Returns true if all accounts stay offline, see [`Accounts::set_offline`].
+    fn is_offline(&self) -> bool {
+        self.inner.offline
+    }
+
+    /// Saves whether all accounts stay offline.
+    async fn set_offline(&mut self, offline: bool) -> Result<()> {
+        self.inner.offline = offline;
+        self.sync().await
+    }
+
     /// Returns configuration file section for the given account ID.
     fn get_account(&self, id: u32) -> Option<AccountConfig> {
         self.inner.accounts.iter().find(|e| e.id == id).cloned()
@@ -608,6 +755,30 @@ async fn test_account_new_open() {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_connectivity_html() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut accounts = Accounts::new(dir.path().join("accounts"), true).await?;
+        let id1 = accounts.add_account().await?;
+        accounts.add_account().await?;
+        accounts
+            .get_account(id1)
+            .unwrap()
+            .set_config(
+                crate::config::Config::ConfiguredAddr,
+                Some("alice@example.org"),
+            )
+            .await?;
+
+        let html = accounts.get_connectivity_html().await?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>alice@example.org</h2>"));
+        assert!(html.contains("<h2>Account 2</h2>"));
+        assert_eq!(html.matches("Not started").count(), 2);
+        assert!(html.ends_with("</body></html>\n"));
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_account_new_open_conflict() {
         let dir = tempfile::tempdir().unwrap();
@@ -911,4 +1082,55 @@ async fn test_accounts_share_translations() -> Result<()> {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_accounts_set_offline() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let mut accounts = Accounts::new(dir.path().join("accounts"), true).await?;
+        accounts.add_account().await?;
+        accounts.add_account().await?;
+
+        accounts.set_offline(true).await?;
+        for account in accounts.accounts.values() {
+            assert!(
+                account
+                    .get_config_bool(crate::config::Config::Offline)
+                    .await?
+            );
+
+            // Starting IO does not connect while offline.
+            account.scheduler.start(account.clone()).await;
+            assert!(!account.scheduler.is_running().await);
+            account.scheduler.stop(account).await;
+        }
+
+        // Accounts added later are offline as well, also after reopening the account manager.
+        let id = accounts.add_account().await?;
+        let account = accounts.get_account(id).unwrap();
+        assert!(
+            account
+                .get_config_bool(crate::config::Config::Offline)
+                .await?
+        );
+        drop(accounts);
+        let mut accounts = Accounts::new(dir.path().join("accounts"), true).await?;
+        let id = accounts.add_account().await?;
+        let account = accounts.get_account(id).unwrap();
+        assert!(
+            account
+                .get_config_bool(crate::config::Config::Offline)
+                .await?
+        );
+
+        accounts.set_offline(false).await?;
+        for account in accounts.accounts.values() {
+            assert!(
+                !account
+                    .get_config_bool(crate::config::Config::Offline)
+                    .await?
+            );
+            assert!(!account.scheduler.is_running().await);
+        }
+        Ok(())
+    }
 }
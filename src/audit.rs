@@ -0,0 +1,164 @@
+//! # Audit trail of IMAP and SMTP operations.
+//!
+//! Fetching, moving, flagging and deleting messages on the server
+//! as well as sending them over SMTP is recorded per message
+//! in the `msgs_audit` table, keyed by Message-ID.
+//! The trail is shown in the message info, see [`MsgId::get_info`],
+//! so that reports about disappeared messages can be debugged
+//! without reproducing them with debug logging enabled.
+//! Entries are removed by housekeeping after [`AUDIT_RETENTION`] seconds.
+
+use anyhow::Result;
+
+use crate::context::Context;
+use crate::message::MsgId;
+use crate::sql;
+use crate::tools::time;
+
+/// Time in seconds after which audit entries are removed.
+pub(crate) const AUDIT_RETENTION: i64 = 30 * 24 * 60 * 60;
+
+/// Operation recorded in the audit trail of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Time of the operation, Unix time in seconds.
+    pub timestamp: i64,
+
+    /// Human-readable description of the operation.
+    pub operation: String,
+}
+
+/// Records an operation on the message with the given Message-ID.
+pub(crate) async fn log(context: &Context, rfc724_mid: &str, operation: &str) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT INTO msgs_audit (rfc724_mid, timestamp, operation) VALUES (?, ?, ?)",
+            (rfc724_mid, time(), operation),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Records an operation on a message in the database.
+pub(crate) async fn log_msg(context: &Context, msg_id: MsgId, operation: &str) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT INTO msgs_audit (rfc724_mid, timestamp, operation)
+             SELECT rfc724_mid, ?, ? FROM msgs WHERE id=? AND rfc724_mid!=''",
+            (time(), operation, msg_id),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Records an operation on the server messages in the given rows of the `imap` table.
+///
+/// Must be called before the rows are removed.
+pub(crate) async fn log_imap_rows(
+    context: &Context,
+    row_ids: &[i64],
+    operation: &str,
+) -> Result<()> {
+    context
+        .sql
+        .execute(
+            &format!(
+                "INSERT INTO msgs_audit (rfc724_mid, timestamp, operation)
+                 SELECT rfc724_mid, ?, ? || ' (' || folder || ', UID ' || uid || ')'
+                 FROM imap WHERE id IN ({})",
+                sql::repeat_vars(row_ids.len())
+            ),
+            rusqlite::params_from_iter(
+                params_slice![time(), operation]
+                    .into_iter()
+                    .chain(sql::params_iter(row_ids)),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns the recorded IMAP and SMTP operations on a message, oldest first.
+pub async fn get_msg_audit_trail(context: &Context, msg_id: MsgId) -> Result<Vec<AuditEntry>> {
+    context
+        .sql
+        .query_map(
+            "SELECT a.timestamp, a.operation FROM msgs_audit a, msgs m
+             WHERE m.id=? AND m.rfc724_mid!='' AND a.rfc724_mid=m.rfc724_mid
+             ORDER BY a.id",
+            (msg_id,),
+            |row| {
+                Ok(AuditEntry {
+                    timestamp: row.get(0)?,
+                    operation: row.get(1)?,
+                })
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+}
+
+/// Removes audit entries older than [`AUDIT_RETENTION`].
+pub(crate) async fn prune(context: &Context) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "DELETE FROM msgs_audit WHERE timestamp<?",
+            (time().saturating_sub(AUDIT_RETENTION),),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message;
+    use crate::test_utils::TestContextManager;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_msg_audit_trail() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let sent = alice
+            .send_text(alice.create_chat(&bob).await.id, "hi")
+            .await;
+        let msg = bob.recv_msg(&sent).await;
+        assert!(get_msg_audit_trail(&bob, msg.id).await?.is_empty());
+
+        bob.sql
+            .execute(
+                "INSERT INTO imap (rfc724_mid, folder, uid, target) VALUES (?, 'INBOX', 7, 'INBOX')",
+                (&msg.rfc724_mid,),
+            )
+            .await?;
+        let row_id: i64 = bob
+            .sql
+            .query_get_value("SELECT id FROM imap WHERE uid=7", ())
+            .await?
+            .unwrap();
+        log_imap_rows(&bob, &[row_id], "Marked as seen").await?;
+        message::delete_msgs(&bob, &[msg.id]).await?;
+
+        let trail = get_msg_audit_trail(&bob, msg.id).await?;
+        let operations: Vec<&str> = trail.iter().map(|e| e.operation.as_str()).collect();
+        assert_eq!(
+            operations,
+            vec!["Marked as seen (INBOX, UID 7)", "Deletion scheduled"]
+        );
+
+        // Old entries are removed by housekeeping.
+        bob.sql
+            .execute(
+                "UPDATE msgs_audit SET timestamp=timestamp-?",
+                (AUDIT_RETENTION + 1,),
+            )
+            .await?;
+        prune(&bob).await?;
+        assert!(get_msg_audit_trail(&bob, msg.id).await?.is_empty());
+        Ok(())
+    }
+}
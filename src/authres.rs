@@ -49,6 +49,9 @@ pub(crate) async fn handle_authres(
 pub(crate) struct DkimResults {
     /// Whether DKIM passed for this particular e-mail.
     pub dkim_passed: bool,
+    /// Whether an Authentication-Results header added by our server says that DKIM passed.
+    /// Unlike `dkim_passed`, this is false if the server doesn't add Authentication-Results.
+    pub dkim_passed_by_server: bool,
     /// Whether DKIM is known to work for e-mails coming from the sender's domain,
     /// i.e. whether we expect DKIM to work.
     pub dkim_should_work: bool,
@@ -242,6 +245,7 @@ async fn compute_dkim_results(
     message_time: i64,
 ) -> Result<DkimResults> {
     let mut dkim_passed = false;
+    let mut dkim_passed_by_server = false;
 
     let ids_config = context.get_config(Config::AuthservIdCandidates).await?;
     let ids = parse_authservid_candidates_config(&ids_config);
@@ -259,6 +263,7 @@ async fn compute_dkim_results(
             match current_dkim_passed {
                 DkimResult::Passed => {
                     dkim_passed = true;
+                    dkim_passed_by_server = true;
                     break;
                 }
                 DkimResult::Failed => {
@@ -281,11 +286,25 @@ async fn compute_dkim_results(
 
     Ok(DkimResults {
         dkim_passed,
+        dkim_passed_by_server,
         dkim_should_work,
         allow_keychange: dkim_passed || !dkim_should_work,
     })
 }
 
+/// Records that a DKIM signature of `from_domain` was verified locally,
+/// so that DKIM is expected to work for this domain from now on.
+pub(crate) async fn dkim_verified(
+    context: &Context,
+    from_domain: &str,
+    message_time: i64,
+) -> Result<()> {
+    if message_time > dkim_works_timestamp(context, from_domain).await? {
+        set_dkim_works_timestamp(context, from_domain, message_time).await?;
+    }
+    Ok(())
+}
+
 /// Whether DKIM in emails from this domain should be considered to work.
 fn dkim_should_work(last_working_timestamp: i64) -> Result<bool> {
     // When we get an email with valid DKIM-Authentication-Results,
@@ -0,0 +1,86 @@
+//! # Fallback avatars.
+//!
+//! Contacts and chats without a profile image are displayed
+//! as a circle filled with their color and the initial letter of their name.
+//! The avatars are rendered in the core so that all platforms display them the same way.
+
+use anyhow::Result;
+
+use crate::color::color_int_to_hex_string;
+
+/// Returns the uppercased initial letter of a name to be displayed in a fallback avatar.
+///
+/// Leading whitespace is skipped, `#` is returned for empty names.
+pub(crate) fn avatar_letter(name: &str) -> String {
+    match name.trim_start().chars().next() {
+        Some(c) => c.to_uppercase().collect(),
+        None => "#".to_string(),
+    }
+}
+
+/// Renders a fallback avatar as an SVG image.
+pub(crate) fn fallback_avatar_svg(name: &str, color: u32) -> Result<String> {
+    const SIZE: f32 = 100.0;
+    const HALF_SIZE: f32 = SIZE / 2.0;
+    let font_size = SIZE * 0.65;
+    let font_offset = font_size * 0.1;
+
+    let mut svg = String::with_capacity(512);
+    let mut w = tagger::new(&mut svg);
+    w.elem("svg", |d| {
+        d.attr("xmlns", "http://www.w3.org/2000/svg")?;
+        d.attr("viewBox", format_args!("0 0 {SIZE} {SIZE}"))
+    })?
+    .build(|w| {
+        w.single("circle", |d| {
+            d.attr("cx", HALF_SIZE)?;
+            d.attr("cy", HALF_SIZE)?;
+            d.attr("r", HALF_SIZE)?;
+            d.attr("style", format!("fill:{}", color_int_to_hex_string(color)))
+        })?;
+        w.elem("text", |d| {
+            d.attr("y", HALF_SIZE + font_offset)?;
+            d.attr("x", HALF_SIZE)?;
+            d.attr("text-anchor", "middle")?;
+            d.attr("dominant-baseline", "central")?;
+            d.attr("alignment-baseline", "middle")?;
+            d.attr(
+                "style",
+                format!(
+                    "font-family:sans-serif;\
+                        font-weight:400;\
+                        font-size:{font_size}px;\
+                        fill:#ffffff;"
+                ),
+            )
+        })?
+        .build(|w| w.put_raw(escaper::encode_minimal(&avatar_letter(name))))
+    })?;
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_avatar_letter() {
+        assert_eq!(avatar_letter("alice"), "A");
+        assert_eq!(avatar_letter("  bob"), "B");
+        assert_eq!(avatar_letter("ßeta"), "SS");
+        assert_eq!(avatar_letter("😺 cats"), "😺");
+        assert_eq!(avatar_letter(""), "#");
+    }
+
+    #[test]
+    fn test_fallback_avatar_svg() -> Result<()> {
+        let svg = fallback_avatar_svg("<script>", 0xff0000)?;
+        assert!(svg.contains("fill:#ff0000"));
+        assert!(svg.contains("&lt;"));
+        assert!(!svg.contains("<script>"));
+
+        // Rendering is deterministic.
+        assert_eq!(svg, fallback_avatar_svg("<script>", 0xff0000)?);
+        Ok(())
+    }
+}
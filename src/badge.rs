@@ -0,0 +1,292 @@
+//! # Badge counter.
+//!
+//! The number of fresh messages, typically shown as a badge on the app icon,
+//! is cached and updated from the emitted events,
+//! so it is not recounted every time an event arrives.
+//! Incoming messages are added to the cached count one by one.
+//! Noticing or deleting messages, changing the badge settings
+//! and muting, archiving or blocking chats and contacts
+//! make the count recounted on the next request.
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::constants::Blocked;
+use crate::contact::ContactId;
+use crate::context::Context;
+use crate::events::EventType;
+use crate::message::{MessageState, MsgId};
+use crate::sql;
+use crate::tools::time;
+
+/// Maximum number of incoming messages remembered for the cached count,
+/// if there are more, the count is recounted.
+const MAX_PENDING_INCOMING: usize = 1000;
+
+/// Cached number of fresh messages, see [`Context::get_fresh_msg_count`].
+#[derive(Debug, Default)]
+pub(crate) struct FreshMsgCount {
+    /// Cached count of messages up to `last_msg_id`, `None` if it has to be recounted.
+    count: Option<usize>,
+
+    /// Last message included in `count`.
+    last_msg_id: MsgId,
+
+    /// Timestamp at which a muted chat is unmuted, the count is outdated then.
+    expires: Option<i64>,
+
+    /// Incoming messages possibly not included in `count` yet.
+    incoming: Vec<MsgId>,
+
+    /// Incremented on each invalidation,
+    /// so that counts started before are not cached.
+    generation: u64,
+}
+
+impl FreshMsgCount {
+    fn invalidate(&mut self) {
+        self.count = None;
+        self.incoming.clear();
+        self.generation = self.generation.wrapping_add(1);
+    }
+}
+
+/// Which fresh messages are counted, see [`Config::BadgeCountMuted`] and others.
+struct BadgePolicy {
+    muted: bool,
+    requests: bool,
+    device_msgs: bool,
+}
+
+impl BadgePolicy {
+    async fn load(context: &Context) -> Result<Self> {
+        Ok(Self {
+            muted: context.get_config_bool(Config::BadgeCountMuted).await?,
+            requests: context.get_config_bool(Config::BadgeCountRequests).await?,
+            device_msgs: context
+                .get_config_bool(Config::BadgeCountDeviceMsgs)
+                .await?,
+        })
+    }
+}
+
+impl Context {
+    /// Updates the cached fresh message count on an emitted event.
+    ///
+    /// The cache is only locked for events which may change the count.
+    pub(crate) fn update_fresh_msg_count(&self, event: &EventType) {
+        match event {
+            EventType::IncomingMsg { msg_id, .. } => {
+                let mut state = self.fresh_msg_count.lock().expect("Mutex is poisoned");
+                if state.incoming.len() < MAX_PENDING_INCOMING {
+                    state.incoming.push(*msg_id);
+                } else {
+                    state.invalidate();
+                }
+            }
+            EventType::MsgsNoticed(_)
+            | EventType::MsgDeleted { .. }
+            | EventType::ConfigChanged(
+                Config::BadgeCountMuted | Config::BadgeCountRequests | Config::BadgeCountDeviceMsgs,
+            ) => self.invalidate_fresh_msg_count(),
+            _ => {}
+        }
+    }
+
+    /// Makes the fresh message count recounted on the next request.
+    ///
+    /// Called when chats or contacts are muted, archived or blocked
+    /// and when messages are restored,
+    /// as the events emitted then do not tell whether the count changed.
+    pub(crate) fn invalidate_fresh_msg_count(&self) {
+        self.fresh_msg_count
+            .lock()
+            .expect("Mutex is poisoned")
+            .invalidate();
+    }
+
+    /// Returns the number of fresh messages to show as a badge, e.g. on the app icon.
+    ///
+    /// Unlike [`Context::get_fresh_msgs`], the count is cached
+    /// and the counted messages can be configured:
+    /// messages in muted chats and contact requests are only counted
+    /// if [`Config::BadgeCountMuted`] and [`Config::BadgeCountRequests`] are set,
+    /// device messages unless [`Config::BadgeCountDeviceMsgs`] is unset.
+    /// Spam is never counted.
+    pub async fn get_fresh_msg_count(&self) -> Result<usize> {
+        let now = time();
+        let (generation, cached) = {
+            let state = self.fresh_msg_count.lock().expect("Mutex is poisoned");
+            let cached = state
+                .count
+                .filter(|_| state.expires.map_or(true, |expires| now < expires))
+                .map(|count| (count, state.last_msg_id, state.incoming.clone()));
+            (state.generation, cached)
+        };
+        let policy = BadgePolicy::load(self).await?;
+
+        if let Some((count, last_msg_id, mut incoming)) = cached {
+            incoming.retain(|msg_id| *msg_id > last_msg_id);
+            incoming.sort_unstable();
+            incoming.dedup();
+            let Some(new_last_msg_id) = incoming.last().copied() else {
+                return Ok(count);
+            };
+            let query = format!(
+                "{} AND m.id IN ({})",
+                fresh_msgs_query(&policy, now),
+                sql::repeat_vars(incoming.len())
+            );
+            let count = count
+                + self
+                    .sql
+                    .count(&query, rusqlite::params_from_iter(&incoming))
+                    .await?;
+            let mut state = self.fresh_msg_count.lock().expect("Mutex is poisoned");
+            if state.generation == generation {
+                state.count = Some(count);
+                state.last_msg_id = new_last_msg_id;
+                state.incoming.retain(|msg_id| *msg_id > new_last_msg_id);
+            }
+            return Ok(count);
+        }
+
+        let last_msg_id: MsgId = self
+            .sql
+            .query_get_value("SELECT IFNULL(MAX(id), 0) FROM msgs", ())
+            .await?
+            .unwrap_or_default();
+        let query = format!("{} AND m.id<=?", fresh_msgs_query(&policy, now));
+        let count = self.sql.count(&query, (last_msg_id,)).await?;
+        let expires = if policy.muted {
+            None
+        } else {
+            self.sql
+                .query_get_value::<Option<i64>>(
+                    "SELECT MIN(muted_until) FROM chats WHERE muted_until>?",
+                    (now,),
+                )
+                .await?
+                .flatten()
+        };
+        let mut state = self.fresh_msg_count.lock().expect("Mutex is poisoned");
+        if state.generation == generation {
+            state.count = Some(count);
+            state.last_msg_id = last_msg_id;
+            state.expires = expires;
+            state.incoming.retain(|msg_id| *msg_id > last_msg_id);
+        }
+        Ok(count)
+    }
+}
+
+/// Returns the query counting fresh messages according to `policy`,
+/// to be extended by a condition on the message ID.
+fn fresh_msgs_query(policy: &BadgePolicy, now: i64) -> String {
+    let mut query = format!(
+        "SELECT COUNT(*) FROM msgs m
+         LEFT JOIN contacts ct ON m.from_id=ct.id
+         LEFT JOIN chats c ON m.chat_id=c.id
+         WHERE m.state={}
+         AND m.hidden=0
+         AND m.chat_id>9
+         AND ct.blocked=0
+         AND c.spam=0",
+        MessageState::InFresh as u32
+    );
+    if policy.requests {
+        query += &format!(" AND c.blocked IN (0, {})", Blocked::Request as u32);
+    } else {
+        query += " AND c.blocked=0";
+    }
+    if !policy.muted {
+        query += &format!(" AND NOT(c.muted_until=-1 OR c.muted_until>{now})");
+    }
+    if !policy.device_msgs {
+        query += &format!(" AND m.from_id!={}", ContactId::DEVICE.to_u32());
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{add_device_msg, marknoticed_chat, set_muted, MuteDuration};
+    use crate::message::{Message, Viewtype};
+    use crate::test_utils::TestContextManager;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_fresh_msg_count() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        assert_eq!(alice.get_fresh_msg_count().await?, 0);
+
+        // Contact requests are not counted by default.
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "Hi Alice").await;
+        let chat_id = alice.recv_msg(&sent).await.chat_id;
+        assert_eq!(alice.get_fresh_msg_count().await?, 0);
+        alice
+            .set_config_bool(Config::BadgeCountRequests, true)
+            .await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 1);
+        alice
+            .set_config_bool(Config::BadgeCountRequests, false)
+            .await?;
+        chat_id.accept(&alice).await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 1);
+
+        // Incoming messages are added to the cached count.
+        let sent = bob.send_text(bob_chat.id, "How are you?").await;
+        alice.recv_msg(&sent).await;
+        assert_eq!(alice.get_fresh_msg_count().await?, 2);
+
+        set_muted(&alice, chat_id, MuteDuration::Forever).await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 0);
+        alice.set_config_bool(Config::BadgeCountMuted, true).await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 2);
+
+        marknoticed_chat(&alice, chat_id).await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 0);
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("Update available".to_string());
+        add_device_msg(&alice, None, Some(&mut msg)).await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 1);
+        alice
+            .set_config_bool(Config::BadgeCountDeviceMsgs, false)
+            .await?;
+        assert_eq!(alice.get_fresh_msg_count().await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_incoming_msgs_not_recounted() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        tcm.send_recv_accept(&bob, &alice, "Hi Alice").await;
+        assert_eq!(alice.get_fresh_msg_count().await?, 1);
+        let generation = alice.fresh_msg_count.lock().unwrap().generation;
+
+        let bob_chat_id = bob.create_chat(&alice).await.id;
+        for i in 0..10 {
+            let sent = bob.send_text(bob_chat_id, &format!("Message {i}")).await;
+            alice.recv_msg(&sent).await;
+        }
+        {
+            let state = alice.fresh_msg_count.lock().unwrap();
+            assert_eq!(state.generation, generation);
+            assert_eq!(state.count, Some(1));
+            assert_eq!(state.incoming.len(), 10);
+        }
+
+        assert_eq!(alice.get_fresh_msg_count().await?, 11);
+        let state = alice.fresh_msg_count.lock().unwrap();
+        assert_eq!(state.generation, generation);
+        assert_eq!(state.count, Some(11));
+        assert!(state.incoming.is_empty());
+        Ok(())
+    }
+}
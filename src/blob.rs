@@ -8,9 +8,11 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{format_err, Context as _, Result};
+use base64::Engine as _;
 use futures::StreamExt;
 use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
 use num_traits::FromPrimitive;
+use sha2::{Digest, Sha256};
 use tokio::io::AsyncWriteExt;
 use tokio::{fs, io};
 use tokio_stream::wrappers::ReadDirStream;
@@ -97,6 +99,81 @@ async fn create_new_file(
         }
     }
 
+    /// Creates a blob object named after the hash of `data`.
+    ///
+    /// If a blob with the same data has been created before, the existing file is reused,
+    /// so receiving the same data repeatedly, e.g. avatars, does not create new files.
+    /// The content of the existing file is compared to `data` before reusing it;
+    /// if it differs, e.g. because the file was modified, a blob with a unique name is created.
+    pub(crate) async fn create_and_deduplicate(
+        context: &'a Context,
+        suggested_name: &str,
+        data: &[u8],
+    ) -> Result<BlobObject<'a>> {
+        let blobdir = context.get_blobdir();
+        let (stem, ext) = BlobObject::sanitise_name(suggested_name);
+        let hash = hex::encode(Sha256::digest(data));
+        let name = format!("{stem}-{hash}{ext}");
+        let blob = BlobObject {
+            blobdir,
+            name: format!("$BLOBDIR/{name}"),
+        };
+
+        let path = blobdir.join(&name);
+        let mut file = match fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                if fs::read(&path).await.ok().as_deref() == Some(data) {
+                    return Ok(blob);
+                }
+                warn!(
+                    context,
+                    "Blob {} has unexpected content, not reusing it.",
+                    blob.as_name()
+                );
+                return BlobObject::create(context, suggested_name, data).await;
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to create {}", path.display()))
+            }
+        };
+        if let Err(err) = file.write_all(data).await {
+            // Do not leave a file with wrong content behind.
+            fs::remove_file(&path).await.ok();
+            return Err(err).context("file write failure");
+        }
+
+        // workaround, see create() for details
+        let _ = file.flush().await;
+
+        context.emit_event(EventType::NewBlobFile(blob.as_name().to_string()));
+        Ok(blob)
+    }
+
+    /// Creates a new blob object with a unique name from base64-encoded data.
+    ///
+    /// Works like [BlobObject::create], but the data is decoded chunk by chunk
+    /// while writing, see [Base64BlobWriter].
+    ///
+    /// Returns the blob and the number of decoded bytes.
+    pub async fn create_from_base64(
+        context: &'a Context,
+        suggested_name: &str,
+        encoded: &[u8],
+    ) -> Result<(BlobObject<'a>, usize)> {
+        let mut writer = Base64BlobWriter::new(context, suggested_name).await?;
+        if let Err(err) = writer.write(encoded).await {
+            writer.abort().await;
+            return Err(err);
+        }
+        writer.finish().await
+    }
+
     /// Creates a new blob object with unique name by copying an existing file.
     ///
     /// This creates a new blob as described in [BlobObject::create]
@@ -242,7 +319,7 @@ pub fn suffix(&self) -> Option<&str> {
     /// ".txt")` while "bar" is returned as `("bar", "")`.
     ///
     /// The extension part will always be lowercased.
-    fn sanitise_name(name: &str) -> (String, String) {
+    pub(crate) fn sanitise_name(name: &str) -> (String, String) {
         let mut name = name.to_string();
         for part in name.rsplit('/') {
             if !part.is_empty() {
@@ -325,14 +402,12 @@ pub async fn recode_to_avatar_size(&mut self, context: &Context) -> Result<()> {
 
         let maybe_sticker = &mut false;
         let strict_limits = true;
-        // max_bytes is 20_000 bytes: Outlook servers don't allow headers larger than 32k.
-        // 32 / 4 * 3 = 24k if you account for base64 encoding. To be safe, we reduced this to 20k.
         if let Some(new_name) = self.recode_to_size(
             context,
             blob_abs,
             maybe_sticker,
             img_wh,
-            20_000,
+            constants::MAX_AVATAR_BYTES,
             strict_limits,
         )? {
             self.name = new_name;
@@ -535,6 +610,117 @@ pub fn metadata(&self) -> Result<(u64, Option<exif::Exif>)> {
     }
 }
 
+/// Writes base64-encoded data into a new blob file.
+///
+/// The data is decoded chunk by chunk while writing,
+/// so the decoded data is never kept in memory as a whole
+/// and the encoded data may be passed in pieces as it arrives.
+/// Characters not belonging to the base64 alphabet, such as line breaks, are skipped.
+#[derive(Debug)]
+pub(crate) struct Base64BlobWriter<'a> {
+    context: &'a Context,
+    blob: BlobObject<'a>,
+    file: fs::File,
+
+    /// Encoded data which is not decoded yet.
+    encoded: Vec<u8>,
+
+    /// Buffer for the decoded chunk.
+    decoded: Vec<u8>,
+
+    /// Number of bytes written to the file.
+    len: usize,
+}
+
+impl<'a> Base64BlobWriter<'a> {
+    /// Size of the encoded chunks.
+    ///
+    /// Multiple of 4, so that only the last chunk may contain padding.
+    const CHUNK_SIZE: usize = 4 * 16384;
+
+    const ENGINE: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
+        &base64::alphabet::STANDARD,
+        base64::engine::GeneralPurposeConfig::new()
+            .with_decode_allow_trailing_bits(true)
+            .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent),
+    );
+
+    /// Creates a new blob file with a unique name derived from `suggested_name`.
+    pub(crate) async fn new(context: &'a Context, suggested_name: &str) -> Result<Self> {
+        let blobdir = context.get_blobdir();
+        let (stem, ext) = BlobObject::sanitise_name(suggested_name);
+        let (name, file) = BlobObject::create_new_file(context, blobdir, &stem, &ext).await?;
+        Ok(Self {
+            context,
+            blob: BlobObject {
+                blobdir,
+                name: format!("$BLOBDIR/{name}"),
+            },
+            file,
+            encoded: Vec::with_capacity(Self::CHUNK_SIZE),
+            decoded: Vec::with_capacity(Self::CHUNK_SIZE / 4 * 3),
+            len: 0,
+        })
+    }
+
+    /// Decodes the next piece of encoded data and writes it to the file.
+    ///
+    /// On error, the writer must be [aborted](Self::abort).
+    pub(crate) async fn write(&mut self, encoded: &[u8]) -> Result<()> {
+        let mut input = encoded
+            .iter()
+            .copied()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, b'+' | b'/' | b'='))
+            .peekable();
+        while input.peek().is_some() {
+            let missing = Self::CHUNK_SIZE - self.encoded.len();
+            self.encoded.extend(input.by_ref().take(missing));
+            if self.encoded.len() == Self::CHUNK_SIZE {
+                self.decode_chunk().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn decode_chunk(&mut self) -> Result<()> {
+        self.decoded.clear();
+        Self::ENGINE
+            .decode_vec(&self.encoded, &mut self.decoded)
+            .context("invalid base64")?;
+        self.encoded.clear();
+        self.file
+            .write_all(&self.decoded)
+            .await
+            .context("file write failure")?;
+        self.len += self.decoded.len();
+        Ok(())
+    }
+
+    /// Decodes the remaining data and closes the file.
+    ///
+    /// Returns the blob and the number of decoded bytes.
+    /// On error, the incomplete file is removed.
+    pub(crate) async fn finish(mut self) -> Result<(BlobObject<'a>, usize)> {
+        if let Err(err) = self.decode_chunk().await {
+            self.abort().await;
+            return Err(err);
+        }
+
+        // workaround, see create() for details
+        let _ = self.file.flush().await;
+
+        self.context
+            .emit_event(EventType::NewBlobFile(self.blob.as_name().to_string()));
+        Ok((self.blob, self.len))
+    }
+
+    /// Removes the incomplete file.
+    pub(crate) async fn abort(self) {
+        // Swallow errors resulting from that.
+        fs::remove_file(self.blob.to_abs_path()).await.ok();
+    }
+}
+
 fn exif_orientation(exif: &exif::Exif, context: &Context) -> i32 {
     if let Some(orientation) = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY) {
         // possible orientation values are described at http://sylvana.net/jpegcrop/exif_orientation.html
@@ -672,6 +858,7 @@ fn encoded_img_exceeds_bytes(
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
+    use base64::Engine as _;
     use fs::File;
     use image::{GenericImageView, Pixel};
 
@@ -700,6 +887,53 @@ async fn test_create() {
         assert_eq!(blob.to_abs_path(), t.get_blobdir().join("foo"));
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_create_from_base64() {
+        let t = TestContext::new().await;
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        let (blob, len) = BlobObject::create_from_base64(&t, "foo.bin", wrapped.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(len, data.len());
+        assert_eq!(fs::read(blob.to_abs_path()).await.unwrap(), data);
+
+        assert!(
+            BlobObject::create_from_base64(&t, "bar.bin", b"aGVsbG8=aGVsbG8=")
+                .await
+                .is_err()
+        );
+        assert!(!t.get_blobdir().join("bar.bin").exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_create_and_deduplicate() -> Result<()> {
+        let t = TestContext::new().await;
+        let blob = BlobObject::create_and_deduplicate(&t, "avatar.png", b"hello").await?;
+        assert!(blob.as_name().starts_with("$BLOBDIR/avatar-"));
+        assert!(blob.as_name().ends_with(".png"));
+        assert_eq!(fs::read(blob.to_abs_path()).await?, b"hello");
+
+        let blob2 = BlobObject::create_and_deduplicate(&t, "avatar.png", b"hello").await?;
+        assert_eq!(blob2, blob);
+        let blob3 = BlobObject::create_and_deduplicate(&t, "avatar.png", b"world").await?;
+        assert_ne!(blob3, blob);
+        assert_eq!(fs::read(blob3.to_abs_path()).await?, b"world");
+
+        // A modified file is not reused.
+        fs::write(blob.to_abs_path(), b"modified").await?;
+        let blob4 = BlobObject::create_and_deduplicate(&t, "avatar.png", b"hello").await?;
+        assert_ne!(blob4, blob);
+        assert_eq!(fs::read(blob4.to_abs_path()).await?, b"hello");
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_lowercase_ext() {
         let t = TestContext::new().await;
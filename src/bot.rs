@@ -0,0 +1,366 @@
+//! # Helpers for bots.
+//!
+//! Bots usually run headless, accept every contact request,
+//! do not send read receipts and react on text commands.
+//! [`Config::HeadlessBot`] switches the account into this mode
+//! and [`Dispatcher`] routes incoming messages to handlers by their text prefix.
+//! Simple responders may instead set [`Config::AutoReplyText`]
+//! to let the core answer incoming messages with a fixed text.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::Result;
+
+use crate::chat::{self, Chat, ChatId};
+use crate::config::Config;
+use crate::constants::{Blocked, Chattype};
+use crate::contact::ContactId;
+use crate::context::Context;
+use crate::message::{markseen_msgs, Message, MsgId, Viewtype};
+use crate::param::Param;
+use crate::tools::time;
+
+/// Boxed handler future.
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// Boxed handler callback.
+type Handler = Box<dyn Fn(Context, BotMsg) -> HandlerFuture + Send + Sync>;
+
+/// Incoming message passed to a [`Dispatcher`] handler.
+#[derive(Debug, Clone)]
+pub struct BotMsg {
+    /// The message itself.
+    pub msg: Message,
+
+    /// Chat the message was received in, replies should go there.
+    pub chat_id: ChatId,
+
+    /// Sender of the message.
+    pub from_id: ContactId,
+
+    /// Matched command prefix, empty if the fallback handler is called.
+    pub command: String,
+
+    /// Message text following the command, trimmed.
+    /// The whole message text if the fallback handler is called.
+    pub args: String,
+}
+
+/// Routes incoming messages to handlers by the prefix of their text.
+///
+/// Commands are matched at the start of the message text
+/// and must be followed by whitespace or the end of the text,
+/// so `/help` matches `/help` and `/help me`, but not `/helpme`.
+/// If several commands match, the longest one wins.
+#[derive(Default)]
+pub struct Dispatcher {
+    commands: Vec<(String, Handler)>,
+    fallback: Option<Handler>,
+}
+
+impl std::fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field(
+                "commands",
+                &self.commands.iter().map(|(c, _)| c).collect::<Vec<_>>(),
+            )
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher without handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for messages starting with `command`, e.g. `/help`.
+    pub fn command<F, Fut>(mut self, command: &str, handler: F) -> Self
+    where
+        F: Fn(Context, BotMsg) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.commands.push((
+            command.to_string(),
+            Box::new(move |context, msg| Box::pin(handler(context, msg))),
+        ));
+        self
+    }
+
+    /// Registers a handler for messages not matching any command.
+    pub fn fallback<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Context, BotMsg) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(move |context, msg| {
+            Box::pin(handler(context, msg))
+        }));
+        self
+    }
+
+    /// Returns the handler and the arguments for the given message text.
+    fn route<'a>(&self, text: &'a str) -> Option<(&Handler, &str, &'a str)> {
+        let text = text.trim_start();
+        let matched = self
+            .commands
+            .iter()
+            .filter_map(|(command, handler)| {
+                let rest = text.strip_prefix(command.as_str())?;
+                if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                    Some((handler, command.as_str(), rest.trim()))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(_, command, _)| command.len());
+        matched.or_else(|| {
+            self.fallback
+                .as_ref()
+                .map(|handler| (handler, "", text.trim_end()))
+        })
+    }
+
+    /// Passes a single message to the matching handler.
+    ///
+    /// Outgoing, info and automatic reply messages are ignored,
+    /// the latter to avoid mail loops with vacation notices.
+    /// Returns true if a handler was called.
+    pub async fn dispatch(&self, context: &Context, msg_id: MsgId) -> Result<bool> {
+        let msg = Message::load_from_db(context, msg_id).await?;
+        if msg.get_from_id() == ContactId::SELF || msg.is_info() || msg.is_auto_reply() {
+            return Ok(false);
+        }
+        let text = msg.get_text();
+        if let Some((handler, command, args)) = self.route(&text) {
+            let bot_msg = BotMsg {
+                chat_id: msg.get_chat_id(),
+                from_id: msg.get_from_id(),
+                command: command.to_string(),
+                args: args.to_string(),
+                msg,
+            };
+            handler(context.clone(), bot_msg).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Dispatches all messages not processed yet, see [`Context::get_next_msgs`].
+    ///
+    /// Errors of handlers are logged and do not stop processing.
+    /// Afterwards, the messages are marked as seen if [`Config::HeadlessBot`] is set,
+    /// otherwise only [`Config::LastMsgId`] is advanced.
+    /// Returns the number of handled messages.
+    pub async fn process_next_msgs(&self, context: &Context) -> Result<usize> {
+        let msg_ids = context.get_next_msgs().await?;
+        let mut handled = 0;
+        for msg_id in &msg_ids {
+            match self.dispatch(context, *msg_id).await {
+                Ok(true) => handled += 1,
+                Ok(false) => {}
+                Err(err) => warn!(context, "Bot failed to handle {msg_id}: {err:#}."),
+            }
+        }
+
+        if let Some(last_msg_id) = msg_ids.last() {
+            if context.get_config_bool(Config::HeadlessBot).await? {
+                markseen_msgs(context, msg_ids.clone()).await?;
+            } else {
+                context
+                    .set_config_u32(Config::LastMsgId, last_msg_id.to_u32())
+                    .await?;
+            }
+        }
+        Ok(handled)
+    }
+
+    /// Waits for new messages and dispatches them until IO is stopped.
+    ///
+    /// Returns when [`Context::wait_next_msgs`] is woken up by [`Context::stop_io`].
+    pub async fn run(&self, context: &Context) -> Result<()> {
+        loop {
+            self.process_next_msgs(context).await?;
+            context.new_msgs_notify.notified().await;
+            if !context.scheduler.is_running().await {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Replies with [`Config::AutoReplyText`] to an incoming message in a 1:1 chat.
+///
+/// Each sender gets at most one automatic reply per [`Config::AutoReplyInterval`].
+/// The caller must check that the message itself may be replied to,
+/// see `MimeMessage::may_be_auto_replied()`.
+pub(crate) async fn maybe_send_auto_reply(
+    context: &Context,
+    chat_id: ChatId,
+    from_id: ContactId,
+) -> Result<()> {
+    let Some(text) = context.get_config(Config::AutoReplyText).await? else {
+        return Ok(());
+    };
+    if text.trim().is_empty() || from_id.is_special() {
+        return Ok(());
+    }
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    if chat.typ != Chattype::Single
+        || chat.blocked != Blocked::Not
+        || !chat.can_send(context).await?
+    {
+        return Ok(());
+    }
+
+    let now = time();
+    let interval = context.get_config_i64(Config::AutoReplyInterval).await?;
+    let last_auto_reply: i64 = context
+        .sql
+        .query_get_value(
+            "SELECT last_auto_reply FROM contacts WHERE id=?",
+            (from_id,),
+        )
+        .await?
+        .unwrap_or_default();
+    if last_auto_reply <= now && now < last_auto_reply.saturating_add(interval) {
+        info!(
+            context,
+            "Not sending automatic reply to {from_id} again yet."
+        );
+        return Ok(());
+    }
+    context
+        .sql
+        .execute(
+            "UPDATE contacts SET last_auto_reply=? WHERE id=?",
+            (now, from_id),
+        )
+        .await?;
+
+    let mut msg = Message::new(Viewtype::Text);
+    msg.set_text(text);
+    msg.param.set_int(Param::AutoReply, 1);
+    chat::send_msg(context, chat_id, &mut msg).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chat;
+    use crate::constants::Blocked;
+    use crate::events::EventType;
+    use crate::message::MessageState;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_dispatcher_route() {
+        let dispatcher = Dispatcher::new()
+            .command("/help", |_, _| async { Ok(()) })
+            .command("/help-all", |_, _| async { Ok(()) });
+        assert_eq!(dispatcher.route("/help").unwrap().1, "/help");
+        assert_eq!(dispatcher.route(" /help  me ").unwrap().2, "me");
+        assert_eq!(dispatcher.route("/help-all x").unwrap().1, "/help-all");
+        assert!(dispatcher.route("/helpme").is_none());
+        assert!(dispatcher.route("hello").is_none());
+
+        let dispatcher = dispatcher.fallback(|_, _| async { Ok(()) });
+        let (_, command, args) = dispatcher.route("hello there").unwrap();
+        assert_eq!(command, "");
+        assert_eq!(args, "hello there");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_headless_bot() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        bob.set_config_bool(Config::HeadlessBot, true).await?;
+        assert!(bob.get_config_bool(Config::Bot).await?);
+        assert!(!bob.get_config_bool(Config::MdnsEnabled).await?);
+        bob.evtracker
+            .get_matching(|evt| matches!(evt, EventType::ConfigChanged(Config::Bot)))
+            .await;
+        bob.evtracker
+            .get_matching(|evt| matches!(evt, EventType::ConfigChanged(Config::MdnsEnabled)))
+            .await;
+
+        let echoed = Arc::new(AtomicUsize::new(0));
+        let echoed_clone = Arc::clone(&echoed);
+        let dispatcher = Dispatcher::new().command("/echo", move |context, msg| {
+            let echoed = Arc::clone(&echoed_clone);
+            async move {
+                echoed.fetch_add(1, Ordering::Relaxed);
+                chat::send_text_msg(&context, msg.chat_id, msg.args).await?;
+                Ok(())
+            }
+        });
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = alice.send_text(alice_chat.id, "/echo hi").await;
+        let msg = bob.recv_msg(&sent).await;
+        assert_eq!(msg.chat_blocked, Blocked::Not);
+
+        assert_eq!(dispatcher.process_next_msgs(&bob).await?, 1);
+        assert_eq!(echoed.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            bob.pop_sent_msg().await.load_from_db().await.get_text(),
+            "hi"
+        );
+        assert_eq!(
+            Message::load_from_db(&bob, msg.id).await?.state,
+            MessageState::InSeen
+        );
+
+        // Already processed messages and own replies are not dispatched again.
+        assert_eq!(dispatcher.process_next_msgs(&bob).await?, 0);
+        assert_eq!(echoed.load(Ordering::Relaxed), 1);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_auto_reply() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        bob.set_config(Config::AutoReplyText, Some("I am a bot, send /help."))
+            .await?;
+        let bob_chat = bob.create_chat(&alice).await;
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = alice.send_text(alice_chat.id, "Hi").await;
+        bob.recv_msg(&sent).await;
+        let auto_reply = bob.pop_sent_msg().await;
+        assert!(auto_reply
+            .payload()
+            .contains("Auto-Submitted: auto-replied"));
+        assert_eq!(auto_reply.load_from_db().await.chat_id, bob_chat.id);
+
+        // Alice's client recognizes the reply and does not answer it automatically.
+        alice
+            .set_config(Config::AutoReplyText, Some("I am on vacation."))
+            .await?;
+        let msg = alice.recv_msg(&auto_reply).await;
+        assert!(msg.is_auto_reply());
+        assert_eq!(msg.get_text(), "I am a bot, send /help.");
+        assert!(alice.pop_sent_msg_opt(Duration::ZERO).await.is_none());
+
+        // The same sender is not replied to again within the interval.
+        let sent = alice.send_text(alice_chat.id, "Hi again").await;
+        bob.recv_msg(&sent).await;
+        assert!(bob.pop_sent_msg_opt(Duration::ZERO).await.is_none());
+
+        bob.set_config(Config::AutoReplyInterval, Some("0")).await?;
+        let sent = alice.send_text(alice_chat.id, "Hi once more").await;
+        bob.recv_msg(&sent).await;
+        assert!(bob.pop_sent_msg_opt(Duration::ZERO).await.is_some());
+        Ok(())
+    }
+}
@@ -3,46 +3,60 @@
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use anyhow::{bail, ensure, Context as _, Result};
+use base64::Engine as _;
 use deltachat_derive::{FromSql, ToSql};
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use crate::aheader::EncryptPreference;
+use crate::avatar::fallback_avatar_svg;
 use crate::blob::BlobObject;
 use crate::chatlist::Chatlist;
 use crate::color::str_to_color;
-use crate::config::Config;
+use crate::config::{Config, InternalConfig};
 use crate::constants::{
-    Blocked, Chattype, DC_CHAT_ID_ALLDONE_HINT, DC_CHAT_ID_ARCHIVED_LINK, DC_CHAT_ID_LAST_SPECIAL,
-    DC_CHAT_ID_TRASH, DC_RESEND_USER_AVATAR_DAYS,
+    Blocked, Chattype, DC_CHAT_ID_ARCHIVED_LINK, DC_CHAT_ID_LAST_SPECIAL, DC_CHAT_ID_TRASH,
+    DC_RESEND_USER_AVATAR_DAYS, MAX_UI_SETTINGS_BYTES,
 };
-use crate::contact::{Contact, ContactId, Origin, VerifiedStatus};
+use crate::contact::{addr_cmp, Contact, ContactAddress, ContactId, Origin, VerifiedStatus};
 use crate::context::Context;
 use crate::debug_logging::maybe_set_logging_xdc;
 use crate::download::DownloadState;
+use crate::e2ee::EncryptHelper;
 use crate::ephemeral::Timer as EphemeralTimer;
 use crate::events::EventType;
 use crate::html::new_html_mimepart;
+use crate::imap::drafts;
+use crate::key::DcKey;
 use crate::location;
 use crate::message::{self, Message, MessageState, MsgId, Viewtype};
-use crate::mimefactory::MimeFactory;
+use crate::mimefactory::{self, MimeFactory, RenderedEmail};
 use crate::mimeparser::SystemMessage;
+use crate::net::ensure_online;
+use crate::net::http::get_client;
 use crate::param::{Param, Params};
 use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
 use crate::receive_imf::ReceivedMsg;
 use crate::scheduler::InterruptInfo;
 use crate::smtp::send_msg_to_smtp;
+use crate::socks::Socks5Config;
+use crate::spam;
 use crate::sql;
 use crate::stock_str;
+use crate::sync::{SyncChat, SyncData, UiSettingsData};
 use crate::tools::{
     buf_compress, create_id, create_outgoing_rfc724_mid, create_smeared_timestamp,
     create_smeared_timestamps, get_abs_path, gm2local_offset, improve_single_line_input,
-    strip_rtlo_characters, time, IsNoneOrEmpty,
+    strip_rtlo_characters, time, timestamp_to_str, IsNoneOrEmpty,
 };
+use crate::undo;
 use crate::webxdc::WEBXDC_SUFFIX;
 
 /// An chat item, such as a message or a marker.
@@ -122,6 +136,9 @@ pub(crate) enum CantSendReason {
 
     /// Not a member of the chat.
     NotAMember,
+
+    /// Read-only channel, only the owner can post.
+    ReadOnlyChannel,
 }
 
 impl fmt::Display for CantSendReason {
@@ -141,6 +158,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 write!(f, "mailing list does not have a know post address")
             }
             Self::NotAMember => write!(f, "not a member of the chat"),
+            Self::ReadOnlyChannel => write!(f, "only the owner can post to the channel"),
         }
     }
 }
@@ -155,6 +173,18 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 pub struct ChatId(u32);
 
 impl ChatId {
+    /// Chat ID for messages which need to be deleted, see [`ChatId::is_trash`].
+    pub const TRASH: ChatId = ChatId::new(3);
+
+    /// Virtual chat ID linking to the archived chats, see [`ChatId::is_archived_link`].
+    pub const ARCHIVED_LINK: ChatId = ChatId::new(6);
+
+    /// Virtual chat ID signalling there are only archived chats, see [`ChatId::is_alldone_hint`].
+    pub const ALLDONE_HINT: ChatId = ChatId::new(7);
+
+    /// Larger chat IDs are "real" chats, see [`ChatId::is_special`].
+    pub const LAST_SPECIAL: ChatId = ChatId::new(9);
+
     /// Create a new [ChatId].
     pub const fn new(id: u32) -> ChatId {
         ChatId(id)
@@ -171,7 +201,7 @@ pub fn is_unset(self) -> bool {
     ///
     /// This kind of chat ID can not be used for real chats.
     pub fn is_special(self) -> bool {
-        (0..=DC_CHAT_ID_LAST_SPECIAL.0).contains(&self.0)
+        (0..=Self::LAST_SPECIAL.0).contains(&self.0)
     }
 
     /// Chat ID for messages which need to be deleted.
@@ -181,7 +211,7 @@ pub fn is_special(self) -> bool {
     /// as they are not deleted on the server so that their rfc724_mid
     /// remains known and downloading them again can be avoided.
     pub fn is_trash(self) -> bool {
-        self == DC_CHAT_ID_TRASH
+        self == Self::TRASH
     }
 
     /// Chat ID signifying there are **any** number of archived chats.
@@ -191,7 +221,7 @@ pub fn is_trash(self) -> bool {
     ///
     /// [`Chatlist`]: crate::chatlist::Chatlist
     pub fn is_archived_link(self) -> bool {
-        self == DC_CHAT_ID_ARCHIVED_LINK
+        self == Self::ARCHIVED_LINK
     }
 
     /// Virtual chat ID signalling there are **only** archived chats.
@@ -203,7 +233,7 @@ pub fn is_archived_link(self) -> bool {
     /// [`DC_GCL_ADD_ALLDONE_HINT`]: crate::constants::DC_GCL_ADD_ALLDONE_HINT
     /// [`Chatlist`]: crate::chatlist::Chatlist
     pub fn is_alldone_hint(self) -> bool {
-        self == DC_CHAT_ID_ALLDONE_HINT
+        self == Self::ALLDONE_HINT
     }
 
     /// Returns the [`ChatId`] for the 1:1 chat with `contact_id` if it exists.
@@ -351,6 +381,9 @@ pub(crate) async fn set_blocked(self, context: &Context, new_blocked: Blocked) -
                 (new_blocked, self),
             )
             .await?;
+        if count > 0 {
+            context.invalidate_fresh_msg_count();
+        }
         Ok(count > 0)
     }
 
@@ -375,7 +408,7 @@ pub async fn block(self, context: &Context) -> Result<()> {
             }
             Chattype::Group => {
                 info!(context, "Can't block groups yet, deleting the chat.");
-                self.delete(context).await?;
+                self.delete_ex(context, false).await?;
             }
             Chattype::Mailinglist => {
                 if self.set_blocked(context, Blocked::Yes).await? {
@@ -427,13 +460,44 @@ pub async fn accept(self, context: &Context) -> Result<()> {
             }
         }
 
-        if self.set_blocked(context, Blocked::Not).await? {
+        let spam_removed = self.set_spam(context, false).await?;
+        if self.set_blocked(context, Blocked::Not).await? || spam_removed {
             context.emit_event(EventType::ChatModified(self));
         }
 
         Ok(())
     }
 
+    /// Sets whether the contact request is classified as spam.
+    ///
+    /// Returns true if the value was modified.
+    pub(crate) async fn set_spam(self, context: &Context, spam: bool) -> Result<bool> {
+        let count = context
+            .sql
+            .execute(
+                "UPDATE chats SET spam=?1 WHERE id=?2 AND spam!=?1",
+                (spam, self),
+            )
+            .await?;
+        if count > 0 {
+            context.invalidate_fresh_msg_count();
+        }
+        Ok(count > 0)
+    }
+
+    /// Moves a contact request classified as spam back to the normal chatlist.
+    ///
+    /// The chat stays a contact request.
+    /// Messages of the chat are moved out of the Spam folder on the server.
+    pub async fn mark_not_spam(self, context: &Context) -> Result<()> {
+        if !self.set_spam(context, false).await? {
+            return Ok(());
+        }
+        spam::move_out_of_spam_folder(context, self).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
     /// Sets protection without sending a message.
     ///
     /// Returns whether the protection status was actually modified.
@@ -556,6 +620,7 @@ pub async fn set_visibility(self, context: &Context, visibility: ChatVisibility)
             })
             .await?;
 
+        context.invalidate_fresh_msg_count();
         context.emit_msgs_changed_without_ids();
 
         Ok(())
@@ -624,15 +689,50 @@ pub(crate) fn emit_msg_event(self, context: &Context, msg_id: MsgId, important:
     }
 
     /// Deletes a chat.
+    ///
+    /// If [`Config::UndoTimeout`] is set, the deletion can be reversed with [`undo::undo`].
     pub async fn delete(self, context: &Context) -> Result<()> {
+        self.delete_ex(context, true).await
+    }
+
+    /// Deletes a chat, queueing the deletion in the undo queue if `undoable` is true
+    /// and [`Config::UndoTimeout`] is set.
+    pub(crate) async fn delete_ex(self, context: &Context, undoable: bool) -> Result<()> {
         ensure!(
             !self.is_special(),
             "bad chat_id, can not be a special chat: {}",
             self
         );
+        if undoable && undo::defer_delete_chat(context, self).await? {
+            return Ok(());
+        }
         /* Up to 2017-11-02 deleting a group also implied leaving it, see above why we have changed this. */
 
         let chat = Chat::load_from_db(context, self).await?;
+        self.delete_from_db(context).await?;
+
+        context.emit_msgs_changed_without_ids();
+
+        context.set_config(Config::LastHousekeeping, None).await?;
+        context
+            .scheduler
+            .interrupt_inbox(InterruptInfo::new(false))
+            .await;
+
+        if chat.is_self_talk() {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.text = stock_str::self_deleted_msg_body(context).await;
+            add_device_msg(context, None, Some(&mut msg)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the chat, its messages and members from the database.
+    ///
+    /// Unlike [`ChatId::delete_ex`], this does not schedule housekeeping,
+    /// so it can be used from housekeeping itself.
+    pub(crate) async fn delete_from_db(self, context: &Context) -> Result<()> {
         context
             .sql
             .execute(
@@ -655,21 +755,7 @@ pub async fn delete(self, context: &Context) -> Result<()> {
             .sql
             .execute("DELETE FROM chats WHERE id=?;", (self,))
             .await?;
-
-        context.emit_msgs_changed_without_ids();
-
-        context.set_config(Config::LastHousekeeping, None).await?;
-        context
-            .scheduler
-            .interrupt_inbox(InterruptInfo::new(false))
-            .await;
-
-        if chat.is_self_talk() {
-            let mut msg = Message::new(Viewtype::Text);
-            msg.text = stock_str::self_deleted_msg_body(context).await;
-            add_device_msg(context, None, Some(&mut msg)).await?;
-        }
-
+        context.invalidate_fresh_msg_count();
         Ok(())
     }
 
@@ -687,6 +773,7 @@ pub async fn set_draft(self, context: &Context, mut msg: Option<&mut Message>) -
         };
 
         if changed {
+            drafts::schedule_draft_upload(context, self).await?;
             context.emit_msgs_changed(
                 self,
                 if msg.is_some() {
@@ -752,6 +839,9 @@ async fn do_set_draft(self, context: &Context, msg: &mut Message) -> Result<bool
                     bail!("No text and no quote in draft");
                 }
             }
+            _ if msg.param.get_bool(Param::FileReference).unwrap_or_default() => {
+                ensure!(msg.param.exists(Param::File), "no file stored in params");
+            }
             _ => {
                 let blob = msg
                     .param
@@ -1106,28 +1196,17 @@ async fn get_parent_mime_headers(
     /// prefer plaintext emails.
     ///
     /// To get more verbose summary for a contact, including its key fingerprint, use [`Contact::get_encrinfo`].
+    ///
+    /// For structured data, use [`Chat::get_encryption_info`].
     pub async fn get_encryption_info(self, context: &Context) -> Result<String> {
         let mut ret_mutual = String::new();
         let mut ret_nopreference = String::new();
         let mut ret_reset = String::new();
 
-        for contact_id in get_chat_contacts(context, self)
-            .await?
-            .iter()
-            .filter(|&contact_id| !contact_id.is_special())
-        {
-            let contact = Contact::get_by_id(context, *contact_id).await?;
-            let addr = contact.get_addr();
-            let peerstate = Peerstate::from_addr(context, addr).await?;
-
-            match peerstate
-                .filter(|peerstate| {
-                    peerstate
-                        .peek_key(PeerstateVerifiedStatus::Unverified)
-                        .is_some()
-                })
-                .map(|peerstate| peerstate.prefer_encrypt)
-            {
+        let chat = Chat::load_from_db(context, self).await?;
+        for member in chat.get_encryption_info(context).await?.members {
+            let addr = &member.addr;
+            match member.fingerprint.as_ref().map(|_| member.prefer_encrypt) {
                 Some(EncryptPreference::Mutual) => ret_mutual += &format!("{addr}\n"),
                 Some(EncryptPreference::NoPreference) => ret_nopreference += &format!("{addr}\n"),
                 Some(EncryptPreference::Reset) | None => ret_reset += &format!("{addr}\n"),
@@ -1163,6 +1242,115 @@ pub async fn get_encryption_info(self, context: &Context) -> Result<String> {
         Ok(ret.trim().to_string())
     }
 
+    /// Sets the address messages to the chat are sent from.
+    ///
+    /// `addr` must be the primary address or one of [`Config::AliasAddrs`].
+    /// `None` resets the choice, then the primary address is used
+    /// unless an incoming message to an alias selects the alias.
+    pub async fn set_self_addr(self, context: &Context, addr: Option<&str>) -> Result<()> {
+        let mut chat = Chat::load_from_db(context, self).await?;
+        if let Some(addr) = addr {
+            let primary_addr = context.get_primary_self_addr().await?;
+            ensure!(
+                addr_cmp(addr, &primary_addr)
+                    || context
+                        .get_alias_addrs()
+                        .await?
+                        .iter()
+                        .any(|alias| addr_cmp(addr, alias)),
+                "{addr} is not a self address"
+            );
+            chat.param.set(Param::SelfAddr, addr);
+        } else {
+            chat.param.remove(Param::SelfAddr);
+        }
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Sets opaque settings of the UI for the chat, e.g. the wallpaper.
+    ///
+    /// The settings are included in backups
+    /// and synchronized to other devices if [`Config::SendSyncMsgs`] is set.
+    /// `None` removes the settings.
+    /// The settings must not be longer than [`MAX_UI_SETTINGS_BYTES`] bytes.
+    pub async fn set_ui_settings(self, context: &Context, settings: Option<&str>) -> Result<()> {
+        ensure!(
+            !self.is_special(),
+            "Cannot set UI settings of special chats"
+        );
+        let settings = settings.unwrap_or_default();
+        ensure!(
+            settings.len() <= MAX_UI_SETTINGS_BYTES,
+            "UI settings must not be longer than {MAX_UI_SETTINGS_BYTES} bytes"
+        );
+        self.set_ui_settings_ex(context, settings).await?;
+        if let Some(chat) = SyncChat::from_chat_id(context, self).await? {
+            context
+                .add_sync_item(SyncData::UiSettings(UiSettingsData {
+                    chat: Some(chat),
+                    settings: settings.to_string(),
+                }))
+                .await?;
+            context.send_sync_msg().await?;
+        }
+        Ok(())
+    }
+
+    /// Stores the UI settings without synchronizing them.
+    pub(crate) async fn set_ui_settings_ex(self, context: &Context, settings: &str) -> Result<()> {
+        context
+            .sql
+            .execute(
+                "UPDATE chats SET ui_settings=? WHERE id=?;",
+                (settings, self),
+            )
+            .await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns the settings set by [`ChatId::set_ui_settings`], `None` if there are none.
+    pub async fn get_ui_settings(self, context: &Context) -> Result<Option<String>> {
+        let settings: Option<String> = context
+            .sql
+            .query_get_value("SELECT ui_settings FROM chats WHERE id=?;", (self,))
+            .await?;
+        Ok(settings.filter(|settings| !settings.is_empty()))
+    }
+
+    /// Sets the last error sending a message to the chat, see [`Chat::get_last_error`].
+    ///
+    /// The error is also set for the contact of a 1:1 chat.
+    /// An empty `error` clears the error of the chat and of all its members
+    /// as the message was accepted for all of them.
+    pub(crate) async fn set_last_error(self, context: &Context, error: &str) -> Result<()> {
+        let changed = context
+            .sql
+            .execute(
+                "UPDATE chats SET last_error=? WHERE id=? AND last_error!=?",
+                (error, self, error),
+            )
+            .await?;
+        if changed > 0 {
+            context.emit_event(EventType::ChatModified(self));
+        }
+
+        let chattype: Option<Chattype> = context
+            .sql
+            .query_get_value("SELECT type FROM chats WHERE id=?", (self,))
+            .await?;
+        if error.is_empty() || chattype == Some(Chattype::Single) {
+            for contact_id in get_chat_contacts(context, self).await? {
+                if !contact_id.is_special() {
+                    contact_id.set_last_error(context, error).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Bad evil escape hatch.
     ///
     /// Avoid using this, eventually types should be cleaned up enough
@@ -1252,6 +1440,66 @@ fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlRe
     }
 }
 
+/// Converts a raw chat ID, e.g. passed over the FFI, into a [ChatId].
+///
+/// The ID may be a special one, check it with [ChatId::is_special] where needed.
+impl From<u32> for ChatId {
+    fn from(id: u32) -> Self {
+        ChatId::new(id)
+    }
+}
+
+/// Converts a [ChatId] into its raw value, e.g. to pass it over the FFI.
+impl From<ChatId> for u32 {
+    fn from(chat_id: ChatId) -> Self {
+        chat_id.0
+    }
+}
+
+/// Whether messages sent to a chat are end-to-end encrypted,
+/// see [`ChatEncryptionInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatEncryption {
+    /// Messages are encrypted.
+    Yes,
+
+    /// Messages are not encrypted, but the keys of some members are available.
+    Partially,
+
+    /// Messages are not encrypted and no keys of the members are available.
+    No,
+}
+
+/// Encryption status of a chat member, see [`ChatEncryptionInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberEncryptionInfo {
+    /// Contact ID of the member.
+    pub contact_id: ContactId,
+
+    /// Email address of the member.
+    pub addr: String,
+
+    /// Hex fingerprint of the key used to encrypt to the member,
+    /// `None` if no key is available.
+    pub fingerprint: Option<String>,
+
+    /// Whether the key is verified.
+    pub verified: bool,
+
+    /// Encryption preference of the member as announced in the Autocrypt header.
+    pub prefer_encrypt: EncryptPreference,
+}
+
+/// Encryption status of a chat, returned by [`Chat::get_encryption_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatEncryptionInfo {
+    /// Encryption status of the members, not including self.
+    pub members: Vec<MemberEncryptionInfo>,
+
+    /// Whether messages sent to the chat are encrypted.
+    pub encryption: ChatEncryption,
+}
+
 /// An object representing a single chat in memory.
 /// Chat objects are created using eg. `Chat::load_from_db`
 /// and are not updated on database changes;
@@ -1287,6 +1535,12 @@ pub struct Chat {
 
     /// If the chat is protected (verified).
     pub(crate) protected: ProtectionStatus,
+
+    /// If the contact request is classified as spam.
+    spam: bool,
+
+    /// Last error sending a message to the chat, empty if there is none.
+    last_error: String,
 }
 
 impl Chat {
@@ -1296,7 +1550,8 @@ pub async fn load_from_db(context: &Context, chat_id: ChatId) -> Result<Self> {
             .sql
             .query_row(
                 "SELECT c.type, c.name, c.grpid, c.param, c.archived,
-                    c.blocked, c.locations_send_until, c.muted_until, c.protected
+                    c.blocked, c.locations_send_until, c.muted_until, c.protected, c.spam,
+                    c.last_error
              FROM chats c
              WHERE c.id=?;",
                 (chat_id,),
@@ -1312,6 +1567,8 @@ pub async fn load_from_db(context: &Context, chat_id: ChatId) -> Result<Self> {
                         is_sending_locations: row.get(6)?,
                         mute_duration: row.get(7)?,
                         protected: row.get(8)?,
+                        spam: row.get(9)?,
+                        last_error: row.get(10)?,
                     };
                     Ok(c)
                 },
@@ -1387,6 +1644,8 @@ pub(crate) async fn why_cant_send(&self, context: &Context) -> Result<Option<Can
             Some(ReadOnlyMailingList)
         } else if !self.is_self_in_chat(context).await? {
             Some(NotAMember)
+        } else if !self.may_post(context, ContactId::SELF).await? {
+            Some(ReadOnlyChannel)
         } else {
             None
         };
@@ -1441,6 +1700,27 @@ pub fn get_mailinglist_addr(&self) -> Option<&str> {
         self.param.get(Param::ListPost)
     }
 
+    /// Returns the address messages to the chat are sent from,
+    /// see [`ChatId::set_self_addr`].
+    ///
+    /// Falls back to the primary address if the chosen alias is not configured anymore.
+    pub async fn get_self_addr(&self, context: &Context) -> Result<String> {
+        let primary_addr = context.get_primary_self_addr().await?;
+        let Some(addr) = self.param.get(Param::SelfAddr) else {
+            return Ok(primary_addr);
+        };
+        if context
+            .get_alias_addrs()
+            .await?
+            .iter()
+            .any(|alias| addr_cmp(addr, alias))
+        {
+            Ok(addr.to_string())
+        } else {
+            Ok(primary_addr)
+        }
+    }
+
     /// Returns profile image path for the chat.
     pub async fn get_profile_image(&self, context: &Context) -> Result<Option<PathBuf>> {
         if let Some(image_rel) = self.param.get(Param::ProfileImage) {
@@ -1487,6 +1767,13 @@ pub async fn get_color(&self, context: &Context) -> Result<u32> {
         Ok(color)
     }
 
+    /// Renders a fallback avatar for chats without profile image as an SVG image.
+    ///
+    /// The avatar shows the initial letter of the chat name on the chat color.
+    pub async fn get_fallback_avatar_svg(&self, context: &Context) -> Result<String> {
+        fallback_avatar_svg(&self.name, self.get_color(context).await?)
+    }
+
     /// Returns a struct describing the current state of the chat.
     ///
     /// This is somewhat experimental, even more so than the rest of
@@ -1529,6 +1816,22 @@ pub fn is_contact_request(&self) -> bool {
         self.blocked == Blocked::Request
     }
 
+    /// Returns true if the chat is a contact request classified as spam.
+    ///
+    /// Such chats are only listed with [`crate::constants::DC_GCL_SPAM_ONLY`].
+    pub fn is_spam(&self) -> bool {
+        self.spam
+    }
+
+    /// Returns the last error sending a message to the chat,
+    /// e.g. a rejected recipient address.
+    ///
+    /// The error is cleared when a message is sent successfully,
+    /// so UIs may show it until then.
+    pub fn get_last_error(&self) -> Option<&str> {
+        Some(self.last_error.as_str()).filter(|error| !error.is_empty())
+    }
+
     /// Returns true if the chat is not promoted.
     pub fn is_unpromoted(&self) -> bool {
         self.param.get_bool(Param::Unpromoted).unwrap_or_default()
@@ -1540,11 +1843,142 @@ pub fn is_promoted(&self) -> bool {
         !self.is_unpromoted()
     }
 
+    /// Returns the addresses of the group admins.
+    ///
+    /// The list is empty if the group does not use roles.
+    pub(crate) fn admin_addrs(&self) -> Vec<&str> {
+        self.param
+            .get(Param::GroupAdmins)
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+            .collect()
+    }
+
+    /// Returns true if the contact may add and remove members
+    /// and change name and avatar of the group.
+    ///
+    /// If the group does not use roles or all admins left the group,
+    /// all contacts are admins.
+    pub async fn is_admin(&self, context: &Context, contact_id: ContactId) -> Result<bool> {
+        let admins = self.admin_addrs();
+        if admins.is_empty() {
+            return Ok(true);
+        }
+        let is_admin_addr = |addr: &str| admins.iter().any(|admin| addr_cmp(admin, addr));
+        let members = get_chat_member_addrs(context, self.id).await?;
+        if !members.iter().any(|(_, addr)| is_admin_addr(addr)) {
+            return Ok(true);
+        }
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        Ok(is_admin_addr(contact.get_addr()))
+    }
+
+    /// Returns the members of the group who are admins.
+    ///
+    /// The list is empty if the group does not use roles or all admins left the group.
+    async fn get_admin_members(&self, context: &Context) -> Result<Vec<(ContactId, String)>> {
+        let admins = self.admin_addrs();
+        if admins.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut members = get_chat_member_addrs(context, self.id).await?;
+        members.retain(|(_, addr)| admins.iter().any(|admin| addr_cmp(admin, addr)));
+        Ok(members)
+    }
+
+    /// Returns true if the chat is a read-only channel
+    /// where only the owner may post, see [`create_channel`].
+    pub fn is_channel(&self) -> bool {
+        self.get_channel_owner().is_some()
+    }
+
+    /// Returns the address of the channel owner if the chat is a read-only channel.
+    pub fn get_channel_owner(&self) -> Option<&str> {
+        self.param
+            .get(Param::ChannelOwner)
+            .filter(|owner| !owner.is_empty())
+    }
+
+    /// Returns true if messages of the contact may be posted to the chat.
+    ///
+    /// This is true for all chats but channels, where only the owner may post.
+    pub(crate) async fn may_post(&self, context: &Context, contact_id: ContactId) -> Result<bool> {
+        let Some(owner) = self.get_channel_owner() else {
+            return Ok(true);
+        };
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        Ok(addr_cmp(owner, contact.get_addr()))
+    }
+
     /// Returns true if chat protection is enabled.
     pub fn is_protected(&self) -> bool {
         self.protected == ProtectionStatus::Protected
     }
 
+    /// Returns the encryption status of the chat members
+    /// and whether messages sent to the chat are encrypted.
+    ///
+    /// Replies to encrypted messages are always encrypted if all keys are available,
+    /// this is not taken into account.
+    pub async fn get_encryption_info(&self, context: &Context) -> Result<ChatEncryptionInfo> {
+        let mut members = Vec::new();
+        let mut peerstates = Vec::new();
+        for contact_id in get_chat_contacts(context, self.id)
+            .await?
+            .into_iter()
+            .filter(|contact_id| !contact_id.is_special())
+        {
+            let contact = Contact::get_by_id(context, contact_id).await?;
+            let addr = contact.get_addr().to_string();
+            let peerstate = Peerstate::from_addr(context, &addr).await?;
+            let fingerprint = peerstate
+                .as_ref()
+                .and_then(|peerstate| peerstate.peek_key(PeerstateVerifiedStatus::Unverified))
+                .map(|key| key.fingerprint().hex());
+            members.push(MemberEncryptionInfo {
+                contact_id,
+                addr,
+                fingerprint: fingerprint.clone(),
+                verified: peerstate
+                    .as_ref()
+                    .map_or(false, |peerstate| peerstate.is_using_verified_key()),
+                prefer_encrypt: peerstate
+                    .as_ref()
+                    .map_or(EncryptPreference::Reset, |peerstate| {
+                        peerstate.prefer_encrypt
+                    }),
+            });
+            // Peerstates without a key cannot be used to encrypt.
+            peerstates.push(peerstate.filter(|_| fingerprint.is_some()));
+        }
+
+        let keys_count = peerstates.iter().flatten().count();
+        let peerstates: Vec<(Option<Peerstate>, &str)> = peerstates
+            .into_iter()
+            .zip(&members)
+            .map(|(peerstate, member)| (peerstate, member.addr.as_str()))
+            .collect();
+        // `should_encrypt()` fails if a key is missing in a protected chat,
+        // so it is only asked if all keys are available.
+        let encrypted = keys_count == members.len()
+            && EncryptHelper::new(context).await?.should_encrypt(
+                context,
+                self.is_protected(),
+                &peerstates,
+            )?;
+        let encryption = if encrypted {
+            ChatEncryption::Yes
+        } else if keys_count > 0 {
+            ChatEncryption::Partially
+        } else {
+            ChatEncryption::No
+        };
+        Ok(ChatEncryptionInfo {
+            members,
+            encryption,
+        })
+    }
+
     /// Returns true if the chat was protected, and then an incoming message broke this protection.
     ///
     /// This function is only useful if the UI enabled the `verified_one_on_one_chats` feature flag,
@@ -1969,7 +2403,11 @@ pub(crate) async fn update_device_icon(context: &Context) -> Result<()> {
 }
 
 pub(crate) async fn get_broadcast_icon(context: &Context) -> Result<String> {
-    if let Some(icon) = context.sql.get_raw_config("icon-broadcast").await? {
+    if let Some(icon) = context
+        .sql
+        .get_raw_config(InternalConfig::IconBroadcast.as_ref())
+        .await?
+    {
         return Ok(icon);
     }
 
@@ -1978,13 +2416,17 @@ pub(crate) async fn get_broadcast_icon(context: &Context) -> Result<String> {
     let icon = blob.as_name().to_string();
     context
         .sql
-        .set_raw_config("icon-broadcast", Some(&icon))
+        .set_raw_config(InternalConfig::IconBroadcast.as_ref(), Some(&icon))
         .await?;
     Ok(icon)
 }
 
 pub(crate) async fn get_archive_icon(context: &Context) -> Result<String> {
-    if let Some(icon) = context.sql.get_raw_config("icon-archive").await? {
+    if let Some(icon) = context
+        .sql
+        .get_raw_config(InternalConfig::IconArchive.as_ref())
+        .await?
+    {
         return Ok(icon);
     }
 
@@ -1993,7 +2435,7 @@ pub(crate) async fn get_archive_icon(context: &Context) -> Result<String> {
     let icon = blob.as_name().to_string();
     context
         .sql
-        .set_raw_config("icon-archive", Some(&icon))
+        .set_raw_config(InternalConfig::IconArchive.as_ref(), Some(&icon))
         .await?;
     Ok(icon)
 }
@@ -2200,6 +2642,8 @@ pub async fn prepare_msg(context: &Context, chat_id: ChatId, msg: &mut Message)
 async fn prepare_msg_blob(context: &Context, msg: &mut Message) -> Result<()> {
     if msg.viewtype == Viewtype::Text || msg.viewtype == Viewtype::VideochatInvitation {
         // the caller should check if the message text is empty
+    } else if msg.param.get_bool(Param::FileReference).unwrap_or_default() {
+        prepare_msg_file_reference(context, msg).await?;
     } else if msg.viewtype.has_file() {
         let mut blob = msg
             .param
@@ -2291,6 +2735,10 @@ async fn prepare_msg_common(
             && msg.param.get_cmd() == SystemMessage::SecurejoinMessage
         {
             // Send out the message, the securejoin message is supposed to repair the verification
+        } else if reason == CantSendReason::ReadOnlyChannel
+            && msg.param.get_cmd() == SystemMessage::MemberRemovedFromGroup
+        {
+            // Everybody may leave a channel.
         } else {
             bail!("cannot send to {chat_id}: {reason}");
         }
@@ -2310,6 +2758,7 @@ async fn prepare_msg_common(
 
     // ... then change the MessageState in the message object
     msg.state = change_state_to;
+    msg.update_markup();
 
     prepare_msg_blob(context, msg).await?;
     if !msg.hidden {
@@ -2328,6 +2777,47 @@ async fn prepare_msg_common(
     Ok(msg.id)
 }
 
+/// Checks a file set by [`Message::set_file_reference()`].
+///
+/// The file is neither copied to the blobdir nor recoded,
+/// it is read from its original location when the message is rendered.
+async fn prepare_msg_file_reference(context: &Context, msg: &mut Message) -> Result<()> {
+    ensure!(
+        matches!(
+            msg.viewtype,
+            Viewtype::File | Viewtype::Audio | Viewtype::Voice | Viewtype::Video
+        ),
+        "file references are not supported for messages of type #{}",
+        msg.viewtype
+    );
+    let path = msg
+        .param
+        .get_path(Param::File, context)?
+        .with_context(|| format!("attachment missing for message of type #{}", msg.viewtype))?;
+    ensure!(
+        path.is_absolute() && !path.starts_with(context.get_blobdir()),
+        "referenced file {} must be an absolute path outside the blobdir",
+        path.display()
+    );
+    tokio::fs::File::open(&path)
+        .await
+        .with_context(|| format!("cannot read referenced file {}", path.display()))?;
+
+    if !msg.param.exists(Param::MimeType) {
+        if let Some((_, mime)) = message::guess_msgtype_from_suffix(&path) {
+            msg.param.set(Param::MimeType, mime);
+        }
+    }
+
+    info!(
+        context,
+        "Attaching referenced file \"{}\" for message type #{}.",
+        path.display(),
+        msg.viewtype
+    );
+    Ok(())
+}
+
 /// Returns whether a contact is in a chat or not.
 pub async fn is_contact_in_chat(
     context: &Context,
@@ -2451,8 +2941,6 @@ pub(crate) async fn create_send_msg_job(
     context: &Context,
     msg: &mut Message,
 ) -> Result<Option<i64>> {
-    let needs_encryption = msg.param.get_bool(Param::GuaranteeE2ee).unwrap_or_default();
-
     let attach_selfavatar = match shall_attach_selfavatar(context, msg.chat_id).await {
         Ok(attach_selfavatar) => attach_selfavatar,
         Err(err) => {
@@ -2470,7 +2958,7 @@ pub(crate) async fn create_send_msg_job(
 
     // Send BCC to self if it is enabled and we are not going to
     // delete it immediately.
-    if context.get_config_bool(Config::BccSelf).await?
+    if context.should_send_bcc_self().await?
         && context.get_config_delete_server_after().await? != Some(0)
         && !recipients
             .iter()
@@ -2490,7 +2978,48 @@ pub(crate) async fn create_send_msg_job(
         return Ok(None);
     }
 
-    let rendered_msg = match mimefactory.render(context).await {
+    ensure!(!recipients.is_empty(), "no recipients for smtp job set");
+
+    let recipients = recipients.join(" ");
+
+    // Messages referencing files outside the blobdir are rendered only when they are sent,
+    // so the attachment is not stored in the `smtp` table, see `smtp::send_msg_to_smtp()`.
+    let mime = if msg.param.get_bool(Param::FileReference).unwrap_or_default() {
+        String::new()
+    } else {
+        let rendered_msg = mimefactory.render(context).await;
+        process_rendered_msg(context, msg, rendered_msg, attach_selfavatar)
+            .await?
+            .message
+    };
+
+    let deadline = match msg.param.get_i64(Param::DeliveryTtl) {
+        Some(ttl) if ttl > 0 => time().saturating_add(ttl),
+        _ => 0,
+    };
+    let row_id = context
+        .sql
+        .insert(
+            "INSERT INTO smtp (rfc724_mid, recipients, mime, msg_id, deadline)
+             VALUES           (?1,         ?2,         ?3,   ?4,     ?5)",
+            (&msg.rfc724_mid, recipients, mime, msg.id, deadline),
+        )
+        .await?;
+    Ok(Some(row_id))
+}
+
+/// Processes the result of rendering `msg` for sending
+/// and records the side effects of rendering, e.g. the time the chat members were gossiped.
+///
+/// Marks the message as failed if it could not be rendered.
+pub(crate) async fn process_rendered_msg(
+    context: &Context,
+    msg: &mut Message,
+    rendered_msg: Result<RenderedEmail>,
+    attach_selfavatar: bool,
+) -> Result<RenderedEmail> {
+    let needs_encryption = msg.param.get_bool(Param::GuaranteeE2ee).unwrap_or_default();
+    let mut rendered_msg = match rendered_msg {
         Ok(res) => Ok(res),
         Err(err) => {
             message::set_msg_failed(context, msg, &err.to_string()).await?;
@@ -2530,7 +3059,7 @@ pub(crate) async fn create_send_msg_job(
         }
     }
 
-    if let Some(sync_ids) = rendered_msg.sync_ids_to_delete {
+    if let Some(sync_ids) = rendered_msg.sync_ids_to_delete.take() {
         if let Err(err) = context.delete_sync_ids(sync_ids).await {
             error!(context, "Failed to delete sync ids: {err:#}.");
         }
@@ -2547,28 +3076,10 @@ pub(crate) async fn create_send_msg_job(
         msg.update_param(context).await?;
     }
 
-    ensure!(!recipients.is_empty(), "no recipients for smtp job set");
-
-    let recipients = recipients.join(" ");
-
     msg.subject = rendered_msg.subject.clone();
     msg.update_subject(context).await?;
-
-    let row_id = context
-        .sql
-        .insert(
-            "INSERT INTO smtp (rfc724_mid, recipients, mime, msg_id)
-             VALUES           (?1,         ?2,         ?3,   ?4)",
-            (
-                &rendered_msg.rfc724_mid,
-                recipients,
-                &rendered_msg.message,
-                msg.id,
-            ),
-        )
-        .await?;
-    Ok(Some(row_id))
-}
+    Ok(rendered_msg)
+}
 
 /// Sends a text message to the given chat.
 ///
@@ -2943,6 +3454,143 @@ pub async fn get_chat_media(
     Ok(list)
 }
 
+/// Returns the number of messages of the given types.
+///
+/// Arguments are the same as for [`get_chat_media`].
+/// Together with [`get_chat_media_page`] and [`get_chat_media_index`]
+/// this allows galleries to load large chats in pages.
+pub async fn get_chat_media_count(
+    context: &Context,
+    chat_id: Option<ChatId>,
+    msg_type: Viewtype,
+    msg_type2: Viewtype,
+    msg_type3: Viewtype,
+) -> Result<usize> {
+    let (msg_type2, msg_type3) = media_types_or_default(msg_type, msg_type2, msg_type3);
+    let count = context
+        .sql
+        .count(
+            "SELECT COUNT(*)
+               FROM msgs
+              WHERE (1=? OR chat_id=?)
+                AND chat_id != ?
+                AND (type=? OR type=? OR type=?)
+                AND hidden=0;",
+            (
+                chat_id.is_none(),
+                chat_id.unwrap_or_else(|| ChatId::new(0)),
+                DC_CHAT_ID_TRASH,
+                msg_type,
+                msg_type2,
+                msg_type3,
+            ),
+        )
+        .await?;
+    Ok(count)
+}
+
+/// Returns up to `limit` database message IDs of the given types,
+/// skipping the first `offset` messages.
+///
+/// The order is the same as for [`get_chat_media`], starting with the oldest message,
+/// so `offset` is the index of the first returned message in the full list.
+pub async fn get_chat_media_page(
+    context: &Context,
+    chat_id: Option<ChatId>,
+    msg_type: Viewtype,
+    msg_type2: Viewtype,
+    msg_type3: Viewtype,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<MsgId>> {
+    let (msg_type2, msg_type3) = media_types_or_default(msg_type, msg_type2, msg_type3);
+    let list = context
+        .sql
+        .query_map(
+            "SELECT id
+               FROM msgs
+              WHERE (1=? OR chat_id=?)
+                AND chat_id != ?
+                AND (type=? OR type=? OR type=?)
+                AND hidden=0
+              ORDER BY timestamp, id
+              LIMIT ? OFFSET ?;",
+            (
+                chat_id.is_none(),
+                chat_id.unwrap_or_else(|| ChatId::new(0)),
+                DC_CHAT_ID_TRASH,
+                msg_type,
+                msg_type2,
+                msg_type3,
+                i64::try_from(limit).unwrap_or(i64::MAX),
+                i64::try_from(offset).unwrap_or(i64::MAX),
+            ),
+            |row| row.get::<_, MsgId>(0),
+            |ids| Ok(ids.flatten().collect()),
+        )
+        .await?;
+    Ok(list)
+}
+
+/// Returns the index of `msg_id` in the list returned by [`get_chat_media`].
+///
+/// Fullscreen viewers can use the index with [`get_chat_media_page`]
+/// to load the previous and next media without loading the whole list.
+/// Returns `None` if the message is not part of the list,
+/// e.g. because it has a different type or belongs to another chat.
+pub async fn get_chat_media_index(
+    context: &Context,
+    chat_id: Option<ChatId>,
+    msg_id: MsgId,
+    msg_type: Viewtype,
+    msg_type2: Viewtype,
+    msg_type3: Viewtype,
+) -> Result<Option<usize>> {
+    let (msg_type2, msg_type3) = media_types_or_default(msg_type, msg_type2, msg_type3);
+    let msg = Message::load_from_db(context, msg_id).await?;
+    if msg.hidden
+        || msg.chat_id == DC_CHAT_ID_TRASH
+        || chat_id.map_or(false, |chat_id| chat_id != msg.chat_id)
+        || ![msg_type, msg_type2, msg_type3].contains(&msg.viewtype)
+    {
+        return Ok(None);
+    }
+    let index = context
+        .sql
+        .count(
+            "SELECT COUNT(*)
+               FROM msgs
+              WHERE (1=? OR chat_id=?)
+                AND chat_id != ?
+                AND (type=? OR type=? OR type=?)
+                AND hidden=0
+                AND (timestamp<? OR (timestamp=? AND id<?));",
+            (
+                chat_id.is_none(),
+                chat_id.unwrap_or_else(|| ChatId::new(0)),
+                DC_CHAT_ID_TRASH,
+                msg_type,
+                msg_type2,
+                msg_type3,
+                msg.timestamp_sort,
+                msg.timestamp_sort,
+                msg_id,
+            ),
+        )
+        .await?;
+    Ok(Some(index))
+}
+
+/// Replaces unused `Viewtype::Unknown` media types by `msg_type`.
+fn media_types_or_default(
+    msg_type: Viewtype,
+    msg_type2: Viewtype,
+    msg_type3: Viewtype,
+) -> (Viewtype, Viewtype) {
+    let or_default = |t| if t != Viewtype::Unknown { t } else { msg_type };
+    (or_default(msg_type2), or_default(msg_type3))
+}
+
 /// Indicates the direction over which to iterate.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[repr(i32)]
@@ -3025,6 +3673,37 @@ pub async fn get_chat_contacts(context: &Context, chat_id: ChatId) -> Result<Vec
     Ok(list)
 }
 
+/// Returns the members of a chat together with their addresses,
+/// ordered as by [`get_chat_contacts`].
+async fn get_chat_member_addrs(
+    context: &Context,
+    chat_id: ChatId,
+) -> Result<Vec<(ContactId, String)>> {
+    let self_addr = context.get_primary_self_addr().await.unwrap_or_default();
+    context
+        .sql
+        .query_map(
+            "SELECT cc.contact_id, IFNULL(c.addr, '')
+               FROM chats_contacts cc
+               LEFT JOIN contacts c
+                      ON c.id=cc.contact_id
+              WHERE cc.chat_id=?
+              ORDER BY c.id=1, c.last_seen DESC, c.id DESC;",
+            (chat_id,),
+            |row| {
+                let contact_id: ContactId = row.get(0)?;
+                let addr: String = if contact_id == ContactId::SELF {
+                    self_addr.clone()
+                } else {
+                    row.get(1)?
+                };
+                Ok((contact_id, addr))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+}
+
 /// Creates a group chat with a given `name`.
 pub async fn create_group_chat(
     context: &Context,
@@ -3063,6 +3742,24 @@ pub async fn create_group_chat(
     Ok(chat_id)
 }
 
+/// Creates a read-only channel.
+///
+/// Channels are groups where only the user creating them may post,
+/// e.g. for announcements by bots.
+/// Other members cannot send to the channel,
+/// their messages sent nevertheless and replies by classic email clients
+/// are shown in the 1:1 chat with the sender.
+pub async fn create_channel(context: &Context, chat_name: &str) -> Result<ChatId> {
+    let chat_id = create_group_chat(context, ProtectionStatus::Unprotected, chat_name).await?;
+    let self_addr = context.get_primary_self_addr().await?;
+    let mut chat = Chat::load_from_db(context, chat_id).await?;
+    chat.param.set(Param::ChannelOwner, &self_addr);
+    // Only the owner may change the channel.
+    chat.param.set(Param::GroupAdmins, &self_addr);
+    chat.update_param(context).await?;
+    Ok(chat_id)
+}
+
 /// Finds an unused name for a new broadcast list.
 async fn find_unused_broadcast_list_name(context: &Context) -> Result<String> {
     let base_name = stock_str::broadcast_list(context).await;
@@ -3195,6 +3892,10 @@ pub(crate) async fn add_contact_to_chat_ex(
         ));
         bail!("can not add contact because the account is not part of the group/broadcast");
     }
+    ensure!(
+        chat.is_admin(context, ContactId::SELF).await?,
+        "Only admins can add members to {chat_id}"
+    );
 
     if from_handshake && chat.param.get_int(Param::Unpromoted).unwrap_or_default() == 1 {
         chat.param.remove(Param::Unpromoted);
@@ -3338,15 +4039,29 @@ pub async fn set_muted(context: &Context, chat_id: ChatId, duration: MuteDuratio
         )
         .await
         .context(format!("Failed to set mute duration for {chat_id}"))?;
+    context.invalidate_fresh_msg_count();
     context.emit_event(EventType::ChatModified(chat_id));
     Ok(())
 }
 
 /// Removes contact from the chat.
+///
+/// If [`Config::UndoTimeout`] is set, the removal can be reversed with [`undo::undo`].
 pub async fn remove_contact_from_chat(
     context: &Context,
     chat_id: ChatId,
     contact_id: ContactId,
+) -> Result<()> {
+    remove_contact_from_chat_ex(context, chat_id, contact_id, true).await
+}
+
+/// Removes a contact from a group, queueing the removal in the undo queue
+/// if `undoable` is true and [`Config::UndoTimeout`] is set.
+pub(crate) async fn remove_contact_from_chat_ex(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: ContactId,
+    undoable: bool,
 ) -> Result<()> {
     ensure!(
         !chat_id.is_special(),
@@ -3369,6 +4084,13 @@ pub async fn remove_contact_from_chat(
             context.emit_event(EventType::ErrorSelfNotInGroup(err_msg.clone()));
             bail!("{}", err_msg);
         } else {
+            ensure!(
+                contact_id == ContactId::SELF || chat.is_admin(context, ContactId::SELF).await?,
+                "Only admins can remove members from {chat_id}"
+            );
+            if undoable && undo::defer_remove_member(context, chat_id, contact_id).await? {
+                return Ok(());
+            }
             // We do not return an error if the contact does not exist in the database.
             // This allows to delete dangling references to deleted contacts
             // in case of the database becoming inconsistent due to a bug.
@@ -3452,6 +4174,8 @@ pub async fn set_chat_name(context: &Context, chat_id: ChatId, new_name: &str) -
             context.emit_event(EventType::ErrorSelfNotInGroup(
                 "Cannot set chat name; self not in group".into(),
             ));
+        } else if !chat.is_admin(context, ContactId::SELF).await? {
+            warn!(context, "Only admins can set the name of {chat_id}.");
         } else {
             context
                 .sql
@@ -3487,6 +4211,124 @@ pub async fn set_chat_name(context: &Context, chat_id: ChatId, new_name: &str) -
     Ok(())
 }
 
+/// Returns the `https:` and `mailto:` URIs of a `List-Unsubscribe` header.
+///
+/// The header contains a comma-separated list of URIs in angle brackets,
+/// other URI schemes are ignored.
+fn parse_list_unsubscribe(header: &str) -> Vec<url::Url> {
+    header
+        .split('<')
+        .skip(1)
+        .filter_map(|part| part.split_once('>'))
+        .filter_map(|(uri, _)| url::Url::parse(uri.trim()).ok())
+        .filter(|uri| uri.scheme() == "https" || uri.scheme() == "mailto")
+        .collect()
+}
+
+/// Unsubscribes from a mailing list using its `List-Unsubscribe` header.
+///
+/// If the list supports one-click unsubscription as defined in RFC 8058,
+/// a `POST` request is sent to its HTTPS URI,
+/// otherwise an email is sent to its `mailto:` URI.
+/// On success, an info message is added to the chat.
+pub async fn unsubscribe(context: &Context, chat_id: ChatId) -> Result<()> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    ensure!(
+        chat.typ == Chattype::Mailinglist,
+        "{chat_id} is not a mailing list"
+    );
+    let uris = parse_list_unsubscribe(chat.param.get(Param::ListUnsubscribe).unwrap_or_default());
+    let one_click = chat
+        .param
+        .get_bool(Param::ListUnsubscribeOneClick)
+        .unwrap_or_default();
+
+    if let Some(uri) = uris.iter().find(|uri| one_click && uri.scheme() == "https") {
+        info!(context, "Unsubscribing from {chat_id} via one-click URI.");
+        ensure_online(context).await?;
+        let socks5_config = Socks5Config::from_context(context).await?;
+        let response = get_client(socks5_config)?
+            .post(uri.as_str())
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await?;
+        ensure!(
+            response.status().is_success(),
+            "Unsubscribe request returned {}",
+            response.status()
+        );
+    } else if let Some(uri) = uris.iter().find(|uri| uri.scheme() == "mailto") {
+        info!(context, "Unsubscribing from {chat_id} via email.");
+        send_unsubscribe_email(context, chat_id, uri).await?;
+        context.emit_event(EventType::ChatModified(chat_id));
+        return Ok(());
+    } else {
+        bail!("{chat_id} has no supported List-Unsubscribe URI");
+    }
+
+    add_info_msg(
+        context,
+        chat_id,
+        &stock_str::unsubscribed_from_mailinglist(context).await,
+        create_smeared_timestamp(context),
+    )
+    .await?;
+    context.emit_event(EventType::ChatModified(chat_id));
+    Ok(())
+}
+
+/// Sends an unsubscribe request to a `mailto:` URI.
+///
+/// The `subject` and `body` fields of the URI are used if present.
+///
+/// The request is not added to any chat, neither is a contact created for the address.
+/// It is queued for sending together with the info message added to the mailing list chat,
+/// so that a sending failure is shown at the info message.
+async fn send_unsubscribe_email(context: &Context, chat_id: ChatId, uri: &url::Url) -> Result<()> {
+    let addr = percent_decode_str(uri.path()).decode_utf8()?;
+    let addr = ContactAddress::new(&addr)?;
+    let mut subject = "unsubscribe".to_string();
+    let mut text = "unsubscribe".to_string();
+    for field in uri.query().unwrap_or_default().split('&') {
+        if let Some((key, value)) = field.split_once('=') {
+            let value = percent_decode_str(value).decode_utf8()?.into_owned();
+            match key.to_lowercase().as_str() {
+                "subject" => subject = value,
+                "body" => text = value,
+                _ => {}
+            }
+        }
+    }
+
+    let (rfc724_mid, mime) =
+        mimefactory::render_unrelated_email(context, &addr, &subject, &text).await?;
+    let info_msg_id = add_info_msg(
+        context,
+        chat_id,
+        &stock_str::unsubscribed_from_mailinglist(context).await,
+        create_smeared_timestamp(context),
+    )
+    .await?;
+    context
+        .sql
+        .insert(
+            "INSERT INTO smtp (rfc724_mid, recipients, mime, msg_id)
+             VALUES           (?1,         ?2,         ?3,   ?4)",
+            (rfc724_mid, addr.to_string(), mime, info_msg_id),
+        )
+        .await?;
+    context
+        .scheduler
+        .interrupt_smtp(InterruptInfo::new(false))
+        .await;
+    Ok(())
+}
+
 /// Sets a new profile image for the chat.
 ///
 /// The profile image can only be set when you are a member of the
@@ -3510,6 +4352,10 @@ pub async fn set_chat_profile_image(
         ));
         bail!("Failed to set profile image");
     }
+    ensure!(
+        chat.is_admin(context, ContactId::SELF).await?,
+        "Only admins can set the profile image of {chat_id}"
+    );
     let mut msg = Message::new(Viewtype::Text);
     msg.param
         .set_int(Param::Cmd, SystemMessage::GroupImageChanged as i32);
@@ -3533,6 +4379,81 @@ pub async fn set_chat_profile_image(
     Ok(())
 }
 
+/// Returns the admins among the members of a group.
+///
+/// The list is empty if the group does not use roles or all admins left the group,
+/// then all members may administrate the group.
+pub async fn get_chat_admins(context: &Context, chat_id: ChatId) -> Result<Vec<ContactId>> {
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    let admins = chat.get_admin_members(context).await?;
+    Ok(admins
+        .into_iter()
+        .map(|(contact_id, _)| contact_id)
+        .collect())
+}
+
+/// Grants or revokes admin rights of a group member.
+///
+/// Only admins may add and remove members and change name and avatar of the group.
+/// Groups do not use roles until the first admin is set,
+/// then the user setting it becomes an admin as well.
+/// If all admins leave the group, all members may administrate it again.
+/// The admins are sent with every message to the group,
+/// Delta Chat clients ignore changes done by other members.
+pub async fn set_chat_admin(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: ContactId,
+    admin: bool,
+) -> Result<()> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let mut chat = Chat::load_from_db(context, chat_id).await?;
+    ensure!(chat.typ == Chattype::Group, "{chat_id} is not a group");
+    if !chat.is_self_in_chat(context).await? {
+        context.emit_event(EventType::ErrorSelfNotInGroup(
+            "Cannot set group admin; self not in group.".into(),
+        ));
+        bail!("Failed to set group admin");
+    }
+    ensure!(
+        chat.is_admin(context, ContactId::SELF).await?,
+        "Only admins can set the admins of {chat_id}"
+    );
+    ensure!(
+        is_contact_in_chat(context, chat_id, contact_id).await?,
+        "{contact_id} is not a member of {chat_id}"
+    );
+
+    let mut admins: Vec<String> = chat
+        .get_admin_members(context)
+        .await?
+        .into_iter()
+        .map(|(_, addr)| addr)
+        .collect();
+    if admins.is_empty() {
+        admins.push(context.get_primary_self_addr().await?);
+    }
+    let contact = Contact::get_by_id(context, contact_id).await?;
+    admins.retain(|admin| !addr_cmp(admin, contact.get_addr()));
+    if admin {
+        admins.push(contact.get_addr().to_string());
+    }
+    ensure!(!admins.is_empty(), "{chat_id} needs at least one admin");
+    chat.param.set(Param::GroupAdmins, admins.join(" "));
+    chat.param.set_i64(Param::GroupAdminsTimestamp, time());
+    chat.update_param(context).await?;
+
+    if chat.is_promoted() {
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = stock_str::msg_grp_admins_changed(context, ContactId::SELF).await;
+        msg.param.set_cmd(SystemMessage::GroupAdminsChanged);
+        msg.id = send_msg(context, chat_id, &mut msg).await?;
+        context.emit_msgs_changed(chat_id, msg.id);
+    }
+    context.emit_event(EventType::ChatModified(chat_id));
+    Ok(())
+}
+
 /// Forwards multiple messages to a chat.
 pub async fn forward_msgs(context: &Context, msg_ids: &[MsgId], chat_id: ChatId) -> Result<()> {
     ensure!(!msg_ids.is_empty(), "empty msgs_ids: nothing to forward");
@@ -3963,17 +4884,246 @@ pub(crate) async fn update_msg_text_and_timestamp(
     Ok(())
 }
 
+/// Format of a chat transcript, see [`export_transcript`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    /// Plain text.
+    Text,
+
+    /// Standalone HTML document with embedded images and avatars.
+    Html,
+}
+
+/// CSS of HTML transcripts.
+const TRANSCRIPT_STYLE: &str = "body{font-family:sans-serif;max-width:50em;margin:auto}\
+    .msg{display:flex;margin:1em 0}\
+    .avatar{flex:none;width:2.5em;height:2.5em;margin-right:.5em;border-radius:50%;\
+    background-size:cover}\
+    .meta{color:#666;font-size:.9em}\
+    .info{text-align:center;color:#666;margin:1em 0}\
+    img{max-width:100%}";
+
+/// Writes a readable transcript of a chat to `writer`, e.g. for legal or archival purposes.
+///
+/// If `range` is given, only messages with a timestamp in the range are included.
+/// Messages are loaded and written one by one,
+/// so huge chats are exported without loading them into memory at once.
+pub async fn export_transcript<W: AsyncWrite + Unpin>(
+    context: &Context,
+    chat_id: ChatId,
+    format: TranscriptFormat,
+    range: Option<RangeInclusive<i64>>,
+    writer: &mut W,
+) -> Result<()> {
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    let header = match format {
+        TranscriptFormat::Text => format!("{}\n\n", chat.name),
+        TranscriptFormat::Html => format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{name}</title>\n<style>{TRANSCRIPT_STYLE}</style>\n</head>\n<body>\n\
+             <h1>{name}</h1>\n",
+            name = escaper::encode_minimal(&chat.name)
+        ),
+    };
+    writer.write_all(header.as_bytes()).await?;
+
+    let mut senders: HashMap<ContactId, Contact> = HashMap::new();
+    for item in get_chat_msgs(context, chat_id).await? {
+        let ChatItem::Message { msg_id } = item else {
+            continue;
+        };
+        let msg = Message::load_from_db(context, msg_id).await?;
+        let timestamp = msg.get_timestamp();
+        if range
+            .as_ref()
+            .map_or(false, |range| !range.contains(&timestamp))
+        {
+            continue;
+        }
+
+        let time = timestamp_to_str(timestamp);
+        if msg.is_info() {
+            let entry = match format {
+                TranscriptFormat::Text => format!("[{time}] {}\n", msg.get_text()),
+                TranscriptFormat::Html => format!(
+                    "<div class=\"info\">{time} {}</div>\n",
+                    escaper::encode_minimal(&msg.get_text())
+                ),
+            };
+            writer.write_all(entry.as_bytes()).await?;
+            continue;
+        }
+
+        let is_new_sender = !senders.contains_key(&msg.from_id);
+        if is_new_sender {
+            let contact = Contact::get_by_id(context, msg.from_id).await?;
+            senders.insert(msg.from_id, contact);
+        }
+        let contact = &senders[&msg.from_id];
+        let sender = msg
+            .get_override_sender_name()
+            .unwrap_or_else(|| contact.get_display_name().to_string());
+        let file = msg.get_filename();
+
+        match format {
+            TranscriptFormat::Text => {
+                let mut entry = format!("[{time}] {sender}: {}\n", msg.get_text());
+                if let Some(file) = file {
+                    entry += &format!("  [{:?}: {file}]\n", msg.get_viewtype());
+                }
+                writer.write_all(entry.as_bytes()).await?;
+            }
+            TranscriptFormat::Html => {
+                if is_new_sender {
+                    // Each avatar is embedded only once and referenced by the following messages.
+                    let style = format!(
+                        "<style>.avatar{}{{background-image:url(\"",
+                        msg.from_id.to_u32()
+                    );
+                    writer.write_all(style.as_bytes()).await?;
+                    write_transcript_avatar(context, contact, writer).await?;
+                    writer.write_all(b"\")}</style>\n").await?;
+                }
+                let meta = format!(
+                    "<div class=\"msg\"><div class=\"avatar avatar{}\"></div><div>\n\
+                     <div class=\"meta\"><b>{}</b> {time}</div>\n",
+                    msg.from_id.to_u32(),
+                    escaper::encode_minimal(&sender)
+                );
+                writer.write_all(meta.as_bytes()).await?;
+                if let Some(file) = file {
+                    if let Some((mime, image)) = open_transcript_image(context, &msg).await {
+                        writer.write_all(b"<img src=\"").await?;
+                        write_data_url(writer, mime, image).await?;
+                        writer.write_all(b"\"><br>\n").await?;
+                    } else {
+                        let name = format!("<i>{}</i><br>\n", escaper::encode_minimal(&file));
+                        writer.write_all(name.as_bytes()).await?;
+                    }
+                }
+                let text = format!(
+                    "{}\n</div></div>\n",
+                    escaper::encode_minimal(&msg.get_text()).replace('\n', "<br>\n")
+                );
+                writer.write_all(text.as_bytes()).await?;
+            }
+        }
+    }
+
+    if format == TranscriptFormat::Html {
+        writer.write_all(b"</body>\n</html>\n").await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Number of bytes encoded at once when embedding files into HTML transcripts,
+/// a multiple of 3 so that the chunks are encoded without padding.
+const TRANSCRIPT_CHUNK_SIZE: u64 = 3 * 16 * 1024;
+
+/// Writes the profile image of a contact as a data URL for HTML transcripts,
+/// the fallback avatar if there is none.
+async fn write_transcript_avatar<W: AsyncWrite + Unpin>(
+    context: &Context,
+    contact: &Contact,
+    writer: &mut W,
+) -> Result<()> {
+    if let Some(path) = contact.get_profile_image(context).await? {
+        if let Some(mime) = transcript_image_mime(&path) {
+            if let Ok(file) = tokio::fs::File::open(&path).await {
+                return write_data_url(writer, mime, file).await;
+            }
+        }
+    }
+    let svg = base64::engine::general_purpose::STANDARD.encode(contact.get_fallback_avatar_svg()?);
+    let url = format!("data:image/svg+xml;base64,{svg}");
+    writer.write_all(url.as_bytes()).await?;
+    Ok(())
+}
+
+/// Returns the MIME type of an image that can be embedded into HTML transcripts.
+///
+/// The type is derived from the file suffix instead of using the one given by the sender.
+/// Returns `None` for other files.
+fn transcript_image_mime(path: &Path) -> Option<&'static str> {
+    let (_, mime) = message::guess_msgtype_from_suffix(path)?;
+    ["image/png", "image/jpeg", "image/gif", "image/webp"]
+        .into_iter()
+        .find(|image_mime| *image_mime == mime)
+}
+
+/// Opens the image attached to a message for embedding it into HTML transcripts,
+/// returns `None` if the message has no image or the file is not available.
+async fn open_transcript_image(
+    context: &Context,
+    msg: &Message,
+) -> Option<(&'static str, tokio::fs::File)> {
+    if !matches!(
+        msg.get_viewtype(),
+        Viewtype::Image | Viewtype::Gif | Viewtype::Sticker
+    ) {
+        return None;
+    }
+    let path = msg.get_file(context)?;
+    let mime = transcript_image_mime(&path)?;
+    let file = tokio::fs::File::open(&path).await.ok()?;
+    Some((mime, file))
+}
+
+/// Writes a file as base64 data URL,
+/// encoding it chunk by chunk so that it is not loaded into memory at once.
+async fn write_data_url<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    mime: &str,
+    mut file: tokio::fs::File,
+) -> Result<()> {
+    writer
+        .write_all(format!("data:{mime};base64,").as_bytes())
+        .await?;
+    let mut chunk = Vec::new();
+    loop {
+        chunk.clear();
+        (&mut file)
+            .take(TRANSCRIPT_CHUNK_SIZE)
+            .read_to_end(&mut chunk)
+            .await?;
+        if chunk.is_empty() {
+            break;
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&chunk);
+        writer.write_all(encoded.as_bytes()).await?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::chatlist::{get_archived_cnt, Chatlist};
     use crate::constants::{DC_GCL_ARCHIVED_ONLY, DC_GCL_NO_SPECIALS};
     use crate::contact::{Contact, ContactAddress};
+    use crate::key::load_self_public_key;
     use crate::message::delete_msgs;
     use crate::receive_imf::receive_imf;
     use crate::test_utils::{TestContext, TestContextManager};
     use tokio::fs;
 
+    #[test]
+    fn test_chat_id_values() {
+        // The values of special chat IDs are part of the FFI and must not change.
+        assert_eq!(ChatId::TRASH.to_u32(), 3);
+        assert_eq!(ChatId::ARCHIVED_LINK.to_u32(), 6);
+        assert_eq!(ChatId::ALLDONE_HINT.to_u32(), 7);
+        assert_eq!(ChatId::LAST_SPECIAL.to_u32(), 9);
+        assert!(ChatId::TRASH.is_trash() && ChatId::TRASH.is_special());
+        assert!(!ChatId::new(10).is_special());
+        assert_eq!(ChatId::from(10), ChatId::new(10));
+        assert_eq!(u32::from(ChatId::ARCHIVED_LINK), 6);
+        assert_eq!(MsgId::DAYMARKER.to_u32(), 9);
+        assert!(MsgId::LAST_SPECIAL.is_special());
+        assert!(!MsgId::new(10).is_special());
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_chat_info() {
         let t = TestContext::new().await;
@@ -4163,105 +5313,383 @@ async fn test_change_quotes_on_reused_message_object() -> Result<()> {
         assert_eq!(test.quoted_text(), Some("quote2".to_string()));
         assert_eq!(test.quoted_message(&t).await?.unwrap().id, quote2.id);
 
-        // remove quote on same message object
-        draft.set_quote(&t, None).await?;
-        chat_id.set_draft(&t, Some(&mut draft)).await?;
+        // remove quote on same message object
+        draft.set_quote(&t, None).await?;
+        chat_id.set_draft(&t, Some(&mut draft)).await?;
+
+        let test = Message::load_from_db(&t, draft.id).await?;
+        assert_eq!(test.text, "another draft text".to_string());
+        assert!(test.quoted_text().is_none());
+        assert!(test.quoted_message(&t).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_add_contact_to_chat_ex_add_self() {
+        // Adding self to a contact should succeed, even though it's pointless.
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "foo")
+            .await
+            .unwrap();
+        let added = add_contact_to_chat_ex(&t, chat_id, ContactId::SELF, false)
+            .await
+            .unwrap();
+        assert_eq!(added, false);
+    }
+
+    /// Test adding and removing members in a group chat.
+    ///
+    /// Make sure messages sent outside contain authname
+    /// and displayed messages contain locally set name.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_member_add_remove() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        // Disable encryption so we can inspect raw message contents.
+        alice.set_config(Config::E2eeEnabled, Some("0")).await?;
+        bob.set_config(Config::E2eeEnabled, Some("0")).await?;
+
+        // Create contact for Bob on the Alice side with name "robert".
+        let alice_bob_contact_id = Contact::create(&alice, "robert", "bob@example.net").await?;
+
+        // Set Bob authname to "Bob" and send it to Alice.
+        bob.set_config(Config::Displayname, Some("Bob")).await?;
+        tcm.send_recv(&bob, &alice, "Hello!").await;
+
+        // Check that Alice has Bob's name set to "robert" and authname set to "Bob".
+        {
+            let alice_bob_contact = Contact::get_by_id(&alice, alice_bob_contact_id).await?;
+            assert_eq!(alice_bob_contact.get_name(), "robert");
+
+            // This is the name that will be sent outside.
+            assert_eq!(alice_bob_contact.get_authname(), "Bob");
+
+            assert_eq!(alice_bob_contact.get_display_name(), "robert");
+        }
+
+        // Create and promote a group.
+        let alice_chat_id =
+            create_group_chat(&alice, ProtectionStatus::Unprotected, "Group chat").await?;
+        let alice_fiona_contact_id = Contact::create(&alice, "Fiona", "fiona@example.net").await?;
+        add_contact_to_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?;
+        let sent = alice
+            .send_text(alice_chat_id, "Hi! I created a group.")
+            .await;
+        assert!(sent.payload.contains("Hi! I created a group."));
+
+        // Alice adds Bob to the chat.
+        add_contact_to_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
+        let sent = alice.pop_sent_msg().await;
+        assert!(sent
+            .payload
+            .contains("I added member Bob (bob@example.net)."));
+        // Locally set name "robert" should not leak.
+        assert!(!sent.payload.contains("robert"));
+        assert_eq!(
+            sent.load_from_db().await.get_text(),
+            "You added member robert (bob@example.net)."
+        );
+
+        // Alice removes Bob from the chat.
+        remove_contact_from_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
+        let sent = alice.pop_sent_msg().await;
+        assert!(sent
+            .payload
+            .contains("I removed member Bob (bob@example.net)."));
+        assert!(!sent.payload.contains("robert"));
+        assert_eq!(
+            sent.load_from_db().await.get_text(),
+            "You removed member robert (bob@example.net)."
+        );
+
+        // Alice leaves the chat.
+        remove_contact_from_chat(&alice, alice_chat_id, ContactId::SELF).await?;
+        let sent = alice.pop_sent_msg().await;
+        assert!(sent.payload.contains("I left the group."));
+        assert_eq!(sent.load_from_db().await.get_text(), "You left the group.");
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_member_add_remove_info_contact_id() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let alice_bob_contact_id = Contact::create(&alice, "Bob", "bob@example.net").await?;
+        let alice_fiona_contact_id = Contact::create(&alice, "Fiona", "fiona@example.net").await?;
+        let alice_chat_id =
+            create_group_chat(&alice, ProtectionStatus::Unprotected, "Group chat").await?;
+        add_contact_to_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?;
+        alice
+            .send_text(alice_chat_id, "Hi! I created a group.")
+            .await;
+
+        add_contact_to_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
+        let sent = alice.pop_sent_msg().await;
+        let alice_msg = sent.load_from_db().await;
+        assert_eq!(alice_msg.get_info_type(), SystemMessage::MemberAddedToGroup);
+        assert_eq!(
+            alice_msg.get_info_contact_id(&alice).await?,
+            Some(alice_bob_contact_id)
+        );
+        let bob_msg = bob.recv_msg(&sent).await;
+        assert_eq!(bob_msg.get_info_type(), SystemMessage::MemberAddedToGroup);
+        assert_eq!(
+            bob_msg.get_info_contact_id(&bob).await?,
+            Some(ContactId::SELF)
+        );
+        let bob_alice_contact_id = bob.add_or_lookup_contact(&alice).await.id;
+        assert_eq!(bob_msg.get_info_actor_id(), Some(bob_alice_contact_id));
+        assert_eq!(alice_msg.get_info_actor_id(), Some(ContactId::SELF));
+
+        remove_contact_from_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?;
+        let bob_msg = bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(
+            bob_msg.get_info_type(),
+            SystemMessage::MemberRemovedFromGroup
+        );
+        let bob_fiona_contact_id =
+            Contact::lookup_id_by_addr(&bob, "fiona@example.net", Origin::Unknown).await?;
+        assert!(bob_fiona_contact_id.is_some());
+        assert_eq!(
+            bob_msg.get_info_contact_id(&bob).await?,
+            bob_fiona_contact_id
+        );
+        assert_eq!(bob_msg.get_info_actor_id(), Some(bob_alice_contact_id));
+
+        // Name and image changes are attributed to the member who made them.
+        set_chat_name(&alice, alice_chat_id, "Renamed").await?;
+        let bob_msg = bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(bob_msg.get_info_type(), SystemMessage::GroupNameChanged);
+        assert_eq!(bob_msg.get_info_actor_id(), Some(bob_alice_contact_id));
+        assert_eq!(bob_msg.get_info_contact_id(&bob).await?, None);
+
+        let file = alice.dir.path().join("avatar.png");
+        tokio::fs::write(&file, include_bytes!("../test-data/image/avatar64x64.png")).await?;
+        set_chat_profile_image(&alice, alice_chat_id, file.to_str().unwrap()).await?;
+        let bob_msg = bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(bob_msg.get_info_type(), SystemMessage::GroupImageChanged);
+        assert_eq!(bob_msg.get_info_actor_id(), Some(bob_alice_contact_id));
+
+        // Other messages have no affected contact.
+        let bob_msg = bob
+            .recv_msg(&alice.send_text(alice_chat_id, "Bye Fiona!").await)
+            .await;
+        assert_eq!(bob_msg.get_info_contact_id(&bob).await?, None);
+        assert_eq!(bob_msg.get_info_actor_id(), None);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_group_admins() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let alice_bob_contact_id = Contact::create(&alice, "Bob", "bob@example.net").await?;
+        let alice_fiona_contact_id = Contact::create(&alice, "Fiona", "fiona@example.net").await?;
+        let alice_chat_id =
+            create_group_chat(&alice, ProtectionStatus::Unprotected, "Group chat").await?;
+        add_contact_to_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
+        add_contact_to_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?;
+        assert!(get_chat_admins(&alice, alice_chat_id).await?.is_empty());
+        let bob_chat_id = bob
+            .recv_msg(&alice.send_text(alice_chat_id, "Hi!").await)
+            .await
+            .chat_id;
+        bob_chat_id.accept(&bob).await?;
+        let bob_alice_contact_id = bob.add_or_lookup_contact(&alice).await.id;
+        let bob_fiona_contact_id =
+            Contact::lookup_id_by_addr(&bob, "fiona@example.net", Origin::Unknown)
+                .await?
+                .unwrap();
+
+        // Alice makes herself the only admin.
+        set_chat_admin(&alice, alice_chat_id, ContactId::SELF, true).await?;
+        assert_eq!(
+            get_chat_admins(&alice, alice_chat_id).await?,
+            vec![ContactId::SELF]
+        );
+        let bob_msg = bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(bob_msg.get_info_type(), SystemMessage::GroupAdminsChanged);
+        assert_eq!(
+            get_chat_admins(&bob, bob_chat_id).await?,
+            vec![bob_alice_contact_id]
+        );
+
+        // Bob is no admin and cannot change the group.
+        assert!(set_chat_name(&bob, bob_chat_id, "Bob's group")
+            .await
+            .is_err());
+        assert!(
+            remove_contact_from_chat(&bob, bob_chat_id, bob_fiona_contact_id)
+                .await
+                .is_err()
+        );
+        assert!(set_chat_admin(&bob, bob_chat_id, ContactId::SELF, true)
+            .await
+            .is_err());
 
-        let test = Message::load_from_db(&t, draft.id).await?;
-        assert_eq!(test.text, "another draft text".to_string());
-        assert!(test.quoted_text().is_none());
-        assert!(test.quoted_message(&t).await?.is_none());
+        // Changes by non-admins are ignored by Alice.
+        let mut bob_chat = Chat::load_from_db(&bob, bob_chat_id).await?;
+        bob_chat.param.remove(Param::GroupAdmins);
+        bob_chat.update_param(&bob).await?;
+        set_chat_name(&bob, bob_chat_id, "Bob's group").await?;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
+        assert_eq!(
+            Chat::load_from_db(&alice, alice_chat_id).await?.get_name(),
+            "Group chat"
+        );
+        remove_contact_from_chat(&bob, bob_chat_id, bob_fiona_contact_id).await?;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
+        assert!(is_contact_in_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?);
 
-        Ok(())
-    }
+        // Alice makes Bob an admin, then Bob's changes are applied.
+        set_chat_admin(&alice, alice_chat_id, alice_bob_contact_id, true).await?;
+        bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(get_chat_admins(&bob, bob_chat_id).await?.len(), 2);
+        set_chat_name(&bob, bob_chat_id, "Bob's group").await?;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
+        assert_eq!(
+            Chat::load_from_db(&alice, alice_chat_id).await?.get_name(),
+            "Bob's group"
+        );
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_add_contact_to_chat_ex_add_self() {
-        // Adding self to a contact should succeed, even though it's pointless.
-        let t = TestContext::new_alice().await;
-        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "foo")
-            .await
-            .unwrap();
-        let added = add_contact_to_chat_ex(&t, chat_id, ContactId::SELF, false)
+        // The last admin cannot be revoked.
+        set_chat_admin(&alice, alice_chat_id, ContactId::SELF, false).await?;
+        assert_eq!(
+            get_chat_admins(&alice, alice_chat_id).await?,
+            vec![alice_bob_contact_id]
+        );
+        bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(
+            get_chat_admins(&bob, bob_chat_id).await?,
+            vec![ContactId::SELF]
+        );
+        assert!(set_chat_admin(&bob, bob_chat_id, ContactId::SELF, false)
             .await
-            .unwrap();
-        assert_eq!(added, false);
+            .is_err());
+
+        Ok(())
     }
 
-    /// Test adding and removing members in a group chat.
-    ///
-    /// Make sure messages sent outside contain authname
-    /// and displayed messages contain locally set name.
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_member_add_remove() -> Result<()> {
+    async fn test_channel() -> Result<()> {
         let mut tcm = TestContextManager::new();
-
         let alice = tcm.alice().await;
         let bob = tcm.bob().await;
 
-        // Disable encryption so we can inspect raw message contents.
-        alice.set_config(Config::E2eeEnabled, Some("0")).await?;
-        bob.set_config(Config::E2eeEnabled, Some("0")).await?;
-
-        // Create contact for Bob on the Alice side with name "robert".
-        let alice_bob_contact_id = Contact::create(&alice, "robert", "bob@example.net").await?;
+        let alice_bob_contact_id = Contact::create(&alice, "Bob", "bob@example.net").await?;
+        let alice_chat_id = create_channel(&alice, "Announcements").await?;
+        add_contact_to_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
+        let alice_chat = Chat::load_from_db(&alice, alice_chat_id).await?;
+        assert!(alice_chat.is_channel());
+        assert!(alice_chat.can_send(&alice).await?);
 
-        // Set Bob authname to "Bob" and send it to Alice.
-        bob.set_config(Config::Displayname, Some("Bob")).await?;
-        tcm.send_recv(&bob, &alice, "Hello!").await;
+        let bob_msg = bob
+            .recv_msg(&alice.send_text(alice_chat_id, "Welcome!").await)
+            .await;
+        let bob_chat_id = bob_msg.chat_id;
+        bob_chat_id.accept(&bob).await?;
+        let bob_chat = Chat::load_from_db(&bob, bob_chat_id).await?;
+        assert!(bob_chat.is_channel());
+        assert_eq!(bob_chat.get_channel_owner(), Some("alice@example.org"));
+        assert_eq!(
+            bob_chat.why_cant_send(&bob).await?,
+            Some(CantSendReason::ReadOnlyChannel)
+        );
+        assert!(send_text_msg(&bob, bob_chat_id, "Hi!".to_string())
+            .await
+            .is_err());
+
+        // Messages from others are assigned to the 1:1 chat with the sender.
+        let mut bob_chat = bob_chat;
+        bob_chat.param.remove(Param::ChannelOwner);
+        bob_chat.update_param(&bob).await?;
+        let alice_msg = alice
+            .recv_msg(&bob.send_text(bob_chat_id, "Hi!").await)
+            .await;
+        assert_ne!(alice_msg.chat_id, alice_chat_id);
+        assert_eq!(alice_msg.chat_id, alice.get_chat(&bob).await.id);
 
-        // Check that Alice has Bob's name set to "robert" and authname set to "Bob".
-        {
-            let alice_bob_contact = Contact::get_by_id(&alice, alice_bob_contact_id).await?;
-            assert_eq!(alice_bob_contact.get_name(), "robert");
+        // Everybody may leave.
+        bob_chat.param.set(Param::ChannelOwner, "alice@example.org");
+        bob_chat.update_param(&bob).await?;
+        remove_contact_from_chat(&bob, bob_chat_id, ContactId::SELF).await?;
+        let alice_msg = alice.recv_msg(&bob.pop_sent_msg().await).await;
+        assert_eq!(alice_msg.chat_id, alice_chat_id);
+        assert!(!is_contact_in_chat(&alice, alice_chat_id, alice_bob_contact_id).await?);
 
-            // This is the name that will be sent outside.
-            assert_eq!(alice_bob_contact.get_authname(), "Bob");
+        Ok(())
+    }
 
-            assert_eq!(alice_bob_contact.get_display_name(), "robert");
-        }
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_group_admins_degradation() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
 
-        // Create and promote a group.
+        let alice_bob_contact_id = Contact::create(&alice, "Bob", "bob@example.net").await?;
+        let alice_fiona_contact_id = Contact::create(&alice, "Fiona", "fiona@example.net").await?;
         let alice_chat_id =
             create_group_chat(&alice, ProtectionStatus::Unprotected, "Group chat").await?;
-        let alice_fiona_contact_id = Contact::create(&alice, "Fiona", "fiona@example.net").await?;
+        add_contact_to_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
         add_contact_to_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?;
-        let sent = alice
-            .send_text(alice_chat_id, "Hi! I created a group.")
-            .await;
-        assert!(sent.payload.contains("Hi! I created a group."));
+        let sent = alice.send_text(alice_chat_id, "Hi!").await;
+        let bob_chat_id = bob.recv_msg(&sent).await.chat_id;
+        bob_chat_id.accept(&bob).await?;
+        set_chat_admin(&alice, alice_chat_id, ContactId::SELF, true).await?;
+        bob.recv_msg(&alice.pop_sent_msg().await).await;
 
-        // Alice adds Bob to the chat.
-        add_contact_to_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
-        let sent = alice.pop_sent_msg().await;
-        assert!(sent
-            .payload
-            .contains("I added member Bob (bob@example.net)."));
-        // Locally set name "robert" should not leak.
-        assert!(!sent.payload.contains("robert"));
+        // Bob replies with a classic MUA, adding Claire and leaving out Fiona.
+        // Claire receives the conversation anyway, so she becomes a member,
+        // but Fiona is not removed.
+        let rfc724_mid = sent.load_from_db().await.rfc724_mid;
+        receive_imf(
+            &alice,
+            format!(
+                "From: bob@example.net\n\
+                 To: alice@example.org, claire@example.org\n\
+                 Subject: Re: Group chat\n\
+                 Message-ID: <classic-reply@example.net>\n\
+                 In-Reply-To: <{rfc724_mid}>\n\
+                 Date: Sun, 22 Mar 2099 22:37:57 +0000\n\
+                 \n\
+                 Hi from my classic MUA!\n"
+            )
+            .as_bytes(),
+            false,
+        )
+        .await?;
+        assert_eq!(alice.get_last_msg().await.chat_id, alice_chat_id);
+        let alice_claire_contact_id =
+            Contact::lookup_id_by_addr(&alice, "claire@example.org", Origin::Unknown)
+                .await?
+                .unwrap();
+        assert!(is_contact_in_chat(&alice, alice_chat_id, alice_claire_contact_id).await?);
+        assert!(is_contact_in_chat(&alice, alice_chat_id, alice_fiona_contact_id).await?);
         assert_eq!(
-            sent.load_from_db().await.get_text(),
-            "You added member robert (bob@example.net)."
+            get_chat_admins(&alice, alice_chat_id).await?,
+            vec![ContactId::SELF]
         );
 
-        // Alice removes Bob from the chat.
-        remove_contact_from_chat(&alice, alice_chat_id, alice_bob_contact_id).await?;
-        let sent = alice.pop_sent_msg().await;
-        assert!(sent
-            .payload
-            .contains("I removed member Bob (bob@example.net)."));
-        assert!(!sent.payload.contains("robert"));
+        // If the only admin leaves, all members may administrate the group.
+        remove_contact_from_chat(&alice, alice_chat_id, ContactId::SELF).await?;
+        bob.recv_msg(&alice.pop_sent_msg().await).await;
+        assert!(get_chat_admins(&bob, bob_chat_id).await?.is_empty());
+        set_chat_name(&bob, bob_chat_id, "Bob's group").await?;
         assert_eq!(
-            sent.load_from_db().await.get_text(),
-            "You removed member robert (bob@example.net)."
+            Chat::load_from_db(&bob, bob_chat_id).await?.get_name(),
+            "Bob's group"
         );
 
-        // Alice leaves the chat.
-        remove_contact_from_chat(&alice, alice_chat_id, ContactId::SELF).await?;
-        let sent = alice.pop_sent_msg().await;
-        assert!(sent.payload.contains("I left the group."));
-        assert_eq!(sent.load_from_db().await.get_text(), "You left the group.");
-
         Ok(())
     }
 
@@ -5212,6 +6640,16 @@ async fn test_shall_attach_selfavatar() -> Result<()> {
 
         t.set_config(Config::Selfavatar, None).await?; // setting to None also forces re-sending
         assert!(shall_attach_selfavatar(&t, chat_id).await?);
+
+        let file = t.dir.path().join("avatar.png");
+        tokio::fs::write(&file, include_bytes!("../test-data/image/avatar64x64.png")).await?;
+        t.set_config(Config::Selfavatar, Some(file.to_str().unwrap()))
+            .await?;
+        chat_id.set_selfavatar_timestamp(&t, time()).await?;
+        // Setting an unchanged avatar does not force re-sending.
+        t.set_config(Config::Selfavatar, Some(file.to_str().unwrap()))
+            .await?;
+        assert!(!shall_attach_selfavatar(&t, chat_id).await?);
         Ok(())
     }
 
@@ -6242,6 +7680,36 @@ async fn test_chat_get_encryption_info() -> Result<()> {
             bob@example.net"
         );
 
+        let chat = Chat::load_from_db(&alice, chat_id).await?;
+        let info = chat.get_encryption_info(&alice).await?;
+        assert_eq!(info.encryption, ChatEncryption::Partially);
+        assert_eq!(info.members.len(), 2);
+        let bob_info = info
+            .members
+            .iter()
+            .find(|member| member.contact_id == contact_bob)
+            .unwrap();
+        assert_eq!(
+            bob_info.fingerprint,
+            Some(load_self_public_key(&bob).await?.fingerprint().hex())
+        );
+        assert_eq!(bob_info.prefer_encrypt, EncryptPreference::Mutual);
+        assert!(!bob_info.verified);
+        let fiona_info = info
+            .members
+            .iter()
+            .find(|member| member.contact_id == contact_fiona)
+            .unwrap();
+        assert_eq!(fiona_info.fingerprint, None);
+
+        let bob_chat = Chat::load_from_db(
+            &alice,
+            ChatId::create_for_contact(&alice, contact_bob).await?,
+        )
+        .await?;
+        let info = bob_chat.get_encryption_info(&alice).await?;
+        assert_eq!(info.encryption, ChatEncryption::Yes);
+
         bob.set_config(Config::E2eeEnabled, Some("0")).await?;
         send_text_msg(&bob, direct_chat.id, "Hello!".to_string()).await?;
         alice.recv_msg(&bob.pop_sent_msg().await).await;
@@ -6424,6 +7892,90 @@ async fn send_media(
             3
         );
 
+        // Paging returns slices of the full list.
+        let (image, sticker, webxdc) = (Viewtype::Image, Viewtype::Sticker, Viewtype::Webxdc);
+        let all = get_chat_media(&t, None, image, sticker, webxdc).await?;
+        assert_eq!(
+            get_chat_media_count(&t, None, image, sticker, webxdc).await?,
+            3
+        );
+        assert_eq!(
+            get_chat_media_page(&t, None, image, sticker, webxdc, 1, 5).await?,
+            all[1..]
+        );
+        for (i, msg_id) in all.iter().enumerate() {
+            assert_eq!(
+                get_chat_media_index(&t, None, *msg_id, image, sticker, webxdc).await?,
+                Some(i)
+            );
+        }
+        assert_eq!(
+            get_chat_media_index(
+                &t,
+                Some(chat_id2),
+                all[0],
+                image,
+                sticker,
+                Viewtype::Unknown
+            )
+            .await?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_send_file_reference() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let chat = alice.create_chat(&bob).await;
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("large.bin");
+        fs::write(&file, "not so large").await?;
+
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file_reference(file.to_str().unwrap(), None);
+        let msg_id = send_msg(&alice, chat.id, &mut msg).await?;
+        // The attachment is read when sending and not stored in the queue.
+        let mime: String = alice
+            .sql
+            .query_get_value("SELECT mime FROM smtp WHERE msg_id=?", (msg_id,))
+            .await?
+            .unwrap();
+        assert!(mime.is_empty());
+        let sent = alice.pop_sent_msg().await;
+        assert_eq!(
+            sent.load_from_db().await.get_file(&alice),
+            Some(file.clone())
+        );
+        assert!(!alice.get_blobdir().join("large.bin").exists());
+
+        let msg = bob.recv_msg(&sent).await;
+        assert_eq!(msg.get_filename(), Some("large.bin".to_string()));
+        let path = msg.get_file(&bob).unwrap();
+        assert_eq!(fs::read_to_string(path).await?, "not so large");
+
+        // Sending fails if the file vanishes before the message is sent.
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file_reference(file.to_str().unwrap(), None);
+        let msg_id = send_msg(&alice, chat.id, &mut msg).await?;
+        fs::remove_file(&file).await?;
+        assert!(crate::smtp::render_deferred_msg(&alice, msg_id)
+            .await
+            .is_err());
+        let msg = Message::load_from_db(&alice, msg_id).await?;
+        assert_eq!(msg.state, MessageState::OutFailed);
+
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file_reference(file.to_str().unwrap(), None);
+        assert!(send_msg(&alice, chat.id, &mut msg).await.is_err());
+
+        // Images are recoded, so they cannot be referenced.
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file_reference(file.to_str().unwrap(), None);
+        assert!(send_msg(&alice, chat.id, &mut msg).await.is_err());
         Ok(())
     }
 
@@ -6452,4 +8004,225 @@ async fn test_blob_renaming() -> Result<()> {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_parse_list_unsubscribe() {
+        let uris = parse_list_unsubscribe(
+            "<mailto:list-request@example.org?subject=unsubscribe>, \
+             <ftp://example.org/unsubscribe>,\t<https://example.org/unsubscribe?id=123>",
+        );
+        assert_eq!(
+            uris.iter().map(|uri| uri.as_str()).collect::<Vec<_>>(),
+            vec![
+                "mailto:list-request@example.org?subject=unsubscribe",
+                "https://example.org/unsubscribe?id=123"
+            ]
+        );
+        assert!(parse_list_unsubscribe("").is_empty());
+        assert!(parse_list_unsubscribe("mailto:no-brackets@example.org").is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_unsubscribe() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        receive_imf(
+            &t,
+            b"From: Bob <bob@example.net>\n\
+              To: list@example.net\n\
+              Subject: [list] Hi\n\
+              Message-ID: <unsubscribe1@example.net>\n\
+              List-ID: <list.example.net>\n\
+              List-Unsubscribe: <https://example.net/unsubscribe>, \n\
+              \t<mailto:list-request@example.net?subject=leave%20list>\n\
+              Precedence: list\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              hello\n",
+            false,
+        )
+        .await?;
+        let chat_id = t.get_last_msg().await.chat_id;
+        let chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.typ, Chattype::Mailinglist);
+        assert_eq!(
+            chat.param.get_bool(Param::ListUnsubscribeOneClick),
+            Some(false)
+        );
+
+        // Without List-Unsubscribe-Post, the mailto: URI is used.
+        unsubscribe(&t, chat_id).await?;
+        let sent = t.pop_sent_msg().await;
+        assert_eq!(sent.recipient().to_string(), "list-request@example.net");
+        assert!(sent.payload().contains("Subject: leave list\r\n"));
+        let info = t.get_last_msg_in(chat_id).await;
+        assert!(info.is_info());
+        assert_eq!(
+            info.get_text(),
+            stock_str::unsubscribed_from_mailinglist(&t).await
+        );
+
+        assert_eq!(sent.sender_msg_id, info.id);
+
+        // The unsubscribe request does not show up in any chat.
+        let chats = Chatlist::try_load(&t, 0, None, None).await?;
+        assert_eq!(chats.len(), 1);
+        assert!(
+            Contact::lookup_id_by_addr(&t, "list-request@example.net", Origin::Unknown)
+                .await?
+                .is_none()
+        );
+
+        let single_chat = t.get_self_chat().await;
+        assert!(unsubscribe(&t, single_chat.id).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_export_transcript() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let alice_chat_id = alice.create_chat(&bob).await.id;
+        let sent = alice.send_text(alice_chat_id, "Hello <b>Bob</b>").await;
+        let bob_chat_id = bob.recv_msg(&sent).await.chat_id;
+
+        let file = bob.get_blobdir().join("image.png");
+        tokio::fs::write(&file, include_bytes!("../test-data/image/avatar64x64.png")).await?;
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file(file.to_str().unwrap(), None);
+        msg.set_text("a picture".to_string());
+        let sent = bob.send_msg(bob_chat_id, &mut msg).await;
+        let mut alice_msg = alice.recv_msg(&sent).await;
+        // The MIME type given by the sender is not used for embedding.
+        alice_msg
+            .param
+            .set(Param::MimeType, "image/png\"><script>alert(1)</script>");
+        alice_msg.update_param(&alice).await?;
+
+        let mut text = Vec::new();
+        export_transcript(
+            &alice,
+            alice_chat_id,
+            TranscriptFormat::Text,
+            None,
+            &mut text,
+        )
+        .await?;
+        let text = String::from_utf8(text)?;
+        let chat_name = Chat::load_from_db(&alice, alice_chat_id).await?.name;
+        let bob_name = alice
+            .add_or_lookup_contact(&bob)
+            .await
+            .get_display_name()
+            .to_string();
+        assert!(text.starts_with(&format!("{chat_name}\n\n")));
+        assert!(text.contains("] Me: Hello <b>Bob</b>\n"));
+        assert!(text.contains(&format!("] {bob_name}: a picture\n  [Image: ")));
+
+        let mut html = Vec::new();
+        export_transcript(
+            &alice,
+            alice_chat_id,
+            TranscriptFormat::Html,
+            None,
+            &mut html,
+        )
+        .await?;
+        let html = String::from_utf8(html)?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Hello &lt;b&gt;Bob&lt;/b&gt;"));
+        let image = tokio::fs::read(alice_msg.get_file(&alice).unwrap()).await?;
+        assert!(html.contains(&format!(
+            "<img src=\"data:image/png;base64,{}\">",
+            base64::engine::general_purpose::STANDARD.encode(image)
+        )));
+        assert!(!html.contains("<script>"));
+        // Both senders use fallback avatars.
+        assert_eq!(html.matches("data:image/svg+xml;base64,").count(), 2);
+        assert!(html.ends_with("</html>\n"));
+
+        // Messages outside of the range are skipped.
+        let mut text = Vec::new();
+        export_transcript(
+            &alice,
+            alice_chat_id,
+            TranscriptFormat::Text,
+            Some(0..=0),
+            &mut text,
+        )
+        .await?;
+        assert_eq!(String::from_utf8(text)?, format!("{chat_name}\n\n"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_chat_self_addr() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let alias = "alice+work@example.org";
+        alice.set_config(Config::AliasAddrs, Some(alias)).await?;
+        assert!(alice.is_self_addr("Alice+Work@example.org").await?);
+
+        // A message to the alias makes the chat reply from the alias.
+        let bob_chat = bob.create_chat_with_contact("", alias).await;
+        let sent = bob.send_text(bob_chat.id, "Hi work address").await;
+        let msg = alice.recv_msg(&sent).await;
+        let alice_chat = Chat::load_from_db(&alice, msg.chat_id).await?;
+        assert_eq!(alice_chat.get_self_addr(&alice).await?, alias);
+
+        let sent = alice.send_text(alice_chat.id, "Hi Bob").await;
+        let parsed = bob.parse_msg(&sent).await;
+        assert_eq!(parsed.from.addr, alias);
+        assert!(sent
+            .payload()
+            .contains("Autocrypt: addr=alice+work@example.org;"));
+
+        // Only self addresses can be chosen.
+        assert!(alice_chat
+            .id
+            .set_self_addr(&alice, Some("bob@example.net"))
+            .await
+            .is_err());
+        alice_chat.id.set_self_addr(&alice, None).await?;
+        let alice_chat = Chat::load_from_db(&alice, alice_chat.id).await?;
+        assert_eq!(alice_chat.get_self_addr(&alice).await?, "alice@example.org");
+
+        // Removed aliases are not used anymore.
+        alice_chat.id.set_self_addr(&alice, Some(alias)).await?;
+        alice.set_config(Config::AliasAddrs, None).await?;
+        let alice_chat = Chat::load_from_db(&alice, alice_chat.id).await?;
+        assert_eq!(alice_chat.get_self_addr(&alice).await?, "alice@example.org");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_last_error() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        assert_eq!(alice_chat.get_last_error(), None);
+
+        let sent = alice.send_text(alice_chat.id, "Hi Bob").await;
+        let mut msg = Message::load_from_db(&alice, sent.sender_msg_id).await?;
+        message::set_msg_failed(&alice, &mut msg, "Recipient address rejected").await?;
+        let alice_chat = Chat::load_from_db(&alice, alice_chat.id).await?;
+        assert_eq!(
+            alice_chat.get_last_error(),
+            Some("Recipient address rejected")
+        );
+        let bob_id = alice.add_or_lookup_contact(&bob).await.id;
+        let contact = Contact::get_by_id(&alice, bob_id).await?;
+        assert_eq!(contact.get_last_error(), Some("Recipient address rejected"));
+
+        // The error is cleared once a message is sent successfully.
+        let sent = alice.send_text(alice_chat.id, "Hi again").await;
+        sent.sender_msg_id.set_delivered(&alice).await?;
+        let alice_chat = Chat::load_from_db(&alice, alice_chat.id).await?;
+        assert_eq!(alice_chat.get_last_error(), None);
+        let contact = Contact::get_by_id(&alice, bob_id).await?;
+        assert_eq!(contact.get_last_error(), None);
+        Ok(())
+    }
 }
@@ -6,7 +6,7 @@
 use crate::chat::{update_special_chat_names, Chat, ChatId, ChatVisibility};
 use crate::constants::{
     Blocked, Chattype, DC_CHAT_ID_ALLDONE_HINT, DC_CHAT_ID_ARCHIVED_LINK, DC_GCL_ADD_ALLDONE_HINT,
-    DC_GCL_ARCHIVED_ONLY, DC_GCL_FOR_FORWARDING, DC_GCL_NO_SPECIALS,
+    DC_GCL_ARCHIVED_ONLY, DC_GCL_FOR_FORWARDING, DC_GCL_NO_SPECIALS, DC_GCL_SPAM_ONLY,
 };
 use crate::contact::{Contact, ContactId};
 use crate::context::Context;
@@ -82,6 +82,8 @@ impl Chatlist {
     ///   not needed when DC_GCL_ARCHIVED_ONLY is already set)
     /// - if the flag DC_GCL_ADD_ALLDONE_HINT is set, DC_CHAT_ID_ALLDONE_HINT
     ///   is added as needed.
+    /// - if the flag DC_GCL_SPAM_ONLY is set, only contact requests classified as spam
+    ///   are returned. Otherwise these chats are not returned at all.
     /// `query`: An optional query for filtering the list. Only chats matching this query
     ///     are returned. When `is:unread` is contained in the query, the chatlist is
     ///     filtered such that only chats with unread messages show up.
@@ -97,6 +99,7 @@ pub async fn try_load(
         let flag_for_forwarding = 0 != listflags & DC_GCL_FOR_FORWARDING;
         let flag_no_specials = 0 != listflags & DC_GCL_NO_SPECIALS;
         let flag_add_alldone_hint = 0 != listflags & DC_GCL_ADD_ALLDONE_HINT;
+        let flag_spam_only = 0 != listflags & DC_GCL_SPAM_ONLY;
 
         let process_row = |row: &rusqlite::Row| {
             let chat_id: ChatId = row.get(0)?;
@@ -142,6 +145,8 @@ pub async fn try_load(
                                   ORDER BY timestamp DESC, id DESC LIMIT 1)
                  WHERE c.id>9
                    AND c.blocked!=1
+                   AND c.id NOT IN (SELECT chat_id FROM undo_queue)
+                   AND c.spam=0
                    AND c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?2)
                  GROUP BY c.id
                  ORDER BY c.archived=?3 DESC, IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
@@ -169,6 +174,8 @@ pub async fn try_load(
                                   ORDER BY timestamp DESC, id DESC LIMIT 1)
                  WHERE c.id>9
                    AND c.blocked!=1
+                   AND c.id NOT IN (SELECT chat_id FROM undo_queue)
+                   AND c.spam=0
                    AND c.archived=1
                  GROUP BY c.id
                  ORDER BY IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
@@ -177,6 +184,32 @@ pub async fn try_load(
                     process_rows,
                 )
                 .await?
+        } else if flag_spam_only {
+            // show contact requests classified as spam
+            context
+                .sql
+                .query_map(
+                    "SELECT c.id, m.id
+                 FROM chats c
+                 LEFT JOIN msgs m
+                        ON c.id=m.chat_id
+                       AND m.id=(
+                               SELECT id
+                                 FROM msgs
+                                WHERE chat_id=c.id
+                                  AND (hidden=0 OR state=?)
+                                  ORDER BY timestamp DESC, id DESC LIMIT 1)
+                 WHERE c.id>9
+                   AND c.blocked=2
+                   AND c.id NOT IN (SELECT chat_id FROM undo_queue)
+                   AND c.spam=1
+                 GROUP BY c.id
+                 ORDER BY IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
+                    (MessageState::OutDraft,),
+                    process_row,
+                    process_rows,
+                )
+                .await?
         } else if let Some(query) = query {
             let mut query = query.trim().to_string();
             ensure!(!query.is_empty(), "query mustn't be empty");
@@ -205,6 +238,8 @@ pub async fn try_load(
                                   ORDER BY timestamp DESC, id DESC LIMIT 1)
                  WHERE c.id>9 AND c.id!=?2
                    AND c.blocked!=1
+                   AND c.id NOT IN (SELECT chat_id FROM undo_queue)
+                   AND c.spam=0
                    AND c.name LIKE ?3
                    AND (NOT ?4 OR EXISTS (SELECT 1 FROM msgs m WHERE m.chat_id = c.id AND m.state == ?5 AND hidden=0))
                  GROUP BY c.id
@@ -258,6 +293,7 @@ pub async fn try_load(
                                       ORDER BY timestamp DESC, id DESC LIMIT 1)
                      WHERE c.id>9 AND c.id!=?
                        AND c.blocked=0
+                       AND c.id NOT IN (SELECT chat_id FROM undo_queue)
                        AND NOT c.archived=?
                        AND (c.type!=? OR c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?))
                      GROUP BY c.id
@@ -285,6 +321,8 @@ pub async fn try_load(
                                       ORDER BY timestamp DESC, id DESC LIMIT 1)
                      WHERE c.id>9 AND c.id!=?
                        AND (c.blocked=0 OR c.blocked=2)
+                       AND c.id NOT IN (SELECT chat_id FROM undo_queue)
+                       AND c.spam=0
                        AND NOT c.archived=?
                      GROUP BY c.id
                      ORDER BY c.id=0 DESC, c.archived=? DESC, IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
@@ -441,7 +479,9 @@ pub async fn get_archived_cnt(context: &Context) -> Result<usize> {
     let count = context
         .sql
         .count(
-            "SELECT COUNT(*) FROM chats WHERE blocked!=? AND archived=?;",
+            "SELECT COUNT(*) FROM chats
+             WHERE blocked!=? AND archived=? AND spam=0
+               AND id NOT IN (SELECT chat_id FROM undo_queue);",
             (Blocked::Yes, ChatVisibility::Archived),
         )
         .await?;
@@ -5,17 +5,26 @@
 use std::str::FromStr;
 
 use anyhow::{ensure, Context as _, Result};
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use strum::{EnumProperty, IntoEnumIterator};
 use strum_macros::{AsRefStr, Display, EnumIter, EnumProperty, EnumString};
 
 use crate::blob::BlobObject;
-use crate::constants::DC_VERSION_STR;
-use crate::contact::addr_cmp;
+use crate::constants::{BccSelf, DC_VERSION_STR, MAX_UI_SETTINGS_BYTES};
+use crate::contact::{addr_cmp, may_be_valid_addr, ContactId};
 use crate::context::Context;
 use crate::events::EventType;
+use crate::imap::drafts;
+use crate::log::{LogExt, LogFilter};
 use crate::mimefactory::RECOMMENDED_FILE_SIZE;
+use crate::param::Param;
 use crate::provider::{get_provider_by_id, Provider};
-use crate::tools::{get_abs_path, improve_single_line_input, EmailAddress};
+use crate::push::register_push_token;
+use crate::sync::{SyncData, UiSettingsData};
+use crate::tools::{get_abs_path, improve_single_line_input, time, EmailAddress};
+use crate::webhook::{update_webhook, WebhookEvent};
 
 /// The available configuration keys.
 #[derive(
@@ -31,8 +40,11 @@
     EnumProperty,
     PartialOrd,
     Ord,
+    Serialize,
+    Deserialize,
 )]
 #[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum Config {
     /// Email address, used in the `From:` field.
     Addr,
@@ -79,6 +91,11 @@ pub enum Config {
     /// Should not be extended in the future, create new config keys instead.
     ServerFlags,
 
+    /// Whether to use OAuth 2 for SMTP.
+    ///
+    /// If unset, OAuth 2 is used for SMTP if it is enabled for IMAP by [`Config::ServerFlags`].
+    SmtpOauth2,
+
     /// True if SOCKS5 is enabled.
     ///
     /// Can be used to disable SOCKS5 without erasing SOCKS5 configuration.
@@ -105,12 +122,34 @@ pub enum Config {
     /// Own avatar filename.
     Selfavatar,
 
-    /// Send BCC copy to self.
+    /// When to send BCC copy to self, one of [`BccSelf`](crate::constants::BccSelf).
     ///
     /// Should be enabled for multidevice setups.
-    #[strum(props(default = "1"))]
+    #[strum(props(default = "1"))] // also change BccSelf.default() on changes
     BccSelf,
 
+    /// Whether to load remote content, e.g. images, of HTML messages,
+    /// one of [`RemoteContent`](crate::constants::RemoteContent).
+    #[strum(props(default = "1"))] // also change RemoteContent.default() on changes
+    RemoteContent,
+
+    /// What notifications built by [`crate::notification::get_notification`] show,
+    /// one of [`NotifyPrivacy`](crate::constants::NotifyPrivacy).
+    #[strum(props(default = "0"))] // also change NotifyPrivacy.default() on changes
+    NotifyPrivacy,
+
+    /// Count fresh messages in muted chats in [`Context::get_fresh_msg_count`].
+    #[strum(props(default = "0"))]
+    BadgeCountMuted,
+
+    /// Count fresh messages in contact requests in [`Context::get_fresh_msg_count`].
+    #[strum(props(default = "0"))]
+    BadgeCountRequests,
+
+    /// Count fresh device messages in [`Context::get_fresh_msg_count`].
+    #[strum(props(default = "1"))]
+    BadgeCountDeviceMsgs,
+
     /// True if encryption is preferred according to Autocrypt standard.
     #[strum(props(default = "1"))]
     E2eeEnabled,
@@ -135,7 +174,9 @@ pub enum Config {
     #[strum(props(default = "0"))]
     OnlyFetchMvbox,
 
-    /// Whether to show classic emails or only chat messages.
+    /// Whether to show classic emails or only chat messages, see [`ShowEmails`].
+    ///
+    /// [`ShowEmails`]: crate::constants::ShowEmails
     #[strum(props(default = "2"))] // also change ShowEmails.default() on changes
     ShowEmails,
 
@@ -154,6 +195,16 @@ pub enum Config {
     #[strum(props(default = "1"))]
     FetchedExistingMsgs,
 
+    /// Number of days of mail history to import into the chats
+    /// on the first time `start_io()` is called after configuring, 0 to not import it.
+    ///
+    /// Messages of this period are fetched from the inbox, the movebox and the sentbox
+    /// instead of only the newest ones as with `FetchExistingMsgs`.
+    /// Classic emails are only imported as allowed by `ShowEmails`.
+    /// The import can be cancelled with `Context::cancel_history_import()`.
+    #[strum(props(default = "0"))]
+    ImportHistoryDays,
+
     /// Type of the OpenPGP key to generate.
     #[strum(props(default = "0"))]
     KeyGenType,
@@ -177,6 +228,13 @@ pub enum Config {
     #[strum(props(default = "0"))]
     DeleteDeviceAfter,
 
+    /// Number of seconds during which deleting messages and chats
+    /// and removing group members can be undone, see [`crate::undo::undo`].
+    ///
+    /// Equals to 0 by default, which means such operations are executed at once.
+    #[strum(props(default = "0"))]
+    UndoTimeout,
+
     /// Move messages to the Trash folder instead of marking them "\Deleted". Overrides
     /// `ProviderOptions::delete_to_trash`.
     DeleteToTrash,
@@ -184,6 +242,17 @@ pub enum Config {
     /// Save raw MIME messages with headers in the database if true.
     SaveMimeHeaders,
 
+    /// Store the texts of incoming encrypted messages encrypted to the own key if true.
+    ///
+    /// By default the texts are stored decrypted, so that they can be searched.
+    /// Texts stored encrypted are not found by [`Context::search_msgs`].
+    /// Senders, timestamps and attachments are stored unencrypted in any case.
+    ///
+    /// The own secret key is stored unencrypted in the same database,
+    /// so this only protects against reading the message table alone,
+    /// e.g. from a partial copy of the database.
+    EncryptAtRest,
+
     /// The primary email address. Also see `SecondaryAddrs`.
     ConfiguredAddr,
 
@@ -223,6 +292,9 @@ pub enum Config {
     /// Whether OAuth 2 is used with configured provider.
     ConfiguredServerFlags,
 
+    /// Whether OAuth 2 is used for configured SMTP server.
+    ConfiguredSmtpOauth2,
+
     /// Configured SMTP server security (e.g. TLS, STARTTLS).
     ConfiguredSendSecurity,
 
@@ -238,6 +310,12 @@ pub enum Config {
     /// Configured "Trash" folder.
     ConfiguredTrashFolder,
 
+    /// Configured "Spam" folder.
+    ConfiguredSpamFolder,
+
+    /// Configured "Drafts" folder.
+    ConfiguredDraftsFolder,
+
     /// Unix timestamp of the last successful configuration.
     ConfiguredTimestamp,
 
@@ -251,21 +329,55 @@ pub enum Config {
     /// (`addr1@example.org addr2@example.org addr3@example.org`)
     SecondaryAddrs,
 
+    /// Alias addresses delivered to the same mailbox as the primary address, separated by spaces,
+    /// e.g. plus-addresses or aliases set up at the provider.
+    ///
+    /// Incoming messages to an alias are received as messages to self.
+    /// Messages can be sent from an alias, see [`ChatId::set_self_addr`],
+    /// using the same key as the primary address.
+    ///
+    /// [`ChatId::set_self_addr`]: crate::chat::ChatId::set_self_addr
+    AliasAddrs,
+
     /// Read-only core version string.
     #[strum(serialize = "sys.version")]
+    #[serde(rename = "sys.version")]
     SysVersion,
 
     /// Maximal recommended attachment size in bytes.
     #[strum(serialize = "sys.msgsize_max_recommended")]
+    #[serde(rename = "sys.msgsize_max_recommended")]
     SysMsgsizeMaxRecommended,
 
     /// Space separated list of all config keys available.
     #[strum(serialize = "sys.config_keys")]
+    #[serde(rename = "sys.config_keys")]
     SysConfigKeys,
 
     /// True if it is a bot account.
     Bot,
 
+    /// Whether to collapse repeated automatic replies, e.g. vacation notices.
+    ///
+    /// If set, an automatic reply is not added to the chat
+    /// if the previous message from the same sender in the chat is an automatic reply as well.
+    #[strum(props(default = "0"))]
+    CollapseAutoReplies,
+
+    /// Text sent automatically as a reply to incoming messages in accepted 1:1 chats,
+    /// e.g. to explain how to talk to a bot. Unset to disable automatic replies.
+    ///
+    /// No automatic replies are sent to automatic replies, bots, mailing lists
+    /// and other automatically submitted messages to avoid mail loops.
+    AutoReplyText,
+
+    /// Minimum time in seconds between two automatic replies to the same sender,
+    /// see [`Config::AutoReplyText`].
+    ///
+    /// 7 days by default as recommended by [RFC 3834](https://tools.ietf.org/html/rfc3834).
+    #[strum(props(default = "604800"))]
+    AutoReplyInterval,
+
     /// Whether we send a warning if the password is wrong (set to false when we send a warning
     /// because we do not want to send a second warning)
     #[strum(props(default = "0"))]
@@ -292,16 +404,60 @@ pub enum Config {
     #[strum(props(default = "0"))]
     DisableIdle,
 
+    /// Whether the account stays offline.
+    ///
+    /// If set, IMAP and SMTP connections are closed and not opened again
+    /// until the option is unset, even if [`Context::start_io`] is called.
+    /// Configuring, downloading messages on demand, webhooks,
+    /// push notification registration and webxdc realtime channels fail while offline.
+    /// Messages are still queued for sending.
+    ///
+    /// The option is not included in backups.
+    #[strum(props(default = "0"))]
+    Offline,
+
+    /// Whether webxdc realtime channels may be used.
+    ///
+    /// Realtime channels connect to other chat members through an iroh relay.
+    /// No IP addresses are advertised, but the connection may be upgraded
+    /// to a direct one by hole punching, which reveals the own IP address
+    /// to the other members of the channel, so they are disabled by default.
+    /// They are not available while a SOCKS5 proxy is used.
+    #[strum(props(default = "0"))]
+    WebxdcRealtimeEnabled,
+
+    /// URL of the iroh relay server used by webxdc realtime channels,
+    /// see [`crate::peer_channels`].
+    ///
+    /// If unset, the default relay servers of iroh are used.
+    WebxdcRealtimeRelay,
+
     /// Defines the max. size (in bytes) of messages downloaded automatically.
     /// 0 = no limit.
     #[strum(props(default = "0"))]
     DownloadLimit,
 
+    /// Maximum number of messages to download with one IMAP FETCH command.
+    /// 0 = no limit.
+    #[strum(props(default = "100"))]
+    FetchBatchSize,
+
+    /// Maximum total size in bytes of messages to download with one IMAP FETCH command.
+    /// Messages larger than this are downloaded one by one.
+    /// 0 = no limit.
+    #[strum(props(default = "10485760"))]
+    FetchBatchBytes,
+
     /// Send sync messages, requires `BccSelf` to be set as well.
     /// In a future versions, this switch may be removed.
     #[strum(props(default = "0"))]
     SendSyncMsgs,
 
+    /// Whether drafts are stored in the Drafts folder on the server,
+    /// so that other devices and other MUAs see them.
+    #[strum(props(default = "0"))]
+    SyncDrafts,
+
     /// Space-separated list of all the authserv-ids which we believe
     /// may be the one of our email server.
     ///
@@ -319,6 +475,15 @@ pub enum Config {
     /// Last message processed by the bot.
     LastMsgId,
 
+    /// Run as a headless bot.
+    ///
+    /// Setting this to 1 also sets [`Config::Bot`] to 1, so contact requests are accepted
+    /// automatically, and [`Config::MdnsEnabled`] to 0, so no read receipts are sent.
+    /// [`crate::bot::Dispatcher`] marks processed messages as seen.
+    /// Setting this back to 0 does not change the other options.
+    #[strum(props(default = "0"))]
+    HeadlessBot,
+
     /// How often to gossip Autocrypt keys in chats with multiple recipients, in seconds. 2 days by
     /// default.
     ///
@@ -335,6 +500,178 @@ pub enum Config {
     /// until `chat_id.accept()` is called.
     #[strum(props(default = "0"))]
     VerifiedOneOnOneChats,
+
+    /// Collect local metrics, see [`Context::get_metrics`].
+    /// Metrics are kept in memory only and are never sent anywhere.
+    #[strum(props(default = "0"))]
+    MetricsEnabled,
+
+    /// Log filter, e.g. `info,imap=debug,sql=warning`, see [`LogFilter`].
+    ///
+    /// [`LogFilter`]: crate::log::LogFilter
+    #[strum(props(default = "info"))]
+    LogFilter,
+
+    /// HTTPS URL to send webhook notifications to, see [`crate::webhook`].
+    /// Webhook notifications are disabled if unset.
+    WebhookUrl,
+
+    /// Secret key used to sign webhook notifications.
+    /// Must be set before [`Config::WebhookUrl`] and must not be empty.
+    WebhookSecret,
+
+    /// Comma-separated list of events to send webhook notifications for,
+    /// see [`crate::webhook::WebhookEvent`].
+    #[strum(props(default = "incoming_msg,securejoin_done,configure_done"))]
+    WebhookEvents,
+
+    /// HTTPS URL of the notification proxy to register the push device token with,
+    /// see [`crate::push`].
+    PushProxyUrl,
+
+    /// Push device token or UnifiedPush endpoint of this device,
+    /// set with [`Context::set_push_device_token`].
+    PushDeviceToken,
+
+    /// Space-separated list of addresses and domains
+    /// whose chats are accepted automatically instead of being shown as contact requests,
+    /// e.g. `example.org bob@example.net`.
+    ///
+    /// A domain also matches its subdomains.
+    /// Mailing lists are not accepted automatically.
+    /// The sender must be authenticated by a signature with a previously known key
+    /// or by a passing DKIM check of the sender domain.
+    AutoAcceptRules,
+
+    /// Verify DKIM signatures of incoming messages, see [`crate::dkim`].
+    ///
+    /// Disabled by default as it requires a DNS lookup of the signing key
+    /// for each sender domain, which reveals the sender domains to the DNS resolver
+    /// and delays receiving messages.
+    /// Messages claiming to be sent by self with an invalid signature are marked with an error.
+    VerifyDkim,
+
+    /// Classify messages from unknown senders as spam, see [`crate::spam`].
+    ///
+    /// Spam is received into contact requests which are only listed with
+    /// [`crate::constants::DC_GCL_SPAM_ONLY`].
+    #[strum(props(default = "0"))]
+    SpamFilter,
+
+    /// Comma-separated list of words which mark a message as spam
+    /// if contained in the subject, case-insensitive.
+    SpamKeywords,
+
+    /// Number of days after which contact requests without new messages,
+    /// including spam, are deleted during housekeeping, see [`crate::spam`].
+    ///
+    /// 0 means contact requests are never deleted.
+    #[strum(props(default = "0"))]
+    DeleteRequestsAfter,
+
+    /// Also delete the messages of stale contact requests from the server.
+    #[strum(props(default = "0"))]
+    DeleteRequestsFromServer,
+
+    /// Newline-separated list of additional IMAP folders to fetch messages from,
+    /// e.g. folders where messages are sorted into by server-side filters.
+    ///
+    /// See [`Context::set_extra_folder_watched`].
+    ExtraFolders,
+
+    /// Opaque settings of the UI for the whole account, e.g. the default wallpaper.
+    ///
+    /// Unlike the keys set by [`Context::set_ui_config`],
+    /// the settings are synchronized to other devices if `SendSyncMsgs` is set.
+    /// The value must not be longer than [`MAX_UI_SETTINGS_BYTES`] bytes.
+    /// Settings of single chats are set with [`ChatId::set_ui_settings`].
+    ///
+    /// [`ChatId::set_ui_settings`]: crate::chat::ChatId::set_ui_settings
+    UiSettings,
+}
+
+impl Config {
+    /// Returns true if the key holds state of the core rather than a setting.
+    ///
+    /// Changes of such keys are not reported with [`EventType::ConfigChanged`].
+    pub(crate) fn is_internal(self) -> bool {
+        matches!(
+            self,
+            Config::NotifyAboutWrongPw
+                | Config::QuotaExceeding
+                | Config::LastHousekeeping
+                | Config::AuthservIdCandidates
+                | Config::LastMsgId
+        ) || self.as_ref().starts_with("configured")
+    }
+}
+
+/// Keys of internal values stored in the `config` table.
+///
+/// Unlike [`Config`], these are no settings and are not accessible via the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr)]
+pub(crate) enum InternalConfig {
+    /// Version of the IMAP folder configuration.
+    /// Folders are reconfigured when this is lower than `DC_FOLDERS_CONFIGURED_VERSION`.
+    #[strum(serialize = "folders_configured")]
+    FoldersConfigured,
+
+    /// Timestamp of the last backup export.
+    #[strum(serialize = "backup_time")]
+    BackupTime,
+
+    /// SHA-256 hash of the current [`Config::Selfavatar`] file,
+    /// used to detect whether setting the avatar changes it.
+    #[strum(serialize = "selfavatar_hash")]
+    SelfavatarHash,
+
+    /// Set if other devices using the account are known.
+    #[strum(serialize = "other_devices_known")]
+    OtherDevicesKnown,
+
+    /// Set once the "Saved messages" chat is added.
+    #[strum(serialize = "self-chat-added")]
+    SelfChatAdded,
+
+    /// Blob name of the icon of broadcast lists.
+    #[strum(serialize = "icon-broadcast")]
+    IconBroadcast,
+
+    /// Blob name of the icon of the archive link.
+    #[strum(serialize = "icon-archive")]
+    IconArchive,
+
+    /// Redirect URI passed to the authorizer when starting OAuth 2 authorization.
+    #[strum(serialize = "oauth2_pending_redirect_uri")]
+    Oauth2PendingRedirectUri,
+
+    /// Redirect URI used to obtain the current OAuth 2 refresh token.
+    #[strum(serialize = "oauth2_redirect_uri")]
+    Oauth2RedirectUri,
+
+    /// OAuth 2 refresh token.
+    #[strum(serialize = "oauth2_refresh_token")]
+    Oauth2RefreshToken,
+
+    /// The authorization code the OAuth 2 refresh token was obtained for.
+    #[strum(serialize = "oauth2_refresh_token_for")]
+    Oauth2RefreshTokenFor,
+
+    /// OAuth 2 access token.
+    #[strum(serialize = "oauth2_access_token")]
+    Oauth2AccessToken,
+
+    /// Timestamp when the OAuth 2 access token expires.
+    #[strum(serialize = "oauth2_timestamp_expires")]
+    Oauth2TimestampExpires,
+
+    /// Random ID identifying this account in push payloads, see [`crate::push`].
+    #[strum(serialize = "push_id")]
+    PushId,
+
+    /// Secret shared with the notification proxy to encrypt push payloads.
+    #[strum(serialize = "push_secret")]
+    PushSecret,
 }
 
 impl Context {
@@ -414,6 +751,48 @@ pub async fn get_config_bool(&self, key: Config) -> Result<bool> {
         Ok(self.get_config_bool_opt(key).await?.unwrap_or_default())
     }
 
+    /// Returns the additional folders to fetch messages from,
+    /// see [`Context::set_extra_folder_watched`].
+    pub async fn get_extra_folders(&self) -> Result<Vec<String>> {
+        Ok(self
+            .get_config(Config::ExtraFolders)
+            .await?
+            .unwrap_or_default()
+            .lines()
+            .filter(|folder| !folder.is_empty())
+            .map(|folder| folder.to_string())
+            .collect())
+    }
+
+    /// Enables or disables fetching messages from an additional IMAP folder.
+    ///
+    /// Each additional folder is watched with its own IMAP connection,
+    /// using IDLE if the server supports it.
+    /// To limit the number of connections, only the first two folders get their own connection
+    /// if there are more than three, and the remaining folders are polled over a shared one.
+    /// Messages found there are processed as in the Inbox,
+    /// in particular chat messages are moved to the DeltaChat folder if
+    /// [`Config::MvboxMove`] is set.
+    /// Folders which do not exist on the server are not created,
+    /// selecting them is retried periodically.
+    pub async fn set_extra_folder_watched(&self, folder: &str, watched: bool) -> Result<()> {
+        ensure!(
+            !folder.is_empty() && !folder.contains('\n'),
+            "Invalid folder name {folder:?}"
+        );
+        let mut folders = self.get_extra_folders().await?;
+        folders.retain(|f| f != folder);
+        if watched {
+            folders.push(folder.to_string());
+        }
+        let value = folders.join("\n");
+        self.set_config(
+            Config::ExtraFolders,
+            Some(value.as_str()).filter(|value| !value.is_empty()),
+        )
+        .await
+    }
+
     /// Returns true if movebox ("DeltaChat" folder) should be watched.
     pub(crate) async fn should_watch_mvbox(&self) -> Result<bool> {
         Ok(self.get_config_bool(Config::MvboxMove).await?
@@ -432,6 +811,38 @@ pub async fn get_config_delete_server_after(&self) -> Result<Option<i64>> {
         }
     }
 
+    /// Returns true if a copy of outgoing messages should be sent to self,
+    /// see [`Config::BccSelf`].
+    pub(crate) async fn should_send_bcc_self(&self) -> Result<bool> {
+        let bcc_self =
+            BccSelf::from_i32(self.get_config_int(Config::BccSelf).await?).unwrap_or_default();
+        match bcc_self {
+            BccSelf::Never => Ok(false),
+            BccSelf::Always => Ok(true),
+            BccSelf::MultiDevice => self.are_other_devices_known().await,
+        }
+    }
+
+    /// Returns true if other devices using the account are known,
+    /// see [`Context::set_other_devices_known`].
+    pub(crate) async fn are_other_devices_known(&self) -> Result<bool> {
+        self.sql
+            .get_raw_config_bool(InternalConfig::OtherDevicesKnown.as_ref())
+            .await
+    }
+
+    /// Remembers that other devices use the account,
+    /// so copies of outgoing messages are sent to self in [`BccSelf::MultiDevice`] mode.
+    pub(crate) async fn set_other_devices_known(&self) -> Result<()> {
+        if !self.are_other_devices_known().await? {
+            info!(self, "Other devices using the account are known.");
+            self.sql
+                .set_raw_config_bool(InternalConfig::OtherDevicesKnown.as_ref(), true)
+                .await?;
+        }
+        Ok(())
+    }
+
     /// Gets the configured provider, as saved in the `configured_provider` value.
     ///
     /// The provider is determined by `get_provider_info()` during configuration and then saved
@@ -456,23 +867,47 @@ pub async fn get_config_delete_device_after(&self) -> Result<Option<i64>> {
 
     /// Set the given config key.
     /// If `None` is passed as a value the value is cleared and set to the default if there is one.
+    ///
+    /// Emits [`EventType::ConfigChanged`] once the new value is stored,
+    /// unless the key holds internal state.
     pub async fn set_config(&self, key: Config, value: Option<&str>) -> Result<()> {
+        self.set_config_internal(key, value).await?;
+        if !key.is_internal() {
+            self.emit_event(EventType::ConfigChanged(key));
+        }
+        Ok(())
+    }
+
+    async fn set_config_internal(&self, key: Config, value: Option<&str>) -> Result<()> {
         match key {
             Config::Selfavatar => {
-                self.sql
-                    .execute("UPDATE contacts SET selfavatar_sent=0;", ())
-                    .await?;
-                match value {
+                let hash = match value {
                     Some(value) => {
                         let mut blob = BlobObject::new_from_path(self, value.as_ref()).await?;
                         blob.recode_to_avatar_size(self).await?;
                         self.sql
                             .set_raw_config(key.as_ref(), Some(blob.as_name()))
                             .await?;
+                        let data = tokio::fs::read(blob.to_abs_path()).await?;
+                        Some(hex::encode(Sha256::digest(data)))
                     }
                     None => {
                         self.sql.set_raw_config(key.as_ref(), None).await?;
+                        None
                     }
+                };
+                let old_hash = self
+                    .sql
+                    .get_raw_config(InternalConfig::SelfavatarHash.as_ref())
+                    .await?;
+                if hash.is_none() || hash != old_hash {
+                    // The avatar is sent again only if it is removed or its content changes.
+                    self.sql
+                        .execute("UPDATE contacts SET selfavatar_sent=0;", ())
+                        .await?;
+                    self.sql
+                        .set_raw_config(InternalConfig::SelfavatarHash.as_ref(), hash.as_deref())
+                        .await?;
                 }
                 self.emit_event(EventType::SelfavatarChanged);
             }
@@ -482,14 +917,189 @@ pub async fn set_config(&self, key: Config, value: Option<&str>) -> Result<()> {
                 self.scheduler.interrupt_ephemeral_task().await;
                 ret?
             }
+            Config::ExtraFolders => {
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                // Restart IO to start or stop the loops watching the folders.
+                self.restart_io_if_running().await;
+            }
             Config::Displayname => {
                 let value = value.map(improve_single_line_input);
+                if self.sql.get_raw_config(key.as_ref()).await? != value {
+                    // Attach the profile including the avatar to the next message to each contact,
+                    // later messages attach the avatar only every `DC_RESEND_USER_AVATAR_DAYS`.
+                    self.sql
+                        .execute("UPDATE contacts SET selfavatar_sent=0;", ())
+                        .await?;
+                }
                 self.sql
                     .set_raw_config(key.as_ref(), value.as_deref())
                     .await?;
+                // Older messages from other devices must not revert the name.
+                self.update_contacts_timestamp(
+                    ContactId::SELF,
+                    Param::DisplaynameTimestamp,
+                    time(),
+                )
+                .await?;
+            }
+            Config::HeadlessBot => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1")),
+                    "Boolean value must be either 0 or 1"
+                );
+                if value == Some("1") {
+                    self.sql
+                        .set_raw_config(Config::Bot.as_ref(), Some("1"))
+                        .await?;
+                    self.emit_event(EventType::ConfigChanged(Config::Bot));
+                    self.sql
+                        .set_raw_config(Config::MdnsEnabled.as_ref(), Some("0"))
+                        .await?;
+                    self.emit_event(EventType::ConfigChanged(Config::MdnsEnabled));
+                }
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+            }
+            Config::WebhookUrl => {
+                if let Some(url) = value.filter(|url| !url.is_empty()) {
+                    ensure!(
+                        url.starts_with("https://"),
+                        "Webhook URL must start with https://"
+                    );
+                    ensure!(
+                        !self
+                            .get_config(Config::WebhookSecret)
+                            .await?
+                            .unwrap_or_default()
+                            .is_empty(),
+                        "Webhook secret must be set before the webhook URL"
+                    );
+                }
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                update_webhook(self).await?;
+            }
+            Config::WebhookEvents => {
+                WebhookEvent::parse_list(value.unwrap_or_default())?;
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                update_webhook(self).await?;
+            }
+            Config::WebhookSecret => {
+                ensure!(
+                    !value.unwrap_or_default().is_empty()
+                        || self
+                            .get_config(Config::WebhookUrl)
+                            .await?
+                            .unwrap_or_default()
+                            .is_empty(),
+                    "Webhook secret must not be empty while a webhook URL is set"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                update_webhook(self).await?;
+            }
+            Config::UiSettings => {
+                let settings = value.unwrap_or_default();
+                ensure!(
+                    settings.len() <= MAX_UI_SETTINGS_BYTES,
+                    "UI settings must not be longer than {MAX_UI_SETTINGS_BYTES} bytes"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                self.add_sync_item(SyncData::UiSettings(UiSettingsData {
+                    chat: None,
+                    settings: settings.to_string(),
+                }))
+                .await?;
+                self.send_sync_msg().await?;
+            }
+            Config::PushProxyUrl => {
+                if let Some(url) = value {
+                    ensure!(
+                        url.is_empty() || url.starts_with("https://"),
+                        "Notification proxy URL must start with https://"
+                    );
+                }
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+            }
+            Config::LogFilter => {
+                let filter: LogFilter = value.unwrap_or_default().parse()?;
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                self.set_log_filter(filter);
+            }
+            Config::SyncDrafts => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1")),
+                    "Boolean value must be either 0 or 1"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                if value == Some("1") {
+                    drafts::schedule_all_draft_uploads(self).await?;
+                }
+            }
+            Config::Offline => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1")),
+                    "Boolean value must be either 0 or 1"
+                );
+                let was_offline = self.get_config_bool(Config::Offline).await?;
+                let offline = value == Some("1");
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                if offline {
+                    self.peer_channels.leave_all().await;
+                }
+                self.scheduler.set_offline(self, offline).await;
+                if was_offline && !offline {
+                    // Registration attempts failed while offline.
+                    register_push_token(self).await.log_err(self).ok();
+                }
+            }
+            Config::WebxdcRealtimeEnabled => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1")),
+                    "Boolean value must be either 0 or 1"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                if value != Some("1") {
+                    self.peer_channels.leave_all().await;
+                }
+            }
+            Config::MetricsEnabled => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1")),
+                    "Boolean value must be either 0 or 1"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+                self.metrics.set_enabled(value == Some("1"));
+            }
+            Config::AliasAddrs => {
+                let value = value.unwrap_or_default();
+                for addr in value.split_ascii_whitespace() {
+                    ensure!(may_be_valid_addr(addr), "Invalid alias address {addr:?}");
+                }
+                let value = value.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                self.sql
+                    .set_raw_config(key.as_ref(), Some(value.as_str()).filter(|v| !v.is_empty()))
+                    .await?;
+            }
+            Config::BccSelf => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1") | Some("2")),
+                    "BccSelf value must be 0, 1 or 2"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+            }
+            Config::RemoteContent => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1") | Some("2")),
+                    "RemoteContent value must be 0, 1 or 2"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
+            }
+            Config::NotifyPrivacy => {
+                ensure!(
+                    matches!(value, None | Some("0") | Some("1") | Some("2")),
+                    "NotifyPrivacy value must be 0, 1 or 2"
+                );
+                self.sql.set_raw_config(key.as_ref(), value).await?;
             }
             Config::Socks5Enabled
-            | Config::BccSelf
             | Config::E2eeEnabled
             | Config::MdnsEnabled
             | Config::SentboxWatch
@@ -498,11 +1108,20 @@ pub async fn set_config(&self, key: Config, value: Option<&str>) -> Result<()> {
             | Config::FetchExistingMsgs
             | Config::DeleteToTrash
             | Config::SaveMimeHeaders
+            | Config::EncryptAtRest
             | Config::Configured
             | Config::Bot
+            | Config::CollapseAutoReplies
             | Config::NotifyAboutWrongPw
             | Config::SendSyncMsgs
             | Config::SignUnencrypted
+            | Config::VerifyDkim
+            | Config::BadgeCountMuted
+            | Config::BadgeCountRequests
+            | Config::BadgeCountDeviceMsgs
+            | Config::SpamFilter
+            | Config::DeleteRequestsFromServer
+            | Config::SmtpOauth2
             | Config::DisableIdle => {
                 ensure!(
                     matches!(value, None | Some("0") | Some("1")),
@@ -560,6 +1179,11 @@ pub(crate) async fn is_self_addr(&self, addr: &str) -> Result<bool> {
                 .get_secondary_self_addrs()
                 .await?
                 .iter()
+                .any(|a| addr_cmp(addr, a))
+            || self
+                .get_alias_addrs()
+                .await?
+                .iter()
                 .any(|a| addr_cmp(addr, a)))
     }
 
@@ -571,7 +1195,11 @@ pub(crate) async fn set_primary_self_addr(&self, primary_new: &str) -> Result<()
         let old_addr = self.get_config(Config::ConfiguredAddr).await?;
 
         // add old primary address (if exists) to secondary addresses
-        let mut secondary_addrs = self.get_all_self_addrs().await?;
+        let mut secondary_addrs: Vec<String> = old_addr
+            .iter()
+            .cloned()
+            .chain(self.get_secondary_self_addrs().await?)
+            .collect();
         // never store a primary address also as a secondary
         secondary_addrs.retain(|a| !addr_cmp(a, primary_new));
         self.set_config(
@@ -597,12 +1225,28 @@ pub(crate) async fn set_primary_self_addr(&self, primary_new: &str) -> Result<()
         Ok(())
     }
 
-    /// Returns all primary and secondary self addresses.
+    /// Returns all primary, secondary and alias self addresses.
     pub(crate) async fn get_all_self_addrs(&self) -> Result<Vec<String>> {
         let primary_addrs = self.get_config(Config::ConfiguredAddr).await?.into_iter();
         let secondary_addrs = self.get_secondary_self_addrs().await?.into_iter();
+        let alias_addrs = self.get_alias_addrs().await?.into_iter();
 
-        Ok(primary_addrs.chain(secondary_addrs).collect())
+        Ok(primary_addrs
+            .chain(secondary_addrs)
+            .chain(alias_addrs)
+            .collect())
+    }
+
+    /// Returns all alias addresses, see [`Config::AliasAddrs`].
+    pub(crate) async fn get_alias_addrs(&self) -> Result<Vec<String>> {
+        let alias_addrs = self
+            .get_config(Config::AliasAddrs)
+            .await?
+            .unwrap_or_default();
+        Ok(alias_addrs
+            .split_ascii_whitespace()
+            .map(|s| s.to_string())
+            .collect())
     }
 
     /// Returns all secondary self addresses.
@@ -641,11 +1285,10 @@ fn get_config_keys_string() -> String {
 mod tests {
     use std::string::ToString;
 
-    use num_traits::FromPrimitive;
-
     use super::*;
     use crate::constants;
-    use crate::test_utils::TestContext;
+    use crate::message::MessageState;
+    use crate::test_utils::{TestContext, TestContextManager};
 
     #[test]
     fn test_to_string() {
@@ -722,6 +1365,27 @@ async fn test_set_config_bool() -> Result<()> {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_config_changed_event() -> Result<()> {
+        let t = TestContext::new().await;
+
+        t.set_config(Config::Selfstatus, Some("Busy")).await?;
+        t.evtracker
+            .get_matching(|evt| matches!(evt, EventType::ConfigChanged(Config::Selfstatus)))
+            .await;
+
+        // Failed changes and changes of internal state do not emit the event.
+        assert!(t.set_config(Config::MdnsEnabled, Some("2")).await.is_err());
+        t.set_config(Config::LastHousekeeping, Some("1")).await?;
+        t.set_config_bool(Config::Bot, true).await?;
+        let evt = t
+            .evtracker
+            .get_matching(|evt| matches!(evt, EventType::ConfigChanged(_)))
+            .await;
+        assert_eq!(evt, EventType::ConfigChanged(Config::Bot));
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_self_addrs() -> Result<()> {
         let alice = TestContext::new_alice().await;
@@ -774,4 +1438,64 @@ async fn test_self_addrs() -> Result<()> {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_bcc_self() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice1 = tcm.alice().await;
+        let alice2 = tcm.alice().await;
+        let bob = tcm.bob().await;
+        assert!(alice1.should_send_bcc_self().await?);
+
+        alice1.set_config(Config::BccSelf, Some("0")).await?;
+        assert!(!alice1.should_send_bcc_self().await?);
+        assert!(alice1.set_config(Config::BccSelf, Some("3")).await.is_err());
+
+        // Copies are only sent once another device is known.
+        alice1.set_config(Config::BccSelf, Some("2")).await?;
+        assert!(!alice1.should_send_bcc_self().await?);
+        let chat = alice1.create_chat(&bob).await;
+        let sent = alice1.send_text(chat.id, "Hi").await;
+        assert!(!sent.is_sent_to("alice@example.org"));
+
+        // The other device sends copies to self, so the first device learns about it.
+        let sent = alice2
+            .send_text(alice2.create_chat(&bob).await.id, "Hello")
+            .await;
+        assert!(sent.is_sent_to("alice@example.org"));
+        let msg = alice1.recv_msg(&sent).await;
+        assert_eq!(msg.get_state(), MessageState::OutDelivered);
+        assert_eq!(msg.get_from_id(), ContactId::SELF);
+        assert!(alice1.should_send_bcc_self().await?);
+        let sent = alice1.send_text(chat.id, "Hi again").await;
+        assert!(sent.is_sent_to("alice@example.org"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_extra_folders() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        assert!(t.get_extra_folders().await?.is_empty());
+
+        t.set_extra_folder_watched("Lists", true).await?;
+        t.set_extra_folder_watched("INBOX/Sorted, by sieve", true)
+            .await?;
+        t.set_extra_folder_watched("Lists", true).await?;
+        assert_eq!(
+            t.get_extra_folders().await?,
+            vec!["INBOX/Sorted, by sieve", "Lists"]
+        );
+        assert!(t.set_extra_folder_watched("", true).await.is_err());
+        assert!(t.set_extra_folder_watched("a\nb", true).await.is_err());
+
+        let watched = crate::imap::scan_folders::get_watched_folders(&t).await?;
+        assert!(watched.contains(&"Lists".to_string()));
+
+        t.set_extra_folder_watched("INBOX/Sorted, by sieve", false)
+            .await?;
+        t.set_extra_folder_watched("Lists", false).await?;
+        assert!(t.get_extra_folders().await?.is_empty());
+        assert_eq!(t.get_config(Config::ExtraFolders).await?, None);
+        Ok(())
+    }
 }
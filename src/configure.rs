@@ -2,21 +2,27 @@
 //!
 //! The module provides automatic lookup of configuration
 //! for email providers based on the built-in [provider database],
-//! [Mozilla Thunderbird Autoconfiguration protocol]
-//! and [Outlook's Autodiscover].
+//! [Mozilla Thunderbird Autoconfiguration protocol],
+//! [Outlook's Autodiscover] and [DNS SRV records].
+//! If none of them succeeds, common server names and ports are guessed.
 //!
 //! [provider database]: crate::provider
 //! [Mozilla Thunderbird Autoconfiguration protocol]: auto_mozilla
 //! [Outlook's Autodiscover]: auto_outlook
+//! [DNS SRV records]: auto_srv
 
 mod auto_mozilla;
 mod auto_outlook;
+mod auto_srv;
 mod server_params;
 
+use std::fmt;
+
 use anyhow::{bail, ensure, Context as _, Result};
+use async_channel::Receiver;
 use auto_mozilla::moz_autoconfigure;
 use auto_outlook::outlk_autodiscover;
-use futures::FutureExt;
+use auto_srv::srv_autoconfigure;
 use futures_lite::FutureExt as _;
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use server_params::{expand_param_vector, ServerParams};
@@ -25,12 +31,15 @@
 use crate::config::Config;
 use crate::contact::addr_cmp;
 use crate::context::Context;
+use crate::events::EventType;
 use crate::imap::Imap;
 use crate::log::LogExt;
 use crate::login_param::{CertificateChecks, LoginParam, ServerLoginParam};
 use crate::message::{Message, Viewtype};
+use crate::net::ensure_online;
 use crate::oauth2::get_oauth2_addr;
-use crate::provider::{Protocol, Socket, UsernamePattern};
+use crate::provider::{Protocol, Provider, Socket, UsernamePattern};
+use crate::push::register_push_token;
 use crate::scheduler::InterruptInfo;
 use crate::smtp::Smtp;
 use crate::socks::Socks5Config;
@@ -40,6 +49,9 @@
 
 macro_rules! progress {
     ($context:tt, $progress:expr, $comment:expr) => {
+        progress!($context, $progress, $comment, None);
+    };
+    ($context:tt, $progress:expr, $comment:expr, $step:expr) => {
         assert!(
             $progress <= 1000,
             "value in range 0..1000 expected with: 0=error, 1..999=progress, 1000=success"
@@ -47,6 +59,7 @@ macro_rules! progress {
         $context.emit_event($crate::events::EventType::ConfigureProgress {
             progress: $progress,
             comment: $comment,
+            step: $step,
         });
     };
     ($context:tt, $progress:expr) => {
@@ -58,7 +71,7 @@ impl Context {
     /// Checks if the context is already configured.
     pub async fn is_configured(&self) -> Result<bool> {
         self.sql
-            .get_raw_config_bool("configured")
+            .get_raw_config_bool(Config::Configured.as_ref())
             .await
             .map_err(Into::into)
     }
@@ -73,19 +86,18 @@ pub async fn configure(&self) -> Result<()> {
             self.sql.is_open().await,
             "cannot configure, database not opened."
         );
+        ensure_online(self).await?;
         let cancel_channel = self.alloc_ongoing().await?;
 
-        let res = self
-            .inner_configure()
-            .race(cancel_channel.recv().map(|_| {
-                progress!(self, 0);
-                Ok(())
-            }))
-            .await;
+        let res = self.inner_configure(cancel_channel).await;
+        let cancelled = res.is_err() && self.shall_stop_ongoing().await;
 
         self.free_ongoing().await;
 
-        if let Err(err) = res.as_ref() {
+        if cancelled {
+            info!(self, "Configuration cancelled.");
+            progress!(self, 0);
+        } else if let Err(err) = res.as_ref() {
             progress!(
                 self,
                 0,
@@ -106,12 +118,16 @@ pub async fn configure(&self) -> Result<()> {
         res
     }
 
-    async fn inner_configure(&self) -> Result<()> {
+    async fn inner_configure(&self, cancel_channel: Receiver<()>) -> Result<()> {
         info!(self, "Configure ...");
 
         let mut param = LoginParam::load_candidate_params(self).await?;
         let old_addr = self.get_config(Config::ConfiguredAddr).await?;
-        let success = configure(self, &mut param).await;
+        let success = configure(self, &mut param, cancel_channel).await;
+        if success.is_err() && self.shall_stop_ongoing().await {
+            // Keep the previous configuration untouched.
+            return success;
+        }
         self.set_config(Config::NotifyAboutWrongPw, None).await?;
 
         on_configure_completed(self, param, old_addr).await?;
@@ -119,6 +135,188 @@ async fn inner_configure(&self) -> Result<()> {
         success?;
         self.set_config(Config::NotifyAboutWrongPw, Some("1"))
             .await?;
+        register_push_token(self)
+            .await
+            .context("Failed to register push token")
+            .log_err(self)
+            .ok();
+        Ok(())
+    }
+}
+
+/// Result of [`Context::check_login`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LoginCheck {
+    /// IMAP login error, `None` if IMAP login succeeded.
+    pub imap_error: Option<String>,
+
+    /// SMTP login error, `None` if SMTP login succeeded.
+    pub smtp_error: Option<String>,
+}
+
+impl LoginCheck {
+    /// Returns true if both IMAP and SMTP login succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.imap_error.is_none() && self.smtp_error.is_none()
+    }
+}
+
+impl Context {
+    /// Tests IMAP and SMTP login with the currently entered settings
+    /// without modifying the configuration.
+    ///
+    /// Unlike [`Context::configure`], no autoconfiguration is done,
+    /// only unset ports and security are guessed.
+    /// The returned [`LoginCheck`] contains an error for each failed transport.
+    pub async fn check_login(&self) -> Result<LoginCheck> {
+        let param = LoginParam::load_candidate_params(self).await?;
+        self.check_login_param(&param).await
+    }
+
+    /// Tests IMAP and SMTP login with the given parameters
+    /// without modifying the configuration.
+    pub(crate) async fn check_login_param(&self, param: &LoginParam) -> Result<LoginCheck> {
+        let parsed = EmailAddress::new(&param.addr).context("Bad email-address")?;
+        let servers = expand_param_vector(
+            vec![
+                ServerParams::from_login_param(Protocol::Imap, &param.imap),
+                ServerParams::from_login_param(Protocol::Smtp, &param.smtp),
+            ],
+            &param.addr,
+            &parsed.domain,
+        );
+        let provider_strict_tls = param
+            .provider
+            .map_or(param.socks5_config.is_some(), |provider| {
+                provider.opt.strict_tls
+            });
+
+        let check_imap = async {
+            let mut errors = Vec::new();
+            let mut imap_param = param.imap.clone();
+            for server in servers.iter().filter(|s| s.protocol == Protocol::Imap) {
+                server.apply_to(&mut imap_param);
+                match try_imap_one_param(
+                    self,
+                    &imap_param,
+                    &param.socks5_config,
+                    &param.addr,
+                    provider_strict_tls,
+                )
+                .await
+                {
+                    Ok(_) => return None,
+                    Err(err) => errors.push(err),
+                }
+            }
+            Some(nicer_configuration_error(self, param.provider, errors).await)
+        };
+        let check_smtp = async {
+            let mut errors = Vec::new();
+            let mut smtp_param = param.smtp.clone();
+            let mut smtp = Smtp::new();
+            for server in servers.iter().filter(|s| s.protocol == Protocol::Smtp) {
+                server.apply_to(&mut smtp_param);
+                match try_smtp_one_param(
+                    self,
+                    &smtp_param,
+                    &param.socks5_config,
+                    &param.addr,
+                    provider_strict_tls,
+                    &mut smtp,
+                )
+                .await
+                {
+                    Ok(()) => return None,
+                    Err(err) => errors.push(err),
+                }
+            }
+            Some(nicer_configuration_error(self, param.provider, errors).await)
+        };
+        let (imap_error, smtp_error) = tokio::join!(check_imap, check_smtp);
+        Ok(LoginCheck {
+            imap_error,
+            smtp_error,
+        })
+    }
+
+    /// Changes the password of the configured account.
+    ///
+    /// The new password is tested with the configured IMAP and SMTP servers first.
+    /// Only if both logins succeed, the entered and configured passwords
+    /// are replaced in a single transaction and the connections are restarted.
+    /// If the SMTP password differs from the IMAP password,
+    /// only the IMAP password is changed.
+    pub async fn change_password(&self, password: &str) -> Result<()> {
+        ensure!(self.is_configured().await?, "Account is not configured.");
+        ensure!(!password.is_empty(), "Missing password.");
+        let mut param = LoginParam::load_configured_params(self).await?;
+        ensure!(
+            !param.imap.oauth2,
+            "Cannot change password of an account using OAuth2."
+        );
+
+        let change_smtp = !param.smtp.oauth2 && param.smtp.password == param.imap.password;
+        param.imap.password = password.to_string();
+        if change_smtp {
+            param.smtp.password = password.to_string();
+        }
+        let check = self.check_login_param(&param).await?;
+        if let Some(err) = check.imap_error {
+            bail!("IMAP login with the new password failed: {err}");
+        }
+        if let Some(err) = check.smtp_error {
+            bail!("SMTP login with the new password failed: {err}");
+        }
+
+        let mut keys = vec![Config::MailPw, Config::ConfiguredMailPw];
+        if change_smtp {
+            keys.push(Config::ConfiguredSendPw);
+            // Empty SMTP password means that the IMAP password is used.
+            if !self
+                .get_config(Config::SendPw)
+                .await?
+                .unwrap_or_default()
+                .is_empty()
+            {
+                keys.push(Config::SendPw);
+            }
+        }
+        let mut lock = self.sql.config_cache.write().await;
+        self.sql
+            .transaction({
+                let keys = keys.clone();
+                let password = password.to_string();
+                move |transaction| {
+                    for key in keys {
+                        let key = key.as_ref();
+                        if transaction.execute(
+                            "UPDATE config SET value=? WHERE keyname=?",
+                            (&password, key),
+                        )? == 0
+                        {
+                            transaction.execute(
+                                "INSERT INTO config (keyname, value) VALUES (?, ?)",
+                                (key, &password),
+                            )?;
+                        }
+                    }
+                    Ok(())
+                }
+            })
+            .await?;
+        for key in &keys {
+            lock.insert(key.to_string(), Some(password.to_string()));
+        }
+        drop(lock);
+        info!(self, "Password changed.");
+        for key in keys.into_iter().filter(|key| !key.is_internal()) {
+            self.emit_event(EventType::ConfigChanged(key));
+        }
+
+        self.set_config(Config::NotifyAboutWrongPw, Some("1"))
+            .await?;
+        self.restart_io_if_running().await;
         Ok(())
     }
 }
@@ -173,20 +371,88 @@ async fn on_configure_completed(
     Ok(())
 }
 
-async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
+/// Configures the account.
+///
+/// Configuration can be cancelled using `cancel_channel`
+/// while the servers are probed.
+/// Once working servers are found, the configuration is saved without interruption
+/// so the previous configuration is not partially overwritten.
+async fn configure(
+    ctx: &Context,
+    param: &mut LoginParam,
+    cancel_channel: Receiver<()>,
+) -> Result<()> {
     progress!(ctx, 1);
 
-    let socks5_config = param.socks5_config.clone();
-    let socks5_enabled = socks5_config.is_some();
-
     let ctx2 = ctx.clone();
     let update_device_chats_handle = task::spawn(async move { ctx2.update_device_chats().await });
 
+    let mut imap = probe_servers(ctx, param)
+        .race(async {
+            cancel_channel.recv().await.ok();
+            bail!("Configuration cancelled")
+        })
+        .await?;
+
+    progress!(ctx, 900);
+
+    let create_mvbox = ctx.should_watch_mvbox().await?;
+
+    imap.configure_folders(ctx, create_mvbox).await?;
+
+    imap.select_with_uidvalidity(ctx, "INBOX")
+        .await
+        .context("could not read INBOX status")?;
+
+    drop(imap);
+
+    progress!(ctx, 910);
+
+    if let Some(configured_addr) = ctx.get_config(Config::ConfiguredAddr).await? {
+        if configured_addr != param.addr {
+            // Switched account, all server UIDs we know are invalid
+            info!(ctx, "Scheduling resync because the address has changed.");
+            ctx.schedule_resync().await?;
+        }
+    }
+
+    // the trailing underscore is correct
+    param.save_as_configured_params(ctx).await?;
+    ctx.set_config(Config::ConfiguredTimestamp, Some(&time().to_string()))
+        .await?;
+
+    progress!(ctx, 920);
+
+    e2ee::ensure_secret_key_exists(ctx).await?;
+    info!(ctx, "key generation completed");
+
+    ctx.set_config_bool(Config::FetchedExistingMsgs, false)
+        .await?;
+    ctx.scheduler
+        .interrupt_inbox(InterruptInfo::new(false))
+        .await;
+
+    progress!(ctx, 940);
+    update_device_chats_handle.await??;
+
+    ctx.set_config_bool(Config::Configured, true).await?;
+
+    Ok(())
+}
+
+/// Looks up server candidates and tries them until working IMAP and SMTP servers are found.
+///
+/// Returns connected IMAP client.
+/// Only the candidate login parameters are modified.
+async fn probe_servers(ctx: &Context, param: &mut LoginParam) -> Result<Imap> {
+    let socks5_config = param.socks5_config.clone();
+    let socks5_enabled = socks5_config.is_some();
+
     // Step 1: Load the parameters and check email-address and password
 
     // Do oauth2 only if socks5 is disabled. As soon as we have a http library that can do
-    // socks5 requests, this can work with socks5 too.  OAuth is always set either for both
-    // IMAP and SMTP or not at all.
+    // socks5 requests, this can work with socks5 too.  The authorized address is looked up
+    // using the IMAP code, SMTP may use a different authorization.
     if param.imap.oauth2 && !socks5_enabled {
         // the used oauth2 addr may differ, check this.
         // if get_oauth2_addr() is not available in the oauth2 implementation, just use the given one.
@@ -197,8 +463,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
         {
             info!(ctx, "Authorized address is {}", oauth2_addr);
             param.addr = oauth2_addr;
-            ctx.sql
-                .set_raw_config("addr", Some(param.addr.as_str()))
+            ctx.set_config(Config::Addr, Some(param.addr.as_str()))
                 .await?;
         }
         progress!(ctx, 20);
@@ -233,6 +498,13 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
             provider::get_provider_info(ctx, &param_domain, socks5_enabled).await
         {
             param.provider = Some(provider);
+            if provider.opt.pop_only {
+                bail!(
+                    "{} {}",
+                    stock_str::configure_pop_only(ctx, &param_domain).await,
+                    provider.before_login_hint
+                );
+            }
             match provider.status {
                 provider::Status::Ok | provider::Status::Preparation => {
                     if provider.server.is_empty() {
@@ -293,27 +565,13 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
         .iter()
         .any(|server| server.protocol == Protocol::Imap)
     {
-        servers.push(ServerParams {
-            protocol: Protocol::Imap,
-            hostname: param.imap.server.clone(),
-            port: param.imap.port,
-            socket: param.imap.security,
-            username: param.imap.user.clone(),
-            strict_tls: None,
-        })
+        servers.push(ServerParams::from_login_param(Protocol::Imap, &param.imap));
     }
     if !servers
         .iter()
         .any(|server| server.protocol == Protocol::Smtp)
     {
-        servers.push(ServerParams {
-            protocol: Protocol::Smtp,
-            hostname: param.smtp.server.clone(),
-            port: param.smtp.port,
-            socket: param.smtp.security,
-            username: param.smtp.user.clone(),
-            strict_tls: None,
-        })
+        servers.push(ServerParams::from_login_param(Protocol::Smtp, &param.smtp));
     }
 
     // respect certificate setting from function parameters
@@ -353,15 +611,14 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
         let mut smtp_configured = false;
         let mut errors = Vec::new();
         for smtp_server in smtp_servers {
-            smtp_param.user = smtp_server.username.clone();
-            smtp_param.server = smtp_server.hostname.clone();
-            smtp_param.port = smtp_server.port;
-            smtp_param.security = smtp_server.socket;
-            smtp_param.certificate_checks = match smtp_server.strict_tls {
-                Some(true) => CertificateChecks::Strict,
-                Some(false) => CertificateChecks::AcceptInvalidCertificates,
-                None => CertificateChecks::Automatic,
-            };
+            if context_smtp.shall_stop_ongoing().await {
+                info!(
+                    context_smtp,
+                    "Stopping SMTP probing, configuration cancelled."
+                );
+                break;
+            }
+            smtp_server.apply_to(&mut smtp_param);
 
             match try_smtp_one_param(
                 &context_smtp,
@@ -400,15 +657,10 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
     let imap_servers_count = imap_servers.len();
     let mut errors = Vec::new();
     for (imap_server_index, imap_server) in imap_servers.into_iter().enumerate() {
-        param.imap.user = imap_server.username.clone();
-        param.imap.server = imap_server.hostname.clone();
-        param.imap.port = imap_server.port;
-        param.imap.security = imap_server.socket;
-        param.imap.certificate_checks = match imap_server.strict_tls {
-            Some(true) => CertificateChecks::Strict,
-            Some(false) => CertificateChecks::AcceptInvalidCertificates,
-            None => CertificateChecks::Automatic,
-        };
+        if ctx.shall_stop_ongoing().await {
+            bail!("Configuration cancelled");
+        }
+        imap_server.apply_to(&mut param.imap);
 
         match try_imap_one_param(
             ctx,
@@ -432,7 +684,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
     }
     let mut imap = match imap {
         Some(imap) => imap,
-        None => bail!(nicer_configuration_error(ctx, errors).await),
+        None => bail!(nicer_configuration_error(ctx, param.provider, errors).await),
     };
 
     progress!(ctx, 850);
@@ -443,127 +695,157 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
             param.smtp = smtp_param;
         }
         Err(errors) => {
-            bail!(nicer_configuration_error(ctx, errors).await);
+            bail!(nicer_configuration_error(ctx, param.provider, errors).await);
         }
     }
 
-    progress!(ctx, 900);
+    Ok(imap)
+}
 
-    let create_mvbox = ctx.should_watch_mvbox().await?;
+/// Step of the online autoconfiguration,
+/// reported in [`EventType::ConfigureProgress`](crate::EventType::ConfigureProgress).
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigureStep {
+    /// Fetching Thunderbird autoconfig XML from the domain of the email address.
+    Autoconfig,
 
-    imap.configure_folders(ctx, create_mvbox).await?;
+    /// Fetching Outlook's Autodiscover XML.
+    Autodiscover,
 
-    imap.select_with_uidvalidity(ctx, "INBOX")
-        .await
-        .context("could not read INBOX status")?;
+    /// Looking up DNS SRV records of the domain.
+    Srv,
 
-    drop(imap);
+    /// Fetching the configuration from Thunderbird's central ISP database.
+    Ispdb,
+}
 
-    progress!(ctx, 910);
+/// Source of server configuration queried during online autoconfiguration.
+#[derive(Debug)]
+enum AutoconfigSource {
+    /// Mozilla Thunderbird Autoconfiguration XML at the given URL.
+    Mozilla(String),
 
-    if let Some(configured_addr) = ctx.get_config(Config::ConfiguredAddr).await? {
-        if configured_addr != param.addr {
-            // Switched account, all server UIDs we know are invalid
-            info!(ctx, "Scheduling resync because the address has changed.");
-            ctx.schedule_resync().await?;
-        }
-    }
+    /// Outlook's Autodiscover XML at the given URL.
+    Outlook(String),
 
-    // the trailing underscore is correct
-    param.save_as_configured_params(ctx).await?;
-    ctx.set_config(Config::ConfiguredTimestamp, Some(&time().to_string()))
-        .await?;
+    /// DNS SRV records of the domain.
+    Srv,
 
-    progress!(ctx, 920);
+    /// Thunderbird's ISP database at the given URL.
+    Ispdb(String),
+}
 
-    e2ee::ensure_secret_key_exists(ctx).await?;
-    info!(ctx, "key generation completed");
+impl fmt::Display for AutoconfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mozilla(url) => write!(f, "autoconfig at {url}"),
+            Self::Outlook(url) => write!(f, "Autodiscover at {url}"),
+            Self::Srv => write!(f, "DNS SRV records"),
+            Self::Ispdb(url) => write!(f, "ISP database at {url}"),
+        }
+    }
+}
 
-    ctx.set_config_bool(Config::FetchedExistingMsgs, false)
-        .await?;
-    ctx.scheduler
-        .interrupt_inbox(InterruptInfo::new(false))
-        .await;
+impl AutoconfigSource {
+    /// Returns all sources in the order they are tried.
+    ///
+    /// A. Search configurations from the domain used in the email-address, prefer encrypted.
+    /// B. If we have no configuration yet, search configuration in Thunderbird's central database.
+    fn all(param_domain: &str, param_addr_urlencoded: &str) -> Vec<Self> {
+        vec![
+            Self::Mozilla(format!(
+                "https://autoconfig.{param_domain}/mail/config-v1.1.xml?emailaddress={param_addr_urlencoded}"
+            )),
+            // the doc does not mention `emailaddress=`, however, Thunderbird adds it, see <https://releases.mozilla.org/pub/thunderbird/>,  which makes some sense
+            Self::Mozilla(format!(
+                "https://{param_domain}/.well-known/autoconfig/mail/config-v1.1.xml?emailaddress={param_addr_urlencoded}"
+            )),
+            // Outlook uses always SSL but different domains
+            Self::Outlook(format!(
+                "https://{param_domain}/autodiscover/autodiscover.xml"
+            )),
+            Self::Outlook(format!(
+                "https://autodiscover.{param_domain}/autodiscover/autodiscover.xml"
+            )),
+            Self::Srv,
+            // always SSL for Thunderbird's database
+            Self::Ispdb(format!(
+                "https://autoconfig.thunderbird.net/v1.1/{param_domain}"
+            )),
+        ]
+    }
 
-    progress!(ctx, 940);
-    update_device_chats_handle.await??;
+    async fn fetch(
+        &self,
+        ctx: &Context,
+        param: &LoginParam,
+        param_domain: &str,
+    ) -> Result<Vec<ServerParams>, Error> {
+        match self {
+            Self::Mozilla(url) | Self::Ispdb(url) => moz_autoconfigure(ctx, url, param).await,
+            Self::Outlook(url) => outlk_autodiscover(ctx, url.to_string()).await,
+            Self::Srv => srv_autoconfigure(ctx, param_domain).await,
+        }
+    }
 
-    ctx.sql.set_raw_config_bool("configured", true).await?;
+    fn step(&self) -> ConfigureStep {
+        match self {
+            Self::Mozilla(_) => ConfigureStep::Autoconfig,
+            Self::Outlook(_) => ConfigureStep::Autodiscover,
+            Self::Srv => ConfigureStep::Srv,
+            Self::Ispdb(_) => ConfigureStep::Ispdb,
+        }
+    }
 
-    Ok(())
+    /// Returns the progress comment shown while the source is queried.
+    async fn comment(&self, ctx: &Context, param_domain: &str) -> String {
+        match self {
+            Self::Mozilla(url) | Self::Outlook(url) | Self::Ispdb(url) => {
+                stock_str::configure_lookup(ctx, url).await
+            }
+            Self::Srv => stock_str::configure_srv_lookup(ctx, param_domain).await,
+        }
+    }
 }
 
 /// Retrieve available autoconfigurations.
 ///
-/// A Search configurations from the domain used in the email-address, prefer encrypted
-/// B. If we have no configuration yet, search configuration in Thunderbird's centeral database
+/// The sources returned by [`AutoconfigSource::all`] are tried one after another
+/// until one of them returns a configuration.
+/// Progress events tell which source is tried and why it failed.
 async fn get_autoconfig(
     ctx: &Context,
     param: &LoginParam,
     param_domain: &str,
     param_addr_urlencoded: &str,
 ) -> Option<Vec<ServerParams>> {
-    if let Ok(res) = moz_autoconfigure(
-        ctx,
-        &format!(
-            "https://autoconfig.{param_domain}/mail/config-v1.1.xml?emailaddress={param_addr_urlencoded}"
-        ),
-        param,
-    )
-    .await
-    {
-        return Some(res);
-    }
-    progress!(ctx, 300);
-
-    if let Ok(res) = moz_autoconfigure(
-        ctx,
-        // the doc does not mention `emailaddress=`, however, Thunderbird adds it, see <https://releases.mozilla.org/pub/thunderbird/>,  which makes some sense
-        &format!(
-            "https://{}/.well-known/autoconfig/mail/config-v1.1.xml?emailaddress={}",
-            &param_domain, &param_addr_urlencoded
-        ),
-        param,
-    )
-    .await
-    {
-        return Some(res);
-    }
-    progress!(ctx, 310);
-
-    // Outlook uses always SSL but different domains (this comment describes the next two steps)
-    if let Ok(res) = outlk_autodiscover(
-        ctx,
-        format!("https://{}/autodiscover/autodiscover.xml", &param_domain),
-    )
-    .await
+    for (i, source) in AutoconfigSource::all(param_domain, param_addr_urlencoded)
+        .into_iter()
+        .enumerate()
     {
-        return Some(res);
-    }
-    progress!(ctx, 320);
-
-    if let Ok(res) = outlk_autodiscover(
-        ctx,
-        format!(
-            "https://autodiscover.{}/autodiscover/autodiscover.xml",
-            &param_domain
-        ),
-    )
-    .await
-    {
-        return Some(res);
-    }
-    progress!(ctx, 330);
-
-    // always SSL for Thunderbird's database
-    if let Ok(res) = moz_autoconfigure(
-        ctx,
-        &format!("https://autoconfig.thunderbird.net/v1.1/{}", &param_domain),
-        param,
-    )
-    .await
-    {
-        return Some(res);
+        let progress = 300 + 10 * i;
+        let step = Some(source.step());
+        progress!(
+            ctx,
+            progress,
+            Some(source.comment(ctx, param_domain).await),
+            step
+        );
+        match source.fetch(ctx, param, param_domain).await {
+            Ok(res) => {
+                info!(ctx, "Using configuration from {source}.");
+                return Some(res);
+            }
+            Err(err) => {
+                info!(ctx, "Failed to get configuration from {source}: {err:#}");
+                progress!(
+                    ctx,
+                    progress + 5,
+                    Some(stock_str::configure_lookup_failed(ctx, &format!("{err:#}")).await),
+                    step
+                );
+            }
+        }
     }
 
     None
@@ -671,7 +953,11 @@ pub struct ConfigurationError {
     msg: String,
 }
 
-async fn nicer_configuration_error(context: &Context, errors: Vec<ConfigurationError>) -> String {
+async fn nicer_configuration_error(
+    context: &Context,
+    provider: Option<&Provider>,
+    errors: Vec<ConfigurationError>,
+) -> String {
     let first_err = if let Some(f) = errors.first() {
         f
     } else {
@@ -694,15 +980,22 @@ async fn nicer_configuration_error(context: &Context, errors: Vec<ConfigurationE
         return stock_str::error_no_network(context).await;
     }
 
-    if errors.iter().all(|e| e.msg == first_err.msg) {
-        return first_err.msg.to_string();
-    }
+    let msg = if errors.iter().all(|e| e.msg == first_err.msg) {
+        first_err.msg.to_string()
+    } else {
+        errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    };
 
-    errors
-        .iter()
-        .map(|e| e.to_string())
-        .collect::<Vec<String>>()
-        .join("\n\n")
+    match provider {
+        Some(provider) if provider.opt.app_password_required => {
+            format!("{msg}\n\n{}", provider.before_login_hint)
+        }
+        _ => msg,
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -728,7 +1021,13 @@ pub enum Error {
 mod tests {
     #![allow(clippy::indexing_slicing)]
 
+    use std::time::Duration;
+
+    use anyhow::Result;
+
     use crate::config::Config;
+    use crate::provider::Socket;
+    use crate::test_utils::imap_server::ImapServer;
     use crate::test_utils::TestContext;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -740,4 +1039,94 @@ async fn test_no_panic_on_bad_credentials() {
         t.set_config(Config::MailPw, Some("123456")).await.unwrap();
         assert!(t.configure().await.is_err());
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_check_login() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::MailServer, Some("imap.unexistant.addr"))
+            .await?;
+        t.set_config(Config::SendServer, Some("smtp.unexistant.addr"))
+            .await?;
+        t.set_config(Config::MailPw, Some("123456")).await?;
+        let configured_addr = t.get_config(Config::ConfiguredAddr).await?;
+
+        let check = t.check_login().await?;
+        assert!(!check.is_ok());
+        assert!(check.imap_error.is_some());
+        assert!(check.smtp_error.is_some());
+        assert!(t.is_configured().await?);
+        assert_eq!(t.get_config(Config::ConfiguredAddr).await?, configured_addr);
+        assert_eq!(t.get_config(Config::ConfiguredMailServer).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_change_password_revalidates() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::ConfiguredMailServer, Some("imap.unexistant.addr"))
+            .await?;
+        t.set_config(Config::ConfiguredSendServer, Some("smtp.unexistant.addr"))
+            .await?;
+        t.set_config(Config::MailPw, Some("old")).await?;
+        t.set_config(Config::ConfiguredMailPw, Some("old")).await?;
+        t.set_config(Config::ConfiguredSendPw, Some("old")).await?;
+
+        assert!(t.change_password("").await.is_err());
+        assert!(t.change_password("new").await.is_err());
+        assert_eq!(t.get_config(Config::MailPw).await?.unwrap(), "old");
+        assert_eq!(
+            t.get_config(Config::ConfiguredMailPw).await?.unwrap(),
+            "old"
+        );
+        assert_eq!(
+            t.get_config(Config::ConfiguredSendPw).await?.unwrap(),
+            "old"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_cancel_configure() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let configured_server = t.get_config(Config::ConfiguredMailServer).await?;
+
+        // The server delays its responses, so the login hangs until configuration is cancelled.
+        let server = ImapServer::new().await?;
+        server.set_latency(Duration::from_secs(600));
+        t.set_config(Config::MailServer, Some("127.0.0.1")).await?;
+        t.set_config(Config::MailPort, Some(&server.port().to_string()))
+            .await?;
+        t.set_config(
+            Config::MailSecurity,
+            Some(&(Socket::Plain as i32).to_string()),
+        )
+        .await?;
+        t.set_config(Config::MailPw, Some("wrong")).await?;
+        // Nothing listens on port 1, so SMTP probing fails without network access.
+        t.set_config(Config::SendServer, Some("127.0.0.1")).await?;
+        t.set_config(Config::SendPort, Some("1")).await?;
+        let configure = tokio::spawn({
+            let ctx = t.ctx.clone();
+            async move { ctx.configure().await }
+        });
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while server.connections() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        t.stop_ongoing().await;
+
+        let res = tokio::time::timeout(Duration::from_secs(10), configure).await??;
+        assert!(res.is_err());
+        assert!(t.is_configured().await?);
+        assert_eq!(
+            t.get_config(Config::ConfiguredMailServer).await?,
+            configured_server
+        );
+
+        // Another process can be started after cancellation.
+        let _cancel_channel = t.alloc_ongoing().await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,187 @@
+//! # DNS SRV records
+//!
+//! This module implements autoconfiguration using DNS SRV records
+//! for locating email submission and access services, see [RFC 6186].
+//!
+//! [RFC 6186]: https://www.rfc-editor.org/rfc/rfc6186
+
+use anyhow::Context as _;
+
+use super::{Error, ServerParams};
+use crate::context::Context;
+use crate::provider::{get_resolver, Protocol, Socket};
+
+/// Services looked up, in the order of preference for each protocol.
+const SERVICES: &[(&str, Protocol, Socket)] = &[
+    ("_imaps._tcp", Protocol::Imap, Socket::Ssl),
+    ("_imap._tcp", Protocol::Imap, Socket::Starttls),
+    ("_submissions._tcp", Protocol::Smtp, Socket::Ssl),
+    ("_submission._tcp", Protocol::Smtp, Socket::Starttls),
+];
+
+/// Single SRV record.
+#[derive(Debug)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    /// Target hostname without the trailing dot, empty if the service is not available.
+    target: String,
+}
+
+/// Returns true if `hostname` is `domain` or a subdomain of it.
+fn is_in_domain(hostname: &str, domain: &str) -> bool {
+    let hostname = hostname.to_lowercase();
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    hostname == domain || hostname.ends_with(&format!(".{domain}"))
+}
+
+/// Converts SRV records of a service into server parameters.
+///
+/// Records with lower priority and then higher weight come first.
+/// A record with the target "." means that the service is not available.
+///
+/// Unsigned DNS records may be forged, so as recommended by [RFC 6186 section 6],
+/// strict TLS checks are enforced for targets which are not in `domain`,
+/// then the certificate must be valid for the target.
+///
+/// [RFC 6186 section 6]: https://www.rfc-editor.org/rfc/rfc6186#section-6
+fn records_to_server_params(
+    domain: &str,
+    protocol: Protocol,
+    socket: Socket,
+    mut records: Vec<SrvRecord>,
+) -> Vec<ServerParams> {
+    records.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| b.weight.cmp(&a.weight))
+    });
+    records
+        .into_iter()
+        .filter(|record| !record.target.is_empty() && record.port != 0)
+        .map(|record| ServerParams {
+            protocol,
+            strict_tls: if is_in_domain(&record.target, domain) {
+                None
+            } else {
+                Some(true)
+            },
+            hostname: record.target,
+            port: record.port,
+            socket,
+            username: String::new(),
+        })
+        .collect()
+}
+
+/// Looks up SRV records of a single service.
+async fn lookup_service(name: &str) -> anyhow::Result<Vec<SrvRecord>> {
+    let lookup = get_resolver()?
+        .srv_lookup(name)
+        .await
+        .with_context(|| format!("failed to look up {name}"))?;
+    let records = lookup
+        .iter()
+        .map(|srv| SrvRecord {
+            priority: srv.priority(),
+            weight: srv.weight(),
+            port: srv.port(),
+            target: srv.target().to_utf8().trim_end_matches('.').to_string(),
+        })
+        .collect();
+    Ok(records)
+}
+
+/// Looks up IMAP and SMTP servers in DNS SRV records of the domain.
+///
+/// Returns an error unless both IMAP and SMTP servers are found.
+pub(crate) async fn srv_autoconfigure(
+    context: &Context,
+    domain: &str,
+) -> Result<Vec<ServerParams>, Error> {
+    let mut servers = Vec::new();
+    for (service, protocol, socket) in SERVICES {
+        let name = format!("{service}.{domain}.");
+        match lookup_service(&name).await {
+            Ok(records) => {
+                servers.extend(records_to_server_params(
+                    domain, *protocol, *socket, records,
+                ));
+            }
+            Err(err) => info!(context, "No SRV records: {err:#}."),
+        }
+    }
+
+    for protocol in [Protocol::Imap, Protocol::Smtp] {
+        if !servers.iter().any(|server| server.protocol == protocol) {
+            return Err(Error::Other(anyhow::anyhow!(
+                "No {protocol} server in SRV records of {domain}"
+            )));
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_to_server_params() {
+        let record = |priority, weight, port, target: &str| SrvRecord {
+            priority,
+            weight,
+            port,
+            target: target.to_string(),
+        };
+        let servers = records_to_server_params(
+            "example.org",
+            Protocol::Imap,
+            Socket::Ssl,
+            vec![
+                record(10, 0, 993, "backup.example.org"),
+                record(0, 1, 993, "imap2.example.org"),
+                record(0, 5, 993, "imap.example.org"),
+                record(0, 0, 0, ""),
+            ],
+        );
+        let hostnames: Vec<&str> = servers.iter().map(|s| s.hostname.as_str()).collect();
+        assert_eq!(
+            hostnames,
+            [
+                "imap.example.org",
+                "imap2.example.org",
+                "backup.example.org"
+            ]
+        );
+        assert!(servers.iter().all(|s| s.port == 993
+            && s.socket == Socket::Ssl
+            && s.protocol == Protocol::Imap
+            && s.username.is_empty()
+            && s.strict_tls.is_none()));
+
+        // "." as the target means that the service is not available.
+        let servers = records_to_server_params(
+            "example.org",
+            Protocol::Smtp,
+            Socket::Starttls,
+            vec![record(0, 0, 0, "")],
+        );
+        assert!(servers.is_empty());
+
+        // Targets outside of the domain require strict TLS checks.
+        let servers = records_to_server_params(
+            "example.org",
+            Protocol::Smtp,
+            Socket::Ssl,
+            vec![
+                record(0, 0, 465, "mail.example.org"),
+                record(0, 0, 465, "example.org.attacker.net"),
+                record(0, 0, 465, "badexample.org"),
+            ],
+        );
+        let strict_tls: Vec<Option<bool>> = servers.iter().map(|s| s.strict_tls).collect();
+        assert_eq!(strict_tls, [None, Some(true), Some(true)]);
+    }
+}
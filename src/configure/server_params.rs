@@ -1,5 +1,6 @@
 //! Variable server parameters lists
 
+use crate::login_param::{CertificateChecks, ServerLoginParam};
 use crate::provider::{Protocol, Socket};
 
 /// Set of variable parameters to try during configuration.
@@ -28,6 +29,36 @@ pub(crate) struct ServerParams {
 }
 
 impl ServerParams {
+    /// Creates server parameters from user entered parameters.
+    pub(crate) fn from_login_param(protocol: Protocol, param: &ServerLoginParam) -> Self {
+        Self {
+            protocol,
+            hostname: param.server.clone(),
+            port: param.port,
+            socket: param.security,
+            username: param.user.clone(),
+            strict_tls: match param.certificate_checks {
+                CertificateChecks::AcceptInvalidCertificates
+                | CertificateChecks::AcceptInvalidCertificates2 => Some(false),
+                CertificateChecks::Strict => Some(true),
+                CertificateChecks::Automatic => None,
+            },
+        }
+    }
+
+    /// Sets server, port, security, username and certificate checks of `param`.
+    pub(crate) fn apply_to(&self, param: &mut ServerLoginParam) {
+        param.user = self.username.clone();
+        param.server = self.hostname.clone();
+        param.port = self.port;
+        param.security = self.socket;
+        param.certificate_checks = match self.strict_tls {
+            Some(true) => CertificateChecks::Strict,
+            Some(false) => CertificateChecks::AcceptInvalidCertificates,
+            None => CertificateChecks::Automatic,
+        };
+    }
+
     fn expand_usernames(self, addr: &str) -> Vec<ServerParams> {
         let mut res = Vec::new();
 
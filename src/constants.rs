@@ -7,6 +7,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::chat::ChatId;
+use crate::message::MsgId;
 
 pub static DC_VERSION_STR: Lazy<String> = Lazy::new(|| env!("CARGO_PKG_VERSION").to_string());
 
@@ -42,6 +43,10 @@ pub enum ShowEmails {
     AcceptedContacts = 1,
     #[default] // also change Config.ShowEmails props(default) on changes
     All = 2,
+
+    /// Only messages with the `Chat-Version` header,
+    /// not even replies of classic email clients to chat messages.
+    ChatMessagesOnly = 3,
 }
 
 #[derive(
@@ -54,6 +59,62 @@ pub enum MediaQuality {
     Worse = 1,
 }
 
+/// When to send a copy of outgoing messages to self, see `Config::BccSelf`.
+#[derive(
+    Debug, Default, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql,
+)]
+#[repr(u8)]
+pub enum BccSelf {
+    /// Never send a copy.
+    Never = 0,
+
+    /// Always send a copy.
+    #[default] // also change Config.BccSelf props(default) on changes
+    Always = 1,
+
+    /// Send a copy only if other devices using the account are known,
+    /// e.g. because a backup was imported, a message sent by another device was received
+    /// or the `DeltaChat` folder already contained messages when configuring.
+    ///
+    /// A device configured later only becomes known once it sends a message,
+    /// so messages sent before are not copied to it.
+    MultiDevice = 2,
+}
+
+/// Whether to load remote content of HTML messages, see `Config::RemoteContent`.
+#[derive(
+    Debug, Default, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql,
+)]
+#[repr(u8)]
+pub enum RemoteContent {
+    /// Never load remote content.
+    Never = 0,
+
+    /// Load remote content only if the user agrees.
+    #[default] // also change Config.RemoteContent props(default) on changes
+    Ask = 1,
+
+    /// Always load remote content.
+    Always = 2,
+}
+
+/// What notifications about incoming messages show, see `Config::NotifyPrivacy`.
+#[derive(
+    Debug, Default, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql,
+)]
+#[repr(u8)]
+pub enum NotifyPrivacy {
+    /// Show the chat name, the sender and the message summary.
+    #[default] // also change Config.NotifyPrivacy props(default) on changes
+    ShowAll = 0,
+
+    /// Show only the chat name.
+    HideContent = 1,
+
+    /// Show neither the chat nor the message, only that a message arrived.
+    HideAll = 2,
+}
+
 /// Type of the key to generate.
 #[derive(
     Debug, Default, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql,
@@ -100,6 +161,7 @@ pub enum VideochatType {
 pub const DC_GCL_NO_SPECIALS: usize = 0x02;
 pub const DC_GCL_ADD_ALLDONE_HINT: usize = 0x04;
 pub const DC_GCL_FOR_FORWARDING: usize = 0x08;
+pub const DC_GCL_SPAM_ONLY: usize = 0x10;
 
 pub const DC_GCL_VERIFIED_ONLY: u32 = 0x01;
 pub const DC_GCL_ADD_SELF: u32 = 0x02;
@@ -114,13 +176,13 @@ pub enum VideochatType {
 pub(crate) const DC_OUTDATED_WARNING_DAYS: i64 = 365;
 
 /// messages that should be deleted get this chat_id; the messages are deleted from the working thread later then. This is also needed as rfc724_mid should be preset as long as the message is not deleted on the server (otherwise it is downloaded again)
-pub const DC_CHAT_ID_TRASH: ChatId = ChatId::new(3);
+pub const DC_CHAT_ID_TRASH: ChatId = ChatId::TRASH;
 /// only an indicator in a chatlist
-pub const DC_CHAT_ID_ARCHIVED_LINK: ChatId = ChatId::new(6);
+pub const DC_CHAT_ID_ARCHIVED_LINK: ChatId = ChatId::ARCHIVED_LINK;
 /// only an indicator in a chatlist
-pub const DC_CHAT_ID_ALLDONE_HINT: ChatId = ChatId::new(7);
+pub const DC_CHAT_ID_ALLDONE_HINT: ChatId = ChatId::ALLDONE_HINT;
 /// larger chat IDs are "real" chats, their messages are "real" messages.
-pub const DC_CHAT_ID_LAST_SPECIAL: ChatId = ChatId::new(9);
+pub const DC_CHAT_ID_LAST_SPECIAL: ChatId = ChatId::LAST_SPECIAL;
 
 /// Chat type.
 #[derive(
@@ -153,8 +215,10 @@ pub enum Chattype {
     Broadcast = 160,
 }
 
-pub const DC_MSG_ID_DAYMARKER: u32 = 9;
-pub const DC_MSG_ID_LAST_SPECIAL: u32 = 9;
+/// Raw value of [`MsgId::DAYMARKER`] for the FFI.
+pub const DC_MSG_ID_DAYMARKER: u32 = MsgId::DAYMARKER.to_u32();
+/// Raw value of [`MsgId::LAST_SPECIAL`] for the FFI.
+pub const DC_MSG_ID_LAST_SPECIAL: u32 = MsgId::LAST_SPECIAL.to_u32();
 
 /// String that indicates that something is left out or truncated.
 pub(crate) const DC_ELLIPSIS: &str = "[...]";
@@ -202,6 +266,24 @@ pub enum Chattype {
 pub(crate) const BALANCED_AVATAR_SIZE: u32 = 256;
 pub(crate) const WORSE_AVATAR_SIZE: u32 = 128;
 
+/// Maximum size of avatars sent in the `Chat-User-Avatar` header.
+///
+/// Outlook servers don't allow headers larger than 32k.
+/// 32 / 4 * 3 = 24k if you account for base64 encoding. To be safe, we reduced this to 20k.
+pub(crate) const MAX_AVATAR_BYTES: usize = 20_000;
+
+/// Maximum decoded size of received avatars; larger ones are ignored.
+pub(crate) const MAX_RECEIVED_AVATAR_BYTES: usize = 1_000_000;
+
+/// Maximum size of the settings stored for UIs by [`Config::UiSettings`]
+/// and [`ChatId::set_ui_settings`].
+///
+/// The settings are sent to other devices in sync messages, so they should stay small.
+///
+/// [`Config::UiSettings`]: crate::config::Config::UiSettings
+/// [`ChatId::set_ui_settings`]: crate::chat::ChatId::set_ui_settings
+pub const MAX_UI_SETTINGS_BYTES: usize = 4096;
+
 // max. width/height of images scaled down because of being too huge
 pub const BALANCED_IMAGE_SIZE: u32 = 1280;
 pub const WORSE_IMAGE_SIZE: u32 = 640;
@@ -244,6 +326,10 @@ fn test_showemails_values() {
             ShowEmails::from_i32(1).unwrap()
         );
         assert_eq!(ShowEmails::All, ShowEmails::from_i32(2).unwrap());
+        assert_eq!(
+            ShowEmails::ChatMessagesOnly,
+            ShowEmails::from_i32(3).unwrap()
+        );
     }
 
     #[test]
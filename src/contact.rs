@@ -1,7 +1,7 @@
 //! Contacts module
 
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::ops::Deref;
@@ -19,13 +19,14 @@
 use tokio::time::{timeout, Duration};
 
 use crate::aheader::EncryptPreference;
+use crate::avatar::fallback_avatar_svg;
 use crate::chat::ChatId;
 use crate::color::str_to_color;
 use crate::config::Config;
 use crate::constants::{Blocked, Chattype, DC_GCL_ADD_SELF, DC_GCL_VERIFIED_ONLY};
 use crate::context::Context;
 use crate::events::EventType;
-use crate::key::{load_self_public_key, DcKey};
+use crate::key::{load_self_public_key, DcKey, Fingerprint};
 use crate::login_param::LoginParam;
 use crate::message::MessageState;
 use crate::mimeparser::AvatarAction;
@@ -36,7 +37,7 @@
     duration_to_str, get_abs_path, improve_single_line_input, strip_rtlo_characters, time,
     EmailAddress,
 };
-use crate::{chat, stock_str};
+use crate::{chat, securejoin, stock_str};
 
 /// Time during which a contact is considered as seen recently.
 const SEEN_RECENTLY_SECONDS: i64 = 600;
@@ -148,6 +149,59 @@ pub(crate) async fn mark_bot(&self, context: &Context, is_bot: bool) -> Result<(
             .await?;
         Ok(())
     }
+
+    /// Sets the last error sending a message to the contact, see [`Contact::get_last_error`].
+    ///
+    /// An empty `error` clears the error.
+    pub(crate) async fn set_last_error(self, context: &Context, error: &str) -> Result<()> {
+        let changed = context
+            .sql
+            .execute(
+                "UPDATE contacts SET last_error=? WHERE id=? AND last_error!=?",
+                (error, self, error),
+            )
+            .await?;
+        if changed > 0 {
+            context.emit_event(EventType::ContactsChanged(Some(self)));
+        }
+        Ok(())
+    }
+
+    /// Returns whether remote content of HTML messages from the contact is loaded,
+    /// see [`ContactId::set_remote_content_decision`].
+    pub async fn get_remote_content_decision(
+        self,
+        context: &Context,
+    ) -> Result<RemoteContentDecision> {
+        let decision = context
+            .sql
+            .query_get_value("SELECT remote_content FROM contacts WHERE id=?", (self,))
+            .await?
+            .unwrap_or_default();
+        Ok(decision)
+    }
+
+    /// Sets whether remote content of HTML messages from the contact is loaded
+    /// by `MsgId::get_sanitized_html()`.
+    pub async fn set_remote_content_decision(
+        self,
+        context: &Context,
+        decision: RemoteContentDecision,
+    ) -> Result<()> {
+        ensure!(
+            !self.is_special(),
+            "Cannot set remote content decision for {self}"
+        );
+        context
+            .sql
+            .execute(
+                "UPDATE contacts SET remote_content=? WHERE id=?",
+                (decision, self),
+            )
+            .await?;
+        context.emit_event(EventType::ContactsChanged(Some(self)));
+        Ok(())
+    }
 }
 
 impl fmt::Display for ContactId {
@@ -188,6 +242,22 @@ fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlRe
     }
 }
 
+/// Converts a raw contact ID, e.g. passed over the FFI, into a [`ContactId`].
+///
+/// The ID may be a special one, check it with [`ContactId::is_special`] where needed.
+impl From<u32> for ContactId {
+    fn from(id: u32) -> Self {
+        ContactId::new(id)
+    }
+}
+
+/// Converts a [`ContactId`] into its raw value, e.g. to pass it over the FFI.
+impl From<ContactId> for u32 {
+    fn from(contact_id: ContactId) -> Self {
+        contact_id.0
+    }
+}
+
 /// An object representing a single contact in memory.
 ///
 /// The contact object is not updated.
@@ -235,6 +305,9 @@ pub struct Contact {
 
     /// If the contact is a bot.
     is_bot: bool,
+
+    /// Last error sending a message to the contact, empty if there is none.
+    last_error: String,
 }
 
 /// Possible origins of a contact.
@@ -324,6 +397,68 @@ pub fn is_known(self) -> bool {
     }
 }
 
+/// Recent activity of a contact, see [`Contact::get_status_summary`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContactStatusSummary {
+    /// Time of the last message from the contact, Unix time in seconds, 0 if never seen.
+    pub last_seen: i64,
+
+    /// IMAP folder the last incoming message from the contact arrived in, if known.
+    pub last_seen_folder: Option<String>,
+
+    /// Time of the last encrypted message from the contact, 0 if there was none.
+    pub last_encrypted: i64,
+
+    /// Time of the last unencrypted message from the contact, 0 if there was none.
+    pub last_unencrypted: i64,
+
+    /// Time of the last message signed with the verified key of the contact,
+    /// 0 if there was none.
+    pub last_verified: i64,
+}
+
+impl ContactStatusSummary {
+    /// Returns true if the last message from the contact was encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.last_encrypted > 0 && self.last_encrypted >= self.last_unencrypted
+    }
+
+    /// Returns true if the last message from the contact was signed with their verified key.
+    pub fn is_verified(&self) -> bool {
+        self.is_encrypted() && self.last_verified >= self.last_encrypted
+    }
+}
+
+/// Decision of the user whether to load remote content, e.g. images,
+/// of HTML messages from a contact.
+///
+/// Loading remote content once is not stored,
+/// see `MsgId::get_sanitized_html()`.
+#[derive(
+    Debug, Default, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql,
+)]
+#[repr(u32)]
+pub enum RemoteContentDecision {
+    /// No decision yet, [`Config::RemoteContent`] applies.
+    #[default]
+    Undecided = 0,
+
+    /// Always load remote content from the contact
+    /// unless [`Config::RemoteContent`] is set to never load it.
+    Allow = 1,
+
+    /// Never load remote content from the contact automatically.
+    Block = 2,
+}
+
+/// Encryption of a received message, see [`update_trust_timeline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReceivedEncryption {
+    Unencrypted = 0,
+    Encrypted = 1,
+    Verified = 2,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) enum Modifier {
     None,
@@ -378,7 +513,7 @@ pub async fn get_by_id_optional(
             .sql
             .query_row_optional(
                 "SELECT c.name, c.addr, c.origin, c.blocked, c.last_seen,
-                c.authname, c.param, c.status, c.is_bot
+                c.authname, c.param, c.status, c.is_bot, c.last_error
                FROM contacts c
               WHERE c.id=?;",
                 (contact_id,),
@@ -392,6 +527,7 @@ pub async fn get_by_id_optional(
                     let param: String = row.get(6)?;
                     let status: Option<String> = row.get(7)?;
                     let is_bot: bool = row.get(8)?;
+                    let last_error: String = row.get(9)?;
                     let contact = Self {
                         id: contact_id,
                         name,
@@ -403,6 +539,7 @@ pub async fn get_by_id_optional(
                         param: param.parse().unwrap_or_default(),
                         status: status.unwrap_or_default(),
                         is_bot,
+                        last_error,
                     };
                     Ok(contact)
                 },
@@ -445,6 +582,31 @@ pub fn was_seen_recently(&self) -> bool {
         time() - self.last_seen <= SEEN_RECENTLY_SECONDS
     }
 
+    /// Returns when the contact was last seen, where their messages arrive
+    /// and whether their recent messages were encrypted and verified.
+    pub async fn get_status_summary(&self, context: &Context) -> Result<ContactStatusSummary> {
+        let summary = context
+            .sql
+            .query_row_optional(
+                "SELECT last_seen, last_seen_folder, last_encrypted, last_unencrypted, last_verified
+                 FROM contacts WHERE id=?",
+                (self.id,),
+                |row| {
+                    let last_seen_folder: String = row.get(1)?;
+                    Ok(ContactStatusSummary {
+                        last_seen: row.get(0)?,
+                        last_seen_folder: Some(last_seen_folder)
+                            .filter(|folder| !folder.is_empty()),
+                        last_encrypted: row.get(2)?,
+                        last_unencrypted: row.get(3)?,
+                        last_verified: row.get(4)?,
+                    })
+                },
+            )
+            .await?;
+        Ok(summary.unwrap_or_default())
+    }
+
     /// Check if a contact is blocked.
     pub async fn is_blocked_load(context: &Context, id: ContactId) -> Result<bool> {
         let blocked = context
@@ -517,6 +679,12 @@ pub fn is_bot(&self) -> bool {
         self.is_bot
     }
 
+    /// Returns the last error sending a message to the contact,
+    /// e.g. a rejected address, see [`Chat::get_last_error`](crate::chat::Chat::get_last_error).
+    pub fn get_last_error(&self) -> Option<&str> {
+        Some(self.last_error.as_str()).filter(|error| !error.is_empty())
+    }
+
     /// Check if an e-mail address belongs to a known and unblocked contact.
     ///
     /// Known and unblocked contacts will be returned by `get_contacts()`.
@@ -581,13 +749,11 @@ pub(crate) async fn add_or_lookup(
         addr: ContactAddress<'_>,
         mut origin: Origin,
     ) -> Result<(ContactId, Modifier)> {
-        let mut sth_modified = Modifier::None;
-
         ensure!(!addr.is_empty(), "Can not add_or_lookup empty address");
         ensure!(origin != Origin::Unknown, "Missing valid origin");
 
         if context.is_self_addr(&addr).await? {
-            return Ok((ContactId::SELF, sth_modified));
+            return Ok((ContactId::SELF, Modifier::None));
         }
 
         let mut name = strip_rtlo_characters(name);
@@ -609,6 +775,29 @@ pub(crate) async fn add_or_lookup(
             }
         }
 
+        context
+            .sql
+            .transaction(|transaction| {
+                let (row_id, sth_modified) =
+                    Contact::add_or_lookup_ex(context, transaction, &name, &addr, origin)?;
+                Ok((ContactId::new(row_id), sth_modified))
+            })
+            .await
+    }
+
+    /// Adds or looks up a contact within a transaction, see [`Contact::add_or_lookup`].
+    ///
+    /// Unlike [`Contact::add_or_lookup`], this does not check for self addresses
+    /// and does not hide contacts with use-once addresses.
+    fn add_or_lookup_ex(
+        context: &Context,
+        transaction: &mut rusqlite::Transaction<'_>,
+        name: &str,
+        addr: &ContactAddress<'_>,
+        origin: Origin,
+    ) -> Result<(u32, Modifier)> {
+        let mut sth_modified = Modifier::None;
+
         // If the origin indicates that user entered the contact manually, from the address book or
         // from the QR-code scan (potentially from the address book of their other phone), then name
         // should go into the "name" column and never into "authname" column, to avoid leaking it
@@ -618,13 +807,11 @@ pub(crate) async fn add_or_lookup(
             Origin::ManuallyCreated | Origin::AddressBook | Origin::UnhandledQrScan
         );
 
-        let mut update_addr = false;
-
-        let row_id = context.sql.transaction(|transaction| {
-            let row = transaction.query_row(
+        let row = transaction
+            .query_row(
                 "SELECT id, name, addr, origin, authname
                  FROM contacts WHERE addr=? COLLATE NOCASE",
-                 [addr.to_string()],
+                [addr.to_string()],
                 |row| {
                     let row_id: isize = row.get(0)?;
                     let row_name: String = row.get(1)?;
@@ -633,132 +820,132 @@ pub(crate) async fn add_or_lookup(
                     let row_authname: String = row.get(4)?;
 
                     Ok((row_id, row_name, row_addr, row_origin, row_authname))
-                }).optional()?;
-
-            let row_id;
-            if let Some((id, row_name, row_addr, row_origin, row_authname)) = row {
-                let update_name = manual && name != row_name;
-                let update_authname = !manual
-                    && name != row_authname
-                    && !name.is_empty()
-                    && (origin >= row_origin
-                        || origin == Origin::IncomingUnknownFrom
-                        || row_authname.is_empty());
-
-                row_id = u32::try_from(id)?;
-                if origin >= row_origin && addr.as_ref() != row_addr {
-                    update_addr = true;
-                }
-                if update_name || update_authname || update_addr || origin > row_origin {
-                    let new_name = if update_name {
-                        name.to_string()
-                    } else {
-                        row_name
-                    };
+                },
+            )
+            .optional()?;
+
+        let row_id;
+        if let Some((id, row_name, row_addr, row_origin, row_authname)) = row {
+            // Names from the address book may be missing and must not override manual edits.
+            let update_name = manual
+                && name != row_name
+                && (origin != Origin::AddressBook
+                    || (!name.is_empty() && row_origin < Origin::ManuallyCreated));
+            let update_authname = !manual
+                && name != row_authname
+                && !name.is_empty()
+                && (origin >= row_origin
+                    || origin == Origin::IncomingUnknownFrom
+                    || row_authname.is_empty());
+
+            row_id = u32::try_from(id)?;
+            let update_addr = origin >= row_origin && addr.as_ref() != row_addr;
+            if update_name || update_authname || update_addr || origin > row_origin {
+                let new_name = if update_name {
+                    name.to_string()
+                } else {
+                    row_name
+                };
 
-                    transaction
-                        .execute(
-                            "UPDATE contacts SET name=?, addr=?, origin=?, authname=? WHERE id=?;",
-                            (
-                                new_name,
-                                if update_addr {
-                                    addr.to_string()
-                                } else {
-                                    row_addr
-                                },
-                                if origin > row_origin {
-                                    origin
-                                } else {
-                                    row_origin
-                                },
-                                if update_authname {
-                                    name.to_string()
-                                } else {
-                                    row_authname
-                                },
-                                row_id
-                            ),
-                        )?;
+                transaction.execute(
+                    "UPDATE contacts SET name=?, addr=?, origin=?, authname=? WHERE id=?;",
+                    (
+                        new_name,
+                        if update_addr {
+                            addr.to_string()
+                        } else {
+                            row_addr
+                        },
+                        if origin > row_origin {
+                            origin
+                        } else {
+                            row_origin
+                        },
+                        if update_authname {
+                            name.to_string()
+                        } else {
+                            row_authname
+                        },
+                        row_id,
+                    ),
+                )?;
 
-                    if update_name || update_authname {
-                        // Update the contact name also if it is used as a group name.
-                        // This is one of the few duplicated data, however, getting the chat list is easier this way.
-                        let chat_id: Option<ChatId> = transaction.query_row(
-                            "SELECT id FROM chats WHERE type=? AND id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?)",
-                            (Chattype::Single, isize::try_from(row_id)?),
+                if update_name || update_authname {
+                    // Update the contact name also if it is used as a group name.
+                    // This is one of the few duplicated data, however, getting the chat list is easier this way.
+                    let chat_id: Option<ChatId> = transaction.query_row(
+                        "SELECT id FROM chats WHERE type=? AND id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?)",
+                        (Chattype::Single, isize::try_from(row_id)?),
+                        |row| {
+                            let chat_id: ChatId = row.get(0)?;
+                            Ok(chat_id)
+                        }
+                    ).optional()?;
+
+                    if let Some(chat_id) = chat_id {
+                        let contact_id = ContactId::new(row_id);
+                        let (addr, name, authname) = transaction.query_row(
+                            "SELECT addr, name, authname
+                             FROM contacts
+                             WHERE id=?",
+                            (contact_id,),
                             |row| {
-                                let chat_id: ChatId = row.get(0)?;
-                                Ok(chat_id)
-                            }
-                        ).optional()?;
-
-                        if let Some(chat_id) = chat_id {
-                            let contact_id = ContactId::new(row_id);
-                            let (addr, name, authname) =
-                                transaction.query_row(
-                                    "SELECT addr, name, authname
-                                     FROM contacts
-                                     WHERE id=?",
-                                     (contact_id,),
-                                |row| {
-                                    let addr: String = row.get(0)?;
-                                    let name: String = row.get(1)?;
-                                    let authname: String = row.get(2)?;
-                                    Ok((addr, name, authname))
-                                })?;
-
-                            let chat_name = if !name.is_empty() {
-                                name
-                            } else if !authname.is_empty() {
-                                authname
-                            } else {
-                                addr
-                            };
-
-                            let count = transaction.execute(
-                                    "UPDATE chats SET name=?1 WHERE id=?2 AND name!=?1",
-                                    (chat_name, chat_id))?;
-
-                            if count > 0 {
-                                // Chat name updated
-                                context.emit_event(EventType::ChatModified(chat_id));
-                            }
+                                let addr: String = row.get(0)?;
+                                let name: String = row.get(1)?;
+                                let authname: String = row.get(2)?;
+                                Ok((addr, name, authname))
+                            },
+                        )?;
+
+                        let chat_name = if !name.is_empty() {
+                            name
+                        } else if !authname.is_empty() {
+                            authname
+                        } else {
+                            addr
+                        };
+
+                        let count = transaction.execute(
+                            "UPDATE chats SET name=?1 WHERE id=?2 AND name!=?1",
+                            (chat_name, chat_id),
+                        )?;
+
+                        if count > 0 {
+                            // Chat name updated
+                            context.emit_event(EventType::ChatModified(chat_id));
                         }
                     }
-                    sth_modified = Modifier::Modified;
                 }
-            } else {
-                let update_name = manual;
-                let update_authname = !manual;
-
-                transaction
-                    .execute(
-                        "INSERT INTO contacts (name, addr, origin, authname)
-                         VALUES (?, ?, ?, ?);",
-                         (
-                            if update_name {
-                                name.to_string()
-                            } else {
-                                "".to_string()
-                            },
-                            addr,
-                            origin,
-                            if update_authname {
-                                name.to_string()
-                            } else {
-                                "".to_string()
-                            }
-                        ),
-                    )?;
-
-                sth_modified = Modifier::Created;
-                row_id = u32::try_from(transaction.last_insert_rowid())?;
-                info!(context, "added contact id={} addr={}", row_id, &addr);
+                sth_modified = Modifier::Modified;
             }
-            Ok(row_id)
-        }).await?;
+        } else {
+            let update_name = manual;
+            let update_authname = !manual;
+
+            transaction.execute(
+                "INSERT INTO contacts (name, addr, origin, authname)
+                     VALUES (?, ?, ?, ?);",
+                (
+                    if update_name {
+                        name.to_string()
+                    } else {
+                        "".to_string()
+                    },
+                    addr,
+                    origin,
+                    if update_authname {
+                        name.to_string()
+                    } else {
+                        "".to_string()
+                    },
+                ),
+            )?;
 
-        Ok((ContactId::new(row_id), sth_modified))
+            sth_modified = Modifier::Created;
+            row_id = u32::try_from(transaction.last_insert_rowid())?;
+            info!(context, "added contact id={} addr={}", row_id, &addr);
+        }
+        Ok((row_id, sth_modified))
     }
 
     /// Add a number of contacts.
@@ -777,27 +964,31 @@ pub(crate) async fn add_or_lookup(
     ///
     /// The `addr_book` is a multiline string in the format `Name one\nAddress one\nName two\nAddress two`.
     ///
+    /// The addresses are written in chunks of [`ADDRESS_BOOK_CHUNK_SIZE`] entries,
+    /// one transaction each, so that address books with tens of thousands of entries
+    /// do not block the database for long.
+    /// For address books with more than one chunk, `DC_EVENT_CONTACTS_IMPORT_PROGRESS`
+    /// is sent after each chunk.
+    ///
+    /// Contacts only known from the address book are not returned by [`Contact::get_all`]
+    /// without a query until there is a chat with them.
+    ///
     /// Returns the number of modified contacts.
     pub async fn add_address_book(context: &Context, addr_book: &str) -> Result<usize> {
-        let mut modify_cnt = 0;
-
+        let self_addrs = context.get_all_self_addrs().await?;
+        let mut seen_addrs = HashSet::new();
+        let mut entries = Vec::new();
         for (name, addr) in split_address_book(addr_book) {
             let (name, addr) = sanitize_name_and_addr(name, addr);
-            let name = normalize_name(&name);
+            let name = strip_rtlo_characters(&normalize_name(&name));
             match ContactAddress::new(&addr) {
                 Ok(addr) => {
-                    match Contact::add_or_lookup(context, &name, addr, Origin::AddressBook).await {
-                        Ok((_, modified)) => {
-                            if modified != Modifier::None {
-                                modify_cnt += 1
-                            }
-                        }
-                        Err(err) => {
-                            warn!(
-                                context,
-                                "Failed to add address {} from address book: {}", addr, err
-                            );
-                        }
+                    if !self_addrs
+                        .iter()
+                        .any(|self_addr| addr_cmp(self_addr, &addr))
+                        && seen_addrs.insert(addr.to_lowercase())
+                    {
+                        entries.push((name, addr.to_string()));
                     }
                 }
                 Err(err) => {
@@ -805,6 +996,21 @@ pub async fn add_address_book(context: &Context, addr_book: &str) -> Result<usiz
                 }
             }
         }
+
+        let mut modify_cnt = 0;
+        let mut done_cnt = 0;
+        for chunk in entries.chunks(ADDRESS_BOOK_CHUNK_SIZE) {
+            modify_cnt += context
+                .sql
+                .transaction(move |transaction| add_address_book_chunk(context, transaction, chunk))
+                .await?;
+            done_cnt += chunk.len();
+            if entries.len() > ADDRESS_BOOK_CHUNK_SIZE {
+                context.emit_event(EventType::ContactsImportProgress(
+                    done_cnt * 1000 / entries.len(),
+                ));
+            }
+        }
         if modify_cnt > 0 {
             context.emit_event(EventType::ContactsChanged(None));
         }
@@ -821,6 +1027,13 @@ pub async fn add_address_book(context: &Context, addr_book: &str) -> Result<usiz
     /// - if the flag DC_GCL_VERIFIED_ONLY is set, only verified contacts are returned.
     ///   if DC_GCL_VERIFIED_ONLY is not set, verified and unverified contacts are returned.
     /// `query` is a string to filter the list.
+    ///
+    /// Contacts only known from the address book are only returned for a `query`
+    /// or if there is a chat with them, so that importing a large address book
+    /// does not clutter the contact list.
+    ///
+    /// Contacts are sorted by last seen time,
+    /// for a `query` contacts whose last message was encrypted come first.
     pub async fn get_all(
         context: &Context,
         listflags: u32,
@@ -850,7 +1063,10 @@ pub async fn get_all(
                  AND c.blocked=0 \
                  AND (iif(c.name='',c.authname,c.name) LIKE ? OR c.addr LIKE ?) \
                  AND (1=? OR LENGTH(ps.verified_key_fingerprint)!=0)  \
-                 ORDER BY c.last_seen DESC, c.id DESC;",
+                 AND (1=? OR c.origin!=? OR c.last_seen>0 \
+                      OR EXISTS (SELECT 1 FROM chats_contacts cc WHERE cc.contact_id=c.id)) \
+                 ORDER BY (c.last_encrypted>0 AND c.last_encrypted>=c.last_unencrypted) DESC, \
+                 c.last_seen DESC, c.id DESC;",
                         sql::repeat_vars(self_addrs.len())
                     ),
                     rusqlite::params_from_iter(params_iter(&self_addrs).chain(params_slice![
@@ -858,7 +1074,9 @@ pub async fn get_all(
                         minimal_origin,
                         s3str_like_cmd,
                         s3str_like_cmd,
-                        if flag_verified_only { 0i32 } else { 1i32 }
+                        if flag_verified_only { 0i32 } else { 1i32 },
+                        if query.is_some() { 1i32 } else { 0i32 },
+                        Origin::AddressBook
                     ])),
                     |row| row.get::<_, ContactId>(0),
                     |ids| {
@@ -902,13 +1120,16 @@ pub async fn get_all(
                  AND id>?
                  AND origin>=?
                  AND blocked=0
+                 AND (origin!=? OR last_seen>0
+                      OR EXISTS (SELECT 1 FROM chats_contacts cc WHERE cc.contact_id=contacts.id))
                  ORDER BY last_seen DESC, id DESC;",
                         sql::repeat_vars(self_addrs.len())
                     ),
-                    rusqlite::params_from_iter(
-                        params_iter(&self_addrs)
-                            .chain(params_slice![ContactId::LAST_SPECIAL, minimal_origin]),
-                    ),
+                    rusqlite::params_from_iter(params_iter(&self_addrs).chain(params_slice![
+                        ContactId::LAST_SPECIAL,
+                        minimal_origin,
+                        Origin::AddressBook
+                    ])),
                     |row| row.get::<_, ContactId>(0),
                     |ids| {
                         for id in ids {
@@ -1223,6 +1444,13 @@ pub fn get_color(&self) -> u32 {
         str_to_color(&self.addr.to_lowercase())
     }
 
+    /// Renders a fallback avatar for contacts without profile image as an SVG image.
+    ///
+    /// The avatar shows the initial letter of the display name on the contact's color.
+    pub fn get_fallback_avatar_svg(&self) -> Result<String> {
+        fallback_avatar_svg(self.get_display_name(), self.get_color())
+    }
+
     /// Gets the contact's status.
     ///
     /// Status is the last signature received in a message from this contact.
@@ -1230,6 +1458,15 @@ pub fn get_status(&self) -> &str {
         self.status.as_str()
     }
 
+    /// Returns how many seconds the clock of the contact is ahead,
+    /// as detected from the last received message, or 0 if the clock is correct.
+    ///
+    /// Messages from contacts with skewed clocks are sorted by the time
+    /// they arrived on the server instead of their `Date` header.
+    pub fn get_clock_skew(&self) -> i64 {
+        self.param.get_i64(Param::ClockSkew).unwrap_or_default()
+    }
+
     /// Check if a contact was verified. E.g. by a secure-join QR code scan
     /// and if the key has not changed since this verification.
     ///
@@ -1250,6 +1487,38 @@ pub async fn is_verified(&self, context: &Context) -> Result<VerifiedStatus> {
         Ok(VerifiedStatus::Unverified)
     }
 
+    /// Returns the fingerprint of the key used to encrypt messages to the contact,
+    /// formatted for comparison by the user, e.g. over the phone.
+    ///
+    /// For [`ContactId::SELF`] the fingerprint of the own key is returned,
+    /// for other contacts `None` if no key is known.
+    pub async fn get_fingerprint(&self, context: &Context) -> Result<Option<String>> {
+        if self.id == ContactId::SELF {
+            let key = load_self_public_key(context).await?;
+            return Ok(Some(key.fingerprint().to_string()));
+        }
+        Ok(Peerstate::from_addr(context, &self.addr)
+            .await?
+            .and_then(|peerstate| {
+                peerstate
+                    .peek_key(PeerstateVerifiedStatus::Unverified)
+                    .map(|key| key.fingerprint().to_string())
+            }))
+    }
+
+    /// Marks the contact as verified after the user compared the fingerprints
+    /// returned by [`Contact::get_fingerprint`] on both devices.
+    ///
+    /// `fingerprint` is the fingerprint the contact reported
+    /// in any format accepted by [`Fingerprint`], e.g. hex with or without spaces.
+    /// Fails if it does not match the contact's key.
+    /// The contact is then verified the same way as by a Secure-Join.
+    pub async fn mark_verified(&self, context: &Context, fingerprint: &str) -> Result<()> {
+        ensure!(!self.id.is_special(), "Can not verify special contact");
+        let fingerprint: Fingerprint = fingerprint.parse()?;
+        securejoin::mark_verified_manually(context, self.id, fingerprint).await
+    }
+
     /// Returns the address that verified the contact.
     pub async fn get_verifier_addr(&self, context: &Context) -> Result<Option<String>> {
         Ok(Peerstate::from_addr(context, self.get_addr())
@@ -1408,6 +1677,7 @@ async fn set_block_contact(
             Contact::mark_noticed(context, contact_id).await?;
             context.emit_event(EventType::ContactsChanged(Some(contact_id)));
         }
+        context.invalidate_fresh_msg_count();
 
         // also unblock mailinglist
         // if the contact is a mailinglist address explicitly created to allow unblocking
@@ -1439,6 +1709,21 @@ pub(crate) async fn set_profile_image(
     let mut contact = Contact::get_by_id(context, contact_id).await?;
     let changed = match profile_image {
         AvatarAction::Change(profile_image) => {
+            let old_profile_image = if contact_id == ContactId::SELF {
+                context.get_config(Config::Selfavatar).await?
+            } else {
+                contact
+                    .param
+                    .get(Param::ProfileImage)
+                    .map(|s| s.to_string())
+            };
+            if let Some(old_profile_image) = old_profile_image {
+                if is_same_file(context, &old_profile_image, profile_image).await {
+                    // Avoid resending our avatar and unnecessary events.
+                    info!(context, "Profile image of {contact_id} is unchanged.");
+                    return Ok(());
+                }
+            }
             if contact_id == ContactId::SELF {
                 if was_encrypted {
                     context
@@ -1472,6 +1757,31 @@ pub(crate) async fn set_profile_image(
     Ok(())
 }
 
+/// Returns true if the blob files `a` and `b` have the same content.
+async fn is_same_file(context: &Context, a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    let read = |name: &str| tokio::fs::read(get_abs_path(context, Path::new(name)));
+    match (read(a).await, read(b).await) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Sets the display name of contact SELF from a message sent by another device.
+pub(crate) async fn set_self_displayname(context: &Context, display_name: &str) -> Result<()> {
+    let display_name = normalize_name(display_name);
+    if display_name.is_empty()
+        || context.get_config(Config::Displayname).await?.as_deref() == Some(display_name.as_str())
+    {
+        return Ok(());
+    }
+    context
+        .set_config(Config::Displayname, Some(&display_name))
+        .await
+}
+
 /// Sets contact status.
 ///
 /// For contact SELF, the status is not saved in the contact table, but as Config::Selfstatus.  This
@@ -1531,6 +1841,95 @@ pub(crate) async fn update_last_seen(
     Ok(())
 }
 
+/// Records the clock skew of the contact detected from a received message.
+///
+/// Only the parameters of the contact are read,
+/// and they are only written if the clock skew changed.
+pub(crate) async fn update_clock_skew(
+    context: &Context,
+    contact_id: ContactId,
+    clock_skew: i64,
+) -> Result<()> {
+    let param: String = context
+        .sql
+        .query_get_value("SELECT param FROM contacts WHERE id=?", (contact_id,))
+        .await?
+        .unwrap_or_default();
+    let mut param: Params = param.parse().unwrap_or_default();
+    if param.get_i64(Param::ClockSkew).unwrap_or_default() == clock_skew {
+        return Ok(());
+    }
+    if clock_skew == 0 {
+        param.remove(Param::ClockSkew);
+    } else {
+        param.set_i64(Param::ClockSkew, clock_skew);
+    }
+    context
+        .sql
+        .execute(
+            "UPDATE contacts SET param=? WHERE id=?",
+            (param.to_string(), contact_id),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Records the folder and the encryption of a message received from the contact
+/// for [`Contact::get_status_summary`].
+///
+/// Must be called before [`update_last_seen`] as the folder is only updated
+/// if the message is not older than the last seen one.
+pub(crate) async fn update_trust_timeline(
+    context: &Context,
+    contact_id: ContactId,
+    timestamp: i64,
+    folder: Option<&str>,
+    encryption: ReceivedEncryption,
+) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "UPDATE contacts SET
+             last_seen_folder=iif(?2!='' AND ?1>=last_seen, ?2, last_seen_folder),
+             last_encrypted=iif(?3>=1, MAX(last_encrypted, ?1), last_encrypted),
+             last_unencrypted=iif(?3=0, MAX(last_unencrypted, ?1), last_unencrypted),
+             last_verified=iif(?3=2, MAX(last_verified, ?1), last_verified)
+             WHERE id=?4",
+            (
+                timestamp,
+                folder.unwrap_or_default(),
+                encryption as i32,
+                contact_id,
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Number of address book entries written in one transaction by [`Contact::add_address_book`].
+pub(crate) const ADDRESS_BOOK_CHUNK_SIZE: usize = 1000;
+
+/// Adds or updates normalized `(name, addr)` address book entries
+/// with [`Contact::add_or_lookup`] semantics.
+///
+/// Returns the number of modified contacts.
+fn add_address_book_chunk(
+    context: &Context,
+    transaction: &mut rusqlite::Transaction<'_>,
+    entries: &[(String, String)],
+) -> Result<usize> {
+    let mut modify_cnt = 0;
+    for (name, addr) in entries {
+        let addr = ContactAddress::new(addr)?;
+        let (_, modified) =
+            Contact::add_or_lookup_ex(context, transaction, name, &addr, Origin::AddressBook)?;
+        if modified != Modifier::None {
+            modify_cnt += 1;
+        }
+    }
+    Ok(modify_cnt)
+}
+
 /// Normalize a name.
 ///
 /// - Remove quotes (come from some bad MUA implementations)
@@ -1728,6 +2127,7 @@ mod tests {
     use super::*;
     use crate::chat::{get_chat_contacts, send_text_msg, Chat};
     use crate::chatlist::Chatlist;
+    use crate::message::{Message, Viewtype};
     use crate::receive_imf::receive_imf;
     use crate::test_utils::{self, TestContext, TestContextManager};
 
@@ -1740,6 +2140,8 @@ fn test_contact_id_values() {
         assert_eq!(ContactId::INFO.to_u32(), 2);
         assert_eq!(ContactId::DEVICE.to_u32(), 5);
         assert_eq!(ContactId::LAST_SPECIAL.to_u32(), 9);
+        assert_eq!(u32::from(ContactId::DEVICE), 5);
+        assert_eq!(ContactId::from(1), ContactId::SELF);
     }
 
     #[test]
@@ -1879,6 +2281,56 @@ fn test_contact_address() -> Result<()> {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_add_address_book_large() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let cnt = ADDRESS_BOOK_CHUNK_SIZE * 2 + 500;
+        let mut book = String::new();
+        for i in 0..cnt {
+            book += &format!("Name {i}\nuser{i}@example.org\n");
+        }
+        // Own address and duplicates are skipped.
+        book += "Me\nalice@example.org\nName 0\nUSER0@example.org\n";
+        assert_eq!(Contact::add_address_book(&t, &book).await?, cnt);
+        t.evtracker
+            .get_matching(|evt| matches!(evt, EventType::ContactsImportProgress(p) if *p < 1000))
+            .await;
+        t.evtracker
+            .get_matching(|evt| matches!(evt, EventType::ContactsImportProgress(1000)))
+            .await;
+        assert_eq!(Contact::add_address_book(&t, &book).await?, 0);
+
+        // Imported contacts are not listed until there is a chat with them.
+        assert!(Contact::get_all(&t, 0, None).await?.is_empty());
+        let contacts = Contact::get_all(&t, 0, Some("user42@")).await?;
+        assert_eq!(contacts.len(), 1);
+        let contact_id = contacts[0];
+        let chat_id = ChatId::create_for_contact(&t, contact_id).await?;
+        assert_eq!(Contact::get_all(&t, 0, None).await?, vec![contact_id]);
+
+        // Renaming the contact renames the chat, missing and manually edited names are kept.
+        Contact::add_address_book(&t, "Renamed\nuser42@example.org\n").await?;
+        assert_eq!(Chat::load_from_db(&t, chat_id).await?.get_name(), "Renamed");
+        assert_eq!(
+            Contact::add_address_book(&t, "\nuser42@example.org\n").await?,
+            0
+        );
+        assert_eq!(
+            Contact::get_by_id(&t, contact_id).await?.get_name(),
+            "Renamed"
+        );
+        Contact::create(&t, "Manual", "user42@example.org").await?;
+        assert_eq!(
+            Contact::add_address_book(&t, "Renamed again\nuser42@example.org\n").await?,
+            0
+        );
+        assert_eq!(
+            Contact::get_by_id(&t, contact_id).await?.get_name(),
+            "Manual"
+        );
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_add_or_lookup() {
         // add some contacts, this also tests add_address_book()
@@ -2587,6 +3039,86 @@ async fn test_synchronize_status() -> Result<()> {
         Ok(())
     }
 
+    /// Tests that the display name is synchronized with encrypted messages
+    /// and not taken from messages with overridden sender name.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_synchronize_displayname() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice1 = tcm.alice().await;
+        let alice2 = tcm.alice().await;
+        let bob = tcm.bob().await;
+        tcm.send_recv_accept(&bob, &alice1, "Hi").await;
+
+        alice1
+            .set_config(Config::Displayname, Some("Alice Liddell"))
+            .await?;
+        let chat = alice1.create_chat(&bob).await;
+        let sent_msg = alice1.send_text(chat.id, "Hello").await;
+        assert!(sent_msg.load_from_db().await.get_showpadlock());
+        alice2.recv_msg(&sent_msg).await;
+        assert_eq!(
+            alice2.get_config(Config::Displayname).await?,
+            Some("Alice Liddell".to_string())
+        );
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("Hello from a bot".to_string());
+        msg.set_override_sender_name(Some("Bot".to_string()));
+        chat::send_msg(&alice1, chat.id, &mut msg).await?;
+        let sent_msg = alice1.pop_sent_msg().await;
+        alice2.recv_msg(&sent_msg).await;
+        assert_eq!(
+            alice2.get_config(Config::Displayname).await?,
+            Some("Alice Liddell".to_string())
+        );
+
+        Ok(())
+    }
+
+    /// Tests that the display name of a contact is not reverted by older messages
+    /// and that changing the own display name attaches the profile to the next messages.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_displayname_update_order() -> Result<()> {
+        let t = TestContext::new_bob().await;
+        let recv = |name: &str, date: &str, mid: &str| {
+            format!(
+                "From: {name} <alice@example.org>\n\
+                 To: bob@example.net\n\
+                 Subject: hi\n\
+                 Message-ID: <{mid}@example.org>\n\
+                 Date: {date}\n\
+                 \n\
+                 hi\n"
+            )
+        };
+        receive_imf(
+            &t,
+            recv("Alice New", "Sun, 22 Mar 2020 22:37:57 +0000", "2").as_bytes(),
+            false,
+        )
+        .await?;
+        receive_imf(
+            &t,
+            recv("Alice Old", "Sun, 22 Mar 2020 22:37:55 +0000", "1").as_bytes(),
+            false,
+        )
+        .await?;
+        let contact_id = Contact::lookup_id_by_addr(&t, "alice@example.org", Origin::Unknown)
+            .await?
+            .unwrap();
+        let contact = Contact::get_by_id(&t, contact_id).await?;
+        assert_eq!(contact.get_authname(), "Alice New");
+
+        let chat_id = ChatId::lookup_by_contact(&t, contact_id).await?.unwrap();
+        t.sql
+            .execute("UPDATE contacts SET selfavatar_sent=?", (time(),))
+            .await?;
+        assert!(!chat::shall_attach_selfavatar(&t, chat_id).await?);
+        t.set_config(Config::Displayname, Some("Robert")).await?;
+        assert!(chat::shall_attach_selfavatar(&t, chat_id).await?);
+        Ok(())
+    }
+
     /// Tests that DC_EVENT_SELFAVATAR_CHANGED is emitted on avatar changes.
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_selfavatar_changed_event() -> Result<()> {
@@ -2702,6 +3234,75 @@ async fn test_was_seen_recently() -> Result<()> {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_status_summary() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let alice_id = bob.add_or_lookup_contact(&alice).await.id;
+        let bob_id = alice.add_or_lookup_contact(&bob).await.id;
+        let summary = Contact::get_by_id(&bob, alice_id)
+            .await?
+            .get_status_summary(&bob)
+            .await?;
+        assert_eq!(summary, ContactStatusSummary::default());
+
+        // Bob does not have Alice's key yet, so the first message is unencrypted.
+        let sent = alice
+            .send_text(alice.create_chat(&bob).await.id, "hi")
+            .await;
+        let rfc724_mid = sent.load_from_db().await.rfc724_mid;
+        bob.sql
+            .execute(
+                "INSERT INTO imap (rfc724_mid, folder, uid, target) VALUES (?,'INBOX',1,'INBOX')",
+                (&rfc724_mid,),
+            )
+            .await?;
+        let msg = bob.recv_msg(&sent).await;
+        let summary = Contact::get_by_id(&bob, alice_id)
+            .await?
+            .get_status_summary(&bob)
+            .await?;
+        assert_eq!(summary.last_seen, msg.timestamp_sent);
+        assert_eq!(summary.last_seen_folder.as_deref(), Some("INBOX"));
+        assert_eq!(summary.last_unencrypted, msg.timestamp_sent);
+        assert_eq!(summary.last_encrypted, 0);
+        assert!(!summary.is_encrypted());
+        assert!(!summary.is_verified());
+
+        // Bob's reply is encrypted.
+        let sent = bob.send_text(msg.chat_id, "hello").await;
+        let msg = alice.recv_msg(&sent).await;
+        let summary = Contact::get_by_id(&alice, bob_id)
+            .await?
+            .get_status_summary(&alice)
+            .await?;
+        assert_eq!(summary.last_encrypted, msg.timestamp_sent);
+        assert_eq!(summary.last_seen_folder, None);
+        assert!(summary.is_encrypted());
+        assert!(!summary.is_verified());
+
+        // Contacts with encrypted messages are ranked first in suggestions.
+        let (charlie_id, _) = Contact::add_or_lookup(
+            &alice,
+            "Bob's friend",
+            ContactAddress::new("charlie@example.net")?,
+            Origin::ManuallyCreated,
+        )
+        .await?;
+        update_last_seen(&alice, charlie_id, msg.timestamp_sent + 10).await?;
+        assert_eq!(
+            Contact::get_all(&alice, 0, Some("bob")).await?,
+            vec![bob_id, charlie_id]
+        );
+        assert_eq!(
+            Contact::get_all(&alice, 0, None).await?,
+            vec![charlie_id, bob_id]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_verified_by_none() -> Result<()> {
         let mut tcm = TestContextManager::new();
@@ -2724,4 +3325,59 @@ async fn test_verified_by_none() -> Result<()> {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_mark_verified() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let contact_id = Contact::create(&alice, "Bob", "bob@example.net").await?;
+        let contact = Contact::get_by_id(&alice, contact_id).await?;
+        assert!(contact.get_fingerprint(&alice).await?.is_none());
+
+        let chat = bob.create_chat(&alice).await;
+        let sent_msg = bob.send_text(chat.id, "moin").await;
+        alice.recv_msg(&sent_msg).await;
+
+        // Bob reads his fingerprint to Alice.
+        let bob_self = Contact::get_by_id(&bob, ContactId::SELF).await?;
+        let bob_fingerprint = bob_self.get_fingerprint(&bob).await?.unwrap();
+        assert_eq!(
+            contact.get_fingerprint(&alice).await?,
+            Some(bob_fingerprint.clone())
+        );
+        assert_eq!(
+            contact.is_verified(&alice).await?,
+            VerifiedStatus::Unverified
+        );
+
+        let alice_fingerprint = Contact::get_by_id(&alice, ContactId::SELF)
+            .await?
+            .get_fingerprint(&alice)
+            .await?
+            .unwrap();
+        assert!(contact
+            .mark_verified(&alice, &alice_fingerprint)
+            .await
+            .is_err());
+        assert_eq!(
+            contact.is_verified(&alice).await?,
+            VerifiedStatus::Unverified
+        );
+
+        contact
+            .mark_verified(&alice, &bob_fingerprint.replace(['\n', ' '], ""))
+            .await?;
+        assert_eq!(
+            contact.is_verified(&alice).await?,
+            VerifiedStatus::BidirectVerified
+        );
+        assert_eq!(
+            contact.get_verifier_id(&alice).await?,
+            Some(ContactId::SELF)
+        );
+
+        Ok(())
+    }
 }
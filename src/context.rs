@@ -13,21 +13,30 @@
 use ratelimit::Ratelimit;
 use tokio::sync::{Mutex, Notify, RwLock};
 
+use crate::badge::FreshMsgCount;
 use crate::chat::{get_chat_cnt, ChatId};
-use crate::config::Config;
+use crate::config::{Config, InternalConfig};
 use crate::constants::DC_VERSION_STR;
-use crate::contact::Contact;
+use crate::contact::{Contact, ContactId};
 use crate::debug_logging::DebugLogging;
 use crate::events::{Event, EventEmitter, EventType, Events};
+use crate::imap::{FolderMeaning, Imap};
 use crate::key::{load_self_public_key, DcKey as _};
+use crate::log::{LogBuffer, LogFilter};
 use crate::login_param::LoginParam;
-use crate::message::{self, MessageState, MsgId};
+use crate::message::{self, MessageState, MsgId, Viewtype};
+use crate::metrics::Metrics;
+use crate::peer_channels::PeerChannels;
+use crate::push::PushState;
 use crate::quota::QuotaInfo;
 use crate::scheduler::{InterruptInfo, SchedulerState};
-use crate::sql::Sql;
+use crate::smtp::{send_smtp_messages, Smtp};
+use crate::socks::Socks5Config;
+use crate::sql::{self, Sql};
 use crate::stock_str::StockStrings;
 use crate::timesmearing::SmearedTimestamp;
 use crate::tools::{duration_to_str, time};
+use crate::webhook::Webhook;
 
 /// Builder for the [`Context`].
 ///
@@ -201,6 +210,8 @@ pub struct InnerContext {
     pub(crate) oauth2_mutex: Mutex<()>,
     /// Mutex to prevent a race condition when a "your pw is wrong" warning is sent, resulting in multiple messages being sent.
     pub(crate) wrong_pw_warning_mutex: Mutex<()>,
+    /// Mutex to prevent receiving the same message concurrently from multiple folders.
+    pub(crate) receive_imf_mutex: Mutex<()>,
     pub(crate) translated_stockstrings: StockStrings,
     pub(crate) events: Events,
 
@@ -214,6 +225,9 @@ pub struct InnerContext {
     /// IMAP UID resync request.
     pub(crate) resync_request: AtomicBool,
 
+    /// Request to cancel the running import of the mail history.
+    pub(crate) history_import_cancel: AtomicBool,
+
     /// Notify about new messages.
     ///
     /// This causes [`Context::wait_next_msgs`] to wake up.
@@ -244,6 +258,38 @@ pub struct InnerContext {
     /// Standard RwLock instead of [`tokio::sync::RwLock`] is used
     /// because the lock is used from synchronous [`Context::emit_event`].
     pub(crate) debug_logging: std::sync::RwLock<Option<DebugLogging>>,
+
+    /// Local metric counters, shared with [`Sql`].
+    pub(crate) metrics: Arc<Metrics>,
+
+    /// Filter deciding which log entries are recorded and emitted.
+    pub(crate) log_filter: std::sync::RwLock<LogFilter>,
+
+    /// Most recent log lines, see [`Context::get_last_log_lines`].
+    pub(crate) log_buffer: LogBuffer,
+
+    /// Webhook notifications, `None` if no webhook is configured.
+    ///
+    /// Standard RwLock is used for the same reason as for `debug_logging`.
+    pub(crate) webhook: std::sync::RwLock<Option<Webhook>>,
+
+    /// State of the push token registration.
+    pub(crate) push_state: std::sync::RwLock<PushState>,
+
+    /// SOCKS5 proxy from the system settings, see [`Context::set_system_proxy`].
+    pub(crate) system_proxy: std::sync::RwLock<Option<Socks5Config>>,
+
+    /// Joined realtime channels of webxdc instances.
+    pub(crate) peer_channels: PeerChannels,
+
+    /// Cached number of fresh messages, see [`Context::get_fresh_msg_count`].
+    ///
+    /// Standard Mutex is used because the count is updated from [`Context::emit_event`].
+    pub(crate) fresh_msg_count: std::sync::Mutex<FreshMsgCount>,
+
+    /// Texts stored with [`Config::EncryptAtRest`] by their ciphertext,
+    /// so that messages loaded again are not decrypted again.
+    pub(crate) decrypted_texts: std::sync::Mutex<HashMap<String, String>>,
 }
 
 /// The state of ongoing process.
@@ -265,6 +311,40 @@ fn default() -> Self {
     }
 }
 
+/// Structured filters for [`Context::search_msgs_ex`].
+#[derive(Debug, Default, Clone)]
+pub struct SearchFilter {
+    /// Only search in this chat.
+    pub chat_id: Option<ChatId>,
+
+    /// Only return messages from this contact.
+    pub from_id: Option<ContactId>,
+
+    /// Only return messages with a timestamp at or after this time.
+    pub after: Option<i64>,
+
+    /// Only return messages with a timestamp before this time.
+    pub before: Option<i64>,
+
+    /// Only return messages of this type.
+    pub viewtype: Option<Viewtype>,
+
+    /// Only return messages with an attached file.
+    pub has_attachment: bool,
+}
+
+impl SearchFilter {
+    /// Returns true if the filter does not restrict the search.
+    pub fn is_empty(&self) -> bool {
+        self.chat_id.is_none()
+            && self.from_id.is_none()
+            && self.after.is_none()
+            && self.before.is_none()
+            && self.viewtype.is_none()
+            && !self.has_attachment
+    }
+}
+
 /// Return some info about deltachat-core
 ///
 /// This contains information mostly about the library itself, the
@@ -367,27 +447,41 @@ pub(crate) fn with_blobdir(
         // without starting I/O.
         new_msgs_notify.notify_one();
 
+        let sql = Sql::new(dbfile);
+        let metrics = Arc::clone(&sql.metrics);
+
         let inner = InnerContext {
             id,
             blobdir,
             running_state: RwLock::new(Default::default()),
-            sql: Sql::new(dbfile),
+            sql,
             smeared_timestamp: SmearedTimestamp::new(),
             generating_key_mutex: Mutex::new(()),
             oauth2_mutex: Mutex::new(()),
             wrong_pw_warning_mutex: Mutex::new(()),
+            receive_imf_mutex: Mutex::new(()),
             translated_stockstrings: stockstrings,
             events,
             scheduler: SchedulerState::new(),
             ratelimit: RwLock::new(Ratelimit::new(Duration::new(60, 0), 6.0)), // Allow at least 1 message every 10 seconds + a burst of 6.
             quota: RwLock::new(None),
             resync_request: AtomicBool::new(false),
+            history_import_cancel: AtomicBool::new(false),
             new_msgs_notify,
             server_id: RwLock::new(None),
             creation_time: std::time::SystemTime::now(),
             last_full_folder_scan: Mutex::new(None),
             last_error: std::sync::RwLock::new("".to_string()),
             debug_logging: std::sync::RwLock::new(None),
+            metrics,
+            log_filter: std::sync::RwLock::new(LogFilter::default()),
+            log_buffer: Default::default(),
+            webhook: std::sync::RwLock::new(None),
+            push_state: std::sync::RwLock::new(PushState::default()),
+            system_proxy: std::sync::RwLock::new(None),
+            peer_channels: PeerChannels::default(),
+            fresh_msg_count: std::sync::Mutex::new(FreshMsgCount::default()),
+            decrypted_texts: std::sync::Mutex::new(HashMap::new()),
         };
 
         let ctx = Context {
@@ -407,6 +501,14 @@ pub async fn start_io(&self) {
     }
 
     /// Stops the IO scheduler.
+    ///
+    /// IMAP and SMTP loops are asked to shut down and finish the operation they are currently
+    /// performing, e.g. a message being sent over SMTP is completed and IMAP IDLE is ended
+    /// with a DONE command before logging out.  Loops which do not finish within 30 seconds
+    /// are aborted.
+    ///
+    /// Returns when all loops are stopped, so platform layers can call this
+    /// before the app is backgrounded and call [`Context::start_io`] again later.
     pub async fn stop_io(&self) {
         self.scheduler.stop(self).await;
     }
@@ -422,6 +524,96 @@ pub async fn maybe_network(&self) {
         self.scheduler.maybe_network().await;
     }
 
+    /// Fetches new messages and sends queued messages as quickly as possible,
+    /// giving up after `timeout`.
+    ///
+    /// This is meant to be called when the operating system wakes up the app in background,
+    /// e.g. on a push notification, and only grants a limited time to run.
+    /// If IO is running, all connections are interrupted as in [`Context::maybe_network`]
+    /// and the function returns once they are idle again.
+    /// Otherwise a single connection is used to fetch the Inbox and the Delta Chat folder.
+    ///
+    /// [`EventType::BackgroundFetchDone`] is emitted when done, even if the timeout is hit
+    /// or fetching fails, so the background task can be ended promptly.
+    pub async fn background_fetch(&self, timeout: Duration) -> Result<()> {
+        self.background_fetch_folder(timeout, None).await
+    }
+
+    /// Like [`Context::background_fetch`], but only fetches the given folder
+    /// if IO is not running.
+    pub(crate) async fn background_fetch_folder(
+        &self,
+        timeout: Duration,
+        folder_meaning: Option<FolderMeaning>,
+    ) -> Result<()> {
+        let res = match tokio::time::timeout(timeout, self.background_fetch_inner(folder_meaning))
+            .await
+        {
+            Ok(res) => res,
+            Err(_) => {
+                info!(
+                    self,
+                    "Background fetch timed out after {}.",
+                    duration_to_str(timeout)
+                );
+                Ok(())
+            }
+        };
+        self.emit_event(EventType::BackgroundFetchDone);
+        res
+    }
+
+    async fn background_fetch_inner(&self, folder_meaning: Option<FolderMeaning>) -> Result<()> {
+        if !self.is_configured().await? || self.get_config_bool(Config::Offline).await? {
+            return Ok(());
+        }
+
+        if self.scheduler.is_running().await {
+            self.maybe_network().await;
+            while !self.all_work_done().await {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            return Ok(());
+        }
+
+        let (_interrupt_sender, interrupt_receiver) = channel::bounded(1);
+        let mut connection = Imap::new_configured(self, interrupt_receiver).await?;
+        connection
+            .prepare(self)
+            .await
+            .context("Failed to prepare IMAP connection")?;
+        let folder_meanings = match folder_meaning {
+            Some(folder_meaning) => vec![folder_meaning],
+            None => {
+                let mut folder_meanings = vec![FolderMeaning::Inbox];
+                if self.should_watch_mvbox().await? {
+                    folder_meanings.push(FolderMeaning::Mvbox);
+                }
+                folder_meanings
+            }
+        };
+        for folder_meaning in folder_meanings {
+            let folder = match folder_meaning.to_config() {
+                Some(config) => self.get_config(config).await?,
+                None => None,
+            };
+            if let Some(folder) = folder {
+                connection
+                    .fetch_move_delete(self, &folder, folder_meaning)
+                    .await
+                    .with_context(|| format!("Failed to fetch {folder_meaning} folder"))?;
+            }
+        }
+        connection.logout(self).await;
+
+        let mut smtp = Smtp::new();
+        send_smtp_messages(self, &mut smtp)
+            .await
+            .context("Failed to send queued messages")?;
+        smtp.disconnect();
+        Ok(())
+    }
+
     pub(crate) async fn schedule_resync(&self) -> Result<()> {
         self.resync_request.store(true, Ordering::Relaxed);
         self.scheduler
@@ -432,6 +624,14 @@ pub(crate) async fn schedule_resync(&self) -> Result<()> {
         Ok(())
     }
 
+    /// Cancels the running import of the mail history, see [`Config::ImportHistoryDays`].
+    ///
+    /// Messages imported so far are kept.
+    /// The import is not restarted until the account is configured again.
+    pub fn cancel_history_import(&self) {
+        self.history_import_cancel.store(true, Ordering::Relaxed);
+    }
+
     /// Returns a reference to the underlying SQL instance.
     ///
     /// Warning: this is only here for testing, not part of the public API.
@@ -452,12 +652,19 @@ pub fn get_blobdir(&self) -> &Path {
 
     /// Emits a single event.
     pub fn emit_event(&self, event: EventType) {
+        self.update_fresh_msg_count(&event);
         {
             let lock = self.debug_logging.read().expect("RwLock is poisoned");
             if let Some(debug_logging) = &*lock {
                 debug_logging.log_event(event.clone());
             }
         }
+        {
+            let lock = self.webhook.read().expect("RwLock is poisoned");
+            if let Some(webhook) = &*lock {
+                webhook.notify(self.id, &event);
+            }
+        }
         self.events.emit(Event {
             id: self.id,
             typ: event,
@@ -546,7 +753,6 @@ pub async fn stop_ongoing(&self) {
         }
     }
 
-    #[allow(unused)]
     pub(crate) async fn shall_stop_ongoing(&self) -> bool {
         match &*self.running_state.read().await {
             RunningState::Running { .. } => false,
@@ -564,6 +770,7 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
         let l = LoginParam::load_candidate_params_unchecked(self).await?;
         let l2 = LoginParam::load_configured_params(self).await?;
         let secondary_addrs = self.get_secondary_self_addrs().await?.join(", ");
+        let alias_addrs = self.get_alias_addrs().await?.join(", ");
         let displayname = self.get_config(Config::Displayname).await?;
         let chats = get_chat_cnt(self).await?;
         let unblocked_msgs = message::get_unblocked_msg_cnt(self).await;
@@ -586,6 +793,8 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
         let bcc_self = self.get_config_int(Config::BccSelf).await?;
         let send_sync_msgs = self.get_config_int(Config::SendSyncMsgs).await?;
         let disable_idle = self.get_config_bool(Config::DisableIdle).await?;
+        let offline = self.get_config_bool(Config::Offline).await?;
+        let webxdc_realtime_enabled = self.get_config_bool(Config::WebxdcRealtimeEnabled).await?;
 
         let prv_key_cnt = self.sql.count("SELECT COUNT(*) FROM keypairs;", ()).await?;
 
@@ -603,7 +812,7 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
         let only_fetch_mvbox = self.get_config_int(Config::OnlyFetchMvbox).await?;
         let folders_configured = self
             .sql
-            .get_raw_config_int("folders_configured")
+            .get_raw_config_int(InternalConfig::FoldersConfigured.as_ref())
             .await?
             .unwrap_or_default();
 
@@ -623,11 +832,67 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
             .get_config(Config::ConfiguredTrashFolder)
             .await?
             .unwrap_or_else(|| "<unset>".to_string());
+        let configured_spam_folder = self
+            .get_config(Config::ConfiguredSpamFolder)
+            .await?
+            .unwrap_or_else(|| "<unset>".to_string());
 
         let mut res = get_info();
 
         // insert values
         res.insert("bot", self.get_config_int(Config::Bot).await?.to_string());
+        res.insert(
+            "auto_reply_text",
+            self.get_config(Config::AutoReplyText)
+                .await?
+                .unwrap_or_else(|| "<unset>".to_string()),
+        );
+        res.insert(
+            "auto_reply_interval",
+            self.get_config_int(Config::AutoReplyInterval)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "remote_content",
+            self.get_config_int(Config::RemoteContent)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "badge_count_muted",
+            self.get_config_int(Config::BadgeCountMuted)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "badge_count_requests",
+            self.get_config_int(Config::BadgeCountRequests)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "badge_count_device_msgs",
+            self.get_config_int(Config::BadgeCountDeviceMsgs)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "notify_privacy",
+            self.get_config_int(Config::NotifyPrivacy)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "collapse_auto_replies",
+            self.get_config_bool(Config::CollapseAutoReplies)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "headless_bot",
+            self.get_config_int(Config::HeadlessBot).await?.to_string(),
+        );
         res.insert("number_of_chats", chats.to_string());
         res.insert("number_of_chat_messages", unblocked_msgs.to_string());
         res.insert("messages_in_contact_requests", request_msgs.to_string());
@@ -660,6 +925,7 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
         }
 
         res.insert("secondary_addrs", secondary_addrs);
+        res.insert("alias_addrs", alias_addrs);
         res.insert(
             "fetch_existing_msgs",
             self.get_config_int(Config::FetchExistingMsgs)
@@ -672,6 +938,12 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
                 .await?
                 .to_string(),
         );
+        res.insert(
+            "import_history_days",
+            self.get_config_int(Config::ImportHistoryDays)
+                .await?
+                .to_string(),
+        );
         res.insert(
             "show_emails",
             self.get_config_int(Config::ShowEmails).await?.to_string(),
@@ -690,6 +962,7 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
         res.insert("configured_sentbox_folder", configured_sentbox_folder);
         res.insert("configured_mvbox_folder", configured_mvbox_folder);
         res.insert("configured_trash_folder", configured_trash_folder);
+        res.insert("configured_spam_folder", configured_spam_folder);
         res.insert("mdns_enabled", mdns_enabled.to_string());
         res.insert("e2ee_enabled", e2ee_enabled.to_string());
         res.insert(
@@ -699,6 +972,11 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
         res.insert("bcc_self", bcc_self.to_string());
         res.insert("send_sync_msgs", send_sync_msgs.to_string());
         res.insert("disable_idle", disable_idle.to_string());
+        res.insert("offline", offline.to_string());
+        res.insert(
+            "webxdc_realtime_enabled",
+            webxdc_realtime_enabled.to_string(),
+        );
         res.insert("private_key_count", prv_key_cnt.to_string());
         res.insert("public_key_count", pub_key_cnt.to_string());
         res.insert("fingerprint", fingerprint_str);
@@ -724,6 +1002,16 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
                 .await?
                 .to_string(),
         );
+        res.insert(
+            "undo_timeout",
+            self.get_config_int(Config::UndoTimeout).await?.to_string(),
+        );
+        res.insert(
+            "encrypt_at_rest",
+            self.get_config_bool(Config::EncryptAtRest)
+                .await?
+                .to_string(),
+        );
         res.insert(
             "delete_to_trash",
             self.get_config(Config::DeleteToTrash)
@@ -736,6 +1024,14 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
                 .await?
                 .to_string(),
         );
+        res.insert(
+            "backup_time",
+            self.sql
+                .get_raw_config_int64(InternalConfig::BackupTime.as_ref())
+                .await?
+                .unwrap_or_default()
+                .to_string(),
+        );
         res.insert(
             "scan_all_folders_debounce_secs",
             self.get_config_int(Config::ScanAllFoldersDebounceSecs)
@@ -764,6 +1060,33 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
             "debug_logging",
             self.get_config_int(Config::DebugLogging).await?.to_string(),
         );
+        res.insert("log_filter", self.get_log_filter().to_string());
+        res.insert(
+            "auto_accept_rules",
+            self.get_config(Config::AutoAcceptRules)
+                .await?
+                .unwrap_or_default(),
+        );
+        res.insert(
+            "verify_dkim",
+            self.get_config_int(Config::VerifyDkim).await?.to_string(),
+        );
+        res.insert(
+            "spam_filter",
+            self.get_config_int(Config::SpamFilter).await?.to_string(),
+        );
+        res.insert(
+            "delete_requests_after",
+            self.get_config_int(Config::DeleteRequestsAfter)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "metrics_enabled",
+            self.get_config_int(Config::MetricsEnabled)
+                .await?
+                .to_string(),
+        );
         res.insert(
             "last_msg_id",
             self.get_config_int(Config::LastMsgId).await?.to_string(),
@@ -772,6 +1095,12 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
             "gossip_period",
             self.get_config_int(Config::GossipPeriod).await?.to_string(),
         );
+        res.insert(
+            "push_proxy_url",
+            self.get_config(Config::PushProxyUrl)
+                .await?
+                .unwrap_or_default(),
+        );
         res.insert(
             "verified_one_on_one_chats",
             self.get_config_bool(Config::VerifiedOneOnOneChats)
@@ -903,73 +1232,102 @@ pub async fn wait_next_msgs(&self) -> Result<Vec<MsgId>> {
     ///
     /// If `chat_id` is provided this searches only for messages in this chat, if `chat_id`
     /// is `None` this searches messages from all chats.
+    /// Messages stored encrypted, see [`Config::EncryptAtRest`], are not found.
     pub async fn search_msgs(&self, chat_id: Option<ChatId>, query: &str) -> Result<Vec<MsgId>> {
+        let filter = SearchFilter {
+            chat_id,
+            ..Default::default()
+        };
+        self.search_msgs_ex(query, &filter).await
+    }
+
+    /// Searches for messages containing the query string and matching the filter.
+    ///
+    /// The query may be empty if the filter restricts the search.
+    /// As for [`Context::search_msgs`], results of a search in a single chat are sorted
+    /// by timestamp, results of a global search are limited to the last 1000 received messages.
+    pub async fn search_msgs_ex(&self, query: &str, filter: &SearchFilter) -> Result<Vec<MsgId>> {
         let real_query = query.trim();
-        if real_query.is_empty() {
+        if real_query.is_empty() && filter.is_empty() {
             return Ok(Vec::new());
         }
         let str_like_in_text = format!("%{real_query}%");
 
-        let list = if let Some(chat_id) = chat_id {
-            self.sql
-                .query_map(
-                    "SELECT m.id AS id
-                 FROM msgs m
-                 LEFT JOIN contacts ct
-                        ON m.from_id=ct.id
-                 WHERE m.chat_id=?
-                   AND m.hidden=0
-                   AND ct.blocked=0
-                   AND txt LIKE ?
-                 ORDER BY m.timestamp,m.id;",
-                    (chat_id, str_like_in_text),
-                    |row| row.get::<_, MsgId>("id"),
-                    |rows| {
-                        let mut ret = Vec::new();
-                        for id in rows {
-                            ret.push(id?);
-                        }
-                        Ok(ret)
-                    },
-                )
-                .await?
+        let mut conditions = String::new();
+        let mut params: Vec<&dyn sql::ToSql> = Vec::new();
+        if !real_query.is_empty() {
+            // Texts stored encrypted, see `Config::EncryptAtRest`, can not be searched.
+            conditions += " AND m.txt LIKE ? AND m.encrypted_at_rest=0";
+            params.push(&str_like_in_text);
+        }
+        if let Some(chat_id) = &filter.chat_id {
+            conditions += " AND m.chat_id=?";
+            params.push(chat_id);
         } else {
-            // For performance reasons results are sorted only by `id`, that is in the order of
-            // message reception.
-            //
-            // Unlike chat view, sorting by `timestamp` is not necessary but slows down the query by
-            // ~25% according to benchmarks.
-            //
-            // To speed up incremental search, where queries for few characters usually return lots
-            // of unwanted results that are discarded moments later, we added `LIMIT 1000`.
-            // According to some tests, this limit speeds up eg. 2 character searches by factor 10.
-            // The limit is documented and UI may add a hint when getting 1000 results.
-            self.sql
-                .query_map(
-                    "SELECT m.id AS id
-                 FROM msgs m
-                 LEFT JOIN contacts ct
-                        ON m.from_id=ct.id
-                 LEFT JOIN chats c
-                        ON m.chat_id=c.id
-                 WHERE m.chat_id>9
-                   AND m.hidden=0
-                   AND c.blocked!=1
-                   AND ct.blocked=0
-                   AND m.txt LIKE ?
-                 ORDER BY m.id DESC LIMIT 1000",
-                    (str_like_in_text,),
-                    |row| row.get::<_, MsgId>("id"),
-                    |rows| {
-                        let mut ret = Vec::new();
-                        for id in rows {
-                            ret.push(id?);
-                        }
-                        Ok(ret)
-                    },
-                )
-                .await?
+            conditions += " AND m.chat_id>9 AND c.blocked!=1";
+        }
+        if let Some(from_id) = &filter.from_id {
+            conditions += " AND m.from_id=?";
+            params.push(from_id);
+        }
+        if let Some(after) = &filter.after {
+            conditions += " AND m.timestamp>=?";
+            params.push(after);
+        }
+        if let Some(before) = &filter.before {
+            conditions += " AND m.timestamp<?";
+            params.push(before);
+        }
+        if let Some(viewtype) = &filter.viewtype {
+            conditions += " AND m.type=?";
+            params.push(viewtype);
+        }
+        if filter.has_attachment {
+            conditions += " AND m.type NOT IN (?,?)";
+            params.push(&Viewtype::Text);
+            params.push(&Viewtype::VideochatInvitation);
+        }
+
+        // For performance reasons results of a global search are sorted only by `id`,
+        // that is in the order of message reception.
+        //
+        // Unlike chat view, sorting by `timestamp` is not necessary but slows down the query by
+        // ~25% according to benchmarks.
+        //
+        // To speed up incremental search, where queries for few characters usually return lots
+        // of unwanted results that are discarded moments later, we added `LIMIT 1000`.
+        // According to some tests, this limit speeds up eg. 2 character searches by factor 10.
+        // The limit is documented and UI may add a hint when getting 1000 results.
+        let order = if filter.chat_id.is_some() {
+            "ORDER BY m.timestamp,m.id"
+        } else {
+            "ORDER BY m.id DESC LIMIT 1000"
         };
+        let list = self
+            .sql
+            .query_map(
+                &format!(
+                    "SELECT m.id AS id
+                     FROM msgs m
+                     LEFT JOIN contacts ct
+                            ON m.from_id=ct.id
+                     LEFT JOIN chats c
+                            ON m.chat_id=c.id
+                     WHERE m.hidden=0
+                       AND ct.blocked=0{conditions}
+                     {order}"
+                ),
+                rusqlite::params_from_iter(params),
+                |row| row.get::<_, MsgId>("id"),
+                |rows| {
+                    let mut ret = Vec::new();
+                    for id in rows {
+                        ret.push(id?);
+                    }
+                    Ok(ret)
+                },
+            )
+            .await?;
 
         Ok(list)
     }
@@ -1056,7 +1414,7 @@ mod tests {
     use crate::contact::ContactId;
     use crate::message::{Message, Viewtype};
     use crate::receive_imf::receive_imf;
-    use crate::test_utils::TestContext;
+    use crate::test_utils::{TestContext, TestContextManager};
     use crate::tools::create_outgoing_rfc724_mid;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -1294,6 +1652,7 @@ async fn test_get_info_completeness() {
             "mail_port",
             "mail_security",
             "notify_about_wrong_pw",
+            "push_device_token",
             "save_mime_headers",
             "selfstatus",
             "send_server",
@@ -1303,10 +1662,12 @@ async fn test_get_info_completeness() {
             "send_security",
             "server_flags",
             "smtp_certificate_checks",
+            "smtp_oauth2",
             "socks5_host",
             "socks5_port",
             "socks5_user",
             "socks5_password",
+            "ui_settings",
         ];
         let t = TestContext::new().await;
         let info = t.get_info().await.unwrap();
@@ -1380,6 +1741,85 @@ async fn test_search_msgs() -> Result<()> {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_search_msgs_ex() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let alice_chat_id = alice.create_chat(&bob).await.id;
+        let sent = alice.send_text(alice_chat_id, "hello bob").await;
+        let bob_chat_id = bob.recv_msg(&sent).await.chat_id;
+
+        let sent = bob.send_text(bob_chat_id, "hello alice").await;
+        let text_msg = alice.recv_msg(&sent).await;
+        let file = bob.get_blobdir().join("image.png");
+        tokio::fs::write(&file, include_bytes!("../test-data/image/avatar64x64.png")).await?;
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file(file.to_str().unwrap(), None);
+        msg.set_text("hello again".to_string());
+        let sent = bob.send_msg(bob_chat_id, &mut msg).await;
+        let image_msg = alice.recv_msg(&sent).await;
+        let bob_id = alice.add_or_lookup_contact(&bob).await.id;
+
+        // An empty filter does not restrict the search.
+        assert_eq!(
+            alice
+                .search_msgs_ex("hello", &SearchFilter::default())
+                .await?
+                .len(),
+            3
+        );
+        // An empty query without filter finds nothing.
+        assert!(alice
+            .search_msgs_ex("", &SearchFilter::default())
+            .await?
+            .is_empty());
+
+        let filter = SearchFilter {
+            from_id: Some(bob_id),
+            ..Default::default()
+        };
+        assert_eq!(
+            alice.search_msgs_ex("", &filter).await?,
+            vec![image_msg.id, text_msg.id]
+        );
+
+        let filter = SearchFilter {
+            chat_id: Some(alice_chat_id),
+            has_attachment: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            alice.search_msgs_ex("hello", &filter).await?,
+            vec![image_msg.id]
+        );
+        let filter = SearchFilter {
+            viewtype: Some(Viewtype::Image),
+            ..Default::default()
+        };
+        assert_eq!(
+            alice.search_msgs_ex("again", &filter).await?,
+            vec![image_msg.id]
+        );
+        assert!(alice.search_msgs_ex("alice", &filter).await?.is_empty());
+
+        let filter = SearchFilter {
+            after: Some(text_msg.timestamp_sort),
+            ..Default::default()
+        };
+        assert!(alice
+            .search_msgs_ex("alice", &filter)
+            .await?
+            .contains(&text_msg.id));
+        let filter = SearchFilter {
+            before: Some(text_msg.timestamp_sort),
+            ..Default::default()
+        };
+        assert!(alice.search_msgs_ex("alice", &filter).await?.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_search_unaccepted_requests() -> Result<()> {
         let t = TestContext::new_alice().await;
@@ -1593,4 +2033,14 @@ async fn test_get_next_msgs() -> Result<()> {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_background_fetch_unconfigured() -> Result<()> {
+        let t = TestContext::new().await;
+        t.background_fetch(Duration::from_secs(1)).await?;
+        t.evtracker
+            .get_matching(|evt| matches!(evt, EventType::BackgroundFetchDone))
+            .await;
+        Ok(())
+    }
 }
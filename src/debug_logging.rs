@@ -164,5 +164,6 @@ pub(crate) async fn set_debug_logging_xdc(ctx: &Context, id: Option<MsgId>) -> a
             info!(ctx, "removing logging webxdc");
         }
     }
+    ctx.emit_event(EventType::ConfigChanged(Config::DebugLogging));
     Ok(())
 }
@@ -78,9 +78,11 @@ pub(crate) async fn prepare_decryption(
             from: from.to_string(),
             autocrypt_header: None,
             peerstate: None,
+            known_fingerprints: HashSet::new(),
             message_time,
             dkim_results: DkimResults {
                 dkim_passed: false,
+                dkim_passed_by_server: false,
                 dkim_should_work: false,
                 allow_keychange: true,
             },
@@ -109,6 +111,19 @@ pub(crate) async fn prepare_decryption(
 
     let dkim_results = handle_authres(context, mail, from, message_time).await?;
 
+    let known_fingerprints = Peerstate::from_addr(context, from)
+        .await?
+        .map(|peerstate| {
+            [
+                peerstate.public_key_fingerprint,
+                peerstate.verified_key_fingerprint,
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        })
+        .unwrap_or_default();
+
     let peerstate = get_autocrypt_peerstate(
         context,
         from,
@@ -123,6 +138,7 @@ pub(crate) async fn prepare_decryption(
         from: from.to_string(),
         autocrypt_header,
         peerstate,
+        known_fingerprints,
         message_time,
         dkim_results,
     })
@@ -136,6 +152,9 @@ pub struct DecryptionInfo {
     pub autocrypt_header: Option<Aheader>,
     /// The peerstate that will be used to validate the signatures
     pub peerstate: Option<Peerstate>,
+    /// Fingerprints of the sender keys known before this message,
+    /// i.e. not taken from the Autocrypt header of this message.
+    pub(crate) known_fingerprints: HashSet<Fingerprint>,
     /// The timestamp when the message was sent.
     /// If this is older than the peerstate's last_seen, this probably
     /// means out-of-order message arrival, We don't modify the
@@ -2,69 +2,386 @@
 //!
 //! A module to remove HTML tags from the email text
 
-use std::io::BufRead;
-
 use once_cell::sync::Lazy;
-use quick_xml::{
-    events::{BytesEnd, BytesStart, BytesText},
-    Reader,
-};
+use quick_xml::events::{BytesStart, Event};
 
 use crate::simplify::{simplify_quote, SimplifiedText};
 
-struct Dehtml {
-    strbuilder: String,
-    quote: String,
-    add_text: AddText,
-    last_href: Option<String>,
-    /// GMX wraps a quote in `<div name="quote">`. After a `<div name="quote">`, this count is
-    /// increased at each `<div>` and decreased at each `</div>`. This way we know when the quote ends.
-    /// If this is > `0`, then we are inside a `<div name="quote">`
-    divs_since_quote_div: u32,
-    /// Everything between `<div name="quote">` and `<div name="quoted-content">` is usually metadata
-    /// If this is > `0`, then we are inside a `<div name="quoted-content">`.
-    divs_since_quoted_content_div: u32,
-    /// All-Inkl just puts the quote into `<blockquote> </blockquote>`. This count is
-    /// increased at each `<blockquote>` and decreased at each `</blockquote>`.
-    blockquotes_since_blockquote: u32,
+/// Elements which never have content, even if there is no closing tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is not displayed.
+const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "title"];
+
+/// Elements separated from the surrounding text by empty lines.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "center", "dl", "div", "footer", "form", "h1", "h2", "h3", "h4",
+    "h5", "h6", "header", "nav", "p", "section", "table",
+];
+
+/// Maximum nesting depth of the document tree.
+///
+/// Tags nested deeper are ignored while their content is kept,
+/// so that rendering does not run out of stack.
+const MAX_DEPTH: usize = 128;
+
+/// An element of the document tree.
+#[derive(Debug, Default)]
+struct Element {
+    /// Lowercase tag name.
+    name: String,
+
+    /// Attributes as lowercase names and decoded values.
+    attrs: Vec<(String, String)>,
+
+    children: Vec<Node>,
+
+    /// True if the element was explicitly closed,
+    /// false if it was closed implicitly, e.g. at the end of the document.
+    closed: bool,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn has_attr_value(&self, value: &str) -> bool {
+        self.attrs.iter().any(|(_, v)| v == value)
+    }
+
+    /// Returns the first descendant `<div>` having an attribute with the given value.
+    fn find_div(&self, value: &str) -> Option<&Element> {
+        self.children.iter().find_map(|child| match child {
+            Node::Element(element) if element.name == "div" && element.has_attr_value(value) => {
+                Some(element)
+            }
+            Node::Element(element) => element.find_div(value),
+            Node::Text(_) => None,
+        })
+    }
 }
 
-impl Dehtml {
-    /// Returns true if HTML parser is currently inside the quote.
-    fn is_quote(&self) -> bool {
-        self.divs_since_quoted_content_div > 0 || self.blockquotes_since_blockquote > 0
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// Builds the document tree from the events of the XML parser,
+/// fixing up the structure like HTML parsers do,
+/// e.g. a `<li>` closes the previous list item.
+#[derive(Default)]
+struct TreeBuilder {
+    root: Element,
+
+    /// Currently open elements, innermost last.
+    open: Vec<Element>,
+}
+
+impl TreeBuilder {
+    fn current(&mut self) -> &mut Element {
+        self.open.last_mut().unwrap_or(&mut self.root)
+    }
+
+    fn text(&mut self, text: String) {
+        if let Some(Node::Text(last)) = self.current().children.last_mut() {
+            *last += &text;
+        } else {
+            self.current().children.push(Node::Text(text));
+        }
+    }
+
+    /// Closes the open elements from the given position of the stack on.
+    fn close_from(&mut self, pos: usize) {
+        while self.open.len() > pos {
+            if let Some(element) = self.open.pop() {
+                self.current().children.push(Node::Element(element));
+            }
+        }
+    }
+
+    /// Returns the position of the innermost open element named one of `names`
+    /// unless an element named one of `boundaries` is open inside of it.
+    fn find_open(&self, names: &[&str], boundaries: &[&str]) -> Option<usize> {
+        for (pos, element) in self.open.iter().enumerate().rev() {
+            if names.contains(&element.name.as_str()) {
+                return Some(pos);
+            }
+            if boundaries.contains(&element.name.as_str()) {
+                return None;
+            }
+        }
+        None
     }
 
+    fn start(&mut self, element: Element, empty: bool) {
+        let implied: Option<(&[&str], &[&str])> = match element.name.as_str() {
+            "li" => Some((&["li"], &["ol", "ul"])),
+            "tr" => Some((&["tr"], &["table"])),
+            "td" | "th" => Some((&["td", "th"], &["tr", "table"])),
+            "ol" | "ul" | "pre" | "blockquote" | "hr" => {
+                Some((&["p"], &["blockquote", "div", "li", "table", "td", "th"]))
+            }
+            name if BLOCK_ELEMENTS.contains(&name) => {
+                Some((&["p"], &["blockquote", "div", "li", "table", "td", "th"]))
+            }
+            _ => None,
+        };
+        if let Some((names, boundaries)) = implied {
+            if let Some(pos) = self.find_open(names, boundaries) {
+                self.close_from(pos);
+            }
+        }
+
+        if empty || VOID_ELEMENTS.contains(&element.name.as_str()) {
+            let element = Element {
+                closed: true,
+                ..element
+            };
+            self.current().children.push(Node::Element(element));
+        } else if self.open.len() < MAX_DEPTH {
+            self.open.push(element);
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        let Some(pos) = self.open.iter().rposition(|e| e.name == name) else {
+            // Closing tag without an open element.
+            return;
+        };
+        if let Some(element) = self.open.get_mut(pos) {
+            element.closed = true;
+        }
+        self.close_from(pos);
+    }
+
+    fn finish(mut self) -> Element {
+        self.close_from(0);
+        self.root
+    }
+}
+
+/// Renders the document tree as text.
+struct Renderer {
+    text: String,
+    quote: String,
+
+    /// True if rendering into the quote.
+    in_quote: bool,
+
+    /// True inside `<pre>`.
+    preserve_line_ends: bool,
+
+    /// Currently open lists, innermost last.
+    /// `None` for unordered lists, the number of the next item for ordered lists.
+    lists: Vec<Option<u32>>,
+}
+
+impl Renderer {
     /// Returns the buffer where the text should be written.
     ///
-    /// If the parser is inside the quote, returns the quote buffer.
-    fn get_buf(&mut self) -> &mut String {
-        if self.is_quote() {
+    /// If the renderer is inside the quote, returns the quote buffer.
+    fn buf(&mut self) -> &mut String {
+        if self.in_quote {
             &mut self.quote
         } else {
-            &mut self.strbuilder
+            &mut self.text
         }
     }
 
-    fn get_add_text(&self) -> AddText {
-        if self.divs_since_quote_div > 0 && self.divs_since_quoted_content_div == 0 {
-            AddText::No // Everything between `<div name="quoted">` and `<div name="quoted_content">` is metadata which we don't want
-        } else {
-            self.add_text
+    /// Starts a new paragraph.
+    fn paragraph(&mut self) {
+        let buf = self.buf();
+        if !buf.is_empty() {
+            *buf += "\n\n";
         }
     }
-}
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum AddText {
-    /// Inside `<script>`, `<style>` and similar tags
-    /// which contents should not be displayed.
-    No,
+    fn push_text(&mut self, text: &str) {
+        static LINE_RE: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"(\r?\n)+").unwrap());
+
+        if self.preserve_line_ends {
+            *self.buf() += LINE_RE.replace_all(text, "\n").as_ref();
+            return;
+        }
+
+        // Replace all line ends with spaces.
+        // E.g. `\r\n\r\n` is replaced with one space.
+        let text = LINE_RE.replace_all(text, " ");
 
-    YesRemoveLineEnds,
+        // Add a space if `text` starts with a space
+        // and there is no whitespace at the end of the buffer yet.
+        // Trim the rest of leading whitespace from `text`.
+        let buf = self.buf();
+        if !buf.ends_with(' ') && !buf.ends_with('\n') && text.starts_with(' ') {
+            *buf += " ";
+        }
+        *buf += text.trim_start();
+    }
 
-    /// Inside `<pre>`.
-    YesPreserveLineEnds,
+    /// Renders `children` into a separate buffer and returns it.
+    fn render_apart(&mut self, children: &[Node]) -> String {
+        let outer = std::mem::take(self.buf());
+        self.render_children(children);
+        std::mem::replace(self.buf(), outer)
+    }
+
+    fn render_children(&mut self, children: &[Node]) {
+        for child in children {
+            match child {
+                Node::Element(element) => self.render_element(element),
+                Node::Text(text) => self.push_text(text),
+            }
+        }
+    }
+
+    fn render_quote(&mut self, children: &[Node]) {
+        let in_quote = std::mem::replace(&mut self.in_quote, true);
+        self.render_children(children);
+        self.in_quote = in_quote;
+    }
+
+    fn render_element(&mut self, element: &Element) {
+        let name = element.name.as_str();
+        match name {
+            name if HIDDEN_ELEMENTS.contains(&name) => {}
+            "br" => *self.buf() += "\n",
+            "hr" => *self.buf() += "\n\n---\n\n",
+            "pre" => {
+                self.paragraph();
+                let preserve_line_ends = std::mem::replace(&mut self.preserve_line_ends, true);
+                self.render_children(&element.children);
+                self.preserve_line_ends = preserve_line_ends;
+                *self.buf() += "\n\n";
+            }
+            // GMX wraps a quote in `<div name="quote">`.
+            // Everything before `<div name="quoted-content">` is usually metadata.
+            "div" if element.has_attr_value("quote") => {
+                if let Some(content) = element.find_div("quoted-content") {
+                    self.render_quote(&content.children);
+                }
+                self.paragraph();
+            }
+            "div" if element.has_attr_value("quoted-content") => {
+                self.render_quote(&element.children);
+                self.paragraph();
+            }
+            // All-Inkl just puts the quote into `<blockquote> </blockquote>`.
+            "blockquote" => self.render_quote(&element.children),
+            "a" => self.render_link(element),
+            "b" | "strong" => self.render_emphasis(element, "*"),
+            "i" | "em" => self.render_emphasis(element, "_"),
+            "ul" | "ol" => {
+                let first = if name == "ol" {
+                    Some(
+                        element
+                            .attr("start")
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(1),
+                    )
+                } else {
+                    None
+                };
+                self.lists.push(first);
+                self.render_children(&element.children);
+                self.lists.pop();
+                let end = if self.lists.is_empty() { "\n\n" } else { "\n" };
+                *self.buf() += end;
+            }
+            "li" => {
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let buf = self.buf();
+                if !buf.ends_with('\n') {
+                    *buf += "\n";
+                }
+                *buf += &format!("{indent}{bullet}");
+                self.render_children(&element.children);
+            }
+            "tr" => self.render_row(element),
+            name if BLOCK_ELEMENTS.contains(&name) => {
+                self.paragraph();
+                self.render_children(&element.children);
+                *self.buf() += "\n\n";
+            }
+            _ => self.render_children(&element.children),
+        }
+    }
+
+    fn render_emphasis(&mut self, element: &Element, marker: &str) {
+        *self.buf() += marker;
+        self.render_children(&element.children);
+        if element.closed {
+            *self.buf() += marker;
+        }
+    }
+
+    /// Renders a link as `[text](url)`
+    /// or just as the text if the text is the URL itself.
+    fn render_link(&mut self, element: &Element) {
+        let href = element.attr("href").unwrap_or_default();
+        if href.is_empty() {
+            self.render_children(&element.children);
+            return;
+        }
+        let link_text = self.render_apart(&element.children);
+        let text = link_text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let buf = self.buf();
+        if text == href
+            || href.strip_prefix("mailto:") == Some(text)
+            || href.strip_suffix('/') == Some(text)
+        {
+            *buf += &link_text;
+        } else {
+            *buf += &format!("[{link_text}]({href})");
+        }
+    }
+
+    /// Renders a table row.
+    ///
+    /// Cells of data tables are separated by ` | `.
+    /// Rows with a single cell or cells containing several lines
+    /// are most likely used for layout, their cells are rendered as separate paragraphs.
+    fn render_row(&mut self, element: &Element) {
+        let mut cells = Vec::new();
+        for child in &element.children {
+            match child {
+                Node::Element(cell) if cell.name == "td" || cell.name == "th" => {
+                    let text = dehtml_cleanup(self.render_apart(&cell.children));
+                    if !text.is_empty() {
+                        cells.push(text);
+                    }
+                }
+                Node::Element(other) => self.render_element(other),
+                Node::Text(_) => {}
+            }
+        }
+        if cells.len() > 1 && cells.iter().all(|cell| !cell.contains('\n')) {
+            *self.buf() += &format!("\n{}", cells.join(" | "));
+        } else {
+            for cell in cells {
+                self.paragraph();
+                *self.buf() += &cell;
+                *self.buf() += "\n\n";
+            }
+        }
+    }
 }
 
 pub(crate) fn dehtml(buf: &str) -> Option<SimplifiedText> {
@@ -117,48 +434,46 @@ fn dehtml_cleanup(mut text: String) -> String {
 
 fn dehtml_quick_xml(buf: &str) -> (String, String) {
     let buf = buf.trim().trim_start_matches("<!doctype html>");
+    let root = parse_html(buf);
 
-    let mut dehtml = Dehtml {
-        strbuilder: String::with_capacity(buf.len()),
+    let mut renderer = Renderer {
+        text: String::with_capacity(buf.len()),
         quote: String::new(),
-        add_text: AddText::YesRemoveLineEnds,
-        last_href: None,
-        divs_since_quote_div: 0,
-        divs_since_quoted_content_div: 0,
-        blockquotes_since_blockquote: 0,
+        in_quote: false,
+        preserve_line_ends: false,
+        lists: Vec::new(),
     };
+    renderer.render_children(&root.children);
+
+    (renderer.text, renderer.quote)
+}
 
+/// Parses HTML into a document tree.
+///
+/// Errors are skipped, so that as much text as possible is shown even for invalid HTML.
+fn parse_html(buf: &str) -> Element {
     let mut reader = quick_xml::Reader::from_str(buf);
     reader.check_end_names(false);
 
+    let mut builder = TreeBuilder::default();
     let mut buf = Vec::new();
 
     loop {
         match reader.read_event_into(&mut buf) {
-            Ok(quick_xml::events::Event::Start(ref e)) => {
-                dehtml_starttag_cb(e, &mut dehtml, &reader)
-            }
-            Ok(quick_xml::events::Event::End(ref e)) => dehtml_endtag_cb(e, &mut dehtml),
-            Ok(quick_xml::events::Event::Text(ref e)) => dehtml_text_cb(e, &mut dehtml),
-            Ok(quick_xml::events::Event::CData(e)) => match e.escape() {
-                Ok(e) => dehtml_text_cb(&e, &mut dehtml),
-                Err(e) => {
-                    eprintln!(
-                        "CDATA escape error at position {}: {:?}",
-                        reader.buffer_position(),
-                        e,
-                    );
-                }
-            },
-            Ok(quick_xml::events::Event::Empty(ref e)) => {
-                // Handle empty tags as a start tag immediately followed by end tag.
+            Ok(Event::Start(ref e)) => builder.start(element(e, &reader), false),
+            Ok(Event::Empty(ref e)) => {
                 // For example, `<p/>` is treated as `<p></p>`.
-                dehtml_starttag_cb(e, &mut dehtml, &reader);
-                dehtml_endtag_cb(
-                    &BytesEnd::new(String::from_utf8_lossy(e.name().as_ref())),
-                    &mut dehtml,
-                );
+                builder.start(element(e, &reader), true)
             }
+            Ok(Event::End(ref e)) => builder.end(
+                &String::from_utf8_lossy(e.name().as_ref())
+                    .trim()
+                    .to_lowercase(),
+            ),
+            Ok(Event::Text(ref e)) => {
+                builder.text(escaper::decode_html_buf_sloppy(e as &[_]).unwrap_or_default())
+            }
+            Ok(Event::CData(ref e)) => builder.text(String::from_utf8_lossy(e).into_owned()),
             Err(e) => {
                 eprintln!(
                     "Parse html error: Error at position {}: {:?}",
@@ -166,189 +481,40 @@ fn dehtml_quick_xml(buf: &str) -> (String, String) {
                     e
                 );
             }
-            Ok(quick_xml::events::Event::Eof) => break,
+            Ok(Event::Eof) => break,
             _ => (),
         }
         buf.clear();
     }
 
-    (dehtml.strbuilder, dehtml.quote)
-}
-
-fn dehtml_text_cb(event: &BytesText, dehtml: &mut Dehtml) {
-    static LINE_RE: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"(\r?\n)+").unwrap());
-
-    if dehtml.get_add_text() == AddText::YesPreserveLineEnds
-        || dehtml.get_add_text() == AddText::YesRemoveLineEnds
-    {
-        let last_added = escaper::decode_html_buf_sloppy(event as &[_]).unwrap_or_default();
-
-        if dehtml.get_add_text() == AddText::YesRemoveLineEnds {
-            // Replace all line ends with spaces.
-            // E.g. `\r\n\r\n` is replaced with one space.
-            let last_added = LINE_RE.replace_all(&last_added, " ");
-
-            // Add a space if `last_added` starts with a space
-            // and there is no whitespace at the end of the buffer yet.
-            // Trim the rest of leading whitespace from `last_added`.
-            let buf = dehtml.get_buf();
-            if !buf.ends_with(' ') && !buf.ends_with('\n') && last_added.starts_with(' ') {
-                *buf += " ";
-            }
-
-            *buf += last_added.trim_start();
-        } else {
-            *dehtml.get_buf() += LINE_RE.replace_all(&last_added, "\n").as_ref();
-        }
-    }
-}
-
-fn dehtml_endtag_cb(event: &BytesEnd, dehtml: &mut Dehtml) {
-    let tag = String::from_utf8_lossy(event.name().as_ref())
-        .trim()
-        .to_lowercase();
-
-    match tag.as_str() {
-        "style" | "script" | "title" | "pre" => {
-            *dehtml.get_buf() += "\n\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
-        }
-        "div" => {
-            pop_tag(&mut dehtml.divs_since_quote_div);
-            pop_tag(&mut dehtml.divs_since_quoted_content_div);
-
-            *dehtml.get_buf() += "\n\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
-        }
-        "a" => {
-            if let Some(ref last_href) = dehtml.last_href.take() {
-                let buf = dehtml.get_buf();
-                if buf.ends_with('[') {
-                    buf.truncate(buf.len() - 1);
-                } else {
-                    *buf += "](";
-                    *buf += last_href;
-                    *buf += ")";
-                }
-            }
-        }
-        "b" | "strong" => {
-            if dehtml.get_add_text() != AddText::No {
-                *dehtml.get_buf() += "*";
-            }
-        }
-        "i" | "em" => {
-            if dehtml.get_add_text() != AddText::No {
-                *dehtml.get_buf() += "_";
-            }
-        }
-        "blockquote" => pop_tag(&mut dehtml.blockquotes_since_blockquote),
-        _ => {}
-    }
+    builder.finish()
 }
 
-fn dehtml_starttag_cb<B: std::io::BufRead>(
-    event: &BytesStart,
-    dehtml: &mut Dehtml,
-    reader: &quick_xml::Reader<B>,
-) {
-    let tag = String::from_utf8_lossy(event.name().as_ref())
+fn element<B: std::io::BufRead>(event: &BytesStart, reader: &quick_xml::Reader<B>) -> Element {
+    let name = String::from_utf8_lossy(event.name().as_ref())
         .trim()
         .to_lowercase();
-
-    match tag.as_str() {
-        "p" | "table" | "td" => {
-            if !dehtml.strbuilder.is_empty() {
-                *dehtml.get_buf() += "\n\n";
-            }
-            dehtml.add_text = AddText::YesRemoveLineEnds;
-        }
-        #[rustfmt::skip]
-        "div" => {
-            maybe_push_tag(event, reader, "quote", &mut dehtml.divs_since_quote_div);
-            maybe_push_tag(event, reader, "quoted-content", &mut dehtml.divs_since_quoted_content_div);
-
-            *dehtml.get_buf() += "\n\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
-        }
-        "br" => {
-            *dehtml.get_buf() += "\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
-        }
-        "style" | "script" | "title" => {
-            dehtml.add_text = AddText::No;
-        }
-        "pre" => {
-            *dehtml.get_buf() += "\n\n";
-            dehtml.add_text = AddText::YesPreserveLineEnds;
-        }
-        "a" => {
-            if let Some(href) = event
-                .html_attributes()
-                .filter_map(|attr| attr.ok())
-                .find(|attr| {
-                    String::from_utf8_lossy(attr.key.as_ref())
-                        .trim()
-                        .to_lowercase()
-                        == "href"
-                })
-            {
-                let href = href
-                    .decode_and_unescape_value(reader)
-                    .unwrap_or_default()
-                    .to_lowercase();
-
-                if !href.is_empty() {
-                    dehtml.last_href = Some(href);
-                    *dehtml.get_buf() += "[";
-                }
-            }
-        }
-        "b" | "strong" => {
-            if dehtml.get_add_text() != AddText::No {
-                *dehtml.get_buf() += "*";
-            }
-        }
-        "i" | "em" => {
-            if dehtml.get_add_text() != AddText::No {
-                *dehtml.get_buf() += "_";
-            }
-        }
-        "blockquote" => dehtml.blockquotes_since_blockquote += 1,
-        _ => {}
-    }
-}
-
-/// In order to know when a specific tag is closed, we need to count the opening and closing tags.
-/// The `counts`s are stored in the `Dehtml` struct.
-fn pop_tag(count: &mut u32) {
-    if *count > 0 {
-        *count -= 1;
-    }
-}
-
-/// In order to know when a specific tag is closed, we need to count the opening and closing tags.
-/// The `counts`s are stored in the `Dehtml` struct.
-fn maybe_push_tag(
-    event: &BytesStart,
-    reader: &Reader<impl BufRead>,
-    tag_name: &str,
-    count: &mut u32,
-) {
-    if *count > 0 || tag_contains_attr(event, reader, tag_name) {
-        *count += 1;
-    }
-}
-
-fn tag_contains_attr(event: &BytesStart, reader: &Reader<impl BufRead>, name: &str) -> bool {
-    event.attributes().any(|r| {
-        r.map(|a| {
-            a.decode_and_unescape_value(reader)
-                .map(|v| v == name)
-                .unwrap_or(false)
+    let attrs = event
+        .html_attributes()
+        .filter_map(|attr| attr.ok())
+        .map(|attr| {
+            let key = String::from_utf8_lossy(attr.key.as_ref())
+                .trim()
+                .to_lowercase();
+            let value = attr
+                .decode_and_unescape_value(reader)
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            (key, value)
         })
-        .unwrap_or(false)
-    })
+        .collect();
+    Element {
+        name,
+        attrs,
+        children: Vec::new(),
+        closed: false,
+    }
 }
 
 pub fn dehtml_manually(buf: &str) -> String {
@@ -458,6 +624,77 @@ fn test_dehtml_parse_href() {
         assert_eq!(plain, "[text](url)");
     }
 
+    #[test]
+    fn test_dehtml_parse_href_case() {
+        let html = "<a href='https://example.org/Foo?Bar=1'>Link</a>";
+        assert_eq!(
+            dehtml(html).unwrap().text,
+            "[Link](https://example.org/Foo?Bar=1)"
+        );
+
+        // Do not repeat the URL if it is the link text.
+        let html = "See <a href='https://example.org/'>https://example.org</a>.";
+        assert_eq!(dehtml(html).unwrap().text, "See https://example.org.");
+        let html = "<a href='mailto:bob@example.net'>bob@example.net</a>";
+        assert_eq!(dehtml(html).unwrap().text, "bob@example.net");
+    }
+
+    #[test]
+    fn test_dehtml_table() {
+        let html = "<p>Order:</p><table>\
+                    <tr><th>Item</th><th>Price</th></tr>\
+                    <tr><td>Apple</td><td>1 EUR</td></tr>\
+                    </table><p>Thanks</p>";
+        assert_eq!(
+            dehtml(html).unwrap().text,
+            "Order:\n\nItem | Price\nApple | 1 EUR\n\nThanks"
+        );
+    }
+
+    #[test]
+    fn test_dehtml_lists() {
+        let html = "<h1>Agenda</h1><ol><li>Intro</li><li>Topics<ul><li>foo</li><li>bar</li></ul></li></ol>Done";
+        assert_eq!(
+            dehtml(html).unwrap().text,
+            "Agenda\n\n1. Intro\n2. Topics\n  - foo\n  - bar\n\nDone"
+        );
+    }
+
+    #[test]
+    fn test_dehtml_unclosed_list_items() {
+        let html = "<ul><li>foo<li>bar<ol start=3><li>baz</ol><li>qux</ul>";
+        assert_eq!(dehtml(html).unwrap().text, "- foo\n- bar\n  3. baz\n- qux");
+    }
+
+    #[test]
+    fn test_dehtml_layout_table() {
+        let html = "<table><tr><td><h1>Newsletter</h1><p>News</p></td></tr>\
+                    <tr><td><table><tr><td>A</td><td>B</td></tr></table></td></tr>\
+                    <tr><td>Unsubscribe</td></tr></table>";
+        assert_eq!(
+            dehtml(html).unwrap().text,
+            "Newsletter\n\nNews\n\nA | B\n\nUnsubscribe"
+        );
+    }
+
+    #[test]
+    fn test_dehtml_blockquote() {
+        let html = "<p>Reply</p><blockquote><p>Quoted <b>text</b></p></blockquote>";
+        let plain = dehtml(html).unwrap();
+        assert_eq!(plain.text, "Reply");
+        assert_eq!(plain.top_quote.as_deref(), Some("Quoted *text*"));
+    }
+
+    #[test]
+    fn test_dehtml_deeply_nested() {
+        let html = format!(
+            "{}text{}",
+            "<div>".repeat(100_000),
+            "</div>".repeat(100_000)
+        );
+        assert_eq!(dehtml(&html).unwrap().text, "text");
+    }
+
     #[test]
     fn test_dehtml_bold_text() {
         let html = "<!DOCTYPE name [<!DOCTYPE ...>]><!-- comment -->text <b><?php echo ... ?>bold</b><![CDATA[<>]]>";
@@ -527,6 +764,6 @@ async fn test_quote_div() {
     fn test_spaces() {
         let input = include_str!("../test-data/spaces.html");
         let txt = dehtml(input).unwrap();
-        assert_eq!(txt.text, "Welcome back to Strolling!\n\nHey there,\n\nWelcome back! Use this link to securely sign in to your Strolling account:\n\nSign in to Strolling\n\nFor your security, the link will expire in 24 hours time.\n\nSee you soon!\n\nYou can also copy\n\nhttps://strolling.rosano.ca/members/?token=XXX\n\nIf you did not make this request, you can safely ignore this email.\n\nThis message was sent from [strolling.rosano.ca](https://strolling.rosano.ca/) to [alice@example.org](mailto:alice@example.org)");
+        assert_eq!(txt.text, "Welcome back to Strolling!\n\nHey there,\n\nWelcome back! Use this link to securely sign in to your Strolling account:\n\nSign in to Strolling\n\nFor your security, the link will expire in 24 hours time.\n\nSee you soon!\n\n---\n\nYou can also copy\n\nhttps://strolling.rosano.ca/members/?token=XXX\n\nIf you did not make this request, you can safely ignore this email.\n\nThis message was sent from [strolling.rosano.ca](https://strolling.rosano.ca/) to alice@example.org");
     }
 }
@@ -0,0 +1,973 @@
+//! # DKIM signature verification.
+//!
+//! Verifies the `DKIM-Signature` headers of incoming messages
+//! as defined in [RFC 6376](https://tools.ietf.org/html/rfc6376).
+//! Only signatures of the `From:` domain or one of its parent domains are considered,
+//! as other signatures say nothing about the authenticity of the sender address.
+//!
+//! Public keys are looked up in DNS and cached in the `dkim_keys` table.
+//! Only `rsa-sha256` signatures are supported,
+//! `rsa-sha1` is considered insecure by [RFC 8301](https://tools.ietf.org/html/rfc8301).
+
+use std::collections::HashMap;
+
+use anyhow::{bail, format_err, Context as _, Result};
+use base64::Engine as _;
+use hickory_resolver::error::ResolveErrorKind;
+use once_cell::sync::Lazy;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{PaddingScheme, PublicKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+
+use crate::context::Context;
+use crate::provider::get_resolver;
+use crate::socks::Socks5Config;
+use crate::tools::{time, EmailAddress};
+
+/// How long DKIM public keys are cached.
+const KEY_CACHE_SECS: i64 = 24 * 60 * 60;
+
+/// Maximum number of signatures verified per message.
+const MAX_SIGNATURES: usize = 5;
+
+/// Result of the DKIM verification of a message.
+#[derive(Debug, Default, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum DkimStatus {
+    /// The message has no supported signature of the sender domain
+    /// or was not verified.
+    #[default]
+    None = 0,
+
+    /// A signature of the sender domain is valid.
+    Pass = 1,
+
+    /// The message has signatures of the sender domain, but none of them is valid.
+    /// The message was modified or the sender address is spoofed.
+    Fail = 2,
+
+    /// The public key could not be retrieved.
+    TempError = 3,
+}
+
+/// Canonicalization algorithm, see RFC 6376 section 3.4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Canonicalization {
+    Simple,
+    Relaxed,
+}
+
+/// Parsed `DKIM-Signature` header.
+#[derive(Debug)]
+struct Signature {
+    /// Signing domain (`d=` tag).
+    domain: String,
+
+    /// Selector (`s=` tag).
+    selector: String,
+
+    /// Signature (`b=` tag).
+    signature: Vec<u8>,
+
+    /// Hash of the canonicalized body (`bh=` tag).
+    body_hash: Vec<u8>,
+
+    header_canonicalization: Canonicalization,
+    body_canonicalization: Canonicalization,
+
+    /// Signed header fields (`h=` tag), lowercased.
+    signed_headers: Vec<String>,
+
+    /// Number of signed body bytes (`l=` tag), whole body if `None`.
+    body_length: Option<usize>,
+
+    /// Expiration timestamp (`x=` tag).
+    expiration: Option<i64>,
+}
+
+/// Parses a tag list like `v=1; a=rsa-sha256; d=example.org`.
+///
+/// Whitespace is removed from the values.
+fn parse_tag_list(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(name, value)| {
+            (
+                name.trim().to_string(),
+                value.chars().filter(|c| !c.is_whitespace()).collect(),
+            )
+        })
+        .collect()
+}
+
+fn parse_canonicalization(value: &str) -> Result<Canonicalization> {
+    match value {
+        "simple" => Ok(Canonicalization::Simple),
+        "relaxed" => Ok(Canonicalization::Relaxed),
+        _ => bail!("Unknown canonicalization {value:?}"),
+    }
+}
+
+impl Signature {
+    /// Parses the value of a `DKIM-Signature` header.
+    ///
+    /// Returns `None` for signatures using unsupported algorithms.
+    fn parse(value: &str) -> Result<Option<Self>> {
+        let tags = parse_tag_list(value);
+        let tag = |name: &str| {
+            tags.get(name)
+                .with_context(|| format!("DKIM-Signature has no {name}= tag"))
+        };
+        if tag("v")? != "1" {
+            bail!("Unsupported DKIM-Signature version");
+        }
+        if tag("a")? != "rsa-sha256" {
+            return Ok(None);
+        }
+
+        let (header_canonicalization, body_canonicalization) =
+            match tags.get("c").map(|c| c.split_once('/')) {
+                None => (Canonicalization::Simple, Canonicalization::Simple),
+                Some(None) => (parse_canonicalization(tag("c")?)?, Canonicalization::Simple),
+                Some(Some((header, body))) => (
+                    parse_canonicalization(header)?,
+                    parse_canonicalization(body)?,
+                ),
+            };
+        let signed_headers: Vec<String> = tag("h")?
+            .split(':')
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_lowercase())
+            .collect();
+        if !signed_headers.iter().any(|name| name == "from") {
+            bail!("DKIM-Signature does not sign the From header");
+        }
+
+        Ok(Some(Self {
+            domain: tag("d")?.to_lowercase(),
+            selector: tag("s")?.to_lowercase(),
+            signature: base64::engine::general_purpose::STANDARD.decode(tag("b")?)?,
+            body_hash: base64::engine::general_purpose::STANDARD.decode(tag("bh")?)?,
+            header_canonicalization,
+            body_canonicalization,
+            signed_headers,
+            body_length: tags.get("l").map(|l| l.parse()).transpose()?,
+            expiration: tags.get("x").map(|x| x.parse()).transpose()?,
+        }))
+    }
+
+    /// Returns true if the signing domain is the sender domain or one of its parent domains.
+    ///
+    /// Public suffixes like `com` or `co.uk` are not accepted as signing domains,
+    /// otherwise anyone registering a domain under them could sign for all of them.
+    fn is_aligned(&self, from_domain: &str) -> bool {
+        (from_domain == self.domain
+            || from_domain
+                .strip_suffix(&self.domain)
+                .map_or(false, |sub| sub.ends_with('.')))
+            && psl::domain_str(&self.domain).is_some()
+    }
+}
+
+/// Converts line endings to CRLF.
+fn to_crlf(raw: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(raw.len() + raw.len() / 32);
+    let mut prev = 0;
+    for &byte in raw {
+        if byte == b'\n' && prev != b'\r' {
+            res.push(b'\r');
+        }
+        res.push(byte);
+        prev = byte;
+    }
+    res
+}
+
+/// Splits a message with CRLF line endings into header fields and body.
+///
+/// Header fields are returned as `(lowercased name, raw field)`,
+/// the raw field includes the folded lines and the final CRLF.
+fn split_message(raw: &[u8]) -> (Vec<(String, &[u8])>, &[u8]) {
+    let mut fields: Vec<(String, &[u8])> = Vec::new();
+    let mut field_start = 0;
+    let mut pos = 0;
+    while pos < raw.len() {
+        let line_end = raw
+            .get(pos..)
+            .and_then(|rest| rest.windows(2).position(|w| w == b"\r\n"))
+            .map_or(raw.len(), |i| pos + i + 2);
+        let line = raw.get(pos..line_end).unwrap_or_default();
+        let next_is_continuation = matches!(raw.get(line_end), Some(b' ' | b'\t'));
+        if line == b"\r\n" {
+            // End of header.
+            return (fields, raw.get(line_end..).unwrap_or_default());
+        }
+        if !next_is_continuation {
+            let field = raw.get(field_start..line_end).unwrap_or_default();
+            let name = field
+                .iter()
+                .position(|&b| b == b':')
+                .and_then(|i| field.get(..i))
+                .map(|name| String::from_utf8_lossy(name).trim().to_lowercase())
+                .unwrap_or_default();
+            fields.push((name, field));
+            field_start = line_end;
+        }
+        pos = line_end;
+    }
+    (fields, &[])
+}
+
+/// Returns true for the whitespace of RFC 6376, space and tab.
+///
+/// Canonicalization works on raw bytes and must not touch anything else,
+/// messages may contain 8bit data in any charset.
+fn is_wsp(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\t')
+}
+
+/// Removes trailing whitespace.
+fn trim_end_wsp(s: &[u8]) -> &[u8] {
+    let end = s.iter().rposition(|b| !is_wsp(b)).map_or(0, |i| i + 1);
+    s.get(..end).unwrap_or_default()
+}
+
+/// Removes leading and trailing whitespace.
+fn trim_wsp(s: &[u8]) -> &[u8] {
+    let s = trim_end_wsp(s);
+    let start = s.iter().position(|b| !is_wsp(b)).unwrap_or(s.len());
+    s.get(start..).unwrap_or_default()
+}
+
+/// Replaces runs of whitespace by a single space.
+fn compress_whitespace(s: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(s.len());
+    let mut in_whitespace = false;
+    for b in s {
+        if is_wsp(b) {
+            in_whitespace = true;
+        } else {
+            if in_whitespace {
+                res.push(b' ');
+                in_whitespace = false;
+            }
+            res.push(*b);
+        }
+    }
+    if in_whitespace {
+        res.push(b' ');
+    }
+    res
+}
+
+/// Removes the CRLF sequences of folded lines.
+fn unfold(s: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.windows(2).position(|w| w == b"\r\n") {
+        let (line, next) = rest.split_at(i);
+        res.extend_from_slice(line);
+        rest = next.get(2..).unwrap_or_default();
+    }
+    res.extend_from_slice(rest);
+    res
+}
+
+/// Canonicalizes a raw header field including the final CRLF.
+fn canonicalize_header(field: &[u8], canonicalization: Canonicalization) -> Vec<u8> {
+    match canonicalization {
+        Canonicalization::Simple => field.to_vec(),
+        Canonicalization::Relaxed => {
+            let colon = field.iter().position(|&b| b == b':');
+            let name = colon.and_then(|i| field.get(..i)).unwrap_or(field);
+            let value = colon.and_then(|i| field.get(i + 1..)).unwrap_or_default();
+            let mut res = trim_wsp(name).to_ascii_lowercase();
+            res.push(b':');
+            res.extend_from_slice(trim_wsp(&compress_whitespace(&unfold(value))));
+            res.extend_from_slice(b"\r\n");
+            res
+        }
+    }
+}
+
+/// Hash of a canonicalized message body, see RFC 6376 section 3.7.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct BodyHash {
+    canonicalization: Canonicalization,
+    body_length: Option<usize>,
+    hash: Vec<u8>,
+
+    /// Whether the canonicalized body is longer than `body_length`.
+    is_truncated: bool,
+}
+
+/// Canonicalizes and hashes a message body piece by piece,
+/// so the body does not have to be kept in memory as a whole.
+#[derive(Debug)]
+struct BodyHasher {
+    canonicalization: Canonicalization,
+    body_length: Option<usize>,
+    hasher: Sha256,
+
+    /// Length of the canonicalized body so far.
+    len: usize,
+
+    /// Number of empty lines which are only hashed if a non-empty line follows,
+    /// as empty lines at the end of the body are ignored.
+    empty_lines: usize,
+
+    /// Incomplete last line.
+    line: Vec<u8>,
+}
+
+impl BodyHasher {
+    fn new(canonicalization: Canonicalization, body_length: Option<usize>) -> Self {
+        Self {
+            canonicalization,
+            body_length,
+            hasher: Sha256::new(),
+            len: 0,
+            empty_lines: 0,
+            line: Vec::new(),
+        }
+    }
+
+    /// Hashes the next piece of the body.
+    fn update(&mut self, mut data: &[u8]) {
+        while let Some(i) = data.iter().position(|&b| b == b'\n') {
+            let (line, rest) = data.split_at(i + 1);
+            if self.line.is_empty() {
+                self.hash_line(line);
+            } else {
+                let mut buffered = std::mem::take(&mut self.line);
+                buffered.extend_from_slice(line);
+                self.hash_line(&buffered);
+            }
+            data = rest;
+        }
+        self.line.extend_from_slice(data);
+    }
+
+    /// Canonicalizes and hashes a line with or without the line ending.
+    fn hash_line(&mut self, line: &[u8]) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let relaxed;
+        let line = match self.canonicalization {
+            Canonicalization::Simple => line,
+            Canonicalization::Relaxed => {
+                relaxed = compress_whitespace(line);
+                trim_end_wsp(&relaxed)
+            }
+        };
+        if line.is_empty() {
+            self.empty_lines += 1;
+            return;
+        }
+        for _ in 0..std::mem::take(&mut self.empty_lines) {
+            self.hash(b"\r\n");
+        }
+        self.hash(line);
+        self.hash(b"\r\n");
+    }
+
+    fn hash(&mut self, data: &[u8]) {
+        let remaining = self.body_length.map_or(data.len(), |body_length| {
+            body_length.saturating_sub(self.len)
+        });
+        self.hasher
+            .update(data.get(..remaining.min(data.len())).unwrap_or_default());
+        self.len += data.len();
+    }
+
+    fn finish(mut self) -> BodyHash {
+        let line = std::mem::take(&mut self.line);
+        self.hash_line(&line);
+        if self.len == 0 && self.canonicalization == Canonicalization::Simple {
+            self.hash(b"\r\n");
+        }
+        BodyHash {
+            canonicalization: self.canonicalization,
+            body_length: self.body_length,
+            hash: self.hasher.finalize().to_vec(),
+            is_truncated: self
+                .body_length
+                .map_or(false, |body_length| body_length < self.len),
+        }
+    }
+}
+
+/// Hashes a message body for the signatures in the message header
+/// while the body is received piece by piece.
+#[derive(Debug, Default)]
+pub(crate) struct BodyHashers(Vec<BodyHasher>);
+
+impl BodyHashers {
+    /// Creates hashers for the supported `DKIM-Signature` fields of `header`.
+    pub(crate) fn new(header: &[u8]) -> Self {
+        let header = to_crlf(header);
+        let (fields, _) = split_message(&header);
+        let mut hashers: Vec<BodyHasher> = Vec::new();
+        for signature in signatures(&fields).filter_map(|(_, signature)| signature.ok()) {
+            if !hashers.iter().any(|hasher| {
+                hasher.canonicalization == signature.body_canonicalization
+                    && hasher.body_length == signature.body_length
+            }) {
+                hashers.push(BodyHasher::new(
+                    signature.body_canonicalization,
+                    signature.body_length,
+                ));
+            }
+        }
+        Self(hashers)
+    }
+
+    /// Hashes the next piece of the body.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for hasher in &mut self.0 {
+            hasher.update(data);
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<BodyHash> {
+        self.0.into_iter().map(BodyHasher::finish).collect()
+    }
+}
+
+/// Returns the first [`MAX_SIGNATURES`] `DKIM-Signature` fields and their parsed values.
+///
+/// Signatures using unsupported algorithms are skipped.
+fn signatures<'a>(
+    fields: &'a [(String, &'a [u8])],
+) -> impl Iterator<Item = (&'a [u8], Result<Signature>)> + 'a {
+    fields
+        .iter()
+        .filter(|(name, _)| name == "dkim-signature")
+        .take(MAX_SIGNATURES)
+        .filter_map(|(_, field)| {
+            let value = String::from_utf8_lossy(field);
+            let value = value.split_once(':').map_or("", |(_, value)| value);
+            Signature::parse(value)
+                .transpose()
+                .map(|signature| (*field, signature))
+        })
+}
+
+/// Removes the value of the `b=` tag from a `DKIM-Signature` header field.
+fn remove_signature_value(field: &[u8]) -> Vec<u8> {
+    static RE: Lazy<regex::bytes::Regex> =
+        Lazy::new(|| regex::bytes::Regex::new(r"((?:^|;)\s*b\s*=)[^;]*").unwrap());
+    // The header name is not part of the tag list.
+    let colon = field.iter().position(|&b| b == b':').map_or(0, |i| i + 1);
+    let (name, value) = field.split_at(colon);
+    let mut res = name.to_vec();
+    res.extend_from_slice(&RE.replace(value, &b"${1}"[..]));
+    res
+}
+
+/// Returns the data covered by the signature, see RFC 6376 section 3.7.
+fn signed_data(
+    fields: &[(String, &[u8])],
+    signature_field: &[u8],
+    signature: &Signature,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut used = vec![false; fields.len()];
+    for name in &signature.signed_headers {
+        // Multiple instances of a header are signed from the bottom up.
+        let found = fields
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(i, (field_name, _))| {
+                field_name == name && !used.get(*i).copied().unwrap_or(true)
+            });
+        if let Some((i, (_, field))) = found {
+            if let Some(used) = used.get_mut(i) {
+                *used = true;
+            }
+            data.extend(canonicalize_header(
+                field,
+                signature.header_canonicalization,
+            ));
+        }
+    }
+    let mut signature_field = canonicalize_header(
+        &remove_signature_value(signature_field),
+        signature.header_canonicalization,
+    );
+    if signature_field.ends_with(b"\r\n") {
+        signature_field.truncate(signature_field.len() - 2);
+    }
+    data.extend(signature_field);
+    data
+}
+
+/// Parses a DKIM key record and returns the RSA public key.
+///
+/// Returns `None` if the key is revoked or uses an unsupported algorithm.
+fn parse_key_record(record: &str) -> Result<Option<RsaPublicKey>> {
+    let tags = parse_tag_list(record);
+    if tags.get("k").map_or(false, |k| k != "rsa") {
+        return Ok(None);
+    }
+    let p = tags.get("p").context("DKIM key record has no p= tag")?;
+    if p.is_empty() {
+        return Ok(None);
+    }
+    let der = base64::engine::general_purpose::STANDARD.decode(p)?;
+    let key = match RsaPublicKey::from_public_key_der(&der) {
+        Ok(key) => key,
+        Err(_) => RsaPublicKey::from_pkcs1_der(&der)
+            .map_err(|err| format_err!("Invalid DKIM public key: {err}"))?,
+    };
+    Ok(Some(key))
+}
+
+/// Returns the DKIM key record for the given selector and domain.
+///
+/// Returns an empty string if there is no record.
+/// Records are cached for [`KEY_CACHE_SECS`].
+async fn lookup_key_record(context: &Context, selector: &str, domain: &str) -> Result<String> {
+    let name = format!("{selector}._domainkey.{domain}");
+    let cached: Option<String> = context
+        .sql
+        .query_get_value(
+            "SELECT record FROM dkim_keys WHERE name=? AND timestamp>?",
+            (&name, time() - KEY_CACHE_SECS),
+        )
+        .await?;
+    if let Some(record) = cached {
+        return Ok(record);
+    }
+    // The resolver does not use the proxy,
+    // a DNS query would reveal the sender domains and the own IP address.
+    if Socks5Config::from_context(context).await?.is_some() {
+        bail!("Not looking up {name} as DNS queries would bypass the proxy");
+    }
+
+    let record = match get_resolver()?.txt_lookup(format!("{name}.")).await {
+        Ok(lookup) => lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data))
+                    .collect::<String>()
+            })
+            .find(|record| record.contains("p="))
+            .unwrap_or_default(),
+        Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Failed to look up {name}")),
+    };
+    context
+        .sql
+        .execute(
+            "INSERT OR REPLACE INTO dkim_keys (name, record, timestamp) VALUES (?,?,?)",
+            (&name, &record, time()),
+        )
+        .await?;
+    Ok(record)
+}
+
+/// Verifies a single signature.
+///
+/// Returns [`DkimStatus::Fail`] if the signature is invalid
+/// and an error if the key could not be retrieved.
+/// A valid signature which covers only a part of the body (`l=` tag)
+/// results in [`DkimStatus::None`] as anything could be appended to the signed part.
+async fn verify_signature(
+    context: &Context,
+    fields: &[(String, &[u8])],
+    body_hash: &BodyHash,
+    signature_field: &[u8],
+    signature: &Signature,
+) -> Result<DkimStatus> {
+    if signature.expiration.map_or(false, |x| x < time()) {
+        return Ok(DkimStatus::Fail);
+    }
+    if body_hash.hash != signature.body_hash {
+        return Ok(DkimStatus::Fail);
+    }
+
+    let record = lookup_key_record(context, &signature.selector, &signature.domain).await?;
+    let Some(key) = parse_key_record(&record)? else {
+        return Ok(DkimStatus::Fail);
+    };
+    let hashed = Sha256::digest(signed_data(fields, signature_field, signature));
+    if key
+        .verify(
+            PaddingScheme::new_pkcs1v15_sign::<Sha256>(),
+            &hashed,
+            &signature.signature,
+        )
+        .is_err()
+    {
+        Ok(DkimStatus::Fail)
+    } else if body_hash.is_truncated {
+        Ok(DkimStatus::None)
+    } else {
+        Ok(DkimStatus::Pass)
+    }
+}
+
+/// Verifies the DKIM signatures of a message sent from `from_addr`.
+///
+/// If the body was hashed while receiving the message with [`BodyHashers`],
+/// `body_hashes` contains the result and the body of `imf_raw` is ignored.
+pub(crate) async fn verify(
+    context: &Context,
+    imf_raw: &[u8],
+    from_addr: &str,
+    body_hashes: Option<&[BodyHash]>,
+) -> Result<DkimStatus> {
+    let from_domain = EmailAddress::new(from_addr)?.domain.to_lowercase();
+    let raw = to_crlf(imf_raw);
+    let (fields, body) = split_message(&raw);
+
+    let mut status = DkimStatus::None;
+    for (signature_field, signature) in signatures(&fields) {
+        let signature = match signature {
+            Ok(signature) if signature.is_aligned(&from_domain) => signature,
+            Ok(_) => continue,
+            Err(err) => {
+                info!(context, "Ignoring invalid DKIM-Signature: {err:#}.");
+                continue;
+            }
+        };
+        let computed_hash;
+        let body_hash = match body_hashes {
+            Some(body_hashes) => {
+                let Some(body_hash) = body_hashes.iter().find(|body_hash| {
+                    body_hash.canonicalization == signature.body_canonicalization
+                        && body_hash.body_length == signature.body_length
+                }) else {
+                    continue;
+                };
+                body_hash
+            }
+            None => {
+                let mut hasher =
+                    BodyHasher::new(signature.body_canonicalization, signature.body_length);
+                hasher.update(body);
+                computed_hash = hasher.finish();
+                &computed_hash
+            }
+        };
+        match verify_signature(context, &fields, body_hash, signature_field, &signature).await {
+            Ok(DkimStatus::Pass) => return Ok(DkimStatus::Pass),
+            Ok(DkimStatus::Fail) => {
+                if status == DkimStatus::None {
+                    status = DkimStatus::Fail;
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn!(context, "DKIM verification failed: {err:#}.");
+                status = DkimStatus::TempError;
+            }
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::pkcs8::EncodePublicKey;
+    use rsa::RsaPrivateKey;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::message::Message;
+    use crate::receive_imf::receive_imf;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_canonicalize_header() {
+        // Example from RFC 6376 section 3.4.6.
+        let (fields, body) =
+            split_message(b"A: X\r\nB : Y\t\r\n\tZ  \r\n\r\n C \r\nD \t E\r\n\r\n\r\n");
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[1].0, "b");
+        let relaxed: Vec<u8> = fields
+            .iter()
+            .flat_map(|(_, field)| canonicalize_header(field, Canonicalization::Relaxed))
+            .collect();
+        assert_eq!(relaxed, b"a:X\r\nb:Y Z\r\n");
+        let simple: Vec<u8> = fields
+            .iter()
+            .flat_map(|(_, field)| canonicalize_header(field, Canonicalization::Simple))
+            .collect();
+        assert_eq!(simple, b"A: X\r\nB : Y\t\r\n\tZ  \r\n");
+        assert_eq!(body, b" C \r\nD \t E\r\n\r\n\r\n");
+    }
+
+    #[test]
+    fn test_body_hasher() {
+        let hash = |body: &[u8], canonicalization, body_length, chunk_size| {
+            let mut hasher = BodyHasher::new(canonicalization, body_length);
+            for chunk in body.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            hasher.finish()
+        };
+
+        // Example from RFC 6376 section 3.4.6.
+        let body = b" C \r\nD \t E\r\n\r\n\r\n";
+        for chunk_size in [1, 2, 5, body.len()] {
+            let relaxed = hash(body, Canonicalization::Relaxed, None, chunk_size);
+            assert_eq!(relaxed.hash, Sha256::digest(b" C\r\nD E\r\n").to_vec());
+            assert!(!relaxed.is_truncated);
+            let simple = hash(body, Canonicalization::Simple, None, chunk_size);
+            assert_eq!(simple.hash, Sha256::digest(b" C \r\nD \t E\r\n").to_vec());
+
+            let truncated = hash(body, Canonicalization::Relaxed, Some(4), chunk_size);
+            assert_eq!(truncated.hash, Sha256::digest(b" C\r\n").to_vec());
+            assert!(truncated.is_truncated);
+        }
+
+        let empty = hash(b"", Canonicalization::Simple, None, 1);
+        assert_eq!(empty.hash, Sha256::digest(b"\r\n").to_vec());
+        let empty = hash(b"\r\n", Canonicalization::Relaxed, None, 1);
+        assert_eq!(empty.hash, Sha256::digest(b"").to_vec());
+        let unterminated = hash(b"a\r\nb", Canonicalization::Simple, None, 1);
+        assert_eq!(unterminated.hash, Sha256::digest(b"a\r\nb\r\n").to_vec());
+    }
+
+    #[test]
+    fn test_canonicalize_8bit() {
+        // Latin-1 body of an 8bit message, including a no-break space (0xA0),
+        // which is whitespace in Unicode, but not for DKIM.
+        let body = b"Gr\xfc\xdfe  aus\xa0K\xf6ln \xa0\r\n\r\n";
+        let mut hasher = BodyHasher::new(Canonicalization::Relaxed, None);
+        hasher.update(body);
+        assert_eq!(
+            hasher.finish().hash,
+            Sha256::digest(b"Gr\xfc\xdfe aus\xa0K\xf6ln \xa0\r\n").to_vec()
+        );
+
+        // UTF-8 header with a no-break space (U+00A0) at the end.
+        assert_eq!(
+            canonicalize_header(
+                b"Subject:  Gr\xc3\xbc\xc3\x9fe\xc2\xa0 \r\n",
+                Canonicalization::Relaxed
+            ),
+            b"subject:Gr\xc3\xbc\xc3\x9fe\xc2\xa0\r\n"
+        );
+        assert_eq!(
+            canonicalize_header(b"Subject: K\xf6ln\r\n", Canonicalization::Relaxed),
+            b"subject:K\xf6ln\r\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_signature_value() {
+        assert_eq!(
+            remove_signature_value(b"DKIM-Signature: v=1; bh=abc; b=sig\r\n\tnature; s=x\r\n"),
+            b"DKIM-Signature: v=1; bh=abc; b=; s=x\r\n"
+        );
+        assert_eq!(
+            remove_signature_value(b"DKIM-Signature: b=signature; v=1\r\n"),
+            b"DKIM-Signature: b=; v=1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_is_aligned() {
+        let signature =
+            Signature::parse("v=1; a=rsa-sha256; d=Example.org; s=sel; h=from:to; bh=; b=")
+                .unwrap()
+                .unwrap();
+        assert!(signature.is_aligned("example.org"));
+        assert!(signature.is_aligned("mail.example.org"));
+        assert!(!signature.is_aligned("badexample.org"));
+        assert!(!signature.is_aligned("example.com"));
+
+        // Public suffixes cannot sign for the domains below them.
+        for suffix in ["org", "co.uk"] {
+            let signature = Signature::parse(&format!(
+                "v=1; a=rsa-sha256; d={suffix}; s=sel; h=from:to; bh=; b="
+            ))
+            .unwrap()
+            .unwrap();
+            assert!(!signature.is_aligned(&format!("example.{suffix}")));
+            assert!(!signature.is_aligned(suffix));
+        }
+
+        // Unsupported algorithm.
+        assert!(
+            Signature::parse("v=1; a=rsa-sha1; d=example.org; s=sel; h=from; bh=; b=")
+                .unwrap()
+                .is_none()
+        );
+        // From header not signed.
+        assert!(
+            Signature::parse("v=1; a=rsa-sha256; d=example.org; s=sel; h=to; bh=; b=").is_err()
+        );
+    }
+
+    /// Signs `raw` and returns it with a `DKIM-Signature` header prepended.
+    ///
+    /// If `body_length` is set, only this many bytes of the body are signed.
+    fn sign(key: &RsaPrivateKey, domain: &str, raw: &str, body_length: Option<usize>) -> String {
+        let raw = to_crlf(raw.as_bytes());
+        let (_, body) = split_message(&raw);
+        let mut hasher = BodyHasher::new(Canonicalization::Relaxed, body_length);
+        hasher.update(body);
+        let body_hash = base64::engine::general_purpose::STANDARD.encode(hasher.finish().hash);
+        let l_tag = body_length.map_or(String::new(), |body_length| format!(" l={body_length};"));
+        let unsigned = format!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d={domain}; s=sel;{l_tag}\r\n\
+             \th=from:subject:from; bh={body_hash}; b=\r\n"
+        );
+        let signature = Signature::parse(unsigned.split_once(':').unwrap().1)
+            .unwrap()
+            .unwrap();
+        let (fields, _) = split_message(&raw);
+        let hashed = Sha256::digest(signed_data(&fields, unsigned.as_bytes(), &signature));
+        let b = base64::engine::general_purpose::STANDARD.encode(
+            key.sign(PaddingScheme::new_pkcs1v15_sign::<Sha256>(), &hashed)
+                .unwrap(),
+        );
+        format!(
+            "{}{b}\r\n{}",
+            unsigned.trim_end(),
+            String::from_utf8(raw).unwrap()
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_verify() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+        let public_key = base64::engine::general_purpose::STANDARD
+            .encode(key.to_public_key().to_public_key_der()?.as_bytes());
+        t.sql
+            .execute(
+                "INSERT INTO dkim_keys (name, record, timestamp) VALUES (?,?,?)",
+                (
+                    "sel._domainkey.example.net",
+                    format!("v=DKIM1; k=rsa; p={public_key}"),
+                    time(),
+                ),
+            )
+            .await?;
+
+        let raw = "From: Bob <bob@example.net>\n\
+                   To: alice@example.org\n\
+                   Subject: Hello\n\
+                   Message-ID: <dkim@example.net>\n\
+                   Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                   \n\
+                   Hello  there!\n";
+        let signed = sign(&key, "example.net", raw, None);
+        assert_eq!(
+            verify(&t, signed.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::Pass
+        );
+        // Signatures of other domains do not authenticate the sender.
+        assert_eq!(
+            verify(&t, signed.as_bytes(), "bob@example.com", None).await?,
+            DkimStatus::None
+        );
+
+        // Whitespace changes are allowed by relaxed canonicalization.
+        let modified = signed.replace("Hello  there!", "Hello there!  ");
+        assert_eq!(
+            verify(&t, modified.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::Pass
+        );
+
+        let modified = signed.replace("Hello  there!", "Hello there?");
+        assert_eq!(
+            verify(&t, modified.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::Fail
+        );
+        let modified = signed.replace("Subject: Hello", "Subject: Hi");
+        assert_eq!(
+            verify(&t, modified.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::Fail
+        );
+        // An added From header is detected because h= lists From twice.
+        let modified = signed.replace("Subject: Hello", "Subject: Hello\r\nFrom: x@example.net");
+        assert_eq!(
+            verify(&t, modified.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::Fail
+        );
+
+        // A signature of a part of the body does not authenticate the message,
+        // anything could be appended.
+        let partial = sign(&key, "example.net", raw, Some(7));
+        assert_eq!(
+            verify(&t, partial.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::None
+        );
+        let modified = partial.replace("Hello  there!", "Hello  there! Please pay.");
+        assert_eq!(
+            verify(&t, modified.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::None
+        );
+        let modified = partial.replace("Hello  there!", "Hi there!");
+        assert_eq!(
+            verify(&t, modified.as_bytes(), "bob@example.net", None).await?,
+            DkimStatus::Fail
+        );
+
+        // Verification is disabled by default.
+        let received = receive_imf(&t, signed.as_bytes(), false).await?.unwrap();
+        let msg = Message::load_from_db(&t, received.msg_ids[0]).await?;
+        assert_eq!(msg.get_dkim_status(), DkimStatus::None);
+
+        // If enabled, the result is stored in the received message.
+        t.set_config_bool(Config::VerifyDkim, true).await?;
+        let signed = signed.replace("<dkim@example.net>", "<dkim2@example.net>");
+        let received = receive_imf(&t, signed.as_bytes(), false).await?.unwrap();
+        let msg = Message::load_from_db(&t, received.msg_ids[0]).await?;
+        assert_eq!(msg.get_dkim_status(), DkimStatus::Pass);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spoofed_self_msg() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+        let other_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+        let public_key = base64::engine::general_purpose::STANDARD
+            .encode(key.to_public_key().to_public_key_der()?.as_bytes());
+        t.sql
+            .execute(
+                "INSERT INTO dkim_keys (name, record, timestamp) VALUES (?,?,?)",
+                (
+                    "sel._domainkey.example.org",
+                    format!("v=DKIM1; p={public_key}"),
+                    time(),
+                ),
+            )
+            .await?;
+        t.set_config_bool(Config::VerifyDkim, true).await?;
+
+        // Message claiming to be from self, but signed with a wrong key.
+        let raw = "From: alice@example.org\n\
+                   To: bob@example.net\n\
+                   Subject: Invoice\n\
+                   Message-ID: <spoofed@example.org>\n\
+                   Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                   \n\
+                   Please pay.\n";
+        let spoofed = sign(&other_key, "example.org", raw, None);
+        let received = receive_imf(&t, spoofed.as_bytes(), false).await?.unwrap();
+        let msg = Message::load_from_db(&t, received.msg_ids[0]).await?;
+        assert_eq!(msg.get_dkim_status(), DkimStatus::Fail);
+        assert!(msg.error().is_some());
+
+        let raw = raw.replace("<spoofed@example.org>", "<genuine@example.org>");
+        let genuine = sign(&key, "example.org", &raw, None);
+        let received = receive_imf(&t, genuine.as_bytes(), false).await?.unwrap();
+        let msg = Message::load_from_db(&t, received.msg_ids[0]).await?;
+        assert_eq!(msg.get_dkim_status(), DkimStatus::Pass);
+        assert!(msg.error().is_none());
+        Ok(())
+    }
+}
@@ -9,9 +9,10 @@
 
 use crate::config::Config;
 use crate::context::Context;
-use crate::imap::{Imap, ImapActionResult};
+use crate::imap::{Imap, ImapActionError, ImapErrorKind};
 use crate::message::{Message, MsgId, Viewtype};
 use crate::mimeparser::{MimeMessage, Part};
+use crate::net::ensure_online;
 use crate::scheduler::InterruptInfo;
 use crate::tools::time;
 use crate::{stock_str, EventType};
@@ -81,6 +82,7 @@ pub(crate) async fn download_limit(&self) -> Result<Option<u32>> {
 impl MsgId {
     /// Schedules full message download for partially downloaded message.
     pub async fn download_full(self, context: &Context) -> Result<()> {
+        ensure_online(context).await?;
         let msg = Message::load_from_db(context, self).await?;
         match msg.download_state() {
             DownloadState::Done | DownloadState::Undecipherable => {
@@ -135,7 +137,7 @@ pub fn download_state(&self) -> DownloadState {
 ///
 /// Most messages are downloaded automatically on fetch instead.
 pub(crate) async fn download_msg(context: &Context, msg_id: MsgId, imap: &mut Imap) -> Result<()> {
-    imap.prepare(context).await?;
+    imap.prepare(context).await.map_err(ImapActionError::from)?;
 
     let msg = Message::load_from_db(context, msg_id).await?;
     let row = context
@@ -156,13 +158,17 @@ pub(crate) async fn download_msg(context: &Context, msg_id: MsgId, imap: &mut Im
             .fetch_single_msg(context, &server_folder, server_uid, msg.rfc724_mid.clone())
             .await
         {
-            ImapActionResult::RetryLater | ImapActionResult::Failed => {
+            Err(err) if err.kind.is_temporary() => {
+                // The message stays in the download queue and is retried later.
+                Err(err.into())
+            }
+            Err(err) => {
                 msg.id
                     .update_download_state(context, DownloadState::Failure)
                     .await?;
-                Err(anyhow!("Call download_full() again to try over."))
+                Err(anyhow::Error::new(err).context("Call download_full() again to try over."))
             }
-            ImapActionResult::Success => {
+            Ok(()) => {
                 // update_download_state() not needed as receive_imf() already
                 // set the state and emitted the event.
                 Ok(())
@@ -188,30 +194,26 @@ async fn fetch_single_msg(
         folder: &str,
         uid: u32,
         rfc724_mid: String,
-    ) -> ImapActionResult {
-        if let Some(imapresult) = self
-            .prepare_imap_operation_on_msg(context, folder, uid)
-            .await
-        {
-            return imapresult;
-        }
+    ) -> Result<(), ImapActionError> {
+        self.prepare_imap_operation_on_msg(context, folder, uid)
+            .await?;
 
         // we are connected, and the folder is selected
         info!(context, "Downloading message {}/{} fully...", folder, uid);
 
         let mut uid_message_ids: BTreeMap<u32, String> = BTreeMap::new();
         uid_message_ids.insert(uid, rfc724_mid);
-        let (last_uid, _received) = match self
-            .fetch_many_msgs(context, folder, vec![uid], &uid_message_ids, false, false)
-            .await
-        {
-            Ok(res) => res,
-            Err(_) => return ImapActionResult::Failed,
-        };
+        // Messages downloaded on demand are large, so they are fetched in chunks.
+        let (last_uid, _received) = self
+            .fetch_streamed_msgs(context, folder, &uid_message_ids, false, None)
+            .await?;
         if last_uid.is_none() {
-            ImapActionResult::Failed
+            Err(ImapActionError::new(
+                ImapErrorKind::NotFound,
+                anyhow!("Message {folder}/{uid} not found on the server"),
+            ))
         } else {
-            ImapActionResult::Success
+            Ok(())
         }
     }
 }
@@ -329,6 +331,30 @@ async fn test_update_download_state() -> Result<()> {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_download_full_offline() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("Bob", "bob@example.org").await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("Hi".to_string());
+        let msg_id = send_msg(&t, chat.id, &mut msg).await?;
+        msg_id
+            .update_download_state(&t, DownloadState::Available)
+            .await?;
+
+        t.set_config_bool(Config::Offline, true).await?;
+        assert!(msg_id.download_full(&t).await.is_err());
+        assert!(t.configure().await.is_err());
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.download_state(), DownloadState::Available);
+
+        t.set_config_bool(Config::Offline, false).await?;
+        msg_id.download_full(&t).await?;
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.download_state(), DownloadState::InProgress);
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_partial_receive_imf() -> Result<()> {
         let t = TestContext::new_alice().await;
@@ -6,7 +6,9 @@
 use crate::aheader::{Aheader, EncryptPreference};
 use crate::config::Config;
 use crate::context::Context;
-use crate::key::{load_self_public_key, load_self_secret_key, SignedPublicKey};
+use crate::key::{
+    load_self_public_key, load_self_secret_key, load_self_secret_keyring, SignedPublicKey,
+};
 use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
 use crate::pgp;
 
@@ -150,14 +152,66 @@ pub async fn ensure_secret_key_exists(context: &Context) -> Result<()> {
     Ok(())
 }
 
+/// Maximum number of texts kept decrypted in memory, see [`decrypt_text_at_rest`].
+const DECRYPTED_TEXTS_CACHE_SIZE: usize = 1000;
+
+/// Encrypts `plain` to the own key to store it in the database,
+/// see [`Config::EncryptAtRest`].
+pub(crate) async fn encrypt_at_rest(context: &Context, plain: &[u8]) -> Result<String> {
+    let public_key = load_self_public_key(context).await?;
+    pgp::pk_encrypt(plain, vec![public_key], None).await
+}
+
+/// Decrypts data stored by [`encrypt_at_rest`].
+pub(crate) async fn decrypt_at_rest(context: &Context, ctext: &[u8]) -> Result<Vec<u8>> {
+    let keyring = load_self_secret_keyring(context).await?;
+    let ctext = ctext.to_vec();
+    let (plain, _) = tokio::task::block_in_place(move || pgp::pk_decrypt(ctext, &keyring, &[]))?;
+    Ok(plain)
+}
+
+/// Encrypts a text with [`encrypt_at_rest`], an empty text stays empty.
+pub(crate) async fn encrypt_text_at_rest(context: &Context, text: &str) -> Result<String> {
+    if text.is_empty() {
+        return Ok(String::new());
+    }
+    encrypt_at_rest(context, text.as_bytes()).await
+}
+
+/// Decrypts a text stored by [`encrypt_text_at_rest`].
+///
+/// Decrypted texts are cached, as decrypting them requires loading the keys
+/// and is too slow to be done whenever a message is loaded.
+pub(crate) async fn decrypt_text_at_rest(context: &Context, text: &str) -> Result<String> {
+    if text.is_empty() {
+        return Ok(String::new());
+    }
+    if let Some(plain) = context
+        .decrypted_texts
+        .lock()
+        .expect("Mutex is poisoned")
+        .get(text)
+    {
+        return Ok(plain.clone());
+    }
+    let plain = decrypt_at_rest(context, text.as_bytes()).await?;
+    let plain = String::from_utf8_lossy(&plain).into_owned();
+    let mut decrypted_texts = context.decrypted_texts.lock().expect("Mutex is poisoned");
+    if decrypted_texts.len() >= DECRYPTED_TEXTS_CACHE_SIZE {
+        decrypted_texts.clear();
+    }
+    decrypted_texts.insert(text.to_string(), plain.clone());
+    Ok(plain)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::chat;
     use crate::key::DcKey;
-    use crate::message::{Message, Viewtype};
+    use crate::message::{self, Message, Viewtype};
     use crate::param::Param;
-    use crate::test_utils::{bob_keypair, TestContext};
+    use crate::test_utils::{bob_keypair, TestContext, TestContextManager};
 
     mod ensure_secret_key_exists {
         use super::*;
@@ -333,4 +387,71 @@ async fn test_should_encrypt() {
         assert!(encrypt_helper.should_encrypt(&t, true, &ps).is_err());
         assert!(!encrypt_helper.should_encrypt(&t, false, &ps).unwrap());
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_encrypt_at_rest() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        alice.set_config_bool(Config::EncryptAtRest, true).await?;
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = alice.send_text(alice_chat.id, "Hi!").await;
+        bob.recv_msg(&sent).await;
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "Top secret").await;
+        let msg = alice.recv_msg(&sent).await;
+        assert_eq!(msg.get_text(), "Top secret");
+        assert!(msg.get_showpadlock());
+
+        let stored: String = alice
+            .sql
+            .query_get_value("SELECT txt FROM msgs WHERE id=?", (msg.id,))
+            .await?
+            .unwrap();
+        assert!(stored.starts_with("-----BEGIN PGP MESSAGE-----"));
+        assert!(alice.search_msgs(None, "secret").await?.is_empty());
+        assert!(msg.id.get_info(&alice).await?.contains("Top secret"));
+
+        alice.set_config_bool(Config::EncryptAtRest, false).await?;
+        let sent = bob.send_text(bob_chat.id, "Another secret").await;
+        let msg = alice.recv_msg(&sent).await;
+        assert_eq!(alice.search_msgs(None, "secret").await?, vec![msg.id]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_mime_headers_encrypted_at_rest() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        alice.set_config_bool(Config::EncryptAtRest, true).await?;
+        alice.set_config_bool(Config::SaveMimeHeaders, true).await?;
+        tcm.send_recv_accept(&alice, &bob, "Hi!").await;
+        let sent = bob
+            .send_text(bob.create_chat(&alice).await.id, "Top secret")
+            .await;
+        let msg = alice.recv_msg(&sent).await;
+        let headers = message::get_mime_headers(&alice, msg.id).await?;
+        assert!(String::from_utf8_lossy(&headers).contains("Top secret"));
+
+        // Headers stored uncompressed are not written back unencrypted.
+        alice
+            .sql
+            .execute(
+                "UPDATE msgs SET mime_headers=?, mime_compressed=0 WHERE id=?",
+                (encrypt_at_rest(&alice, &headers).await?, msg.id),
+            )
+            .await?;
+        for _ in 0..2 {
+            assert_eq!(message::get_mime_headers(&alice, msg.id).await?, headers);
+        }
+        let stored: String = alice
+            .sql
+            .query_get_value("SELECT mime_headers FROM msgs WHERE id=?", (msg.id,))
+            .await?
+            .unwrap();
+        assert!(stored.starts_with("-----BEGIN PGP MESSAGE-----"));
+        Ok(())
+    }
 }
@@ -62,7 +62,7 @@
 //! the database entries which are expired either according to their
 //! ephemeral message timers or global `delete_server_after` setting.
 
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::collections::BTreeSet;
 use std::convert::{TryFrom, TryInto};
 use std::num::ParseIntError;
@@ -86,6 +86,7 @@
 use crate::sql::{self, params_iter};
 use crate::stock_str;
 use crate::tools::{duration_to_str, time};
+use crate::undo;
 
 /// Ephemeral timer value.
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
@@ -248,7 +249,7 @@ pub(crate) async fn stock_ephemeral_timer_changed(
             61..=3599 => {
                 stock_str::msg_ephemeral_timer_minutes(
                     context,
-                    &format!("{}", (f64::from(duration) / 6.0).round() / 10.0),
+                    (f64::from(duration) / 6.0).round() / 10.0,
                     from_id,
                 )
                 .await
@@ -257,7 +258,7 @@ pub(crate) async fn stock_ephemeral_timer_changed(
             3601..=86399 => {
                 stock_str::msg_ephemeral_timer_hours(
                     context,
-                    &format!("{}", (f64::from(duration) / 360.0).round() / 10.0),
+                    (f64::from(duration) / 360.0).round() / 10.0,
                     from_id,
                 )
                 .await
@@ -266,7 +267,7 @@ pub(crate) async fn stock_ephemeral_timer_changed(
             86401..=604_799 => {
                 stock_str::msg_ephemeral_timer_days(
                     context,
-                    &format!("{}", (f64::from(duration) / 8640.0).round() / 10.0),
+                    (f64::from(duration) / 8640.0).round() / 10.0,
                     from_id,
                 )
                 .await
@@ -275,7 +276,7 @@ pub(crate) async fn stock_ephemeral_timer_changed(
             _ => {
                 stock_str::msg_ephemeral_timer_weeks(
                     context,
-                    &format!("{}", (f64::from(duration) / 60480.0).round() / 10.0),
+                    (f64::from(duration) / 60480.0).round() / 10.0,
                     from_id,
                 )
                 .await
@@ -442,7 +443,7 @@ pub(crate) async fn delete_expired_messages(context: &Context, now: i64) -> Resu
                     transaction.execute(
                         "UPDATE msgs
                      SET chat_id=?, txt='', subject='', txt_raw='',
-                         mime_headers='', from_id=0, to_id=0, param=''
+                         mime_headers='', html='', from_id=0, to_id=0, param=''
                      WHERE id=?",
                         (DC_CHAT_ID_TRASH, msg_id),
                     )?;
@@ -552,6 +553,14 @@ async fn next_expiration_timestamp(context: &Context) -> Option<i64> {
 pub(crate) async fn ephemeral_loop(context: &Context, interrupt_receiver: Receiver<()>) {
     loop {
         let ephemeral_timestamp = next_expiration_timestamp(context).await;
+        let undo_deadline = undo::next_deadline(context)
+            .await
+            .log_err(context)
+            .unwrap_or_default();
+        let ephemeral_timestamp = match (ephemeral_timestamp, undo_deadline) {
+            (Some(a), Some(b)) => Some(min(a, b)),
+            (a, b) => a.or(b),
+        };
 
         let now = SystemTime::now();
         let until = if let Some(ephemeral_timestamp) = ephemeral_timestamp {
@@ -579,6 +588,10 @@ pub(crate) async fn ephemeral_loop(context: &Context, interrupt_receiver: Receiv
             .await
             .log_err(context)
             .ok();
+        undo::execute_expired(context, time())
+            .await
+            .log_err(context)
+            .ok();
     }
 }
 
@@ -598,22 +611,33 @@ pub(crate) async fn delete_expired_imap_messages(context: &Context) -> Result<()
 
     context
         .sql
-        .execute(
-            "UPDATE imap
-             SET target=?
-             WHERE rfc724_mid IN (
+        .transaction(move |transaction| {
+            let condition = "rfc724_mid IN (
                SELECT rfc724_mid FROM msgs
-               WHERE ((download_state = 0 AND timestamp < ?) OR
-                      (download_state != 0 AND timestamp < ?) OR
-                      (ephemeral_timestamp != 0 AND ephemeral_timestamp <= ?))
-             )",
-            (
+               WHERE ((download_state = 0 AND timestamp < ?2) OR
+                      (download_state != 0 AND timestamp < ?3) OR
+                      (ephemeral_timestamp != 0 AND ephemeral_timestamp <= ?4))
+             )";
+            let params = (
                 &target,
                 threshold_timestamp,
                 threshold_timestamp_extended,
                 now,
-            ),
-        )
+            );
+            transaction.execute(
+                &format!(
+                    "INSERT INTO msgs_audit (rfc724_mid, timestamp, operation)
+                     SELECT rfc724_mid, ?4, 'Deletion scheduled (expired)'
+                     FROM imap WHERE target!=?1 AND {condition}"
+                ),
+                params,
+            )?;
+            transaction.execute(
+                &format!("UPDATE imap SET target=?1 WHERE {condition}"),
+                params,
+            )?;
+            Ok(())
+        })
         .await?;
 
     Ok(())
@@ -4,8 +4,11 @@
 use std::path::PathBuf;
 
 use crate::chat::ChatId;
+use crate::config::Config;
+use crate::configure::ConfigureStep;
 use crate::contact::ContactId;
 use crate::ephemeral::Timer as EphemeralTimer;
+use crate::job::JobKind;
 use crate::message::MsgId;
 use crate::webxdc::StatusUpdateSerial;
 
@@ -189,6 +192,34 @@ pub enum EventType {
     /// @param data1 (int) If set, this is the contact_id of an added contact that should be selected.
     ContactsChanged(Option<ContactId>),
 
+    /// Inform about the progress of importing a large address book
+    /// using `Contact::add_address_book()`.
+    ///
+    /// @param data1 (usize) 1-999=progress in permille, 1000=done
+    ContactsImportProgress(usize),
+
+    /// Inform about the progress of downloading many messages,
+    /// e.g. after being offline for a long time.
+    ///
+    /// Sent for each message if there are more new messages in a folder
+    /// than fit into one batch, see [`crate::config::Config::FetchBatchSize`],
+    /// and when existing messages are fetched, e.g. to import the mail history.
+    FetchProgress {
+        /// Number of new messages downloaded or skipped so far.
+        fetched: usize,
+
+        /// Total number of new messages.
+        total: usize,
+    },
+
+    /// Inform about the progress of importing the mail history after configuring,
+    /// see [`crate::config::Config::ImportHistoryDays`].
+    ///
+    /// Progress within a folder is reported by [`EventType::FetchProgress`].
+    ///
+    /// @param data1 (usize) 0=error or cancelled, 1-999=progress in permille, 1000=done
+    HistoryImportProgress(usize),
+
     /// Location of one or more contact has changed.
     ///
     /// @param data1 (u32) contact_id of the contact for which the location has changed.
@@ -205,6 +236,9 @@ pub enum EventType {
 
         /// Progress comment or error, something to display to the user.
         comment: Option<String>,
+
+        /// Step of the online autoconfiguration the event is about, if any.
+        step: Option<ConfigureStep>,
     },
 
     /// Inform about the import/export progress started by imex().
@@ -262,6 +296,12 @@ pub enum EventType {
     /// The user's avatar changed.
     SelfavatarChanged,
 
+    /// A setting changed, either by a call to `set_config()`
+    /// or as a result of a message from another device.
+    ///
+    /// UIs and bots showing the setting should reload it with `get_config()`.
+    ConfigChanged(Config),
+
     /// Webxdc status update received.
     WebxdcStatusUpdate {
         /// Message ID.
@@ -276,4 +316,66 @@ pub enum EventType {
         /// ID of the deleted message.
         msg_id: MsgId,
     },
+
+    /// Data received over the realtime channel of a webxdc instance,
+    /// see [`crate::peer_channels`].
+    WebxdcRealtimeData {
+        /// ID of the webxdc instance.
+        msg_id: MsgId,
+
+        /// Chat member who sent the data.
+        ///
+        /// The data is signed by the device the member advertised for the channel.
+        contact_id: ContactId,
+
+        /// Received data.
+        data: Vec<u8>,
+    },
+
+    /// Remote content of an HTML message was not loaded by `MsgId::get_sanitized_html()`.
+    ///
+    /// UIs may ask the user whether to load it once
+    /// or to set the [`crate::contact::RemoteContentDecision`] for the sender.
+    RemoteContentBlocked {
+        /// ID of the message.
+        msg_id: MsgId,
+
+        /// ID of the sender.
+        contact_id: ContactId,
+    },
+
+    /// Background fetch started by `background_fetch()` is done,
+    /// either because all messages were fetched and sent or because the timeout was reached.
+    ///
+    /// Emitted for each account and additionally with account ID 0 by the account manager
+    /// when all accounts are done.
+    BackgroundFetchDone,
+
+    /// A queued job was given up and removed from the queue,
+    /// either because it failed permanently or exceeded its retry limit.
+    JobFailed {
+        /// Kind of the job.
+        kind: JobKind,
+
+        /// ID of the message the job belongs to.
+        msg_id: MsgId,
+
+        /// Error of the last attempt.
+        error: String,
+    },
+
+    /// Inconsistent entries were found and repaired when opening the database
+    /// or during housekeeping.
+    ///
+    /// UIs may show a hint if the user should check the affected chats.
+    DatabaseRepaired {
+        /// Number of messages whose file is missing, they are marked with an error.
+        missing_files: usize,
+
+        /// Number of 1:1 chats without members whose contact was restored or which were deleted.
+        chats: usize,
+
+        /// Number of removed reactions and jobs referring to deleted messages.
+        orphans: usize,
+    },
 }
@@ -1,6 +1,86 @@
 //! # Fuzzing module.
 //!
 //! This module exposes private APIs for fuzzing.
+//!
+//! The entry points take arbitrary input and return [`FuzzError::Panic`]
+//! if parsing panics, so fuzz targets and tests can tell crashes
+//! apart from inputs which are rejected as invalid.
+//!
+//! Catching panics requires unwinding. The fuzz workspace and the test profile
+//! use `panic = "unwind"`. If the crate is built with `panic = "abort"`,
+//! e.g. with the `internals` feature in the dev profile,
+//! a panic aborts the process instead, which fuzzers also detect as a crash.
+
+#[cfg(panic = "unwind")]
+use std::any::Any;
+use std::future::Future;
+#[cfg(panic = "unwind")]
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+
+#[cfg(panic = "unwind")]
+use futures::FutureExt;
+use mailparse::MailHeaderMap;
+
+use crate::aheader::Aheader;
+use crate::context::Context;
+use crate::headerdef::{HeaderDef, HeaderDefMap};
+use crate::mimeparser;
+use crate::qr;
+
+/// Error returned by fuzzing entry points.
+#[derive(Debug, thiserror::Error)]
+pub enum FuzzError {
+    /// Input was rejected by the parser. This is the expected outcome for most inputs.
+    #[error("Invalid input: {0:#}")]
+    Invalid(anyhow::Error),
+
+    /// Parser panicked. This is always a bug.
+    ///
+    /// Only returned if panics unwind, otherwise the process is aborted.
+    #[error("Panic: {0}")]
+    Panic(String),
+}
+
+#[cfg(panic = "unwind")]
+impl FuzzError {
+    fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        FuzzError::Panic(message)
+    }
+}
+
+#[cfg(panic = "unwind")]
+fn catch_panic(f: impl FnOnce() -> anyhow::Result<()>) -> Result<(), FuzzError> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => res.map_err(FuzzError::Invalid),
+        Err(payload) => Err(FuzzError::from_panic(payload)),
+    }
+}
+
+#[cfg(not(panic = "unwind"))]
+fn catch_panic(f: impl FnOnce() -> anyhow::Result<()>) -> Result<(), FuzzError> {
+    f().map_err(FuzzError::Invalid)
+}
+
+#[cfg(panic = "unwind")]
+async fn catch_panic_async(f: impl Future<Output = anyhow::Result<()>>) -> Result<(), FuzzError> {
+    match AssertUnwindSafe(f).catch_unwind().await {
+        Ok(res) => res.map_err(FuzzError::Invalid),
+        Err(payload) => Err(FuzzError::from_panic(payload)),
+    }
+}
+
+#[cfg(not(panic = "unwind"))]
+async fn catch_panic_async(f: impl Future<Output = anyhow::Result<()>>) -> Result<(), FuzzError> {
+    f.await.map_err(FuzzError::Invalid)
+}
 
 /// Fuzzing target for simplify().
 ///
@@ -10,3 +90,94 @@
 pub fn simplify(input: String, is_chat_message: bool) {
     crate::simplify::simplify(input, is_chat_message);
 }
+
+/// Fuzzing target for MIME header parsing.
+///
+/// Parses the header of a message and extracts the addresses, Message-IDs
+/// and Autocrypt headers from it the same way as for received messages.
+pub fn mime_headers(data: &[u8]) -> Result<(), FuzzError> {
+    catch_panic(|| {
+        let (headers, _) = mailparse::parse_headers(data)?;
+        mimeparser::get_from(&headers);
+        mimeparser::get_recipients(&headers);
+        mimeparser::get_list_post(&headers);
+        for header in [
+            HeaderDef::MessageId,
+            HeaderDef::InReplyTo,
+            HeaderDef::References,
+        ] {
+            if let Some(value) = headers.get_header_value(header) {
+                mimeparser::parse_message_ids(&value);
+            }
+        }
+        for value in headers.get_all_values(HeaderDef::Autocrypt.get_headername()) {
+            Aheader::from_str(&value).ok();
+        }
+        Ok(())
+    })
+}
+
+/// Fuzzing target for Autocrypt header parsing.
+///
+/// Parses the header value including the key in `keydata`.
+pub fn autocrypt_header(input: &str) -> Result<(), FuzzError> {
+    catch_panic(|| {
+        Aheader::from_str(input)?;
+        Ok(())
+    })
+}
+
+/// Fuzzing target for QR code parsing.
+///
+/// Calls [`check_qr`](crate::qr::check_qr), which may add contacts to the database,
+/// so a throwaway context should be used.
+pub async fn qr(context: &Context, input: &str) -> Result<(), FuzzError> {
+    catch_panic_async(async {
+        qr::check_qr(context, input).await?;
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::test_utils::TestContext;
+
+    proptest! {
+        #[test]
+        fn test_mime_headers_no_panic(data: Vec<u8>) {
+            prop_assert!(!matches!(mime_headers(&data), Err(FuzzError::Panic(_))));
+        }
+
+        #[test]
+        fn test_autocrypt_header_no_panic(input: String) {
+            prop_assert!(!matches!(autocrypt_header(&input), Err(FuzzError::Panic(_))));
+        }
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        assert!(catch_panic(|| Ok(())).is_ok());
+        assert!(matches!(
+            catch_panic(|| anyhow::bail!("invalid")),
+            Err(FuzzError::Invalid(_))
+        ));
+        match catch_panic(|| panic!("parser bug")) {
+            Err(FuzzError::Panic(message)) => assert_eq!(message, "parser bug"),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_qr() {
+        let t = TestContext::new_alice().await;
+        assert!(qr(&t, "https://example.org/").await.is_ok());
+        assert!(matches!(
+            qr(&t, "DCBACKUP:garbage").await,
+            Err(FuzzError::Invalid(_))
+        ));
+    }
+}
@@ -38,8 +38,21 @@ pub enum HeaderDef {
     /// Mailing list ID defined in [RFC 2919](https://tools.ietf.org/html/rfc2919).
     ListId,
     ListPost,
+
+    /// Unsubscribe URIs of a mailing list defined in [RFC 2369](https://tools.ietf.org/html/rfc2369).
+    ListUnsubscribe,
+
+    /// Set to `List-Unsubscribe=One-Click` if the HTTPS URI in `List-Unsubscribe`
+    /// supports one-click unsubscription as defined in [RFC 8058](https://tools.ietf.org/html/rfc8058).
+    ListUnsubscribePost,
     References,
 
+    /// Set to `YES` by SpamAssassin and similar filters if the message is considered spam.
+    XSpamFlag,
+
+    /// SpamAssassin result, starting with `Yes` if the message is considered spam.
+    XSpamStatus,
+
     /// In-Reply-To header containing Message-ID of the parent message.
     InReplyTo,
 
@@ -47,6 +60,16 @@ pub enum HeaderDef {
     /// as described in [RFC 3834](https://tools.ietf.org/html/rfc3834)
     Precedence,
 
+    /// `auto-replied` for automatic replies such as vacation notices,
+    /// see [RFC 3834](https://tools.ietf.org/html/rfc3834).
+    AutoSubmitted,
+
+    /// Non-standard header set by some auto-responders.
+    XAutoreply,
+
+    /// Non-standard header set by some auto-responders.
+    XAutorespond,
+
     ContentType,
     ContentId,
     ChatVersion,
@@ -56,9 +79,16 @@ pub enum HeaderDef {
     ChatVerified,
     ChatGroupAvatar,
     ChatUserAvatar,
+
+    /// Status text of the sender, see [`crate::config::Config::Selfstatus`].
+    ChatUserStatus,
     ChatVoiceMessage,
     ChatGroupMemberRemoved,
     ChatGroupMemberAdded,
+    ChatGroupAdmins,
+
+    /// Address of the only member allowed to post to a read-only channel.
+    ChatGroupChannelOwner,
     ChatContent,
 
     /// Duration of the attached media file.
@@ -67,6 +97,13 @@ pub enum HeaderDef {
     ChatDispositionNotificationTo,
     ChatWebrtcRoom,
 
+    /// Advertisement of a webxdc realtime channel, see [`crate::peer_channels`].
+    ChatWebxdcRealtime,
+
+    /// Fingerprint of the sender's new key signed by the old key,
+    /// see [`Context::rotate_key`](crate::context::Context::rotate_key).
+    ChatKeyTransition,
+
     /// [Autocrypt](https://autocrypt.org/) header.
     Autocrypt,
     AutocryptSetupMessage,
@@ -6,6 +6,9 @@
 //! Even when the original mime-message is not HTML,
 //! `MsgId.get_html()` will return HTML -
 //! this allows nice quoting, handling linebreaks properly etc.
+//!
+//! `MsgId.get_sanitized_html()` returns the same HTML made safe to display,
+//! so that UIs do not need to sanitize it themselves.
 
 use std::future::Future;
 use std::pin::Pin;
@@ -16,13 +19,26 @@
 use lettre_email::mime::{self, Mime};
 use lettre_email::PartBuilder;
 use mailparse::ParsedContentType;
+use num_traits::FromPrimitive;
 
+use crate::config::Config;
+use crate::constants::RemoteContent;
+use crate::contact::RemoteContentDecision;
 use crate::context::Context;
+use crate::e2ee;
+use crate::events::EventType;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::message::{self, Message, MsgId};
 use crate::mimeparser::parse_message_id;
+use crate::param::Param;
 use crate::param::Param::SendHtml;
 use crate::plaintext::PlainText;
+use crate::sql;
+use crate::tools::buf_decompress;
+
+mod sanitize;
+
+pub use sanitize::{sanitize_html, SanitizedHtml};
 
 impl Message {
     /// Check if the message can be retrieved as HTML.
@@ -40,8 +56,8 @@ pub fn has_html(&self) -> bool {
     /// used as the `text/html` part in the MIME-structure.
     ///
     /// Received HTML parts are handled differently,
-    /// they are saved together with the whole MIME-structure
-    /// in `mime_headers` and the HTML-part is extracted using `MsgId::get_html()`.
+    /// they are sanitized and saved in the `html` column on receiving
+    /// and can be retrieved using `MsgId::get_html()`.
     /// (To underline this asynchronicity, we are using the wording "SendHtml")
     pub fn set_html(&mut self, html: Option<String>) {
         if let Some(html) = html {
@@ -199,8 +215,11 @@ fn cid_to_data_recursive<'a>(
                         if let Some(cid) = mail.headers.get_header_value(HeaderDef::ContentId) {
                             if let Ok(cid) = parse_message_id(&cid) {
                                 if let Ok(replacement) = mimepart_to_data_url(mail) {
+                                    // Replace references in attributes as `<img src="cid:...">`
+                                    // or `<td background="cid:...">` and in CSS as `url(cid:...)`,
+                                    // but not in the text.
                                     let re_string = format!(
-                                        "(<img[^>]*src[^>]*=[^>]*)(cid:{})([^>]*>)",
+                                        r#"(?i)(["'(=]\s*)cid:{}(["')\s>])"#,
                                         regex::escape(&cid)
                                     );
                                     match regex::Regex::new(&re_string) {
@@ -208,7 +227,7 @@ fn cid_to_data_recursive<'a>(
                                             self.html = re
                                                 .replace_all(
                                                     &self.html,
-                                                    format!("${{1}}{replacement}${{3}}").as_str(),
+                                                    format!("${{1}}{replacement}${{2}}").as_str(),
                                                 )
                                                 .as_ref()
                                                 .to_string()
@@ -239,13 +258,47 @@ fn mimepart_to_data_url(mail: &mailparse::ParsedMail<'_>) -> Result<String> {
     Ok(format!("data:{};base64,{}", mail.ctype.mimetype, data))
 }
 
+/// Converts a received mime-message to HTML to be stored in the database.
+///
+/// The HTML is self-contained, `cid:` images are inlined, and active content is removed.
+/// Remote content is kept, it is only removed when the HTML is displayed,
+/// see [`MsgId::get_sanitized_html`].
+pub(crate) async fn sanitized_html_from_bytes(context: &Context, rawmime: &[u8]) -> Result<String> {
+    let parser = HtmlMsgParser::from_bytes(context, rawmime).await?;
+    Ok(sanitize::remove_active_content(&parser.html))
+}
+
 impl MsgId {
     /// Get HTML by database message id.
-    /// This requires `mime_headers` field to be set for the message;
-    /// this is the case at least when `Message.has_html()` returns true
+    ///
+    /// For received messages, this is the sanitized HTML stored
+    /// when `Message.has_html()` returns true, with `cid:` images inlined.
+    /// Otherwise, HTML is created from the `mime_headers` field
     /// (we do not save raw mime unconditionally in the database to save space).
     /// The corresponding ffi-function is `dc_get_msg_html()`.
     pub async fn get_html(self, context: &Context) -> Result<Option<String>> {
+        let (html, encrypted_at_rest) = context
+            .sql
+            .query_row(
+                "SELECT html, encrypted_at_rest FROM msgs WHERE id=?",
+                (self,),
+                |row| {
+                    let html = sql::row_get_vec(row, 0)?;
+                    let encrypted_at_rest: bool = row.get(1)?;
+                    Ok((html, encrypted_at_rest))
+                },
+            )
+            .await?;
+        if !html.is_empty() {
+            let html = if encrypted_at_rest {
+                e2ee::decrypt_at_rest(context, &html).await?
+            } else {
+                html
+            };
+            let html = buf_decompress(&html)?;
+            return Ok(Some(String::from_utf8_lossy(&html).into_owned()));
+        }
+
         let rawmime = message::get_mime_headers(context, self).await?;
 
         if !rawmime.is_empty() {
@@ -261,6 +314,60 @@ pub async fn get_html(self, context: &Context) -> Result<Option<String>> {
             Ok(None)
         }
     }
+
+    /// Gets the HTML of the message like [`MsgId::get_html`], sanitized to be safe to display.
+    ///
+    /// Remote content is loaded as configured by [`Config::RemoteContent`]
+    /// and the [`RemoteContentDecision`] for the sender.
+    /// [`RemoteContentDecision::Allow`] is only applied if the sender address is authenticated,
+    /// otherwise anybody could forge the address to load remote content.
+    /// `load_remote` loads remote content once, e.g. because the user was asked,
+    /// unless [`Config::RemoteContent`] is set to never load it.
+    ///
+    /// Emits [`EventType::RemoteContentBlocked`] if remote content was removed
+    /// and the user may be asked whether to load it.
+    /// The corresponding ffi-function is `dc_get_msg_sanitized_html()`.
+    pub async fn get_sanitized_html(
+        self,
+        context: &Context,
+        load_remote: bool,
+    ) -> Result<Option<SanitizedHtml>> {
+        let Some(html) = self.get_html(context).await? else {
+            return Ok(None);
+        };
+        let msg = Message::load_from_db(context, self).await?;
+        let from_id = msg.from_id;
+        let mut decision = from_id.get_remote_content_decision(context).await?;
+        if decision == RemoteContentDecision::Allow
+            && !msg
+                .param
+                .get_bool(Param::SenderAuthenticated)
+                .unwrap_or_default()
+        {
+            decision = RemoteContentDecision::Undecided;
+        }
+        let remote_content =
+            RemoteContent::from_i32(context.get_config_int(Config::RemoteContent).await?)
+                .unwrap_or_default();
+        let allow_remote = match (remote_content, decision) {
+            (RemoteContent::Never, _) => false,
+            (_, RemoteContentDecision::Allow) => true,
+            (_, RemoteContentDecision::Block) => load_remote,
+            (RemoteContent::Ask, RemoteContentDecision::Undecided) => load_remote,
+            (RemoteContent::Always, RemoteContentDecision::Undecided) => true,
+        };
+        let sanitized = sanitize_html(&html, allow_remote);
+        if sanitized.blocked_remote_content
+            && remote_content == RemoteContent::Ask
+            && decision == RemoteContentDecision::Undecided
+        {
+            context.emit_event(EventType::RemoteContentBlocked {
+                msg_id: self,
+                contact_id: from_id,
+            });
+        }
+        Ok(Some(sanitized))
+    }
 }
 
 /// Wraps HTML text into a new text/html mimepart structure.
@@ -282,7 +389,7 @@ mod tests {
     use crate::contact::ContactId;
     use crate::message::{MessengerMessage, Viewtype};
     use crate::receive_imf::receive_imf;
-    use crate::test_utils::TestContext;
+    use crate::test_utils::{TestContext, TestContextManager};
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_htmlparse_plain_unspecified() {
@@ -436,6 +543,46 @@ async fn test_htmlparse_apple_cid_jpg() {
         assert!(!parser.html.contains("cid:"));
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_htmlparse_cid_background() {
+        let t = TestContext::new().await;
+        let raw = b"From: sender@example.org\n\
+                    To: receiver@example.org\n\
+                    Subject: Newsletter\n\
+                    Content-Type: multipart/related; boundary=\"BOUNDARY\"\n\
+                    \n\
+                    --BOUNDARY\n\
+                    Content-Type: text/html\n\
+                    \n\
+                    <table><tr><td background=\"cid:bg\" style=\"background: url(cid:bg)\">\
+                    <img src=cid:logo>cid:bg</td></tr></table>\n\
+                    --BOUNDARY\n\
+                    Content-Type: image/png\n\
+                    Content-ID: <bg>\n\
+                    Content-Transfer-Encoding: base64\n\
+                    \n\
+                    iVBORw0KGgo=\n\
+                    --BOUNDARY\n\
+                    Content-Type: image/png\n\
+                    Content-ID: <logo>\n\
+                    Content-Transfer-Encoding: base64\n\
+                    \n\
+                    R0lGODlh\n\
+                    --BOUNDARY--\n";
+        let parser = HtmlMsgParser::from_bytes(&t.ctx, raw).await.unwrap();
+        assert!(parser
+            .html
+            .contains(r#"background="data:image/png;base64,iVBORw0KGgo=""#));
+        assert!(parser
+            .html
+            .contains("url(data:image/png;base64,iVBORw0KGgo=)"));
+        assert!(parser
+            .html
+            .contains("<img src=data:image/png;base64,R0lGODlh>"));
+        // Text is not replaced.
+        assert!(parser.html.contains(">cid:bg<"));
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_get_html_invalid_msgid() {
         let t = TestContext::new().await;
@@ -582,4 +729,177 @@ async fn test_cp1252_html() -> Result<()> {
         assert!(html.contains("foo bar ä ö ü ß"));
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_sanitized_html() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        receive_imf(
+            &t,
+            b"From: Bob <bob@example.net>\n\
+              To: alice@example.org\n\
+              Subject: Newsletter\n\
+              Message-ID: <newsletter@example.net>\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              Content-Type: text/html; charset=utf-8\n\
+              \n\
+              <p>News<script>alert(1)</script><img src=\"https://example.net/t.png\"></p>\n",
+            false,
+        )
+        .await?;
+        let msg_id = t.get_last_msg().await.id;
+        let html = msg_id.get_html(&t).await?.unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<img src=\"https://example.net/t.png\">"));
+
+        let sanitized = msg_id.get_sanitized_html(&t, false).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+        assert!(sanitized.html.contains("<p>News<img></p>"));
+
+        let sanitized = msg_id.get_sanitized_html(&t, true).await?.unwrap();
+        assert!(!sanitized.blocked_remote_content);
+        assert!(sanitized
+            .html
+            .contains("<p>News<img src=\"https://example.net/t.png\"></p>"));
+
+        t.set_config(Config::RemoteContent, Some("0")).await?;
+        let sanitized = msg_id.get_sanitized_html(&t, true).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+
+        t.set_config(Config::RemoteContent, Some("2")).await?;
+        let sanitized = msg_id.get_sanitized_html(&t, false).await?.unwrap();
+        assert!(!sanitized.blocked_remote_content);
+        assert!(t
+            .set_config(Config::RemoteContent, Some("3"))
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_html_stored() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        receive_imf(
+            &t,
+            include_bytes!("../test-data/message/apple_cid_jpg.eml"),
+            false,
+        )
+        .await?;
+        let msg = t.get_last_msg().await;
+        assert!(msg.has_html());
+
+        // The raw message is not saved, only the sanitized HTML.
+        assert!(message::get_mime_headers(&t, msg.id).await?.is_empty());
+        let html = msg.id.get_html(&t).await?.unwrap();
+        assert!(!html.contains("Content-Security-Policy"));
+        assert!(html.contains("src=\"data:image/jpeg;base64,/9j/4AAQ"));
+        assert!(!html.contains("cid:"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_remote_content_decision() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        receive_imf(
+            &t,
+            b"From: Bob <bob@example.net>\n\
+              To: alice@example.org\n\
+              Subject: Newsletter\n\
+              Message-ID: <newsletter@example.net>\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              Content-Type: text/html; charset=utf-8\n\
+              \n\
+              <p>News<img src=\"https://example.net/t.png\"></p>\n",
+            false,
+        )
+        .await?;
+        let msg = t.get_last_msg().await;
+        let bob_id = msg.from_id;
+        assert_eq!(
+            bob_id.get_remote_content_decision(&t).await?,
+            RemoteContentDecision::Undecided
+        );
+
+        let sanitized = msg.id.get_sanitized_html(&t, false).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+        let event = t
+            .evtracker
+            .get_matching(|evt| matches!(evt, EventType::RemoteContentBlocked { .. }))
+            .await;
+        assert_eq!(
+            event,
+            EventType::RemoteContentBlocked {
+                msg_id: msg.id,
+                contact_id: bob_id
+            }
+        );
+
+        // The sender address is not authenticated, so allowing the sender is not enough.
+        bob_id
+            .set_remote_content_decision(&t, RemoteContentDecision::Allow)
+            .await?;
+        let sanitized = msg.id.get_sanitized_html(&t, false).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+        let sanitized = msg.id.get_sanitized_html(&t, true).await?.unwrap();
+        assert!(!sanitized.blocked_remote_content);
+
+        // Blocked senders are not loaded even if loading is always allowed.
+        bob_id
+            .set_remote_content_decision(&t, RemoteContentDecision::Block)
+            .await?;
+        t.set_config(Config::RemoteContent, Some("2")).await?;
+        let sanitized = msg.id.get_sanitized_html(&t, false).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+        let sanitized = msg.id.get_sanitized_html(&t, true).await?.unwrap();
+        assert!(!sanitized.blocked_remote_content);
+
+        // Never loading remote content overrides allowed senders.
+        bob_id
+            .set_remote_content_decision(&t, RemoteContentDecision::Allow)
+            .await?;
+        t.set_config(Config::RemoteContent, Some("0")).await?;
+        let sanitized = msg.id.get_sanitized_html(&t, true).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+
+        assert!(ContactId::SELF
+            .set_remote_content_decision(&t, RemoteContentDecision::Allow)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_remote_content_allowed_authenticated_sender() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = alice.send_text(alice_chat.id, "Hi!").await;
+        bob.recv_msg(&sent).await;
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "Hello!").await;
+        alice.recv_msg(&sent).await;
+
+        // Signed with a key known before the message.
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("News".to_string());
+        msg.set_html(Some(
+            "<p>News<img src=\"https://example.net/t.png\"></p>".to_string(),
+        ));
+        let sent = bob.send_msg(bob_chat.id, &mut msg).await;
+        let msg = alice.recv_msg(&sent).await;
+        assert!(msg
+            .param
+            .get_bool(Param::SenderAuthenticated)
+            .unwrap_or_default());
+
+        let sanitized = msg.id.get_sanitized_html(&alice, false).await?.unwrap();
+        assert!(sanitized.blocked_remote_content);
+        msg.from_id
+            .set_remote_content_decision(&alice, RemoteContentDecision::Allow)
+            .await?;
+        let sanitized = msg.id.get_sanitized_html(&alice, false).await?.unwrap();
+        assert!(!sanitized.blocked_remote_content);
+        Ok(())
+    }
 }
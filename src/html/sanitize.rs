@@ -0,0 +1,646 @@
+//! # HTML sanitizer.
+//!
+//! Makes the HTML of received messages safe to display:
+//! scripts, frames, plugins and event handlers are removed,
+//! links are restricted to harmless schemes
+//! and remote content such as images and stylesheets is only kept if allowed.
+//!
+//! The result has a `Content-Security-Policy` matching these rules in its `<head>`,
+//! so that UIs rendering it in a web view get a second line of defense.
+
+/// Elements removed together with their content.
+const DROPPED_ELEMENTS: &[&str] = &[
+    "applet",
+    "frameset",
+    "iframe",
+    "math",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "plaintext",
+    "script",
+    "svg",
+    "template",
+    "title",
+    "xmp",
+];
+
+/// Elements removed while their content is kept.
+const UNWRAPPED_ELEMENTS: &[&str] = &["form"];
+
+/// Void elements removed completely.
+const DROPPED_VOID_ELEMENTS: &[&str] = &[
+    "base", "embed", "frame", "link", "meta", "param", "source", "track",
+];
+
+/// Attributes loading content when the message is displayed.
+const RESOURCE_ATTRIBUTES: &[&str] = &["src", "background", "poster"];
+
+/// Attributes removed from all elements.
+const DROPPED_ATTRIBUTES: &[&str] = &[
+    "action",
+    "dynsrc",
+    "formaction",
+    "http-equiv",
+    "lowsrc",
+    "ping",
+    "srcdoc",
+    "srcset",
+    "xlink:href",
+];
+
+/// Schemes allowed in links.
+const LINK_SCHEMES: &[&str] = &["http:", "https:", "mailto:", "tel:", "openpgp4fpr:"];
+
+/// HTML made safe to display by [`sanitize_html`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizedHtml {
+    /// The sanitized HTML.
+    pub html: String,
+
+    /// True if remote content was removed because loading it was not allowed.
+    ///
+    /// UIs may then offer to load the remote content.
+    pub blocked_remote_content: bool,
+}
+
+/// Removes everything that could run code or, unless `allow_remote` is set,
+/// load content from the network when displaying `html`.
+pub fn sanitize_html(html: &str, allow_remote: bool) -> SanitizedHtml {
+    let sanitizer = Sanitizer::run(html, allow_remote);
+
+    let remote = if allow_remote { " http: https:" } else { "" };
+    let csp = format!(
+        "default-src 'none'; img-src data:{remote}; media-src data:{remote}; \
+         font-src data:{remote}; style-src 'unsafe-inline' data:{remote}"
+    );
+    let meta = format!("<meta http-equiv=\"Content-Security-Policy\" content=\"{csp}\">");
+    let out = &sanitizer.out;
+    // The policy must be in the `<head>`, which is created if there is only an `<html>` element.
+    // Without both, it is put at the start, where it is part of the implied `<head>`.
+    let body = if let Some(pos) = sanitizer.head_content_start {
+        format!(
+            "{}{meta}{}",
+            out.get(..pos).unwrap_or_default(),
+            out.get(pos..).unwrap_or_default()
+        )
+    } else if let Some(pos) = sanitizer.html_content_start {
+        format!(
+            "{}<head>{meta}</head>{}",
+            out.get(..pos).unwrap_or_default(),
+            out.get(pos..).unwrap_or_default()
+        )
+    } else {
+        format!("{meta}\n{out}")
+    };
+    let html = sanitizer.doctype.unwrap_or_default() + &body;
+    SanitizedHtml {
+        html,
+        blocked_remote_content: sanitizer.blocked_remote_content,
+    }
+}
+
+/// Removes everything that could run code from `html`, but keeps remote content.
+///
+/// Used for the HTML stored for received messages,
+/// which is sanitized again by [`sanitize_html`] when it is displayed.
+/// Unlike [`sanitize_html`], no `Content-Security-Policy` is added.
+pub(crate) fn remove_active_content(html: &str) -> String {
+    let sanitizer = Sanitizer::run(html, true);
+    sanitizer.doctype.unwrap_or_default() + &sanitizer.out
+}
+
+struct Sanitizer {
+    allow_remote: bool,
+    out: String,
+    doctype: Option<String>,
+    blocked_remote_content: bool,
+
+    /// Position in `out` after the first `<head>` start tag.
+    head_content_start: Option<usize>,
+
+    /// Position in `out` after the first `<html>` start tag.
+    html_content_start: Option<usize>,
+}
+
+impl Sanitizer {
+    fn run(html: &str, allow_remote: bool) -> Self {
+        let mut sanitizer = Self {
+            allow_remote,
+            out: String::with_capacity(html.len()),
+            doctype: None,
+            blocked_remote_content: false,
+            head_content_start: None,
+            html_content_start: None,
+        };
+        let mut rest = html;
+        while !rest.is_empty() {
+            let Some(pos) = rest.find('<') else {
+                sanitizer.out += rest;
+                break;
+            };
+            sanitizer.out += rest.get(..pos).unwrap_or_default();
+            rest = rest.get(pos..).unwrap_or_default();
+            rest = sanitizer.markup(rest);
+        }
+        sanitizer
+    }
+
+    /// Handles markup starting with `<`, returns the remaining input.
+    fn markup<'a>(&mut self, input: &'a str) -> &'a str {
+        let after = input.get(1..).unwrap_or_default();
+        if let Some(comment) = after.strip_prefix("!--") {
+            return comment
+                .find("-->")
+                .map_or("", |end| comment.get(end + 3..).unwrap_or_default());
+        }
+        if after.starts_with('!') || after.starts_with('?') {
+            let end = after.find('>').map_or(after.len(), |end| end + 1);
+            let declaration = after.get(..end).unwrap_or_default();
+            if self.doctype.is_none()
+                && self.out.trim().is_empty()
+                && declaration.to_ascii_lowercase().starts_with("!doctype")
+                && !declaration.contains('<')
+            {
+                self.doctype = Some(format!("<{declaration}"));
+            }
+            return after.get(end..).unwrap_or_default();
+        }
+        if let Some(closing) = after.strip_prefix('/') {
+            let Some((name, rest)) = tag_name(closing) else {
+                self.out += "&lt;";
+                return after;
+            };
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            if !UNWRAPPED_ELEMENTS.contains(&name.as_str())
+                && !DROPPED_VOID_ELEMENTS.contains(&name.as_str())
+                && !DROPPED_ELEMENTS.contains(&name.as_str())
+            {
+                self.out += &format!("</{name}>");
+            }
+            return rest.get(end..).unwrap_or_default();
+        }
+        let Some((name, rest)) = tag_name(after) else {
+            self.out += "&lt;";
+            return after;
+        };
+        let Some((attributes, self_closing, rest)) = parse_attributes(rest) else {
+            // Unterminated tag at the end of the input.
+            return "";
+        };
+
+        if DROPPED_ELEMENTS.contains(&name.as_str()) {
+            return if self_closing.is_some() {
+                rest
+            } else {
+                skip_raw_text(rest, &name)
+            };
+        }
+        if UNWRAPPED_ELEMENTS.contains(&name.as_str())
+            || DROPPED_VOID_ELEMENTS.contains(&name.as_str())
+        {
+            return rest;
+        }
+
+        self.out.push('<');
+        self.out += &name;
+        for (attr_name, value) in attributes {
+            if let Some(value) = self.attribute(&attr_name, value) {
+                self.out += &format!(" {attr_name}=\"{}\"", value.replace('"', "&quot;"));
+            }
+        }
+        if let Some(self_closing) = self_closing {
+            self.out += self_closing;
+        }
+        self.out.push('>');
+        match name.as_str() {
+            "head" => {
+                self.head_content_start.get_or_insert(self.out.len());
+            }
+            "html" => {
+                self.html_content_start.get_or_insert(self.out.len());
+            }
+            _ => {}
+        }
+
+        match name.as_str() {
+            "style" => {
+                let (css, rest) = split_raw_text(rest, &name);
+                if let Some(css) = self.css(css) {
+                    self.out += &css;
+                }
+                self.out += "</style>";
+                rest
+            }
+            "textarea" => {
+                let (text, rest) = split_raw_text(rest, &name);
+                self.out += &text.replace('<', "&lt;");
+                self.out += "</textarea>";
+                rest
+            }
+            _ => rest,
+        }
+    }
+
+    /// Returns the sanitized attribute value or `None` if the attribute is removed.
+    fn attribute(&mut self, name: &str, value: Option<String>) -> Option<String> {
+        if name.starts_with("on") || DROPPED_ATTRIBUTES.contains(&name) {
+            return None;
+        }
+        let value = value.unwrap_or_default();
+        if RESOURCE_ATTRIBUTES.contains(&name) {
+            return self.is_allowed_resource(&value).then_some(value);
+        }
+        match name {
+            "href" => {
+                let url = normalize_url(&value);
+                (url.starts_with('#') || LINK_SCHEMES.iter().any(|s| url.starts_with(s)))
+                    .then_some(value)
+            }
+            // Character references are decoded, so they cannot hide `url(`,
+            // and `&` is escaped, so the browser does not decode them a second time.
+            "style" => self
+                .css(&decode_char_refs(&value))
+                .map(|css| css.replace('&', "&amp;")),
+            _ => Some(value),
+        }
+    }
+
+    /// Returns true if the URL may be loaded when displaying the message.
+    ///
+    /// Sets `blocked_remote_content` if a remote URL is not allowed.
+    fn is_allowed_resource(&mut self, url: &str) -> bool {
+        let url = normalize_url(url);
+        if url.starts_with("data:image/") {
+            true
+        } else if url.starts_with("http:") || url.starts_with("https:") || url.starts_with("//") {
+            if !self.allow_remote {
+                self.blocked_remote_content = true;
+            }
+            self.allow_remote
+        } else {
+            false
+        }
+    }
+
+    /// Returns the sanitized CSS or `None` if it cannot be made safe.
+    ///
+    /// CSS containing escapes is rejected, as they may hide `url(` or `@import`,
+    /// and so is `image-set()`, which loads images from plain strings.
+    fn css(&mut self, css: &str) -> Option<String> {
+        let lowercase = css.to_ascii_lowercase();
+        if [
+            "expression",
+            "behavior",
+            "-moz-binding",
+            "javascript:",
+            "image-set",
+            "\\",
+            "</",
+        ]
+        .iter()
+        .any(|keyword| lowercase.contains(keyword))
+        {
+            return None;
+        }
+
+        let mut out = String::with_capacity(css.len());
+        let mut rest = css;
+        loop {
+            let lowercase = rest.to_ascii_lowercase();
+            let url_pos = lowercase.find("url(");
+            let import_pos = lowercase.find("@import");
+            let import_first = match (url_pos, import_pos) {
+                (Some(url_pos), Some(import_pos)) => import_pos < url_pos,
+                (None, Some(_)) => true,
+                _ => false,
+            };
+            if let (true, Some(pos)) = (import_first, import_pos) {
+                // Imported stylesheets are never loaded.
+                out += rest.get(..pos).unwrap_or_default();
+                let after = rest.get(pos..).unwrap_or_default();
+                let end = after.find(';').map_or(after.len(), |end| end + 1);
+                if !self.allow_remote {
+                    self.blocked_remote_content = true;
+                }
+                rest = after.get(end..).unwrap_or_default();
+            } else if let Some(pos) = url_pos {
+                out += rest.get(..pos).unwrap_or_default();
+                let inner = rest.get(pos + 4..).unwrap_or_default();
+                let end = inner.find(')').unwrap_or(inner.len());
+                let url = inner.get(..end).unwrap_or_default();
+                let url = url.trim().trim_matches(|c| c == '"' || c == '\'');
+                if self.is_allowed_resource(url) {
+                    out += &format!("url('{}')", url.replace('\'', "%27"));
+                } else {
+                    out += "none";
+                }
+                rest = inner.get(end + 1..).unwrap_or_default();
+            } else {
+                out += rest;
+                return Some(out);
+            }
+        }
+    }
+}
+
+/// Parses a lowercase tag name at the start of `input`.
+fn tag_name(input: &str) -> Option<(String, &str)> {
+    if !input.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let end = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':'))
+        .unwrap_or(input.len());
+    Some((
+        input.get(..end)?.to_ascii_lowercase(),
+        input.get(end..).unwrap_or_default(),
+    ))
+}
+
+/// Attributes as lowercase names and raw values.
+type Attributes = Vec<(String, Option<String>)>;
+
+/// Parses the attributes of a tag until the closing `>`.
+///
+/// Returns the attributes, the `/` of a self-closing tag with the whitespace before it,
+/// so that the tag is serialized as it was written,
+/// and the input after the tag or `None` if the tag is not terminated.
+fn parse_attributes(mut input: &str) -> Option<(Attributes, Option<&str>, &str)> {
+    let mut attributes = Vec::new();
+    let mut self_closing = None;
+    loop {
+        let trimmed = input.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        let whitespace_len = input.len() - trimmed.len();
+        if let Some(rest) = trimmed.strip_prefix('>') {
+            return Some((attributes, self_closing, rest));
+        }
+        if let Some(rest) = trimmed.strip_prefix('/') {
+            self_closing = input.get(..whitespace_len + 1);
+            input = rest;
+            continue;
+        }
+        input = trimmed;
+        if input.is_empty() {
+            return None;
+        }
+        self_closing = None;
+
+        let end = input
+            .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>' || c == '=')
+            .unwrap_or(input.len())
+            .max(1);
+        let name = input.get(..end)?.to_ascii_lowercase();
+        input = input
+            .get(end..)?
+            .trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        let mut value = None;
+        if let Some(rest) = input.strip_prefix('=') {
+            let rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            if let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                let quoted = rest.get(1..)?;
+                let end = quoted.find(quote)?;
+                value = Some(quoted.get(..end)?.to_string());
+                input = quoted.get(end + 1..)?;
+            } else {
+                let end = rest
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                value = Some(rest.get(..end)?.to_string());
+                input = rest.get(end..)?;
+            }
+        }
+        if name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ':' || c == '_')
+            && !attributes.iter().any(|(n, _)| *n == name)
+        {
+            attributes.push((name, value));
+        }
+    }
+}
+
+/// Splits the content of a raw text element like `<style>` from the input after its end tag.
+fn split_raw_text<'a>(input: &'a str, name: &str) -> (&'a str, &'a str) {
+    let end_tag = format!("</{name}");
+    let Some(pos) = input.to_ascii_lowercase().find(&end_tag) else {
+        return (input, "");
+    };
+    let text = input.get(..pos).unwrap_or_default();
+    let after = input.get(pos..).unwrap_or_default();
+    let end = after.find('>').map_or(after.len(), |end| end + 1);
+    (text, after.get(end..).unwrap_or_default())
+}
+
+/// Skips the content of a removed element, returns the input after its end tag.
+fn skip_raw_text<'a>(input: &'a str, name: &str) -> &'a str {
+    split_raw_text(input, name).1
+}
+
+/// Decodes character references and removes whitespace and control characters
+/// that browsers ignore in URLs, for checking the scheme.
+fn normalize_url(url: &str) -> String {
+    decode_char_refs(url)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// Decodes numeric character references and the named ones that may hide a URL scheme.
+///
+/// Other named references are kept, so URLs containing them before the scheme
+/// do not match any allowed scheme.
+fn decode_char_refs(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('&') {
+        out += rest.get(..pos).unwrap_or_default();
+        rest = rest.get(pos + 1..).unwrap_or_default();
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+            .unwrap_or(rest.len());
+        let reference = rest.get(..end).unwrap_or_default();
+        let decoded = if let Some(hex) = reference
+            .strip_prefix("#x")
+            .or_else(|| reference.strip_prefix("#X"))
+        {
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        } else if let Some(dec) = reference.strip_prefix('#') {
+            dec.parse().ok().and_then(char::from_u32)
+        } else {
+            match reference.to_ascii_lowercase().as_str() {
+                "colon" => Some(':'),
+                "tab" => Some('\t'),
+                "newline" => Some('\n'),
+                "amp" => Some('&'),
+                "sol" => Some('/'),
+                _ => None,
+            }
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = rest.get(end..).unwrap_or_default();
+                rest = rest.strip_prefix(';').unwrap_or(rest);
+            }
+            None => out.push('&'),
+        }
+    }
+    out += rest;
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(html: &str, allow_remote: bool) -> (String, bool) {
+        let sanitized = sanitize_html(html, allow_remote);
+        let body = sanitized
+            .html
+            .split_once('\n')
+            .map(|(_, body)| body.to_string())
+            .unwrap_or_default();
+        (body, sanitized.blocked_remote_content)
+    }
+
+    #[test]
+    fn test_sanitize_scripts() {
+        let (html, _) = body(
+            "<p onclick=\"alert(1)\">Hi<script>alert(2)</script>\
+             <iframe src=\"https://example.org\"></iframe></p>\
+             <a href=\"javascript:alert(3)\">a</a>\
+             <a href=\"java&#115;cript&colon;alert(4)\">b</a>\
+             <a href=\" JAVASCRIPT:alert(5)\">c</a>\
+             <a href=\"https://delta.chat\">d</a><a href=\"mailto:bob@example.net\">e</a>\
+             <!-- <script>alert(6)</script> -->",
+            false,
+        );
+        assert_eq!(
+            html,
+            "<p>Hi</p><a>a</a><a>b</a><a>c</a>\
+             <a href=\"https://delta.chat\">d</a><a href=\"mailto:bob@example.net\">e</a>"
+        );
+
+        // Raw text elements cannot hide markup inside attributes.
+        let (html, _) = body(
+            "<xmp><p title=\"</xmp><img src=x onerror=alert(1)>\"></xmp>\
+             <textarea><img src=x onerror=alert(1)></textarea>",
+            false,
+        );
+        assert_eq!(
+            html,
+            "<img>\"><textarea>&lt;img src=x onerror=alert(1)></textarea>"
+        );
+
+        let (html, _) = body("<div style=\"width: expression(alert(1))\">x</div>", false);
+        assert_eq!(html, "<div>x</div>");
+        // Character references in styles are not decoded twice.
+        let (html, _) = body("<p style=\"background: u&amp;#114;l(x)\">x</p>", false);
+        assert_eq!(html, "<p style=\"background: u&amp;#114;l(x)\">x</p>");
+        assert_eq!(body("1 < 2 <", false).0, "1 &lt; 2 &lt;");
+        assert_eq!(body("<img src='a\"b' alt=x", false).0, "");
+    }
+
+    #[test]
+    fn test_sanitize_remote_content() {
+        let html = "<img src=\"https://example.org/tracker.png\" alt=\"t\">\
+                    <img src=\"data:image/png;base64,AAAA\">\
+                    <td background=\"http://example.org/bg.png\" \
+                    style=\"background: url('https://example.org/bg.png')\">x</td>\
+                    <style>@import \"https://example.org/x.css\"; p { color: red }</style>";
+        let (blocked, has_blocked) = body(html, false);
+        assert!(has_blocked);
+        assert!(!blocked.contains("example.org"));
+        assert!(blocked.contains("<img alt=\"t\">"));
+        assert!(blocked.contains("<img src=\"data:image/png;base64,AAAA\">"));
+        assert!(blocked.contains("<td style=\"background: none\">x</td>"));
+        assert!(blocked.contains("<style> p { color: red }</style>"));
+
+        let (allowed, has_blocked) = body(html, true);
+        assert!(!has_blocked);
+        assert!(allowed.contains("<img src=\"https://example.org/tracker.png\" alt=\"t\">"));
+        assert!(allowed.contains("url('https://example.org/bg.png')"));
+        // Imports are dropped in any case.
+        assert!(!allowed.contains("@import"));
+
+        // Remote content cannot be hidden from the sanitizer.
+        for html in [
+            "<p style=\"background: image-set('https://example.org/a.png' 1x)\">x</p>",
+            "<p style=\"background: -webkit-image-set('https://example.org/a.png' 1x)\">x</p>",
+            "<p style=\"background: IMAGE-SET(url(https://example.org/a.png) 1x)\">x</p>",
+            "<p style=\"background: u\\72 l(https://example.org/a.png)\">x</p>",
+            "<p style=\"background: u&#114;l(https://example.org/a.png)\">x</p>",
+            "<style>@\\69mport 'https://example.org/x.css';</style>",
+            "<style>p { background: image-set(\"https://example.org/a.png\" 1x) }</style>",
+        ] {
+            let (sanitized, _) = body(html, false);
+            assert!(!sanitized.contains("example.org/a.png"), "{html}");
+            assert!(!sanitized.contains("example.org/x.css"), "{html}");
+        }
+
+        let sanitized = sanitize_html("<img src=\"https://example.org/x.png\">", false);
+        assert!(sanitized.html.contains("img-src data:;"));
+        let sanitized = sanitize_html("<img src=\"https://example.org/x.png\">", true);
+        assert!(sanitized.html.contains("img-src data: http: https:;"));
+    }
+
+    #[test]
+    fn test_sanitize_doctype() {
+        let sanitized = sanitize_html(
+            "<!DOCTYPE html>\n<html><head><meta http-equiv=\"refresh\" content=\"0; url=https://example.org\">\
+             <title>Hi</title><base href=\"https://example.org\"></head>\
+             <body><form action=\"https://example.org\"><b>Hi</b></form></body></html>",
+            false,
+        );
+        assert!(sanitized.html.starts_with(
+            "<!DOCTYPE html>\n<html><head>\
+             <meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none';"
+        ));
+        assert!(sanitized
+            .html
+            .ends_with("\"></head><body><b>Hi</b></body></html>"));
+    }
+
+    #[test]
+    fn test_sanitize_csp_in_head() {
+        let csp = "<meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none'; \
+                   img-src data:; media-src data:; font-src data:; style-src 'unsafe-inline' data:\">";
+
+        let sanitized = sanitize_html(
+            "<HTML lang=\"de\"><Head ></head><body>x</body></html>",
+            false,
+        );
+        assert_eq!(
+            sanitized.html,
+            format!("<html lang=\"de\"><head>{csp}</head><body>x</body></html>")
+        );
+
+        let sanitized = sanitize_html("<html><body><header>x</header></body></html>", false);
+        assert_eq!(
+            sanitized.html,
+            format!("<html><head>{csp}</head><body><header>x</header></body></html>")
+        );
+
+        // Without `<html>` and `<head>`, the implied `<head>` starts the document.
+        let sanitized = sanitize_html("<p>x</p>", false);
+        assert_eq!(sanitized.html, format!("{csp}\n<p>x</p>"));
+    }
+
+    #[test]
+    fn test_remove_active_content() {
+        let html = "<!DOCTYPE html>\n<html><head></head><body>\
+                    <p onclick=\"alert(1)\">Hi<br/>there<br />\
+                    <img src=\"https://example.org/x.png\"/></p>\
+                    <script>alert(2)</script></body></html>";
+        assert_eq!(
+            remove_active_content(html),
+            "<!DOCTYPE html>\n<html><head></head><body>\
+             <p>Hi<br/>there<br />\
+             <img src=\"https://example.org/x.png\"/></p></body></html>"
+        );
+    }
+}
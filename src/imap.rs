@@ -9,16 +9,19 @@
     collections::{BTreeMap, BTreeSet, HashMap},
     iter::Peekable,
     mem::take,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
 };
 
-use anyhow::{bail, format_err, Context as _, Result};
+use anyhow::{bail, ensure, format_err, Context as _, Result};
 use async_channel::Receiver;
 use async_imap::types::{Fetch, Flag, Name, NameAttribute, UnsolicitedResponse};
 use futures::{StreamExt, TryStreamExt};
 use num_traits::FromPrimitive;
 
+use crate::audit;
 use crate::chat::{self, ChatId, ChatIdBlocked};
-use crate::config::Config;
+use crate::config::{Config, InternalConfig};
 use crate::constants::{
     Blocked, Chattype, ShowEmails, DC_FETCH_EXISTING_MSGS_COUNT, DC_FOLDERS_CONFIGURED_VERSION,
 };
@@ -26,41 +29,134 @@
 use crate::context::Context;
 use crate::events::EventType;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
+use crate::log::LogExt;
 use crate::login_param::{CertificateChecks, LoginParam, ServerLoginParam};
 use crate::message::{self, Message, MessageState, MessengerMessage, MsgId, Viewtype};
-use crate::mimeparser;
+use crate::mimeparser::{self, MessageStreamer};
 use crate::oauth2::get_oauth2_access_token;
 use crate::provider::Socket;
 use crate::receive_imf::{
-    from_field_to_contact_id, get_prefetch_parent_message, receive_imf_inner, ReceivedMsg,
+    from_field_to_contact_id, get_prefetch_parent_message, receive_imf_inner, receive_streamed_imf,
+    ReceivedMsg,
 };
 use crate::scheduler::connectivity::ConnectivityStore;
 use crate::scheduler::InterruptInfo;
 use crate::socks::Socks5Config;
 use crate::sql;
 use crate::stock_str;
-use crate::tools::create_id;
+use crate::tools::{create_id, time};
 
 pub(crate) mod capabilities;
 mod client;
+pub(crate) mod drafts;
 mod idle;
+mod quirks;
 pub mod scan_folders;
 pub mod select_folder;
 pub(crate) mod session;
 
 use client::Client;
 use mailparse::SingleInfo;
+use quirks::Quirks;
 use session::Session;
 
 use self::select_folder::NewlySelected;
 
 pub(crate) const GENERATED_PREFIX: &str = "GEN_";
 
+/// Time in seconds after which moves recorded in the `imap_moves` table are forgotten,
+/// see [`was_moved_to`].
+pub(crate) const MOVES_RETENTION: i64 = 30 * 24 * 60 * 60;
+
+/// Cause of a failed IMAP operation on a message.
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
-pub enum ImapActionResult {
-    Failed,
-    RetryLater,
-    Success,
+pub enum ImapErrorKind {
+    /// Login failed, credentials must be fixed by the user.
+    Auth,
+
+    /// Connection to the server failed or was lost.
+    Network,
+
+    /// The message or the folder does not exist on the server.
+    NotFound,
+
+    /// The UID of the message is not known yet,
+    /// e.g. because the message is being moved to another folder.
+    Pending,
+
+    /// The server does not allow the operation.
+    Permission,
+
+    /// Any other error.
+    Other,
+}
+
+impl ImapErrorKind {
+    /// Returns true if the operation may succeed when retried later without user action.
+    pub fn is_temporary(self) -> bool {
+        matches!(self, ImapErrorKind::Network | ImapErrorKind::Pending)
+    }
+
+    /// Determines the cause of an error.
+    ///
+    /// Server responses are classified by their response codes, see
+    /// <https://www.rfc-editor.org/rfc/rfc5530>.
+    fn classify(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(err) = cause.downcast_ref::<async_imap::error::Error>() {
+                match err {
+                    async_imap::error::Error::Io(_) | async_imap::error::Error::ConnectionLost => {
+                        return ImapErrorKind::Network
+                    }
+                    async_imap::error::Error::No(response) => {
+                        return Self::classify_response(response)
+                    }
+                    _ => {}
+                }
+            }
+            if cause.is::<std::io::Error>() {
+                return ImapErrorKind::Network;
+            }
+        }
+        Self::classify_response(&format!("{err:#}"))
+    }
+
+    fn classify_response(response: &str) -> Self {
+        let response = response.to_uppercase();
+        if response.contains("[AUTHENTICATIONFAILED]") {
+            ImapErrorKind::Auth
+        } else if response.contains("[NONEXISTENT]") {
+            ImapErrorKind::NotFound
+        } else if response.contains("[NOPERM]") {
+            ImapErrorKind::Permission
+        } else if response.contains("[UNAVAILABLE]") {
+            ImapErrorKind::Network
+        } else {
+            ImapErrorKind::Other
+        }
+    }
+}
+
+/// Error of an IMAP operation on a message.
+#[derive(Debug, thiserror::Error)]
+#[error("{kind} error: {error:#}")]
+pub struct ImapActionError {
+    /// Cause of the error.
+    pub kind: ImapErrorKind,
+
+    error: anyhow::Error,
+}
+
+impl ImapActionError {
+    pub(crate) fn new(kind: ImapErrorKind, error: anyhow::Error) -> Self {
+        Self { kind, error }
+    }
+}
+
+impl From<anyhow::Error> for ImapActionError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(ImapErrorKind::classify(&error), error)
+    }
 }
 
 /// Prefetch:
@@ -84,6 +180,19 @@ pub enum ImapActionResult {
 const BODY_FULL: &str = "(FLAGS BODY.PEEK[])";
 const BODY_PARTIAL: &str = "(FLAGS RFC822.SIZE BODY.PEEK[HEADER])";
 
+/// Minimum size of messages which are fetched in chunks,
+/// see [`Imap::fetch_streamed_msgs`].
+const STREAMED_MSG_MIN_SIZE: u32 = 4 * 1024 * 1024;
+
+/// Size of the chunks large messages are fetched in.
+const STREAMED_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Number of messages prefetched with one command if there are many new messages.
+///
+/// If there are more new messages, they are downloaded over a second connection
+/// while the next chunk is prefetched.
+const PREFETCH_CHUNK_SIZE: usize = 500;
+
 #[derive(Debug)]
 pub struct Imap {
     pub(crate) idle_interrupt_receiver: Receiver<InterruptInfo>,
@@ -136,18 +245,18 @@ impl FolderMeaning {
     pub fn to_config(self) -> Option<Config> {
         match self {
             FolderMeaning::Unknown => None,
-            FolderMeaning::Spam => None,
+            FolderMeaning::Spam => Some(Config::ConfiguredSpamFolder),
             FolderMeaning::Inbox => Some(Config::ConfiguredInboxFolder),
             FolderMeaning::Mvbox => Some(Config::ConfiguredMvboxFolder),
             FolderMeaning::Sent => Some(Config::ConfiguredSentboxFolder),
             FolderMeaning::Trash => Some(Config::ConfiguredTrashFolder),
-            FolderMeaning::Drafts => None,
+            FolderMeaning::Drafts => Some(Config::ConfiguredDraftsFolder),
             FolderMeaning::Virtual => None,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ImapConfig {
     /// Email address.
     pub addr: String,
@@ -378,7 +487,22 @@ pub async fn connect(&mut self, context: &Context) -> Result<()> {
         };
 
         match login_res {
-            Ok(session) => {
+            Ok(mut session) => {
+                let provider_id = context
+                    .get_configured_provider()
+                    .await
+                    .log_err(context)
+                    .ok()
+                    .flatten()
+                    .map(|provider| provider.id);
+                session.quirks = Quirks::new(provider_id, &session.capabilities);
+                if session.quirks != Quirks::default() {
+                    info!(context, "Using server workarounds: {:?}.", session.quirks);
+                }
+                if let Some(delay) = session.quirks.login_delay {
+                    tokio::time::sleep(delay).await;
+                }
+
                 // Store server ID in the context to display in account info.
                 let mut lock = context.server_id.write().await;
                 *lock = session.capabilities.server_id.clone();
@@ -390,6 +514,7 @@ pub async fn connect(&mut self, context: &Context) -> Result<()> {
                     self.config.lp.user
                 )));
                 self.connectivity.set_connected(context).await;
+                context.metrics.record_imap_connect();
                 info!(context, "Successfully logged into IMAP server");
                 Ok(())
             }
@@ -449,6 +574,20 @@ pub fn trigger_reconnect(&mut self, context: &Context) {
         self.session = None;
     }
 
+    /// Logs out and closes the connection, if there is one.
+    ///
+    /// Used on shutdown to end the session cleanly instead of dropping the connection.
+    pub(crate) async fn logout(&mut self, context: &Context) {
+        if let Some(mut session) = self.session.take() {
+            let timeout_duration = std::time::Duration::from_secs(10);
+            match tokio::time::timeout(timeout_duration, session.logout()).await {
+                Ok(Ok(())) => info!(context, "IMAP logout successful."),
+                Ok(Err(err)) => warn!(context, "IMAP logout failed: {err:#}."),
+                Err(_) => warn!(context, "IMAP logout timed out."),
+            }
+        }
+    }
+
     /// FETCH-MOVE-DELETE iteration.
     ///
     /// Prefetches headers and downloads new message from the folder, moves messages away from the
@@ -465,10 +604,12 @@ pub async fn fetch_move_delete(
         }
         self.prepare(context).await?;
 
+        let fetch_start = Instant::now();
         let msgs_fetched = self
             .fetch_new_messages(context, watch_folder, folder_meaning, false)
             .await
             .context("fetch_new_messages")?;
+        context.metrics.record_fetch(fetch_start.elapsed());
         if msgs_fetched && context.get_config_delete_device_after().await?.is_some() {
             // New messages were fetched and shall be deleted later, restart ephemeral loop.
             // Note that the `Config::DeleteDeviceAfter` timer starts as soon as the messages are
@@ -544,28 +685,7 @@ pub(crate) async fn resync_folder_uids(
         );
 
         let uid_validity = get_uidvalidity(context, folder).await?;
-
-        // Write collected UIDs to SQLite database.
-        context
-            .sql
-            .transaction(move |transaction| {
-                transaction.execute("DELETE FROM imap WHERE folder=?", (folder,))?;
-                for (uid, (rfc724_mid, target)) in &msgs {
-                    // This may detect previously undetected moved
-                    // messages, so we update server_folder too.
-                    transaction.execute(
-                        "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
-                         VALUES           (?1,         ?2,     ?3,  ?4,          ?5)
-                         ON CONFLICT(folder, uid, uidvalidity)
-                         DO UPDATE SET rfc724_mid=excluded.rfc724_mid,
-                                       target=excluded.target",
-                        (rfc724_mid, folder, uid, uid_validity, target),
-                    )?;
-                }
-                Ok(())
-            })
-            .await?;
-        Ok(())
+        replace_folder_uids(context, folder, uid_validity, msgs).await
     }
 
     /// Select a folder and take care of uidvalidity changes.
@@ -600,6 +720,13 @@ pub(crate) async fn select_with_uidvalidity(
             .with_context(|| format!("failed to get old UID NEXT for folder {folder}"))?;
 
         if new_uid_validity == old_uid_validity {
+            if newly_selected == NewlySelected::Yes
+                && has_stale_uids(context, folder, new_uid_validity).await?
+            {
+                // Resync requested after UIDVALIDITY change was not done yet,
+                // e.g. because the app was restarted.
+                context.schedule_resync().await?;
+            }
             let new_emails = if newly_selected == NewlySelected::No {
                 // The folder was not newly selected i.e. no SELECT command was run. This means that mailbox.uid_next
                 // was not updated and may contain an incorrect value. So, just return true so that
@@ -668,10 +795,12 @@ pub(crate) async fn select_with_uidvalidity(
         set_uidvalidity(context, folder, new_uid_validity).await?;
 
         // Collect garbage entries in `imap` table.
+        // Entries with pending MOVE or DELETE operations are kept
+        // until the resync re-associates them with the new UIDs by Message-ID.
         context
             .sql
             .execute(
-                "DELETE FROM imap WHERE folder=? AND uidvalidity!=?",
+                "DELETE FROM imap WHERE folder=? AND uidvalidity!=? AND target=folder",
                 (&folder, new_uid_validity),
             )
             .await?;
@@ -719,152 +848,305 @@ pub(crate) async fn fetch_new_messages(
         let uid_validity = get_uidvalidity(context, folder).await?;
         let old_uid_next = get_uid_next(context, folder).await?;
 
-        let msgs = if fetch_existing_msgs {
-            self.prefetch_existing_msgs()
+        // Prefetched messages are passed to the checks over a channel,
+        // so that they can be checked while the next chunk is prefetched.
+        let (prefetch_sender, prefetch_receiver) = async_channel::unbounded();
+        let mut new_uids = Vec::new();
+        let read_cnt = if fetch_existing_msgs {
+            let history_days = context.get_config_int(Config::ImportHistoryDays).await?;
+            if history_days > 0 {
+                let since = time().saturating_sub(i64::from(history_days) * 24 * 60 * 60);
+                new_uids = self
+                    .session
+                    .as_mut()
+                    .context("no IMAP session")?
+                    .search_history_uids(since)
+                    .await
+                    .context("search_history_uids")?;
+                new_uids.len()
+            } else {
+                let msgs = self
+                    .prefetch_existing_msgs()
+                    .await
+                    .context("prefetch_existing_msgs")?;
+                let read_cnt = msgs.len();
+                for msg in msgs {
+                    prefetch_sender.send(msg).await?;
+                }
+                read_cnt
+            }
+        } else if self.has_many_new_messages(old_uid_next) {
+            new_uids = self
+                .session
+                .as_mut()
+                .context("no IMAP session")?
+                .search_new_uids(old_uid_next)
                 .await
-                .context("prefetch_existing_msgs")?
+                .context("search_new_uids")?;
+            new_uids.len()
         } else {
-            self.prefetch(old_uid_next).await.context("prefetch")?
+            let msgs = self.prefetch(old_uid_next).await.context("prefetch")?;
+            let read_cnt = msgs.len();
+            for msg in msgs {
+                prefetch_sender.send(msg).await?;
+            }
+            read_cnt
         };
-        let read_cnt = msgs.len();
+
+        // After a long offline period, message bodies are downloaded over a second connection,
+        // so that the next chunks are prefetched and checked while the previous ones are downloaded.
+        let mut downloader = None;
+        if new_uids.len() > PREFETCH_CHUNK_SIZE {
+            downloader = self
+                .connect_for_download(context, folder, uid_validity)
+                .await;
+        }
+        if downloader.is_none() && !new_uids.is_empty() {
+            self.session
+                .as_mut()
+                .context("no IMAP session")?
+                .prefetch_chunks(&new_uids, &prefetch_sender)
+                .await
+                .context("prefetch_chunks")?;
+            new_uids.clear();
+        }
 
         let download_limit = context.download_limit().await?;
-        let mut uids_fetch = Vec::<(_, bool /* partially? */)>::with_capacity(msgs.len() + 1);
-        let mut uid_message_ids = BTreeMap::new();
-        let mut largest_uid_skipped = None;
+        let spam_filter = context.get_config_bool(Config::SpamFilter).await?;
+        let batch_size = self
+            .session
+            .as_ref()
+            .context("no session")?
+            .quirks
+            .fetch_batch_size(context.get_config_int(Config::FetchBatchSize).await?);
+        let mut batcher = FetchBatcher::new(
+            batch_size,
+            context.get_config_int(Config::FetchBatchBytes).await?,
+        );
+        let emit_progress = fetch_existing_msgs
+            || usize::try_from(batch_size)
+                .map_or(false, |batch_size| batch_size > 0 && read_cnt > batch_size);
+        let progress = emit_progress.then(|| FetchProgress::new(read_cnt));
+        let connectivity = self.connectivity.clone();
+        let (prefetch_session, download_imap) = match &mut downloader {
+            Some(downloader) => (
+                Some(self.session.as_mut().context("no IMAP session")?),
+                downloader,
+            ),
+            None => (None, self),
+        };
 
-        // Store the info about IMAP messages in the database.
-        for (uid, ref fetch_response) in msgs {
-            let headers = match get_fetch_headers(fetch_response) {
-                Ok(headers) => headers,
-                Err(err) => {
-                    warn!(context, "Failed to parse FETCH headers: {err:#}.");
-                    continue;
-                }
-            };
+        // Prefetch the remaining chunks while previous messages are downloaded.
+        let prefetch = async {
+            if let Some(session) = prefetch_session {
+                session
+                    .prefetch_chunks(&new_uids, &prefetch_sender)
+                    .await
+                    .context("prefetch_chunks")?;
+            }
+            prefetch_sender.close();
+            Ok::<_, anyhow::Error>(())
+        };
 
-            let message_id = prefetch_get_message_id(&headers);
+        // Messages are checked and downloaded concurrently,
+        // so that downloading the first messages does not wait
+        // until all prefetched messages are checked.
+        let (batch_sender, batch_receiver) = async_channel::unbounded();
+        let precheck = async {
+            let mut largest_uid_skipped = None;
+
+            // Store the info about IMAP messages in the database.
+            while let Ok((uid, fetch_response)) = prefetch_receiver.recv().await {
+                let fetch_response = &fetch_response;
+                let headers = match get_fetch_headers(fetch_response) {
+                    Ok(headers) => headers,
+                    Err(err) => {
+                        warn!(context, "Failed to parse FETCH headers: {err:#}.");
+                        continue;
+                    }
+                };
 
-            // Determine the target folder where the message should be moved to.
-            //
-            // If we have seen the message on the IMAP server before, do not move it.
-            // This is required to avoid infinite MOVE loop on IMAP servers
-            // that alias `DeltaChat` folder to other names.
-            // For example, some Dovecot servers alias `DeltaChat` folder to `INBOX.DeltaChat`.
-            // In this case Delta Chat configured with `DeltaChat` as the destination folder
-            // would detect messages in the `INBOX.DeltaChat` folder
-            // and try to move them to the `DeltaChat` folder.
-            // Such move to the same folder results in the messages
-            // getting a new UID, so the messages will be detected as new
-            // in the `INBOX.DeltaChat` folder again.
-            let target = if let Some(message_id) = &message_id {
-                if context
-                    .sql
-                    .exists(
-                        "SELECT COUNT (*) FROM imap WHERE rfc724_mid=?",
-                        (message_id,),
-                    )
-                    .await?
-                {
-                    info!(
+                let message_id = prefetch_get_message_id(&headers);
+
+                // Determine the target folder where the message should be moved to.
+                //
+                // If we have seen the message on the IMAP server before, do not move it.
+                // This is required to avoid infinite MOVE loop on IMAP servers
+                // that alias `DeltaChat` folder to other names.
+                // For example, some Dovecot servers alias `DeltaChat` folder to `INBOX.DeltaChat`.
+                // In this case Delta Chat configured with `DeltaChat` as the destination folder
+                // would detect messages in the `INBOX.DeltaChat` folder
+                // and try to move them to the `DeltaChat` folder.
+                // Such move to the same folder results in the messages
+                // getting a new UID, so the messages will be detected as new
+                // in the `INBOX.DeltaChat` folder again.
+                let target = if let Some(message_id) = &message_id {
+                    if context
+                        .sql
+                        .exists(
+                            "SELECT COUNT (*) FROM imap WHERE rfc724_mid=?",
+                            (message_id,),
+                        )
+                        .await?
+                    {
+                        info!(
+                            context,
+                            "Not moving the message {} that we have seen before.", &message_id
+                        );
+                        folder.to_string()
+                    } else {
+                        let target =
+                            target_folder(context, folder, folder_meaning, &headers).await?;
+                        if target != folder && was_moved_to(context, message_id, &target).await? {
+                            // Another client moved the message back after we moved it.
+                            // Moving it again would start a MOVE loop with that client.
+                            info!(
+                                context,
+                                "Not moving the message {} to {:?} again.", &message_id, &target
+                            );
+                            folder.to_string()
+                        } else {
+                            target
+                        }
+                    }
+                } else {
+                    // Do not move the messages without Message-ID.
+                    // We cannot reliably determine if we have seen them before,
+                    // so it is safer not to move them.
+                    warn!(
                         context,
-                        "Not moving the message {} that we have seen before.", &message_id
+                        "Not moving the message that does not have a Message-ID."
                     );
                     folder.to_string()
-                } else {
-                    target_folder(context, folder, folder_meaning, &headers).await?
-                }
-            } else {
-                // Do not move the messages without Message-ID.
-                // We cannot reliably determine if we have seen them before,
-                // so it is safer not to move them.
-                warn!(
-                    context,
-                    "Not moving the message that does not have a Message-ID."
-                );
-                folder.to_string()
-            };
+                };
 
-            // Generate a fake Message-ID to identify the message in the database
-            // if the message has no real Message-ID.
-            let message_id = message_id.unwrap_or_else(create_message_id);
+                // Generate a fake Message-ID to identify the message in the database
+                // if the message has no real Message-ID.
+                let message_id = message_id.unwrap_or_else(create_message_id);
 
-            context
-                .sql
-                .execute(
-                    "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
-                       VALUES         (?1,         ?2,     ?3,  ?4,          ?5)
-                       ON CONFLICT(folder, uid, uidvalidity)
-                       DO UPDATE SET rfc724_mid=excluded.rfc724_mid,
-                                     target=excluded.target",
-                    (&message_id, &folder, uid, uid_validity, &target),
-                )
-                .await?;
+                context
+                    .sql
+                    .execute(
+                        "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target, internaldate)
+                           VALUES         (?1,         ?2,     ?3,  ?4,          ?5,     ?6)
+                           ON CONFLICT(folder, uid, uidvalidity)
+                           DO UPDATE SET rfc724_mid=excluded.rfc724_mid,
+                                         target=excluded.target,
+                                         internaldate=excluded.internaldate",
+                        (
+                            &message_id,
+                            &folder,
+                            uid,
+                            uid_validity,
+                            &target,
+                            fetch_response
+                                .internal_date()
+                                .map_or(0, |date| date.timestamp()),
+                        ),
+                    )
+                    .await?;
 
-            // Download only the messages which have reached their target folder if there are
-            // multiple devices. This prevents race conditions in multidevice case, where one
-            // device tries to download the message while another device moves the message at the
-            // same time. Even in single device case it is possible to fail downloading the first
-            // message, move it to the movebox and then download the second message before
-            // downloading the first one, if downloading from inbox before moving is allowed.
-            if folder == target
-                // Never download messages directly from the spam folder.
-                // If the sender is known, the message will be moved to the Inbox or Mvbox
-                // and then we download the message from there.
-                // Also see `spam_target_folder_cfg()`.
-                && folder_meaning != FolderMeaning::Spam
-                && prefetch_should_download(
-                    context,
-                    &headers,
-                    &message_id,
-                    fetch_response.flags(),
-                )
-                .await.context("prefetch_should_download")?
-            {
-                match download_limit {
-                    Some(download_limit) => uids_fetch.push((
-                        uid,
-                        fetch_response.size.unwrap_or_default() > download_limit,
-                    )),
-                    None => uids_fetch.push((uid, false)),
+                // Download only the messages which have reached their target folder if there are
+                // multiple devices. This prevents race conditions in multidevice case, where one
+                // device tries to download the message while another device moves the message at the
+                // same time. Even in single device case it is possible to fail downloading the first
+                // message, move it to the movebox and then download the second message before
+                // downloading the first one, if downloading from inbox before moving is allowed.
+                if folder == target
+                    // Never download messages directly from the spam folder
+                    // unless they are received as spam contact requests.
+                    // If the sender is known, the message will be moved to the Inbox or Mvbox
+                    // and then we download the message from there.
+                    // Also see `spam_target_folder_cfg()`.
+                    && (folder_meaning != FolderMeaning::Spam || spam_filter)
+                    && !is_downloaded_from_other_folder(context, folder, &message_id).await?
+                    && prefetch_should_download(
+                        context,
+                        &headers,
+                        &message_id,
+                        fetch_response.flags(),
+                    )
+                    .await.context("prefetch_should_download")?
+                {
+                    let size = fetch_response.size.unwrap_or_default();
+                    let fetch_partially = download_limit.map_or(false, |limit| size > limit);
+                    if let Some(batch) = batcher.push(uid, message_id, fetch_partially, size) {
+                        batch_sender.send(batch).await?;
+                    }
+                } else {
+                    largest_uid_skipped = Some(uid);
+                    if let Some(progress) = &progress {
+                        progress.message_done(context);
+                    }
                 }
-                uid_message_ids.insert(uid, message_id);
-            } else {
-                largest_uid_skipped = Some(uid);
             }
-        }
 
-        if !uids_fetch.is_empty() {
-            self.connectivity.set_working(context).await;
-        }
+            if let Some(batch) = batcher.finish() {
+                batch_sender.send(batch).await?;
+            }
+            batch_sender.close();
+            Ok::<_, anyhow::Error>(largest_uid_skipped)
+        };
 
         // Actually download messages.
-        let mut largest_uid_fetched: u32 = 0;
-        let mut received_msgs = Vec::with_capacity(uids_fetch.len());
-        let mut uids_fetch_in_batch = Vec::with_capacity(max(uids_fetch.len(), 1));
-        let mut fetch_partially = false;
-        uids_fetch.push((0, !uids_fetch.last().unwrap_or(&(0, false)).1));
-        for (uid, fp) in uids_fetch {
-            if fp != fetch_partially {
-                let (largest_uid_fetched_in_batch, received_msgs_in_batch) = self
-                    .fetch_many_msgs(
-                        context,
-                        folder,
-                        uids_fetch_in_batch.split_off(0),
-                        &uid_message_ids,
-                        fetch_partially,
-                        fetch_existing_msgs,
-                    )
-                    .await
-                    .context("fetch_many_msgs")?;
+        let download = async {
+            let mut largest_uid_fetched: u32 = 0;
+            let mut received_msgs = Vec::new();
+            let mut fetched = 0;
+            while let Ok(batch) = batch_receiver.recv().await {
+                if fetch_existing_msgs && context.history_import_cancel.load(Ordering::Relaxed) {
+                    info!(context, "Fetching existing messages cancelled.");
+                    break;
+                }
+                if fetched == 0 {
+                    connectivity.set_working(context).await;
+                }
+                let uids: Vec<u32> = batch.uid_message_ids.keys().copied().collect();
+                fetched += uids.len();
+                let (largest_uid_fetched_in_batch, received_msgs_in_batch) = if batch.streamed {
+                    download_imap
+                        .fetch_streamed_msgs(
+                            context,
+                            folder,
+                            &batch.uid_message_ids,
+                            fetch_existing_msgs,
+                            progress.as_ref(),
+                        )
+                        .await
+                        .context("fetch_streamed_msgs")?
+                } else {
+                    download_imap
+                        .fetch_many_msgs(
+                            context,
+                            folder,
+                            uids,
+                            &batch.uid_message_ids,
+                            batch.fetch_partially,
+                            fetch_existing_msgs,
+                            progress.as_ref(),
+                        )
+                        .await
+                        .context("fetch_many_msgs")?
+                };
                 received_msgs.extend(received_msgs_in_batch);
                 largest_uid_fetched = max(
                     largest_uid_fetched,
                     largest_uid_fetched_in_batch.unwrap_or(0),
                 );
-                fetch_partially = fp;
             }
-            uids_fetch_in_batch.push(uid);
+            Ok::<_, anyhow::Error>((largest_uid_fetched, received_msgs))
+        };
+
+        let res = tokio::try_join!(prefetch, precheck, download);
+        if let Some(mut downloader) = downloader {
+            downloader.logout(context).await;
+        }
+        let ((), largest_uid_skipped, (largest_uid_fetched, received_msgs)) = res?;
+        if progress.is_some() {
+            context.emit_event(EventType::FetchProgress {
+                fetched: read_cnt,
+                total: read_cnt,
+            });
         }
 
         // determine which uid_next to use to update to
@@ -898,7 +1180,8 @@ pub(crate) async fn fetch_new_messages(
     /// Read the recipients from old emails sent by the user and add them as contacts.
     /// This way, we can already offer them some email addresses they can write to.
     ///
-    /// Then, Fetch the last messages DC_FETCH_EXISTING_MSGS_COUNT emails from the server
+    /// Then, Fetch the last messages DC_FETCH_EXISTING_MSGS_COUNT emails
+    /// or the messages of the last `Config::ImportHistoryDays` days from the server
     /// and show them in the chat list.
     pub(crate) async fn fetch_existing_msgs(&mut self, context: &Context) -> Result<()> {
         if context.get_config_bool(Config::Bot).await? {
@@ -916,25 +1199,22 @@ pub(crate) async fn fetch_existing_msgs(&mut self, context: &Context) -> Result<
             .await
             .context("failed to get recipients from the inbox")?;
 
-        if context.get_config_bool(Config::FetchExistingMsgs).await? {
-            for meaning in [
-                FolderMeaning::Mvbox,
-                FolderMeaning::Inbox,
-                FolderMeaning::Sent,
-            ] {
-                let config = match meaning.to_config() {
-                    Some(c) => c,
-                    None => continue,
+        let import_history = context.get_config_int(Config::ImportHistoryDays).await? > 0;
+        if import_history || context.get_config_bool(Config::FetchExistingMsgs).await? {
+            context
+                .history_import_cancel
+                .store(false, Ordering::Relaxed);
+            let res = self.fetch_existing_folders(context, import_history).await;
+            if import_history {
+                let progress = match res {
+                    Ok(true) => 1000,
+                    Ok(false) | Err(_) => 0,
                 };
-                if let Some(folder) = context.get_config(config).await? {
-                    info!(
-                        context,
-                        "Fetching existing messages from folder {folder:?}."
-                    );
-                    self.fetch_new_messages(context, &folder, meaning, true)
-                        .await
-                        .context("could not fetch existing messages")?;
-                }
+                context.emit_event(EventType::HistoryImportProgress(progress));
+            }
+            if !res? {
+                info!(context, "Fetching existing messages cancelled.");
+                return Ok(());
             }
         }
 
@@ -942,6 +1222,44 @@ pub(crate) async fn fetch_existing_msgs(&mut self, context: &Context) -> Result<
         Ok(())
     }
 
+    /// Fetches existing messages from the movebox, the inbox and the sentbox.
+    ///
+    /// Returns false if cancelled by [`Context::cancel_history_import`].
+    async fn fetch_existing_folders(
+        &mut self,
+        context: &Context,
+        emit_progress: bool,
+    ) -> Result<bool> {
+        let meanings = [
+            FolderMeaning::Mvbox,
+            FolderMeaning::Inbox,
+            FolderMeaning::Sent,
+        ];
+        for (i, meaning) in meanings.iter().enumerate() {
+            if context.history_import_cancel.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            if emit_progress {
+                context.emit_event(EventType::HistoryImportProgress(
+                    1 + 998 * i / meanings.len(),
+                ));
+            }
+            let Some(config) = meaning.to_config() else {
+                continue;
+            };
+            if let Some(folder) = context.get_config(config).await? {
+                info!(
+                    context,
+                    "Fetching existing messages from folder {folder:?}."
+                );
+                self.fetch_new_messages(context, &folder, *meaning, true)
+                    .await
+                    .context("could not fetch existing messages")?;
+            }
+        }
+        Ok(!context.history_import_cancel.load(Ordering::Relaxed))
+    }
+
     /// Synchronizes UIDs for all folders.
     pub(crate) async fn resync_folders(&mut self, context: &Context) -> Result<()> {
         self.prepare(context).await?;
@@ -973,6 +1291,7 @@ async fn delete_message_batch(
         // mark the message for deletion
         self.add_flag_finalized_with_set(uid_set, "\\Deleted")
             .await?;
+        audit::log_imap_rows(context, &row_ids, "Marked as deleted").await?;
         context
             .sql
             .execute(
@@ -1004,6 +1323,8 @@ async fn move_message_batch(
             match self.uid_mv(set, &target).await {
                 Ok(()) => {
                     // Messages are moved or don't exist, IMAP returns OK response in both cases.
+                    audit::log_imap_rows(context, &row_ids, &format!("Moved to {target}")).await?;
+                    record_moves(context, &row_ids, target).await?;
                     context
                         .sql
                         .execute(
@@ -1052,12 +1373,19 @@ async fn move_message_batch(
                 "Server does not support MOVE, fallback to COPY/DELETE {} to {}", set, target
             );
             self.uid_copy(&set, &target).await?;
+            record_moves(context, &row_ids, target).await?;
         } else {
             error!(
                 context,
                 "Server does not support MOVE, fallback to DELETE {} to {}", set, target,
             );
         }
+        let operation = if copy {
+            format!("Copied to {target}, deletion scheduled")
+        } else {
+            format!("Deletion scheduled instead of moving to {target}")
+        };
+        audit::log_imap_rows(context, &row_ids, &operation).await?;
         context
             .sql
             .execute(
@@ -1087,6 +1415,7 @@ async fn move_delete_messages(&mut self, context: &Context, folder: &str) -> Res
                 "SELECT id, uid, target FROM imap
         WHERE folder = ?
         AND target != folder
+        AND uidvalidity = (SELECT uidvalidity FROM imap_sync WHERE folder=imap.folder)
         ORDER BY target, uid",
                 (folder,),
                 |row| {
@@ -1139,6 +1468,7 @@ pub(crate) async fn store_seen_flags_on_imap(&mut self, context: &Context) -> Re
             .query_map(
                 "SELECT imap.id, uid, folder FROM imap, imap_markseen
                  WHERE imap.id = imap_markseen.id AND target = folder
+                 AND uidvalidity = (SELECT uidvalidity FROM imap_sync WHERE folder=imap.folder)
                  ORDER BY folder, uid",
                 [],
                 |row| {
@@ -1169,6 +1499,7 @@ pub(crate) async fn store_seen_flags_on_imap(&mut self, context: &Context) -> Re
                     context,
                     "Marked messages {} in folder {} as seen.", uid_set, folder
                 );
+                audit::log_imap_rows(context, &rowid_set, "Marked as seen").await?;
                 context
                     .sql
                     .execute(
@@ -1317,6 +1648,70 @@ pub async fn get_all_recipients(&mut self, context: &Context) -> Result<Vec<Sing
         Ok(result)
     }
 
+    /// Returns true if the selected folder has more new messages than fit into one prefetch chunk.
+    fn has_many_new_messages(&self, old_uid_next: u32) -> bool {
+        self.session
+            .as_ref()
+            .and_then(|session| session.selected_mailbox.as_ref())
+            .and_then(|mailbox| mailbox.uid_next)
+            .and_then(|uid_next| usize::try_from(uid_next.saturating_sub(old_uid_next)).ok())
+            .map_or(false, |new_cnt| new_cnt > PREFETCH_CHUNK_SIZE)
+    }
+
+    /// Opens a second connection with `folder` selected to download messages
+    /// while this connection prefetches the next ones.
+    ///
+    /// Returns `None` if the connection cannot be established,
+    /// e.g. because the server limits the number of connections,
+    /// or if the UIDVALIDITY of the folder has changed in the meantime.
+    async fn connect_for_download(
+        &self,
+        context: &Context,
+        folder: &str,
+        uid_validity: u32,
+    ) -> Option<Imap> {
+        let (_, idle_interrupt_receiver) = async_channel::bounded(1);
+        let mut imap = Imap {
+            idle_interrupt_receiver,
+            config: self.config.clone(),
+            session: None,
+            login_failed_once: false,
+            connectivity: Default::default(),
+        };
+        let res = async {
+            imap.connect(context).await?;
+            let session = imap.session.as_mut().context("no IMAP session")?;
+            session.select_folder(context, Some(folder)).await?;
+            let mailbox = session
+                .selected_mailbox
+                .as_ref()
+                .context("no mailbox selected")?;
+            ensure!(
+                mailbox.uid_validity == Some(uid_validity),
+                "UIDVALIDITY of {folder:?} changed"
+            );
+            Ok(())
+        }
+        .await;
+        match res {
+            Ok(()) => {
+                info!(
+                    context,
+                    "Downloading messages from {folder:?} over a second connection."
+                );
+                Some(imap)
+            }
+            Err(err) => {
+                warn!(
+                    context,
+                    "Cannot download messages over a second connection: {err:#}."
+                );
+                imap.logout(context).await;
+                None
+            }
+        }
+    }
+
     /// Prefetch all messages greater than or equal to `uid_next`. Returns a list of fetch results
     /// in the order of ascending delivery time to the server (INTERNALDATE).
     async fn prefetch(&mut self, uid_next: u32) -> Result<Vec<(u32, async_imap::types::Fetch)>> {
@@ -1384,6 +1779,8 @@ async fn prefetch_existing_msgs(&mut self) -> Result<Vec<(u32, async_imap::types
     /// Returns the last UID fetched successfully and the info about each downloaded message.
     /// If the message is incorrect or there is a failure to write a message to the database,
     /// it is skipped and the error is logged.
+    /// Each processed message is counted in `progress`.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn fetch_many_msgs(
         &mut self,
         context: &Context,
@@ -1392,6 +1789,7 @@ pub(crate) async fn fetch_many_msgs(
         uid_message_ids: &BTreeMap<u32, String>,
         fetch_partially: bool,
         fetching_existing_messages: bool,
+        progress: Option<&FetchProgress>,
     ) -> Result<(Option<u32>, Vec<ReceivedMsg>)> {
         let mut last_uid = None;
         let mut received_msgs = Vec::new();
@@ -1490,6 +1888,9 @@ pub(crate) async fn fetch_many_msgs(
                     (fetch_response.body(), None) // ... while `BODY.PEEK[]` goes to body() - and includes header()
                 };
 
+                if let Some(progress) = progress {
+                    progress.message_done(context);
+                }
                 if is_deleted {
                     info!(context, "Not processing deleted msg {}.", request_uid);
                     last_uid = Some(request_uid);
@@ -1535,6 +1936,15 @@ pub(crate) async fn fetch_many_msgs(
                 .await
                 {
                     Ok(received_msg) => {
+                        let operation = format!(
+                            "{} from {folder}, UID {request_uid}",
+                            if partial.is_some() {
+                                "Partially fetched"
+                            } else {
+                                "Fetched"
+                            }
+                        );
+                        audit::log(context, rfc724_mid, &operation).await?;
                         if let Some(m) = received_msg {
                             received_msgs.push(m);
                         }
@@ -1569,9 +1979,186 @@ pub(crate) async fn fetch_many_msgs(
 
         Ok((last_uid, received_msgs))
     }
+
+    /// Fetches large messages by server UID in chunks of [`STREAMED_CHUNK_SIZE`] bytes.
+    ///
+    /// The chunks are passed to a [`MessageStreamer`] as they arrive,
+    /// so neither the whole raw message nor its large attachments are kept in memory.
+    /// Returns the same as [`Imap::fetch_many_msgs`].
+    pub(crate) async fn fetch_streamed_msgs(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        uid_message_ids: &BTreeMap<u32, String>,
+        fetching_existing_messages: bool,
+        progress: Option<&FetchProgress>,
+    ) -> Result<(Option<u32>, Vec<ReceivedMsg>)> {
+        let mut last_uid = None;
+        let mut received_msgs = Vec::new();
+        let session = self.session.as_mut().context("no IMAP session")?;
+
+        for (&uid, rfc724_mid) in uid_message_ids {
+            info!(context, "Starting a streamed FETCH of message {uid}.");
+            let mut streamer = MessageStreamer::new(context);
+            let mut flags = None;
+            let mut size = 0;
+            loop {
+                let mut fetch_responses = session
+                    .uid_fetch(
+                        uid.to_string(),
+                        format!("(FLAGS BODY.PEEK[]<{size}.{STREAMED_CHUNK_SIZE}>)"),
+                    )
+                    .await
+                    .with_context(|| format!("fetching message {uid} from folder \"{folder}\""))?;
+                let mut chunk_flags = None;
+                let mut chunk_len = None;
+                while let Some(fetch_response) = fetch_responses.next().await {
+                    let fetch_response = match fetch_response {
+                        Ok(fetch_response) => fetch_response,
+                        Err(err) => {
+                            warn!(context, "Failed to process IMAP FETCH result: {}.", err);
+                            continue;
+                        }
+                    };
+                    if fetch_response.uid != Some(uid) {
+                        info!(context, "Skipping not requested FETCH response.");
+                        continue;
+                    }
+                    chunk_flags = Some((
+                        fetch_response.flags().any(|flag| flag == Flag::Seen),
+                        fetch_response.flags().any(|flag| flag == Flag::Deleted),
+                    ));
+                    if let Some(chunk) = fetch_response.body() {
+                        streamer.push(chunk).await;
+                        chunk_len = Some(chunk.len());
+                    }
+                }
+                drop(fetch_responses);
+
+                if chunk_flags.is_none() {
+                    // The message is missing or was expunged while it was fetched.
+                    flags = None;
+                    break;
+                }
+                flags = flags.or(chunk_flags);
+                // A server may return NIL instead of an empty chunk at the end of the message.
+                let chunk_len = chunk_len.unwrap_or_default();
+                size += chunk_len;
+                if chunk_len < STREAMED_CHUNK_SIZE
+                    || flags.map_or(false, |(_, is_deleted)| is_deleted)
+                {
+                    break;
+                }
+            }
+
+            let Some((is_seen, is_deleted)) = flags else {
+                warn!(context, "Missed UID {uid} in the server response.");
+                streamer.abort().await;
+                continue;
+            };
+            if let Some(progress) = progress {
+                progress.message_done(context);
+            }
+            if is_deleted || size == 0 {
+                if is_deleted {
+                    info!(context, "Not processing deleted msg {uid}.");
+                } else {
+                    info!(context, "Not processing message {uid} without a BODY.");
+                }
+                streamer.abort().await;
+                last_uid = Some(uid);
+                continue;
+            }
+            let streamed = streamer.finish().await;
+
+            info!(context, "Passing message UID {uid} to receive_imf().");
+            match receive_streamed_imf(
+                context,
+                rfc724_mid,
+                &streamed,
+                is_seen,
+                fetching_existing_messages,
+            )
+            .await
+            {
+                Ok(received_msg) => {
+                    let operation = format!("Fetched from {folder}, UID {uid}");
+                    audit::log(context, rfc724_mid, &operation).await?;
+                    if let Some(m) = received_msg {
+                        received_msgs.push(m);
+                    }
+                }
+                Err(err) => {
+                    warn!(context, "receive_imf error: {:#}.", err);
+                }
+            };
+            last_uid = Some(uid);
+        }
+
+        Ok((last_uid, received_msgs))
+    }
 }
 
 impl Session {
+    /// Returns the UIDs of the messages in the selected folder
+    /// which are greater than or equal to `uid_next`, in ascending order.
+    async fn search_new_uids(&mut self, uid_next: u32) -> Result<Vec<u32>> {
+        // As with FETCH, `uid_next:*` includes the last message even if its UID is smaller.
+        let mut uids: Vec<u32> = self
+            .uid_search(format!("UID {uid_next}:*"))
+            .await
+            .context("IMAP could not search")?
+            .into_iter()
+            .filter(|uid| *uid >= uid_next)
+            .collect();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// Returns the UIDs of the messages in the selected folder
+    /// received at `since` or later, in ascending order.
+    async fn search_history_uids(&mut self, since: i64) -> Result<Vec<u32>> {
+        // IMAP compares only dates, so a few more messages may be returned.
+        let since = chrono::NaiveDateTime::from_timestamp_opt(since, 0)
+            .context("invalid history start")?
+            .format("%d-%b-%Y");
+        let mut uids: Vec<u32> = self
+            .uid_search(format!("SINCE {since}"))
+            .await
+            .context("IMAP could not search")?
+            .into_iter()
+            .collect();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// Prefetches the messages with the given UIDs in chunks of [`PREFETCH_CHUNK_SIZE`]
+    /// and sends them to `sender`, each chunk in the order of ascending delivery time.
+    async fn prefetch_chunks(
+        &mut self,
+        uids: &[u32],
+        sender: &async_channel::Sender<(u32, Fetch)>,
+    ) -> Result<()> {
+        for chunk in uids.chunks(PREFETCH_CHUNK_SIZE) {
+            let mut msgs = BTreeMap::new();
+            for (_, set) in build_sequence_sets(chunk)? {
+                let mut list = self
+                    .uid_fetch(&set, PREFETCH_FLAGS)
+                    .await
+                    .context("IMAP could not fetch")?;
+                while let Some(msg) = list.try_next().await? {
+                    if let Some(msg_uid) = msg.uid {
+                        msgs.insert((msg.internal_date(), msg_uid), msg);
+                    }
+                }
+            }
+            for ((_, uid), msg) in msgs {
+                sender.send((uid, msg)).await?;
+            }
+        }
+        Ok(())
+    }
+
     /// Returns success if we successfully set the flag or we otherwise
     /// think add_flag should not be retried: Disconnection during setting
     /// the flag, or other imap-errors, returns true as well.
@@ -1594,45 +2181,45 @@ async fn add_flag_finalized_with_set(&mut self, uid_set: &str, flag: &str) -> Re
 }
 
 impl Imap {
+    /// Connects and selects the folder of a message.
     pub(crate) async fn prepare_imap_operation_on_msg(
         &mut self,
         context: &Context,
         folder: &str,
         uid: u32,
-    ) -> Option<ImapActionResult> {
+    ) -> std::result::Result<(), ImapActionError> {
         if uid == 0 {
-            return Some(ImapActionResult::RetryLater);
+            return Err(ImapActionError::new(
+                ImapErrorKind::Pending,
+                format_err!("UID of the message is unknown"),
+            ));
         }
         if let Err(err) = self.prepare(context).await {
-            warn!(context, "prepare_imap_op failed: {}", err);
-            return Some(ImapActionResult::RetryLater);
+            warn!(context, "prepare_imap_op failed: {:#}", err);
+            return Err(err.into());
         }
 
-        let session = match self
-            .session
-            .as_mut()
-            .context("no IMAP connection established")
-        {
-            Err(err) => {
-                error!(context, "Failed to prepare IMAP operation: {:#}", err);
-                return Some(ImapActionResult::Failed);
-            }
-            Ok(session) => session,
-        };
+        let session = self.session.as_mut().ok_or_else(|| {
+            ImapActionError::new(
+                ImapErrorKind::Network,
+                format_err!("no IMAP connection established"),
+            )
+        })?;
 
         match session.select_folder(context, Some(folder)).await {
-            Ok(_) => None,
-            Err(select_folder::Error::ConnectionLost) => {
-                warn!(context, "Lost imap connection");
-                Some(ImapActionResult::RetryLater)
-            }
-            Err(select_folder::Error::BadFolderName(folder_name)) => {
-                warn!(context, "invalid folder name: {:?}", folder_name);
-                Some(ImapActionResult::Failed)
-            }
+            Ok(_) => Ok(()),
             Err(err) => {
                 warn!(context, "failed to select folder {:?}: {:#}", folder, err);
-                Some(ImapActionResult::RetryLater)
+                let kind = match err {
+                    select_folder::Error::ConnectionLost => ImapErrorKind::Network,
+                    select_folder::Error::BadFolderName(_) | select_folder::Error::NoFolder(..) => {
+                        ImapErrorKind::NotFound
+                    }
+                    select_folder::Error::Other(ref response) => {
+                        ImapErrorKind::classify_response(response)
+                    }
+                };
+                Err(ImapActionError::new(kind, err.into()))
             }
         }
     }
@@ -1642,7 +2229,10 @@ pub async fn ensure_configured_folders(
         context: &Context,
         create_mvbox: bool,
     ) -> Result<()> {
-        let folders_configured = context.sql.get_raw_config_int("folders_configured").await?;
+        let folders_configured = context
+            .sql
+            .get_raw_config_int(InternalConfig::FoldersConfigured.as_ref())
+            .await?;
         if folders_configured.unwrap_or_default() >= DC_FOLDERS_CONFIGURED_VERSION {
             return Ok(());
         }
@@ -1676,12 +2266,22 @@ async fn configure_mvbox<'a>(
         for folder in folders {
             info!(context, "Looking for MVBOX-folder \"{}\"...", &folder);
             let res = session.examine(&folder).await;
-            if res.is_ok() {
+            if let Ok(mailbox) = res {
                 info!(
                     context,
                     "MVBOX-folder {:?} successfully selected, using it.", &folder
                 );
                 session.close().await?;
+                if mailbox.exists > 0
+                    && !context
+                        .sql
+                        .exists("SELECT COUNT(*) FROM imap WHERE folder=?", (folder,))
+                        .await?
+                {
+                    // Another device moved chat messages there before,
+                    // it should get copies of outgoing messages in BccSelf::MultiDevice mode.
+                    context.set_other_devices_known().await?;
+                }
                 return Ok(Some(folder));
             }
         }
@@ -1764,7 +2364,10 @@ pub async fn configure_folders(&mut self, context: &Context, create_mvbox: bool)
         }
         context
             .sql
-            .set_raw_config_int("folders_configured", DC_FOLDERS_CONFIGURED_VERSION)
+            .set_raw_config_int(
+                InternalConfig::FoldersConfigured.as_ref(),
+                DC_FOLDERS_CONFIGURED_VERSION,
+            )
             .await?;
 
         info!(context, "FINISHED configuring IMAP-folders.");
@@ -1899,6 +2502,118 @@ async fn spam_target_folder_cfg(
     }
 }
 
+/// Progress of downloading messages in [`Imap::fetch_new_messages`],
+/// emitted as [`EventType::FetchProgress`] for each message.
+#[derive(Debug)]
+pub(crate) struct FetchProgress {
+    /// Number of messages downloaded or skipped so far.
+    fetched: AtomicUsize,
+
+    /// Number of messages to download.
+    total: usize,
+}
+
+impl FetchProgress {
+    fn new(total: usize) -> Self {
+        Self {
+            fetched: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    /// Counts a downloaded or skipped message and emits the progress.
+    fn message_done(&self, context: &Context) {
+        let fetched = self.fetched.fetch_add(1, Ordering::Relaxed) + 1;
+        context.emit_event(EventType::FetchProgress {
+            fetched: cmp::min(fetched, self.total),
+            total: self.total,
+        });
+    }
+}
+
+/// Messages to download with one FETCH command.
+#[derive(Debug)]
+struct FetchBatch {
+    /// Whether only the headers of the messages are downloaded.
+    fetch_partially: bool,
+
+    /// Whether the messages are downloaded in chunks, see [`Imap::fetch_streamed_msgs`].
+    streamed: bool,
+
+    /// Message-IDs of the messages by UID.
+    uid_message_ids: BTreeMap<u32, String>,
+}
+
+/// Splits messages to download into [`FetchBatch`]es of messages
+/// which are all downloaded either fully or partially.
+///
+/// Batches contain at most `max_count` messages and at most `max_bytes` bytes,
+/// unless a single message is larger, so that the memory used for FETCH responses
+/// stays bounded even if there are many large messages to download.
+/// Limits less than 1 are ignored.
+#[derive(Debug)]
+struct FetchBatcher {
+    max_count: usize,
+    max_bytes: u64,
+    batch: Option<FetchBatch>,
+    batch_bytes: u64,
+}
+
+impl FetchBatcher {
+    fn new(max_count: i32, max_bytes: i32) -> Self {
+        Self {
+            max_count: usize::try_from(max_count)
+                .ok()
+                .filter(|max_count| *max_count > 0)
+                .unwrap_or(usize::MAX),
+            max_bytes: u64::try_from(max_bytes)
+                .ok()
+                .filter(|max_bytes| *max_bytes > 0)
+                .unwrap_or(u64::MAX),
+            batch: None,
+            batch_bytes: 0,
+        }
+    }
+
+    /// Adds a message to download.
+    ///
+    /// Returns the previous batch if the message does not fit into it anymore.
+    fn push(
+        &mut self,
+        uid: u32,
+        rfc724_mid: String,
+        fetch_partially: bool,
+        size: u32,
+    ) -> Option<FetchBatch> {
+        let streamed = !fetch_partially && size >= STREAMED_MSG_MIN_SIZE;
+        // Partially downloaded messages only have their headers fetched.
+        let size = if fetch_partially { 0 } else { u64::from(size) };
+        if let Some(batch) = &mut self.batch {
+            if batch.fetch_partially == fetch_partially
+                && !batch.streamed
+                && !streamed
+                && batch.uid_message_ids.len() < self.max_count
+                && self.batch_bytes + size <= self.max_bytes
+            {
+                batch.uid_message_ids.insert(uid, rfc724_mid);
+                self.batch_bytes += size;
+                return None;
+            }
+        }
+        self.batch_bytes = size;
+        self.batch.replace(FetchBatch {
+            fetch_partially,
+            streamed,
+            uid_message_ids: BTreeMap::from([(uid, rfc724_mid)]),
+        })
+    }
+
+    /// Returns the last batch.
+    fn finish(self) -> Option<FetchBatch> {
+        self.batch
+    }
+}
+
 /// Returns `ConfiguredInboxFolder`, `ConfiguredMvboxFolder` or `ConfiguredSentboxFolder` if
 /// the message needs to be moved from `folder`. Otherwise returns `None`.
 pub async fn target_folder_cfg(
@@ -1970,7 +2685,7 @@ async fn needs_move_to_mvbox(
 // only watching this folder is not working. at least, this is no show stopper.
 // CAVE: if possible, take care not to add a name here that is "sent" in one language
 // but sth. different in others - a hard job.
-fn get_folder_meaning_by_name(folder_name: &str) -> FolderMeaning {
+pub(crate) fn get_folder_meaning_by_name(folder_name: &str) -> FolderMeaning {
     // source: <https://stackoverflow.com/questions/2185391/localized-gmail-imap-folders>
     const SENT_NAMES: &[&str] = &[
         "sent",
@@ -2046,9 +2761,19 @@ fn get_folder_meaning_by_name(folder_name: &str) -> FolderMeaning {
         "草稿",
         "임시보관함",
     ];
+    // Gmail folders containing copies of messages from other folders,
+    // used if the server does not support SPECIAL-USE attributes.
+    const VIRTUAL_NAMES: &[&str] = &[
+        "[Gmail]/All Mail",
+        "[Gmail]/Alle Nachrichten",
+        "[Google Mail]/All Mail",
+        "[Google Mail]/Alle Nachrichten",
+    ];
     let lower = folder_name.to_lowercase();
 
-    if SENT_NAMES.iter().any(|s| s.to_lowercase() == lower) {
+    if VIRTUAL_NAMES.iter().any(|s| s.to_lowercase() == lower) {
+        FolderMeaning::Virtual
+    } else if SENT_NAMES.iter().any(|s| s.to_lowercase() == lower) {
         FolderMeaning::Sent
     } else if SPAM_NAMES.iter().any(|s| s.to_lowercase() == lower) {
         FolderMeaning::Spam
@@ -2109,6 +2834,109 @@ pub(crate) fn create_message_id() -> String {
     format!("{}{}", GENERATED_PREFIX, create_id())
 }
 
+/// Returns the priority of `folder` for downloading messages which are in multiple folders,
+/// e.g. in the Inbox and in the `DeltaChat` folder, or in the Inbox and the Sent folder
+/// because of Bcc-to-self.
+///
+/// Only watched folders have a priority,
+/// otherwise the message may never be downloaded.
+async fn folder_download_priority(context: &Context, folder: &str) -> Result<u8> {
+    let is_configured = |config| async move {
+        Ok::<_, anyhow::Error>(context.get_config(config).await?.as_deref() == Some(folder))
+    };
+    if is_configured(Config::ConfiguredMvboxFolder).await? {
+        Ok(if context.should_watch_mvbox().await? {
+            3
+        } else {
+            0
+        })
+    } else if is_configured(Config::ConfiguredInboxFolder).await? {
+        Ok(2)
+    } else if is_configured(Config::ConfiguredSentboxFolder).await? {
+        Ok(if context.get_config_bool(Config::SentboxWatch).await? {
+            1
+        } else {
+            0
+        })
+    } else {
+        Ok(0)
+    }
+}
+
+/// Records that the messages of the `imap` table rows were moved to `target`.
+///
+/// See [`was_moved_to`].
+async fn record_moves(context: &Context, row_ids: &[i64], target: &str) -> Result<()> {
+    let row_ids = row_ids.to_vec();
+    let target = target.to_string();
+    let now = time();
+    context
+        .sql
+        .transaction(move |transaction| {
+            let mut stmt = transaction.prepare(
+                "INSERT INTO imap_moves (rfc724_mid, target, timestamp)
+                 SELECT rfc724_mid, ?, ? FROM imap WHERE id=?
+                 ON CONFLICT(rfc724_mid, target) DO UPDATE SET timestamp=excluded.timestamp",
+            )?;
+            for row_id in row_ids {
+                stmt.execute((&target, now, row_id))?;
+            }
+            Ok(())
+        })
+        .await
+        .context("cannot record moved messages")
+}
+
+/// Returns true if the message was moved to `target` before.
+///
+/// A message which is found outside of `target` again was moved back by another client,
+/// e.g. a classic email client or a Delta Chat version with other settings.
+/// Such messages are not moved again to avoid endless MOVE loops.
+/// The moves are forgotten after [`MOVES_RETENTION`].
+async fn was_moved_to(context: &Context, message_id: &str, target: &str) -> Result<bool> {
+    context
+        .sql
+        .exists(
+            "SELECT COUNT(*) FROM imap_moves WHERE rfc724_mid=? AND target=?",
+            (message_id, target),
+        )
+        .await
+}
+
+/// Returns true if the message is also in a folder with higher download priority,
+/// so it should be downloaded from there and not from `folder`.
+///
+/// Copies which are going to be moved or deleted are not taken into account.
+async fn is_downloaded_from_other_folder(
+    context: &Context,
+    folder: &str,
+    message_id: &str,
+) -> Result<bool> {
+    let other_folders = context
+        .sql
+        .query_map(
+            "SELECT DISTINCT folder FROM imap WHERE rfc724_mid=? AND folder!=? AND folder=target",
+            (message_id, folder),
+            |row| row.get::<_, String>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    if other_folders.is_empty() {
+        return Ok(false);
+    }
+    let priority = folder_download_priority(context, folder).await?;
+    for other_folder in other_folders {
+        if folder_download_priority(context, &other_folder).await? > priority {
+            info!(
+                context,
+                "Not downloading {message_id} from {folder:?}, it is downloaded from {other_folder:?}."
+            );
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 /// Returns chat by prefetched headers.
 async fn prefetch_get_chat(
     context: &Context,
@@ -2199,6 +3027,7 @@ pub(crate) async fn prefetch_should_download(
                 is_chat_message || is_reply_to_chat_message || accepted_contact
             }
             ShowEmails::All => true,
+            ShowEmails::ChatMessagesOnly => is_chat_message,
         };
 
     let should_download = (show && !blocked_contact) || maybe_ndn;
@@ -2342,6 +3171,70 @@ async fn get_uidvalidity(context: &Context, folder: &str) -> Result<u32> {
         .unwrap_or(0))
 }
 
+/// Returns true if there are entries in the `imap` table of the folder
+/// which refer to UIDs from before the last UIDVALIDITY change.
+async fn has_stale_uids(context: &Context, folder: &str, uid_validity: u32) -> Result<bool> {
+    context
+        .sql
+        .exists(
+            "SELECT COUNT(*) FROM imap WHERE folder=? AND uidvalidity!=?",
+            (folder, uid_validity),
+        )
+        .await
+}
+
+/// Replaces entries of the folder in the `imap` table
+/// with UIDs and Message-IDs collected from the server.
+///
+/// Pending MOVE and DELETE operations of the replaced entries,
+/// including entries with UIDs from before a UIDVALIDITY change,
+/// are re-associated with the new UIDs by Message-ID.
+async fn replace_folder_uids(
+    context: &Context,
+    folder: &str,
+    uid_validity: u32,
+    msgs: BTreeMap<u32, (String, String)>,
+) -> Result<()> {
+    let reassociated = context
+        .sql
+        .transaction(move |transaction| {
+            let pending_targets = transaction
+                .prepare("SELECT rfc724_mid, target FROM imap WHERE folder=? AND target!=folder")?
+                .query_map((folder,), |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+            transaction.execute("DELETE FROM imap WHERE folder=?", (folder,))?;
+            let mut reassociated = 0;
+            for (uid, (rfc724_mid, target)) in &msgs {
+                let target = match pending_targets.get(rfc724_mid) {
+                    Some(pending_target) => {
+                        reassociated += 1;
+                        pending_target
+                    }
+                    None => target,
+                };
+                // This may detect previously undetected moved
+                // messages, so we update server_folder too.
+                transaction.execute(
+                    "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
+                     VALUES           (?1,         ?2,     ?3,  ?4,          ?5)
+                     ON CONFLICT(folder, uid, uidvalidity)
+                     DO UPDATE SET rfc724_mid=excluded.rfc724_mid,
+                                   target=excluded.target",
+                    (rfc724_mid, folder, uid, uid_validity, target),
+                )?;
+            }
+            Ok(reassociated)
+        })
+        .await?;
+    if reassociated > 0 {
+        info!(
+            context,
+            "Resync: re-associated {reassociated} pending MOVE/DELETE operations by Message-ID."
+        );
+    }
+    Ok(())
+}
+
 pub(crate) async fn set_modseq(context: &Context, folder: &str, modseq: u64) -> Result<()> {
     context
         .sql
@@ -2367,7 +3260,12 @@ pub(crate) async fn get_imap_self_sent_search_command(context: &Context) -> Resu
     // See https://www.rfc-editor.org/rfc/rfc3501#section-6.4.4 for syntax of SEARCH and OR
     let mut search_command = format!("FROM \"{}\"", context.get_primary_self_addr().await?);
 
-    for item in context.get_secondary_self_addrs().await? {
+    for item in context
+        .get_secondary_self_addrs()
+        .await?
+        .into_iter()
+        .chain(context.get_alias_addrs().await?)
+    {
         search_command = format!("OR ({search_command}) (FROM \"{item}\")");
     }
 
@@ -2401,6 +3299,14 @@ async fn should_ignore_folder(
     if !context.get_config_bool(Config::OnlyFetchMvbox).await? {
         return Ok(false);
     }
+    if context
+        .get_extra_folders()
+        .await?
+        .contains(&folder.to_string())
+    {
+        // Additional folders are fetched as requested by the user.
+        return Ok(false);
+    }
     if context.is_sentbox(folder).await? {
         // Still respect the SentboxWatch setting.
         return Ok(!context.get_config_bool(Config::SentboxWatch).await?);
@@ -2535,6 +3441,43 @@ mod tests {
     use crate::contact::Contact;
     use crate::test_utils::TestContext;
 
+    #[test]
+    fn test_classify_imap_error() {
+        let classify = |err: anyhow::Error| ImapActionError::from(err).kind;
+        assert_eq!(
+            classify(async_imap::error::Error::ConnectionLost.into()),
+            ImapErrorKind::Network
+        );
+        assert_eq!(
+            classify(
+                async_imap::error::Error::No("[NONEXISTENT] No such message".to_string()).into()
+            ),
+            ImapErrorKind::NotFound
+        );
+        assert_eq!(
+            classify(
+                anyhow::Error::from(async_imap::error::Error::No(
+                    "[NOPERM] Access denied".to_string()
+                ))
+                .context("fetch failed")
+            ),
+            ImapErrorKind::Permission
+        );
+        assert_eq!(
+            classify(format_err!("NO [AUTHENTICATIONFAILED] Invalid credentials")),
+            ImapErrorKind::Auth
+        );
+        assert_eq!(
+            classify(format_err!("Unexpected response")),
+            ImapErrorKind::Other
+        );
+
+        assert!(ImapErrorKind::Network.is_temporary());
+        assert!(ImapErrorKind::Pending.is_temporary());
+        assert!(!ImapErrorKind::NotFound.is_temporary());
+        assert!(!ImapErrorKind::Auth.is_temporary());
+    }
+
     #[test]
     fn test_get_folder_meaning_by_name() {
         assert_eq!(get_folder_meaning_by_name("Gesendet"), FolderMeaning::Sent);
@@ -2550,6 +3493,52 @@ fn test_get_folder_meaning_by_name() {
         );
         assert_eq!(get_folder_meaning_by_name("xxx"), FolderMeaning::Unknown);
         assert_eq!(get_folder_meaning_by_name("SPAM"), FolderMeaning::Spam);
+        assert_eq!(
+            get_folder_meaning_by_name("[Gmail]/All Mail"),
+            FolderMeaning::Virtual
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_is_downloaded_from_other_folder() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::ConfiguredInboxFolder, Some("INBOX"))
+            .await?;
+        t.set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await?;
+        t.set_config(Config::ConfiguredSentboxFolder, Some("Sent"))
+            .await?;
+        t.set_config_bool(Config::MvboxMove, true).await?;
+        t.set_config_bool(Config::SentboxWatch, true).await?;
+
+        for (folder, uid) in [("INBOX", 1), ("DeltaChat", 2), ("Sent", 3), ("Other", 4)] {
+            t.sql
+                .execute(
+                    "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
+                     VALUES ('dup@example.org', ?1, ?2, 1, ?1)",
+                    (folder, uid),
+                )
+                .await?;
+        }
+        assert!(is_downloaded_from_other_folder(&t, "INBOX", "dup@example.org").await?);
+        assert!(!is_downloaded_from_other_folder(&t, "DeltaChat", "dup@example.org").await?);
+        assert!(is_downloaded_from_other_folder(&t, "Other", "dup@example.org").await?);
+        assert!(!is_downloaded_from_other_folder(&t, "INBOX", "other@example.org").await?);
+
+        // If the `DeltaChat` folder is not watched, the Inbox copy is downloaded.
+        t.set_config_bool(Config::MvboxMove, false).await?;
+        assert!(!is_downloaded_from_other_folder(&t, "INBOX", "dup@example.org").await?);
+        assert!(is_downloaded_from_other_folder(&t, "Sent", "dup@example.org").await?);
+
+        // Copies which are moved away are not taken into account.
+        t.sql
+            .execute(
+                "UPDATE imap SET target='DeltaChat' WHERE folder='INBOX'",
+                (),
+            )
+            .await?;
+        assert!(!is_downloaded_from_other_folder(&t, "Sent", "dup@example.org").await?);
+        Ok(())
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -2568,6 +3557,155 @@ async fn test_set_uid_next_validity() {
         assert_eq!(get_uidvalidity(&t.ctx, "Inbox").await.unwrap(), 6);
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_replace_folder_uids_after_uidvalidity_change() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        set_uidvalidity(&t, "INBOX", 2).await?;
+
+        // Entries with pending operations left over from before the UIDVALIDITY change.
+        for (rfc724_mid, uid, target) in [
+            ("deleted@example.org", 1, ""),
+            ("moved@example.org", 2, "DeltaChat"),
+            ("gone@example.org", 4, ""),
+        ] {
+            t.sql
+                .execute(
+                    "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
+                     VALUES (?, 'INBOX', ?, 1, ?)",
+                    (rfc724_mid, uid, target),
+                )
+                .await?;
+        }
+        assert!(has_stale_uids(&t, "INBOX", 2).await?);
+
+        let msgs = BTreeMap::from([
+            (11, ("deleted@example.org".to_string(), "INBOX".to_string())),
+            (12, ("moved@example.org".to_string(), "INBOX".to_string())),
+            (13, ("seen@example.org".to_string(), "INBOX".to_string())),
+        ]);
+        replace_folder_uids(&t, "INBOX", 2, msgs).await?;
+        assert!(!has_stale_uids(&t, "INBOX", 2).await?);
+
+        let rows = t
+            .sql
+            .query_map(
+                "SELECT uid, target FROM imap WHERE folder='INBOX' AND uidvalidity=2 ORDER BY uid",
+                (),
+                |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        assert_eq!(
+            rows,
+            vec![
+                (11, "".to_string()),
+                (12, "DeltaChat".to_string()),
+                (13, "INBOX".to_string())
+            ]
+        );
+        Ok(())
+    }
+
+    fn fetch_batches(
+        uids_fetch: Vec<(u32, bool, u32)>,
+        max_count: i32,
+        max_bytes: i32,
+    ) -> Vec<(bool, Vec<u32>)> {
+        let mut batcher = FetchBatcher::new(max_count, max_bytes);
+        let mut batches: Vec<FetchBatch> = uids_fetch
+            .into_iter()
+            .filter_map(|(uid, fetch_partially, size)| {
+                batcher.push(uid, format!("{uid}@example.org"), fetch_partially, size)
+            })
+            .collect();
+        batches.extend(batcher.finish());
+        batches
+            .into_iter()
+            .map(|batch| {
+                (
+                    batch.fetch_partially,
+                    batch.uid_message_ids.into_keys().collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fetch_batches() {
+        let uids_fetch = vec![
+            (1, false, 100),
+            (2, false, 100),
+            (3, false, 100),
+            (4, true, 5000),
+            (5, true, 5000),
+            (6, false, 1000),
+            (7, false, 100),
+        ];
+        assert_eq!(
+            fetch_batches(uids_fetch.clone(), 0, 0),
+            vec![
+                (false, vec![1, 2, 3]),
+                (true, vec![4, 5]),
+                (false, vec![6, 7])
+            ]
+        );
+        assert_eq!(
+            fetch_batches(uids_fetch.clone(), 2, 0),
+            vec![
+                (false, vec![1, 2]),
+                (false, vec![3]),
+                (true, vec![4, 5]),
+                (false, vec![6, 7])
+            ]
+        );
+
+        // Messages larger than the byte budget are fetched one by one,
+        // the size of partially downloaded messages is not taken into account.
+        assert_eq!(
+            fetch_batches(uids_fetch, 0, 250),
+            vec![
+                (false, vec![1, 2]),
+                (false, vec![3]),
+                (true, vec![4, 5]),
+                (false, vec![6]),
+                (false, vec![7])
+            ]
+        );
+
+        // Large messages are fetched in chunks one by one.
+        let large = STREAMED_MSG_MIN_SIZE;
+        let uids_fetch = vec![
+            (1, false, 100),
+            (2, false, large),
+            (3, false, large),
+            (4, true, large),
+            (5, false, 100),
+        ];
+        let mut batcher = FetchBatcher::new(0, 0);
+        let mut batches: Vec<FetchBatch> = uids_fetch
+            .into_iter()
+            .filter_map(|(uid, fetch_partially, size)| {
+                batcher.push(uid, format!("{uid}@example.org"), fetch_partially, size)
+            })
+            .collect();
+        batches.extend(batcher.finish());
+        let batches: Vec<(bool, Vec<u32>)> = batches
+            .into_iter()
+            .map(|batch| (batch.streamed, batch.uid_message_ids.into_keys().collect()))
+            .collect();
+        assert_eq!(
+            batches,
+            vec![
+                (false, vec![1]),
+                (true, vec![2]),
+                (true, vec![3]),
+                (false, vec![4]),
+                (false, vec![5])
+            ]
+        );
+        assert!(fetch_batches(Vec::new(), 100, 1000).is_empty());
+    }
+
     #[test]
     fn test_build_sequence_sets() {
         assert_eq!(build_sequence_sets(&[]).unwrap(), vec![]);
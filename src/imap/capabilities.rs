@@ -1,7 +1,7 @@
 //! # IMAP capabilities
 //!
 //! IMAP server capabilities are determined with a `CAPABILITY` command.
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub(crate) struct Capabilities {
@@ -23,4 +23,7 @@ pub(crate) struct Capabilities {
 
     /// Server ID if the server supports ID capability.
     pub server_id: Option<HashMap<String, String>>,
+
+    /// All capabilities announced by the server, uppercased.
+    pub all: HashSet<String>,
 }
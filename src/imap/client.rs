@@ -4,6 +4,7 @@
 };
 
 use anyhow::{Context as _, Result};
+use async_imap::types::Capability;
 use async_imap::Client as ImapClient;
 use async_imap::Session as ImapSession;
 use tokio::io::BufWriter;
@@ -60,6 +61,13 @@ async fn determine_capabilities(
         can_check_quota: caps.has_str("QUOTA"),
         can_condstore: caps.has_str("CONDSTORE"),
         server_id,
+        all: caps
+            .iter()
+            .filter_map(|cap| match cap {
+                Capability::Atom(atom) => Some(atom.to_uppercase()),
+                _ => None,
+            })
+            .collect(),
     };
     Ok(capabilities)
 }
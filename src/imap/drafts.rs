@@ -0,0 +1,476 @@
+//! # Synchronization of drafts via the IMAP Drafts folder.
+//!
+//! If [`Config::SyncDrafts`] is enabled, the draft of each chat is uploaded
+//! with IMAP APPEND to the Drafts folder as a message flagged `\Draft`,
+//! so other devices and other MUAs see it.
+//! Whenever the draft changes, a new version is appended
+//! and the versions it replaces are deleted.
+//!
+//! Drafts appended by other Delta Chat devices are imported back on fetch.
+//! If the draft was changed on both devices, the one modified last wins.
+//! Drafts written in other MUAs and drafts with attachments are not imported.
+//!
+//! Chats with local draft changes to be uploaded are stored in the `drafts_sync` table,
+//! Message-IDs of the draft versions on the server in the `imap_drafts` table.
+
+use anyhow::{Context as _, Result};
+use async_imap::types::Flag;
+use futures::TryStreamExt;
+
+use super::{get_uid_next, get_uidvalidity, set_uid_next, set_uidvalidity, Imap};
+use crate::chat::{self, ChatId};
+use crate::config::Config;
+use crate::contact::{Contact, Origin};
+use crate::context::Context;
+use crate::headerdef::HeaderDef;
+use crate::log::LogExt;
+use crate::message::{Message, MessageState, Viewtype};
+use crate::mimefactory::MimeFactory;
+use crate::mimeparser::MimeMessage;
+use crate::scheduler::InterruptInfo;
+use crate::tools::create_outgoing_rfc724_mid;
+
+/// Draft appended to the Drafts folder by another device.
+#[derive(Debug)]
+struct RemoteDraft {
+    rfc724_mid: String,
+    chat_id: ChatId,
+
+    /// Time of the last modification, taken from the `Date` header.
+    timestamp: i64,
+
+    /// Draft text, `None` if the draft is not a text message.
+    text: Option<String>,
+}
+
+/// Schedules uploading the draft of the chat if [`Config::SyncDrafts`] is enabled.
+pub(crate) async fn schedule_draft_upload(context: &Context, chat_id: ChatId) -> Result<()> {
+    if !context.get_config_bool(Config::SyncDrafts).await? {
+        return Ok(());
+    }
+    context
+        .sql
+        .execute(
+            "INSERT OR IGNORE INTO drafts_sync (chat_id) VALUES (?)",
+            (chat_id,),
+        )
+        .await?;
+    context
+        .scheduler
+        .interrupt_inbox(InterruptInfo::new(false))
+        .await;
+    Ok(())
+}
+
+/// Schedules uploading all drafts, called when [`Config::SyncDrafts`] is enabled.
+pub(crate) async fn schedule_all_draft_uploads(context: &Context) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT OR IGNORE INTO drafts_sync (chat_id) SELECT chat_id FROM msgs WHERE state=?",
+            (MessageState::OutDraft,),
+        )
+        .await?;
+    context
+        .scheduler
+        .interrupt_inbox(InterruptInfo::new(false))
+        .await;
+    Ok(())
+}
+
+impl Imap {
+    /// Imports drafts changed on other devices and uploads local draft changes.
+    ///
+    /// Does nothing if [`Config::SyncDrafts`] is disabled or there is no Drafts folder.
+    pub(crate) async fn sync_drafts(&mut self, context: &Context) -> Result<()> {
+        if !context.get_config_bool(Config::SyncDrafts).await? {
+            return Ok(());
+        }
+        let Some(folder) = context.get_config(Config::ConfiguredDraftsFolder).await? else {
+            return Ok(());
+        };
+        self.import_drafts(context, &folder)
+            .await
+            .context("Failed to import drafts")?;
+        self.upload_drafts(context, &folder)
+            .await
+            .context("Failed to upload drafts")?;
+        Ok(())
+    }
+
+    /// Fetches drafts added to the Drafts folder since the last sync
+    /// and replaces local drafts which are older.
+    async fn import_drafts(&mut self, context: &Context, folder: &str) -> Result<()> {
+        let session = self.session.as_mut().context("no session")?;
+        session.select_folder(context, Some(folder)).await?;
+        let uid_validity = session
+            .selected_mailbox
+            .as_ref()
+            .and_then(|mailbox| mailbox.uid_validity)
+            .with_context(|| format!("No UIDVALIDITY for folder {folder}"))?;
+        let uid_start = if uid_validity == get_uidvalidity(context, folder).await? {
+            get_uid_next(context, folder).await?.max(1)
+        } else {
+            set_uidvalidity(context, folder, uid_validity).await?;
+            1
+        };
+
+        let mut fetched = Vec::new();
+        let mut list = session
+            .uid_fetch(format!("{uid_start}:*"), "(UID FLAGS BODY.PEEK[])")
+            .await
+            .context("IMAP FETCH of drafts failed")?;
+        while let Some(fetch) = list.try_next().await? {
+            let Some(uid) = fetch.uid.filter(|uid| *uid >= uid_start) else {
+                // `N:*` returns the last message even if its UID is lower than N.
+                continue;
+            };
+            let is_draft = fetch.flags().any(|flag| matches!(flag, Flag::Draft));
+            if let (true, Some(body)) = (is_draft, fetch.body()) {
+                fetched.push((uid, body.to_vec()));
+            } else {
+                fetched.push((uid, Vec::new()));
+            }
+        }
+        drop(list);
+
+        let mut uid_next = uid_start;
+        for (uid, body) in fetched {
+            uid_next = uid_next.max(uid.saturating_add(1));
+            if body.is_empty() {
+                continue;
+            }
+            let Some(draft) = parse_draft(context, &body)
+                .await
+                .with_context(|| format!("Failed to parse draft {folder}/{uid}"))
+                .log_err(context)
+                .ok()
+                .flatten()
+            else {
+                continue;
+            };
+            self.import_draft(context, folder, draft).await?;
+        }
+        set_uid_next(context, folder, uid_next).await?;
+        Ok(())
+    }
+
+    async fn import_draft(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        draft: RemoteDraft,
+    ) -> Result<()> {
+        let RemoteDraft {
+            rfc724_mid,
+            chat_id,
+            timestamp,
+            text,
+        } = draft;
+        if context
+            .sql
+            .exists(
+                "SELECT COUNT(*) FROM imap_drafts WHERE rfc724_mid=?",
+                (&rfc724_mid,),
+            )
+            .await?
+        {
+            // Uploaded by this device or already imported.
+            return Ok(());
+        }
+        context
+            .sql
+            .execute(
+                "INSERT INTO imap_drafts (rfc724_mid, chat_id) VALUES (?, ?)",
+                (&rfc724_mid, chat_id),
+            )
+            .await?;
+
+        let local_timestamp = chat_id
+            .get_draft(context)
+            .await?
+            .map(|local| local.timestamp_sort);
+        match (local_timestamp, text) {
+            (Some(local_timestamp), _) if local_timestamp >= timestamp => {
+                // Local draft is newer, replace the imported one.
+                schedule_draft_upload(context, chat_id).await?;
+            }
+            (_, Some(text)) => {
+                info!(context, "Importing draft {rfc724_mid} for {chat_id}.");
+                let mut msg = Message::new(Viewtype::Text);
+                msg.set_text(text);
+                chat_id.set_draft(context, Some(&mut msg)).await?;
+                context
+                    .sql
+                    .execute(
+                        "UPDATE msgs SET timestamp=? WHERE id=?",
+                        (timestamp, msg.id),
+                    )
+                    .await?;
+                // Setting the draft scheduled an upload, but the draft is on the server already.
+                context
+                    .sql
+                    .execute("DELETE FROM drafts_sync WHERE chat_id=?", (chat_id,))
+                    .await?;
+                self.delete_drafts(context, folder, chat_id, Some(&rfc724_mid))
+                    .await?;
+            }
+            (_, None) => {
+                info!(
+                    context,
+                    "Not importing draft {rfc724_mid}, it is not a text."
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads the drafts of chats with local draft changes.
+    async fn upload_drafts(&mut self, context: &Context, folder: &str) -> Result<()> {
+        let chat_ids: Vec<ChatId> = context
+            .sql
+            .query_map(
+                "SELECT chat_id FROM drafts_sync",
+                (),
+                |row| row.get(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        for chat_id in chat_ids {
+            // Remove the entry first, so that changes during the upload schedule another one.
+            context
+                .sql
+                .execute("DELETE FROM drafts_sync WHERE chat_id=?", (chat_id,))
+                .await?;
+            if let Err(err) = self.upload_draft(context, folder, chat_id).await {
+                context
+                    .sql
+                    .execute(
+                        "INSERT OR IGNORE INTO drafts_sync (chat_id) VALUES (?)",
+                        (chat_id,),
+                    )
+                    .await?;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the current draft of the chat to the Drafts folder
+    /// and deletes the previous versions.
+    async fn upload_draft(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        chat_id: ChatId,
+    ) -> Result<()> {
+        let keep = match render_draft(context, chat_id)
+            .await
+            .with_context(|| format!("Failed to render draft of {chat_id}"))
+            .log_err(context)
+        {
+            Ok(Some((rfc724_mid, message))) => {
+                let session = self.session.as_mut().context("no session")?;
+                session
+                    .append(folder, Some(r"(\Seen \Draft)"), None, message)
+                    .await
+                    .context("IMAP APPEND failed")?;
+                info!(context, "Uploaded draft {rfc724_mid} for {chat_id}.");
+                context
+                    .sql
+                    .execute(
+                        "INSERT INTO imap_drafts (rfc724_mid, chat_id) VALUES (?, ?)",
+                        (&rfc724_mid, chat_id),
+                    )
+                    .await?;
+                Some(rfc724_mid)
+            }
+            Ok(None) => None,
+            // Rendering will fail again, don't retry.
+            Err(_) => return Ok(()),
+        };
+        self.delete_drafts(context, folder, chat_id, keep.as_deref())
+            .await
+    }
+
+    /// Deletes the versions of the chat draft from the Drafts folder, except for `keep`.
+    async fn delete_drafts(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        chat_id: ChatId,
+        keep: Option<&str>,
+    ) -> Result<()> {
+        let rfc724_mids: Vec<String> = context
+            .sql
+            .query_map(
+                "SELECT rfc724_mid FROM imap_drafts WHERE chat_id=? AND rfc724_mid!=?",
+                (chat_id, keep.unwrap_or_default()),
+                |row| row.get(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        if rfc724_mids.is_empty() {
+            return Ok(());
+        }
+
+        let session = self.session.as_mut().context("no session")?;
+        session.select_folder(context, Some(folder)).await?;
+        for rfc724_mid in rfc724_mids {
+            if !rfc724_mid.contains(['"', '\\']) {
+                let uids = session
+                    .uid_search(format!("HEADER Message-ID \"{rfc724_mid}\""))
+                    .await
+                    .context("IMAP SEARCH of draft failed")?;
+                if !uids.is_empty() {
+                    let uid_set = uids
+                        .iter()
+                        .map(|uid| uid.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    session
+                        .add_flag_finalized_with_set(&uid_set, "\\Deleted")
+                        .await?;
+                }
+            }
+            context
+                .sql
+                .execute("DELETE FROM imap_drafts WHERE rfc724_mid=?", (&rfc724_mid,))
+                .await?;
+        }
+        session.maybe_close_folder(context).await?;
+        Ok(())
+    }
+}
+
+/// Renders the draft of the chat as a message to be appended to the Drafts folder.
+///
+/// Returns the Message-ID and the message, `None` if the chat has no draft.
+/// The draft is encrypted if a message to the chat would be encrypted.
+async fn render_draft(context: &Context, chat_id: ChatId) -> Result<Option<(String, String)>> {
+    let Some(mut draft) = chat_id.get_draft(context).await? else {
+        return Ok(None);
+    };
+    let from_addr = context.get_primary_self_addr().await?;
+    draft.rfc724_mid = create_outgoing_rfc724_mid(None, &from_addr);
+    let rendered = MimeFactory::from_msg(context, &draft, false)
+        .await?
+        .render(context)
+        .await?;
+    Ok(Some((draft.rfc724_mid, rendered.message)))
+}
+
+/// Parses a message from the Drafts folder.
+///
+/// Returns `None` if the message is not a draft written by Delta Chat on another device
+/// or the chat of the draft is not known.
+async fn parse_draft(context: &Context, body: &[u8]) -> Result<Option<RemoteDraft>> {
+    let mime_message = MimeMessage::from_bytes(context, body, None).await?;
+    if mime_message.get_header(HeaderDef::ChatVersion).is_none()
+        || !context.is_self_addr(&mime_message.from.addr).await?
+    {
+        return Ok(None);
+    }
+    let Some(rfc724_mid) = mime_message.get_rfc724_mid() else {
+        return Ok(None);
+    };
+    let Some(chat_id) = lookup_draft_chat(context, &mime_message).await? else {
+        return Ok(None);
+    };
+    let timestamp = mime_message
+        .get_header(HeaderDef::Date)
+        .and_then(|date| mailparse::dateparse(date).ok())
+        .unwrap_or_default();
+    let text = match &mime_message.parts[..] {
+        [part] if part.typ == Viewtype::Text && !part.msg.is_empty() => Some(part.msg.clone()),
+        _ => None,
+    };
+    Ok(Some(RemoteDraft {
+        rfc724_mid,
+        chat_id,
+        timestamp,
+        text,
+    }))
+}
+
+/// Returns the chat a draft belongs to, identified by the group ID
+/// or, for 1:1 chats, by the only recipient.
+async fn lookup_draft_chat(
+    context: &Context,
+    mime_message: &MimeMessage,
+) -> Result<Option<ChatId>> {
+    if let Some(grpid) = mime_message.get_header(HeaderDef::ChatGroupId) {
+        let chat = chat::get_chat_id_by_grpid(context, grpid).await?;
+        return Ok(chat.map(|(chat_id, _protected, _blocked)| chat_id));
+    }
+    let [recipient] = &mime_message.recipients[..] else {
+        return Ok(None);
+    };
+    let Some(contact_id) =
+        Contact::lookup_id_by_addr(context, &recipient.addr, Origin::Unknown).await?
+    else {
+        return Ok(None);
+    };
+    ChatId::lookup_by_contact(context, contact_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestContextManager;
+
+    async fn scheduled(context: &Context) -> Result<usize> {
+        context
+            .sql
+            .count("SELECT COUNT(*) FROM drafts_sync", ())
+            .await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_schedule_draft_upload() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let chat_id = alice.create_chat(&bob).await.id;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("first".to_string());
+        chat_id.set_draft(&alice, Some(&mut msg)).await?;
+        assert_eq!(scheduled(&alice).await?, 0);
+
+        // Enabling the option schedules existing drafts.
+        alice.set_config_bool(Config::SyncDrafts, true).await?;
+        assert_eq!(scheduled(&alice).await?, 1);
+
+        alice.sql.execute("DELETE FROM drafts_sync", ()).await?;
+        chat_id.set_draft(&alice, None).await?;
+        assert_eq!(scheduled(&alice).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_parse_draft() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let alice2 = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let chat_id = alice.create_chat(&bob).await.id;
+        let chat_id2 = alice2.create_chat(&bob).await.id;
+
+        assert!(render_draft(&alice, chat_id).await?.is_none());
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("Draft from another device".to_string());
+        chat_id.set_draft(&alice, Some(&mut msg)).await?;
+        let (rfc724_mid, message) = render_draft(&alice, chat_id).await?.unwrap();
+
+        let draft = parse_draft(&alice2, message.as_bytes()).await?.unwrap();
+        assert_eq!(draft.rfc724_mid, rfc724_mid);
+        assert_eq!(draft.chat_id, chat_id2);
+        assert_eq!(draft.text.as_deref(), Some("Draft from another device"));
+        assert!(draft.timestamp > 0);
+
+        // Messages not written by ourselves are not drafts.
+        assert!(parse_draft(&bob, message.as_bytes()).await?.is_none());
+        Ok(())
+    }
+}
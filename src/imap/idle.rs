@@ -77,22 +77,22 @@ enum Event {
 
         match fut.await {
             Ok(Event::IdleResponse(IdleResponse::NewData(x))) => {
-                info!(context, "{}: Idle has NewData {:?}", folder_name, x);
+                debug!(context, "{}: Idle has NewData {:?}", folder_name, x);
             }
             Ok(Event::IdleResponse(IdleResponse::Timeout)) => {
-                info!(
+                debug!(
                     context,
                     "{}: Idle-wait timeout or interruption", folder_name
                 );
             }
             Ok(Event::IdleResponse(IdleResponse::ManualInterrupt)) => {
-                info!(
+                debug!(
                     context,
                     "{}: Idle wait was interrupted manually", folder_name
                 );
             }
             Ok(Event::Interrupt(i)) => {
-                info!(
+                debug!(
                     context,
                     "{}: Idle wait was interrupted: {:?}", folder_name, &i
                 );
@@ -181,7 +181,7 @@ enum Event {
                             break InterruptInfo::new(false);
                         }
                     }
-                    info!(context, "fake_idle is connected");
+                    debug!(context, "fake_idle is connected");
                     // we are connected, let's see if fetching messages results
                     // in anything.  If so, we behave as if IDLE had data but
                     // will have already fetched the messages so perform_*_fetch
@@ -191,7 +191,7 @@ enum Event {
                         .await
                     {
                         Ok(res) => {
-                            info!(context, "fetch_new_messages returned {:?}", res);
+                            debug!(context, "fetch_new_messages returned {:?}", res);
                             if res {
                                 break InterruptInfo::new(false);
                             }
@@ -0,0 +1,206 @@
+//! # Server-specific workarounds.
+//!
+//! Some servers misbehave in ways which can not be detected from their responses,
+//! e.g. they announce IDLE but never send updates while idling.
+//! Workarounds for such servers are listed in [`QUIRKS`]
+//! instead of being hardcoded where they are applied.
+//! Entries are keyed by the provider ID from the provider database,
+//! by a capability from the CAPABILITY response
+//! or by the server name from the ID response.
+//!
+//! The quirks of a server are determined on login
+//! and stored in the [`Session`](super::session::Session).
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use super::capabilities::Capabilities;
+
+/// Key identifying the servers a quirk applies to.
+#[derive(Debug)]
+pub(crate) enum QuirkKey {
+    /// ID of the configured provider, see [`Provider::id`](crate::provider::Provider::id).
+    Provider(&'static str),
+
+    /// Capability announced by the server, compared case-insensitively.
+    Capability(&'static str),
+
+    /// `name` field of the ID response, compared case-insensitively.
+    ServerName(&'static str),
+}
+
+/// Workaround for a server bug.
+#[derive(Debug)]
+pub(crate) enum Quirk {
+    /// Do not use IDLE even if the server announces it.
+    NoIdle,
+
+    /// Do not fetch more than the given number of messages with one UID FETCH command.
+    MaxFetchBatch(u32),
+
+    /// Wait for the given time after login before sending further commands.
+    LoginDelay(Duration),
+}
+
+/// Known server quirks.
+pub(crate) const QUIRKS: &[(QuirkKey, Quirk)] = &[
+    // Exchange Online drops the connection
+    // if sending the response to a FETCH command takes too long.
+    (QuirkKey::Provider("outlook.com"), Quirk::MaxFetchBatch(50)),
+    // The Yahoo server, also used for AOL and custom domains,
+    // accepts IDLE but often does not report new messages while idling.
+    (QuirkKey::Capability("XYMHIGHESTMODSEQ"), Quirk::NoIdle),
+    // Zimbra answers commands sent right after login with NO
+    // while the mailbox is still being loaded.
+    (
+        QuirkKey::ServerName("Zimbra"),
+        Quirk::LoginDelay(Duration::from_secs(1)),
+    ),
+];
+
+/// Quirks applying to a server.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Quirks {
+    /// Whether IDLE must not be used.
+    pub no_idle: bool,
+
+    /// Maximum number of messages to fetch with one UID FETCH command.
+    pub max_fetch_batch: Option<u32>,
+
+    /// Time to wait after login.
+    pub login_delay: Option<Duration>,
+}
+
+impl Quirks {
+    /// Returns the quirks of a server.
+    pub fn new(provider_id: Option<&str>, capabilities: &Capabilities) -> Self {
+        Self::from_table(QUIRKS, provider_id, capabilities)
+    }
+
+    fn from_table(
+        table: &[(QuirkKey, Quirk)],
+        provider_id: Option<&str>,
+        capabilities: &Capabilities,
+    ) -> Self {
+        let server_name = capabilities
+            .server_id
+            .as_ref()
+            .and_then(|server_id| server_id.get("name"))
+            .map(|name| name.to_lowercase());
+        let mut quirks = Self::default();
+        for (key, quirk) in table {
+            let matches = match key {
+                QuirkKey::Provider(id) => provider_id == Some(*id),
+                QuirkKey::Capability(capability) => has_capability(&capabilities.all, capability),
+                QuirkKey::ServerName(name) => {
+                    server_name.as_deref() == Some(name.to_lowercase().as_str())
+                }
+            };
+            if !matches {
+                continue;
+            }
+            match quirk {
+                Quirk::NoIdle => quirks.no_idle = true,
+                Quirk::MaxFetchBatch(max) => {
+                    quirks.max_fetch_batch =
+                        Some(quirks.max_fetch_batch.map_or(*max, |m| m.min(*max)))
+                }
+                Quirk::LoginDelay(delay) => {
+                    quirks.login_delay = Some(quirks.login_delay.map_or(*delay, |d| d.max(*delay)))
+                }
+            }
+        }
+        quirks
+    }
+
+    /// Returns the number of messages to fetch with one UID FETCH command,
+    /// given the configured [`Config::FetchBatchSize`](crate::config::Config::FetchBatchSize).
+    pub fn fetch_batch_size(&self, configured: i32) -> i32 {
+        match self.max_fetch_batch.and_then(|max| i32::try_from(max).ok()) {
+            Some(max) if configured <= 0 || configured > max => max,
+            _ => configured,
+        }
+    }
+}
+
+fn has_capability(all: &HashSet<String>, capability: &str) -> bool {
+    all.contains(&capability.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_quirks_from_table() {
+        let table = [
+            (QuirkKey::Provider("example.org"), Quirk::NoIdle),
+            (QuirkKey::Capability("X-Slow"), Quirk::MaxFetchBatch(50)),
+            (QuirkKey::Capability("X-Slow"), Quirk::MaxFetchBatch(80)),
+            (
+                QuirkKey::ServerName("Buggy IMAPd"),
+                Quirk::LoginDelay(Duration::from_secs(2)),
+            ),
+        ];
+        let mut capabilities = Capabilities {
+            can_idle: true,
+            can_move: false,
+            can_check_quota: false,
+            can_condstore: false,
+            server_id: None,
+            all: HashSet::from(["IMAP4REV1".to_string(), "IDLE".to_string()]),
+        };
+        let quirks = Quirks::from_table(&table, Some("example.net"), &capabilities);
+        assert_eq!(quirks, Quirks::default());
+        assert_eq!(quirks.fetch_batch_size(100), 100);
+
+        let quirks = Quirks::from_table(&table, Some("example.org"), &capabilities);
+        assert!(quirks.no_idle);
+
+        capabilities.all.insert("X-SLOW".to_string());
+        capabilities.server_id = Some(HashMap::from([(
+            "name".to_string(),
+            "buggy imapd".to_string(),
+        )]));
+        let quirks = Quirks::from_table(&table, None, &capabilities);
+        assert_eq!(
+            quirks,
+            Quirks {
+                no_idle: false,
+                max_fetch_batch: Some(50),
+                login_delay: Some(Duration::from_secs(2)),
+            }
+        );
+        assert_eq!(quirks.fetch_batch_size(100), 50);
+        assert_eq!(quirks.fetch_batch_size(20), 20);
+        assert_eq!(quirks.fetch_batch_size(0), 50);
+    }
+
+    #[test]
+    fn test_known_quirks() {
+        let mut capabilities = Capabilities {
+            can_idle: true,
+            can_move: false,
+            can_check_quota: false,
+            can_condstore: false,
+            server_id: None,
+            all: HashSet::from(["IMAP4REV1".to_string(), "IDLE".to_string()]),
+        };
+        assert_eq!(Quirks::new(Some("gmail"), &capabilities), Quirks::default());
+        assert_eq!(
+            Quirks::new(Some("outlook.com"), &capabilities).fetch_batch_size(200),
+            50
+        );
+
+        capabilities.all.insert("XYMHIGHESTMODSEQ".to_string());
+        assert!(Quirks::new(None, &capabilities).no_idle);
+
+        capabilities.server_id = Some(HashMap::from([("name".to_string(), "Zimbra".to_string())]));
+        assert_eq!(
+            Quirks::new(None, &capabilities).login_delay,
+            Some(Duration::from_secs(1))
+        );
+    }
+}
@@ -3,7 +3,7 @@
 use anyhow::{Context as _, Result};
 use futures::stream::StreamExt;
 
-use super::{get_folder_meaning_by_attrs, get_folder_meaning_by_name};
+use super::{get_folder_meaning_by_attrs, get_folder_meaning_by_name, select_folder};
 use crate::config::Config;
 use crate::imap::Imap;
 use crate::log::LogExt;
@@ -87,6 +87,8 @@ pub(crate) async fn scan_folders(&mut self, context: &Context) -> Result<bool> {
         for conf in [
             Config::ConfiguredSentboxFolder,
             Config::ConfiguredTrashFolder,
+            Config::ConfiguredSpamFolder,
+            Config::ConfiguredDraftsFolder,
         ] {
             context
                 .set_config(conf, folder_configs.get(&conf).map(|s| s.as_str()))
@@ -97,6 +99,28 @@ pub(crate) async fn scan_folders(&mut self, context: &Context) -> Result<bool> {
         Ok(true)
     }
 
+    /// Selects an additional folder set with [`Context::set_extra_folder_watched`].
+    ///
+    /// Unlike the configured folders, additional folders are not created
+    /// if they do not exist on the server. Returns false in this case.
+    pub(crate) async fn select_extra_folder(
+        &mut self,
+        context: &Context,
+        folder: &str,
+    ) -> Result<bool> {
+        let session = self.session.as_mut().context("no session")?;
+        match session.select_folder(context, Some(folder)).await {
+            Ok(_) => Ok(true),
+            Err(select_folder::Error::NoFolder(..) | select_folder::Error::BadFolderName(..)) => {
+                warn!(context, "Additional folder {folder:?} does not exist.");
+                Ok(false)
+            }
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to select additional folder {folder:?}"))
+            }
+        }
+    }
+
     /// Returns the names of all folders on the IMAP server.
     pub async fn list_folders(
         self: &mut Imap,
@@ -127,7 +151,8 @@ pub(crate) async fn get_watched_folder_configs(context: &Context) -> Result<Vec<
     Ok(res)
 }
 
-pub(crate) async fn get_watched_folders(context: &Context) -> Result<Vec<String>> {
+/// Returns the names of the configured Inbox, Sent and DeltaChat folders if they are watched.
+pub(crate) async fn get_configured_watched_folders(context: &Context) -> Result<Vec<String>> {
     let mut res = Vec::new();
     for folder_config in get_watched_folder_configs(context).await? {
         if let Some(folder) = context.get_config(folder_config).await? {
@@ -136,3 +161,15 @@ pub(crate) async fn get_watched_folders(context: &Context) -> Result<Vec<String>
     }
     Ok(res)
 }
+
+/// Returns the names of all watched folders, including the additional folders
+/// set with [`Context::set_extra_folder_watched`].
+pub(crate) async fn get_watched_folders(context: &Context) -> Result<Vec<String>> {
+    let mut res = get_configured_watched_folders(context).await?;
+    for folder in context.get_extra_folders().await? {
+        if !res.contains(&folder) {
+            res.push(folder);
+        }
+    }
+    Ok(res)
+}
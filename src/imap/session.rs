@@ -4,6 +4,7 @@
 use async_imap::Session as ImapSession;
 
 use crate::imap::capabilities::Capabilities;
+use crate::imap::quirks::Quirks;
 use crate::net::session::SessionStream;
 
 #[derive(Debug)]
@@ -12,6 +13,9 @@ pub(crate) struct Session {
 
     pub capabilities: Capabilities,
 
+    /// Workarounds for the server, see [`Quirks`].
+    pub quirks: Quirks,
+
     /// Selected folder name.
     pub selected_folder: Option<String>,
 
@@ -43,6 +47,7 @@ pub(crate) fn new(
         Self {
             inner,
             capabilities,
+            quirks: Quirks::default(),
             selected_folder: None,
             selected_mailbox: None,
             selected_folder_needs_expunge: false,
@@ -50,7 +55,7 @@ pub(crate) fn new(
     }
 
     pub fn can_idle(&self) -> bool {
-        self.capabilities.can_idle
+        self.capabilities.can_idle && !self.quirks.no_idle
     }
 
     pub fn can_move(&self) -> bool {
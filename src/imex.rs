@@ -14,7 +14,7 @@
 
 use crate::blob::{BlobDirContents, BlobObject};
 use crate::chat::{self, delete_and_reset_all_device_msgs, ChatId};
-use crate::config::Config;
+use crate::config::{Config, InternalConfig};
 use crate::contact::ContactId;
 use crate::context::Context;
 use crate::e2ee;
@@ -25,7 +25,7 @@
 use crate::log::LogExt;
 use crate::message::{Message, MsgId, Viewtype};
 use crate::mimeparser::SystemMessage;
-use crate::param::Param;
+use crate::param::{Param, Params};
 use crate::pgp;
 use crate::sql;
 use crate::stock_str;
@@ -34,8 +34,12 @@
     EmailAddress,
 };
 
+mod eml;
+mod provisioning;
 mod transfer;
 
+pub use eml::{import_eml, receive_eml_file};
+pub use provisioning::{export_provisioning, import_provisioning};
 pub use transfer::{get_backup, BackupProvider};
 
 // Name of the database file in the backup.
@@ -251,7 +255,12 @@ pub fn create_setup_code(_context: &Context) -> String {
 }
 
 async fn maybe_add_bcc_self_device_msg(context: &Context) -> Result<()> {
-    if !context.sql.get_raw_config_bool("bcc_self").await? {
+    context.set_other_devices_known().await?;
+    if !context
+        .sql
+        .get_raw_config_bool(Config::BccSelf.as_ref())
+        .await?
+    {
         let mut msg = Message::new(Viewtype::Text);
         // TODO: define this as a stockstring once the wording is settled.
         msg.text = "It seems you are using multiple devices with Delta Chat. Great!\n\n\
@@ -306,15 +315,14 @@ async fn set_self_key(
     match preferencrypt.map(|s| s.as_str()) {
         Some(headerval) => {
             let e2ee_enabled = match headerval {
-                "nopreference" => 0,
-                "mutual" => 1,
+                "nopreference" => false,
+                "mutual" => true,
                 _ => {
                     bail!("invalid Autocrypt-Prefer-Encrypt header: {:?}", header);
                 }
             };
             context
-                .sql
-                .set_raw_config_int("e2ee_enabled", e2ee_enabled)
+                .set_config_bool(Config::E2eeEnabled, e2ee_enabled)
                 .await?;
         }
         None => {
@@ -474,6 +482,8 @@ async fn import_backup(
 
     context.sql.run_migrations(context).await?;
     delete_and_reset_all_device_msgs(context).await?;
+    // The account is now also used on the device the backup was exported from.
+    context.set_other_devices_known().await?;
 
     Ok(())
 }
@@ -526,7 +536,7 @@ async fn export_backup(context: &Context, dir: &Path, passphrase: String) -> Res
     let _d1 = DeleteOnDrop(temp_db_path.clone());
     let _d2 = DeleteOnDrop(temp_path.clone());
 
-    export_database(context, &temp_db_path, passphrase)
+    let referenced_files = export_database(context, &temp_db_path, passphrase)
         .await
         .context("could not export database")?;
 
@@ -537,7 +547,7 @@ async fn export_backup(context: &Context, dir: &Path, passphrase: String) -> Res
         dest_path.display(),
     );
 
-    let res = export_backup_inner(context, &temp_db_path, &temp_path).await;
+    let res = export_backup_inner(context, &temp_db_path, &temp_path, referenced_files).await;
 
     match &res {
         Ok(_) => {
@@ -565,6 +575,7 @@ async fn export_backup_inner(
     context: &Context,
     temp_db_path: &Path,
     temp_path: &Path,
+    referenced_files: Vec<(PathBuf, String)>,
 ) -> Result<()> {
     let file = File::create(temp_path).await?;
 
@@ -575,13 +586,18 @@ async fn export_backup_inner(
         .await?;
 
     let blobdir = BlobDirContents::new(context).await?;
+    let files: Vec<(PathBuf, String)> = blobdir
+        .iter()
+        .map(|blob| (blob.to_abs_path(), blob.as_name().to_string()))
+        .chain(referenced_files)
+        .collect();
     let mut last_progress = 0;
 
-    for (i, blob) in blobdir.iter().enumerate() {
-        let mut file = File::open(blob.to_abs_path()).await?;
-        let path_in_archive = PathBuf::from(BLOBS_BACKUP_NAME).join(blob.as_name());
+    for (i, (path, name)) in files.iter().enumerate() {
+        let mut file = File::open(path).await?;
+        let path_in_archive = PathBuf::from(BLOBS_BACKUP_NAME).join(name);
         builder.append_file(path_in_archive, &mut file).await?;
-        let progress = 1000 * i / blobdir.len();
+        let progress = 1000 * i / files.len();
         if progress != last_progress && progress > 10 && progress < 1000 {
             context.emit_event(EventType::ImexProgress(progress));
             last_progress = progress;
@@ -711,6 +727,8 @@ async fn export_self_keys(context: &Context, dir: &Path) -> Result<()> {
     }
 
     ensure!(export_errors == 0, "errors while exporting keys");
+    // The exported keys may be imported on another device.
+    context.set_other_devices_known().await?;
     Ok(())
 }
 
@@ -756,26 +774,112 @@ async fn export_key_to_asc_file<T>(
     Ok(())
 }
 
+/// Tables of work queued by the exporting device, which are cleared in backups.
+const QUEUE_TABLES: &[&str] = &[
+    "smtp",
+    "smtp_mdns",
+    "smtp_status_updates",
+    "multi_device_sync",
+    "drafts_sync",
+];
+
+/// Returns the files outside the blobdir referenced by messages,
+/// see [`Message::set_file_reference`], together with the blob names they get in backups.
+///
+/// Files which do not exist anymore are skipped.
+async fn get_referenced_files(context: &Context) -> Result<Vec<(MsgId, PathBuf, String)>> {
+    let rows = context
+        .sql
+        .query_map(
+            "SELECT id, param FROM msgs WHERE param LIKE '%1=1%'",
+            (),
+            |row| {
+                let msg_id: MsgId = row.get(0)?;
+                let param: String = row.get(1)?;
+                Ok((msg_id, param))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    let mut files = Vec::new();
+    for (msg_id, param) in rows {
+        let param: Params = param.parse().unwrap_or_default();
+        if !param.get_bool(Param::FileReference).unwrap_or_default() {
+            continue;
+        }
+        let Some(path) = param.get_path(Param::File, context)? else {
+            continue;
+        };
+        if !path.is_file() {
+            warn!(
+                context,
+                "Referenced file {} of {msg_id} is missing, not adding it to the backup.",
+                path.display()
+            );
+            continue;
+        }
+        let (stem, ext) =
+            BlobObject::sanitise_name(&path.file_name().unwrap_or_default().to_string_lossy());
+        // The message ID keeps the name unique among the referenced files,
+        // the counter avoids clashes with the blobs.
+        let mut name = format!("{stem}-{}{ext}", msg_id.to_u32());
+        let mut i = 0;
+        while context.get_blobdir().join(&name).exists() {
+            i += 1;
+            name = format!("{stem}-{}-{i}{ext}", msg_id.to_u32());
+        }
+        files.push((msg_id, path, name));
+    }
+    Ok(files)
+}
+
 /// Exports the database to *dest*, encrypted using *passphrase*.
 ///
 /// The directory of *dest* must already exist, if *dest* itself exists it will be
 /// overwritten.
 ///
+/// Messages referencing files outside the blobdir are changed in the exported database
+/// to refer to blobs instead.
+/// Returns the paths of these files and their blob names,
+/// the caller has to add them to the backup.
+///
 /// This also verifies that IO is not running during the export.
-async fn export_database(context: &Context, dest: &Path, passphrase: String) -> Result<()> {
+async fn export_database(
+    context: &Context,
+    dest: &Path,
+    passphrase: String,
+) -> Result<Vec<(PathBuf, String)>> {
     ensure!(
         !context.scheduler.is_running().await,
         "cannot export backup, IO is running"
     );
-    let now = time().try_into().context("32-bit UNIX time overflow")?;
+    let now = time();
 
     // TODO: Maybe introduce camino crate for UTF-8 paths where we need them.
     let dest = dest
         .to_str()
         .with_context(|| format!("path {} is not valid unicode", dest.display()))?;
 
-    context.sql.set_raw_config_int("backup_time", now).await?;
+    context
+        .sql
+        .set_raw_config_int64(InternalConfig::BackupTime.as_ref(), now)
+        .await?;
+    // The backup is going to be imported on another device.
+    context.set_other_devices_known().await?;
     sql::housekeeping(context).await.log_err(context).ok();
+    let referenced_files = get_referenced_files(context).await?;
+    let mut referenced_params = Vec::new();
+    for (msg_id, path, name) in &referenced_files {
+        let mut msg = Message::load_from_db(context, *msg_id).await?;
+        if !msg.param.exists(Param::Filename) {
+            if let Some(file_name) = path.file_name() {
+                msg.param.set(Param::Filename, file_name.to_string_lossy());
+            }
+        }
+        msg.param.remove(Param::FileReference);
+        msg.param.set(Param::File, format!("$BLOBDIR/{name}"));
+        referenced_params.push((*msg_id, msg.param.to_string()));
+    }
     context
         .sql
         .call_write(|conn| {
@@ -786,7 +890,35 @@ async fn export_database(context: &Context, dest: &Path, passphrase: String) ->
                 .context("failed to attach backup database")?;
             let res = conn
                 .query_row("SELECT sqlcipher_export('backup')", [], |_row| Ok(()))
-                .context("failed to export to attached backup database");
+                .context("failed to export to attached backup database")
+                .and_then(|()| {
+                    // Webxdc status updates, messages and sync items are part of the backup,
+                    // but pending ones are sent by this device once IO is started again
+                    // and must not be sent a second time by the importing one.
+                    for table in QUEUE_TABLES {
+                        conn.execute(&format!("DELETE FROM backup.{table};"), [])
+                            .with_context(|| format!("failed to clear {table} in backup"))?;
+                    }
+                    // Push notifications are registered for this device only.
+                    conn.execute(
+                        "DELETE FROM backup.config WHERE keyname IN ('push_device_token', 'push_id', 'push_secret');",
+                        [],
+                    )
+                    .context("failed to clear push registration in backup")?;
+                    // The offline mode is a setting of this device,
+                    // the importing device must not stay offline because of it.
+                    conn.execute("DELETE FROM backup.config WHERE keyname='offline';", [])
+                        .context("failed to clear offline mode in backup")?;
+                    // Referenced files are added to the backup as blobs.
+                    for (msg_id, param) in &referenced_params {
+                        conn.execute(
+                            "UPDATE backup.msgs SET param=? WHERE id=?",
+                            (param, msg_id),
+                        )
+                        .context("failed to update referenced file in backup")?;
+                    }
+                    Ok(())
+                });
             conn.execute(
                 "UPDATE backup.config SET value='0' WHERE keyname='verified_one_on_one_chats';",
                 [],
@@ -797,7 +929,11 @@ async fn export_database(context: &Context, dest: &Path, passphrase: String) ->
             res?;
             Ok(())
         })
-        .await
+        .await?;
+    Ok(referenced_files
+        .into_iter()
+        .map(|(_, path, name)| (path, name))
+        .collect())
 }
 
 #[cfg(test)]
@@ -811,6 +947,7 @@ mod tests {
     use crate::pgp::{split_armored_data, HEADER_AUTOCRYPT, HEADER_SETUPCODE};
     use crate::stock_str::StockMessage;
     use crate::test_utils::{alice_keypair, TestContext};
+    use crate::webxdc::StatusUpdateSerial;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_render_setup_file() {
@@ -932,6 +1069,7 @@ async fn test_export_and_import_backup() -> Result<()> {
                     .set_config_bool(Config::VerifiedOneOnOneChats, true)
                     .await?;
             }
+            context1.set_config_bool(Config::Offline, true).await?;
 
             let context2 = TestContext::new().await;
             assert!(!context2.is_configured().await?);
@@ -976,6 +1114,7 @@ async fn test_export_and_import_backup() -> Result<()> {
                 context2.get_config(Config::Addr).await?,
                 Some("alice@example.org".to_string())
             );
+            assert!(!context2.get_config_bool(Config::Offline).await?);
             assert_eq!(
                 context2
                     .get_config_bool(Config::VerifiedOneOnOneChats)
@@ -992,6 +1131,83 @@ async fn test_export_and_import_backup() -> Result<()> {
         Ok(())
     }
 
+    /// Tests that webxdc status updates are backed up,
+    /// but pending updates are only sent by the exporting device.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_export_and_import_webxdc_status_updates() -> Result<()> {
+        let backup_dir = tempfile::tempdir()?;
+        let context1 = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let chat = context1.create_chat(&bob).await;
+
+        let file = context1.get_blobdir().join("minimal.xdc");
+        tokio::fs::write(&file, include_bytes!("../test-data/webxdc/minimal.xdc")).await?;
+        let mut instance = Message::new(Viewtype::Webxdc);
+        instance.set_file(file.to_str().unwrap(), None);
+        let instance_id = chat::send_msg(&context1, chat.id, &mut instance).await?;
+        context1
+            .send_webxdc_status_update(instance_id, r#"{"payload": "backed up"}"#, "descr")
+            .await?;
+        async fn count_pending(t: &TestContext) -> Result<usize> {
+            let mut count = 0;
+            for table in QUEUE_TABLES {
+                count += t
+                    .sql
+                    .count(&format!("SELECT COUNT(*) FROM {table}"), ())
+                    .await?;
+            }
+            Ok(count)
+        }
+        // The message and the status update are queued.
+        assert_eq!(count_pending(&context1).await?, 2);
+
+        imex(&context1, ImexMode::ExportBackup, backup_dir.path(), None).await?;
+        let context2 = TestContext::new().await;
+        let backup = has_backup(&context2, backup_dir.path()).await?;
+        imex(&context2, ImexMode::ImportBackup, backup.as_ref(), None).await?;
+
+        let updates = context2
+            .get_webxdc_status_updates(instance_id, StatusUpdateSerial::new(0))
+            .await?;
+        assert!(updates.contains("backed up"));
+        assert_eq!(count_pending(&context2).await?, 0);
+        assert_eq!(count_pending(&context1).await?, 2);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_export_referenced_file() -> Result<()> {
+        let backup_dir = tempfile::tempdir()?;
+        let file_dir = tempfile::tempdir()?;
+        let context1 = TestContext::new_alice().await;
+        let chat = context1
+            .create_chat_with_contact("Bob", "bob@example.net")
+            .await;
+        let file = file_dir.path().join("large.bin");
+        fs::write(&file, "referenced").await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file_reference(file.to_str().unwrap(), None);
+        let msg_id = chat::send_msg(&context1, chat.id, &mut msg).await?;
+
+        imex(&context1, ImexMode::ExportBackup, backup_dir.path(), None).await?;
+        let context2 = TestContext::new().await;
+        let backup = has_backup(&context2, backup_dir.path()).await?;
+        imex(&context2, ImexMode::ImportBackup, backup.as_ref(), None).await?;
+
+        // The imported message refers to a blob with the content of the file.
+        let msg = Message::load_from_db(&context2, msg_id).await?;
+        let path = msg.get_file(&context2).unwrap();
+        assert!(path.starts_with(context2.get_blobdir()));
+        assert_eq!(fs::read_to_string(&path).await?, "referenced");
+        assert_eq!(msg.get_filename().unwrap(), "large.bin");
+        assert!(!msg.param.get_bool(Param::FileReference).unwrap_or_default());
+
+        // The exporting device still refers to the file.
+        let msg = Message::load_from_db(&context1, msg_id).await?;
+        assert_eq!(msg.get_file(&context1), Some(file));
+        Ok(())
+    }
+
     /// This is a regression test for
     /// https://github.com/deltachat/deltachat-android/issues/2263
     /// where the config cache wasn't reset properly after a backup.
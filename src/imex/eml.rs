@@ -0,0 +1,102 @@
+//! # Import of single messages.
+//!
+//! Emails obtained outside of the IMAP connection, e.g. `.eml` files exported
+//! from another mail program, are added to the chats using the same pipeline
+//! as received messages. Messages already in the database are skipped.
+
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use mailparse::parse_mail;
+
+use crate::constants::DC_CHAT_ID_TRASH;
+use crate::context::Context;
+use crate::imap;
+use crate::message::MsgId;
+use crate::receive_imf::receive_imf_inner;
+use crate::tools::read_file;
+
+/// Imports an email in RFC 5322 format into the chats.
+///
+/// The message is handled like a received one, e.g. it is decrypted
+/// and assigned to a chat, but it is marked as seen
+/// as it is usually a part of the history.
+///
+/// Returns the IDs of the added messages,
+/// which is empty if the message is already in the database
+/// or is not shown in a chat, e.g. because it is a read receipt.
+pub async fn import_eml(context: &Context, data: &[u8]) -> Result<Vec<MsgId>> {
+    let mail = parse_mail(data).context("Cannot parse email")?;
+    let rfc724_mid =
+        imap::prefetch_get_message_id(&mail.headers).unwrap_or_else(imap::create_message_id);
+
+    // Unlike in `receive_imf()`, the download limit is ignored
+    // as there is no way to download the rest of the message later.
+    let Some(received) = receive_imf_inner(context, &rfc724_mid, data, true, None, false).await?
+    else {
+        return Ok(Vec::new());
+    };
+    if received.chat_id == DC_CHAT_ID_TRASH {
+        return Ok(Vec::new());
+    }
+    Ok(received.msg_ids)
+}
+
+/// Imports an `.eml` file into the chats, see [`import_eml`].
+pub async fn receive_eml_file(context: &Context, path: &Path) -> Result<Vec<MsgId>> {
+    let data = read_file(context, path).await?;
+    let msg_ids = import_eml(context, &data)
+        .await
+        .with_context(|| format!("Cannot import {}", path.display()))?;
+    info!(
+        context,
+        "Imported {} messages from {}.",
+        msg_ids.len(),
+        path.display()
+    );
+    Ok(msg_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::Chat;
+    use crate::message::{Message, MessageState};
+    use crate::test_utils::TestContext;
+
+    const EML: &[u8] = b"From: Bob <bob@example.net>\n\
+        To: alice@example.org\n\
+        Subject: Old times\n\
+        Message-ID: <old@example.net>\n\
+        Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+        \n\
+        Do you remember?\n";
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_import_eml() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let msg_ids = import_eml(&t, EML).await?;
+        assert_eq!(msg_ids.len(), 1);
+        let msg = Message::load_from_db(&t, msg_ids[0]).await?;
+        assert!(msg.get_text().contains("Do you remember?"));
+        assert_eq!(msg.get_state(), MessageState::InSeen);
+        let chat = Chat::load_from_db(&t, msg.chat_id).await?;
+        assert!(chat.is_contact_request());
+
+        // Importing the same message again does not duplicate it.
+        assert!(import_eml(&t, EML).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_receive_eml_file() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let path = t.get_blobdir().join("old.eml");
+        tokio::fs::write(&path, EML).await?;
+        assert_eq!(receive_eml_file(&t, &path).await?.len(), 1);
+        assert!(receive_eml_file(&t, &t.get_blobdir().join("missing.eml"))
+            .await
+            .is_err());
+        Ok(())
+    }
+}
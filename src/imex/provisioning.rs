@@ -0,0 +1,233 @@
+//! # Account provisioning.
+//!
+//! The login configuration of a configured account can be exported
+//! as a passphrase-encrypted provisioning string,
+//! optionally including the secret key.
+//! The string can be shown as a QR code or passed to a bot
+//! and is imported into an unconfigured account
+//! without running autoconfiguration.
+
+use std::io::Cursor;
+
+use anyhow::{ensure, Context as _, Result};
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+use super::set_self_key;
+use crate::config::Config;
+use crate::context::Context;
+use crate::e2ee;
+use crate::key::{load_self_secret_key, DcKey};
+use crate::login_param::{LoginParam, ServerLoginParam};
+use crate::pgp;
+use crate::provider::get_provider_by_id;
+use crate::qr::PROVISIONING_SCHEME;
+use crate::tools::time;
+
+/// Login parameters of a single server in the provisioning data.
+#[derive(Debug, Serialize, Deserialize)]
+struct ServerData {
+    server: String,
+    user: String,
+    password: String,
+    port: u16,
+    security: u32,
+    certificate_checks: u32,
+    oauth2: bool,
+}
+
+impl From<&ServerLoginParam> for ServerData {
+    fn from(param: &ServerLoginParam) -> Self {
+        Self {
+            server: param.server.clone(),
+            user: param.user.clone(),
+            password: param.password.clone(),
+            port: param.port,
+            security: param.security.to_u32().unwrap_or_default(),
+            certificate_checks: param.certificate_checks.to_u32().unwrap_or_default(),
+            oauth2: param.oauth2,
+        }
+    }
+}
+
+impl From<ServerData> for ServerLoginParam {
+    fn from(data: ServerData) -> Self {
+        Self {
+            server: data.server,
+            user: data.user,
+            password: data.password,
+            port: data.port,
+            security: FromPrimitive::from_u32(data.security).unwrap_or_default(),
+            certificate_checks: FromPrimitive::from_u32(data.certificate_checks)
+                .unwrap_or_default(),
+            oauth2: data.oauth2,
+        }
+    }
+}
+
+/// Plaintext of the provisioning string.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProvisioningData {
+    addr: String,
+    imap: ServerData,
+    smtp: ServerData,
+
+    /// ID of the provider in the provider database.
+    provider: Option<String>,
+
+    /// ASCII-armored secret key.
+    secret_key: Option<String>,
+}
+
+/// Exports the login configuration of the account as a provisioning string
+/// encrypted with `passphrase`.
+///
+/// If `include_secret_key` is true, the secret key is exported as well,
+/// so the imported account keeps its fingerprint.
+pub async fn export_provisioning(
+    context: &Context,
+    passphrase: &str,
+    include_secret_key: bool,
+) -> Result<String> {
+    ensure!(
+        context.is_configured().await?,
+        "Cannot export provisioning data of unconfigured account."
+    );
+    ensure!(!passphrase.is_empty(), "Passphrase must not be empty.");
+
+    let param = LoginParam::load_configured_params(context).await?;
+    let secret_key = if include_secret_key {
+        Some(load_self_secret_key(context).await?.to_asc(None))
+    } else {
+        None
+    };
+    let data = ProvisioningData {
+        addr: param.addr.clone(),
+        imap: (&param.imap).into(),
+        smtp: (&param.smtp).into(),
+        provider: param.provider.map(|provider| provider.id.to_string()),
+        secret_key,
+    };
+    let plain = serde_json::to_vec(&data)?;
+    let encrypted = pgp::symm_encrypt(passphrase, &plain).await?;
+    Ok(format!("{PROVISIONING_SCHEME}{encrypted}"))
+}
+
+/// Configures an unconfigured account from a provisioning string
+/// created by [`export_provisioning`].
+///
+/// The login parameters are saved as they are, without autoconfiguration.
+/// If the provisioning string contains no secret key, a new key is generated.
+pub async fn import_provisioning(context: &Context, blob: &str, passphrase: &str) -> Result<()> {
+    ensure!(
+        !context.is_configured().await?,
+        "Cannot import provisioning data into configured account."
+    );
+    let blob = blob.trim();
+    let armored = blob
+        .get(..PROVISIONING_SCHEME.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(PROVISIONING_SCHEME))
+        .and_then(|_| blob.get(PROVISIONING_SCHEME.len()..))
+        .context("Not a provisioning string.")?;
+    let plain = pgp::symm_decrypt(passphrase, Cursor::new(armored))
+        .await
+        .context("Cannot decrypt provisioning string, wrong passphrase?")?;
+    let data: ProvisioningData =
+        serde_json::from_slice(&plain).context("Invalid provisioning data.")?;
+
+    let param = LoginParam {
+        addr: data.addr,
+        imap: data.imap.into(),
+        smtp: data.smtp.into(),
+        provider: data.provider.as_deref().and_then(get_provider_by_id),
+        socks5_config: None,
+    };
+
+    // Entered parameters are set as well, so the account can be reconfigured later.
+    context.set_config(Config::Addr, Some(&param.addr)).await?;
+    context
+        .set_config(Config::MailPw, Some(&param.imap.password))
+        .await?;
+    param.save_as_configured_params(context).await?;
+
+    // The key is imported after saving the address, it is stored for the configured address.
+    if let Some(secret_key) = data.secret_key {
+        set_self_key(context, &secret_key, true, false).await?;
+    }
+    context
+        .set_config(Config::ConfiguredTimestamp, Some(&time().to_string()))
+        .await?;
+    e2ee::ensure_secret_key_exists(context).await?;
+    context.set_config_bool(Config::Configured, true).await?;
+    info!(context, "Account configured from provisioning data.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::load_self_public_key;
+    use crate::test_utils::TestContext;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_provisioning_roundtrip() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        alice
+            .set_config(Config::ConfiguredMailServer, Some("imap.example.org"))
+            .await?;
+        alice
+            .set_config(Config::ConfiguredMailPw, Some("secret"))
+            .await?;
+        alice
+            .set_config(Config::ConfiguredSendServer, Some("smtp.example.org"))
+            .await?;
+
+        let blob = export_provisioning(&alice, "passphrase", true).await?;
+        assert!(blob.starts_with(PROVISIONING_SCHEME));
+        assert!(!blob.contains("secret"));
+
+        let t = TestContext::new().await;
+        assert!(import_provisioning(&t, &blob, "wrong").await.is_err());
+        assert!(!t.is_configured().await?);
+
+        import_provisioning(&t, &blob, "passphrase").await?;
+        assert!(t.is_configured().await?);
+        assert_eq!(
+            t.get_config(Config::ConfiguredAddr).await?.unwrap(),
+            "alice@example.org"
+        );
+        assert_eq!(
+            t.get_config(Config::ConfiguredMailServer).await?.unwrap(),
+            "imap.example.org"
+        );
+        assert_eq!(
+            t.get_config(Config::ConfiguredMailPw).await?.unwrap(),
+            "secret"
+        );
+        assert_eq!(
+            load_self_public_key(&t).await?.fingerprint(),
+            load_self_public_key(&alice).await?.fingerprint()
+        );
+
+        // Configured accounts are not overwritten.
+        assert!(import_provisioning(&alice, &blob, "passphrase")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_provisioning_without_key() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let blob = export_provisioning(&alice, "passphrase", false).await?;
+
+        let t = TestContext::new().await;
+        import_provisioning(&t, &blob, "passphrase").await?;
+        assert!(t.is_configured().await?);
+        assert_ne!(
+            load_self_public_key(&t).await?.fingerprint(),
+            load_self_public_key(&alice).await?.fingerprint()
+        );
+        Ok(())
+    }
+}
@@ -158,7 +158,7 @@ async fn prepare_inner(context: &Context, dbfile: &Path) -> Result<(Provider, Ti
         // Generate the token up front: we also use it to encrypt the database.
         let token = AuthToken::generate();
         context.emit_event(SendProgress::Started.into());
-        export_database(context, dbfile, token.to_string())
+        let referenced_files = export_database(context, dbfile, token.to_string())
             .await
             .context("Database export failed")?;
         context.emit_event(SendProgress::DatabaseExported.into());
@@ -174,6 +174,9 @@ async fn prepare_inner(context: &Context, dbfile: &Path) -> Result<(Provider, Ti
             let name = format!("blob/{}", blob.as_file_name());
             files.push(DataSource::with_name(path, name));
         }
+        for (path, name) in referenced_files {
+            files.push(DataSource::with_name(path, format!("blob/{name}")));
+        }
 
         // Start listening.
         let (db, hash) = iroh::provider::create_collection(files).await?;
@@ -0,0 +1,285 @@
+//! # Job queue inspection and retry policy.
+//!
+//! Background work which has to survive restarts is queued in the database:
+//! outgoing messages in the `smtp` table, read receipts in `smtp_mdns`
+//! and pending downloads in `download`.
+//! This module defines the priorities and the retry backoff of these jobs
+//! and allows to list them for debugging.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+use crate::events::EventType;
+use crate::message::MsgId;
+use crate::tools::time;
+
+/// Maximum delay between two attempts to run a job, in seconds.
+const MAX_RETRY_DELAY: i64 = 60 * 60;
+
+/// Kind of a queued job.
+///
+/// Variants are ordered by priority, the jobs that should run first come first.
+#[derive(
+    Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum JobKind {
+    /// Sending a message the user has written.
+    SendMsg,
+
+    /// Downloading a message the user has requested to download.
+    Download,
+
+    /// Sending a read receipt.
+    SendMdn,
+}
+
+impl JobKind {
+    /// Returns the number of attempts after which the job is given up.
+    pub fn max_retries(self) -> i64 {
+        match self {
+            JobKind::SendMsg => 6,
+            JobKind::Download => 1,
+            JobKind::SendMdn => 6,
+        }
+    }
+
+    /// Returns the delay in seconds before the next attempt
+    /// after the job failed `retries` times.
+    ///
+    /// The delay after the first failure is the base delay of the job kind,
+    /// it doubles with every further failure and is capped at one hour.
+    pub fn retry_delay(self, retries: i64) -> i64 {
+        let base: i64 = match self {
+            JobKind::SendMsg => 30,
+            JobKind::Download => 60,
+            JobKind::SendMdn => 60,
+        };
+        let exponent = u32::try_from(retries.saturating_sub(1).clamp(0, 16)).unwrap_or_default();
+        base.saturating_mul(1 << exponent).min(MAX_RETRY_DELAY)
+    }
+}
+
+/// A job waiting in the queue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingJob {
+    /// Kind of the job.
+    pub kind: JobKind,
+
+    /// Message the job belongs to.
+    pub msg_id: MsgId,
+
+    /// Number of failed attempts so far.
+    pub retries: i64,
+
+    /// Timestamp before which the job is not attempted again.
+    /// 0 if the job can run immediately.
+    pub next_try: i64,
+
+    /// Error of the last failed attempt, empty if there was none.
+    pub last_error: String,
+}
+
+impl Context {
+    /// Returns all jobs waiting in the queue, in the order they are going to run.
+    ///
+    /// This is meant for debugging stuck messages, the result is not stable API.
+    pub async fn list_pending_jobs(&self) -> Result<Vec<PendingJob>> {
+        let mut jobs = self
+            .sql
+            .query_map(
+                "SELECT msg_id, retries, next_try, last_error FROM smtp ORDER BY id",
+                (),
+                |row| {
+                    Ok(PendingJob {
+                        kind: JobKind::SendMsg,
+                        msg_id: row.get(0)?,
+                        retries: row.get(1)?,
+                        next_try: row.get(2)?,
+                        last_error: row.get(3)?,
+                    })
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        jobs.extend(
+            self.sql
+                .query_map(
+                    "SELECT msg_id, retries, next_try, last_error FROM download",
+                    (),
+                    |row| {
+                        Ok(PendingJob {
+                            kind: JobKind::Download,
+                            msg_id: row.get(0)?,
+                            retries: row.get(1)?,
+                            next_try: row.get(2)?,
+                            last_error: row.get(3)?,
+                        })
+                    },
+                    |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+                )
+                .await?,
+        );
+        jobs.extend(
+            self.sql
+                .query_map(
+                    "SELECT msg_id, retries, next_try, last_error FROM smtp_mdns ORDER BY retries",
+                    (),
+                    |row| {
+                        Ok(PendingJob {
+                            kind: JobKind::SendMdn,
+                            msg_id: row.get(0)?,
+                            retries: row.get(1)?,
+                            next_try: row.get(2)?,
+                            last_error: row.get(3)?,
+                        })
+                    },
+                    |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+                )
+                .await?,
+        );
+
+        // Jobs which are due run by priority, postponed jobs run once they become due.
+        // Stable sort keeps the queue order within a single kind.
+        let now = time();
+        jobs.sort_by_key(|job| (job.next_try.max(now), job.kind));
+        Ok(jobs)
+    }
+
+    /// Emits [`EventType::JobFailed`] for a job which is removed from the queue
+    /// without being done.
+    pub(crate) fn emit_job_failed(&self, kind: JobKind, msg_id: MsgId, error: &str) {
+        warn!(self, "{kind} job for {msg_id} failed permanently: {error}");
+        self.emit_event(EventType::JobFailed {
+            kind,
+            msg_id,
+            error: error.to_string(),
+        });
+    }
+}
+
+/// Returns the earliest timestamp at which a postponed SMTP or MDN job becomes due
+/// or a queued message expires, `None` if there are no postponed jobs.
+pub(crate) async fn next_postponed_job(context: &Context) -> Result<Option<i64>> {
+    let now = time();
+    let next_try = context
+        .sql
+        .query_get_value(
+            "SELECT MIN(next_try) FROM
+             (SELECT next_try FROM smtp WHERE next_try>?1
+              UNION ALL
+              SELECT deadline FROM smtp WHERE deadline>?1
+              UNION ALL
+              SELECT next_try FROM smtp_mdns WHERE next_try>?1)",
+            (now,),
+        )
+        .await?;
+    Ok(next_try)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat;
+    use crate::message::{Message, MessageState, Viewtype};
+    use crate::smtp::expire_smtp_messages;
+    use crate::stock_str;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_job_kind_priority() {
+        assert!(JobKind::SendMsg < JobKind::Download);
+        assert!(JobKind::Download < JobKind::SendMdn);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(JobKind::SendMsg.retry_delay(1), 30);
+        assert_eq!(JobKind::SendMsg.retry_delay(2), 60);
+        assert_eq!(JobKind::SendMsg.retry_delay(3), 120);
+        assert_eq!(JobKind::SendMdn.retry_delay(1), 60);
+        assert_eq!(JobKind::Download.retry_delay(1), 60);
+        assert_eq!(JobKind::SendMsg.retry_delay(100), MAX_RETRY_DELAY);
+        assert_eq!(JobKind::SendMsg.retry_delay(0), 30);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_list_pending_jobs() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        assert!(t.list_pending_jobs().await?.is_empty());
+
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+        let msg_id = chat::send_text_msg(&t, chat.id, "Hi".to_string()).await?;
+
+        let jobs = t.list_pending_jobs().await?;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].kind, JobKind::SendMsg);
+        assert_eq!(jobs[0].msg_id, msg_id);
+        assert_eq!(jobs[0].retries, 0);
+        assert_eq!(jobs[0].next_try, 0);
+        assert_eq!(next_postponed_job(&t).await?, None);
+
+        t.sql
+            .execute(
+                "INSERT INTO download (msg_id) VALUES (?)",
+                (MsgId::new(1234),),
+            )
+            .await?;
+        t.sql
+            .execute(
+                "UPDATE smtp SET retries=1, next_try=?, last_error='timeout'",
+                (time() + 100,),
+            )
+            .await?;
+        // The postponed message is sent after the download which is due.
+        let jobs = t.list_pending_jobs().await?;
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].kind, JobKind::Download);
+        assert_eq!(jobs[1].kind, JobKind::SendMsg);
+        assert_eq!(jobs[1].last_error, "timeout");
+        assert!(next_postponed_job(&t).await?.is_some());
+
+        t.sql.execute("UPDATE smtp SET next_try=0", ()).await?;
+        let jobs = t.list_pending_jobs().await?;
+        assert_eq!(jobs[0].kind, JobKind::SendMsg);
+        assert_eq!(jobs[1].kind, JobKind::Download);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_delivery_ttl() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("Your code is 123456".to_string());
+        msg.set_delivery_ttl(60);
+        let msg_id = chat::send_msg(&t, chat.id, &mut msg).await?;
+        let next_try = next_postponed_job(&t).await?.unwrap();
+        assert!(next_try > time() && next_try <= time() + 60);
+
+        // Not expired yet.
+        expire_smtp_messages(&t).await?;
+        assert_eq!(t.list_pending_jobs().await?.len(), 1);
+
+        t.sql
+            .execute("UPDATE smtp SET deadline=?", (time() - 1,))
+            .await?;
+        expire_smtp_messages(&t).await?;
+        assert!(t.list_pending_jobs().await?.is_empty());
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.state, MessageState::OutFailed);
+        assert_eq!(msg.error(), Some(stock_str::msg_delivery_expired(&t).await));
+        t.evtracker
+            .get_matching(|evt| matches!(evt, EventType::MsgFailed { .. }))
+            .await;
+
+        // Messages without TTL do not expire.
+        chat::send_text_msg(&t, chat.id, "Hi".to_string()).await?;
+        expire_smtp_messages(&t).await?;
+        assert_eq!(t.list_pending_jobs().await?.len(), 1);
+
+        Ok(())
+    }
+}
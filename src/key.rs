@@ -13,12 +13,22 @@
 use pgp::types::{KeyTrait, SecretKeyTrait};
 use tokio::runtime::Handle;
 
+use crate::chat::{self, ChatIdBlocked};
 use crate::config::Config;
-use crate::constants::KeyGenType;
+use crate::constants::{Blocked, KeyGenType};
+use crate::contact::ContactId;
 use crate::context::Context;
+use crate::log::LogExt;
+use crate::message::{Message, Viewtype};
+use crate::mimeparser::SystemMessage;
+use crate::param::Param;
 use crate::pgp::KeyPair;
 use crate::tools::{time, EmailAddress};
 
+/// Maximum time in seconds since a peer was last seen
+/// for it to get a key-transition notice from [`Context::rotate_key`].
+pub const KEY_TRANSITION_PEER_AGE: i64 = 90 * 24 * 60 * 60;
+
 /// Convenience trait for working with keys.
 ///
 /// This trait is implemented for rPGP's [SignedPublicKey] and
@@ -178,6 +188,30 @@ fn split_public_key(&self) -> Result<SignedPublicKey> {
     }
 }
 
+/// Loads all own secret keys for decryption, the default key first.
+///
+/// Keys replaced by [`Context::rotate_key`] are kept
+/// to decrypt messages that were encrypted to them.
+pub(crate) async fn load_self_secret_keyring(context: &Context) -> Result<Vec<SignedSecretKey>> {
+    let mut keyring = vec![load_self_secret_key(context).await?];
+    let old_keys = context
+        .sql
+        .query_map(
+            "SELECT private_key FROM keypairs WHERE is_default=0 ORDER BY id DESC",
+            (),
+            |row| row.get::<_, Vec<u8>>(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for bytes in old_keys {
+        keyring.push(SignedSecretKey::from_slice(&bytes)?);
+    }
+    Ok(keyring)
+}
+
 async fn generate_keypair(context: &Context) -> Result<KeyPair> {
     let addr = context.get_primary_self_addr().await?;
     let addr = EmailAddress::new(&addr)?;
@@ -239,6 +273,81 @@ pub(crate) async fn load_keypair(
     })
 }
 
+impl Context {
+    /// Replaces the own key with a newly generated one.
+    ///
+    /// The old key is kept to decrypt messages encrypted to it,
+    /// e.g. messages sent by peers that did not learn the new key yet.
+    ///
+    /// Contacts seen within the last [`KEY_TRANSITION_PEER_AGE`] seconds
+    /// get a hidden key-transition notice containing the new fingerprint signed by the old key,
+    /// so that they keep the contact verified if they verified the old key.
+    ///
+    /// Other devices using the same account would not get the new key
+    /// and could not decrypt messages encrypted to it anymore,
+    /// so the key is not rotated if other devices are known.
+    pub async fn rotate_key(&self) -> Result<()> {
+        ensure!(
+            !self.are_other_devices_known().await?,
+            "Cannot rotate the key while other devices use the account"
+        );
+        let old_secret = load_self_secret_key(self).await?;
+        let addr = EmailAddress::new(&self.get_primary_self_addr().await?)?;
+        let keytype = KeyGenType::from_i32(self.get_config_int(Config::KeyGenType).await?)
+            .unwrap_or_default();
+        let keypair = {
+            let _guard = self.generating_key_mutex.lock().await;
+            let keypair = Handle::current()
+                .spawn_blocking(move || crate::pgp::create_keypair(addr, keytype))
+                .await??;
+            store_self_keypair(self, &keypair, KeyPairUse::Default).await?;
+            keypair
+        };
+        let fingerprint = DcKey::fingerprint(&keypair.public).hex();
+        info!(self, "Rotated own key, new fingerprint is {fingerprint}.");
+
+        let statement = crate::pgp::pk_calc_signature(fingerprint.as_bytes(), &old_secret)?;
+        let statement = base64::engine::general_purpose::STANDARD.encode(statement);
+        let contact_ids = self
+            .sql
+            .query_map(
+                "SELECT c.id FROM contacts c
+                 INNER JOIN acpeerstates ps ON ps.addr=c.addr COLLATE NOCASE
+                 WHERE c.id>? AND c.blocked=0 AND ps.last_seen>?
+                 AND (ps.public_key IS NOT NULL OR ps.gossip_key IS NOT NULL)",
+                (ContactId::LAST_SPECIAL, time() - KEY_TRANSITION_PEER_AGE),
+                |row| row.get::<_, ContactId>(0),
+                |ids| {
+                    ids.collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await?;
+        for contact_id in contact_ids {
+            let Some(chat) = ChatIdBlocked::lookup_by_contact(self, contact_id).await? else {
+                continue;
+            };
+            if chat.blocked != Blocked::Not {
+                continue;
+            }
+            let mut msg = Message {
+                viewtype: Viewtype::Text,
+                hidden: true,
+                ..Default::default()
+            };
+            msg.param.set_cmd(SystemMessage::KeyTransition);
+            msg.param.set(Param::Arg, &statement);
+            msg.param.set_int(Param::GuaranteeE2ee, 1);
+            chat::send_msg(self, chat.id, &mut msg)
+                .await
+                .context("Failed to send key-transition notice")
+                .log_err(self)
+                .ok();
+        }
+        Ok(())
+    }
+}
+
 /// Use of a key pair for encryption or decryption.
 ///
 /// This is used by [store_self_keypair] to know what kind of key is
@@ -393,7 +502,9 @@ mod tests {
     use once_cell::sync::Lazy;
 
     use super::*;
-    use crate::test_utils::{alice_keypair, TestContext};
+    use crate::contact::{Contact, VerifiedStatus};
+    use crate::imex::{imex, ImexMode};
+    use crate::test_utils::{alice_keypair, TestContext, TestContextManager};
 
     static KEYPAIR: Lazy<KeyPair> = Lazy::new(alice_keypair);
 
@@ -639,4 +750,79 @@ fn test_fingerprint_to_string() {
             "0102 0408 1020 4080 FF01\n0204 0810 2040 80FF 1314"
         );
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rotate_key() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = alice.send_text(alice_chat.id, "Hi!").await;
+        bob.recv_msg(&sent).await;
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "Hello!").await;
+        alice.recv_msg(&sent).await;
+
+        let old_fingerprint = DcKey::fingerprint(&load_self_public_key(&alice).await?);
+        let contact = bob.add_or_lookup_contact(&alice).await;
+        contact.mark_verified(&bob, &old_fingerprint.hex()).await?;
+
+        // Bob encrypts this message to the old key.
+        let sent_before = bob.send_text(bob_chat.id, "Before rotation").await;
+
+        alice.rotate_key().await?;
+        let new_fingerprint = DcKey::fingerprint(&load_self_public_key(&alice).await?);
+        assert_ne!(new_fingerprint, old_fingerprint);
+        assert_eq!(load_self_secret_keyring(&alice).await?.len(), 2);
+        let notice = alice.pop_sent_msg().await;
+
+        let msg = alice.recv_msg(&sent_before).await;
+        assert_eq!(msg.get_text(), "Before rotation");
+
+        assert!(bob.recv_msg_opt(&notice).await.is_some());
+        let contact = Contact::get_by_id(&bob, contact.id).await?;
+        assert_eq!(
+            contact.get_fingerprint(&bob).await?,
+            Some(new_fingerprint.to_string())
+        );
+        assert_eq!(
+            contact.is_verified(&bob).await?,
+            VerifiedStatus::BidirectVerified
+        );
+        assert_eq!(
+            bob.get_last_msg_in(bob_chat.id).await.get_text(),
+            "Before rotation"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rotate_key_other_devices() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice1 = tcm.alice().await;
+        let alice2 = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let sent = alice2
+            .send_text(alice2.create_chat(&bob).await.id, "Hi")
+            .await;
+        alice1.recv_msg(&sent).await;
+        let fingerprint = DcKey::fingerprint(&load_self_public_key(&alice1).await?);
+        assert!(alice1.rotate_key().await.is_err());
+        assert_eq!(
+            DcKey::fingerprint(&load_self_public_key(&alice1).await?),
+            fingerprint
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rotate_key_after_backup_export() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let backup_dir = tempfile::tempdir()?;
+        imex(&alice, ImexMode::ExportBackup, backup_dir.path(), None).await?;
+        assert!(alice.rotate_key().await.is_err());
+        Ok(())
+    }
 }
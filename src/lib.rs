@@ -51,28 +51,38 @@
 pub use events::*;
 
 mod aheader;
+pub mod audit;
+mod badge;
 mod blob;
+pub mod bot;
 pub mod chat;
 pub mod chatlist;
 pub mod config;
 mod configure;
+pub use configure::{ConfigureStep, LoginCheck};
 pub mod constants;
 pub mod contact;
 pub mod context;
 mod decrypt;
+pub mod dkim;
 pub mod download;
 mod e2ee;
 pub mod ephemeral;
 mod imap;
 pub mod imex;
+pub mod job;
 pub mod key;
 pub mod location;
 mod login_param;
+pub mod markup;
 pub mod message;
+pub mod metrics;
 mod mimefactory;
 pub mod mimeparser;
+pub mod notification;
 pub mod oauth2;
 mod param;
+pub mod peer_channels;
 pub mod peerstate;
 mod pgp;
 pub mod provider;
@@ -85,24 +95,29 @@
 mod simplify;
 mod smtp;
 mod socks;
+pub mod spam;
 pub mod stock_str;
 mod sync;
 mod timesmearing;
 mod token;
 mod update_helper;
+pub mod webhook;
 pub mod webxdc;
 #[macro_use]
 mod dehtml;
 mod authres;
+mod avatar;
 mod color;
 pub mod html;
 pub mod net;
 pub mod plaintext;
+pub mod push;
 pub mod summary;
 
 mod debug_logging;
 pub mod receive_imf;
 pub mod tools;
+pub mod undo;
 
 pub mod accounts;
 pub mod reaction;
@@ -115,5 +130,8 @@
 #[cfg(test)]
 mod tests;
 
-#[cfg(fuzzing)]
+#[cfg(any(fuzzing, test, feature = "internals"))]
 pub mod fuzzing;
+
+#[cfg(any(test, feature = "internals"))]
+pub mod synthetic_db;
@@ -11,6 +11,7 @@
 use crate::chat::{self, ChatId};
 use crate::contact::ContactId;
 use crate::context::Context;
+use crate::e2ee;
 use crate::events::EventType;
 use crate::message::{Message, MsgId, Viewtype};
 use crate::mimeparser::SystemMessage;
@@ -38,8 +39,8 @@ pub struct Location {
     /// Contact ID.
     pub contact_id: ContactId,
 
-    /// Message ID.
-    pub msg_id: u32,
+    /// Message ID, unset if the location is not attached to a message.
+    pub msg_id: MsgId,
 
     /// Chat ID.
     pub chat_id: ChatId,
@@ -375,7 +376,7 @@ pub async fn set(context: &Context, latitude: f64, longitude: f64, accuracy: f64
 pub async fn get_range(
     context: &Context,
     chat_id: Option<ChatId>,
-    contact_id: Option<u32>,
+    contact_id: Option<ContactId>,
     timestamp_from: i64,
     mut timestamp_to: i64,
 ) -> Result<Vec<Location>> {
@@ -389,13 +390,14 @@ pub async fn get_range(
     };
     let (disable_contact_id, contact_id) = match contact_id {
         Some(contact_id) => (0, contact_id),
-        None => (1, 0), // this contact_id is unused
+        None => (1, ContactId::UNDEFINED), // this contact_id is unused
     };
     let list = context
         .sql
         .query_map(
             "SELECT l.id, l.latitude, l.longitude, l.accuracy, l.timestamp, l.independent, \
-             COALESCE(m.id, 0) AS msg_id, l.from_id, l.chat_id, COALESCE(m.txt, '') AS txt \
+             COALESCE(m.id, 0) AS msg_id, l.from_id, l.chat_id, COALESCE(m.txt, '') AS txt, \
+             COALESCE(m.encrypted_at_rest, 0) AS encrypted_at_rest \
              FROM locations l  LEFT JOIN msgs m ON l.id=m.location_id  WHERE (? OR l.chat_id=?) \
              AND (? OR l.from_id=?) \
              AND (l.independent=1 OR (l.timestamp>=? AND l.timestamp<=?)) \
@@ -404,14 +406,15 @@ pub async fn get_range(
                 disable_chat_id,
                 chat_id,
                 disable_contact_id,
-                contact_id as i32,
+                contact_id,
                 timestamp_from,
                 timestamp_to,
             ),
             |row| {
-                let msg_id = row.get(6)?;
+                let msg_id: MsgId = row.get(6)?;
                 let txt: String = row.get(9)?;
-                let marker = if msg_id != 0 && is_marker(&txt) {
+                let encrypted_at_rest: bool = row.get(10)?;
+                let marker = if !msg_id.is_unset() && !txt.is_empty() {
                     Some(txt)
                 } else {
                     None
@@ -428,7 +431,7 @@ pub async fn get_range(
                     chat_id: row.get(8)?,
                     marker,
                 };
-                Ok(loc)
+                Ok((loc, encrypted_at_rest))
             },
             |locations| {
                 let mut ret = Vec::new();
@@ -440,7 +443,33 @@ pub async fn get_range(
             },
         )
         .await?;
-    Ok(list)
+
+    let mut ret = Vec::with_capacity(list.len());
+    for (mut loc, encrypted_at_rest) in list {
+        if let Some(txt) = loc.marker.take() {
+            // The marker is the message text, which has to be decrypted
+            // before it can be checked, see `Config::EncryptAtRest`.
+            let txt = if encrypted_at_rest {
+                match e2ee::decrypt_text_at_rest(context, &txt).await {
+                    Ok(txt) => txt,
+                    Err(err) => {
+                        warn!(
+                            context,
+                            "Cannot decrypt stored message {}: {err:#}.", loc.msg_id
+                        );
+                        String::new()
+                    }
+                }
+            } else {
+                txt
+            };
+            if is_marker(&txt) {
+                loc.marker = Some(txt);
+            }
+        }
+        ret.push(loc);
+    }
+    Ok(ret)
 }
 
 fn is_marker(txt: &str) -> bool {
@@ -798,8 +827,9 @@ mod tests {
     #![allow(clippy::indexing_slicing)]
 
     use super::*;
+    use crate::config::Config;
     use crate::receive_imf::receive_imf;
-    use crate::test_utils::TestContext;
+    use crate::test_utils::{TestContext, TestContextManager};
 
     #[test]
     fn test_kml_parse() {
@@ -927,6 +957,33 @@ async fn receive_location_kml() -> Result<()> {
         Ok(())
     }
 
+    /// Tests that the marker of a message stored with `Config::EncryptAtRest` is returned.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_marker_encrypted_at_rest() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        bob.set_config_bool(Config::EncryptAtRest, true).await?;
+
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "Hi!").await;
+        alice.recv_msg(&sent).await;
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text("🏠".to_string());
+        msg.set_location(10.0, 20.0);
+        let sent = alice.send_msg(alice_chat.id, &mut msg).await;
+        let msg = bob.recv_msg(&sent).await;
+        assert!(msg.encrypted_at_rest);
+
+        let locations = get_range(&bob, None, None, 0, 0).await?;
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].msg_id, msg.id);
+        assert_eq!(locations[0].marker.as_deref(), Some("🏠"));
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_send_locations_to_chat() -> Result<()> {
         let alice = TestContext::new_alice().await;
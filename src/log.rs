@@ -2,21 +2,33 @@
 
 #![allow(missing_docs)]
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context as _, Result};
+
 use crate::context::Context;
+use crate::events::EventType;
+
+#[macro_export]
+macro_rules! debug {
+    ($ctx:expr,  $msg:expr) => {
+        debug!($ctx, $msg,)
+    };
+    ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {
+        $crate::log_at!($ctx, $crate::log::LogLevel::Debug, $msg, $($args),*)
+    };
+}
 
 #[macro_export]
 macro_rules! info {
     ($ctx:expr,  $msg:expr) => {
         info!($ctx, $msg,)
     };
-    ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
-        let formatted = format!($msg, $($args),*);
-        let full = format!("{file}:{line}: {msg}",
-                           file = file!(),
-                           line = line!(),
-                           msg = &formatted);
-        $ctx.emit_event($crate::EventType::Info(full));
-    }};
+    ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {
+        $crate::log_at!($ctx, $crate::log::LogLevel::Info, $msg, $($args),*)
+    };
 }
 
 #[macro_export]
@@ -24,14 +36,9 @@ macro_rules! warn {
     ($ctx:expr, $msg:expr) => {
         warn!($ctx, $msg,)
     };
-    ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
-        let formatted = format!($msg, $($args),*);
-        let full = format!("{file}:{line}: {msg}",
-                           file = file!(),
-                           line = line!(),
-                           msg = &formatted);
-        $ctx.emit_event($crate::EventType::Warning(full));
-    }};
+    ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {
+        $crate::log_at!($ctx, $crate::log::LogLevel::Warning, $msg, $($args),*)
+    };
 }
 
 #[macro_export]
@@ -42,10 +49,114 @@ macro_rules! error {
     ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
         let formatted = format!($msg, $($args),*);
         $ctx.set_last_error(&formatted);
-        $ctx.emit_event($crate::EventType::Error(formatted));
+        $ctx.log_entry($crate::log::LogLevel::Error, module_path!(), file!(), line!(), formatted);
+    }};
+}
+
+/// Logs a message at the given level if the log filter of the context allows it.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_at {
+    ($ctx:expr, $level:expr, $msg:expr, $($args:expr),* $(,)?) => {{
+        let ctx = &$ctx;
+        if ctx.log_enabled($level, module_path!()) {
+            let formatted = format!($msg, $($args),*);
+            ctx.log_entry($level, module_path!(), file!(), line!(), formatted);
+        }
     }};
 }
 
+/// Number of log lines kept in memory for [`Context::get_last_log_lines`].
+pub(crate) const LOG_BUFFER_SIZE: usize = 1000;
+
+/// Severity of a log entry.
+#[derive(
+    Debug, Display, EnumString, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum LogLevel {
+    /// Details only useful for debugging, never emitted as an event.
+    Debug,
+
+    /// Normal operation, emitted as [`EventType::Info`].
+    #[default]
+    Info,
+
+    /// Something went wrong but can be recovered, emitted as [`EventType::Warning`].
+    #[strum(to_string = "warning", serialize = "warn")]
+    Warning,
+
+    /// Something went wrong, emitted as [`EventType::Error`].
+    /// Errors are never filtered out.
+    Error,
+}
+
+/// Log filter deciding which entries are logged.
+///
+/// The filter is written as a comma-separated list of directives,
+/// similar to `RUST_LOG`, e.g. `info,imap=debug,sql=warning`.
+/// A directive without `=` sets the default level,
+/// `module=level` sets the level for a module and its submodules.
+/// Module names are given without the `deltachat::` prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LogFilter {
+    default: LogLevel,
+    modules: Vec<(String, LogLevel)>,
+}
+
+impl LogFilter {
+    /// Returns the minimum level of entries logged for the given module path.
+    pub fn level_for(&self, module_path: &str) -> LogLevel {
+        let module = module_path
+            .strip_prefix("deltachat::")
+            .unwrap_or(module_path);
+        self.modules
+            .iter()
+            .filter(|(name, _)| {
+                module == name
+                    || module
+                        .strip_prefix(name.as_str())
+                        .map_or(false, |rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// Returns true if an entry of the given level and module is logged.
+    pub fn enabled(&self, level: LogLevel, module_path: &str) -> bool {
+        level == LogLevel::Error || level >= self.level_for(module_path)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut filter = LogFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            if let Some((module, level)) = directive.split_once('=') {
+                let level = LogLevel::from_str(level.trim())
+                    .with_context(|| format!("Invalid log level {level:?}"))?;
+                filter.modules.push((module.trim().to_string(), level));
+            } else {
+                filter.default = LogLevel::from_str(directive)
+                    .with_context(|| format!("Invalid log level {directive:?}"))?;
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.default)?;
+        for (module, level) in &self.modules {
+            write!(f, ",{module}={level}")?;
+        }
+        Ok(())
+    }
+}
+
 impl Context {
     /// Set last error string.
     /// Implemented as blocking as used from macros in different, not always async blocks.
@@ -59,8 +170,76 @@ pub fn get_last_error(&self) -> String {
         let last_error = &*self.last_error.read().unwrap();
         last_error.clone()
     }
+
+    /// Returns true if an entry of the given level and module passes the log filter.
+    #[doc(hidden)]
+    pub fn log_enabled(&self, level: LogLevel, module_path: &str) -> bool {
+        self.log_filter
+            .read()
+            .expect("RwLock is poisoned")
+            .enabled(level, module_path)
+    }
+
+    /// Records a log entry and emits it as an event unless it is a debug entry.
+    ///
+    /// This is called by the logging macros and should not be used directly.
+    #[doc(hidden)]
+    pub fn log_entry(
+        &self,
+        level: LogLevel,
+        module_path: &str,
+        file: &str,
+        line: u32,
+        msg: String,
+    ) {
+        {
+            let mut buffer = self.log_buffer.lock().expect("Mutex is poisoned");
+            if buffer.len() >= LOG_BUFFER_SIZE {
+                buffer.pop_front();
+            }
+            buffer.push_back(format!(
+                "{timestamp} {level} {module_path} {file}:{line}: {msg}",
+                timestamp = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            ));
+        }
+
+        let event = match level {
+            LogLevel::Debug => return,
+            LogLevel::Info => EventType::Info(format!("{file}:{line}: {msg}")),
+            LogLevel::Warning => EventType::Warning(format!("{file}:{line}: {msg}")),
+            LogLevel::Error => EventType::Error(msg),
+        };
+        self.emit_event(event);
+    }
+
+    /// Returns the current log filter.
+    pub fn get_log_filter(&self) -> LogFilter {
+        self.log_filter.read().expect("RwLock is poisoned").clone()
+    }
+
+    /// Sets the log filter used until the context is dropped.
+    ///
+    /// To persist the filter, set [`Config::LogFilter`] instead.
+    ///
+    /// [`Config::LogFilter`]: crate::config::Config::LogFilter
+    pub fn set_log_filter(&self, filter: LogFilter) {
+        *self.log_filter.write().expect("RwLock is poisoned") = filter;
+    }
+
+    /// Returns up to `count` most recent log lines, oldest first.
+    ///
+    /// The lines are kept in memory only, at most the last 1000 lines are available.
+    /// This is meant to be attached to bug reports.
+    pub fn get_last_log_lines(&self, count: usize) -> Vec<String> {
+        let buffer = self.log_buffer.lock().expect("Mutex is poisoned");
+        let skip = buffer.len().saturating_sub(count);
+        buffer.iter().skip(skip).cloned().collect()
+    }
 }
 
+/// Ring buffer of the most recent log lines.
+pub(crate) type LogBuffer = std::sync::Mutex<VecDeque<String>>;
+
 pub trait LogExt<T, E>
 where
     Self: std::marker::Sized,
@@ -86,15 +265,15 @@ fn log_err(self, context: &Context) -> Result<T, E> {
             let location = std::panic::Location::caller();
 
             // We are using Anyhow's .context() and to show the inner error, too, we need the {:#}:
-            let full = format!(
-                "{file}:{line}: {e:#}",
-                file = location.file(),
-                line = location.line(),
-                e = e
-            );
             // We can't use the warn!() macro here as the file!() and line!() macros
             // don't work with #[track_caller]
-            context.emit_event(crate::EventType::Warning(full));
+            context.log_entry(
+                LogLevel::Warning,
+                "",
+                location.file(),
+                location.line(),
+                format!("{e:#}"),
+            );
         };
         self
     }
@@ -104,6 +283,8 @@ fn log_err(self, context: &Context) -> Result<T, E> {
 mod tests {
     use anyhow::Result;
 
+    use super::*;
+    use crate::config::Config;
     use crate::test_utils::TestContext;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -127,4 +308,56 @@ async fn test_get_last_error() -> Result<()> {
 
         Ok(())
     }
+
+    #[test]
+    fn test_log_filter() -> Result<()> {
+        let filter: LogFilter = "warning, imap=debug ,imap::idle=error".parse()?;
+        assert_eq!(filter.level_for("deltachat::chat"), LogLevel::Warning);
+        assert_eq!(filter.level_for("deltachat::imap"), LogLevel::Debug);
+        assert_eq!(
+            filter.level_for("deltachat::imap::session"),
+            LogLevel::Debug
+        );
+        assert_eq!(filter.level_for("deltachat::imap::idle"), LogLevel::Error);
+        assert_eq!(filter.level_for("deltachat::imapx"), LogLevel::Warning);
+        assert!(!filter.enabled(LogLevel::Info, "deltachat::chat"));
+        assert!(filter.enabled(LogLevel::Error, "deltachat::imap::idle"));
+        assert_eq!(filter.to_string(), "warning,imap=debug,imap::idle=error");
+
+        assert_eq!("".parse::<LogFilter>()?, LogFilter::default());
+        assert_eq!("warn".parse::<LogFilter>()?.default, LogLevel::Warning);
+        assert!("verbose".parse::<LogFilter>().is_err());
+        assert!("imap=verbose".parse::<LogFilter>().is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_log_filter_config() -> Result<()> {
+        let t = TestContext::new().await;
+        debug!(t, "hidden-debug");
+        assert!(!t
+            .get_last_log_lines(LOG_BUFFER_SIZE)
+            .iter()
+            .any(|line| line.contains("hidden-debug")));
+
+        t.set_config(Config::LogFilter, Some("info,log=debug"))
+            .await?;
+        debug!(t, "visible-debug");
+        info!(t, "some-info");
+        let lines = t.get_last_log_lines(2);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(" debug deltachat::log "));
+        assert!(lines[0].ends_with(": visible-debug"));
+        assert!(lines[1].ends_with(": some-info"));
+
+        assert!(t.set_config(Config::LogFilter, Some("foo")).await.is_err());
+        assert_eq!(t.get_log_filter().to_string(), "info,log=debug");
+
+        t.set_config(Config::LogFilter, Some("error")).await?;
+        warn!(t, "filtered-warning");
+        error!(t, "unfiltered-error");
+        let lines = t.get_last_log_lines(1);
+        assert!(lines[0].ends_with(": unfiltered-error"));
+        Ok(())
+    }
 }
@@ -155,13 +155,20 @@ async fn from_database(context: &Context, prefix: &str) -> Result<Self> {
         let server_flags = sql.get_raw_config_int(key).await?.unwrap_or_default();
         let oauth2 = matches!(server_flags & DC_LP_AUTH_FLAGS, DC_LP_AUTH_OAUTH2);
 
+        // Older configurations use the same OAuth2 flag for IMAP and SMTP.
+        let key = &format!("{prefix}smtp_oauth2");
+        let smtp_oauth2 = sql
+            .get_raw_config_int(key)
+            .await?
+            .map_or(oauth2, |smtp_oauth2| smtp_oauth2 != 0);
+
         let key = &format!("{prefix}provider");
         let provider = sql
             .get_raw_config(key)
             .await?
             .and_then(|provider_id| get_provider_by_id(&provider_id));
 
-        let socks5_config = Socks5Config::from_database(&context.sql).await?;
+        let socks5_config = Socks5Config::from_context(context).await?;
 
         Ok(LoginParam {
             addr,
@@ -180,7 +187,7 @@ async fn from_database(context: &Context, prefix: &str) -> Result<Self> {
                 password: send_pw,
                 port: send_port as u16,
                 security: send_security,
-                oauth2,
+                oauth2: smtp_oauth2,
                 certificate_checks: smtp_certificate_checks,
             },
             provider,
@@ -237,7 +244,7 @@ pub async fn save_as_configured_params(&self, context: &Context) -> Result<()> {
         sql.set_raw_config_int(key, self.smtp.certificate_checks as i32)
             .await?;
 
-        // The OAuth2 flag is either set for both IMAP and SMTP or not at all.
+        // `server_flags` contains the IMAP OAuth2 flag.
         let key = &format!("{prefix}server_flags");
         let server_flags = match self.imap.oauth2 {
             true => DC_LP_AUTH_OAUTH2,
@@ -245,6 +252,10 @@ pub async fn save_as_configured_params(&self, context: &Context) -> Result<()> {
         };
         sql.set_raw_config_int(key, server_flags).await?;
 
+        let key = &format!("{prefix}smtp_oauth2");
+        sql.set_raw_config_int(key, i32::from(self.smtp.oauth2))
+            .await?;
+
         if let Some(provider) = self.provider {
             let key = &format!("{prefix}provider");
             sql.set_raw_config(key, Some(provider.id)).await?;
@@ -308,6 +319,7 @@ fn unset_empty(s: &str) -> &str {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
     use crate::test_utils::TestContext;
 
     #[test]
@@ -355,4 +367,36 @@ async fn test_save_load_login_param() -> Result<()> {
         assert_eq!(param, loaded);
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_independent_smtp_oauth2() -> Result<()> {
+        let t = TestContext::new().await;
+        t.set_config(Config::Addr, Some("alice@example.org"))
+            .await?;
+        t.set_config(Config::MailPw, Some("foo")).await?;
+
+        // Older configurations only have `server_flags`.
+        t.set_config(Config::ServerFlags, Some(&DC_LP_AUTH_OAUTH2.to_string()))
+            .await?;
+        let param = LoginParam::load_candidate_params(&t).await?;
+        assert!(param.imap.oauth2);
+        assert!(param.smtp.oauth2);
+
+        t.set_config_bool(Config::SmtpOauth2, false).await?;
+        t.set_config(Config::SendUser, Some("submission-user"))
+            .await?;
+        t.set_config(Config::SendPw, Some("bar")).await?;
+        let param = LoginParam::load_candidate_params(&t).await?;
+        assert!(param.imap.oauth2);
+        assert!(!param.smtp.oauth2);
+        assert_eq!(param.imap.password, "foo");
+        assert_eq!(param.smtp.user, "submission-user");
+        assert_eq!(param.smtp.password, "bar");
+
+        param.save_as_configured_params(&t).await?;
+        let loaded = LoginParam::load_configured_params(&t).await?;
+        assert!(loaded.imap.oauth2);
+        assert!(!loaded.smtp.oauth2);
+        Ok(())
+    }
 }
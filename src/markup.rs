@@ -0,0 +1,390 @@
+//! # Rich text markup.
+//!
+//! Messages may contain a minimal markup subset:
+//! `*bold*`, `_italic_`, `~strikethrough~` and `` `code` ``.
+//! The markup is parsed once by the core and stored as ranges on the message,
+//! so that all UIs render the formatting the same way.
+//!
+//! The markers stay in the message text, so the text is still readable
+//! by clients not supporting the markup.
+//! A marker preceded by a backslash, e.g. `\*`, is not parsed as markup.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::Serialize;
+
+/// Style of a [`MarkupRange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkupStyle {
+    /// `*bold*`
+    Bold,
+
+    /// `_italic_`
+    Italic,
+
+    /// `~strikethrough~`
+    Strikethrough,
+
+    /// `` `code` ``, the text inside is not parsed for other markup.
+    Code,
+}
+
+impl MarkupStyle {
+    fn from_marker(marker: char) -> Option<Self> {
+        match marker {
+            '*' => Some(Self::Bold),
+            '_' => Some(Self::Italic),
+            '~' => Some(Self::Strikethrough),
+            '`' => Some(Self::Code),
+            _ => None,
+        }
+    }
+
+    /// Returns the character enclosing text of this style.
+    pub fn marker(self) -> char {
+        match self {
+            Self::Bold => '*',
+            Self::Italic => '_',
+            Self::Strikethrough => '~',
+            Self::Code => '`',
+        }
+    }
+}
+
+/// Formatted part of a message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MarkupRange {
+    /// Style of the text.
+    pub style: MarkupStyle,
+
+    /// Index of the first formatted character.
+    ///
+    /// Indices count Unicode scalar values, not bytes.
+    /// The marker is the character right before `start`.
+    pub start: usize,
+
+    /// Index after the last formatted character.
+    ///
+    /// The marker is the character at `end`.
+    /// UIs rendering the formatting should hide both markers.
+    pub end: usize,
+}
+
+impl fmt::Display for MarkupRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}-{}", self.style.marker(), self.start, self.end)
+    }
+}
+
+/// Part of a message text with an optional style,
+/// used to build a text with markup by [`Message::set_text_with_markup`].
+///
+/// [`Message::set_text_with_markup`]: crate::message::Message::set_text_with_markup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkupSpan {
+    /// The text.
+    pub text: String,
+
+    /// Style of the text, `None` for plain text.
+    pub style: Option<MarkupStyle>,
+}
+
+/// Parses the markup of a message text.
+///
+/// Markers must enclose non-empty text on a single line,
+/// must not be surrounded by whitespace on the inner side
+/// and must not be next to letters or digits on the outer side,
+/// so that e.g. `snake_case_names` are not formatted.
+/// Markers escaped with a backslash are ignored.
+pub fn parse_markup(text: &str) -> Vec<MarkupRange> {
+    let chars: Vec<char> = text.chars().collect();
+    let char_at = |i: usize| chars.get(i).copied();
+    let is_escaped = |i: usize| {
+        let backslashes = (0..i)
+            .rev()
+            .take_while(|&j| char_at(j) == Some('\\'))
+            .count();
+        backslashes % 2 == 1
+    };
+    let is_outer_boundary = |c: Option<char>| c.map_or(true, |c| !c.is_alphanumeric());
+    let is_inner_boundary =
+        |c: Option<char>, marker: char| c.map_or(false, |c| !c.is_whitespace() && c != marker);
+
+    let mut ranges = Vec::new();
+    let mut closers = BTreeSet::new();
+    let mut i = 0;
+    while let Some(marker) = char_at(i) {
+        let Some(style) = MarkupStyle::from_marker(marker) else {
+            i += 1;
+            continue;
+        };
+        let can_open = !closers.contains(&i)
+            && !is_escaped(i)
+            && is_outer_boundary(i.checked_sub(1).and_then(char_at))
+            && is_inner_boundary(char_at(i + 1), marker);
+        if !can_open {
+            i += 1;
+            continue;
+        }
+
+        let closer = (i + 2..chars.len())
+            .take_while(|&j| char_at(j) != Some('\n'))
+            .find(|&j| {
+                char_at(j) == Some(marker)
+                    && !is_escaped(j)
+                    && is_inner_boundary(char_at(j - 1), marker)
+                    && is_outer_boundary(char_at(j + 1))
+            });
+        let Some(closer) = closer else {
+            i += 1;
+            continue;
+        };
+        ranges.push(MarkupRange {
+            style,
+            start: i + 1,
+            end: closer,
+        });
+        if style == MarkupStyle::Code {
+            i = closer + 1;
+        } else {
+            // Other markup may be nested inside.
+            closers.insert(closer);
+            i += 1;
+        }
+    }
+    ranges
+}
+
+/// Builds a message text with markup from `spans`.
+///
+/// Whitespace at the start and the end of a styled span is moved outside of the markers,
+/// so that the markup is parsed back by [`parse_markup`].
+/// Marker characters in the text are escaped, so that they are not parsed as markup,
+/// except inside of code which is not parsed anyway.
+pub fn build_markup_text(spans: &[MarkupSpan]) -> String {
+    let mut text = String::new();
+    for span in spans {
+        let Some(style) = span.style else {
+            text += &escape_markup(&span.text);
+            continue;
+        };
+        let inner = span.text.trim();
+        if inner.is_empty() {
+            text += &escape_markup(&span.text);
+            continue;
+        }
+        let leading = span.text.len() - span.text.trim_start().len();
+        let trailing = span.text.trim_end().len();
+        text += span.text.get(..leading).unwrap_or_default();
+        text.push(style.marker());
+        if style == MarkupStyle::Code {
+            text += inner;
+        } else {
+            text += &escape_markup(inner);
+        }
+        text.push(style.marker());
+        text += span.text.get(trailing..).unwrap_or_default();
+    }
+    text
+}
+
+/// Escapes marker characters with a backslash.
+///
+/// Backslashes before a marker or at the end of the text are doubled,
+/// so that they do not escape the marker.
+fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut backslashes = 0;
+    for c in text.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let is_marker = MarkupStyle::from_marker(c).is_some();
+        let backslashes = std::mem::take(&mut backslashes);
+        let count = if is_marker {
+            2 * backslashes + 1
+        } else {
+            backslashes
+        };
+        escaped.extend(std::iter::repeat('\\').take(count));
+        escaped.push(c);
+    }
+    escaped.extend(std::iter::repeat('\\').take(2 * backslashes));
+    escaped
+}
+
+/// Serializes the ranges to be stored in [`Param::Markup`](crate::param::Param::Markup).
+pub(crate) fn ranges_to_param(ranges: &[MarkupRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| range.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses ranges stored by [`ranges_to_param`], skipping invalid ones.
+pub(crate) fn ranges_from_param(param: &str) -> Vec<MarkupRange> {
+    param
+        .split_ascii_whitespace()
+        .filter_map(|range| {
+            let mut chars = range.chars();
+            let style = MarkupStyle::from_marker(chars.next()?)?;
+            let (start, end) = chars.as_str().split_once('-')?;
+            Some(MarkupRange {
+                style,
+                start: start.parse().ok()?,
+                end: end.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Message, Viewtype};
+    use crate::test_utils::TestContextManager;
+
+    fn styled(text: &str) -> Vec<(MarkupStyle, String)> {
+        let chars: Vec<char> = text.chars().collect();
+        parse_markup(text)
+            .into_iter()
+            .map(|range| (range.style, chars[range.start..range.end].iter().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_markup() {
+        use MarkupStyle::{Bold, Code, Italic, Strikethrough};
+
+        assert_eq!(
+            styled("*bold* _italic_ ~strike~ `code`"),
+            vec![
+                (Bold, "bold".to_string()),
+                (Italic, "italic".to_string()),
+                (Strikethrough, "strike".to_string()),
+                (Code, "code".to_string())
+            ]
+        );
+        assert_eq!(
+            styled("Über *fett und _kursiv_*!"),
+            vec![
+                (Bold, "fett und _kursiv_".to_string()),
+                (Italic, "kursiv".to_string())
+            ]
+        );
+        assert_eq!(
+            styled("`*not bold*` *bold*"),
+            vec![(Code, "*not bold*".to_string()), (Bold, "bold".to_string())]
+        );
+
+        assert!(parse_markup("snake_case_name").is_empty());
+        assert!(parse_markup("2 * 3 * 4").is_empty());
+        assert!(parse_markup("**").is_empty());
+        assert!(parse_markup("*multi\nline*").is_empty());
+        assert!(parse_markup("* not bold*").is_empty());
+        assert!(parse_markup("~/path and ~user").is_empty());
+        assert!(parse_markup("\\*not bold*").is_empty());
+        assert!(parse_markup("*not bold\\*").is_empty());
+        assert_eq!(styled("\\\\*bold*"), vec![(Bold, "bold".to_string())]);
+    }
+
+    #[test]
+    fn test_build_markup_text() {
+        let spans = [
+            MarkupSpan {
+                text: "Hello".to_string(),
+                style: None,
+            },
+            MarkupSpan {
+                text: " world ".to_string(),
+                style: Some(MarkupStyle::Bold),
+            },
+            MarkupSpan {
+                text: "and code".to_string(),
+                style: Some(MarkupStyle::Code),
+            },
+        ];
+        let text = build_markup_text(&spans);
+        assert_eq!(text, "Hello *world* `and code`");
+        assert_eq!(
+            styled(&text),
+            vec![
+                (MarkupStyle::Bold, "world".to_string()),
+                (MarkupStyle::Code, "and code".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_markup_text_escaped() {
+        let spans = [
+            MarkupSpan {
+                text: "*not bold* and C:\\".to_string(),
+                style: None,
+            },
+            MarkupSpan {
+                text: "_bold_".to_string(),
+                style: Some(MarkupStyle::Bold),
+            },
+            MarkupSpan {
+                text: "*code*".to_string(),
+                style: Some(MarkupStyle::Code),
+            },
+        ];
+        let text = build_markup_text(&spans);
+        assert_eq!(text, "\\*not bold\\* and C:\\\\*\\_bold\\_*`*code*`");
+        assert_eq!(
+            styled(&text),
+            vec![
+                (MarkupStyle::Bold, "\\_bold\\_".to_string()),
+                (MarkupStyle::Code, "*code*".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_send_markup() -> anyhow::Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let alice_chat = alice.create_chat(&bob).await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text_with_markup(&[
+            MarkupSpan {
+                text: "Say ".to_string(),
+                style: None,
+            },
+            MarkupSpan {
+                text: "hello".to_string(),
+                style: Some(MarkupStyle::Bold),
+            },
+        ]);
+        let sent = alice.send_msg(alice_chat.id, &mut msg).await;
+        let expected = vec![MarkupRange {
+            style: MarkupStyle::Bold,
+            start: 5,
+            end: 10,
+        }];
+        let msg = Message::load_from_db(&alice, sent.sender_msg_id).await?;
+        assert_eq!(msg.get_text(), "Say *hello*");
+        assert_eq!(msg.get_markup(), expected);
+
+        let msg = bob.recv_msg(&sent).await;
+        assert_eq!(msg.get_markup(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ranges_param() {
+        let ranges = parse_markup("*a* _bc_");
+        let param = ranges_to_param(&ranges);
+        assert_eq!(param, "*1-2 _5-7");
+        assert_eq!(ranges_from_param(&param), ranges);
+        assert!(ranges_from_param("x1-2 *a-b").is_empty());
+    }
+}
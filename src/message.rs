@@ -5,32 +5,40 @@
 
 use anyhow::{ensure, format_err, Context as _, Result};
 use deltachat_derive::{FromSql, ToSql};
+use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
+use crate::audit;
 use crate::blob::BlobObject;
 use crate::chat::{Chat, ChatId};
 use crate::config::Config;
 use crate::constants::{
     Blocked, Chattype, VideochatType, DC_CHAT_ID_TRASH, DC_DESIRED_TEXT_LEN, DC_MSG_ID_LAST_SPECIAL,
 };
-use crate::contact::{Contact, ContactId};
+use crate::contact::{Contact, ContactId, Origin};
 use crate::context::Context;
 use crate::debug_logging::set_debug_logging_xdc;
+use crate::dkim::DkimStatus;
 use crate::download::DownloadState;
+use crate::e2ee;
 use crate::ephemeral::{start_ephemeral_timers_msgids, Timer as EphemeralTimer};
 use crate::events::EventType;
 use crate::imap::markseen_on_imap_table;
+use crate::markup::{self, MarkupRange, MarkupSpan};
+use crate::mimefactory;
 use crate::mimeparser::{parse_message_id, SystemMessage};
 use crate::param::{Param, Params};
 use crate::pgp::split_armored_data;
 use crate::reaction::get_msg_reactions;
 use crate::scheduler::InterruptInfo;
 use crate::sql;
+use crate::stock_str;
 use crate::summary::Summary;
 use crate::tools::{
-    buf_compress, buf_decompress, get_filebytes, get_filemeta, gm2local_offset, read_file, time,
-    timestamp_to_str, truncate,
+    buf_compress, buf_decompress, get_filebytes, get_filemeta, gm2local_offset,
+    is_animated_image_from_path, read_file, time, timestamp_to_str, truncate, write_file,
 };
+use crate::undo;
 
 /// Message ID, including reserved IDs.
 ///
@@ -43,8 +51,14 @@
 pub struct MsgId(u32);
 
 impl MsgId {
+    /// Virtual message ID of day markers in lists of chat items.
+    pub const DAYMARKER: MsgId = MsgId::new(9);
+
+    /// Larger message IDs are "real" messages, see [MsgId::is_special].
+    pub const LAST_SPECIAL: MsgId = MsgId::new(9);
+
     /// Create a new [MsgId].
-    pub fn new(id: u32) -> MsgId {
+    pub const fn new(id: u32) -> MsgId {
         MsgId(id)
     }
 
@@ -57,7 +71,7 @@ pub fn new_unset() -> MsgId {
     ///
     /// This kind of message ID can not be used for real messages.
     pub fn is_special(self) -> bool {
-        self.0 <= DC_MSG_ID_LAST_SPECIAL
+        self <= Self::LAST_SPECIAL
     }
 
     /// Whether the message ID is unset.
@@ -101,7 +115,7 @@ pub async fn trash(self, context: &Context) -> Result<()> {
 SET 
   chat_id=?, txt='', 
   subject='', txt_raw='', 
-  mime_headers='', 
+  mime_headers='', html='',
   from_id=0, to_id=0, 
   param='' 
 WHERE id=?;
@@ -135,6 +149,7 @@ pub(crate) async fn set_delivered(self, context: &Context) -> Result<()> {
             .query_get_value("SELECT chat_id FROM msgs WHERE id=?", (self,))
             .await?
             .unwrap_or_default();
+        chat_id.set_last_error(context, "").await?;
         context.emit_event(EventType::MsgDelivered {
             chat_id,
             msg_id: self,
@@ -146,7 +161,7 @@ pub(crate) async fn set_delivered(self, context: &Context) -> Result<()> {
     ///
     /// Avoid using this, eventually types should be cleaned up enough
     /// that it is no longer necessary.
-    pub fn to_u32(self) -> u32 {
+    pub const fn to_u32(self) -> u32 {
         self.0
     }
 
@@ -160,11 +175,17 @@ pub async fn get_info(self, context: &Context) -> Result<String> {
 
         let mut ret = String::new();
 
-        if rawtxt.is_none() {
+        let Some(rawtxt) = rawtxt else {
             ret += &format!("Cannot load message {self}.");
             return Ok(ret);
-        }
-        let rawtxt = rawtxt.unwrap_or_default();
+        };
+        let rawtxt = if msg.encrypted_at_rest {
+            e2ee::decrypt_text_at_rest(context, &rawtxt)
+                .await
+                .unwrap_or_default()
+        } else {
+            rawtxt
+        };
         let rawtxt = truncate(rawtxt.trim(), DC_DESIRED_TEXT_LEN);
 
         let fts = timestamp_to_str(msg.get_timestamp());
@@ -188,6 +209,12 @@ pub async fn get_info(self, context: &Context) -> Result<String> {
             ret += "\n";
         }
 
+        if msg.timestamp_server != 0 {
+            let s = timestamp_to_str(msg.timestamp_server);
+            ret += &format!("Server: {}", &s);
+            ret += "\n";
+        }
+
         if let EphemeralTimer::Enabled { duration } = msg.ephemeral_timer {
             ret += &format!("Ephemeral timer: {duration}\n");
         }
@@ -312,6 +339,18 @@ pub async fn get_info(self, context: &Context) -> Result<String> {
         ret += "\n\n";
         ret += &hop_info.unwrap_or_else(|| "No Hop Info".to_owned());
 
+        let audit_trail = audit::get_msg_audit_trail(context, self).await?;
+        if !audit_trail.is_empty() {
+            ret += "\n\nAudit trail:";
+            for entry in audit_trail {
+                ret += &format!(
+                    "\n{}: {}",
+                    timestamp_to_str(entry.timestamp),
+                    entry.operation
+                );
+            }
+        }
+
         Ok(ret)
     }
 }
@@ -332,7 +371,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 /// the database and the conversion will fail if this is not the case.
 impl rusqlite::types::ToSql for MsgId {
     fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput> {
-        if self.0 <= DC_MSG_ID_LAST_SPECIAL {
+        if self.is_special() {
             return Err(rusqlite::Error::ToSqlConversionFailure(
                 format_err!("Invalid MsgId {}", self.0).into(),
             ));
@@ -357,6 +396,22 @@ fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlRe
     }
 }
 
+/// Converts a raw message ID, e.g. passed over the FFI, into a [MsgId].
+///
+/// The ID may be a special one, check it with [MsgId::is_special] where needed.
+impl From<u32> for MsgId {
+    fn from(id: u32) -> Self {
+        MsgId::new(id)
+    }
+}
+
+/// Converts a [MsgId] into its raw value, e.g. to pass it over the FFI.
+impl From<MsgId> for u32 {
+    fn from(msg_id: MsgId) -> Self {
+        msg_id.0
+    }
+}
+
 #[derive(
     Debug,
     Copy,
@@ -413,6 +468,7 @@ pub struct Message {
     pub(crate) timestamp_sort: i64,
     pub(crate) timestamp_sent: i64,
     pub(crate) timestamp_rcvd: i64,
+    pub(crate) timestamp_server: i64,
     pub(crate) ephemeral_timer: EphemeralTimer,
     pub(crate) ephemeral_timestamp: i64,
     pub(crate) text: String,
@@ -433,6 +489,10 @@ pub struct Message {
     pub(crate) location_id: u32,
     pub(crate) error: Option<String>,
     pub(crate) param: Params,
+
+    /// Whether the texts are stored encrypted in the database,
+    /// see [`Config::EncryptAtRest`].
+    pub(crate) encrypted_at_rest: bool,
 }
 
 impl Message {
@@ -451,7 +511,7 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
             "Can not load special message ID {} from DB",
             id
         );
-        let msg = context
+        let mut msg = context
             .sql
             .query_row(
                 concat!(
@@ -465,6 +525,7 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
                     "    m.timestamp AS timestamp,",
                     "    m.timestamp_sent AS timestamp_sent,",
                     "    m.timestamp_rcvd AS timestamp_rcvd,",
+                    "    m.timestamp_server AS timestamp_server,",
                     "    m.ephemeral_timer AS ephemeral_timer,",
                     "    m.ephemeral_timestamp AS ephemeral_timestamp,",
                     "    m.type AS type,",
@@ -478,6 +539,7 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
                     "    m.param AS param,",
                     "    m.hidden AS hidden,",
                     "    m.location_id AS location,",
+                    "    m.encrypted_at_rest AS encrypted_at_rest,",
                     "    c.blocked AS blocked",
                     " FROM msgs m LEFT JOIN chats c ON c.id=m.chat_id",
                     " WHERE m.id=?;"
@@ -515,6 +577,7 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
                         timestamp_sort: row.get("timestamp")?,
                         timestamp_sent: row.get("timestamp_sent")?,
                         timestamp_rcvd: row.get("timestamp_rcvd")?,
+                        timestamp_server: row.get("timestamp_server")?,
                         ephemeral_timer: row.get("ephemeral_timer")?,
                         ephemeral_timestamp: row.get("ephemeral_timestamp")?,
                         viewtype: row.get("type")?,
@@ -529,6 +592,7 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
                         param: row.get::<_, String>("param")?.parse().unwrap_or_default(),
                         hidden: row.get("hidden")?,
                         location_id: row.get("location")?,
+                        encrypted_at_rest: row.get("encrypted_at_rest")?,
                         chat_blocked: row
                             .get::<_, Option<Blocked>>("blocked")?
                             .unwrap_or_default(),
@@ -539,9 +603,45 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
             .await
             .with_context(|| format!("failed to load message {id} from the database"))?;
 
+        if msg.encrypted_at_rest {
+            if let Err(err) = msg.decrypt_at_rest(context).await {
+                warn!(context, "Cannot decrypt stored message {id}: {err:#}.");
+                let msg_body = stock_str::cant_decrypt_msg_body(context).await;
+                msg.text = format!("[{msg_body}]");
+                msg.subject = String::new();
+                msg.param.remove(Param::Quote);
+            }
+        }
+
         Ok(msg)
     }
 
+    /// Decrypts the texts stored with [`Config::EncryptAtRest`].
+    async fn decrypt_at_rest(&mut self, context: &Context) -> Result<()> {
+        self.text = e2ee::decrypt_text_at_rest(context, &self.text).await?;
+        self.subject = e2ee::decrypt_text_at_rest(context, &self.subject).await?;
+        if let Some(quote) = self.param.get(Param::Quote) {
+            let quote = e2ee::decrypt_text_at_rest(context, quote).await?;
+            self.param.set(Param::Quote, quote);
+        }
+        Ok(())
+    }
+
+    /// Returns the params as stored in the database,
+    /// with the quote encrypted again if the message is stored encrypted.
+    async fn stored_param(&self, context: &Context) -> Result<String> {
+        let mut param = self.param.clone();
+        if self.encrypted_at_rest {
+            if let Some(quote) = self.param.get(Param::Quote) {
+                param.set(
+                    Param::Quote,
+                    e2ee::encrypt_text_at_rest(context, quote).await?,
+                );
+            }
+        }
+        Ok(param.to_string())
+    }
+
     /// Returns the MIME type of an attached file if it exists.
     ///
     /// If the MIME type is not known, the function guesses the MIME type
@@ -567,11 +667,25 @@ pub fn get_file(&self, context: &Context) -> Option<PathBuf> {
         self.param.get_path(Param::File, context).unwrap_or(None)
     }
 
-    /// If message is an image or gif, set Param::Width and Param::Height
+    /// If message is an image or gif, set Param::Width and Param::Height;
+    /// for stickers as well, set Param::Animated.
     pub(crate) async fn try_calc_and_set_dimensions(&mut self, context: &Context) -> Result<()> {
         if self.viewtype.has_file() {
             let file_param = self.param.get_path(Param::File, context)?;
             if let Some(path_and_filename) = file_param {
+                if matches!(
+                    self.viewtype,
+                    Viewtype::Image | Viewtype::Gif | Viewtype::Sticker
+                ) && !self.param.exists(Param::Animated)
+                {
+                    if is_animated_image_from_path(&path_and_filename).unwrap_or_default() {
+                        self.param.set_int(Param::Animated, 1);
+                        if !self.id.is_unset() {
+                            self.update_param(context).await?;
+                        }
+                    }
+                }
+
                 if (self.viewtype == Viewtype::Image || self.viewtype == Viewtype::Gif)
                     && !self.param.exists(Param::Width)
                 {
@@ -680,6 +794,15 @@ pub fn get_received_timestamp(&self) -> i64 {
         self.timestamp_rcvd
     }
 
+    /// Returns the time the message arrived on the IMAP server
+    /// as a unix timestamp in seconds, or 0 if unknown.
+    ///
+    /// Unlike [`Message::get_timestamp`], which uses the `Date` header,
+    /// this does not depend on the clock of the sender.
+    pub fn get_server_timestamp(&self) -> i64 {
+        self.timestamp_server
+    }
+
     /// Returns the timestamp of the message for sorting.
     pub fn get_sort_timestamp(&self) -> i64 {
         self.timestamp_sort
@@ -690,6 +813,27 @@ pub fn get_text(&self) -> String {
         self.text.clone()
     }
 
+    /// Returns the ranges of the text with markup, e.g. bold or italic text.
+    ///
+    /// The ranges are parsed from received messages and when sending a message.
+    pub fn get_markup(&self) -> Vec<MarkupRange> {
+        self.param
+            .get(Param::Markup)
+            .map(markup::ranges_from_param)
+            .unwrap_or_default()
+    }
+
+    /// Stores the markup parsed from the text in the message parameters.
+    pub(crate) fn update_markup(&mut self) {
+        let ranges = markup::parse_markup(&self.text);
+        if ranges.is_empty() {
+            self.param.remove(Param::Markup);
+        } else {
+            self.param
+                .set(Param::Markup, markup::ranges_to_param(&ranges));
+        }
+    }
+
     /// Returns message subject.
     pub fn get_subject(&self) -> &str {
         &self.subject
@@ -734,6 +878,12 @@ pub fn get_duration(&self) -> i32 {
         self.param.get_int(Param::Duration).unwrap_or_default()
     }
 
+    /// Returns true if the associated image has more than one frame,
+    /// e.g. an animated GIF or an animated sticker.
+    pub fn is_animated(&self) -> bool {
+        self.param.get_bool(Param::Animated).unwrap_or_default()
+    }
+
     /// Returns true if padlock indicating message encryption should be displayed in the UI.
     pub fn get_showpadlock(&self) -> bool {
         self.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() != 0
@@ -744,6 +894,21 @@ pub fn is_bot(&self) -> bool {
         self.param.get_bool(Param::Bot).unwrap_or_default()
     }
 
+    /// Returns true if the message is an automatic reply, e.g. a vacation notice.
+    ///
+    /// Bots should not reply to such messages to avoid mail loops.
+    pub fn is_auto_reply(&self) -> bool {
+        self.param.get_bool(Param::AutoReply).unwrap_or_default()
+    }
+
+    /// Returns the result of the DKIM verification of an incoming message.
+    pub fn get_dkim_status(&self) -> DkimStatus {
+        self.param
+            .get_int(Param::DkimStatus)
+            .and_then(DkimStatus::from_i32)
+            .unwrap_or_default()
+    }
+
     /// Return the ephemeral timer duration for a message.
     pub fn get_ephemeral_timer(&self) -> EphemeralTimer {
         self.ephemeral_timer
@@ -837,6 +1002,40 @@ pub fn get_info_type(&self) -> SystemMessage {
         self.param.get_cmd()
     }
 
+    /// Returns the contact affected by an informational message,
+    /// i.e. the added or removed member
+    /// for [`SystemMessage::MemberAddedToGroup`] and [`SystemMessage::MemberRemovedFromGroup`].
+    ///
+    /// The contact who performed the action is returned by [`Message::get_info_actor_id`].
+    pub async fn get_info_contact_id(&self, context: &Context) -> Result<Option<ContactId>> {
+        match self.param.get_cmd() {
+            SystemMessage::MemberAddedToGroup | SystemMessage::MemberRemovedFromGroup => {
+                match self.param.get(Param::Arg) {
+                    Some(addr) => Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await,
+                    None => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the contact who performed the group change described by an informational message,
+    /// e.g. who renamed the group or changed its avatar.
+    ///
+    /// This is the sender of the message. A member leaving the group
+    /// is both the actor and the contact returned by [`Message::get_info_contact_id`].
+    /// Returns `None` for messages not describing a group change.
+    pub fn get_info_actor_id(&self) -> Option<ContactId> {
+        match self.param.get_cmd() {
+            SystemMessage::GroupNameChanged
+            | SystemMessage::GroupImageChanged
+            | SystemMessage::GroupAdminsChanged
+            | SystemMessage::MemberAddedToGroup
+            | SystemMessage::MemberRemovedFromGroup => Some(self.from_id),
+            _ => None,
+        }
+    }
+
     /// Returns true if the message is a system message.
     pub fn is_system_message(&self) -> bool {
         let cmd = self.param.get_cmd();
@@ -969,6 +1168,14 @@ pub fn set_text(&mut self, text: String) {
         self.text = text;
     }
 
+    /// Sets the text of the message from spans of plain and formatted text.
+    ///
+    /// The markers of the formatting are added to the text,
+    /// so that the formatting is also shown by other clients.
+    pub fn set_text_with_markup(&mut self, spans: &[MarkupSpan]) {
+        self.text = markup::build_markup_text(spans);
+    }
+
     /// Sets the email's subject. If it's empty, a default subject
     /// will be used (e.g. `Message from Alice` or `Re: <last subject>`).
     pub fn set_subject(&mut self, subject: String) {
@@ -990,6 +1197,20 @@ pub fn set_file(&mut self, file: impl ToString, filemime: Option<&str>) {
         self.param.set_optional(Param::MimeType, filemime);
     }
 
+    /// Sets a file outside the blobdir to be sent without copying it to the blobdir.
+    ///
+    /// This avoids using twice the disk space for large files.
+    /// `file` must be an absolute path.
+    /// The file is read when the message is sent, so it must not be deleted or modified
+    /// before; if it has vanished or cannot be read, sending fails with an error.
+    /// Backups contain a copy of the file.
+    /// Images and stickers are not recoded and webxdc apps cannot be referenced,
+    /// so this is only possible for files, audio and video.
+    pub fn set_file_reference(&mut self, file: impl ToString, filemime: Option<&str>) {
+        self.set_file(file, filemime);
+        self.param.set_int(Param::FileReference, 1);
+    }
+
     /// Creates a new blob and sets it as a file associated with a message.
     pub async fn set_file_from_bytes(
         &mut self,
@@ -1004,6 +1225,20 @@ pub async fn set_file_from_bytes(
         Ok(())
     }
 
+    /// Sets the time in seconds after which the message is not sent anymore.
+    ///
+    /// If the message cannot be delivered within this time after sending it,
+    /// e.g. because there is no network, it is marked as failed
+    /// instead of being retried. This is useful for messages becoming stale quickly,
+    /// e.g. one-time passwords sent by bots. 0 means no limit, this is the default.
+    pub fn set_delivery_ttl(&mut self, seconds: u32) {
+        if seconds == 0 {
+            self.param.remove(Param::DeliveryTtl);
+        } else {
+            self.param.set_i64(Param::DeliveryTtl, i64::from(seconds));
+        }
+    }
+
     /// Set different sender name for a message.
     /// This overrides the name set by the `set_config()`-option `displayname`.
     pub fn set_override_sender_name(&mut self, name: Option<String>) {
@@ -1136,7 +1371,7 @@ pub async fn update_param(&self, context: &Context) -> Result<()> {
             .sql
             .execute(
                 "UPDATE msgs SET param=? WHERE id=?;",
-                (self.param.to_string(), self.id),
+                (self.stored_param(context).await?, self.id),
             )
             .await?;
         Ok(())
@@ -1391,21 +1626,31 @@ pub(crate) fn guess_msgtype_from_suffix(path: &Path) -> Option<(Viewtype, &str)>
 /// e.g. because of save_mime_headers is not set
 /// or the message is not incoming.
 pub async fn get_mime_headers(context: &Context, msg_id: MsgId) -> Result<Vec<u8>> {
-    let (headers, compressed) = context
+    let (headers, compressed, encrypted_at_rest) = context
         .sql
         .query_row(
-            "SELECT mime_headers, mime_compressed FROM msgs WHERE id=?",
+            "SELECT mime_headers, mime_compressed, encrypted_at_rest FROM msgs WHERE id=?",
             (msg_id,),
             |row| {
                 let headers = sql::row_get_vec(row, 0)?;
                 let compressed: bool = row.get(1)?;
-                Ok((headers, compressed))
+                let encrypted_at_rest: bool = row.get(2)?;
+                Ok((headers, compressed, encrypted_at_rest))
             },
         )
         .await?;
+    let headers = if encrypted_at_rest && !headers.is_empty() {
+        e2ee::decrypt_at_rest(context, &headers).await?
+    } else {
+        headers
+    };
     if compressed {
         return buf_decompress(&headers);
     }
+    if encrypted_at_rest {
+        // Writing the headers back compressed would store them unencrypted.
+        return Ok(headers);
+    }
 
     let headers2 = headers.clone();
     let compressed = match tokio::task::block_in_place(move || buf_compress(&headers2)) {
@@ -1440,10 +1685,60 @@ pub async fn get_mime_headers(context: &Context, msg_id: MsgId) -> Result<Vec<u8
     Ok(headers)
 }
 
+/// Exports a message as an email in RFC 5322 format,
+/// e.g. to open it in another mail program or to archive it.
+///
+/// If the raw message was saved, see [`get_mime_headers`], it is exported unchanged,
+/// otherwise the email is reconstructed from the database, including the attachment.
+/// End-to-end encrypted messages are only stored decrypted,
+/// so they are exported decrypted and marked with a `Chat-Decrypted: 1` header.
+pub async fn export_eml(context: &Context, msg_id: MsgId) -> Result<Vec<u8>> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        !msg.chat_id.is_special(),
+        "Cannot export message {msg_id} of special chat"
+    );
+
+    let raw = get_mime_headers(context, msg_id).await?;
+    if raw.is_empty() {
+        let eml = mimefactory::render_export(context, &msg).await?;
+        return Ok(eml.into_bytes());
+    }
+    if msg.get_showpadlock() {
+        let mut eml = b"Chat-Decrypted: 1\r\n".to_vec();
+        eml.extend_from_slice(&raw);
+        return Ok(eml);
+    }
+    Ok(raw)
+}
+
+/// Exports a message as `.eml` file, see [`export_eml`].
+pub async fn export_eml_file(context: &Context, msg_id: MsgId, path: &Path) -> Result<()> {
+    let eml = export_eml(context, msg_id).await?;
+    write_file(context, path, &eml).await?;
+    Ok(())
+}
+
 /// Deletes requested messages
 /// by moving them to the trash chat
 /// and scheduling for deletion on IMAP.
+///
+/// If [`Config::UndoTimeout`] is set, the deletion can be reversed with [`undo::undo`].
 pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) -> Result<()> {
+    delete_msgs_ex(context, msg_ids, true).await
+}
+
+/// Deletes messages, queueing the deletion in the undo queue if `undoable` is true
+/// and [`Config::UndoTimeout`] is set.
+pub(crate) async fn delete_msgs_ex(
+    context: &Context,
+    msg_ids: &[MsgId],
+    undoable: bool,
+) -> Result<()> {
+    if undoable && undo::defer_delete_msgs(context, msg_ids).await? {
+        return Ok(());
+    }
+
     let mut modified_chat_ids = BTreeSet::new();
     let mut res = Ok(());
 
@@ -1457,17 +1752,19 @@ pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) -> Result<()> {
             .await
             .with_context(|| format!("Unable to trash message {msg_id}"))?;
 
-        context.emit_event(EventType::MsgDeleted {
-            chat_id: msg.chat_id,
-            msg_id,
-        });
+        // Messages hidden by the undo queue were already reported as deleted.
+        if !msg.chat_id.is_trash() {
+            context.emit_event(EventType::MsgDeleted {
+                chat_id: msg.chat_id,
+                msg_id,
+            });
+            modified_chat_ids.insert(msg.chat_id);
+        }
 
         if msg.viewtype == Viewtype::Webxdc {
             context.emit_event(EventType::WebxdcInstanceDeleted { msg_id });
         }
 
-        modified_chat_ids.insert(msg.chat_id);
-
         let target = context.get_delete_msgs_target().await?;
         let update_db = |conn: &mut rusqlite::Connection| {
             conn.execute(
@@ -1482,6 +1779,7 @@ pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) -> Result<()> {
             res = Err(e);
             continue;
         }
+        audit::log(context, &msg.rfc724_mid, "Deletion scheduled").await?;
 
         let logging_xdc_id = context
             .debug_logging
@@ -1688,6 +1986,9 @@ pub(crate) async fn set_msg_failed(
             (msg.state, error, msg.id),
         )
         .await?;
+    if msg.state == MessageState::OutFailed {
+        msg.chat_id.set_last_error(context, error).await?;
+    }
 
     context.emit_event(EventType::MsgFailed {
         chat_id: msg.chat_id,
@@ -1916,6 +2217,7 @@ pub fn has_file(&self) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use mailparse::MailHeaderMap;
     use num_traits::FromPrimitive;
 
     use super::*;
@@ -2364,6 +2666,18 @@ async fn test_is_bot() -> Result<()> {
         Ok(())
     }
 
+    #[test]
+    fn test_set_delivery_ttl() {
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_delivery_ttl(u32::MAX);
+        assert_eq!(
+            msg.param.get_i64(Param::DeliveryTtl),
+            Some(i64::from(u32::MAX))
+        );
+        msg.set_delivery_ttl(0);
+        assert_eq!(msg.param.get_i64(Param::DeliveryTtl), None);
+    }
+
     #[test]
     fn test_viewtype_derive_display_works_as_expected() {
         assert_eq!(format!("{}", Viewtype::Audio), "Audio");
@@ -2460,4 +2774,51 @@ async fn test_delete_msgs_offline() -> Result<()> {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_export_eml() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let msg = tcm.send_recv_accept(&alice, &bob, "Hello!").await;
+        let eml = export_eml(&bob, msg.id).await?;
+        let mail = mailparse::parse_mail(&eml)?;
+        assert_eq!(
+            mail.headers.get_header_value("Message-ID"),
+            Some(format!("<{}>", msg.rfc724_mid))
+        );
+        assert!(mail
+            .headers
+            .get_header_value("From")
+            .unwrap()
+            .contains("alice@example.org"));
+        assert!(mail
+            .headers
+            .get_header_value("To")
+            .unwrap()
+            .contains("bob@example.net"));
+        assert_eq!(mail.headers.get_header_value("Chat-Decrypted"), None);
+        assert_eq!(mail.get_body()?.trim(), "Hello!");
+
+        // The reply is encrypted.
+        let reply = tcm.send_recv(&bob, &alice, "Hi!").await;
+        let eml = export_eml(&alice, reply.id).await?;
+        let mail = mailparse::parse_mail(&eml)?;
+        assert_eq!(
+            mail.headers.get_header_value("Chat-Decrypted").as_deref(),
+            Some("1")
+        );
+
+        bob.set_config_bool(Config::SaveMimeHeaders, true).await?;
+        let msg = tcm.send_recv(&alice, &bob, "Raw").await;
+        let eml = export_eml(&bob, msg.id).await?;
+        assert!(eml.starts_with(b"Chat-Decrypted: 1\r\n"));
+        assert!(String::from_utf8_lossy(&eml).contains("Raw"));
+
+        let path = bob.get_blobdir().join("export.eml");
+        export_eml_file(&bob, msg.id, &path).await?;
+        assert_eq!(tokio::fs::read(&path).await?, eml);
+        Ok(())
+    }
 }
@@ -0,0 +1,220 @@
+//! # Local metrics.
+//!
+//! Counters for IMAP fetches, received messages, reconnects, job retries and SQL time.
+//! Metrics are only collected if [`Config::MetricsEnabled`] is set.
+//! They are kept in memory and are never sent anywhere,
+//! the UI or bot can query them with [`Context::get_metrics`].
+//!
+//! [`Config::MetricsEnabled`]: crate::config::Config::MetricsEnabled
+
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+use crate::tools::time;
+
+/// Metric counters of a single context.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    enabled: AtomicBool,
+
+    /// Timestamp of the last reset.
+    since: AtomicI64,
+
+    fetches: AtomicU64,
+    fetch_time_ms: AtomicU64,
+    msgs_received: AtomicU64,
+    imap_connects: AtomicU64,
+    smtp_connects: AtomicU64,
+    job_retries: AtomicU64,
+    sql_queries: AtomicU64,
+    sql_time_us: AtomicU64,
+}
+
+/// Snapshot of the metric counters.
+///
+/// All counters are accumulated since `since`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Whether metrics are collected.
+    pub enabled: bool,
+
+    /// Timestamp of the last reset or of enabling the metrics.
+    pub since: i64,
+
+    /// Number of IMAP folder fetches.
+    pub fetches: u64,
+
+    /// Total time spent fetching IMAP folders, in milliseconds.
+    pub fetch_time_ms: u64,
+
+    /// Number of received messages.
+    pub msgs_received: u64,
+
+    /// Number of established IMAP connections.
+    pub imap_connects: u64,
+
+    /// Number of established SMTP connections.
+    pub smtp_connects: u64,
+
+    /// Number of temporary failures of queued jobs which are going to be retried.
+    pub job_retries: u64,
+
+    /// Number of SQL calls.
+    pub sql_queries: u64,
+
+    /// Total time spent in SQL calls, in microseconds.
+    pub sql_time_us: u64,
+}
+
+impl Metrics {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        let was_enabled = self.enabled.swap(enabled, Ordering::Relaxed);
+        if enabled && !was_enabled {
+            self.reset();
+        }
+    }
+
+    fn add(&self, counter: &AtomicU64, value: u64) {
+        if self.is_enabled() {
+            counter.fetch_add(value, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_fetch(&self, duration: Duration) {
+        self.add(&self.fetches, 1);
+        self.add(
+            &self.fetch_time_ms,
+            u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        );
+    }
+
+    pub(crate) fn record_msg_received(&self) {
+        self.add(&self.msgs_received, 1);
+    }
+
+    pub(crate) fn record_imap_connect(&self) {
+        self.add(&self.imap_connects, 1);
+    }
+
+    pub(crate) fn record_smtp_connect(&self) {
+        self.add(&self.smtp_connects, 1);
+    }
+
+    pub(crate) fn record_job_retry(&self) {
+        self.add(&self.job_retries, 1);
+    }
+
+    pub(crate) fn record_sql(&self, duration: Duration) {
+        self.add(&self.sql_queries, 1);
+        self.add(
+            &self.sql_time_us,
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+        );
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            enabled: self.is_enabled(),
+            since: self.since.load(Ordering::Relaxed),
+            fetches: self.fetches.load(Ordering::Relaxed),
+            fetch_time_ms: self.fetch_time_ms.load(Ordering::Relaxed),
+            msgs_received: self.msgs_received.load(Ordering::Relaxed),
+            imap_connects: self.imap_connects.load(Ordering::Relaxed),
+            smtp_connects: self.smtp_connects.load(Ordering::Relaxed),
+            job_retries: self.job_retries.load(Ordering::Relaxed),
+            sql_queries: self.sql_queries.load(Ordering::Relaxed),
+            sql_time_us: self.sql_time_us.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.since.store(time(), Ordering::Relaxed);
+        for counter in [
+            &self.fetches,
+            &self.fetch_time_ms,
+            &self.msgs_received,
+            &self.imap_connects,
+            &self.smtp_connects,
+            &self.job_retries,
+            &self.sql_queries,
+            &self.sql_time_us,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Context {
+    /// Returns a snapshot of the metric counters.
+    ///
+    /// Counters stay at zero unless [`Config::MetricsEnabled`] is set.
+    ///
+    /// [`Config::MetricsEnabled`]: crate::config::Config::MetricsEnabled
+    pub fn get_metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Returns a snapshot of the metric counters serialized as JSON.
+    pub fn get_metrics_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(&self.get_metrics())?)
+    }
+
+    /// Resets all metric counters to zero.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::receive_imf::receive_imf;
+    use crate::test_utils::TestContext;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_metrics_opt_in() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let snapshot = t.get_metrics();
+        assert!(!snapshot.enabled);
+        assert_eq!(snapshot.sql_queries, 0);
+
+        t.set_config_bool(Config::MetricsEnabled, true).await?;
+        receive_imf(
+            &t,
+            b"From: bob@example.net\n\
+              To: alice@example.org\n\
+              Message-ID: <metrics@example.net>\n\
+              Chat-Version: 1.0\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              hello\n",
+            false,
+        )
+        .await?;
+        let snapshot = t.get_metrics();
+        assert!(snapshot.enabled);
+        assert_eq!(snapshot.msgs_received, 1);
+        assert!(snapshot.sql_queries > 0);
+
+        let json: MetricsSnapshot = serde_json::from_str(&t.get_metrics_json()?)?;
+        assert_eq!(json.msgs_received, 1);
+
+        t.reset_metrics();
+        assert_eq!(t.get_metrics().msgs_received, 0);
+
+        t.set_config_bool(Config::MetricsEnabled, false).await?;
+        t.metrics.record_msg_received();
+        assert_eq!(t.get_metrics().msgs_received, 0);
+        Ok(())
+    }
+}
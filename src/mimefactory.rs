@@ -9,11 +9,10 @@
 use lettre_email::{mime, Address, Header, MimeMultipartType, PartBuilder};
 use tokio::fs;
 
-use crate::blob::BlobObject;
-use crate::chat::Chat;
+use crate::chat::{self, Chat};
 use crate::config::Config;
-use crate::constants::{Chattype, DC_FROM_HANDSHAKE};
-use crate::contact::Contact;
+use crate::constants::{Chattype, DC_FROM_HANDSHAKE, MAX_AVATAR_BYTES};
+use crate::contact::{Contact, ContactId};
 use crate::context::{get_version_str, Context};
 use crate::e2ee::EncryptHelper;
 use crate::ephemeral::Timer as EphemeralTimer;
@@ -27,7 +26,8 @@
 use crate::stock_str;
 use crate::tools::IsNoneOrEmpty;
 use crate::tools::{
-    create_outgoing_rfc724_mid, create_smeared_timestamp, remove_subject_prefix, time,
+    create_outgoing_rfc724_mid, create_smeared_timestamp, get_filesuffix_lc, remove_subject_prefix,
+    time,
 };
 
 // attachments of 25 mb brutto should work on the majority of providers
@@ -135,7 +135,7 @@ pub async fn from_msg(
     ) -> Result<MimeFactory<'a>> {
         let chat = Chat::load_from_db(context, msg.chat_id).await?;
 
-        let from_addr = context.get_primary_self_addr().await?;
+        let from_addr = chat.get_self_addr(context).await?;
         let config_displayname = context
             .get_config(Config::Displayname)
             .await?
@@ -582,7 +582,13 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
         }
 
         // Automatic Response headers <https://www.rfc-editor.org/rfc/rfc3834>
-        if let Loaded::Mdn { .. } = self.loaded {
+        if matches!(self.loaded, Loaded::Mdn { .. })
+            || self
+                .msg
+                .param
+                .get_bool(Param::AutoReply)
+                .unwrap_or_default()
+        {
             headers.unprotected.push(Header::new(
                 "Auto-Submitted".to_string(),
                 "auto-replied".to_string(),
@@ -623,7 +629,10 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
         let force_plaintext = self.should_force_plaintext();
         let skip_autocrypt = self.should_skip_autocrypt();
         let e2ee_guaranteed = self.is_e2ee_guaranteed();
-        let encrypt_helper = EncryptHelper::new(context).await?;
+        let mut encrypt_helper = EncryptHelper::new(context).await?;
+        // Receivers ignore Autocrypt headers not matching the From address,
+        // so the header uses the alias the message is sent from, with the same key.
+        encrypt_helper.addr = self.from_addr.clone();
 
         if !skip_autocrypt {
             // unless determined otherwise we add the Autocrypt header
@@ -710,7 +719,7 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
                 .fold(message, |message, header| message.header(header));
 
             // Add gossip headers in chats with multiple recipients
-            if (peerstates.len() > 1 || context.get_config_bool(Config::BccSelf).await?)
+            if (peerstates.len() > 1 || context.should_send_bcc_self().await?)
                 && self.should_do_gossip(context).await?
             {
                 for peerstate in peerstates.iter().filter_map(|(state, _)| state.as_ref()) {
@@ -942,6 +951,18 @@ async fn render_message(
                 .protected
                 .push(Header::new("Chat-Group-Name".into(), encoded));
 
+            if let Some(admins) = chat.param.get(Param::GroupAdmins) {
+                headers
+                    .protected
+                    .push(Header::new("Chat-Group-Admins".into(), admins.to_string()));
+            }
+            if let Some(owner) = chat.get_channel_owner() {
+                headers.protected.push(Header::new(
+                    "Chat-Group-Channel-Owner".into(),
+                    owner.to_string(),
+                ));
+            }
+
             match command {
                 SystemMessage::MemberRemovedFromGroup => {
                     let email_to_remove = self.msg.param.get(Param::Arg).unwrap_or_default();
@@ -1020,6 +1041,12 @@ async fn render_message(
                         ));
                     }
                 }
+                SystemMessage::GroupAdminsChanged => {
+                    headers.protected.push(Header::new(
+                        "Chat-Content".to_string(),
+                        "group-admins-changed".to_string(),
+                    ));
+                }
                 _ => {}
             }
         }
@@ -1037,6 +1064,30 @@ async fn render_message(
                     "ephemeral-timer-changed".to_string(),
                 ));
             }
+            SystemMessage::WebxdcRealtimeAdvertisement => {
+                if let Some(advertisement) = self.msg.param.get(Param::Arg) {
+                    headers.protected.push(Header::new(
+                        "Chat-Webxdc-Realtime".to_string(),
+                        advertisement.to_string(),
+                    ));
+                }
+                headers.unprotected.push(Header::new(
+                    "Auto-Submitted".to_string(),
+                    "auto-generated".to_string(),
+                ));
+            }
+            SystemMessage::KeyTransition => {
+                if let Some(statement) = self.msg.param.get(Param::Arg) {
+                    headers.protected.push(Header::new(
+                        "Chat-Key-Transition".to_string(),
+                        statement.to_string(),
+                    ));
+                }
+                headers.unprotected.push(Header::new(
+                    "Auto-Submitted".to_string(),
+                    "auto-generated".to_string(),
+                ));
+            }
             SystemMessage::LocationOnly
             | SystemMessage::MultiDeviceSync
             | SystemMessage::WebxdcStatusUpdate => {
@@ -1213,6 +1264,14 @@ async fn render_message(
             footer
         );
 
+        // The status is also sent as the footer for other MUAs,
+        // but the footer can not be recovered exactly on the receiver side.
+        if !footer.is_empty() {
+            headers
+                .protected
+                .push(Header::new("Chat-User-Status".into(), encode_words(footer)));
+        }
+
         // Message is sent as text/plain, with charset = utf-8
         let mut main_part = PartBuilder::new()
             .header((
@@ -1410,12 +1469,24 @@ async fn build_body_file(
     msg: &Message,
     base_name: &str,
 ) -> Result<(PartBuilder, String)> {
-    let blob = msg
-        .param
-        .get_blob(Param::File, context, true)
-        .await?
-        .context("msg has no file")?;
-    let suffix = blob.suffix().unwrap_or("dat");
+    let path = if msg.param.get_bool(Param::FileReference).unwrap_or_default() {
+        // Referenced files are read from their original location, see `set_file_reference()`.
+        msg.param
+            .get_path(Param::File, context)?
+            .context("msg has no file")?
+    } else {
+        msg.param
+            .get_blob(Param::File, context, true)
+            .await?
+            .context("msg has no file")?
+            .to_abs_path()
+    };
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let suffix = get_filesuffix_lc(file_name);
+    let suffix = suffix.as_deref().unwrap_or("dat");
 
     // Get file name to use for sending.  For privacy purposes, we do
     // not transfer the original filenames eg. for images; these names
@@ -1462,7 +1533,7 @@ async fn build_body_file(
         _ => msg
             .param
             .get(Param::Filename)
-            .unwrap_or_else(|| blob.as_file_name())
+            .unwrap_or(file_name)
             .to_string(),
     };
 
@@ -1470,7 +1541,7 @@ async fn build_body_file(
     let mimetype: mime::Mime = match msg.param.get(Param::MimeType) {
         Some(mtype) => mtype.parse()?,
         None => {
-            if let Some(res) = message::guess_msgtype_from_suffix(blob.as_rel_path()) {
+            if let Some(res) = message::guess_msgtype_from_suffix(&path) {
                 res.1.parse()?
             } else {
                 mime::APPLICATION_OCTET_STREAM
@@ -1488,7 +1559,9 @@ async fn build_body_file(
         maybe_encode_words(&filename_to_send)
     );
 
-    let body = fs::read(blob.to_abs_path()).await?;
+    let body = fs::read(&path)
+        .await
+        .with_context(|| format!("cannot read attachment {}", path.display()))?;
     let encoded_body = wrapped_base64_encode(&body);
 
     let mail = PartBuilder::new()
@@ -1501,12 +1574,158 @@ async fn build_body_file(
 }
 
 async fn build_selfavatar_file(context: &Context, path: &str) -> Result<String> {
-    let blob = BlobObject::from_path(context, path.as_ref())?;
-    let body = fs::read(blob.to_abs_path()).await?;
+    let mut body = fs::read(path).await?;
+    if body.len() > MAX_AVATAR_BYTES {
+        // The avatar may be set by an older version or another device with other limits.
+        info!(
+            context,
+            "Selfavatar is too large ({} bytes), recoding.",
+            body.len()
+        );
+        // Setting the config recodes the avatar and updates its hash.
+        context.set_config(Config::Selfavatar, Some(path)).await?;
+        let path = context
+            .get_config(Config::Selfavatar)
+            .await?
+            .context("Selfavatar removed while recoding")?;
+        body = fs::read(path).await?;
+    }
     let encoded_body = wrapped_base64_encode(&body);
     Ok(encoded_body)
 }
 
+/// Reconstructs a message from the database as an unencrypted email,
+/// see [`message::export_eml`].
+pub(crate) async fn render_export(context: &Context, msg: &Message) -> Result<String> {
+    let mailbox = |contact: &Contact, name: &str| {
+        if name.is_empty() {
+            Address::new_mailbox(contact.get_addr().to_string())
+        } else {
+            Address::new_mailbox_with_name(name.to_string(), contact.get_addr().to_string())
+        }
+    };
+    let self_name = context
+        .get_config(Config::Displayname)
+        .await?
+        .unwrap_or_default();
+    let contact_name = |contact: &Contact| {
+        if contact.id == ContactId::SELF {
+            self_name.clone()
+        } else {
+            contact.get_authname().to_string()
+        }
+    };
+
+    let from_contact = Contact::get_by_id(context, msg.from_id).await?;
+    let from = mailbox(&from_contact, &contact_name(&from_contact));
+    let mut to = Vec::new();
+    let mut recipient_ids = chat::get_chat_contacts(context, msg.chat_id).await?;
+    if !recipient_ids.contains(&ContactId::SELF) {
+        recipient_ids.push(ContactId::SELF);
+    }
+    for contact_id in recipient_ids {
+        if contact_id != msg.from_id {
+            let contact = Contact::get_by_id(context, contact_id).await?;
+            to.push(mailbox(&contact, &contact_name(&contact)));
+        }
+    }
+    if to.is_empty() {
+        to.push(from.clone());
+    }
+
+    let subject = if msg.subject.is_empty() {
+        Chat::load_from_db(context, msg.chat_id).await?.name
+    } else {
+        msg.subject.clone()
+    };
+    let timestamp = if msg.timestamp_sent != 0 {
+        msg.timestamp_sent
+    } else {
+        msg.timestamp_sort
+    };
+    let date = chrono::Utc
+        .from_local_datetime(
+            &chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+                .context("can't convert timestamp to NativeDateTime")?,
+        )
+        .unwrap()
+        .to_rfc2822();
+
+    let mut message = PartBuilder::new()
+        .header(Header::new_with_value("From".into(), vec![from]).unwrap())
+        .header(Header::new_with_value("To".into(), to).unwrap())
+        .header(("Subject".to_string(), encode_words(&subject)))
+        .header(("Date".to_string(), date))
+        .header(("Message-ID".to_string(), render_rfc724_mid(&msg.rfc724_mid)));
+    if let Some(in_reply_to) = msg.in_reply_to.as_deref().filter(|mid| !mid.is_empty()) {
+        message = message.header(("In-Reply-To".to_string(), render_rfc724_mid(in_reply_to)));
+    }
+    if msg.get_showpadlock() {
+        // The original message was end-to-end encrypted, only the decrypted content is stored.
+        message = message.header(("Chat-Decrypted".to_string(), "1".to_string()));
+    }
+    message = message.header(("MIME-Version".to_string(), "1.0".to_string()));
+
+    let text_content_type = (
+        "Content-Type".to_string(),
+        "text/plain; charset=utf-8; format=flowed; delsp=no".to_string(),
+    );
+    let text = format_flowed(&msg.text);
+    let message = if msg.param.exists(Param::File) {
+        let (file_part, _) = build_body_file(context, msg, "").await?;
+        let text_part = PartBuilder::new().header(text_content_type).body(text);
+        message
+            .message_type(MimeMultipartType::Mixed)
+            .child(text_part.build())
+            .child(file_part.build())
+    } else {
+        message.header(text_content_type).body(text)
+    };
+    Ok(message.build().as_string())
+}
+
+/// Renders a plain text email to `to_addr` which does not belong to any chat,
+/// e.g. a request to unsubscribe from a mailing list.
+///
+/// Returns the Message-ID and the rendered email.
+pub(crate) async fn render_unrelated_email(
+    context: &Context,
+    to_addr: &str,
+    subject: &str,
+    text: &str,
+) -> Result<(String, String)> {
+    let from_addr = context.get_primary_self_addr().await?;
+    let rfc724_mid = create_outgoing_rfc724_mid(None, &from_addr);
+    let date = chrono::Utc
+        .from_local_datetime(
+            &chrono::NaiveDateTime::from_timestamp_opt(time(), 0)
+                .context("can't convert timestamp to NativeDateTime")?,
+        )
+        .single()
+        .context("can't convert local time to UTC")?
+        .to_rfc2822();
+    let from_header = Header::new_with_value("From".into(), vec![Address::new_mailbox(from_addr)])
+        .ok()
+        .context("invalid From header")?;
+    let to_header =
+        Header::new_with_value("To".into(), vec![Address::new_mailbox(to_addr.to_string())])
+            .ok()
+            .context("invalid To header")?;
+    let message = PartBuilder::new()
+        .header(from_header)
+        .header(to_header)
+        .header(("Subject".to_string(), encode_words(subject)))
+        .header(("Date".to_string(), date))
+        .header(("Message-ID".to_string(), render_rfc724_mid(&rfc724_mid)))
+        .header(("MIME-Version".to_string(), "1.0".to_string()))
+        .header((
+            "Content-Type".to_string(),
+            "text/plain; charset=utf-8; format=flowed; delsp=no".to_string(),
+        ))
+        .body(format_flowed(text));
+    Ok((rfc724_mid, message.build().as_string()))
+}
+
 fn recipients_contain_addr(recipients: &[(String, String)], addr: &str) -> bool {
     let addr_lc = addr.to_lowercase();
     recipients
@@ -1561,14 +1780,17 @@ mod tests {
     use std::str;
 
     use super::*;
+    use crate::blob::BlobObject;
     use crate::chat::ChatId;
     use crate::chat::{
         self, add_contact_to_chat, create_group_chat, remove_contact_from_chat, send_text_msg,
         ProtectionStatus,
     };
     use crate::chatlist::Chatlist;
+    use crate::config::InternalConfig;
     use crate::constants;
     use crate::contact::{ContactAddress, Origin};
+    use crate::events::EventType;
     use crate::mimeparser::MimeMessage;
     use crate::receive_imf::receive_imf;
     use crate::test_utils::{get_chat_msg, TestContext, TestContextManager};
@@ -2133,6 +2355,43 @@ fn test_no_empty_lines_in_header() {
         assert!(!headers.lines().any(|l| l.trim().is_empty()));
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_oversized_selfavatar_recoded() -> anyhow::Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("bob", "bob@example.org").await;
+
+        // Set the avatar without recoding it, as older versions might have done.
+        let file = t.get_blobdir().join("avatar.jpg");
+        let bytes = include_bytes!("../test-data/image/avatar1000x1000.jpg");
+        assert!(bytes.len() > MAX_AVATAR_BYTES);
+        tokio::fs::write(&file, bytes).await?;
+        t.sql
+            .set_raw_config(Config::Selfavatar.as_ref(), Some("$BLOBDIR/avatar.jpg"))
+            .await?;
+
+        let sent_msg = t.send_text(chat.id, "Hi").await;
+        assert_eq!(
+            sent_msg
+                .payload()
+                .match_indices("Chat-User-Avatar:")
+                .count(),
+            1
+        );
+        let avatar = t.get_config(Config::Selfavatar).await?.unwrap();
+        let avatar = BlobObject::from_path(&t, avatar.as_ref())?;
+        let size = tokio::fs::metadata(avatar.to_abs_path()).await?.len();
+        assert!(size <= MAX_AVATAR_BYTES as u64);
+        assert!(t
+            .sql
+            .get_raw_config(InternalConfig::SelfavatarHash.as_ref())
+            .await?
+            .is_some());
+        t.evtracker
+            .get_matching(|evt| matches!(evt, EventType::ConfigChanged(Config::Selfavatar)))
+            .await;
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_selfavatar_unencrypted() -> anyhow::Result<()> {
         // create chat with bob, set selfavatar
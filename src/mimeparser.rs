@@ -17,7 +17,9 @@
 use crate::blob::BlobObject;
 use crate::chat::{add_info_msg, ChatId};
 use crate::config::Config;
-use crate::constants::{Chattype, DC_DESIRED_TEXT_LINES, DC_DESIRED_TEXT_LINE_LEN};
+use crate::constants::{
+    Chattype, DC_DESIRED_TEXT_LINES, DC_DESIRED_TEXT_LINE_LEN, MAX_RECEIVED_AVATAR_BYTES,
+};
 use crate::contact::{addr_cmp, addr_normalize, Contact, ContactId, Origin};
 use crate::context::Context;
 use crate::decrypt::{
@@ -27,7 +29,8 @@
 use crate::dehtml::dehtml;
 use crate::events::EventType;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
-use crate::key::{load_self_secret_key, DcKey, Fingerprint, SignedPublicKey};
+use crate::key::{load_self_secret_keyring, DcKey, Fingerprint, SignedPublicKey};
+use crate::log::LogExt;
 use crate::message::{
     self, set_msg_failed, update_msg_state, Message, MessageState, MsgId, Viewtype,
 };
@@ -37,11 +40,16 @@
 use crate::stock_str;
 use crate::sync::SyncItems;
 use crate::tools::{
-    create_smeared_timestamp, get_filemeta, parse_receive_headers, strip_rtlo_characters,
-    truncate_by_lines,
+    create_smeared_timestamp, get_filemeta_from_path, is_animated_image_from_path,
+    parse_receive_headers, strip_rtlo_characters, truncate_by_lines,
 };
+use crate::webxdc::WEBXDC_SUFFIX;
 use crate::{location, tools};
 
+mod stream;
+
+pub(crate) use self::stream::{MessageStreamer, StreamedMessage};
+
 /// A parsed MIME message.
 ///
 /// This represents the relevant information of a parsed MIME message
@@ -92,6 +100,7 @@ pub(crate) struct MimeMessage {
     pub message_kml: Option<location::Kml>,
     pub(crate) sync_items: Option<SyncItems>,
     pub(crate) webxdc_status_update: Option<String>,
+    pub(crate) webxdc_realtime_advertisement: Option<String>,
     pub(crate) user_avatar: Option<AvatarAction>,
     pub(crate) group_avatar: Option<AvatarAction>,
     pub(crate) mdn_reports: Vec<Report>,
@@ -103,6 +112,11 @@ pub(crate) struct MimeMessage {
     /// received.
     pub(crate) footer: Option<String>,
 
+    /// Status text of the sender from the `Chat-User-Status` header.
+    ///
+    /// Takes precedence over the footer, which may be mangled by signature stripping.
+    pub(crate) user_status: Option<String>,
+
     // if this flag is set, the parts/text/etc. are just close to the original mime-message;
     // clients should offer a way to view the original message in this case
     pub is_mime_modified: bool,
@@ -118,6 +132,13 @@ pub(crate) struct MimeMessage {
 
     /// Whether the contact sending this should be marked as bot.
     pub(crate) is_bot: bool,
+
+    /// Nesting level of the attached message (`message/rfc822`) currently being parsed.
+    attached_msg_depth: usize,
+
+    /// Blob names and sizes of the attachments decoded while receiving the message
+    /// by the placeholder replacing their body, see [`MessageStreamer`].
+    streamed_attachments: HashMap<String, (String, usize)>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -169,6 +190,9 @@ pub enum SystemMessage {
     /// Chat protection is disabled.
     ChatProtectionDisabled = 12,
 
+    /// Group admins changed.
+    GroupAdminsChanged = 13,
+
     /// Self-sent-message that contains only json used for multi-device-sync;
     /// if possible, we attach that to other messages as for locations.
     MultiDeviceSync = 20,
@@ -178,12 +202,47 @@ pub enum SystemMessage {
     /// These messages are not shown in the chat.
     WebxdcStatusUpdate = 30,
 
+    /// Hidden message advertising a realtime channel of a webxdc instance,
+    /// see [`crate::peer_channels`].
+    WebxdcRealtimeAdvertisement = 31,
+
     /// Webxdc info added with `info` set in `send_webxdc_status_update()`.
     WebxdcInfoMessage = 32,
+
+    /// Hidden message announcing a new key of the sender,
+    /// see [`Context::rotate_key`].
+    KeyTransition = 33,
 }
 
 const MIME_AC_SETUP_FILE: &str = "application/autocrypt-setup";
 
+/// Maximum nesting level of attached messages (`message/rfc822`) which are parsed.
+/// Messages nested deeper are added as `.eml` files.
+const MAX_ATTACHED_MSG_DEPTH: usize = 3;
+
+/// Minimum size of base64-encoded attachments which are decoded directly into blob files
+/// instead of decoding them into memory first.
+const STREAMED_ATTACHMENT_MIN_SIZE: usize = 1024 * 1024;
+
+/// Header fields kept when salvaging a damaged message,
+/// see [`MimeMessage::from_damaged_bytes`].
+const SALVAGED_HEADERS: &[&str] = &[
+    "from",
+    "sender",
+    "reply-to",
+    "to",
+    "cc",
+    "date",
+    "subject",
+    "message-id",
+    "in-reply-to",
+    "references",
+    "list-id",
+    "chat-version",
+    "chat-group-id",
+    "chat-group-name",
+];
+
 impl MimeMessage {
     /// Parse a mime message.
     ///
@@ -193,6 +252,29 @@ pub(crate) async fn from_bytes(
         context: &Context,
         body: &[u8],
         partial: Option<u32>,
+    ) -> Result<Self> {
+        Self::from_bytes_with_attachments(context, body, partial, HashMap::new()).await
+    }
+
+    /// Parses a message received with [`MessageStreamer`].
+    pub(crate) async fn from_streamed(
+        context: &Context,
+        streamed: &StreamedMessage,
+    ) -> Result<Self> {
+        Self::from_bytes_with_attachments(
+            context,
+            &streamed.raw,
+            None,
+            streamed.attachments.clone(),
+        )
+        .await
+    }
+
+    async fn from_bytes_with_attachments(
+        context: &Context,
+        body: &[u8],
+        partial: Option<u32>,
+        streamed_attachments: HashMap<String, (String, usize)>,
     ) -> Result<Self> {
         let mail = mailparse::parse_mail(body)?;
 
@@ -262,11 +344,12 @@ pub(crate) async fn from_bytes(
         // remove headers that are allowed _only_ in the encrypted part
         headers.remove("secure-join-fingerprint");
         headers.remove("chat-verified");
+        headers.remove("chat-key-transition");
 
         let from = from.context("No from in message")?;
-        let private_keyring = vec![load_self_secret_key(context)
+        let private_keyring = load_self_secret_keyring(context)
             .await
-            .context("Failed to get own key")?];
+            .context("Failed to get own keys")?;
 
         let mut decryption_info =
             prepare_decryption(context, &mail, &from.addr, message_time).await?;
@@ -401,14 +484,18 @@ pub(crate) async fn from_bytes(
             message_kml: None,
             sync_items: None,
             webxdc_status_update: None,
+            webxdc_realtime_advertisement: None,
             user_avatar: None,
             group_avatar: None,
             delivery_report: None,
             footer: None,
+            user_status: None,
             is_mime_modified: false,
             decoded_data: Vec::new(),
             hop_info,
             is_bot,
+            attached_msg_depth: 0,
+            streamed_attachments,
         };
 
         match partial {
@@ -456,6 +543,7 @@ pub(crate) async fn from_bytes(
         }
 
         crate::peerstate::maybe_do_aeap_transition(context, &mut parser).await?;
+        crate::peerstate::maybe_do_key_transition(context, &mut parser).await?;
         if let Some(peerstate) = &parser.decryption_info.peerstate {
             peerstate
                 .handle_fingerprint_change(context, message_time)
@@ -472,6 +560,57 @@ pub(crate) async fn from_bytes(
         Ok(parser)
     }
 
+    /// Parses a message [`MimeMessage::from_bytes`] failed on, `err` is the error it returned.
+    ///
+    /// Known header fields and the first text part are extracted on a best-effort basis,
+    /// the result has a single text part noting the damage.
+    /// Fails if not even the `From` field can be extracted.
+    pub(crate) async fn from_damaged_bytes(
+        context: &Context,
+        body: &[u8],
+        err: &anyhow::Error,
+    ) -> Result<Self> {
+        let (header, body) = split_header_body(body);
+        let fields = salvage_header_fields(header);
+        let content_type = fields
+            .iter()
+            .find(|(key, _)| key == "content-type")
+            .map_or("text/plain", |(_, value)| value.as_str());
+        let content_transfer_encoding = fields
+            .iter()
+            .find(|(key, _)| key == "content-transfer-encoding")
+            .map_or("8bit", |(_, value)| value.as_str());
+        let text = salvage_text(content_type, content_transfer_encoding, body).unwrap_or_default();
+        let text = if text.trim().is_empty() {
+            format!("[{}]", stock_str::damaged_msg_body(context).await)
+        } else {
+            text
+        };
+
+        let mut raw = String::new();
+        for (key, value) in &fields {
+            if !SALVAGED_HEADERS.contains(&key.as_str()) {
+                continue;
+            }
+            // Raw 8-bit header values are not decoded as UTF-8 by the parser.
+            let value = if !value.is_ascii() && (key == "subject" || key == "chat-group-name") {
+                encoded_words::encode(value, None, encoded_words::EncodingFlag::Shortest, None)
+            } else {
+                value.to_string()
+            };
+            raw += &format!("{key}: {value}\r\n");
+        }
+        raw += "Content-Type: text/plain; charset=utf-8\r\n\r\n";
+        raw += &text;
+
+        let mut parser = MimeMessage::from_bytes(context, raw.as_bytes(), None).await?;
+        let error = stock_str::msg_not_parsed_completely(context, &format!("{err:#}")).await;
+        for part in &mut parser.parts {
+            part.error = Some(error.clone());
+        }
+        Ok(parser)
+    }
+
     /// Parses system messages.
     fn parse_system_message_headers(&mut self, context: &Context) {
         if self.get_header(HeaderDef::AutocryptSetupMessage).is_some() {
@@ -496,6 +635,8 @@ fn parse_system_message_headers(&mut self, context: &Context) {
                 self.is_system_message = SystemMessage::ChatProtectionDisabled;
             } else if value == "group-avatar-changed" {
                 self.is_system_message = SystemMessage::GroupImageChanged;
+            } else if value == "group-admins-changed" {
+                self.is_system_message = SystemMessage::GroupAdminsChanged;
             }
         } else if self.get_header(HeaderDef::ChatGroupMemberRemoved).is_some() {
             self.is_system_message = SystemMessage::MemberRemovedFromGroup;
@@ -610,6 +751,9 @@ async fn parse_headers(&mut self, context: &Context) -> Result<()> {
         self.parse_system_message_headers(context);
         self.parse_avatar_headers(context).await;
         self.parse_videochat_headers();
+        self.webxdc_realtime_advertisement =
+            self.get_header(HeaderDef::ChatWebxdcRealtime).cloned();
+        self.user_status = self.get_header(HeaderDef::ChatUserStatus).cloned();
         if self.delivery_report.is_none() {
             self.squash_attachment_parts();
         }
@@ -696,6 +840,11 @@ async fn parse_headers(&mut self, context: &Context) -> Result<()> {
                 part.param.set(Param::Bot, "1");
             }
         }
+        if self.is_auto_reply() {
+            for part in &mut self.parts {
+                part.param.set(Param::AutoReply, "1");
+            }
+        }
 
         Ok(())
     }
@@ -715,6 +864,14 @@ async fn avatar_action_from_header(
         {
             // Avatar sent directly in the header as base64.
             if let Ok(decoded_data) = avatar {
+                if decoded_data.len() > MAX_RECEIVED_AVATAR_BYTES {
+                    warn!(
+                        context,
+                        "Ignoring avatar of {} bytes, it is too large.",
+                        decoded_data.len()
+                    );
+                    return None;
+                }
                 let extension = if let Ok(format) = image::guess_format(&decoded_data) {
                     if let Some(ext) = format.extensions_str().first() {
                         format!(".{ext}")
@@ -724,8 +881,14 @@ async fn avatar_action_from_header(
                 } else {
                     String::new()
                 };
-                match BlobObject::create(context, &format!("avatar{extension}"), &decoded_data)
-                    .await
+                // The same avatar is usually received with many messages,
+                // so it is stored only once.
+                match BlobObject::create_and_deduplicate(
+                    context,
+                    &format!("avatar{extension}"),
+                    &decoded_data,
+                )
+                .await
                 {
                     Ok(blob) => Some(AvatarAction::Change(blob.as_name().to_string())),
                     Err(err) => {
@@ -799,6 +962,7 @@ enum MimeS {
                 Multiple,
                 Single,
                 Message,
+                AttachedMessage,
             }
 
             let mimetype = mail.ctype.mimetype.to_lowercase();
@@ -812,6 +976,10 @@ enum MimeS {
             } else if mimetype.starts_with("message") {
                 if mimetype == "message/rfc822" && !is_attachment_disposition(mail) {
                     MimeS::Message
+                } else if mimetype == "message/rfc822"
+                    && self.attached_msg_depth < MAX_ATTACHED_MSG_DEPTH
+                {
+                    MimeS::AttachedMessage
                 } else {
                     MimeS::Single
                 }
@@ -831,6 +999,16 @@ enum MimeS {
 
                     self.parse_mime_recursive(context, &mail, is_related).await
                 }
+                MimeS::AttachedMessage => {
+                    let raw = mail.get_body_raw()?;
+                    if raw.is_empty() {
+                        return Ok(false);
+                    }
+                    let mail =
+                        mailparse::parse_mail(&raw).context("failed to parse attached mail")?;
+
+                    self.add_attached_message(context, &mail, is_related).await
+                }
                 MimeS::Single => {
                     self.add_single_part_if_known(context, mail, is_related)
                         .await
@@ -840,6 +1018,67 @@ enum MimeS {
         .boxed()
     }
 
+    /// Adds the parts of a message attached as `message/rfc822`,
+    /// e.g. a mail forwarded as attachment.
+    ///
+    /// The text of the attached message is prefixed with its headers
+    /// and appended to the text preceding the attachment, if any.
+    /// Attachments of the attached message are added as separate parts.
+    async fn add_attached_message(
+        &mut self,
+        context: &Context,
+        mail: &mailparse::ParsedMail<'_>,
+        is_related: bool,
+    ) -> Result<bool> {
+        let start = self.parts.len();
+        self.attached_msg_depth += 1;
+        let res = self.parse_mime_recursive(context, mail, is_related).await;
+        self.attached_msg_depth -= 1;
+        res?;
+
+        let header = attached_msg_header(mail);
+        let text_part = self
+            .parts
+            .iter_mut()
+            .enumerate()
+            .skip(start)
+            .find(|(_, part)| part.typ == Viewtype::Text && !part.is_reaction);
+        let text_idx = match text_part {
+            Some((text_idx, part)) => {
+                part.msg = format!("{header}\n\n{}", part.msg);
+                text_idx
+            }
+            None => {
+                let part = Part {
+                    typ: Viewtype::Text,
+                    msg: header.clone(),
+                    msg_raw: Some(header),
+                    ..Default::default()
+                };
+                self.parts.insert(start, part);
+                start
+            }
+        };
+
+        // Append the attached message to the text preceding it.
+        if let Some(prev_idx) = start.checked_sub(1) {
+            let prev_is_text = self.parts.get(prev_idx).map_or(false, |part| {
+                part.typ == Viewtype::Text && !part.is_reaction && part.error.is_none()
+            });
+            if text_idx == start && prev_is_text {
+                let attached = self.parts.remove(start);
+                if let Some(part) = self.parts.get_mut(prev_idx) {
+                    if part.msg.is_empty() {
+                        part.msg = attached.msg;
+                    } else {
+                        part.msg = format!("{}\n\n{}", part.msg, attached.msg);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
     async fn handle_multiple(
         &mut self,
         context: &Context,
@@ -913,7 +1152,9 @@ async fn handle_multiple(
                         .await?;
                 }
             }
-            (mime::MULTIPART, "report") => {
+            // Reports inside an attached message, e.g. a forwarded read receipt,
+            // are not control data of this message, their parts are added as usual below.
+            (mime::MULTIPART, "report") if self.attached_msg_depth == 0 => {
                 /* RFC 6522: the first part is for humans, the second for machines */
                 if mail.subparts.len() >= 2 {
                     match mail.ctype.params.get("report-type").map(|s| s as &str) {
@@ -1006,13 +1247,7 @@ async fn add_single_part_if_known(
         match filename {
             Some(filename) => {
                 self.do_add_single_file_part(
-                    context,
-                    msg_type,
-                    mime_type,
-                    &raw_mime,
-                    &mail.get_body_raw()?,
-                    &filename,
-                    is_related,
+                    context, mail, msg_type, mime_type, &raw_mime, &filename, is_related,
                 )
                 .await?;
             }
@@ -1161,35 +1396,166 @@ async fn add_single_part_if_known(
         Ok(self.parts.len() > old_part_count)
     }
 
+    /// Adds a file attachment.
+    ///
+    /// Large base64-encoded attachments which cannot contain control data
+    /// are decoded directly into a blob file, so the decoded data is not kept in memory.
+    /// Other attachments are decoded into memory and checked for control data first.
     #[allow(clippy::too_many_arguments)]
     async fn do_add_single_file_part(
         &mut self,
         context: &Context,
+        mail: &mailparse::ParsedMail<'_>,
         msg_type: Viewtype,
         mime_type: Mime,
         raw_mime: &str,
-        decoded_data: &[u8],
         filename: &str,
         is_related: bool,
     ) -> Result<()> {
-        if decoded_data.is_empty() {
+        let (blob, bytes, msg_type) = match self
+            .decode_large_file_part(context, mail, &mime_type, filename)
+            .await
+        {
+            Some((blob, bytes)) => (blob, bytes, msg_type),
+            None => {
+                let decoded_data = mail.get_body_raw()?;
+                let Some(msg_type) = self
+                    .handle_file_part_data(context, msg_type, &mime_type, &decoded_data, filename)
+                    .await?
+                else {
+                    return Ok(());
+                };
+
+                /* we have a regular file attachment,
+                write decoded data to new blob object */
+                match BlobObject::create(context, filename, &decoded_data).await {
+                    Ok(blob) => (blob, decoded_data.len(), msg_type),
+                    Err(err) => {
+                        error!(
+                            context,
+                            "Could not add blob for mime part {}, error {:#}", filename, err
+                        );
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        if bytes == 0 {
+            tokio::fs::remove_file(blob.to_abs_path()).await.ok();
             return Ok(());
         }
+        info!(context, "added blobfile: {:?}", blob.as_name());
+
+        /* create and register Mime part referencing the new Blob object */
+        let mut part = Part::default();
+        if mime_type.type_() == mime::IMAGE {
+            let path = blob.to_abs_path();
+            let (filemeta, is_animated) = tokio::task::spawn_blocking(move || {
+                (
+                    get_filemeta_from_path(&path),
+                    is_animated_image_from_path(&path).unwrap_or_default(),
+                )
+            })
+            .await?;
+            if let Ok((width, height)) = filemeta {
+                part.param.set_int(Param::Width, width as i32);
+                part.param.set_int(Param::Height, height as i32);
+            }
+            if is_animated {
+                part.param.set_int(Param::Animated, 1);
+            }
+        }
+
+        part.typ = msg_type;
+        part.org_filename = Some(filename.to_string());
+        part.mimetype = Some(mime_type);
+        part.bytes = bytes;
+        part.param.set(Param::File, blob.as_name());
+        part.param.set(Param::Filename, filename);
+        part.param.set(Param::MimeType, raw_mime);
+        part.is_related = is_related;
+
+        self.do_add_single_part(part);
+        Ok(())
+    }
+
+    /// Decodes a large base64-encoded attachment directly into a blob file
+    /// or returns the blob file it was decoded into while receiving the message.
+    ///
+    /// Returns `None` if the attachment must be decoded into memory,
+    /// e.g. because it is small or may contain control data.
+    async fn decode_large_file_part<'a>(
+        &self,
+        context: &'a Context,
+        mail: &mailparse::ParsedMail<'_>,
+        mime_type: &Mime,
+        filename: &str,
+    ) -> Option<(BlobObject<'a>, usize)> {
+        let mailparse::body::Body::Base64(encoded) = mail.get_body_encoded() else {
+            return None;
+        };
+        let encoded = encoded.get_raw();
+        if let Some((name, bytes)) = str::from_utf8(encoded)
+            .ok()
+            .and_then(|placeholder| self.streamed_attachments.get(placeholder.trim()))
+        {
+            return BlobObject::from_name(context, name.clone())
+                .map(|blob| (blob, *bytes))
+                .log_err(context)
+                .ok();
+        }
+        if encoded.len() < STREAMED_ATTACHMENT_MIN_SIZE || may_be_control_data(filename, mime_type)
+        {
+            return None;
+        }
+        match BlobObject::create_from_base64(context, filename, encoded).await {
+            Ok(res) => Some(res),
+            Err(err) => {
+                warn!(
+                    context,
+                    "Could not decode mime part {} into blob, decoding in memory: {:#}.",
+                    filename,
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Processes control data contained in a decoded attachment,
+    /// such as keys, locations, sync items and webxdc status updates.
+    ///
+    /// Returns the view type to add the attachment with
+    /// or `None` if it must not be added as a file.
+    async fn handle_file_part_data(
+        &mut self,
+        context: &Context,
+        msg_type: Viewtype,
+        mime_type: &Mime,
+        decoded_data: &[u8],
+        filename: &str,
+    ) -> Result<Option<Viewtype>> {
+        if decoded_data.is_empty() {
+            return Ok(None);
+        }
         if let Some(peerstate) = &mut self.decryption_info.peerstate {
             if peerstate.prefer_encrypt != EncryptPreference::Mutual
                 && mime_type.type_() == mime::APPLICATION
                 && mime_type.subtype().as_str() == "pgp-keys"
                 && Self::try_set_peer_key_from_file_part(context, peerstate, decoded_data).await?
             {
-                return Ok(());
+                return Ok(None);
             }
         }
-        let msg_type = if context
+        if context
             .is_webxdc_file(filename, decoded_data)
             .await
             .unwrap_or(false)
         {
-            Viewtype::Webxdc
+            Ok(Some(Viewtype::Webxdc))
+        } else if self.attached_msg_depth > 0 {
+            // Files of attached messages are never control data for the outer message.
+            Ok(Some(msg_type))
         } else if filename.ends_with(".kml") {
             // XXX what if somebody sends eg an "location-highlights.kml"
             // attachment unrelated to location streaming?
@@ -1204,9 +1570,9 @@ async fn do_add_single_file_part(
                 } else {
                     self.message_kml = parsed;
                 }
-                return Ok(());
+                return Ok(None);
             }
-            msg_type
+            Ok(Some(msg_type))
         } else if filename == "multi-device-sync.json" {
             let serialized = String::from_utf8_lossy(decoded_data)
                 .parse()
@@ -1217,52 +1583,16 @@ async fn do_add_single_file_part(
                     warn!(context, "failed to parse sync data: {:#}", err);
                 })
                 .ok();
-            return Ok(());
+            Ok(None)
         } else if filename == "status-update.json" {
             let serialized = String::from_utf8_lossy(decoded_data)
                 .parse()
                 .unwrap_or_default();
             self.webxdc_status_update = Some(serialized);
-            return Ok(());
+            Ok(None)
         } else {
-            msg_type
-        };
-
-        /* we have a regular file attachment,
-        write decoded data to new blob object */
-
-        let blob = match BlobObject::create(context, filename, decoded_data).await {
-            Ok(blob) => blob,
-            Err(err) => {
-                error!(
-                    context,
-                    "Could not add blob for mime part {}, error {:#}", filename, err
-                );
-                return Ok(());
-            }
-        };
-        info!(context, "added blobfile: {:?}", blob.as_name());
-
-        /* create and register Mime part referencing the new Blob object */
-        let mut part = Part::default();
-        if mime_type.type_() == mime::IMAGE {
-            if let Ok((width, height)) = get_filemeta(decoded_data) {
-                part.param.set_int(Param::Width, width as i32);
-                part.param.set_int(Param::Height, height as i32);
-            }
+            Ok(Some(msg_type))
         }
-
-        part.typ = msg_type;
-        part.org_filename = Some(filename.to_string());
-        part.mimetype = Some(mime_type);
-        part.bytes = decoded_data.len();
-        part.param.set(Param::File, blob.as_name());
-        part.param.set(Param::Filename, filename);
-        part.param.set(Param::MimeType, raw_mime);
-        part.is_related = is_related;
-
-        self.do_add_single_part(part);
-        Ok(())
     }
 
     /// Returns whether a key from the attachment was set as peer's pubkey.
@@ -1351,6 +1681,58 @@ pub(crate) fn get_mailinglist_header(&self) -> Option<&str> {
         None
     }
 
+    /// Returns true if the message is an automatic reply, e.g. a vacation notice.
+    ///
+    /// Besides `Auto-Submitted: auto-replied` as described in
+    /// [RFC 3834](https://tools.ietf.org/html/rfc3834),
+    /// headers of common auto-responders not following the RFC are checked.
+    /// Messages of Delta Chat bots are `auto-generated`, not automatic replies.
+    pub(crate) fn is_auto_reply(&self) -> bool {
+        let auto_submitted = self
+            .get_header(HeaderDef::AutoSubmitted)
+            .map_or(false, |value| {
+                value
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .eq_ignore_ascii_case("auto-replied")
+            });
+        let precedence = self
+            .get_header(HeaderDef::Precedence)
+            .map_or(false, |value| {
+                value.trim().eq_ignore_ascii_case("auto_reply")
+            });
+        auto_submitted
+            || precedence
+            || self.get_header(HeaderDef::XAutoreply).is_some()
+            || self.get_header(HeaderDef::XAutorespond).is_some()
+    }
+
+    /// Returns true if an automatic reply to the message may be sent,
+    /// following the recommendations of [RFC 3834](https://tools.ietf.org/html/rfc3834).
+    ///
+    /// Automatically submitted messages, mailing list messages,
+    /// reports and system messages are not replied to.
+    pub(crate) fn may_be_auto_replied(&self) -> bool {
+        let auto_submitted = self
+            .get_header(HeaderDef::AutoSubmitted)
+            .map_or(false, |value| !value.trim().eq_ignore_ascii_case("no"));
+        let precedence = self
+            .get_header(HeaderDef::Precedence)
+            .map_or(false, |value| {
+                ["bulk", "list", "junk"].contains(&value.trim().to_lowercase().as_str())
+            });
+        !auto_submitted
+            && !precedence
+            && !self.is_auto_reply()
+            && !self.is_mailinglist_message()
+            && self.mdn_reports.is_empty()
+            && self.delivery_report.is_none()
+            && self.is_system_message == SystemMessage::Unknown
+            && !self.decrypting_failed
+    }
+
     pub(crate) fn is_mailinglist_message(&self) -> bool {
         self.get_mailinglist_header().is_some()
     }
@@ -1548,25 +1930,38 @@ fn maybe_remove_bad_parts(&mut self) {
             self.parts.retain(|p| !p.dehtml_failed);
         }
 
-        // remove images that are descendants of multipart/related but the first one:
-        // - for newsletters or so, that is often the logo
+        // remove images that are descendants of multipart/related but one used as a preview:
+        // - for newsletters or so, small images are often logos, icons or tracking pixels,
+        //   so the largest image is picked
         // - for user-generated html-mails, that may be some drag'n'drop photo,
-        //   so, the recipient sees at least the first image directly
-        // - all other images can be accessed by "show full message"
+        //   so, the recipient sees at least one image directly
+        // - all other images can be accessed by "show full message",
+        //   where they are inlined
         // - to ensure, there is such a button, we do removal only if
         //   `is_mime_modified` is set
         if !self.has_chat_version() && self.is_mime_modified {
-            fn is_related_image(p: &&Part) -> bool {
+            fn is_related_image(p: &Part) -> bool {
                 (p.typ == Viewtype::Image || p.typ == Viewtype::Gif) && p.is_related
             }
-            let related_image_cnt = self.parts.iter().filter(is_related_image).count();
+            let related_image_cnt = self.parts.iter().filter(|p| is_related_image(p)).count();
             if related_image_cnt > 1 {
-                let mut is_first_image = true;
+                let preview_index = self
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| is_related_image(p))
+                    // `max_by_key` returns the last maximum, reverse to prefer the first one.
+                    .rev()
+                    .max_by_key(|(_, p)| {
+                        let width = p.param.get_int(Param::Width).unwrap_or_default();
+                        let height = p.param.get_int(Param::Height).unwrap_or_default();
+                        i64::from(width) * i64::from(height)
+                    })
+                    .map(|(index, _)| index);
+                let mut index = 0;
                 self.parts.retain(|p| {
-                    let retain = is_first_image || !is_related_image(&p);
-                    if p.typ == Viewtype::Image || p.typ == Viewtype::Gif {
-                        is_first_image = false;
-                    }
+                    let retain = !is_related_image(p) || Some(index) == preview_index;
+                    index += 1;
                     retain
                 });
             }
@@ -1726,6 +2121,10 @@ async fn update_gossip_peerstates(
             );
             continue;
         }
+        if context.is_self_addr(&header.addr).await? {
+            // Our own key is never taken from gossip.
+            continue;
+        }
 
         let peerstate;
         if let Some(mut p) = Peerstate::from_addr(context, &header.addr).await? {
@@ -1910,6 +2309,21 @@ fn get_mime_type(
     Ok((mimetype, viewtype))
 }
 
+/// Returns a header block like `---------- Forwarded message ----------`
+/// with the sender, date and subject of an attached message.
+fn attached_msg_header(mail: &mailparse::ParsedMail<'_>) -> String {
+    let mut header = "---------- Forwarded message ----------".to_string();
+    for key in ["From", "Date", "Subject"] {
+        if let Some(value) = mail.headers.get_first_value(key) {
+            let value = value.trim();
+            if !value.is_empty() {
+                header += &format!("\n{key}: {value}");
+            }
+        }
+    }
+    header
+}
+
 fn is_attachment_disposition(mail: &mailparse::ParsedMail<'_>) -> bool {
     let ct = mail.get_content_disposition();
     ct.disposition == DispositionType::Attachment
@@ -1956,6 +2370,14 @@ fn get_attachment_filename(
         desired_filename = mail.ctype.params.get("name").map(|s| s.to_string());
     }
 
+    let mut desired_filename = desired_filename.and_then(|filename| {
+        let sanitized = sanitize_attachment_filename(&filename);
+        if sanitized.as_deref() != Some(filename.as_str()) {
+            warn!(context, "Sanitized attachment filename {filename:?}.");
+        }
+        sanitized
+    });
+
     // If there is no filename, but part is an attachment, guess filename
     if desired_filename.is_none() && ct.disposition == DispositionType::Attachment {
         if let Some(subtype) = mail.ctype.mimetype.split('/').nth(1) {
@@ -1968,11 +2390,40 @@ fn get_attachment_filename(
         };
     }
 
-    let desired_filename = desired_filename.map(|filename| strip_rtlo_characters(&filename));
-
     Ok(desired_filename)
 }
 
+/// Returns whether an attachment may be processed as control data
+/// by [`MimeMessage::handle_file_part_data`] and thus must be decoded into memory.
+fn may_be_control_data(filename: &str, mime_type: &Mime) -> bool {
+    filename.ends_with(WEBXDC_SUFFIX)
+        || filename.ends_with(".kml")
+        || filename.ends_with(".json")
+        || (mime_type.type_() == mime::APPLICATION && mime_type.subtype().as_str() == "pgp-keys")
+}
+
+/// Sanitizes an attachment filename taken from the MIME headers.
+///
+/// Only the last path component is kept so the name cannot be used for path traversal,
+/// control and right-to-left override characters are removed
+/// as well as trailing dots.
+/// Returns `None` if nothing but whitespace is left.
+fn sanitize_attachment_filename(filename: &str) -> Option<String> {
+    let filename = filename
+        .rsplit(['/', '\\'])
+        .find(|part| !part.trim().is_empty())?;
+    let filename: String = strip_rtlo_characters(filename)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    let filename = filename.trim_end_matches('.');
+    if filename.trim().is_empty() {
+        None
+    } else {
+        Some(filename.to_string())
+    }
+}
+
 /// Returned addresses are normalized and lowercased.
 pub(crate) fn get_recipients(headers: &[MailHeader]) -> Vec<SingleInfo> {
     get_all_addresses_from_header(headers, |header_key| {
@@ -1986,6 +2437,103 @@ pub(crate) fn get_from(headers: &[MailHeader]) -> Option<SingleInfo> {
     tools::single_value(all)
 }
 
+/// Splits a raw message into the header and the body,
+/// tolerating both CRLF and LF line endings.
+fn split_header_body(raw: &[u8]) -> (&[u8], &[u8]) {
+    let mut line_start = 0;
+    for (i, &byte) in raw.iter().enumerate() {
+        if byte != b'\n' {
+            continue;
+        }
+        let line = raw.get(line_start..i).unwrap_or_default();
+        if line.is_empty() || line == b"\r" {
+            return (
+                raw.get(..line_start).unwrap_or_default(),
+                raw.get(i + 1..).unwrap_or_default(),
+            );
+        }
+        line_start = i + 1;
+    }
+    (raw, &[])
+}
+
+/// Extracts header fields from a possibly broken header.
+///
+/// Folded lines are unfolded, lines which are no valid header fields are skipped
+/// and non-UTF-8 bytes are replaced. Keys are returned in lowercase.
+fn salvage_header_fields(header: &[u8]) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(header).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !key.is_empty() && key.bytes().all(|b| b.is_ascii_graphic()) {
+            fields.push((key.to_lowercase(), value.trim().to_string()));
+        }
+    }
+    fields
+}
+
+/// Returns the first non-empty `text/plain` part of a parsed MIME structure.
+fn first_text_part(mail: &mailparse::ParsedMail) -> Option<String> {
+    if mail.ctype.mimetype == "text/plain" {
+        let text = mail.get_body().ok()?;
+        return (!text.trim().is_empty()).then_some(text);
+    }
+    mail.subparts.iter().find_map(first_text_part)
+}
+
+/// Extracts the first text part from a body which could not be parsed.
+///
+/// For multipart bodies, the parts are split at the boundary
+/// without requiring the closing delimiter and parsed one by one.
+fn salvage_text(
+    content_type: &str,
+    content_transfer_encoding: &str,
+    body: &[u8],
+) -> Option<String> {
+    let ctype = mailparse::parse_content_type(content_type);
+    if ctype.mimetype.starts_with("multipart/") {
+        let boundary = ctype.params.get("boundary")?;
+        let delimiter = format!("--{boundary}");
+        let mut rest = body;
+        let mut parts = Vec::new();
+        while let Some(pos) = rest
+            .windows(delimiter.len())
+            .position(|window| window == delimiter.as_bytes())
+        {
+            parts.push(rest.get(..pos).unwrap_or_default());
+            rest = rest.get(pos + delimiter.len()..).unwrap_or_default();
+        }
+        parts.push(rest);
+        // Skip the preamble.
+        parts.into_iter().skip(1).find_map(|part| {
+            let part = part.strip_prefix(b"\r").unwrap_or(part);
+            let part = part.strip_prefix(b"\n")?;
+            first_text_part(&mailparse::parse_mail(part).ok()?)
+        })
+    } else if ctype.mimetype.starts_with("text/") || ctype.mimetype.parse::<Mime>().is_err() {
+        // Decode the body as a single text part, assuming text if the type is broken.
+        let mut part = format!(
+            "Content-Type: text/plain; charset=\"{}\"\r\nContent-Transfer-Encoding: {content_transfer_encoding}\r\n\r\n",
+            ctype.charset
+        )
+        .into_bytes();
+        part.extend_from_slice(body);
+        let text = mailparse::parse_mail(&part).ok()?.get_body().ok()?;
+        (!text.trim().is_empty()).then_some(text)
+    } else {
+        None
+    }
+}
+
 /// Returned addresses are normalized and lowercased.
 pub(crate) fn get_list_post(headers: &[MailHeader]) -> Option<String> {
     get_all_addresses_from_header(headers, |header_key| header_key == "list-post")
@@ -2143,6 +2691,13 @@ async fn handle_ndn(
     } else {
         "Delivery to at least one recipient failed.".to_string()
     };
+    if let Some(failed_recipient) = &failed.failed_recipient {
+        if let Some(contact_id) =
+            Contact::lookup_id_by_addr(context, failed_recipient, Origin::Unknown).await?
+        {
+            contact_id.set_last_error(context, &error).await?;
+        }
+    }
 
     let mut first = true;
     for msg in msgs {
@@ -2478,6 +3033,72 @@ async fn test_get_attachment_filename_combined() {
         assert_eq!(filename, Some("Maßnahmen Okt. 2020.html".to_string()))
     }
 
+    #[test]
+    fn test_sanitize_attachment_filename() {
+        assert_eq!(
+            sanitize_attachment_filename("../../.ssh/authorized_keys"),
+            Some("authorized_keys".to_string())
+        );
+        assert_eq!(
+            sanitize_attachment_filename("C:\\Users\\me\\report.pdf"),
+            Some("report.pdf".to_string())
+        );
+        assert_eq!(
+            sanitize_attachment_filename("dir/file.txt/"),
+            Some("file.txt".to_string())
+        );
+        assert_eq!(
+            sanitize_attachment_filename(".hidden\u{0}\r\n.txt."),
+            Some(".hidden.txt".to_string())
+        );
+        assert_eq!(
+            sanitize_attachment_filename("harmless_file.\u{202e}txt.exe"),
+            Some("harmless_file.txt.exe".to_string())
+        );
+        assert_eq!(sanitize_attachment_filename(".."), None);
+        assert_eq!(sanitize_attachment_filename("/ /"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_attachment_filename_path_traversal() -> Result<()> {
+        let t = TestContext::new().await;
+        let raw = b"From: sender@testrun.org\n\
+            To: recp@testrun.org\n\
+            Subject: Files\n\
+            Message-ID: <traversal@testrun.org>\n\
+            Date: Sat, 07 Dec 2019 19:00:27 +0000\n\
+            Content-Type: multipart/mixed; boundary=\"==BREAK==\"\n\
+            \n\
+            --==BREAK==\n\
+            Content-Type: application/octet-stream\n\
+            Content-Disposition: attachment; filename=\"../../report.txt\"\n\
+            \n\
+            first\n\
+            --==BREAK==\n\
+            Content-Type: application/octet-stream\n\
+            Content-Disposition: attachment;\n\
+            \tfilename*0*=utf-8''..%2F..%2Frep;\n\
+            \tfilename*1=ort.txt\n\
+            \n\
+            second\n\
+            --==BREAK==--\n";
+        let mime = MimeMessage::from_bytes(&t, &raw[..], None).await?;
+        assert_eq!(mime.parts.len(), 2);
+        for part in &mime.parts {
+            assert_eq!(part.param.get(Param::Filename), Some("report.txt"));
+            assert_eq!(part.org_filename.as_deref(), Some("report.txt"));
+            let blob = part.param.get(Param::File).unwrap();
+            assert!(blob.starts_with("$BLOBDIR/report"));
+            assert!(blob.ends_with(".txt"));
+        }
+        // Attachments with the same name are stored in different blobs.
+        assert_ne!(
+            mime.parts[0].param.get(Param::File),
+            mime.parts[1].param.get(Param::File)
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_mailparse_content_type() {
         let ctype =
@@ -2635,6 +3256,43 @@ async fn test_mimeparser_with_avatars() {
         assert!(mimeparser.group_avatar.unwrap().is_change());
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_avatar_in_header() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let msg_with_avatar = |avatar: &[u8]| {
+            format!(
+                "From: Bob <bob@example.net>\n\
+                 To: alice@example.org\n\
+                 Chat-Version: 1.0\n\
+                 Chat-User-Avatar: base64:{}\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 Hello\n",
+                base64::engine::general_purpose::STANDARD.encode(avatar)
+            )
+        };
+
+        // The same avatar is stored only once.
+        let avatar = include_bytes!("../test-data/image/avatar64x64.png");
+        let raw = msg_with_avatar(avatar);
+        let mimeparser = MimeMessage::from_bytes(&t, raw.as_bytes(), None).await?;
+        let Some(AvatarAction::Change(first_blob)) = mimeparser.user_avatar else {
+            panic!("no avatar");
+        };
+        assert!(first_blob.ends_with(".png"));
+        let mimeparser = MimeMessage::from_bytes(&t, raw.as_bytes(), None).await?;
+        assert_eq!(
+            mimeparser.user_avatar,
+            Some(AvatarAction::Change(first_blob))
+        );
+
+        // Too large avatars are ignored.
+        let raw = msg_with_avatar(&vec![0; MAX_RECEIVED_AVATAR_BYTES + 1]);
+        let mimeparser = MimeMessage::from_bytes(&t, raw.as_bytes(), None).await?;
+        assert_eq!(mimeparser.user_avatar, None);
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_mimeparser_with_videochat() {
         let t = TestContext::new_alice().await;
@@ -3704,23 +4362,156 @@ async fn test_receive_eml() -> Result<()> {
         )
         .await?;
 
+        // The attached message is shown below the text instead of as an .eml file.
         assert_eq!(mime_message.parts.len(), 1);
-        assert_eq!(mime_message.parts[0].typ, Viewtype::File);
-        assert_eq!(
-            mime_message.parts[0].mimetype,
-            Some("message/rfc822".parse().unwrap(),)
-        );
-        assert_eq!(
-            mime_message.parts[0].msg,
-            "this is a classic email – I attached the .EML file".to_string()
-        );
+        assert_eq!(mime_message.parts[0].typ, Viewtype::Text);
+        let text = &mime_message.parts[0].msg;
+        assert!(text.starts_with(
+            "this is a classic email – I attached the .EML file\n\n\
+             ---------- Forwarded message ----------\n\
+             From: adb <bob@disrooot.org>\n\
+             Date: Fri, 26 Aug 2022 18:42:18 +0000\n\n"
+        ));
+        assert!(text.contains("hello from disroot"));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_attached_message_with_attachment() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let raw = b"From: bob@example.net\n\
+            To: alice@example.org\n\
+            Subject: Fwd: Report\n\
+            Message-ID: <fwd-report@example.net>\n\
+            Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\n\
+            \n\
+            --outer\n\
+            Content-Type: text/plain\n\
+            \n\
+            see below\n\
+            --outer\n\
+            Content-Type: message/rfc822\n\
+            Content-Disposition: attachment; filename=\"report.eml\"\n\
+            \n\
+            From: Charlie <charlie@example.com>\n\
+            Subject: =?utf-8?q?Monatsbericht_M=C3=A4rz?=\n\
+            Content-Type: multipart/mixed; boundary=\"inner\"\n\
+            \n\
+            --inner\n\
+            Content-Type: text/plain\n\
+            \n\
+            the report is attached\n\
+            --inner\n\
+            Content-Type: application/pdf\n\
+            Content-Disposition: attachment; filename=\"report.pdf\"\n\
+            \n\
+            %PDF-1.4\n\
+            --inner--\n\
+            --outer--\n";
+        let mime_message = MimeMessage::from_bytes(&t, &raw[..], None).await?;
+
+        // The attachment of the attached message is extracted
+        // and the text is squashed into it.
+        assert_eq!(mime_message.parts.len(), 1);
+        let part = &mime_message.parts[0];
+        assert_eq!(part.typ, Viewtype::File);
+        assert_eq!(part.org_filename.as_deref(), Some("report.pdf"));
         assert_eq!(
-            mime_message.parts[0].param.get(Param::File),
-            Some("$BLOBDIR/.eml")
+            part.msg,
+            "see below\n\n\
+             ---------- Forwarded message ----------\n\
+             From: Charlie <charlie@example.com>\n\
+             Subject: Monatsbericht März\n\n\
+             the report is attached"
         );
+        Ok(())
+    }
 
-        assert_eq!(mime_message.parts[0].org_filename, Some(".eml".to_string()));
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_attached_message_with_report() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let raw = b"From: bob@example.net\n\
+            To: alice@example.org\n\
+            Subject: Fwd: Read receipt\n\
+            Message-ID: <fwd-mdn@example.net>\n\
+            Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\n\
+            \n\
+            --outer\n\
+            Content-Type: text/plain\n\
+            \n\
+            what does this mean?\n\
+            --outer\n\
+            Content-Type: message/rfc822\n\
+            Content-Disposition: attachment; filename=\"receipt.eml\"\n\
+            \n\
+            From: Charlie <charlie@example.com>\n\
+            Subject: Read receipt\n\
+            Content-Type: multipart/report; report-type=disposition-notification; boundary=\"inner\"\n\
+            \n\
+            --inner\n\
+            Content-Type: text/plain\n\
+            \n\
+            The message was displayed.\n\
+            --inner\n\
+            Content-Type: message/disposition-notification\n\
+            \n\
+            Original-Recipient: rfc822;charlie@example.com\n\
+            Final-Recipient: rfc822;charlie@example.com\n\
+            Original-Message-ID: <foo@example.org>\n\
+            Disposition: manual-action/MDN-sent-automatically; displayed\n\
+            --inner--\n\
+            --outer--\n";
+        let mime_message = MimeMessage::from_bytes(&t, &raw[..], None).await?;
+
+        // The attached report is not treated as a report for the outer message.
+        assert!(mime_message.mdn_reports.is_empty());
+        assert!(mime_message.delivery_report.is_none());
+        assert_eq!(mime_message.parts.len(), 1);
+        let part = &mime_message.parts[0];
+        assert_eq!(part.typ, Viewtype::Text);
+        assert!(part.msg.starts_with("what does this mean?"));
+        assert!(part.msg.contains("The message was displayed."));
+        Ok(())
+    }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_attached_message_max_depth() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let mut raw = "From: bob@example.net\n\
+            Subject: Innermost\n\
+            \n\
+            innermost text\n"
+            .to_string();
+        for level in 1..=MAX_ATTACHED_MSG_DEPTH + 1 {
+            raw = format!(
+                "From: bob@example.net\n\
+                 Subject: Level {level}\n\
+                 Content-Type: multipart/mixed; boundary=\"b{level}\"\n\
+                 \n\
+                 --b{level}\n\
+                 Content-Type: text/plain\n\
+                 \n\
+                 level {level}\n\
+                 --b{level}\n\
+                 Content-Type: message/rfc822\n\
+                 Content-Disposition: attachment; filename=\"fwd.eml\"\n\
+                 \n\
+                 {raw}\n\
+                 --b{level}--\n"
+            );
+        }
+        let mime_message = MimeMessage::from_bytes(&t, raw.as_bytes(), None).await?;
+
+        // Messages nested too deep are kept as .eml file.
+        assert_eq!(mime_message.parts.len(), 1);
+        let part = &mime_message.parts[0];
+        assert_eq!(part.typ, Viewtype::File);
+        assert_eq!(part.org_filename.as_deref(), Some("fwd.eml"));
+        assert!(part.msg.contains("Subject: Level 1\n\nlevel 1"));
+        assert!(!part.msg.contains("innermost text"));
         Ok(())
     }
 
@@ -3777,4 +4568,82 @@ async fn test_jpeg_as_application_octet_stream() -> Result<()> {
 
         Ok(())
     }
+
+    #[test]
+    fn test_salvage_header_fields() {
+        let raw = b"From: a@example.org\r\nSubject: Hello\r\n  world\r\nbroken line\r\nX-8bit: Gr\xfc\xdfe\r\n\r\nbody\r\n";
+        let (header, body) = split_header_body(raw);
+        assert_eq!(body, b"body\r\n");
+        let fields = salvage_header_fields(header);
+        assert_eq!(
+            fields,
+            vec![
+                ("from".to_string(), "a@example.org".to_string()),
+                ("subject".to_string(), "Hello world".to_string()),
+                ("x-8bit".to_string(), "Gr\u{fffd}\u{fffd}e".to_string()),
+            ]
+        );
+
+        let (header, body) = split_header_body(b"Subject: no body");
+        assert_eq!(header, b"Subject: no body");
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn test_salvage_text() {
+        assert_eq!(
+            salvage_text("text/plain; charset=utf-8", "base64", b"aGVsbG8=\n").unwrap(),
+            "hello"
+        );
+        assert_eq!(
+            salvage_text(
+                "multipart/alternative; boundary=b",
+                "8bit",
+                b"preamble\n--b\nContent-Type: text/html\n\n<p>hi</p>\n--b\n\nhi\n"
+            )
+            .unwrap(),
+            "hi\n"
+        );
+        assert!(salvage_text("multipart/mixed", "8bit", b"--b\n\nhi\n").is_none());
+        assert!(salvage_text("image/png", "base64", b"AAAA").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_large_attachment_streamed() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let data: Vec<u8> = (0..2_000_000u32).map(|i| (i % 253) as u8).collect();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
+        let mut raw = "From: Bob <bob@example.net>\n\
+                       To: alice@example.org\n\
+                       Subject: Large file\n\
+                       Message-ID: <large@example.net>\n\
+                       Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                       Content-Type: multipart/mixed; boundary=\"b\"\n\
+                       \n\
+                       --b\n\
+                       Content-Type: text/plain; charset=utf-8\n\
+                       \n\
+                       Here is the file.\n\
+                       --b\n\
+                       Content-Type: application/octet-stream\n\
+                       Content-Disposition: attachment; filename=\"large.bin\"\n\
+                       Content-Transfer-Encoding: base64\n\
+                       \n"
+        .to_string();
+        for line in encoded.as_bytes().chunks(76) {
+            raw += std::str::from_utf8(line)?;
+            raw += "\n";
+        }
+        raw += "--b--\n";
+
+        let mimeparser = MimeMessage::from_bytes(&t, raw.as_bytes(), None).await?;
+        assert_eq!(mimeparser.parts.len(), 1);
+        let part = &mimeparser.parts[0];
+        assert_eq!(part.typ, Viewtype::File);
+        assert_eq!(part.msg, "Here is the file.");
+        assert_eq!(part.bytes, data.len());
+        let blob = BlobObject::from_name(&t, part.param.get(Param::File).unwrap().to_string())?;
+        assert_eq!(tokio::fs::read(blob.to_abs_path()).await?, data);
+        Ok(())
+    }
 }
@@ -0,0 +1,397 @@
+//! # Receiving large messages in chunks.
+//!
+//! Large messages are fetched from the server in chunks.
+//! [`MessageStreamer`] follows the MIME structure of the chunks as they arrive
+//! and decodes the bodies of base64-encoded attachments directly into blob files,
+//! so only the headers and the text parts of the message are kept in memory.
+//! The bodies are replaced by random placeholders
+//! which [`MimeMessage`](super::MimeMessage) resolves to the blob files.
+//!
+//! Parts of signed or encrypted entities and attached messages are kept as they are,
+//! they are verified, decrypted or parsed as a whole.
+
+use std::collections::HashMap;
+
+use mailparse::MailHeaderMap;
+
+use super::{get_attachment_filename, get_mime_type, may_be_control_data};
+use crate::blob::{Base64BlobWriter, BlobObject};
+use crate::context::Context;
+use crate::dkim::{BodyHash, BodyHashers};
+use crate::message::Viewtype;
+use crate::tools::create_id;
+
+/// A message received with [`MessageStreamer`].
+#[derive(Debug, Default)]
+pub(crate) struct StreamedMessage {
+    /// The message with the bodies of the attachments decoded into blob files
+    /// replaced by placeholders.
+    pub raw: Vec<u8>,
+
+    /// Blob names and sizes of the decoded attachments by placeholder.
+    pub attachments: HashMap<String, (String, usize)>,
+
+    /// Hashes of the original message body for the DKIM verification.
+    pub dkim_body_hashes: Vec<BodyHash>,
+}
+
+/// State of the MIME entity being received.
+#[derive(Debug)]
+enum State<'a> {
+    /// Receiving the header of an entity.
+    Header(Vec<u8>),
+
+    /// Copying the body of an entity to the message.
+    Body,
+
+    /// Decoding the body of an attachment into a blob file.
+    Attachment(Base64BlobWriter<'a>),
+
+    /// Skipping the rest of an attachment which could not be decoded.
+    Skip,
+}
+
+/// Receives a message in chunks, see the [module documentation](self).
+#[derive(Debug)]
+pub(crate) struct MessageStreamer<'a> {
+    context: &'a Context,
+    state: State<'a>,
+
+    /// Boundaries of the enclosing multipart entities, innermost last.
+    boundaries: Vec<String>,
+
+    /// Incomplete last line of the previous chunk.
+    line: Vec<u8>,
+
+    /// DKIM body hashers, set once the message header is received.
+    body_hashers: Option<BodyHashers>,
+
+    message: StreamedMessage,
+}
+
+impl<'a> MessageStreamer<'a> {
+    pub(crate) fn new(context: &'a Context) -> Self {
+        Self {
+            context,
+            state: State::Header(Vec::new()),
+            boundaries: Vec::new(),
+            line: Vec::new(),
+            body_hashers: None,
+            message: StreamedMessage::default(),
+        }
+    }
+
+    /// Processes the next chunk of the raw message.
+    pub(crate) async fn push(&mut self, mut chunk: &[u8]) {
+        while let Some(i) = chunk.iter().position(|&b| b == b'\n') {
+            let (line, rest) = chunk.split_at(i + 1);
+            if self.line.is_empty() {
+                self.process_line(line).await;
+            } else {
+                let mut buffered = std::mem::take(&mut self.line);
+                buffered.extend_from_slice(line);
+                self.process_line(&buffered).await;
+            }
+            chunk = rest;
+        }
+        self.line.extend_from_slice(chunk);
+    }
+
+    /// Finishes receiving the message.
+    pub(crate) async fn finish(mut self) -> StreamedMessage {
+        let line = std::mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.process_line(&line).await;
+        }
+        self.finish_entity().await;
+        if let Some(body_hashers) = self.body_hashers {
+            self.message.dkim_body_hashes = body_hashers.finish();
+        }
+        self.message
+    }
+
+    /// Removes the blob files of the attachments decoded so far.
+    pub(crate) async fn abort(self) {
+        if let State::Attachment(writer) = self.state {
+            writer.abort().await;
+        }
+        for (name, _) in self.message.attachments.into_values() {
+            if let Ok(blob) = BlobObject::from_name(self.context, name) {
+                tokio::fs::remove_file(blob.to_abs_path()).await.ok();
+            }
+        }
+    }
+
+    async fn process_line(&mut self, line: &[u8]) {
+        if let Some(body_hashers) = &mut self.body_hashers {
+            body_hashers.update(line);
+        }
+        if let Some((i, is_closing)) = self.find_boundary(line) {
+            self.finish_entity().await;
+            if is_closing {
+                self.boundaries.truncate(i);
+                self.state = State::Body;
+            } else {
+                self.boundaries.truncate(i + 1);
+                self.state = State::Header(Vec::new());
+            }
+            self.message.raw.extend_from_slice(line);
+            return;
+        }
+
+        match &mut self.state {
+            State::Header(header) => {
+                header.extend_from_slice(line);
+                if line == b"\r\n" || line == b"\n" {
+                    let header = std::mem::take(header);
+                    self.message.raw.extend_from_slice(&header);
+                    if self.body_hashers.is_none() {
+                        self.body_hashers = Some(BodyHashers::new(&header));
+                    }
+                    self.state = self.start_body(&header).await;
+                }
+            }
+            State::Body => self.message.raw.extend_from_slice(line),
+            State::Attachment(writer) => {
+                if let Err(err) = writer.write(line).await {
+                    warn!(
+                        self.context,
+                        "Could not decode attachment into blob: {err:#}."
+                    );
+                    if let State::Attachment(writer) =
+                        std::mem::replace(&mut self.state, State::Skip)
+                    {
+                        writer.abort().await;
+                    }
+                }
+            }
+            State::Skip => {}
+        }
+    }
+
+    /// Returns the index of the multipart boundary delimited by `line`
+    /// and whether it is the closing delimiter.
+    fn find_boundary(&self, line: &[u8]) -> Option<(usize, bool)> {
+        let line = line.strip_prefix(b"--")?;
+        let end = line
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        let line = line.get(..end).unwrap_or_default();
+        self.boundaries
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(
+                |(i, boundary)| match line.strip_prefix(boundary.as_bytes())? {
+                    b"" => Some((i, false)),
+                    b"--" => Some((i, true)),
+                    _ => None,
+                },
+            )
+    }
+
+    /// Returns the state for the body of an entity with the given header.
+    ///
+    /// Only the bodies of base64-encoded attachments which cannot contain control data
+    /// are decoded into blob files.
+    async fn start_body(&mut self, header: &[u8]) -> State<'a> {
+        let Ok(mail) = mailparse::parse_mail(header) else {
+            return State::Body;
+        };
+        let mimetype = mail.ctype.mimetype.to_lowercase();
+        if let Some(subtype) = mimetype.strip_prefix("multipart/") {
+            if subtype != "signed" && subtype != "encrypted" {
+                if let Some(boundary) = mail.ctype.params.get("boundary") {
+                    self.boundaries.push(boundary.clone());
+                }
+            }
+            return State::Body;
+        }
+        if mimetype.starts_with("message/")
+            || !mail
+                .headers
+                .get_first_value("Content-Transfer-Encoding")
+                .map_or(false, |encoding| {
+                    encoding.trim().eq_ignore_ascii_case("base64")
+                })
+        {
+            return State::Body;
+        }
+        let Ok(Some(filename)) = get_attachment_filename(self.context, &mail) else {
+            return State::Body;
+        };
+        let Ok((mime_type, viewtype)) = get_mime_type(&mail, &Some(filename.clone())) else {
+            return State::Body;
+        };
+        if matches!(viewtype, Viewtype::Unknown | Viewtype::Text)
+            || may_be_control_data(&filename, &mime_type)
+        {
+            return State::Body;
+        }
+        match Base64BlobWriter::new(self.context, &filename).await {
+            Ok(writer) => State::Attachment(writer),
+            Err(err) => {
+                warn!(
+                    self.context,
+                    "Could not create blob for {filename}: {err:#}."
+                );
+                State::Body
+            }
+        }
+    }
+
+    /// Finishes the entity being received before a boundary or at the end of the message.
+    async fn finish_entity(&mut self) {
+        match std::mem::replace(&mut self.state, State::Body) {
+            State::Header(header) => self.message.raw.extend_from_slice(&header),
+            State::Attachment(writer) => match writer.finish().await {
+                Ok((blob, bytes)) => {
+                    let placeholder = create_id();
+                    self.message.raw.extend_from_slice(placeholder.as_bytes());
+                    self.message.raw.extend_from_slice(b"\r\n");
+                    self.message
+                        .attachments
+                        .insert(placeholder, (blob.as_name().to_string(), bytes));
+                }
+                Err(err) => warn!(
+                    self.context,
+                    "Could not decode attachment into blob: {err:#}."
+                ),
+            },
+            State::Body | State::Skip => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine as _;
+
+    use super::*;
+    use crate::dkim;
+    use crate::mimeparser::MimeMessage;
+    use crate::param::Param;
+    use crate::test_utils::TestContext;
+
+    fn large_attachment_msg(data: &[u8]) -> String {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        format!(
+            "From: alice@example.org\r\n\
+             To: bob@example.net\r\n\
+             Subject: Large file\r\n\
+             Message-ID: <large@example.org>\r\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+             \r\n\
+             --outer\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             \r\n\
+             Here is the file.\r\n\
+             --outer\r\n\
+             Content-Type: application/octet-stream; name=\"data.bin\"\r\n\
+             Content-Disposition: attachment; filename=\"data.bin\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n\
+             {wrapped}\r\n\
+             --outer\r\n\
+             Content-Type: application/vnd.google-earth.kml+xml\r\n\
+             Content-Disposition: attachment; filename=\"location.kml\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n\
+             {kml}\r\n\
+             --outer--\r\n",
+            kml = base64::engine::general_purpose::STANDARD.encode("<kml/>"),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stream_attachment() {
+        let t = TestContext::new_bob().await;
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let raw = large_attachment_msg(&data);
+
+        for chunk_size in [1000, 65536, raw.len()] {
+            let mut streamer = MessageStreamer::new(&t);
+            for chunk in raw.as_bytes().chunks(chunk_size) {
+                streamer.push(chunk).await;
+            }
+            let streamed = streamer.finish().await;
+            assert_eq!(streamed.attachments.len(), 1);
+            assert!(streamed.raw.len() < 2000);
+            // Control data is kept in the message.
+            assert!(String::from_utf8_lossy(&streamed.raw).contains("location.kml"));
+
+            let mime_message = MimeMessage::from_streamed(&t, &streamed).await.unwrap();
+            assert!(mime_message
+                .parts
+                .iter()
+                .any(|part| part.msg.contains("Here is the file.")));
+            let file_part = mime_message
+                .parts
+                .iter()
+                .find(|part| part.typ == Viewtype::File)
+                .unwrap();
+            assert_eq!(file_part.bytes, data.len());
+            assert_eq!(file_part.org_filename.as_deref(), Some("data.bin"));
+            let blob =
+                BlobObject::from_name(&t, file_part.param.get(Param::File).unwrap().to_string())
+                    .unwrap();
+            assert_eq!(tokio::fs::read(blob.to_abs_path()).await.unwrap(), data);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stream_dkim_body_hash() {
+        let t = TestContext::new_bob().await;
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let raw = large_attachment_msg(&data);
+        let raw = format!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/simple; d=example.org; s=sel;\r\n\
+             \th=from; bh=; b=\r\n{raw}"
+        );
+
+        let mut streamer = MessageStreamer::new(&t);
+        for chunk in raw.as_bytes().chunks(1000) {
+            streamer.push(chunk).await;
+        }
+        let streamed = streamer.finish().await;
+        assert_eq!(streamed.dkim_body_hashes.len(), 1);
+
+        // The hash covers the original body, not the one with the placeholders.
+        let (header, body) = raw.split_once("\r\n\r\n").unwrap();
+        let mut body_hashers = dkim::BodyHashers::new(format!("{header}\r\n\r\n").as_bytes());
+        body_hashers.update(body.as_bytes());
+        assert_eq!(streamed.dkim_body_hashes, body_hashers.finish());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stream_abort() {
+        let t = TestContext::new_bob().await;
+        let data = vec![7u8; 100_000];
+        let raw = large_attachment_msg(&data);
+
+        let blob_count = || async {
+            let mut dir = tokio::fs::read_dir(t.get_blobdir()).await.unwrap();
+            let mut count = 0;
+            while dir.next_entry().await.unwrap().is_some() {
+                count += 1;
+            }
+            count
+        };
+        let count = blob_count().await;
+
+        let mut streamer = MessageStreamer::new(&t);
+        streamer.push(&raw.as_bytes()[..raw.len() / 2]).await;
+        assert_eq!(blob_count().await, count + 1);
+        streamer.abort().await;
+        assert_eq!(blob_count().await, count);
+    }
+}
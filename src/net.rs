@@ -4,11 +4,12 @@
 use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::{Context as _, Error, Result};
+use anyhow::{ensure, Context as _, Error, Result};
 use tokio::net::{lookup_host, TcpStream};
 use tokio::time::timeout;
 use tokio_io_timeout::TimeoutStream;
 
+use crate::config::Config;
 use crate::context::Context;
 use crate::tools::time;
 
@@ -18,6 +19,33 @@
 
 pub use http::{read_url, read_url_blob, Response as HttpResponse};
 
+/// SOCKS5 proxy configured in the system settings.
+///
+/// The platform layer can supply it with [`Context::set_system_proxy`]
+/// so that users do not have to enter it for every account.
+/// Other proxy types are not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemProxy {
+    /// Proxy hostname or IP address.
+    pub host: String,
+
+    /// Proxy port.
+    pub port: u16,
+
+    /// Username and password if the proxy requires authentication.
+    pub user_password: Option<(String, String)>,
+}
+
+/// Returns an error if [`Config::Offline`] is set,
+/// so that no connections are opened while the account stays offline.
+pub(crate) async fn ensure_online(context: &Context) -> Result<()> {
+    ensure!(
+        !context.get_config_bool(Config::Offline).await?,
+        "The account is offline"
+    );
+    Ok(())
+}
+
 async fn connect_tcp_inner(addr: SocketAddr, timeout_val: Duration) -> Result<TcpStream> {
     let tcp_stream = timeout(timeout_val, TcpStream::connect(addr))
         .await
@@ -143,6 +171,7 @@ pub(crate) async fn connect_tcp(
     timeout_val: Duration,
     load_cache: bool,
 ) -> Result<Pin<Box<TimeoutStream<TcpStream>>>> {
+    ensure_online(context).await?;
     let mut tcp_stream = None;
     let mut last_error = None;
 
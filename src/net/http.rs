@@ -7,6 +7,7 @@
 use once_cell::sync::Lazy;
 
 use crate::context::Context;
+use crate::net::ensure_online;
 use crate::socks::Socks5Config;
 
 const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
@@ -60,7 +61,8 @@ pub async fn read_url_blob(context: &Context, url: &str) -> Result<Response> {
 }
 
 async fn read_url_inner(context: &Context, url: &str) -> Result<reqwest::Response> {
-    let socks5_config = Socks5Config::from_database(&context.sql).await?;
+    ensure_online(context).await?;
+    let socks5_config = Socks5Config::from_context(context).await?;
     let client = get_client(socks5_config)?;
     let mut url = url.to_string();
 
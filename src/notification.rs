@@ -0,0 +1,192 @@
+//! # Notification texts.
+//!
+//! Texts of notifications about incoming messages are built by the core,
+//! so that all UIs show the same localized texts
+//! and respect [`Config::NotifyPrivacy`] and muted chats.
+
+use anyhow::Result;
+use num_traits::FromPrimitive;
+use serde::Serialize;
+
+use crate::chat::{Chat, ChatId};
+use crate::config::Config;
+use crate::constants::{Blocked, Chattype, NotifyPrivacy};
+use crate::contact::{Contact, ContactId};
+use crate::context::Context;
+use crate::message::{Message, MessageState, MsgId};
+use crate::stock_str;
+use crate::summary::Summary;
+
+/// Approximate maximum number of characters of the notification body.
+const NOTIFICATION_BODY_CHARS: usize = 250;
+
+/// Notification about an incoming message, see [`get_notification`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Notification {
+    /// Chat the message belongs to.
+    pub chat_id: ChatId,
+
+    /// The message.
+    pub msg_id: MsgId,
+
+    /// Title of the notification, usually the chat name.
+    pub title: String,
+
+    /// Body of the notification, usually the message summary,
+    /// prefixed by the sender name in groups.
+    ///
+    /// Empty if [`NotifyPrivacy::HideAll`] is set.
+    pub body: String,
+
+    /// True if the message mentions the user in a group,
+    /// i.e. quotes an own message or contains the own address or `@` followed by the own name.
+    ///
+    /// UIs may highlight these notifications.
+    pub is_mention: bool,
+}
+
+/// Returns the notification to show for an incoming message.
+///
+/// `None` is returned if no notification should be shown,
+/// e.g. because the message is already seen, sent by self or the chat is muted.
+pub async fn get_notification(context: &Context, msg_id: MsgId) -> Result<Option<Notification>> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    if msg.from_id == ContactId::SELF || msg.state != MessageState::InFresh || msg.hidden {
+        return Ok(None);
+    }
+    let chat = Chat::load_from_db(context, msg.chat_id).await?;
+    if chat.blocked == Blocked::Yes || chat.is_spam() || chat.is_muted() {
+        return Ok(None);
+    }
+
+    let is_group = chat.typ != Chattype::Single;
+    let is_mention = is_group && is_mention(context, &msg).await?;
+    let privacy = NotifyPrivacy::from_i32(context.get_config_int(Config::NotifyPrivacy).await?)
+        .unwrap_or_default();
+    let (title, body) = match privacy {
+        NotifyPrivacy::ShowAll => {
+            let contact = Contact::get_by_id(context, msg.from_id).await?;
+            let summary = Summary::new(context, &msg, &chat, Some(&contact)).await;
+            let text = summary.truncated_text(NOTIFICATION_BODY_CHARS);
+            let body = match summary.prefix {
+                Some(prefix) => format!("{prefix}: {text}"),
+                None => text.to_string(),
+            };
+            (chat.get_name().to_string(), body)
+        }
+        NotifyPrivacy::HideContent => (
+            chat.get_name().to_string(),
+            stock_str::new_msg_notification(context).await,
+        ),
+        NotifyPrivacy::HideAll => (
+            stock_str::new_msg_notification(context).await,
+            String::new(),
+        ),
+    };
+
+    Ok(Some(Notification {
+        chat_id: chat.id,
+        msg_id,
+        title,
+        body,
+        is_mention,
+    }))
+}
+
+/// Returns true if the message quotes an own message,
+/// contains the own address or `@` followed by the own name.
+async fn is_mention(context: &Context, msg: &Message) -> Result<bool> {
+    if let Some(quote) = msg.quoted_message(context).await? {
+        if quote.from_id == ContactId::SELF {
+            return Ok(true);
+        }
+    }
+
+    let text = msg.get_text().to_lowercase();
+    let self_addr = context.get_primary_self_addr().await?;
+    if text.contains(&self_addr.to_lowercase()) {
+        return Ok(true);
+    }
+    if let Some(name) = context
+        .get_config(Config::Displayname)
+        .await?
+        .filter(|name| !name.is_empty())
+    {
+        if text.contains(&format!("@{}", name.to_lowercase())) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{create_group_chat, send_text_msg, MuteDuration, ProtectionStatus};
+    use crate::message::Viewtype;
+    use crate::test_utils::TestContextManager;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_notification() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "Hi Alice").await;
+        let msg = alice.recv_msg(&sent).await;
+        let notification = get_notification(&alice, msg.id).await?.unwrap();
+        assert_eq!(notification.chat_id, msg.chat_id);
+        assert_eq!(notification.title, "bob@example.net");
+        assert_eq!(notification.body, "Hi Alice");
+        assert!(!notification.is_mention);
+
+        alice.set_config(Config::NotifyPrivacy, Some("1")).await?;
+        let notification = get_notification(&alice, msg.id).await?.unwrap();
+        assert_eq!(notification.title, "bob@example.net");
+        assert_eq!(notification.body, "New message");
+
+        alice.set_config(Config::NotifyPrivacy, Some("2")).await?;
+        let notification = get_notification(&alice, msg.id).await?.unwrap();
+        assert_eq!(notification.title, "New message");
+        assert_eq!(notification.body, "");
+
+        alice.set_config(Config::NotifyPrivacy, Some("0")).await?;
+        crate::chat::set_muted(&alice, msg.chat_id, MuteDuration::Forever).await?;
+        assert!(get_notification(&alice, msg.id).await?.is_none());
+
+        msg.chat_id.accept(&alice).await?;
+        let mut reply = Message::new(Viewtype::Text);
+        reply.set_text("Hi Bob".to_string());
+        let sent = alice.send_msg(msg.chat_id, &mut reply).await;
+        assert!(get_notification(&alice, sent.sender_msg_id)
+            .await?
+            .is_none());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_notification_mention() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        alice.set_config(Config::Displayname, Some("Alice")).await?;
+
+        let bob_chat_id = create_group_chat(&bob, ProtectionStatus::Unprotected, "Group").await?;
+        let alice_contact_id = bob.add_or_lookup_contact(&alice).await.id;
+        crate::chat::add_contact_to_chat(&bob, bob_chat_id, alice_contact_id).await?;
+
+        send_text_msg(&bob, bob_chat_id, "Hi all".to_string()).await?;
+        let msg = alice.recv_msg(&bob.pop_sent_msg().await).await;
+        let notification = get_notification(&alice, msg.id).await?.unwrap();
+        assert_eq!(notification.title, "Group");
+        assert_eq!(notification.body, "bob@example.net: Hi all");
+        assert!(!notification.is_mention);
+
+        send_text_msg(&bob, bob_chat_id, "Any news, @alice?".to_string()).await?;
+        let msg = alice.recv_msg(&bob.pop_sent_msg().await).await;
+        let notification = get_notification(&alice, msg.id).await?.unwrap();
+        assert!(notification.is_mention);
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 //! OAuth 2 module.
+//!
+//! Supported authorizers are listed in [`Oauth2Authorizer`],
+//! the provider database tells which authorizer is used for a domain.
 
 use std::collections::HashMap;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::Deserialize;
 
-use crate::config::Config;
+use crate::config::{Config, InternalConfig};
+use crate::constants::DC_LP_AUTH_OAUTH2;
 use crate::context::Context;
 use crate::provider;
 use crate::provider::Oauth2Authorizer;
@@ -61,11 +65,14 @@ pub async fn get_oauth2_url(
     addr: &str,
     redirect_uri: &str,
 ) -> Result<Option<String>> {
-    let socks5_enabled = context.get_config_bool(Config::Socks5Enabled).await?;
+    let socks5_enabled = Socks5Config::from_context(context).await?.is_some();
     if let Some(oauth2) = Oauth2::from_address(context, addr, socks5_enabled).await {
         context
             .sql
-            .set_raw_config("oauth2_pending_redirect_uri", Some(redirect_uri))
+            .set_raw_config(
+                InternalConfig::Oauth2PendingRedirectUri.as_ref(),
+                Some(redirect_uri),
+            )
             .await?;
         let oauth2_url = replace_in_uri(oauth2.get_code, "$CLIENT_ID", oauth2.client_id);
         let oauth2_url = replace_in_uri(&oauth2_url, "$REDIRECT_URI", redirect_uri);
@@ -82,13 +89,16 @@ pub(crate) async fn get_oauth2_access_token(
     code: &str,
     regenerate: bool,
 ) -> Result<Option<String>> {
-    let socks5_enabled = context.get_config_bool(Config::Socks5Enabled).await?;
+    let socks5_enabled = Socks5Config::from_context(context).await?.is_some();
     if let Some(oauth2) = Oauth2::from_address(context, addr, socks5_enabled).await {
         let lock = context.oauth2_mutex.lock().await;
 
         // read generated token
         if !regenerate && !is_expired(context).await? {
-            let access_token = context.sql.get_raw_config("oauth2_access_token").await?;
+            let access_token = context
+                .sql
+                .get_raw_config(InternalConfig::Oauth2AccessToken.as_ref())
+                .await?;
             if access_token.is_some() {
                 // success
                 return Ok(access_token);
@@ -96,10 +106,13 @@ pub(crate) async fn get_oauth2_access_token(
         }
 
         // generate new token: build & call auth url
-        let refresh_token = context.sql.get_raw_config("oauth2_refresh_token").await?;
+        let refresh_token = context
+            .sql
+            .get_raw_config(InternalConfig::Oauth2RefreshToken.as_ref())
+            .await?;
         let refresh_token_for = context
             .sql
-            .get_raw_config("oauth2_refresh_token_for")
+            .get_raw_config(InternalConfig::Oauth2RefreshTokenFor.as_ref())
             .await?
             .unwrap_or_else(|| "unset".into());
 
@@ -109,7 +122,7 @@ pub(crate) async fn get_oauth2_access_token(
                 (
                     context
                         .sql
-                        .get_raw_config("oauth2_pending_redirect_uri")
+                        .get_raw_config(InternalConfig::Oauth2PendingRedirectUri.as_ref())
                         .await?
                         .unwrap_or_else(|| "unset".into()),
                     oauth2.init_token,
@@ -123,7 +136,7 @@ pub(crate) async fn get_oauth2_access_token(
                 (
                     context
                         .sql
-                        .get_raw_config("oauth2_redirect_uri")
+                        .get_raw_config(InternalConfig::Oauth2RedirectUri.as_ref())
                         .await?
                         .unwrap_or_else(|| "unset".into()),
                     oauth2.refresh_token,
@@ -159,7 +172,7 @@ pub(crate) async fn get_oauth2_access_token(
         }
 
         // ... and POST
-        let socks5_config = Socks5Config::from_database(&context.sql).await?;
+        let socks5_config = Socks5Config::from_context(context).await?;
         let client = crate::net::http::get_client(socks5_config)?;
 
         let response: Response = match client.post(post_url).form(&post_param).send().await {
@@ -183,11 +196,11 @@ pub(crate) async fn get_oauth2_access_token(
         if let Some(ref token) = response.refresh_token {
             context
                 .sql
-                .set_raw_config("oauth2_refresh_token", Some(token))
+                .set_raw_config(InternalConfig::Oauth2RefreshToken.as_ref(), Some(token))
                 .await?;
             context
                 .sql
-                .set_raw_config("oauth2_refresh_token_for", Some(code))
+                .set_raw_config(InternalConfig::Oauth2RefreshTokenFor.as_ref(), Some(code))
                 .await?;
         }
 
@@ -196,7 +209,7 @@ pub(crate) async fn get_oauth2_access_token(
         if let Some(ref token) = response.access_token {
             context
                 .sql
-                .set_raw_config("oauth2_access_token", Some(token))
+                .set_raw_config(InternalConfig::Oauth2AccessToken.as_ref(), Some(token))
                 .await?;
             let expires_in = response
                 .expires_in
@@ -205,13 +218,16 @@ pub(crate) async fn get_oauth2_access_token(
                 .unwrap_or_else(|| 0);
             context
                 .sql
-                .set_raw_config_int64("oauth2_timestamp_expires", expires_in)
+                .set_raw_config_int64(InternalConfig::Oauth2TimestampExpires.as_ref(), expires_in)
                 .await?;
 
             if update_redirect_uri_on_success {
                 context
                     .sql
-                    .set_raw_config("oauth2_redirect_uri", Some(redirect_uri.as_ref()))
+                    .set_raw_config(
+                        InternalConfig::Oauth2RedirectUri.as_ref(),
+                        Some(redirect_uri.as_ref()),
+                    )
                     .await?;
             }
         } else {
@@ -228,12 +244,37 @@ pub(crate) async fn get_oauth2_access_token(
     }
 }
 
+/// Completes OAuth 2 authorization started with [`get_oauth2_url`].
+///
+/// `code` is the parameter the provider attached to the redirect URI.
+/// The code is exchanged for access and refresh tokens
+/// and the account is set up to log in using OAuth 2,
+/// so [`Context::configure`] can be called afterwards.
+pub async fn complete_oauth2_flow(context: &Context, addr: &str, code: &str) -> Result<()> {
+    if get_oauth2_access_token(context, addr, code, true)
+        .await?
+        .is_none()
+    {
+        bail!("Failed to get OAuth2 access token for {addr}");
+    }
+    context.set_config(Config::Addr, Some(addr)).await?;
+    context.set_config(Config::MailPw, Some(code)).await?;
+    let server_flags = context.get_config_int(Config::ServerFlags).await?;
+    context
+        .set_config(
+            Config::ServerFlags,
+            Some(&(server_flags | DC_LP_AUTH_OAUTH2).to_string()),
+        )
+        .await?;
+    Ok(())
+}
+
 pub(crate) async fn get_oauth2_addr(
     context: &Context,
     addr: &str,
     code: &str,
 ) -> Result<Option<String>> {
-    let socks5_enabled = context.get_config_bool(Config::Socks5Enabled).await?;
+    let socks5_enabled = Socks5Config::from_context(context).await?.is_some();
     let oauth2 = match Oauth2::from_address(context, addr, socks5_enabled).await {
         Some(o) => o,
         None => return Ok(None),
@@ -270,15 +311,20 @@ async fn from_address(context: &Context, addr: &str, skip_mx: bool) -> Option<Se
                 .await
                 .and_then(|provider| provider.oauth2_authorizer.as_ref())
             {
-                return Some(match oauth2_authorizer {
-                    Oauth2Authorizer::Gmail => OAUTH2_GMAIL,
-                    Oauth2Authorizer::Yandex => OAUTH2_YANDEX,
-                });
+                return Some(Self::from_authorizer(oauth2_authorizer));
             }
         }
         None
     }
 
+    /// Returns endpoints of the authorizer.
+    fn from_authorizer(oauth2_authorizer: &Oauth2Authorizer) -> Self {
+        match oauth2_authorizer {
+            Oauth2Authorizer::Gmail => OAUTH2_GMAIL,
+            Oauth2Authorizer::Yandex => OAUTH2_YANDEX,
+        }
+    }
+
     async fn get_addr(&self, context: &Context, access_token: &str) -> Option<String> {
         let userinfo_url = self.get_userinfo.unwrap_or("");
         let userinfo_url = replace_in_uri(userinfo_url, "$ACCESS_TOKEN", access_token);
@@ -290,7 +336,7 @@ async fn get_addr(&self, context: &Context, access_token: &str) -> Option<String
         //   "verified_email": true,
         //   "picture": "https://lh4.googleusercontent.com/-Gj5jh_9R0BY/AAAAAAAAAAI/AAAAAAAAAAA/IAjtjfjtjNA/photo.jpg"
         // }
-        let socks5_config = Socks5Config::from_database(&context.sql).await.ok()?;
+        let socks5_config = Socks5Config::from_context(context).await.ok()?;
         let client = match crate::net::http::get_client(socks5_config) {
             Ok(cl) => cl,
             Err(err) => {
@@ -332,7 +378,7 @@ async fn get_addr(&self, context: &Context, access_token: &str) -> Option<String
 async fn is_expired(context: &Context) -> Result<bool> {
     let expire_timestamp = context
         .sql
-        .get_raw_config_int64("oauth2_timestamp_expires")
+        .get_raw_config_int64(InternalConfig::Oauth2TimestampExpires.as_ref())
         .await?
         .unwrap_or_default();
 
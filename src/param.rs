@@ -38,6 +38,46 @@ pub enum Param {
     /// For Messages
     Duration = b'd',
 
+    /// For Messages: 1 if the image has more than one frame, e.g. an animated GIF or sticker.
+    ///
+    /// All letters are in use, so this is a digit.
+    Animated = b'0',
+
+    /// For Messages: 1 if [`Param::File`] refers to a file outside the blobdir
+    /// that is read when sending instead of being copied, see `Message::set_file_reference()`.
+    FileReference = b'1',
+
+    /// For Messages: time in seconds after which the message is not sent anymore
+    /// but marked as failed if it could not be delivered yet, see `Message::set_delivery_ttl()`.
+    DeliveryTtl = b'2',
+
+    /// For Groups: space-separated addresses of the group admins, see `chat::set_chat_admin()`.
+    /// If unset, all members may administrate the group.
+    GroupAdmins = b'3',
+
+    /// For Groups: timestamp of the last [`Param::GroupAdmins`] change.
+    GroupAdminsTimestamp = b'4',
+
+    /// For Groups: address of the only member allowed to post to the group,
+    /// set for read-only channels created by `chat::create_channel()`.
+    ChannelOwner = b'5',
+
+    /// For Contacts: seconds the clock of the contact was ahead of the server
+    /// when the last message from the contact was delivered, see `Contact::get_clock_skew()`.
+    /// Unset if the clock was correct.
+    ClockSkew = b'6',
+
+    /// For Messages: space-separated ranges of the text with markup, see `Message::get_markup()`.
+    Markup = b'7',
+
+    /// For Chats: the address messages are sent from,
+    /// the primary address or an alias, see `ChatId::set_self_addr()`.
+    SelfAddr = b'8',
+
+    /// For Messages: 1 if the message is an automatic reply, e.g. a vacation notice,
+    /// see `Message::is_auto_reply()`.
+    AutoReply = b'9',
+
     /// For Messages
     MimeType = b'm',
 
@@ -151,6 +191,16 @@ pub enum Param {
     /// post something to the mailing list.
     ListPost = b'p',
 
+    /// For Chats: If this is a mailing list chat, contains the last `List-Unsubscribe` header.
+    ListUnsubscribe = b'y',
+
+    /// For Chats: True if the HTTPS URI in [`Param::ListUnsubscribe`]
+    /// supports one-click unsubscription.
+    ListUnsubscribeOneClick = b'z',
+
+    /// For Messages: result of the DKIM verification, see [`crate::dkim::DkimStatus`].
+    DkimStatus = b'Y',
+
     /// For Contacts: If this is the List-Post address of a mailing list, contains
     /// the List-Id of the mailing list (which is also used as the group id of the chat).
     ListId = b's',
@@ -161,6 +211,12 @@ pub enum Param {
     /// For Contacts and Chats: timestamp of avatar update.
     AvatarTimestamp = b'J',
 
+    /// For Contacts: timestamp of the message the display name was last taken from.
+    ///
+    /// For contact SELF: timestamp of the last display name change,
+    /// used to synchronize the display name between devices.
+    DisplaynameTimestamp = b'Z',
+
     /// For Chats: timestamp of status/signature/footer update.
     EphemeralSettingsTimestamp = b'B',
 
@@ -190,6 +246,10 @@ pub enum Param {
 
     /// For messages: Whether [crate::message::Viewtype::Sticker] should be forced.
     ForceSticker = b'X',
+
+    /// For Messages: true if the sender address is authenticated
+    /// by DKIM or by a signature of a key known before the message.
+    SenderAuthenticated = b'!',
 }
 
 /// An object for handling key=value parameter lists.
@@ -0,0 +1,979 @@
+//! # Realtime channels for webxdc apps.
+//!
+//! Webxdc apps can exchange small binary frames with the other chat members
+//! with low latency using [`send_webxdc_realtime_data`],
+//! e.g. for shared whiteboards or games.
+//! Unlike status updates, the data is not sent by email and is not stored.
+//!
+//! Channels use [iroh] gossip: each webxdc instance has a gossip topic
+//! and frames are broadcast to all devices which joined it.
+//! A device joining the channel of an instance starts an iroh node
+//! and advertises its node ID, the topic and its relay server
+//! in a hidden, end-to-end encrypted message to the chat,
+//! see [`send_webxdc_realtime_advertisement`].
+//! Devices which already joined the channel answer with their own advertisement.
+//! If devices joined with different topics, e.g. because they joined at the same time,
+//! they all switch to the smallest one.
+//!
+//! Each frame is signed with the node key of the sending device.
+//! Frames are only accepted if they are signed by a node advertised by a chat member
+//! and are emitted as [`EventType::WebxdcRealtimeData`] together with this member.
+//!
+//! No IP addresses are advertised, neither in the advertisement nor in the gossip protocol,
+//! and peers are dialed by their node ID through their relay server,
+//! which is set with [`Config::WebxdcRealtimeRelay`] or one of the iroh default relays.
+//! The only socket is the one of the iroh node,
+//! which only accepts connections of other iroh nodes for the gossip protocol.
+//! iroh may still upgrade a connection to a direct one by hole punching,
+//! which reveals the IP address to the other devices in the channel,
+//! so realtime channels are disabled unless [`Config::WebxdcRealtimeEnabled`] is set
+//! and are not available while a SOCKS5 proxy is used.
+//!
+//! [iroh]: https://iroh.computer
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use iroh_gossip::net::{Gossip, GOSSIP_ALPN};
+use iroh_gossip::proto::{Event as GossipEvent, TopicId};
+use iroh_net::derp::{DerpMap, DerpMode, DerpUrl};
+use iroh_net::key::{PublicKey, SecretKey, Signature};
+use iroh_net::magic_endpoint::accept_conn;
+use iroh_net::{MagicEndpoint, NodeAddr};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::timeout;
+
+use crate::chat;
+use crate::config::Config;
+use crate::contact::ContactId;
+use crate::context::Context;
+use crate::events::{Event, EventType, Events};
+use crate::message::{Message, MsgId, Viewtype};
+use crate::mimeparser::SystemMessage;
+use crate::net::ensure_online;
+use crate::param::Param;
+use crate::socks::Socks5Config;
+
+/// Maximum size of the data sent with [`send_webxdc_realtime_data`].
+pub const MAX_REALTIME_DATA_SIZE: usize = 128 * 1024;
+
+/// Size of the node ID and the signature preceding the data of a frame.
+const FRAME_HEADER_SIZE: usize = 32 + 64;
+
+/// Maximum time to wait for the iroh node to connect to its relay,
+/// so that the relay can be advertised.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Realtime channels joined by a context.
+#[derive(Debug, Default)]
+pub(crate) struct PeerChannels {
+    /// Running iroh node and topics seen in advertisements.
+    state: Mutex<State>,
+
+    /// Chat members by the node ID they advertised, for each webxdc instance.
+    ///
+    /// Standard mutex is used as it is never held across await points
+    /// and is also locked by the tasks receiving frames.
+    peers: Arc<std::sync::Mutex<HashMap<MsgId, HashMap<NodeId, Peer>>>>,
+}
+
+/// Node ID of an iroh node.
+type NodeId = PublicKey;
+
+#[derive(Debug, Default)]
+struct State {
+    /// iroh node, running while any channel is joined.
+    iroh: Option<Iroh>,
+
+    /// Smallest topic advertised for each webxdc instance,
+    /// used when joining the channel of the instance.
+    topics: HashMap<MsgId, TopicId>,
+}
+
+#[derive(Debug)]
+struct Iroh {
+    endpoint: MagicEndpoint,
+    gossip: Gossip,
+    secret_key: SecretKey,
+
+    /// Joined channels by webxdc instance.
+    channels: HashMap<MsgId, Channel>,
+
+    /// Task accepting connections from other nodes.
+    accept_task: JoinHandle<()>,
+}
+
+impl Drop for Iroh {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    topic: TopicId,
+
+    /// Task receiving the frames broadcast to the topic.
+    receive_task: JoinHandle<()>,
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.receive_task.abort();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Peer {
+    /// Chat member who advertised the node.
+    contact_id: ContactId,
+
+    /// Relay server the node can be reached through.
+    relay: Option<DerpUrl>,
+}
+
+/// Value of the `Chat-Webxdc-Realtime` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Advertisement {
+    node_id: NodeId,
+    topic: TopicId,
+    relay: Option<DerpUrl>,
+
+    /// True if the advertisement answers the advertisement of another device.
+    answer: bool,
+}
+
+impl fmt::Display for Advertisement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "node={}; topic={}",
+            hex::encode(self.node_id.as_bytes()),
+            hex::encode(self.topic.as_bytes())
+        )?;
+        if let Some(relay) = &self.relay {
+            write!(f, "; relay={relay}")?;
+        }
+        if self.answer {
+            write!(f, "; answer=1")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Advertisement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut attributes: HashMap<&str, &str> = s
+            .split(';')
+            .filter_map(|attribute| attribute.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect();
+        let node_id: [u8; 32] = hex::decode(attributes.remove("node").context("Missing node")?)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid node length"))?;
+        let topic: [u8; 32] = hex::decode(attributes.remove("topic").context("Missing topic")?)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid topic length"))?;
+        let relay = attributes
+            .remove("relay")
+            .map(|relay| relay.parse())
+            .transpose()
+            .context("Invalid relay")?;
+        let answer = attributes.remove("answer") == Some("1");
+        Ok(Self {
+            node_id: PublicKey::from_bytes(&node_id)?,
+            topic: TopicId::from_bytes(topic),
+            relay,
+            answer,
+        })
+    }
+}
+
+/// Returns true if topic `a` is smaller than topic `b`.
+///
+/// If devices joined a channel with different topics, the smallest one is used.
+fn is_smaller(a: &TopicId, b: &TopicId) -> bool {
+    a.as_bytes() < b.as_bytes()
+}
+
+impl Iroh {
+    /// Starts an iroh node for realtime channels.
+    async fn start(context: &Context) -> Result<Self> {
+        let derp_mode = match context.get_config(Config::WebxdcRealtimeRelay).await? {
+            Some(relay) => DerpMode::Custom(DerpMap::from_url(
+                relay.parse().context("Invalid realtime relay")?,
+            )),
+            None => DerpMode::Default,
+        };
+        let secret_key = SecretKey::generate();
+        let endpoint = MagicEndpoint::builder()
+            .secret_key(secret_key.clone())
+            .alpns(vec![GOSSIP_ALPN.to_vec()])
+            .derp_mode(derp_mode)
+            .bind(0)
+            .await?;
+        // Direct addresses are never passed to the gossip protocol,
+        // so they are not sent to other nodes as part of the peer data.
+        let gossip = Gossip::from_endpoint(
+            endpoint.clone(),
+            iroh_gossip::proto::Config {
+                max_message_size: FRAME_HEADER_SIZE + MAX_REALTIME_DATA_SIZE,
+                ..Default::default()
+            },
+        );
+        let accept_task = tokio::spawn(accept_connections(endpoint.clone(), gossip.clone()));
+        info!(
+            context,
+            "Started iroh node {} for realtime channels.",
+            endpoint.node_id()
+        );
+        Ok(Self {
+            endpoint,
+            gossip,
+            secret_key,
+            channels: HashMap::new(),
+            accept_task,
+        })
+    }
+
+    /// Stops the node, leaving all channels.
+    async fn stop(mut self) {
+        self.channels.clear();
+        self.endpoint
+            .clone()
+            .close(0u8.into(), b"leaving realtime channels")
+            .await
+            .ok();
+    }
+
+    /// Returns the relay server the node is connected to,
+    /// waiting for the connection if the node was just started.
+    async fn relay(&self) -> Option<DerpUrl> {
+        timeout(RELAY_TIMEOUT, async {
+            loop {
+                if let Ok(addr) = self.endpoint.my_addr().await {
+                    if let Some(relay) = addr.info.derp_url {
+                        return relay;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .ok()
+    }
+
+    /// Adds the relay of a peer to the address book of the node,
+    /// so that the peer can be dialed by its node ID.
+    fn add_peer_addr(&self, node_id: NodeId, peer: &Peer) {
+        if let Some(relay) = &peer.relay {
+            let addr = NodeAddr::new(node_id).with_derp_url(relay.clone());
+            self.endpoint.add_node_addr(addr).ok();
+        }
+    }
+
+    /// Joins the gossip topic of a webxdc instance, leaving the previous topic if any,
+    /// and starts receiving frames.
+    async fn join_topic(
+        &mut self,
+        context: &Context,
+        peers: &Arc<std::sync::Mutex<HashMap<MsgId, HashMap<NodeId, Peer>>>>,
+        instance_id: MsgId,
+        topic: TopicId,
+    ) -> Result<()> {
+        if let Some(channel) = self.channels.remove(&instance_id) {
+            self.gossip.quit(channel.topic).await.ok();
+        }
+        let instance_peers = peers
+            .lock()
+            .expect("Mutex is poisoned")
+            .get(&instance_id)
+            .cloned()
+            .unwrap_or_default();
+        for (node_id, peer) in &instance_peers {
+            self.add_peer_addr(*node_id, peer);
+        }
+
+        // Subscribe before joining, so that no frames are missed.
+        let receiver = self.gossip.subscribe(topic).await?;
+        // The returned future completes once a peer is connected, it is not waited for,
+        // as there may be no other peer yet.
+        self.gossip
+            .join(topic, instance_peers.into_keys().collect())
+            .await?;
+        let receive_task = tokio::spawn(receive_frames(
+            receiver,
+            instance_id,
+            topic,
+            Arc::clone(peers),
+            context.events.clone(),
+            context.id,
+        ));
+        self.channels.insert(
+            instance_id,
+            Channel {
+                topic,
+                receive_task,
+            },
+        );
+        Ok(())
+    }
+
+    async fn advertisement(&self, instance_id: MsgId, answer: bool) -> Result<Advertisement> {
+        let channel = self
+            .channels
+            .get(&instance_id)
+            .context("Realtime channel is not joined")?;
+        Ok(Advertisement {
+            node_id: self.endpoint.node_id(),
+            topic: channel.topic,
+            relay: self.relay().await,
+            answer,
+        })
+    }
+}
+
+impl PeerChannels {
+    /// Joins the channel of a webxdc instance, starting the iroh node if needed,
+    /// and returns the own advertisement.
+    async fn join(&self, context: &Context, instance_id: MsgId) -> Result<Advertisement> {
+        ensure_allowed(context).await?;
+        let mut state = self.state.lock().await;
+        let State { iroh, topics } = &mut *state;
+        let iroh = match iroh {
+            Some(iroh) => iroh,
+            None => iroh.insert(Iroh::start(context).await?),
+        };
+        if !iroh.channels.contains_key(&instance_id) {
+            let topic = *topics
+                .entry(instance_id)
+                .or_insert_with(|| TopicId::from_bytes(rand::random()));
+            iroh.join_topic(context, &self.peers, instance_id, topic)
+                .await?;
+        }
+        iroh.advertisement(instance_id, false).await
+    }
+
+    /// Leaves the channel of a webxdc instance,
+    /// stopping the iroh node if no channel is joined anymore.
+    async fn leave(&self, instance_id: MsgId) {
+        let mut state = self.state.lock().await;
+        let Some(iroh) = &mut state.iroh else {
+            return;
+        };
+        if let Some(channel) = iroh.channels.remove(&instance_id) {
+            iroh.gossip.quit(channel.topic).await.ok();
+        }
+        if iroh.channels.is_empty() {
+            if let Some(iroh) = state.iroh.take() {
+                iroh.stop().await;
+            }
+        }
+    }
+
+    /// Leaves all channels and stops the iroh node,
+    /// e.g. when the account goes offline.
+    pub(crate) async fn leave_all(&self) {
+        let iroh = self.state.lock().await.iroh.take();
+        if let Some(iroh) = iroh {
+            iroh.stop().await;
+        }
+    }
+
+    async fn is_joined(&self, instance_id: MsgId) -> bool {
+        self.state
+            .lock()
+            .await
+            .iroh
+            .as_ref()
+            .map_or(false, |iroh| iroh.channels.contains_key(&instance_id))
+    }
+
+    /// Handles an advertisement sent by the chat member `contact_id`.
+    ///
+    /// The advertised node is added as a peer of the instance.
+    /// If the channel is joined, the node is connected to
+    /// and answered with the own advertisement if needed.
+    async fn receive_advertisement(
+        &self,
+        context: &Context,
+        instance: &Message,
+        contact_id: ContactId,
+        advertisement: Advertisement,
+    ) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let State { iroh, topics } = &mut *state;
+        if iroh.as_ref().map_or(false, |iroh| {
+            iroh.endpoint.node_id() == advertisement.node_id
+        }) {
+            // Own advertisement.
+            return Ok(());
+        }
+
+        let peer = Peer {
+            contact_id,
+            relay: advertisement.relay.clone(),
+        };
+        let is_new = {
+            let mut peers = self.peers.lock().expect("Mutex is poisoned");
+            let instance_peers = peers.entry(instance.id).or_default();
+            match instance_peers.get(&advertisement.node_id) {
+                Some(known) if known.contact_id != contact_id => {
+                    // Otherwise a member could claim the frames of another member.
+                    bail!("Realtime node is already advertised by another contact.");
+                }
+                known => {
+                    let is_new = known.is_none();
+                    instance_peers.insert(advertisement.node_id, peer.clone());
+                    is_new
+                }
+            }
+        };
+        let topic = topics.entry(instance.id).or_insert(advertisement.topic);
+        if is_smaller(&advertisement.topic, topic) {
+            *topic = advertisement.topic;
+        }
+        let topic = *topic;
+
+        let Some(iroh) = iroh else {
+            return Ok(());
+        };
+        let Some(joined_topic) = iroh.channels.get(&instance.id).map(|channel| channel.topic)
+        else {
+            return Ok(());
+        };
+        let answer = if joined_topic != topic {
+            // Switch to the smaller topic and announce it to all members,
+            // so that the members which are still using the other topic switch as well.
+            iroh.join_topic(context, &self.peers, instance.id, topic)
+                .await?;
+            Some(iroh.advertisement(instance.id, false).await?)
+        } else if is_smaller(&joined_topic, &advertisement.topic) {
+            // The peer switches to the own topic when receiving the answer.
+            Some(iroh.advertisement(instance.id, true).await?)
+        } else {
+            iroh.add_peer_addr(advertisement.node_id, &peer);
+            iroh.gossip.join(topic, vec![advertisement.node_id]).await?;
+            if is_new && !advertisement.answer {
+                Some(iroh.advertisement(instance.id, true).await?)
+            } else {
+                None
+            }
+        };
+        drop(state);
+
+        if let Some(answer) = answer {
+            send_advertisement(context, instance, &answer).await?;
+        }
+        Ok(())
+    }
+
+    /// Signs data and broadcasts it to the joined channel of a webxdc instance.
+    async fn broadcast(&self, instance_id: MsgId, data: &[u8]) -> Result<()> {
+        let state = self.state.lock().await;
+        let iroh = state
+            .iroh
+            .as_ref()
+            .context("Realtime channel is not joined")?;
+        let channel = iroh
+            .channels
+            .get(&instance_id)
+            .context("Realtime channel is not joined")?;
+        let frame = sign_frame(&iroh.secret_key, &channel.topic, data);
+        iroh.gossip.broadcast(channel.topic, frame.into()).await?;
+        Ok(())
+    }
+}
+
+/// Returns an error if realtime channels must not be used,
+/// because they are disabled, the account is offline or a proxy is used.
+async fn ensure_allowed(context: &Context) -> Result<()> {
+    ensure!(
+        context
+            .get_config_bool(Config::WebxdcRealtimeEnabled)
+            .await?,
+        "Webxdc realtime channels are disabled"
+    );
+    ensure_online(context).await?;
+    ensure!(
+        Socks5Config::from_context(context).await?.is_none(),
+        "Webxdc realtime channels are not available while a proxy is used"
+    );
+    Ok(())
+}
+
+/// Joins the realtime channel of a webxdc instance and advertises it to the chat members.
+///
+/// Data sent by other members is emitted as [`EventType::WebxdcRealtimeData`]
+/// as soon as they received the advertisement.
+/// Calling the function again sends the advertisement again,
+/// e.g. after the network changed.
+pub async fn send_webxdc_realtime_advertisement(
+    context: &Context,
+    instance_id: MsgId,
+) -> Result<()> {
+    let instance = Message::load_from_db(context, instance_id).await?;
+    ensure!(
+        instance.viewtype == Viewtype::Webxdc,
+        "{instance_id} is not a webxdc instance"
+    );
+    let advertisement = context.peer_channels.join(context, instance_id).await?;
+    send_advertisement(context, &instance, &advertisement).await
+}
+
+async fn send_advertisement(
+    context: &Context,
+    instance: &Message,
+    advertisement: &Advertisement,
+) -> Result<()> {
+    let mut msg = Message {
+        chat_id: instance.chat_id,
+        viewtype: Viewtype::Text,
+        hidden: true,
+        ..Default::default()
+    };
+    msg.param
+        .set_cmd(SystemMessage::WebxdcRealtimeAdvertisement);
+    msg.param.set(Param::Arg, advertisement.to_string());
+    msg.set_quote(context, Some(instance)).await?;
+    // Only chat members may learn the node ID and the topic,
+    // so the advertisement must never be sent unencrypted.
+    msg.param.set_int(Param::GuaranteeE2ee, 1);
+    chat::send_msg(context, instance.chat_id, &mut msg).await?;
+    Ok(())
+}
+
+/// Sends data to the members of the realtime channel of a webxdc instance.
+///
+/// Joins the channel first if needed, see [`send_webxdc_realtime_advertisement`].
+/// The data is broadcast to the members which joined the channel so far.
+/// At most [`MAX_REALTIME_DATA_SIZE`] bytes can be sent at once.
+pub async fn send_webxdc_realtime_data(
+    context: &Context,
+    instance_id: MsgId,
+    data: Vec<u8>,
+) -> Result<()> {
+    ensure!(
+        data.len() <= MAX_REALTIME_DATA_SIZE,
+        "Realtime data exceeds {MAX_REALTIME_DATA_SIZE} bytes"
+    );
+    if !context.peer_channels.is_joined(instance_id).await {
+        send_webxdc_realtime_advertisement(context, instance_id).await?;
+    }
+    context.peer_channels.broadcast(instance_id, &data).await
+}
+
+/// Leaves the realtime channel of a webxdc instance.
+///
+/// No data is received for the instance anymore.
+pub async fn leave_webxdc_realtime(context: &Context, instance_id: MsgId) -> Result<()> {
+    context.peer_channels.leave(instance_id).await;
+    Ok(())
+}
+
+/// Handles a `Chat-Webxdc-Realtime` header received in the message `msg_id`.
+///
+/// The sender is added as a peer and, if the channel is joined,
+/// answered with the own advertisement if it is new.
+pub(crate) async fn receive_webxdc_realtime_advertisement(
+    context: &Context,
+    from_id: ContactId,
+    msg_id: MsgId,
+    value: &str,
+) -> Result<()> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    let Some(instance) = msg.parent(context).await? else {
+        bail!("Realtime advertisement has no parent.");
+    };
+    ensure!(
+        instance.viewtype == Viewtype::Webxdc,
+        "Realtime advertisement is not a reply to a webxdc instance."
+    );
+    ensure!(
+        from_id == ContactId::SELF
+            || chat::is_contact_in_chat(context, instance.chat_id, from_id).await?,
+        "Realtime advertisement sender is not a chat member."
+    );
+
+    let advertisement: Advertisement = value.parse()?;
+    context
+        .peer_channels
+        .receive_advertisement(context, &instance, from_id, advertisement)
+        .await
+}
+
+/// Accepts connections from other nodes and passes them to the gossip protocol.
+async fn accept_connections(endpoint: MagicEndpoint, gossip: Gossip) {
+    // Connections are closed when the set is dropped, i.e. when the node is stopped.
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            connecting = endpoint.accept() => {
+                let Some(connecting) = connecting else {
+                    break;
+                };
+                let gossip = gossip.clone();
+                connections.spawn(async move {
+                    // The endpoint only accepts the gossip ALPN.
+                    let (_node_id, _alpn, connection) = accept_conn(connecting).await?;
+                    gossip.handle_connection(connection).await
+                });
+            }
+            Some(_) = connections.join_next() => {}
+        }
+    }
+}
+
+/// Receives the frames broadcast to the topic of a webxdc instance
+/// and emits the ones signed by the node of a chat member as events.
+async fn receive_frames(
+    mut receiver: tokio::sync::broadcast::Receiver<GossipEvent>,
+    instance_id: MsgId,
+    topic: TopicId,
+    peers: Arc<std::sync::Mutex<HashMap<MsgId, HashMap<NodeId, Peer>>>>,
+    events: Events,
+    context_id: u32,
+) {
+    loop {
+        let frame = match receiver.recv().await {
+            Ok(GossipEvent::Received(message)) => message.content,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+        let Ok((node_id, data)) = verify_frame(&topic, &frame) else {
+            continue;
+        };
+        let contact_id = peers
+            .lock()
+            .expect("Mutex is poisoned")
+            .get(&instance_id)
+            .and_then(|instance_peers| instance_peers.get(&node_id))
+            .map(|peer| peer.contact_id);
+        // Frames of nodes which were not advertised by a chat member are dropped.
+        let Some(contact_id) = contact_id else {
+            continue;
+        };
+        events.emit(Event {
+            id: context_id,
+            typ: EventType::WebxdcRealtimeData {
+                msg_id: instance_id,
+                contact_id,
+                data,
+            },
+        });
+    }
+}
+
+/// Returns the data signed for a frame.
+///
+/// The topic is included, so that frames can not be replayed to another channel.
+fn signed_data(topic: &TopicId, data: &[u8]) -> Vec<u8> {
+    let mut signed = Vec::with_capacity(32 + data.len());
+    signed.extend_from_slice(topic.as_bytes());
+    signed.extend_from_slice(data);
+    signed
+}
+
+/// Creates a frame consisting of the node ID, the signature and the data.
+fn sign_frame(secret_key: &SecretKey, topic: &TopicId, data: &[u8]) -> Vec<u8> {
+    let signature = secret_key.sign(&signed_data(topic, data));
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + data.len());
+    frame.extend_from_slice(secret_key.public().as_bytes());
+    frame.extend_from_slice(&signature.to_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// Verifies the signature of a frame and returns the node ID of the signer and the data.
+fn verify_frame(topic: &TopicId, frame: &[u8]) -> Result<(NodeId, Vec<u8>)> {
+    ensure!(
+        frame.len() >= FRAME_HEADER_SIZE,
+        "Realtime frame is too short."
+    );
+    let (node_id, rest) = frame.split_at(32);
+    let (signature, data) = rest.split_at(64);
+    let node_id = PublicKey::from_bytes(node_id.try_into()?)?;
+    let signature = Signature::from_bytes(signature.try_into()?);
+    node_id
+        .verify(&signed_data(topic, data), &signature)
+        .map_err(|_| anyhow!("Invalid signature of realtime frame"))?;
+    Ok((node_id, data.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{create_group_chat, ProtectionStatus};
+    use crate::test_utils::{TestContext, TestContextManager};
+
+    #[test]
+    fn test_advertisement_roundtrip() -> Result<()> {
+        let advertisement = Advertisement {
+            node_id: SecretKey::generate().public(),
+            topic: TopicId::from_bytes(rand::random()),
+            relay: None,
+            answer: false,
+        };
+        let parsed: Advertisement = advertisement.to_string().parse()?;
+        assert_eq!(parsed, advertisement);
+
+        let answer = Advertisement {
+            relay: Some("https://relay.example.org".parse()?),
+            answer: true,
+            ..advertisement
+        };
+        assert!(answer.to_string().ends_with("; answer=1"));
+        assert_eq!(answer.to_string().parse::<Advertisement>()?, answer);
+
+        assert!("node=00; topic=00".parse::<Advertisement>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_signature() -> Result<()> {
+        let secret_key = SecretKey::generate();
+        let topic = TopicId::from_bytes(rand::random());
+        let frame = sign_frame(&secret_key, &topic, b"hello");
+        assert_eq!(
+            verify_frame(&topic, &frame)?,
+            (secret_key.public(), b"hello".to_vec())
+        );
+
+        // Frames can not be replayed to another channel.
+        let other_topic = TopicId::from_bytes(rand::random());
+        assert!(verify_frame(&other_topic, &frame).is_err());
+
+        // Neither the data nor the sender can be changed.
+        let mut modified = frame.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(verify_frame(&topic, &modified).is_err());
+        let mut modified = frame;
+        modified[..32].copy_from_slice(SecretKey::generate().public().as_bytes());
+        assert!(verify_frame(&topic, &modified).is_err());
+
+        assert!(verify_frame(&topic, b"short").is_err());
+        Ok(())
+    }
+
+    /// Creates a chat between Alice and Bob with a webxdc instance
+    /// and enables realtime channels.
+    ///
+    /// Returns the IDs of the instance on both sides.
+    async fn create_instance(
+        tcm: &TestContextManager,
+        alice: &TestContext,
+        bob: &TestContext,
+    ) -> Result<(MsgId, MsgId)> {
+        // Exchange keys, advertisements are always encrypted.
+        tcm.send_recv_accept(alice, bob, "Hi").await;
+        tcm.send_recv(bob, alice, "Hello").await;
+        for t in [alice, bob] {
+            t.set_config_bool(Config::WebxdcRealtimeEnabled, true)
+                .await?;
+        }
+
+        let alice_chat_id = alice.create_chat(bob).await.id;
+        let mut instance = Message::new(Viewtype::File);
+        instance
+            .set_file_from_bytes(
+                alice,
+                "minimal.xdc",
+                include_bytes!("../test-data/webxdc/minimal.xdc"),
+                None,
+            )
+            .await?;
+        chat::send_msg(alice, alice_chat_id, &mut instance).await?;
+        let bob_instance = bob.recv_msg(&alice.pop_sent_msg().await).await;
+        Ok((instance.id, bob_instance.id))
+    }
+
+    async fn joined_topic(t: &TestContext, instance_id: MsgId) -> Option<TopicId> {
+        t.peer_channels
+            .state
+            .lock()
+            .await
+            .iroh
+            .as_ref()
+            .and_then(|iroh| iroh.channels.get(&instance_id))
+            .map(|channel| channel.topic)
+    }
+
+    fn context_peers(t: &TestContext, instance_id: MsgId) -> HashMap<NodeId, Peer> {
+        t.peer_channels
+            .peers
+            .lock()
+            .unwrap()
+            .get(&instance_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_advertisements() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let (instance_id, bob_instance_id) = create_instance(&tcm, &alice, &bob).await?;
+
+        send_webxdc_realtime_advertisement(&alice, instance_id).await?;
+        let advertisement = alice.pop_sent_msg().await;
+        let parsed = bob.parse_msg(&advertisement).await;
+        assert!(parsed.was_encrypted());
+        assert!(parsed.webxdc_realtime_advertisement.is_some());
+        bob.recv_msg_opt(&advertisement).await;
+
+        // Bob is not in the channel yet, but knows Alice's node and topic.
+        assert!(joined_topic(&bob, bob_instance_id).await.is_none());
+        let alice_topic = joined_topic(&alice, instance_id).await.unwrap();
+        let bob_alice_id = bob.add_or_lookup_contact(&alice).await.id;
+        let peers = context_peers(&bob, bob_instance_id);
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers.values().next().unwrap().contact_id, bob_alice_id);
+
+        // Bob joins with Alice's topic, Alice learns Bob's node
+        // and answers, as Bob is new.
+        send_webxdc_realtime_advertisement(&bob, bob_instance_id).await?;
+        assert_eq!(joined_topic(&bob, bob_instance_id).await, Some(alice_topic));
+        alice.recv_msg_opt(&bob.pop_sent_msg().await).await;
+        assert_eq!(context_peers(&alice, instance_id).len(), 1);
+        let answer = bob.parse_msg(&alice.pop_sent_msg().await).await;
+        assert!(answer
+            .webxdc_realtime_advertisement
+            .unwrap()
+            .ends_with("; answer=1"));
+
+        leave_webxdc_realtime(&alice, instance_id).await?;
+        assert!(!alice.peer_channels.is_joined(instance_id).await);
+        assert!(alice.peer_channels.state.lock().await.iroh.is_none());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_switch_to_smaller_topic() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let (instance_id, bob_instance_id) = create_instance(&tcm, &alice, &bob).await?;
+
+        // Both join before receiving the advertisement of the other one.
+        send_webxdc_realtime_advertisement(&alice, instance_id).await?;
+        let alice_advertisement = alice.pop_sent_msg().await;
+        send_webxdc_realtime_advertisement(&bob, bob_instance_id).await?;
+        let bob_advertisement = bob.pop_sent_msg().await;
+        let alice_topic = joined_topic(&alice, instance_id).await.unwrap();
+        let bob_topic = joined_topic(&bob, bob_instance_id).await.unwrap();
+        assert_ne!(alice_topic, bob_topic);
+
+        bob.recv_msg_opt(&alice_advertisement).await;
+        alice.recv_msg_opt(&bob_advertisement).await;
+        let smaller = if is_smaller(&alice_topic, &bob_topic) {
+            alice_topic
+        } else {
+            bob_topic
+        };
+        assert_eq!(joined_topic(&alice, instance_id).await, Some(smaller));
+        assert_eq!(joined_topic(&bob, bob_instance_id).await, Some(smaller));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_node_advertised_by_other_contact() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let (instance_id, _) = create_instance(&tcm, &alice, &bob).await?;
+        let instance = Message::load_from_db(&alice, instance_id).await?;
+        let bob_id = alice.add_or_lookup_contact(&bob).await.id;
+        let advertisement = Advertisement {
+            node_id: SecretKey::generate().public(),
+            topic: TopicId::from_bytes(rand::random()),
+            relay: None,
+            answer: false,
+        };
+        alice
+            .peer_channels
+            .receive_advertisement(&alice, &instance, bob_id, advertisement.clone())
+            .await?;
+        assert!(alice
+            .peer_channels
+            .receive_advertisement(&alice, &instance, ContactId::SELF, advertisement)
+            .await
+            .is_err());
+        let peers = context_peers(&alice, instance_id);
+        assert_eq!(peers.values().next().unwrap().contact_id, bob_id);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_advertise_non_webxdc() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let chat_id = create_group_chat(&alice, ProtectionStatus::Unprotected, "Group").await?;
+        let msg_id = chat::send_text_msg(&alice, chat_id, "Hi".to_string()).await?;
+        assert!(send_webxdc_realtime_advertisement(&alice, msg_id)
+            .await
+            .is_err());
+        assert!(
+            send_webxdc_realtime_data(&alice, msg_id, vec![0; MAX_REALTIME_DATA_SIZE + 1])
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_realtime_not_allowed() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let chat_id = alice.create_chat(&bob).await.id;
+        let mut instance = Message::new(Viewtype::File);
+        instance
+            .set_file_from_bytes(
+                &alice,
+                "minimal.xdc",
+                include_bytes!("../test-data/webxdc/minimal.xdc"),
+                None,
+            )
+            .await?;
+        chat::send_msg(&alice, chat_id, &mut instance).await?;
+
+        // Disabled by default.
+        assert!(send_webxdc_realtime_advertisement(&alice, instance.id)
+            .await
+            .is_err());
+        assert!(!alice.peer_channels.is_joined(instance.id).await);
+
+        alice
+            .set_config_bool(Config::WebxdcRealtimeEnabled, true)
+            .await?;
+        alice.set_config_bool(Config::Socks5Enabled, true).await?;
+        alice
+            .set_config(Config::Socks5Host, Some("127.0.0.1"))
+            .await?;
+        alice.set_config(Config::Socks5Port, Some("9050")).await?;
+        assert!(send_webxdc_realtime_advertisement(&alice, instance.id)
+            .await
+            .is_err());
+
+        alice.set_config_bool(Config::Socks5Enabled, false).await?;
+        send_webxdc_realtime_advertisement(&alice, instance.id).await?;
+        assert!(alice.peer_channels.is_joined(instance.id).await);
+
+        // Disabling leaves all channels.
+        alice
+            .set_config_bool(Config::WebxdcRealtimeEnabled, false)
+            .await?;
+        assert!(!alice.peer_channels.is_joined(instance.id).await);
+        assert!(alice.peer_channels.state.lock().await.iroh.is_none());
+        Ok(())
+    }
+}
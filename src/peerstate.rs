@@ -3,6 +3,7 @@
 use std::collections::HashSet;
 
 use anyhow::{Context as _, Error, Result};
+use base64::Engine as _;
 use num_traits::FromPrimitive;
 
 use crate::aheader::{Aheader, EncryptPreference};
@@ -12,6 +13,7 @@
 use crate::contact::{addr_cmp, Contact, ContactAddress, Origin};
 use crate::context::Context;
 use crate::events::EventType;
+use crate::headerdef::HeaderDef;
 use crate::key::{DcKey, Fingerprint, SignedPublicKey};
 use crate::message::Message;
 use crate::mimeparser::SystemMessage;
@@ -713,6 +715,68 @@ pub(crate) async fn maybe_do_aeap_transition(
     Ok(())
 }
 
+/// Keeps the contact verified if the key changed because of [`Context::rotate_key`].
+///
+/// The key-transition notice contains the fingerprint of the new key
+/// signed by the old key, which must be the verified one.
+pub(crate) async fn maybe_do_key_transition(
+    context: &Context,
+    mime_parser: &mut crate::mimeparser::MimeMessage,
+) -> Result<()> {
+    let Some(statement) = mime_parser
+        .get_header(HeaderDef::ChatKeyTransition)
+        .cloned()
+    else {
+        return Ok(());
+    };
+    if !mime_parser.from_is_signed {
+        return Ok(());
+    }
+    let Some(peerstate) = mime_parser.decryption_info.peerstate.as_mut() else {
+        return Ok(());
+    };
+    let (Some(verified_key), Some(new_fingerprint)) =
+        (&peerstate.verified_key, &peerstate.public_key_fingerprint)
+    else {
+        return Ok(());
+    };
+    if peerstate.verified_key_fingerprint.as_ref() == Some(new_fingerprint)
+        || !mime_parser.signatures.contains(new_fingerprint)
+    {
+        return Ok(());
+    }
+
+    let statement: String = statement.split_ascii_whitespace().collect();
+    let signature = base64::engine::general_purpose::STANDARD
+        .decode(statement)
+        .context("Invalid key-transition notice")?;
+    let signers = crate::pgp::pk_validate_signature(
+        new_fingerprint.hex().as_bytes(),
+        &signature,
+        std::slice::from_ref(verified_key),
+    )?;
+    if signers.is_empty() {
+        warn!(
+            context,
+            "Key-transition notice from {} is not signed by the verified key.", peerstate.addr
+        );
+        return Ok(());
+    }
+
+    info!(
+        context,
+        "Key of {} changed to {}, keeping it verified.",
+        peerstate.addr,
+        new_fingerprint.hex()
+    );
+    peerstate.verified_key = peerstate.public_key.clone();
+    peerstate.verified_key_fingerprint = Some(new_fingerprint.clone());
+    peerstate.fingerprint_changed = false;
+    peerstate.save_to_db(&context.sql).await?;
+    context.emit_event(EventType::ContactsChanged(None));
+    Ok(())
+}
+
 /// Type of the peerstate change.
 ///
 /// Changes to the peerstate are notified to the user via a message
@@ -359,6 +359,23 @@ pub fn pk_validate(
     Ok(ret)
 }
 
+/// Validates a signature created by [`pk_calc_signature`].
+///
+/// Returns fingerprints of all keys from the `public_keys_for_validation` keyring
+/// that made the signature.
+pub fn pk_validate_signature(
+    content: &[u8],
+    signature: &[u8],
+    public_keys_for_validation: &[SignedPublicKey],
+) -> Result<HashSet<Fingerprint>> {
+    let standalone_signature = StandaloneSignature::from_armor_single(Cursor::new(signature))?.0;
+    Ok(public_keys_for_validation
+        .iter()
+        .filter(|pkey| standalone_signature.verify(*pkey, content).is_ok())
+        .map(DcKey::fingerprint)
+        .collect())
+}
+
 /// Symmetric encryption.
 pub async fn symm_encrypt(passphrase: &str, plain: &[u8]) -> Result<String> {
     let lit_msg = Message::new_literal_bytes("", plain);
@@ -583,4 +600,21 @@ async fn test_decrypt_unsigned() {
         assert_eq!(plain, CLEARTEXT);
         assert_eq!(valid_signatures.len(), 0);
     }
+
+    #[test]
+    fn test_validate_signature() {
+        let signature = pk_calc_signature(CLEARTEXT, &KEYS.alice_secret).unwrap();
+        let keyring = vec![KEYS.alice_public.clone(), KEYS.bob_public.clone()];
+        let valid_signatures =
+            pk_validate_signature(CLEARTEXT, signature.as_bytes(), &keyring).unwrap();
+        assert_eq!(
+            valid_signatures,
+            HashSet::from([DcKey::fingerprint(&KEYS.alice_public)])
+        );
+        assert!(
+            pk_validate_signature(b"Something else", signature.as_bytes(), &keyring)
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
@@ -8,6 +8,7 @@
 use crate::config::Config;
 use crate::context::Context;
 use crate::provider::data::{PROVIDER_DATA, PROVIDER_IDS};
+use crate::socks::Socks5Config;
 use crate::tools::EmailAddress;
 
 /// Provider status according to manual testing.
@@ -148,6 +149,13 @@ pub struct ProviderOptions {
 
     /// Move messages to the Trash folder instead of marking them "\Deleted".
     pub delete_to_trash: bool,
+
+    /// True if the account password cannot be used to log in
+    /// and an app password must be created in the web interface instead.
+    pub app_password_required: bool,
+
+    /// True if provider offers only POP3 and no IMAP access.
+    pub pop_only: bool,
 }
 
 impl ProviderOptions {
@@ -156,16 +164,18 @@ const fn new() -> Self {
             strict_tls: true,
             max_smtp_rcpt_to: None,
             delete_to_trash: false,
+            app_password_required: false,
+            pop_only: false,
         }
     }
 }
 
-/// Get resolver to query MX records.
+/// Get resolver to query MX and other DNS records.
 ///
 /// We first try to read the system's resolver from `/etc/resolv.conf`.
 /// This does not work at least on some Androids, therefore we fallback
 /// to the default `ResolverConfig` which uses eg. to google's `8.8.8.8` or `8.8.4.4`.
-fn get_resolver() -> Result<TokioAsyncResolver> {
+pub(crate) fn get_resolver() -> Result<TokioAsyncResolver> {
     if let Ok(resolver) = AsyncResolver::tokio_from_system_conf() {
         return Ok(resolver);
     }
@@ -176,6 +186,19 @@ fn get_resolver() -> Result<TokioAsyncResolver> {
     Ok(resolver)
 }
 
+/// Returns provider for the given e-mail address
+/// so UIs can show provider-specific hints before configuration.
+///
+/// The offline database is looked up first.
+/// MX records are only queried if SOCKS5 proxy is disabled,
+/// to avoid leaking DNS requests around the proxy.
+///
+/// Returns an error if provided address is not valid.
+pub async fn get_info(context: &Context, addr: &str) -> Result<Option<&'static Provider>> {
+    let socks5_enabled = Socks5Config::from_context(context).await?.is_some();
+    get_provider_info_by_addr(context, addr, socks5_enabled).await
+}
+
 /// Returns provider for the given an e-mail address.
 ///
 /// Returns an error if provided address is not valid.
@@ -319,6 +342,32 @@ fn test_get_provider_by_id() {
         assert!(provider.id == "gmail");
     }
 
+    #[test]
+    fn test_provider_quirks() {
+        let provider = get_provider_by_domain("aol.com").unwrap();
+        assert!(provider.opt.app_password_required);
+        assert!(!provider.opt.pop_only);
+
+        let provider = get_provider_by_domain("i.ua").unwrap();
+        assert!(provider.opt.pop_only);
+
+        let provider = get_provider_by_domain("nauta.cu").unwrap();
+        assert!(!provider.opt.app_password_required);
+        assert!(!provider.opt.pop_only);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_info() -> Result<()> {
+        let t = TestContext::new().await;
+        t.set_config_bool(Config::Socks5Enabled, true).await?;
+        let provider = get_info(&t, "alice@gmail.com").await?.unwrap();
+        assert_eq!(provider.id, "gmail");
+        assert!(provider.oauth2_authorizer.is_some());
+        assert!(get_info(&t, "alice@unexistant.org").await?.is_none());
+        assert!(get_info(&t, "not an address").await.is_err());
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_get_provider_info() {
         let t = TestContext::new().await;
@@ -77,7 +77,10 @@
         Server { protocol: Imap, socket: Ssl, hostname: "imap.aol.com", port: 993, username_pattern: Email },
         Server { protocol: Smtp, socket: Ssl, hostname: "smtp.aol.com", port: 465, username_pattern: Email },
     ],
-    opt: ProviderOptions::new(),
+    opt: ProviderOptions {
+        app_password_required: true,
+        ..ProviderOptions::new()
+    },
     config_defaults: None,
     oauth2_authorizer: None,
 };
@@ -551,7 +554,10 @@
     after_login_hint: "",
     overview_page: "https://providers.delta.chat/i-ua",
     server: &[],
-    opt: ProviderOptions::new(),
+    opt: ProviderOptions {
+        pop_only: true,
+        ..ProviderOptions::new()
+    },
     config_defaults: None,
     oauth2_authorizer: None,
 };
@@ -1144,7 +1150,10 @@
         Server { protocol: Imap, socket: Ssl, hostname: "secureimap.t-online.de", port: 993, username_pattern: Email },
         Server { protocol: Smtp, socket: Ssl, hostname: "securesmtp.t-online.de", port: 465, username_pattern: Email },
     ],
-    opt: ProviderOptions::new(),
+    opt: ProviderOptions {
+        app_password_required: true,
+        ..ProviderOptions::new()
+    },
     config_defaults: None,
     oauth2_authorizer: None,
 };
@@ -1377,7 +1386,10 @@
         Server { protocol: Imap, socket: Ssl, hostname: "imap.mail.yahoo.com", port: 993, username_pattern: Email },
         Server { protocol: Smtp, socket: Ssl, hostname: "smtp.mail.yahoo.com", port: 465, username_pattern: Email },
     ],
-    opt: ProviderOptions::new(),
+    opt: ProviderOptions {
+        app_password_required: true,
+        ..ProviderOptions::new()
+    },
     config_defaults: None,
     oauth2_authorizer: None,
 };
@@ -0,0 +1,408 @@
+//! # Push notifications.
+//!
+//! The UI obtains a device token from the platform push service,
+//! e.g. an FCM or APNS token or a UnifiedPush endpoint,
+//! and registers it with [`Context::set_push_device_token`]
+//! or [`Accounts::set_push_device_token`].
+//! If [`Config::PushProxyUrl`] is set, the token is sent to the notification proxy
+//! as a JSON `POST` request together with a random push ID and a random secret:
+//! `{"token": "...", "push_id": "...", "secret": "..."}`.
+//! The address of the account is not sent, the proxy only learns the push ID.
+//!
+//! To wake up the device, the proxy sends a push payload
+//! `{"push_id": "...", "data": "..."}`, where `data` is an ASCII-armored OpenPGP message
+//! symmetrically encrypted with the secret and containing `{"folder": "..."}`.
+//! The push ID does not reveal the address to the push service
+//! and the encryption prevents spoofed wake-ups.
+//! [`Accounts::handle_push_payload`] maps the payload to the account and folder to fetch.
+//!
+//! Setting an empty token unregisters the account from the proxy
+//! by sending the registration with an empty `token`.
+//! Push ID and secret are then forgotten,
+//! so that a new push ID is generated when a token is registered again.
+//!
+//! Device token, push ID and secret are specific to the device
+//! and are not exported to backups.
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::accounts::Accounts;
+use crate::config::{Config, InternalConfig};
+use crate::context::Context;
+use crate::events::EventType;
+use crate::imap::FolderMeaning;
+use crate::log::LogExt;
+use crate::net::ensure_online;
+use crate::net::http::get_client;
+use crate::pgp;
+use crate::socks::Socks5Config;
+use crate::tools::create_id;
+
+/// State of the push token registration, shown by [`Context::get_connectivity_html`].
+#[derive(Debug, Default, Clone)]
+pub(crate) enum PushState {
+    /// No token was registered since the start of the app.
+    #[default]
+    NotRegistered,
+
+    /// The token is registered with the notification proxy.
+    Registered,
+
+    /// Registering the token failed.
+    Error(String),
+}
+
+/// Registration sent to the notification proxy.
+#[derive(Debug, Serialize)]
+struct Registration<'a> {
+    token: &'a str,
+    push_id: &'a str,
+    secret: &'a str,
+}
+
+/// Push payload as delivered by the push service.
+#[derive(Debug, Deserialize)]
+struct PushPayload {
+    push_id: String,
+    data: String,
+}
+
+/// Encrypted part of the push payload.
+#[derive(Debug, Serialize, Deserialize)]
+struct WakeUp {
+    /// Folder with new messages, all watched folders are fetched if unset or unknown.
+    #[serde(default)]
+    folder: Option<String>,
+}
+
+impl Context {
+    /// Sets the push device token of this device
+    /// and registers it with the notification proxy if [`Config::PushProxyUrl`] is set.
+    ///
+    /// Should be called on every app start as the token may change.
+    /// An empty token unsets the token and unregisters the account from the notification proxy.
+    pub async fn set_push_device_token(&self, token: &str) -> Result<()> {
+        if token.is_empty() {
+            let res = unregister_push_token(self).await;
+            self.set_config(Config::PushDeviceToken, None).await?;
+            register_push_token(self).await?;
+            return res;
+        }
+        self.set_config(Config::PushDeviceToken, Some(token))
+            .await?;
+        register_push_token(self).await
+    }
+}
+
+impl Accounts {
+    /// Sets the push device token for all accounts,
+    /// see [`Context::set_push_device_token`].
+    ///
+    /// Failing registrations are logged to the respective account.
+    pub async fn set_push_device_token(&self, token: &str) -> Result<()> {
+        for id in self.get_all() {
+            if let Some(account) = self.get_account(id) {
+                account
+                    .set_push_device_token(token)
+                    .await
+                    .context("Failed to register push token")
+                    .log_err(&account)
+                    .ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a push payload received from the push service
+    /// by fetching the folder of the account the payload is meant for,
+    /// giving up after `timeout`.
+    ///
+    /// Returns the ID of the account. See [`Context::background_fetch`] for details
+    /// about fetching and the emitted events.
+    pub async fn handle_push_payload(&self, payload: &str, timeout: Duration) -> Result<u32> {
+        let payload: PushPayload =
+            serde_json::from_str(payload).context("Failed to parse push payload")?;
+        for id in self.get_all() {
+            let Some(account) = self.get_account(id) else {
+                continue;
+            };
+            if account
+                .sql
+                .get_raw_config(InternalConfig::PushId.as_ref())
+                .await?
+                .as_deref()
+                != Some(payload.push_id.as_str())
+            {
+                continue;
+            }
+            let wake_up = decrypt_wake_up(&account, &payload.data).await?;
+            let folder_meaning = match wake_up.folder {
+                Some(folder) => folder_meaning(&account, &folder).await?,
+                None => None,
+            };
+            account
+                .background_fetch_folder(timeout, folder_meaning)
+                .await?;
+            return Ok(id);
+        }
+        bail!("No account for push ID {:?}", payload.push_id)
+    }
+}
+
+/// Returns push ID and secret of the account, generating them if needed.
+async fn get_push_credentials(context: &Context) -> Result<(String, String)> {
+    if let (Some(push_id), Some(secret)) = load_push_credentials(context).await? {
+        return Ok((push_id, secret));
+    }
+    let push_id = create_id();
+    let secret = create_id();
+    context
+        .sql
+        .set_raw_config(InternalConfig::PushId.as_ref(), Some(&push_id))
+        .await?;
+    context
+        .sql
+        .set_raw_config(InternalConfig::PushSecret.as_ref(), Some(&secret))
+        .await?;
+    Ok((push_id, secret))
+}
+
+/// Returns push ID and secret of the account if they were generated before.
+async fn load_push_credentials(context: &Context) -> Result<(Option<String>, Option<String>)> {
+    let push_id = context
+        .sql
+        .get_raw_config(InternalConfig::PushId.as_ref())
+        .await?;
+    let secret = context
+        .sql
+        .get_raw_config(InternalConfig::PushSecret.as_ref())
+        .await?;
+    Ok((push_id, secret))
+}
+
+/// Returns the URL of the notification proxy if one is set.
+async fn push_proxy_url(context: &Context) -> Result<Option<String>> {
+    Ok(context
+        .get_config(Config::PushProxyUrl)
+        .await?
+        .filter(|url| !url.is_empty()))
+}
+
+/// Sends the push device token to the notification proxy
+/// and updates the [`PushState`].
+///
+/// Does nothing if the account is not configured
+/// or no token or notification proxy is set.
+pub(crate) async fn register_push_token(context: &Context) -> Result<()> {
+    let res = register_push_token_inner(context).await;
+    let push_state = match &res {
+        Ok(true) => PushState::Registered,
+        Ok(false) => PushState::NotRegistered,
+        Err(err) => PushState::Error(format!("{err:#}")),
+    };
+    *context.push_state.write().expect("RwLock is poisoned") = push_state;
+    context.emit_event(EventType::ConnectivityChanged);
+    res.map(|_| ())
+}
+
+/// Returns true if the token was registered, false if there is nothing to register.
+async fn register_push_token_inner(context: &Context) -> Result<bool> {
+    let Some(url) = push_proxy_url(context).await? else {
+        return Ok(false);
+    };
+    let Some(token) = context.get_config(Config::PushDeviceToken).await? else {
+        return Ok(false);
+    };
+    if !context.is_configured().await? {
+        return Ok(false);
+    }
+    let (push_id, secret) = get_push_credentials(context).await?;
+    send_registration(
+        context,
+        &url,
+        &Registration {
+            token: &token,
+            push_id: &push_id,
+            secret: &secret,
+        },
+    )
+    .await?;
+    info!(context, "Registered push token with notification proxy.");
+    Ok(true)
+}
+
+/// Unregisters the push ID from the notification proxy if a token was registered
+/// and forgets push ID and secret.
+///
+/// Push ID and secret are kept if the proxy cannot be reached,
+/// so that unregistering can be retried.
+async fn unregister_push_token(context: &Context) -> Result<()> {
+    let (Some(push_id), Some(secret)) = load_push_credentials(context).await? else {
+        return Ok(());
+    };
+    let token = context.get_config(Config::PushDeviceToken).await?;
+    let url = push_proxy_url(context).await?;
+    let configured = context.is_configured().await?;
+    if let (Some(_), Some(url), true) = (token, url, configured) {
+        send_registration(
+            context,
+            &url,
+            &Registration {
+                token: "",
+                push_id: &push_id,
+                secret: &secret,
+            },
+        )
+        .await?;
+        info!(context, "Unregistered push token from notification proxy.");
+    }
+    context
+        .sql
+        .set_raw_config(InternalConfig::PushId.as_ref(), None)
+        .await?;
+    context
+        .sql
+        .set_raw_config(InternalConfig::PushSecret.as_ref(), None)
+        .await?;
+    Ok(())
+}
+
+/// Sends a registration to the notification proxy.
+async fn send_registration(
+    context: &Context,
+    url: &str,
+    registration: &Registration<'_>,
+) -> Result<()> {
+    ensure_online(context).await?;
+    let body = serde_json::to_string(registration)?;
+    let socks5_config = Socks5Config::from_context(context).await?;
+    let response = get_client(socks5_config)?
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .context("Failed to reach notification proxy")?;
+    let status = response.status();
+    ensure!(status.is_success(), "Notification proxy returned {status}");
+    Ok(())
+}
+
+/// Decrypts the encrypted part of a push payload with the push secret.
+async fn decrypt_wake_up(context: &Context, data: &str) -> Result<WakeUp> {
+    let secret = context
+        .sql
+        .get_raw_config(InternalConfig::PushSecret.as_ref())
+        .await?
+        .context("No push secret")?;
+    let plain = pgp::symm_decrypt(&secret, Cursor::new(data.as_bytes()))
+        .await
+        .context("Failed to decrypt push payload")?;
+    serde_json::from_slice(&plain).context("Failed to parse decrypted push payload")
+}
+
+/// Returns the meaning of a watched folder given by name.
+async fn folder_meaning(context: &Context, folder: &str) -> Result<Option<FolderMeaning>> {
+    for folder_meaning in [FolderMeaning::Inbox, FolderMeaning::Mvbox] {
+        if let Some(config) = folder_meaning.to_config() {
+            if context.get_config(config).await?.as_deref() == Some(folder) {
+                return Ok(Some(folder_meaning));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a push payload as the notification proxy would.
+    async fn build_payload(push_id: &str, secret: &str, folder: Option<&str>) -> Result<String> {
+        let wake_up = WakeUp {
+            folder: folder.map(|folder| folder.to_string()),
+        };
+        let data = pgp::symm_encrypt(secret, serde_json::to_string(&wake_up)?.as_bytes()).await?;
+        Ok(serde_json::json!({ "push_id": push_id, "data": data }).to_string())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_handle_push_payload() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut accounts = Accounts::new(dir.path().join("accounts"), true).await?;
+        let id1 = accounts.add_account().await?;
+        let id2 = accounts.add_account().await?;
+        let ctx2 = accounts.get_account(id2).unwrap();
+        let (push_id, secret) = get_push_credentials(&ctx2).await?;
+        assert_eq!(
+            get_push_credentials(&ctx2).await?,
+            (push_id.clone(), secret.clone())
+        );
+        let (other_push_id, _) = get_push_credentials(&accounts.get_account(id1).unwrap()).await?;
+        assert_ne!(push_id, other_push_id);
+
+        let events = accounts.get_event_emitter();
+        let payload = build_payload(&push_id, &secret, Some("INBOX")).await?;
+        let timeout = Duration::from_secs(10);
+        assert_eq!(accounts.handle_push_payload(&payload, timeout).await?, id2);
+        loop {
+            let event = events.recv().await.unwrap();
+            if matches!(event.typ, EventType::BackgroundFetchDone) {
+                assert_eq!(event.id, id2);
+                break;
+            }
+        }
+
+        // Payloads encrypted with a wrong secret are rejected.
+        let payload = build_payload(&push_id, "wrong secret", None).await?;
+        assert!(accounts
+            .handle_push_payload(&payload, timeout)
+            .await
+            .is_err());
+
+        // Unknown push IDs are rejected.
+        let payload = build_payload("unknown", &secret, None).await?;
+        assert!(accounts
+            .handle_push_payload(&payload, timeout)
+            .await
+            .is_err());
+        assert!(accounts
+            .handle_push_payload("garbage", timeout)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_set_push_device_token_unconfigured() -> Result<()> {
+        let t = crate::test_utils::TestContext::new().await;
+        t.set_config(
+            Config::PushProxyUrl,
+            Some("https://push.example.org/register"),
+        )
+        .await?;
+        assert!(t
+            .set_config(Config::PushProxyUrl, Some("http://push.example.org/"))
+            .await
+            .is_err());
+
+        // Nothing is sent for unconfigured accounts, so no push ID is generated.
+        t.set_push_device_token("token").await?;
+        assert_eq!(
+            t.get_config(Config::PushDeviceToken).await?.as_deref(),
+            Some("token")
+        );
+        assert_eq!(load_push_credentials(&t).await?, (None, None));
+
+        // Unsetting the token forgets the push ID.
+        get_push_credentials(&t).await?;
+        t.set_push_device_token("").await?;
+        assert_eq!(t.get_config(Config::PushDeviceToken).await?, None);
+        assert_eq!(load_push_credentials(&t).await?, (None, None));
+        Ok(())
+    }
+}
@@ -1,9 +1,14 @@
 //! # QR code generation module.
 
+use std::io::Cursor;
+
 use anyhow::Result;
 use base64::Engine as _;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
 use qrcodegen::{QrCode, QrCodeEcc};
 
+use crate::avatar::avatar_letter;
 use crate::blob::BlobObject;
 use crate::chat::{Chat, ChatId};
 use crate::color::color_int_to_hex_string;
@@ -19,14 +24,44 @@
 /// If `chat_id` is `None`, returns verification QR code.
 /// Otherwise, returns secure join QR code.
 pub async fn get_securejoin_qr_svg(context: &Context, chat_id: Option<ChatId>) -> Result<String> {
+    let card = securejoin_qr_card(context, chat_id).await?;
+    inner_generate_secure_join_qr_code(
+        &card.description,
+        &card.content,
+        &color_int_to_hex_string(card.color),
+        card.avatar,
+        &card.avatar_letter,
+    )
+}
+
+/// Returns PNG of the QR code to join the group or verify contact.
+///
+/// Unlike [`get_securejoin_qr_svg`], the PNG contains only the QR code
+/// with the avatar or the color badge in the middle,
+/// but neither the description nor the avatar letter.
+pub async fn get_securejoin_qr_png(context: &Context, chat_id: Option<ChatId>) -> Result<Vec<u8>> {
+    let card = securejoin_qr_card(context, chat_id).await?;
+    render_qr_png(&card.content, card.color, card.avatar.as_deref())
+}
+
+/// Contents of a secure join QR code image.
+struct QrCard {
+    description: String,
+    content: String,
+    color: u32,
+    avatar: Option<Vec<u8>>,
+    avatar_letter: String,
+}
+
+async fn securejoin_qr_card(context: &Context, chat_id: Option<ChatId>) -> Result<QrCard> {
     if let Some(chat_id) = chat_id {
-        generate_join_group_qr_code(context, chat_id).await
+        join_group_qr_card(context, chat_id).await
     } else {
-        generate_verification_qr(context).await
+        verification_qr_card(context).await
     }
 }
 
-async fn generate_join_group_qr_code(context: &Context, chat_id: ChatId) -> Result<String> {
+async fn join_group_qr_card(context: &Context, chat_id: ChatId) -> Result<QrCard> {
     let chat = Chat::load_from_db(context, chat_id).await?;
 
     let avatar = match chat.get_profile_image(context).await? {
@@ -37,25 +72,25 @@ async fn generate_join_group_qr_code(context: &Context, chat_id: ChatId) -> Resu
         None => None,
     };
 
-    inner_generate_secure_join_qr_code(
-        &stock_str::secure_join_group_qr_description(context, &chat).await,
-        &securejoin::get_securejoin_qr(context, Some(chat_id)).await?,
-        &color_int_to_hex_string(chat.get_color(context).await?),
+    Ok(QrCard {
+        description: stock_str::secure_join_group_qr_description(context, &chat).await,
+        content: securejoin::get_securejoin_qr(context, Some(chat_id)).await?,
+        color: chat.get_color(context).await?,
         avatar,
-        chat.get_name().chars().next().unwrap_or('#'),
-    )
+        avatar_letter: avatar_letter(chat.get_name()),
+    })
 }
 
-async fn generate_verification_qr(context: &Context) -> Result<String> {
+async fn verification_qr_card(context: &Context) -> Result<QrCard> {
     let (avatar, displayname, addr, color) = self_info(context).await?;
 
-    inner_generate_secure_join_qr_code(
-        &stock_str::setup_contact_qr_description(context, &displayname, &addr).await,
-        &securejoin::get_securejoin_qr(context, None).await?,
-        &color,
+    Ok(QrCard {
+        description: stock_str::setup_contact_qr_description(context, &displayname, &addr).await,
+        content: securejoin::get_securejoin_qr(context, None).await?,
+        color,
         avatar,
-        displayname.chars().next().unwrap_or('#'),
-    )
+        avatar_letter: avatar_letter(&displayname),
+    })
 }
 
 /// Renders a [`Qr::Backup`] QR code as an SVG image.
@@ -67,14 +102,14 @@ pub async fn generate_backup_qr(context: &Context, qr: &Qr) -> Result<String> {
     inner_generate_secure_join_qr_code(
         &description,
         &content,
-        &color,
+        &color_int_to_hex_string(color),
         avatar,
-        displayname.chars().next().unwrap_or('#'),
+        &avatar_letter(&displayname),
     )
 }
 
 /// Returns `(avatar, displayname, addr, color) of the configured account.
-async fn self_info(context: &Context) -> Result<(Option<Vec<u8>>, String, String, String)> {
+async fn self_info(context: &Context) -> Result<(Option<Vec<u8>>, String, String, u32)> {
     let contact = Contact::get_by_id(context, ContactId::SELF).await?;
 
     let avatar = match contact.get_profile_image(context).await? {
@@ -90,8 +125,7 @@ async fn self_info(context: &Context) -> Result<(Option<Vec<u8>>, String, String
         None => contact.get_addr().to_string(),
     };
     let addr = contact.get_addr().to_string();
-    let color = color_int_to_hex_string(contact.get_color());
-    Ok((avatar, displayname, addr, color))
+    Ok((avatar, displayname, addr, contact.get_color()))
 }
 
 fn inner_generate_secure_join_qr_code(
@@ -99,7 +133,7 @@ fn inner_generate_secure_join_qr_code(
     qrcode_content: &str,
     color: &str,
     avatar: Option<Vec<u8>>,
-    avatar_letter: char,
+    avatar_letter: &str,
 ) -> Result<String> {
     // config
     let width = 515.0;
@@ -272,7 +306,7 @@ fn inner_generate_secure_join_qr_code(
                     ),
                 )
             })?
-            .build(|w| w.put_raw(avatar_letter.to_uppercase()))?;
+            .build(|w| w.put_raw(avatar_letter))?;
         }
 
         // Footer logo
@@ -294,10 +328,74 @@ fn inner_generate_secure_join_qr_code(
     Ok(svg)
 }
 
+/// Renders a QR code as PNG with a circular badge in the middle.
+///
+/// The badge shows the avatar if there is one,
+/// otherwise it is filled with the given color.
+fn render_qr_png(content: &str, color: u32, avatar: Option<&[u8]>) -> Result<Vec<u8>> {
+    // Size of one QR code module in pixels.
+    const MODULE_SIZE: u32 = 8;
+    // Quiet zone around the QR code in modules.
+    const BORDER: i32 = 4;
+
+    let qr = QrCode::encode_text(content, QrCodeEcc::Medium)?;
+    let modules = qr.size() + 2 * BORDER;
+    let img_size = u32::try_from(modules)? * MODULE_SIZE;
+
+    // Same proportions as in the SVG.
+    let center = img_size as f32 / 2.0;
+    let badge_radius = qr.size() as f32 * MODULE_SIZE as f32 * 94.4 / 400.0 / 2.0;
+    let badge_border = qr.size() as f32 * MODULE_SIZE as f32 * 9.0 / 400.0;
+    let badge_origin = (center - badge_radius).max(0.0) as u32;
+    let badge_size = (badge_radius * 2.0) as u32;
+    let avatar = match avatar {
+        Some(avatar) => Some(
+            image::load_from_memory(avatar)?
+                .resize_exact(badge_size, badge_size, FilterType::Triangle)
+                .to_rgb8(),
+        ),
+        None => None,
+    };
+    let badge_color = Rgb([(color >> 16) as u8, (color >> 8) as u8, color as u8]);
+
+    let img = RgbImage::from_fn(img_size, img_size, |x, y| {
+        let distance = (x as f32 + 0.5 - center).hypot(y as f32 + 0.5 - center);
+        if distance <= badge_radius {
+            avatar
+                .as_ref()
+                .and_then(|avatar| {
+                    avatar.get_pixel_checked(
+                        x.saturating_sub(badge_origin),
+                        y.saturating_sub(badge_origin),
+                    )
+                })
+                .copied()
+                .unwrap_or(badge_color)
+        } else if distance <= badge_radius + badge_border {
+            Rgb([255, 255, 255])
+        } else {
+            let (module_x, module_y) = (
+                (x / MODULE_SIZE) as i32 - BORDER,
+                (y / MODULE_SIZE) as i32 - BORDER,
+            );
+            if qr.get_module(module_x, module_y) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }
+    });
+
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(img).write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
 #[cfg(test)]
 mod tests {
     use testdir::testdir;
 
+    use crate::chat::{create_group_chat, ProtectionStatus};
     use crate::imex::BackupProvider;
     use crate::qr::format_backup;
     use crate::test_utils::TestContextManager;
@@ -311,12 +409,41 @@ async fn test_svg_escaping() {
             "qr-code-content",
             "#000000",
             None,
-            'X',
+            "X",
         )
         .unwrap();
         assert!(svg.contains("descr123 &quot; &lt; &gt; &amp;"))
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_securejoin_qr_png() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let chat_id = create_group_chat(&alice, ProtectionStatus::Unprotected, "Group").await?;
+
+        for chat_id in [None, Some(chat_id)] {
+            let png = get_securejoin_qr_png(&alice, chat_id).await?;
+            let img = image::load_from_memory(&png)?.to_rgb8();
+            assert_eq!(img.width(), img.height());
+            // The corner is in the quiet zone, the finder pattern starts after it.
+            assert_eq!(img.get_pixel(0, 0), &Rgb([255, 255, 255]));
+            assert_eq!(img.get_pixel(4 * 8, 4 * 8), &Rgb([0, 0, 0]));
+        }
+
+        // The badge has the color of the contact.
+        let png = get_securejoin_qr_png(&alice, None).await?;
+        let img = image::load_from_memory(&png)?.to_rgb8();
+        let color = Contact::get_by_id(&alice, ContactId::SELF)
+            .await?
+            .get_color();
+        let center = img.width() / 2;
+        assert_eq!(
+            img.get_pixel(center, center),
+            &Rgb([(color >> 16) as u8, (color >> 8) as u8, color as u8])
+        );
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_generate_backup_qr() {
         let dir = testdir!();
@@ -153,15 +153,30 @@ pub(crate) async fn update_recent_quota(&self, imap: &mut Imap) -> Result<()> {
         self.emit_event(EventType::ConnectivityChanged);
         Ok(())
     }
+
+    /// Returns the usage of the fullest quota resource in percent.
+    ///
+    /// Returns `None` if the quota was not loaded yet
+    /// or could not be loaded, e.g. because the server does not support quota.
+    pub async fn get_quota_usage_percentage(&self) -> Option<u64> {
+        let quota = self.quota.read().await;
+        let recent = quota.as_ref()?.recent.as_ref().ok()?;
+        get_highest_usage(recent)
+            .ok()
+            .map(|(percentage, _, _)| percentage)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use async_imap::types::QuotaResourceName;
+
     use super::*;
     use crate::quota::{
         QUOTA_ALLCLEAR_PERCENTAGE, QUOTA_ERROR_THRESHOLD_PERCENTAGE,
         QUOTA_WARN_THRESHOLD_PERCENTAGE,
     };
+    use crate::test_utils::TestContext;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_needs_quota_warning() -> Result<()> {
@@ -181,6 +196,37 @@ async fn test_needs_quota_warning() -> Result<()> {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_quota_usage_percentage() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        assert_eq!(t.get_quota_usage_percentage().await, None);
+
+        *t.quota.write().await = Some(QuotaInfo {
+            recent: Err(anyhow!("not supported")),
+            modified: time(),
+        });
+        assert_eq!(t.get_quota_usage_percentage().await, None);
+
+        let resources = vec![
+            QuotaResource {
+                name: QuotaResourceName::Storage,
+                usage: 30,
+                limit: 100,
+            },
+            QuotaResource {
+                name: QuotaResourceName::Message,
+                usage: 90,
+                limit: 1000,
+            },
+        ];
+        *t.quota.write().await = Some(QuotaInfo {
+            recent: Ok(BTreeMap::from([("".to_string(), resources)])),
+            modified: time(),
+        });
+        assert_eq!(t.get_quota_usage_percentage().await, Some(30));
+        Ok(())
+    }
+
     #[allow(clippy::assertions_on_constants)]
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_quota_thresholds() -> anyhow::Result<()> {
@@ -1,7 +1,7 @@
 //! Internet Message Format reception pipeline.
 
 use std::cmp::min;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::convert::TryFrom;
 
 use anyhow::{Context as _, Result};
@@ -10,37 +10,54 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::authres;
+use crate::bot;
 use crate::chat::{self, Chat, ChatId, ChatIdBlocked, ProtectionStatus};
 use crate::config::Config;
-use crate::constants::{Blocked, Chattype, ShowEmails, DC_CHAT_ID_TRASH};
+use crate::constants::{Blocked, Chattype, ShowEmails, DC_CHAT_ID_LAST_SPECIAL, DC_CHAT_ID_TRASH};
 use crate::contact::{
-    may_be_valid_addr, normalize_name, Contact, ContactAddress, ContactId, Origin,
+    addr_cmp, may_be_valid_addr, normalize_name, Contact, ContactAddress, ContactId, Origin,
+    ReceivedEncryption,
 };
 use crate::context::Context;
 use crate::debug_logging::maybe_set_logging_xdc_inner;
+use crate::dkim::{self, DkimStatus};
 use crate::download::DownloadState;
+use crate::e2ee;
 use crate::ephemeral::{stock_ephemeral_timer_changed, Timer as EphemeralTimer};
 use crate::events::EventType;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
+use crate::html;
 use crate::imap::{markseen_on_imap_table, GENERATED_PREFIX};
 use crate::location;
 use crate::log::LogExt;
+use crate::markup;
 use crate::message::{
     self, rfc724_mid_exists, Message, MessageState, MessengerMessage, MsgId, Viewtype,
 };
-use crate::mimeparser::{parse_message_ids, AvatarAction, MimeMessage, SystemMessage};
+use crate::mimeparser::{
+    parse_message_ids, AvatarAction, MimeMessage, StreamedMessage, SystemMessage,
+};
 use crate::param::{Param, Params};
+use crate::peer_channels;
 use crate::peerstate::{Peerstate, PeerstateKeyType, PeerstateVerifiedStatus};
 use crate::reaction::{set_msg_reaction, Reaction};
 use crate::securejoin::{self, handle_securejoin_handshake, observe_securejoin_on_other_device};
 use crate::simplify;
+use crate::spam;
 use crate::sql;
 use crate::stock_str;
 use crate::tools::{
-    buf_compress, extract_grpid_from_rfc724_mid, smeared_time, strip_rtlo_characters,
+    buf_compress, extract_grpid_from_rfc724_mid, remove_subject_prefix, smeared_time,
+    strip_rtlo_characters, EmailAddress,
 };
+use crate::undo;
 use crate::{contact, imap};
 
+/// How many seconds the `Date` of a message may be ahead of the server timestamp
+/// before the clock of the sender is considered skewed.
+const CLOCK_SKEW_TOLERANCE: i64 = 10 * 60;
+
 /// This is the struct that is returned after receiving one email (aka MIME message).
 ///
 /// One email with multiple attachments can end up as multiple chat messages, but they
@@ -115,6 +132,49 @@ pub(crate) async fn receive_imf_inner(
     seen: bool,
     is_partial_download: Option<u32>,
     fetching_existing_messages: bool,
+) -> Result<Option<ReceivedMsg>> {
+    receive_imf_impl(
+        context,
+        rfc724_mid,
+        imf_raw,
+        None,
+        seen,
+        is_partial_download,
+        fetching_existing_messages,
+    )
+    .await
+}
+
+/// Receives a message fetched in chunks
+/// with [`MessageStreamer`](crate::mimeparser::MessageStreamer), see [`receive_imf_inner`].
+pub(crate) async fn receive_streamed_imf(
+    context: &Context,
+    rfc724_mid: &str,
+    streamed: &StreamedMessage,
+    seen: bool,
+    fetching_existing_messages: bool,
+) -> Result<Option<ReceivedMsg>> {
+    receive_imf_impl(
+        context,
+        rfc724_mid,
+        &streamed.raw,
+        Some(streamed),
+        seen,
+        None,
+        fetching_existing_messages,
+    )
+    .await
+}
+
+/// Receives a message, `streamed` is set if `imf_raw` is the raw message of it.
+async fn receive_imf_impl(
+    context: &Context,
+    rfc724_mid: &str,
+    imf_raw: &[u8],
+    streamed: Option<&StreamedMessage>,
+    seen: bool,
+    is_partial_download: Option<u32>,
+    fetching_existing_messages: bool,
 ) -> Result<Option<ReceivedMsg>> {
     info!(context, "Receiving message, seen={seen}...");
 
@@ -126,8 +186,21 @@ pub(crate) async fn receive_imf_inner(
         );
     }
 
-    let mut mime_parser = match MimeMessage::from_bytes(context, imf_raw, is_partial_download).await
-    {
+    let mime_parser = match streamed {
+        Some(streamed) => MimeMessage::from_streamed(context, streamed).await,
+        None => MimeMessage::from_bytes(context, imf_raw, is_partial_download).await,
+    };
+    let mime_parser = match mime_parser {
+        Err(err) if is_partial_download.is_none() => {
+            warn!(
+                context,
+                "receive_imf: can't parse MIME: {err:#}, salvaging."
+            );
+            MimeMessage::from_damaged_bytes(context, imf_raw, &err).await
+        }
+        res => res,
+    };
+    let mut mime_parser = match mime_parser {
         Err(err) => {
             warn!(context, "receive_imf: can't parse MIME: {err:#}.");
             if rfc724_mid.starts_with(GENERATED_PREFIX) {
@@ -135,28 +208,31 @@ pub(crate) async fn receive_imf_inner(
                 return Ok(None);
             }
 
-            let row_id = context
-                .sql
-                .execute(
-                    "INSERT INTO msgs(rfc724_mid, chat_id) VALUES (?,?)",
-                    (rfc724_mid, DC_CHAT_ID_TRASH),
-                )
-                .await?;
-            let msg_ids = vec![MsgId::new(u32::try_from(row_id)?)];
-
-            return Ok(Some(ReceivedMsg {
-                chat_id: DC_CHAT_ID_TRASH,
-                state: MessageState::Undefined,
-                sort_timestamp: 0,
-                msg_ids,
-                needs_delete_job: false,
-            }));
+            return trash_received_msg(context, rfc724_mid).await.map(Some);
         }
         Ok(mime_parser) => mime_parser,
     };
 
     info!(context, "Received message has Message-Id: {rfc724_mid}");
 
+    // DKIM verification may need DNS lookups, so it is done before `receive_imf_mutex` is taken.
+    let dkim_status =
+        if is_partial_download.is_none() && context.get_config_bool(Config::VerifyDkim).await? {
+            let body_hashes = streamed.map(|streamed| streamed.dkim_body_hashes.as_slice());
+            dkim::verify(context, imf_raw, &mime_parser.from.addr, body_hashes)
+                .await
+                .unwrap_or_else(|err| {
+                    warn!(context, "receive_imf: DKIM verification failed: {err:#}.");
+                    DkimStatus::None
+                })
+        } else {
+            DkimStatus::None
+        };
+
+    // The check and adding the message to the database must not be interrupted
+    // by receiving the same message, e.g. from another folder.
+    let receive_guard = context.receive_imf_mutex.lock().await;
+
     // check, if the mail is already in our database.
     // make sure, this check is done eg. before securejoin-processing.
     let (replace_partial_download, replace_chat_id) =
@@ -172,14 +248,33 @@ pub(crate) async fn receive_imf_inner(
             } else {
                 // the message was probably moved around.
                 info!(context, "Message already in DB, doing nothing.");
+                if msg.from_id == ContactId::SELF {
+                    // Copy of a message sent by this device,
+                    // it should not be shown as unread by other clients.
+                    markseen_on_imap_table(context, rfc724_mid).await?;
+                }
                 return Ok(None);
             }
         } else {
             (None, None)
         };
 
+    let rcvd_timestamp = smeared_time(context);
+    let server_timestamp = get_server_timestamp(context, rfc724_mid).await?;
+    let date = mime_parser
+        .get_header(HeaderDef::Date)
+        .and_then(|value| mailparse::dateparse(value).ok());
+
+    // Sender timestamp is allowed to be a bit in the future due to
+    // unsynchronized clocks, but not too much.
+    let sent_timestamp = date.map_or(rcvd_timestamp, |value| min(value, rcvd_timestamp + 60));
+
     let prevent_rename =
         mime_parser.is_mailinglist_message() || mime_parser.get_header(HeaderDef::Sender).is_some();
+    // The display name is not taken from messages older than the one it was last taken from,
+    // otherwise fetching messages out of order would revert name changes.
+    let keep_name = prevent_rename
+        || is_outdated_displayname(context, &mime_parser.from.addr, sent_timestamp).await?;
 
     // get From: (it can be an address list!) and check if it is known (for known From:'s we add
     // the other To:/Cc: in the 3rd pass)
@@ -190,7 +285,7 @@ pub(crate) async fn receive_imf_inner(
     // If this is a mailing list email (i.e. list_id_header is some), don't change the displayname because in
     // a mailing list the sender displayname sometimes does not belong to the sender email address.
     let (from_id, _from_id_blocked, incoming_origin) =
-        match from_field_to_contact_id(context, &mime_parser.from, prevent_rename).await? {
+        match from_field_to_contact_id(context, &mime_parser.from, keep_name).await? {
             Some(contact_id_res) => contact_id_res,
             None => {
                 warn!(
@@ -202,6 +297,18 @@ pub(crate) async fn receive_imf_inner(
         };
 
     let incoming = from_id != ContactId::SELF;
+    if incoming
+        && !keep_name
+        && mime_parser
+            .from
+            .display_name
+            .as_ref()
+            .map_or(false, |name| !name.is_empty())
+    {
+        context
+            .update_contacts_timestamp(from_id, Param::DisplaynameTimestamp, sent_timestamp)
+            .await?;
+    }
 
     let to_ids = add_or_lookup_contacts_by_address_list(
         context,
@@ -216,14 +323,52 @@ pub(crate) async fn receive_imf_inner(
     )
     .await?;
 
-    let rcvd_timestamp = smeared_time(context);
+    // Unlike the `Date` header, the server timestamp does not depend on the clock of the sender.
+    // If the `Date` is too far ahead of it, the message is sorted by the server timestamp.
+    // `Date` in the past is not corrected as it cannot be told apart from a delayed delivery.
+    let clock_skew = match date {
+        Some(date) if server_timestamp > 0 && date - server_timestamp > CLOCK_SKEW_TOLERANCE => {
+            date - server_timestamp
+        }
+        _ => 0,
+    };
+    let sort_base_timestamp = if clock_skew > 0 {
+        server_timestamp
+    } else {
+        sent_timestamp
+    };
 
-    // Sender timestamp is allowed to be a bit in the future due to
-    // unsynchronized clocks, but not too much.
-    let sent_timestamp = mime_parser
-        .get_header(HeaderDef::Date)
-        .and_then(|value| mailparse::dateparse(value).ok())
-        .map_or(rcvd_timestamp, |value| min(value, rcvd_timestamp + 60));
+    if !incoming
+        && dkim_status == DkimStatus::Fail
+        && mime_parser.signatures.is_empty()
+        && !mime_parser.is_mailinglist_message()
+    {
+        // Someone else may pretend to be us, warn the user instead of hiding the message
+        // as it may also be a genuine message modified by a mailing list or a forwarder.
+        warn!(
+            context,
+            "receive_imf: Message from self has invalid DKIM signature."
+        );
+        let error = stock_str::sender_not_verified(context).await;
+        for part in &mut mime_parser.parts {
+            part.error = Some(error.clone());
+        }
+    }
+    if incoming && dkim_status == DkimStatus::Pass {
+        if let Ok(from) = EmailAddress::new(&mime_parser.from.addr) {
+            authres::dkim_verified(context, &from.domain, sent_timestamp).await?;
+        }
+    }
+    if dkim_status != DkimStatus::None {
+        for part in &mut mime_parser.parts {
+            part.param.set_int(Param::DkimStatus, dkim_status as i32);
+        }
+    }
+    if incoming && is_sender_authenticated(&mime_parser) {
+        for part in &mut mime_parser.parts {
+            part.param.set_int(Param::SenderAuthenticated, 1);
+        }
+    }
 
     // Add parts
     let received_msg = add_parts(
@@ -235,6 +380,8 @@ pub(crate) async fn receive_imf_inner(
         rfc724_mid,
         sent_timestamp,
         rcvd_timestamp,
+        server_timestamp,
+        sort_base_timestamp,
         from_id,
         seen || replace_partial_download.is_some(),
         is_partial_download,
@@ -244,8 +391,46 @@ pub(crate) async fn receive_imf_inner(
     )
     .await
     .context("add_parts error")?;
+    drop(receive_guard);
+    if incoming {
+        context.metrics.record_msg_received();
+    }
 
     if !from_id.is_special() {
+        if incoming {
+            let folder: Option<String> = context
+                .sql
+                .query_get_value(
+                    "SELECT folder FROM imap WHERE rfc724_mid=? ORDER BY id DESC LIMIT 1",
+                    (rfc724_mid,),
+                )
+                .await?;
+            let encryption = if !mime_parser.was_encrypted() {
+                ReceivedEncryption::Unencrypted
+            } else if mime_parser
+                .decryption_info
+                .peerstate
+                .as_ref()
+                .and_then(|peerstate| peerstate.verified_key_fingerprint.as_ref())
+                .filter(|fp| mime_parser.signatures.contains(fp))
+                .is_some()
+            {
+                ReceivedEncryption::Verified
+            } else {
+                ReceivedEncryption::Encrypted
+            };
+            contact::update_trust_timeline(
+                context,
+                from_id,
+                sent_timestamp,
+                folder.as_deref(),
+                encryption,
+            )
+            .await?;
+            if server_timestamp > 0 {
+                contact::update_clock_skew(context, from_id, clock_skew).await?;
+            }
+        }
         contact::update_last_seen(context, from_id, sent_timestamp).await?;
     }
 
@@ -253,12 +438,7 @@ pub(crate) async fn receive_imf_inner(
     // Autocrypt-Gossip for all recipients in the chat to avoid sending Autocrypt-Gossip ourselves
     // and waste traffic.
     let chat_id = received_msg.chat_id;
-    if !chat_id.is_special()
-        && mime_parser
-            .recipients
-            .iter()
-            .all(|recipient| mime_parser.gossiped_addr.contains(&recipient.addr))
-    {
+    if !chat_id.is_special() && is_gossiped_for_all_recipients(context, &mime_parser).await? {
         info!(
             context,
             "Received message contains Autocrypt-Gossip for all members, updating timestamp."
@@ -301,6 +481,26 @@ pub(crate) async fn receive_imf_inner(
         }
     }
 
+    if let Some(ref advertisement) = mime_parser.webxdc_realtime_advertisement {
+        if mime_parser.was_encrypted() {
+            if let Err(err) = peer_channels::receive_webxdc_realtime_advertisement(
+                context,
+                from_id,
+                insert_msg_id,
+                advertisement,
+            )
+            .await
+            {
+                warn!(
+                    context,
+                    "receive_imf cannot handle realtime advertisement: {err:#}."
+                );
+            }
+        } else {
+            warn!(context, "Realtime advertisement is not encrypted.");
+        }
+    }
+
     if let Some(avatar_action) = &mime_parser.user_avatar {
         if from_id != ContactId::UNDEFINED
             && context
@@ -321,7 +521,12 @@ pub(crate) async fn receive_imf_inner(
     }
 
     // Ignore footers from mailinglists as they are often created or modified by the mailinglist software.
-    if let Some(footer) = &mime_parser.footer {
+    // The status from the `Chat-User-Status` header is preferred as the footer may be mangled.
+    if let Some(status) = mime_parser
+        .user_status
+        .as_ref()
+        .or(mime_parser.footer.as_ref())
+    {
         if !mime_parser.is_mailinglist_message()
             && from_id != ContactId::UNDEFINED
             && context
@@ -331,7 +536,7 @@ pub(crate) async fn receive_imf_inner(
             if let Err(err) = contact::set_status(
                 context,
                 from_id,
-                footer.to_string(),
+                status.to_string(),
                 mime_parser.was_encrypted(),
                 mime_parser.has_chat_version(),
             )
@@ -342,6 +547,26 @@ pub(crate) async fn receive_imf_inner(
         }
     }
 
+    // Take over the display name set on another device.
+    // As for the status, this is only done if the message is sent from Delta Chat and encrypted.
+    // Messages with `Sender:` header have the name overridden, see `set_override_sender_name()`.
+    if from_id == ContactId::SELF
+        && mime_parser.get_header(HeaderDef::Sender).is_none()
+        && mime_parser.was_encrypted()
+        && mime_parser.has_chat_version()
+    {
+        if let Some(display_name) = &mime_parser.from.display_name {
+            if context
+                .update_contacts_timestamp(from_id, Param::DisplaynameTimestamp, sent_timestamp)
+                .await?
+            {
+                if let Err(err) = contact::set_self_displayname(context, display_name).await {
+                    warn!(context, "Cannot update self displayname: {err:#}.");
+                }
+            }
+        }
+    }
+
     // Get user-configured server deletion
     let delete_server_after = context.get_config_delete_server_after().await?;
 
@@ -379,6 +604,18 @@ pub(crate) async fn receive_imf_inner(
 
     from_id.mark_bot(context, mime_parser.is_bot).await?;
 
+    if incoming
+        && !fetching_existing_messages
+        && !chat_id.is_special()
+        && received_msg.state == MessageState::InFresh
+        && !received_msg.msg_ids.is_empty()
+        && mime_parser.may_be_auto_replied()
+    {
+        if let Err(err) = bot::maybe_send_auto_reply(context, chat_id, from_id).await {
+            warn!(context, "Cannot send automatic reply: {err:#}.");
+        }
+    }
+
     Ok(Some(received_msg))
 }
 
@@ -431,6 +668,46 @@ pub async fn from_field_to_contact_id(
     }
 }
 
+/// Returns true if the display name of the contact with the address `addr`
+/// was taken from a message newer than `sent_timestamp`.
+async fn is_outdated_displayname(
+    context: &Context,
+    addr: &str,
+    sent_timestamp: i64,
+) -> Result<bool> {
+    let Some(contact_id) = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await? else {
+        return Ok(false);
+    };
+    if contact_id == ContactId::SELF {
+        return Ok(false);
+    }
+    let contact = Contact::get_by_id(context, contact_id).await?;
+    Ok(contact
+        .param
+        .get_i64(Param::DisplaynameTimestamp)
+        .map_or(false, |timestamp| timestamp > sent_timestamp))
+}
+
+/// Inserts a trash entry for the message so that it is not downloaded again.
+async fn trash_received_msg(context: &Context, rfc724_mid: &str) -> Result<ReceivedMsg> {
+    let row_id = context
+        .sql
+        .execute(
+            "INSERT INTO msgs(rfc724_mid, chat_id) VALUES (?,?)",
+            (rfc724_mid, DC_CHAT_ID_TRASH),
+        )
+        .await?;
+    let msg_ids = vec![MsgId::new(u32::try_from(row_id)?)];
+
+    Ok(ReceivedMsg {
+        chat_id: DC_CHAT_ID_TRASH,
+        state: MessageState::Undefined,
+        sort_timestamp: 0,
+        msg_ids,
+        needs_delete_job: false,
+    })
+}
+
 /// Creates a `ReceivedMsg` from given parts which might consist of
 /// multiple messages (if there are multiple attachments).
 /// Every entry in `mime_parser.parts` produces a new row in the `msgs` table.
@@ -444,6 +721,8 @@ async fn add_parts(
     rfc724_mid: &str,
     sent_timestamp: i64,
     rcvd_timestamp: i64,
+    server_timestamp: i64,
+    sort_base_timestamp: i64,
     from_id: ContactId,
     seen: bool,
     is_partial_download: Option<u32>,
@@ -479,13 +758,18 @@ async fn add_parts(
     let show_emails =
         ShowEmails::from_i32(context.get_config_int(Config::ShowEmails).await?).unwrap_or_default();
 
+    let is_classic_email = match show_emails {
+        // Replies of classic email clients to chat messages are classic emails as well.
+        ShowEmails::ChatMessagesOnly => is_dc_message != MessengerMessage::Yes,
+        ShowEmails::Off | ShowEmails::AcceptedContacts | ShowEmails::All => {
+            is_dc_message == MessengerMessage::No
+        }
+    };
     let allow_creation;
-    if mime_parser.is_system_message != SystemMessage::AutocryptSetupMessage
-        && is_dc_message == MessengerMessage::No
-    {
+    if mime_parser.is_system_message != SystemMessage::AutocryptSetupMessage && is_classic_email {
         // this message is a classic email not a chat-message nor a reply to one
         match show_emails {
-            ShowEmails::Off => {
+            ShowEmails::Off | ShowEmails::ChatMessagesOnly => {
                 info!(context, "Classical email not shown (TRASH).");
                 chat_id = Some(DC_CHAT_ID_TRASH);
                 allow_creation = false;
@@ -567,8 +851,9 @@ async fn add_parts(
 
         // signals whether the current user is a bot
         let is_bot = context.get_config_bool(Config::Bot).await?;
+        let auto_accept = is_bot || is_auto_accepted(context, mime_parser).await?;
 
-        let create_blocked_default = if is_bot {
+        let create_blocked_default = if auto_accept {
             Blocked::Not
         } else {
             Blocked::Request
@@ -617,6 +902,22 @@ async fn add_parts(
             }
         }
 
+        // Only the owner may post to channels, but everybody may leave.
+        // Other messages are assigned to the 1:1 chat with the sender.
+        if let Some(group_chat_id) = chat_id.filter(|chat_id| !chat_id.is_special()) {
+            let chat = Chat::load_from_db(context, group_chat_id).await?;
+            let is_leaving = mime_parser
+                .get_header(HeaderDef::ChatGroupMemberRemoved)
+                .map_or(false, |addr| addr_cmp(addr, &mime_parser.from.addr));
+            if !chat.may_post(context, from_id).await? && !is_leaving {
+                info!(
+                    context,
+                    "Assigning message to {from_id} in channel {group_chat_id} to 1:1 chat."
+                );
+                chat_id = None;
+            }
+        }
+
         // if the chat is somehow blocked but we want to create a non-blocked chat,
         // unblock the chat
         if chat_id_blocked != Blocked::Not && create_blocked != Blocked::Yes {
@@ -700,7 +1001,7 @@ async fn add_parts(
                 let contact = Contact::get_by_id(context, from_id).await?;
                 match contact.is_blocked() {
                     true => Blocked::Yes,
-                    false if is_bot => Blocked::Not,
+                    false if auto_accept => Blocked::Not,
                     false => Blocked::Request,
                 }
             };
@@ -772,9 +1073,14 @@ async fn add_parts(
                         // The message itself will be sorted under the device message since the device
                         // message is `MessageState::InNoticed`, which means that all following
                         // messages are sorted under it.
-                        let sort_timestamp =
-                            calc_sort_timestamp(context, sent_timestamp, chat_id, true, incoming)
-                                .await?;
+                        let sort_timestamp = calc_sort_timestamp(
+                            context,
+                            sort_base_timestamp,
+                            chat_id,
+                            true,
+                            incoming,
+                        )
+                        .await?;
                         chat_id
                             .set_protection(context, new_protection, sort_timestamp, Some(from_id))
                             .await?;
@@ -783,12 +1089,20 @@ async fn add_parts(
             }
         }
 
+        let is_spam = match chat_id {
+            Some(chat_id) if chat_id_blocked == Blocked::Request => {
+                spam::classify_contact_request(context, chat_id, mime_parser, rfc724_mid).await?
+            }
+            _ => false,
+        };
+
         state = if seen
             || fetching_existing_messages
             || is_mdn
             || is_reaction
             || is_location_kml
             || securejoin_seen
+            || is_spam
             || chat_id_blocked == Blocked::Yes
         {
             MessageState::InSeen
@@ -803,6 +1117,15 @@ async fn add_parts(
         state = MessageState::OutDelivered;
         to_id = to_ids.get(0).copied().unwrap_or_default();
 
+        // This is a copy of a message sent by another device,
+        // it should not be shown as unread by other clients.
+        markseen_on_imap_table(context, rfc724_mid).await?;
+        // Messages sent before this device was set up count as well,
+        // the device which sent them may still be in use.
+        if mime_parser.has_chat_version() {
+            context.set_other_devices_known().await?;
+        }
+
         let self_sent =
             from_id == ContactId::SELF && to_ids.len() == 1 && to_ids.contains(&ContactId::SELF);
 
@@ -942,6 +1265,36 @@ async fn add_parts(
         }
     }
 
+    if mime_parser.webxdc_realtime_advertisement.is_some() {
+        chat_id = Some(DC_CHAT_ID_TRASH);
+        info!(
+            context,
+            "Message is a webxdc realtime advertisement (TRASH)."
+        );
+        markseen_on_imap_table(context, rfc724_mid).await.ok();
+    }
+
+    if mime_parser
+        .get_header(HeaderDef::ChatKeyTransition)
+        .is_some()
+    {
+        chat_id = Some(DC_CHAT_ID_TRASH);
+        info!(context, "Message is a key-transition notice (TRASH).");
+        markseen_on_imap_table(context, rfc724_mid).await.ok();
+    }
+
+    if let Some(chat_id_some) = chat_id {
+        if incoming
+            && mime_parser.is_auto_reply()
+            && context.get_config_bool(Config::CollapseAutoReplies).await?
+            && is_repeated_auto_reply(context, chat_id_some, from_id).await?
+        {
+            chat_id = Some(DC_CHAT_ID_TRASH);
+            info!(context, "Message is a repeated automatic reply (TRASH).");
+            markseen_on_imap_table(context, rfc724_mid).await.ok();
+        }
+    }
+
     let orig_chat_id = chat_id;
     let chat_id = if is_mdn || is_reaction {
         DC_CHAT_ID_TRASH
@@ -952,6 +1305,10 @@ async fn add_parts(
         })
     };
 
+    if incoming && !chat_id.is_special() {
+        maybe_set_chat_self_addr(context, mime_parser, chat_id).await?;
+    }
+
     // Extract ephemeral timer from the message or use the existing timer if the message is not fully downloaded.
     let mut ephemeral_timer = if is_partial_download.is_some() {
         chat_id.get_ephemeral_timer(context).await?
@@ -969,7 +1326,7 @@ async fn add_parts(
 
     let in_fresh = state == MessageState::InFresh;
     let sort_timestamp =
-        calc_sort_timestamp(context, sent_timestamp, chat_id, false, incoming).await?;
+        calc_sort_timestamp(context, sort_base_timestamp, chat_id, false, incoming).await?;
 
     // Apply ephemeral timer changes to the chat.
     //
@@ -1097,22 +1454,49 @@ async fn add_parts(
     let is_system_message = mime_parser.is_system_message;
 
     // if indicated by the parser,
-    // we save the full mime-message and add a flag
+    // we save the sanitized HTML of the full message and add a flag
     // that the ui should show button to display the full message.
 
     // a flag used to avoid adding "show full message" button to multiple parts of the message.
     let mut save_mime_modified = mime_parser.is_mime_modified;
 
-    let mime_headers = if save_mime_headers || save_mime_modified {
-        let headers = if !mime_parser.decoded_data.is_empty() {
-            mime_parser.decoded_data.clone()
-        } else {
-            imf_raw.to_vec()
-        };
+    let raw_mime = if !mime_parser.decoded_data.is_empty() {
+        mime_parser.decoded_data.as_slice()
+    } else {
+        imf_raw
+    };
+    let mime_headers = if save_mime_headers {
+        let headers = raw_mime.to_vec();
         tokio::task::block_in_place(move || buf_compress(&headers))?
     } else {
         Vec::new()
     };
+    let html = if save_mime_modified {
+        match html::sanitized_html_from_bytes(context, raw_mime).await {
+            Ok(html) => tokio::task::block_in_place(move || buf_compress(html.as_bytes()))?,
+            Err(err) => {
+                warn!(context, "Cannot convert message to HTML: {err:#}.");
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let encrypt_at_rest =
+        mime_parser.was_encrypted() && context.get_config_bool(Config::EncryptAtRest).await?;
+    let mime_headers = if encrypt_at_rest && !mime_headers.is_empty() {
+        e2ee::encrypt_at_rest(context, &mime_headers)
+            .await?
+            .into_bytes()
+    } else {
+        mime_headers
+    };
+    let html = if encrypt_at_rest && !html.is_empty() {
+        e2ee::encrypt_at_rest(context, &html).await?.into_bytes()
+    } else {
+        html
+    };
 
     let mut created_db_entries = Vec::with_capacity(mime_parser.parts.len());
 
@@ -1133,6 +1517,15 @@ async fn add_parts(
         if is_system_message != SystemMessage::Unknown {
             param.set_int(Param::Cmd, is_system_message as i32);
         }
+        // Store the affected member as for outgoing messages, see `Message::get_info_contact_id()`.
+        let member_header = match is_system_message {
+            SystemMessage::MemberAddedToGroup => Some(HeaderDef::ChatGroupMemberAdded),
+            SystemMessage::MemberRemovedFromGroup => Some(HeaderDef::ChatGroupMemberRemoved),
+            _ => None,
+        };
+        if let Some(addr) = member_header.and_then(|header| mime_parser.get_header(header)) {
+            param.set(Param::Arg, addr);
+        }
 
         if let Some(replace_msg_id) = replace_msg_id {
             let placeholder = Message::load_from_db(context, replace_msg_id).await?;
@@ -1154,6 +1547,12 @@ async fn add_parts(
         } else {
             (&part.msg, part.typ)
         };
+        if better_msg.is_none() {
+            let markup = markup::parse_markup(msg);
+            if !markup.is_empty() {
+                param.set(Param::Markup, markup::ranges_to_param(&markup));
+            }
+        }
 
         let part_is_empty =
             typ == Viewtype::Text && msg.is_empty() && part.param.get(Param::Quote).is_none();
@@ -1184,6 +1583,23 @@ async fn add_parts(
         let trash =
             chat_id.is_trash() || (is_location_kml && msg.is_empty() && typ == Viewtype::Text);
 
+        let encrypted_at_rest = encrypt_at_rest && !trash;
+        let (msg, subject, txt_raw) = if encrypted_at_rest {
+            (
+                e2ee::encrypt_text_at_rest(context, msg).await?,
+                e2ee::encrypt_text_at_rest(context, &subject).await?,
+                e2ee::encrypt_text_at_rest(context, &txt_raw).await?,
+            )
+        } else {
+            (msg.to_string(), subject.clone(), txt_raw)
+        };
+        if encrypted_at_rest {
+            if let Some(quote) = param.get(Param::Quote) {
+                let quote = e2ee::encrypt_text_at_rest(context, quote).await?;
+                param.set(Param::Quote, quote);
+            }
+        }
+
         let row_id = context
             .sql
             .call_write(|conn| {
@@ -1194,30 +1610,32 @@ async fn add_parts(
     id,
     rfc724_mid, chat_id,
     from_id, to_id, timestamp, timestamp_sent, 
-    timestamp_rcvd, type, state, msgrmsg, 
+    timestamp_rcvd, timestamp_server, type, state, msgrmsg, 
     txt, subject, txt_raw, param, 
-    bytes, mime_headers, mime_compressed, mime_in_reply_to,
+    bytes, mime_headers, html, mime_compressed, mime_in_reply_to,
     mime_references, mime_modified, error, ephemeral_timer,
-    ephemeral_timestamp, download_state, hop_info
+    ephemeral_timestamp, download_state, hop_info, encrypted_at_rest, part
   )
   VALUES (
     ?,
     ?, ?, ?, ?,
+    ?, ?, ?, ?, ?,
     ?, ?, ?, ?,
+    ?, ?, ?, ?, ?, 1,
     ?, ?, ?, ?,
-    ?, ?, ?, ?, 1,
-    ?, ?, ?, ?,
-    ?, ?, ?, ?
+    ?, ?, ?, ?, ?, ?
   )
 ON CONFLICT (id) DO UPDATE
 SET rfc724_mid=excluded.rfc724_mid, chat_id=excluded.chat_id,
     from_id=excluded.from_id, to_id=excluded.to_id, timestamp_sent=excluded.timestamp_sent,
+    timestamp_server=excluded.timestamp_server,
     type=excluded.type, msgrmsg=excluded.msgrmsg,
     txt=excluded.txt, subject=excluded.subject, txt_raw=excluded.txt_raw, param=excluded.param,
-    bytes=excluded.bytes, mime_headers=excluded.mime_headers,
+    bytes=excluded.bytes, mime_headers=excluded.mime_headers, html=excluded.html,
     mime_compressed=excluded.mime_compressed, mime_in_reply_to=excluded.mime_in_reply_to,
     mime_references=excluded.mime_references, mime_modified=excluded.mime_modified, error=excluded.error, ephemeral_timer=excluded.ephemeral_timer,
-    ephemeral_timestamp=excluded.ephemeral_timestamp, download_state=excluded.download_state, hop_info=excluded.hop_info
+    ephemeral_timestamp=excluded.ephemeral_timestamp, download_state=excluded.download_state, hop_info=excluded.hop_info,
+    encrypted_at_rest=excluded.encrypted_at_rest, part=excluded.part
 RETURNING id
 "#)?;
                 let row_id: MsgId = stmt.query_row(params![
@@ -1229,10 +1647,11 @@ async fn add_parts(
                     sort_timestamp,
                     sent_timestamp,
                     rcvd_timestamp,
+                    server_timestamp,
                     typ,
                     state,
                     is_dc_message,
-                    if trash { "" } else { msg },
+                    if trash { "" } else { &msg },
                     if trash { "" } else { &subject },
                     // txt_raw might contain invalid utf8
                     if trash { "" } else { &txt_raw },
@@ -1242,11 +1661,16 @@ async fn add_parts(
                         param.to_string()
                     },
                     part.bytes as isize,
-                    if (save_mime_headers || mime_modified) && !trash {
+                    if save_mime_headers && !trash {
                         mime_headers.clone()
                     } else {
                         Vec::new()
                     },
+                    if mime_modified && !trash {
+                        html.clone()
+                    } else {
+                        Vec::new()
+                    },
                     mime_in_reply_to,
                     mime_references,
                     mime_modified,
@@ -1260,7 +1684,10 @@ async fn add_parts(
                     } else {
                         DownloadState::Done
                     },
-                    mime_parser.hop_info
+                    mime_parser.hop_info,
+                    encrypted_at_rest,
+                    // The first part is unique per Message-ID, see `msgs_index10`.
+                    created_db_entries.len()
                 ],
                 |row| {
                     let msg_id: MsgId = row.get(0)?;
@@ -1299,6 +1726,9 @@ async fn add_parts(
     }
 
     chat_id.unarchive_if_not_muted(context, state).await?;
+    if !chat_id.is_trash() {
+        undo::cancel_chat_deletion(context, chat_id).await?;
+    }
 
     info!(
         context,
@@ -1345,6 +1775,122 @@ async fn add_parts(
     })
 }
 
+/// Returns true if the last message from `from_id` in the chat is an automatic reply,
+/// see [`Config::CollapseAutoReplies`].
+async fn is_repeated_auto_reply(
+    context: &Context,
+    chat_id: ChatId,
+    from_id: ContactId,
+) -> Result<bool> {
+    if chat_id.is_special() {
+        return Ok(false);
+    }
+    let param: Option<String> = context
+        .sql
+        .query_get_value(
+            "SELECT param FROM msgs
+             WHERE chat_id=? AND from_id=? AND hidden=0
+             ORDER BY timestamp DESC, id DESC LIMIT 1",
+            (chat_id, from_id),
+        )
+        .await?;
+    let param: Params = param.unwrap_or_default().parse().unwrap_or_default();
+    Ok(param.get_bool(Param::AutoReply).unwrap_or_default())
+}
+
+/// Returns true if chats with the sender are accepted without a contact request
+/// because of [`Config::AutoAcceptRules`].
+///
+/// As the sender address may be spoofed, it must be authenticated,
+/// see [`is_sender_authenticated`].
+async fn is_auto_accepted(context: &Context, mime_parser: &MimeMessage) -> Result<bool> {
+    let Some(rules) = context.get_config(Config::AutoAcceptRules).await? else {
+        return Ok(false);
+    };
+    if !auto_accept_rules_match(&rules, &mime_parser.from.addr) {
+        return Ok(false);
+    }
+    Ok(is_sender_authenticated(mime_parser))
+}
+
+/// Returns true if the sender address of the message is authenticated.
+///
+/// The message must be signed by a key of the sender known before this message
+/// or have a DKIM signature of the sender domain,
+/// verified locally or by the own server according to its Authentication-Results header.
+/// A key from the Autocrypt header of the same message authenticates nothing,
+/// anybody can send one together with a forged From address.
+fn is_sender_authenticated(mime_parser: &MimeMessage) -> bool {
+    mime_parser
+        .signatures
+        .iter()
+        .any(|fp| mime_parser.decryption_info.known_fingerprints.contains(fp))
+        || mime_parser
+            .parts
+            .iter()
+            .any(|part| part.param.get_int(Param::DkimStatus) == Some(DkimStatus::Pass as i32))
+        || mime_parser
+            .decryption_info
+            .dkim_results
+            .dkim_passed_by_server
+}
+
+/// Returns true if one of the space-separated `rules` matches `addr`.
+///
+/// A rule is either an address or a domain, matching all addresses of the domain
+/// and its subdomains.
+pub(crate) fn auto_accept_rules_match(rules: &str, addr: &str) -> bool {
+    let addr = addr.to_lowercase();
+    let Some((_, domain)) = addr.rsplit_once('@') else {
+        return false;
+    };
+    rules.split_ascii_whitespace().any(|rule| {
+        let rule = rule.trim_start_matches('@').to_lowercase();
+        if rule.contains('@') {
+            rule == addr
+        } else {
+            domain == rule
+                || domain
+                    .strip_suffix(rule.as_str())
+                    .map_or(false, |sub| sub.ends_with('.'))
+        }
+    })
+}
+
+/// Makes the chat reply from an alias address
+/// if an incoming message is sent to the alias and no self address is chosen for the chat yet.
+async fn maybe_set_chat_self_addr(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    chat_id: ChatId,
+) -> Result<()> {
+    let alias_addrs = context.get_alias_addrs().await?;
+    if alias_addrs.is_empty() {
+        return Ok(());
+    }
+    let primary_addr = context.get_primary_self_addr().await?;
+    if mime_parser
+        .recipients
+        .iter()
+        .any(|recipient| addr_cmp(&recipient.addr, &primary_addr))
+    {
+        return Ok(());
+    }
+    let Some(alias) = alias_addrs.iter().find(|alias| {
+        mime_parser
+            .recipients
+            .iter()
+            .any(|recipient| addr_cmp(&recipient.addr, alias))
+    }) else {
+        return Ok(());
+    };
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    if chat.param.exists(Param::SelfAddr) {
+        return Ok(());
+    }
+    chat_id.set_self_addr(context, Some(alias)).await
+}
+
 /// Saves attached locations to the database.
 ///
 /// Emits an event if at least one new location was added.
@@ -1398,6 +1944,20 @@ async fn save_locations(
     Ok(())
 }
 
+/// Returns the `INTERNALDATE` of the message on the IMAP server
+/// as stored during prefetch, or 0 if unknown.
+async fn get_server_timestamp(context: &Context, rfc724_mid: &str) -> Result<i64> {
+    Ok(context
+        .sql
+        .query_get_value(
+            "SELECT internaldate FROM imap WHERE rfc724_mid=? AND internaldate>0
+             ORDER BY internaldate LIMIT 1",
+            (rfc724_mid,),
+        )
+        .await?
+        .unwrap_or_default())
+}
+
 async fn calc_sort_timestamp(
     context: &Context,
     message_timestamp: i64,
@@ -1562,8 +2122,7 @@ async fn create_or_lookup_group(
 
         let res = create_adhoc_group(context, mime_parser, create_blocked, &member_ids)
             .await
-            .context("could not create ad hoc group")?
-            .map(|chat_id| (chat_id, create_blocked));
+            .context("could not create ad hoc group")?;
         return Ok(res);
     } else {
         info!(context, "Creating ad-hoc group prevented from caller.");
@@ -1636,6 +2195,13 @@ async fn self_explicitly_added(
             // W/a for "Space added before long group names after MIME serialization/deserialization
             // #3650" issue. DC itself never creates group names with leading/trailing whitespace.
             .trim();
+        let param = mime_parser
+            .get_header(HeaderDef::ChatGroupChannelOwner)
+            .map(|owner| {
+                let mut param = Params::new();
+                param.set(Param::ChannelOwner, owner);
+                param.to_string()
+            });
         let new_chat_id = ChatId::create_multiuser_record(
             context,
             Chattype::Group,
@@ -1643,7 +2209,7 @@ async fn self_explicitly_added(
             grpname,
             create_blocked,
             create_protected,
-            None,
+            param,
         )
         .await
         .with_context(|| format!("Failed to create group '{grpname}' for grpid={grpid}"))?;
@@ -1692,6 +2258,25 @@ async fn self_explicitly_added(
     }
 }
 
+/// Returns true if the message contains Autocrypt-Gossip for all its recipients.
+///
+/// Our own addresses are skipped as there is no need to gossip our key to us.
+async fn is_gossiped_for_all_recipients(
+    context: &Context,
+    mime_parser: &MimeMessage,
+) -> Result<bool> {
+    if mime_parser.gossiped_addr.is_empty() {
+        return Ok(false);
+    }
+    for recipient in &mime_parser.recipients {
+        let addr = recipient.addr.to_lowercase();
+        if !mime_parser.gossiped_addr.contains(&addr) && !context.is_self_addr(&addr).await? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
 /// Apply group member list, name, avatar and protection status changes from the MIME message.
 ///
 /// Optionally returns better message to replace the original system message.
@@ -1724,8 +2309,25 @@ async fn apply_group_changes(
     let is_from_in_chat =
         !chat_contacts.contains(&ContactId::SELF) || chat_contacts.contains(&from_id);
 
-    // Reject group membership changes from non-members and old changes.
+    // If the group uses roles, only admins may change it, but everybody may leave.
+    let is_from_admin = chat.is_admin(context, from_id).await?;
+    let is_from_leaving = if let Some(removed_addr) =
+        mime_parser.get_header(HeaderDef::ChatGroupMemberRemoved)
+    {
+        Contact::lookup_id_by_addr(context, removed_addr, Origin::Unknown).await? == Some(from_id)
+    } else {
+        false
+    };
+
+    // Classic MUAs do not know about roles. Recipients they add receive the conversation anyway,
+    // so they are added as members, but classic MUAs never remove members or recreate the
+    // member list, see below.
+    let may_change_members = is_from_admin || is_from_leaving;
+    let is_from_classic_mua = !mime_parser.has_chat_version();
+
+    // Reject group membership changes from non-members, non-admins and old changes.
     let allow_member_list_changes = is_from_in_chat
+        && (may_change_members || is_from_classic_mua)
         && chat_id
             .update_timestamp(context, Param::MemberListTimestamp, sent_timestamp)
             .await?;
@@ -1743,13 +2345,13 @@ async fn apply_group_changes(
                 None => false,
             }
     } && {
-        if !allow_member_list_changes {
+        if !allow_member_list_changes || !may_change_members {
             info!(
                 context,
                 "Ignoring a try to recreate member list of {chat_id} by {from_id}.",
             );
         }
-        allow_member_list_changes
+        allow_member_list_changes && may_change_members
     };
 
     if mime_parser.get_header(HeaderDef::ChatVerified).is_some() {
@@ -1808,7 +2410,9 @@ async fn apply_group_changes(
         // See create_or_lookup_group() for explanation
         .map(|s| s.trim())
     {
-        if let Some(grpname) = mime_parser
+        if !is_from_admin {
+            info!(context, "Ignoring name change of {chat_id} by non-admin.");
+        } else if let Some(grpname) = mime_parser
             .get_header(HeaderDef::ChatGroupName)
             // See create_or_lookup_group() for explanation
             .map(|grpname| grpname.trim())
@@ -1831,8 +2435,14 @@ async fn apply_group_changes(
 
             better_msg = Some(stock_str::msg_grp_name(context, old_name, grpname, from_id).await);
         }
-    } else if let Some(value) = mime_parser.get_header(HeaderDef::ChatContent) {
-        if value == "group-avatar-changed" {
+    } else if let Some(value) = mime_parser
+        .get_header(HeaderDef::ChatContent)
+        // Changes by non-admins are ignored below.
+        .filter(|_| is_from_admin)
+    {
+        if value == "group-admins-changed" {
+            better_msg = Some(stock_str::msg_grp_admins_changed(context, from_id).await);
+        } else if value == "group-avatar-changed" {
             if let Some(avatar_action) = &mime_parser.group_avatar {
                 // this is just an explicit message containing the group-avatar,
                 // apart from that, the group-avatar is send along with various other messages
@@ -1891,6 +2501,12 @@ async fn apply_group_changes(
         }
 
         if new_members != chat_contacts {
+            if !new_members.is_subset(&chat_contacts) {
+                // New members may not have the keys of all other members yet,
+                // so send Autocrypt-Gossip with our next message
+                // unless the received message already did it.
+                chat_id.reset_gossiped_timestamp(context).await?;
+            }
             let new_members_ref = &new_members;
             context
                 .sql
@@ -1922,6 +2538,8 @@ async fn apply_group_changes(
                 context,
                 "Contact {from_id} attempts to modify group chat {chat_id} avatar without being a member.",
             );
+        } else if !is_from_admin {
+            info!(context, "Ignoring avatar change of {chat_id} by non-admin.");
         } else {
             info!(context, "Group-avatar change for {chat_id}.");
             if chat
@@ -1942,6 +2560,21 @@ async fn apply_group_changes(
         }
     }
 
+    if let Some(admins) = mime_parser.get_header(HeaderDef::ChatGroupAdmins) {
+        if is_from_admin
+            && chat.param.get(Param::GroupAdmins) != Some(admins.as_str())
+            && chat_id
+                .update_timestamp(context, Param::GroupAdminsTimestamp, sent_timestamp)
+                .await?
+        {
+            info!(context, "Updating admins of {chat_id}.");
+            let mut chat = Chat::load_from_db(context, chat_id).await?;
+            chat.param.set(Param::GroupAdmins, admins);
+            chat.update_param(context).await?;
+            send_event_chat_modified = true;
+        }
+    }
+
     if send_event_chat_modified {
         context.emit_event(EventType::ChatModified(chat_id));
     }
@@ -2084,7 +2717,7 @@ fn compute_mailinglist_name(
     strip_rtlo_characters(&name)
 }
 
-/// Set ListId param on the contact and ListPost param the chat.
+/// Set ListId param on the contact and ListPost and ListUnsubscribe params on the chat.
 /// Only called for incoming messages since outgoing messages never have a
 /// List-Post header, anyway.
 async fn apply_mailinglist_changes(
@@ -2117,6 +2750,23 @@ async fn apply_mailinglist_changes(
         context.emit_event(EventType::ChatModified(chat_id));
     }
 
+    if let Some(list_unsubscribe) = mime_parser.get_header(HeaderDef::ListUnsubscribe) {
+        let one_click = mime_parser
+            .get_header(HeaderDef::ListUnsubscribePost)
+            .map_or(false, |post| {
+                post.trim()
+                    .eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+            });
+        if chat.param.get(Param::ListUnsubscribe) != Some(list_unsubscribe.as_str())
+            || chat.param.get_bool(Param::ListUnsubscribeOneClick) != Some(one_click)
+        {
+            chat.param.set(Param::ListUnsubscribe, list_unsubscribe);
+            chat.param
+                .set_int(Param::ListUnsubscribeOneClick, one_click.into());
+            chat.update_param(context).await?;
+        }
+    }
+
     let Some(list_post) = &mime_parser.list_post else {
         return Ok(());
     };
@@ -2181,13 +2831,13 @@ fn extract_grpid(mime_parser: &MimeMessage, headerdef: HeaderDef) -> Option<&str
     parts.filter_map(extract_grpid_from_rfc724_mid).next()
 }
 
-/// Creates ad-hoc group and returns chat ID on success.
+/// Looks up or creates ad-hoc group and returns (chat_id, chat_blocked) tuple on success.
 async fn create_adhoc_group(
     context: &Context,
     mime_parser: &MimeMessage,
     create_blocked: Blocked,
     member_ids: &[ContactId],
-) -> Result<Option<ChatId>> {
+) -> Result<Option<(ChatId, Blocked)>> {
     if mime_parser.is_mailinglist_message() {
         info!(
             context,
@@ -2223,6 +2873,14 @@ async fn create_adhoc_group(
         .get_subject()
         .unwrap_or_else(|| "Unnamed group".to_string());
 
+    if let Some((chat_id, blocked)) = lookup_adhoc_group(context, &grpname, member_ids).await? {
+        info!(
+            context,
+            "Assigning message to ad-hoc group {chat_id} with the same members and subject."
+        );
+        return Ok(Some((chat_id, blocked)));
+    }
+
     let new_chat_id: ChatId = ChatId::create_multiuser_record(
         context,
         Chattype::Group,
@@ -2237,7 +2895,69 @@ async fn create_adhoc_group(
 
     context.emit_event(EventType::ChatModified(new_chat_id));
 
-    Ok(Some(new_chat_id))
+    Ok(Some((new_chat_id, create_blocked)))
+}
+
+/// Returns the ad-hoc group with the given members and subject if there is one.
+///
+/// Ad-hoc groups have no group ID, so they are identified by the set of members
+/// and the subject without `Re:`-like prefixes.
+/// The order of recipients in the message does not matter.
+async fn lookup_adhoc_group(
+    context: &Context,
+    grpname: &str,
+    member_ids: &[ContactId],
+) -> Result<Option<(ChatId, Blocked)>> {
+    let member_ids: Vec<ContactId> = member_ids
+        .iter()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let Some(first_member_id) = member_ids.first() else {
+        return Ok(None);
+    };
+    let member_cnt = member_ids.len();
+    let subject = remove_subject_prefix(&strip_rtlo_characters(grpname));
+    // Only groups with exactly these members are selected, so there are few candidates.
+    let candidates = context
+        .sql
+        .query_map(
+            &format!(
+                "SELECT c.id, c.name, c.blocked
+                 FROM chats_contacts cc0
+                 INNER JOIN chats c ON c.id=cc0.chat_id
+                 WHERE cc0.contact_id=? AND c.type=? AND c.grpid='' AND c.id>?
+                 AND (SELECT COUNT(*) FROM chats_contacts cc WHERE cc.chat_id=c.id)=?
+                 AND (SELECT COUNT(*) FROM chats_contacts cc
+                      WHERE cc.chat_id=c.id AND cc.contact_id IN ({}))=?
+                 ORDER BY c.id",
+                sql::repeat_vars(member_cnt)
+            ),
+            rusqlite::params_from_iter(
+                params_slice![
+                    *first_member_id,
+                    Chattype::Group,
+                    DC_CHAT_ID_LAST_SPECIAL,
+                    member_cnt
+                ]
+                .into_iter()
+                .chain(sql::params_iter(&member_ids))
+                .chain(params_slice![member_cnt]),
+            ),
+            |row| {
+                let chat_id: ChatId = row.get(0)?;
+                let name: String = row.get(1)?;
+                let blocked: Option<Blocked> = row.get(2)?;
+                Ok((chat_id, name, blocked.unwrap_or_default()))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(candidates
+        .into_iter()
+        .find(|(_, name, _)| remove_subject_prefix(name) == subject)
+        .map(|(chat_id, _, blocked)| (chat_id, blocked)))
 }
 
 enum VerifiedEncryption {
@@ -117,6 +117,42 @@ async fn test_adhoc_group_show_chats_only() {
     assert_eq!(chats.len(), 1);
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_show_chat_messages_only() -> Result<()> {
+    let t = TestContext::new_alice().await;
+    t.set_config(Config::ShowEmails, Some("3")).await?;
+
+    receive_imf(&t, MSGRMSG, false).await?;
+    let msg = t.get_last_msg().await;
+    assert_eq!(msg.text, "hello");
+
+    // A classic email client replying to a chat message is not shown either,
+    // it is not even downloaded.
+    let raw_reply = b"From: Bob <bob@example.com>\n\
+          To: alice@example.org\n\
+          Subject: Re: Chat: hello\n\
+          Message-ID: <reply@example.com>\n\
+          In-Reply-To: <Mr.1111@example.com>\n\
+          Date: Sun, 22 Mar 2020 22:37:58 +0000\n\
+          \n\
+          reply from a classic email client\n";
+    let headers = mailparse::parse_mail(raw_reply)?.headers;
+    assert!(
+        !prefetch_should_download(&t, &headers, "reply@example.com", std::iter::empty()).await?
+    );
+    // With `ShowEmails::Off`, the reply would be shown.
+    t.set_config(Config::ShowEmails, Some("0")).await?;
+    assert!(prefetch_should_download(&t, &headers, "reply@example.com", std::iter::empty()).await?);
+    t.set_config(Config::ShowEmails, Some("3")).await?;
+
+    let reply = receive_imf(&t, raw_reply, false).await?.unwrap();
+    assert_eq!(reply.chat_id, DC_CHAT_ID_TRASH);
+
+    receive_imf(&t, ONETOONE_NOREPLY_MAIL, false).await?;
+    assert_eq!(t.get_last_msg().await.id, msg.id);
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_adhoc_group_show_accepted_contact_unknown() {
     let t = TestContext::new_alice().await;
@@ -2203,6 +2239,105 @@ async fn test_ignore_old_status_updates() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_user_status_header() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+
+    // The footer would only contain the part after the last signature separator.
+    let status = "Available\n-- \nÜber alles";
+    alice.set_config(Config::Selfstatus, Some(status)).await?;
+    let msg = tcm.send_recv_accept(&alice, &bob, "Hi").await;
+    let contact = Contact::get_by_id(&bob, msg.from_id).await?;
+    assert_eq!(contact.get_status(), status);
+
+    // Removing the status is also received.
+    alice.set_config(Config::Selfstatus, None).await?;
+    tcm.send_recv(&alice, &bob, "Hi again").await;
+    let contact = Contact::get_by_id(&bob, msg.from_id).await?;
+    assert_eq!(contact.get_status(), "");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_clock_skew() -> Result<()> {
+    let t = TestContext::new_alice().await;
+
+    async fn receive_with_server_timestamp(
+        t: &TestContext,
+        rfc724_mid: &str,
+        date: i64,
+        server_timestamp: i64,
+    ) -> Result<Message> {
+        t.sql
+            .execute(
+                "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target, internaldate)
+                 VALUES (?, 'INBOX', ?, 1, 'INBOX', ?)",
+                (rfc724_mid, server_timestamp, server_timestamp),
+            )
+            .await?;
+        let date = chrono::TimeZone::timestamp_opt(&chrono::Utc, date, 0)
+            .unwrap()
+            .to_rfc2822();
+        let raw = format!(
+            "From: bob@example.net\n\
+             To: alice@example.org\n\
+             Chat-Version: 1.0\n\
+             Message-ID: <{rfc724_mid}>\n\
+             Date: {date}\n\
+             \n\
+             hi\n"
+        );
+        let received = receive_imf(t, raw.as_bytes(), false).await?.unwrap();
+        Message::load_from_db(t, received.msg_ids[0]).await
+    }
+
+    // The clock of Bob is a day ahead.
+    let server_timestamp = crate::tools::time() - 3600;
+    let msg = receive_with_server_timestamp(
+        &t,
+        "skewed@example.net",
+        server_timestamp + 24 * 3600,
+        server_timestamp,
+    )
+    .await?;
+    assert_eq!(msg.get_server_timestamp(), server_timestamp);
+    assert_eq!(msg.get_sort_timestamp(), server_timestamp);
+    assert!(msg.get_timestamp() > server_timestamp + 3600);
+    let contact = Contact::get_by_id(&t, msg.from_id).await?;
+    assert_eq!(contact.get_clock_skew(), 24 * 3600);
+
+    // Bob fixed the clock.
+    let server_timestamp = crate::tools::time() - 60;
+    let msg =
+        receive_with_server_timestamp(&t, "fixed@example.net", server_timestamp, server_timestamp)
+            .await?;
+    assert_eq!(msg.get_sort_timestamp(), server_timestamp);
+    let contact = Contact::get_by_id(&t, msg.from_id).await?;
+    assert_eq!(contact.get_clock_skew(), 0);
+
+    // Without the server timestamp, e.g. for imported messages, nothing is corrected.
+    let received = receive_imf(
+        &t,
+        b"From: bob@example.net\n\
+          To: alice@example.org\n\
+          Message-ID: <unknown@example.net>\n\
+          Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+          \n\
+          hi\n",
+        false,
+    )
+    .await?
+    .unwrap();
+    let msg = Message::load_from_db(&t, received.msg_ids[0]).await?;
+    assert_eq!(msg.get_server_timestamp(), 0);
+    assert_eq!(msg.get_timestamp(), 1584916677);
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_chat_assignment_private_classical_reply() {
     for outgoing_is_classical in &[true, false] {
@@ -2561,6 +2696,63 @@ async fn test_chat_assignment_adhoc() -> Result<()> {
     Ok(())
 }
 
+/// Tests that messages to the same ad hoc group are assigned to one chat
+/// even if they don't reference each other and list the recipients in a different order.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_adhoc_group_lookup_by_members() -> Result<()> {
+    let t = TestContext::new_alice().await;
+    t.set_config(Config::ShowEmails, Some("2")).await?;
+
+    receive_imf(
+        &t,
+        b"Subject: Meeting\n\
+          Message-ID: <first@example.org>\n\
+          To: Alice <alice@example.org>, Bob <bob@example.net>\n\
+          From: Claire <claire@example.org>\n\
+          Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+          \n\
+          First message.\n",
+        false,
+    )
+    .await?;
+    let first_msg = t.get_last_msg().await;
+    let chat = Chat::load_from_db(&t, first_msg.chat_id).await?;
+    assert_eq!(chat.typ, Chattype::Group);
+
+    receive_imf(
+        &t,
+        b"Subject: Re: Meeting\n\
+          Message-ID: <second@example.org>\n\
+          To: Claire <claire@example.org>, Alice <alice@example.org>\n\
+          From: Bob <bob@example.net>\n\
+          Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+          \n\
+          Second message.\n",
+        false,
+    )
+    .await?;
+    let second_msg = t.get_last_msg().await;
+    assert_eq!(second_msg.chat_id, first_msg.chat_id);
+
+    // Another subject starts another thread.
+    receive_imf(
+        &t,
+        b"Subject: Lunch\n\
+          Message-ID: <third@example.org>\n\
+          To: Bob <bob@example.net>, Alice <alice@example.org>\n\
+          From: Claire <claire@example.org>\n\
+          Date: Sun, 22 Mar 2020 22:39:57 +0000\n\
+          \n\
+          Third message.\n",
+        false,
+    )
+    .await?;
+    let third_msg = t.get_last_msg().await;
+    assert_ne!(third_msg.chat_id, first_msg.chat_id);
+
+    Ok(())
+}
+
 /// Test that read receipts don't create chats.
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_read_receipts_dont_create_chats() -> Result<()> {
@@ -3810,3 +4002,370 @@ async fn test_create_group_with_big_msg() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_receive_damaged_msg() -> Result<()> {
+    let t = TestContext::new_alice().await;
+
+    // The second part has an invalid Content-Type and the closing delimiter is missing.
+    let raw = b"From: Bob <bob@example.net>\r\n\
+                To: alice@example.org\r\n\
+                Subject: Broken multipart\r\n\
+                Message-ID: <damaged1@example.net>\r\n\
+                Date: Sun, 22 Mar 2020 22:37:57 +0000\r\n\
+                Content-Type: multipart/mixed; boundary=\"xyz\"\r\n\
+                \r\n\
+                --xyz\r\n\
+                Content-Type: text/plain; charset=iso-8859-1\r\n\
+                Content-Transfer-Encoding: quoted-printable\r\n\
+                \r\n\
+                Gr=FC=DFe from the salvaged part\r\n\
+                --xyz\r\n\
+                Content-Type: application\r\n\
+                \r\n\
+                AAAA\r\n";
+    assert!(MimeMessage::from_bytes(&t, raw, None).await.is_err());
+    let msg = receive_imf(&t, raw, false).await?.unwrap();
+    let msg = Message::load_from_db(&t, *msg.msg_ids.last().unwrap()).await?;
+    assert!(msg.text.contains("Grüße from the salvaged part"));
+    assert!(msg
+        .error()
+        .unwrap()
+        .contains("could not be parsed completely"));
+    assert_eq!(msg.get_subject(), "Broken multipart");
+    assert_eq!(
+        Contact::get_by_id(&t, msg.from_id).await?.get_addr(),
+        "bob@example.net"
+    );
+
+    // Invalid top-level Content-Type and an 8-bit header.
+    let raw = "From: Bob <bob@example.net>\n\
+               To: alice@example.org\n\
+               Subject: Grüße\n\
+               Message-ID: <damaged2@example.net>\n\
+               Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+               Content-Type: text\n\
+               \n\
+               Plain body\n";
+    let msg = receive_imf(&t, raw.as_bytes(), false).await?.unwrap();
+    let msg = Message::load_from_db(&t, *msg.msg_ids.last().unwrap()).await?;
+    assert!(msg.text.contains("Plain body"));
+    assert_eq!(msg.get_subject(), "Grüße");
+    assert!(msg.error().is_some());
+
+    // Nothing salvageable in the body, a placeholder is shown.
+    let raw = b"From: Bob <bob@example.net>\n\
+                To: alice@example.org\n\
+                Message-ID: <damaged3@example.net>\n\
+                Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                Content-Type: foo\n\
+                \n";
+    let msg = receive_imf(&t, raw, false).await?.unwrap();
+    let msg = Message::load_from_db(&t, *msg.msg_ids.last().unwrap()).await?;
+    assert_eq!(
+        msg.text,
+        "[This message is damaged and could not be displayed completely.]"
+    );
+    Ok(())
+}
+
+/// Tests that a new group member receives the keys of all other members
+/// with the message adding them and can send encrypted messages right away.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_gossip_to_new_member() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let fiona = tcm.fiona().await;
+    tcm.send_recv_accept(&bob, &alice, "Hi Alice").await;
+    tcm.send_recv_accept(&fiona, &alice, "Hi Alice").await;
+
+    let alice_chat_id = alice
+        .create_group_with_members(ProtectionStatus::Unprotected, "Group", &[&bob])
+        .await;
+    let sent = alice.send_text(alice_chat_id, "Hello").await;
+    let bob_chat_id = bob.recv_msg(&sent).await.chat_id;
+    bob_chat_id.accept(&bob).await?;
+
+    let alice_fiona_id = alice.add_or_lookup_contact(&fiona).await.id;
+    add_contact_to_chat(&alice, alice_chat_id, alice_fiona_id).await?;
+    let sent = alice.pop_sent_msg().await;
+    assert!(sent.payload().contains("Autocrypt-Gossip"));
+    bob.recv_msg(&sent).await;
+    assert!(bob_chat_id.get_gossiped_timestamp(&bob).await? > 0);
+    let fiona_chat_id = fiona.recv_msg(&sent).await.chat_id;
+    fiona_chat_id.accept(&fiona).await?;
+    assert!(fiona_chat_id.get_gossiped_timestamp(&fiona).await? > 0);
+
+    let sent = fiona.send_text(fiona_chat_id, "Hi all").await;
+    let msg = bob.recv_msg(&sent).await;
+    assert_eq!(msg.chat_id, bob_chat_id);
+    assert!(msg.get_showpadlock());
+    let msg = alice.recv_msg(&sent).await;
+    assert_eq!(msg.chat_id, alice_chat_id);
+    assert!(msg.get_showpadlock());
+    Ok(())
+}
+
+/// Tests that the gossiped timestamp is reset
+/// if members are added by a message without Autocrypt-Gossip.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_reset_gossiped_timestamp_on_member_added() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let fiona = tcm.fiona().await;
+    tcm.send_recv_accept(&bob, &alice, "Hi Alice").await;
+
+    let alice_chat_id = alice
+        .create_group_with_members(ProtectionStatus::Unprotected, "Group", &[&bob])
+        .await;
+    let sent = alice.send_text(alice_chat_id, "Hello").await;
+    let bob_chat_id = bob.recv_msg(&sent).await.chat_id;
+    bob_chat_id
+        .set_gossiped_timestamp(&bob, crate::tools::time())
+        .await?;
+
+    // Alice does not have Fiona's key, so the message is not encrypted.
+    let alice_fiona_id = alice.add_or_lookup_contact(&fiona).await.id;
+    add_contact_to_chat(&alice, alice_chat_id, alice_fiona_id).await?;
+    let sent = alice.pop_sent_msg().await;
+    assert!(!sent.payload().contains("Autocrypt-Gossip"));
+    bob.recv_msg(&sent).await;
+    assert_eq!(get_chat_contacts(&bob, bob_chat_id).await?.len(), 3);
+    assert_eq!(bob_chat_id.get_gossiped_timestamp(&bob).await?, 0);
+    Ok(())
+}
+
+/// Tests that a message received concurrently, e.g. from the Inbox and the `DeltaChat` folder,
+/// is added only once.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_receive_same_msg_concurrently() -> Result<()> {
+    let t = TestContext::new_alice().await;
+    let raw = b"From: Bob <bob@example.net>\n\
+                To: alice@example.org\n\
+                Chat-Version: 1.0\n\
+                Message-ID: <concurrent@example.net>\n\
+                Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                \n\
+                Hello\n";
+    let (res1, res2) = tokio::join!(receive_imf(&t, raw, false), receive_imf(&t, raw, false));
+    let received = [res1?, res2?];
+    assert_eq!(received.iter().filter(|r| r.is_some()).count(), 1);
+    let cnt: usize = t
+        .sql
+        .count(
+            "SELECT COUNT(*) FROM msgs WHERE rfc724_mid='concurrent@example.net'",
+            (),
+        )
+        .await?;
+    assert_eq!(cnt, 1);
+    Ok(())
+}
+
+/// Tests that only the first part of a message is unique per Message-ID in the database.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_rfc724_mid_unique() -> Result<()> {
+    let t = TestContext::new_alice().await;
+    let raw = b"From: Bob <bob@example.net>\n\
+                To: alice@example.org\n\
+                Subject: Files\n\
+                Message-ID: <multipart@example.net>\n\
+                Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                Content-Type: multipart/mixed; boundary=\"b\"\n\
+                \n\
+                --b\n\
+                Content-Type: text/plain\n\
+                Content-Disposition: attachment; filename=\"a.txt\"\n\
+                \n\
+                a\n\
+                --b\n\
+                Content-Type: text/plain\n\
+                Content-Disposition: attachment; filename=\"b.txt\"\n\
+                \n\
+                b\n\
+                --b--\n";
+    let received = receive_imf(&t, raw, false).await?.unwrap();
+    assert_eq!(received.msg_ids.len(), 2);
+    let parts: Vec<usize> = t
+        .sql
+        .query_map(
+            "SELECT part FROM msgs WHERE rfc724_mid='multipart@example.net' ORDER BY id",
+            (),
+            |row| row.get(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    assert_eq!(parts, [0, 1]);
+
+    // A second message with the same Message-ID cannot be added.
+    assert!(t
+        .sql
+        .execute(
+            "INSERT INTO msgs (rfc724_mid, chat_id) VALUES (?,?)",
+            ("multipart@example.net", DC_CHAT_ID_TRASH),
+        )
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_collapse_auto_replies() -> Result<()> {
+    let t = TestContext::new_alice().await;
+    t.set_config_bool(Config::CollapseAutoReplies, true).await?;
+    let vacation_notice = |id: u32, header: &str| {
+        format!(
+            "From: Bob <bob@example.net>\n\
+             To: alice@example.org\n\
+             Subject: Out of office\n\
+             {header}\n\
+             Message-ID: <vacation{id}@example.net>\n\
+             Date: Sun, 22 Mar 2020 22:37:5{id} +0000\n\
+             \n\
+             I am on vacation.\n"
+        )
+    };
+
+    let received = receive_imf(
+        &t,
+        vacation_notice(1, "Auto-Submitted: auto-replied").as_bytes(),
+        false,
+    )
+    .await?
+    .unwrap();
+    let msg = t.get_last_msg().await;
+    assert!(msg.is_auto_reply());
+    assert_eq!(msg.chat_id, received.chat_id);
+
+    // The repeated notice is trashed.
+    let received = receive_imf(&t, vacation_notice(2, "X-Autoreply: yes").as_bytes(), false)
+        .await?
+        .unwrap();
+    assert_eq!(received.chat_id, DC_CHAT_ID_TRASH);
+
+    // A normal message from the sender resets collapsing.
+    let raw = b"From: Bob <bob@example.net>\n\
+                To: alice@example.org\n\
+                Subject: Back\n\
+                Message-ID: <back@example.net>\n\
+                Date: Sun, 22 Mar 2020 22:38:00 +0000\n\
+                \n\
+                I am back.\n";
+    receive_imf(&t, raw, false).await?;
+    assert!(!t.get_last_msg().await.is_auto_reply());
+    let received = receive_imf(
+        &t,
+        vacation_notice(3, "Precedence: auto_reply").as_bytes(),
+        false,
+    )
+    .await?
+    .unwrap();
+    assert_ne!(received.chat_id, DC_CHAT_ID_TRASH);
+    assert!(t.get_last_msg().await.is_auto_reply());
+    Ok(())
+}
+
+#[test]
+fn test_auto_accept_rules_match() {
+    let rules = "example.net  @Example.Org carol@example.com";
+    assert!(auto_accept_rules_match(rules, "bob@example.net"));
+    assert!(auto_accept_rules_match(rules, "Bob@Mail.Example.NET"));
+    assert!(auto_accept_rules_match(rules, "alice@example.org"));
+    assert!(auto_accept_rules_match(rules, "carol@example.com"));
+    assert!(!auto_accept_rules_match(rules, "dora@example.com"));
+    assert!(!auto_accept_rules_match(rules, "eve@badexample.net"));
+    assert!(!auto_accept_rules_match(rules, "example.net"));
+    assert!(!auto_accept_rules_match("", "bob@example.net"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_auto_accept_rules() -> Result<()> {
+    let t = TestContext::new_alice().await;
+    t.set_config(Config::AutoAcceptRules, Some("example.net"))
+        .await?;
+    let msg_from = |from: &str, id: u32, dkim: &str| {
+        let domain = from.split('@').last().unwrap();
+        format!(
+            "Authentication-Results: example.org; dkim={dkim} header.d={domain}\n\
+             From: {from}\n\
+             To: alice@example.org\n\
+             Subject: Hi\n\
+             Message-ID: <{id}@example.net>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             \n\
+             Hello\n"
+        )
+    };
+
+    receive_imf(&t, msg_from("bob@example.net", 1, "pass").as_bytes(), false).await?;
+    let chat = Chat::load_from_db(&t, t.get_last_msg().await.chat_id).await?;
+    assert!(!chat.is_contact_request());
+
+    receive_imf(&t, msg_from("eve@example.com", 2, "pass").as_bytes(), false).await?;
+    let chat = Chat::load_from_db(&t, t.get_last_msg().await.chat_id).await?;
+    assert!(chat.is_contact_request());
+
+    // The sender address may be spoofed if DKIM did not pass.
+    receive_imf(
+        &t,
+        msg_from("fiona@example.net", 4, "fail").as_bytes(),
+        false,
+    )
+    .await?;
+    let chat = Chat::load_from_db(&t, t.get_last_msg().await.chat_id).await?;
+    assert!(chat.is_contact_request());
+
+    let msg = b"From: dora@example.net\n\
+        To: alice@example.org\n\
+        Subject: Hi\n\
+        Message-ID: <5@example.net>\n\
+        Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+        \n\
+        Hello\n";
+    receive_imf(&t, msg, false).await?;
+    let chat = Chat::load_from_db(&t, t.get_last_msg().await.chat_id).await?;
+    assert!(chat.is_contact_request());
+
+    // A blocked contact stays blocked.
+    let carol_id = Contact::create(&t, "", "carol@example.net").await?;
+    Contact::block(&t, carol_id).await?;
+    let received = receive_imf(
+        &t,
+        msg_from("carol@example.net", 3, "pass").as_bytes(),
+        false,
+    )
+    .await?
+    .unwrap();
+    let chat = Chat::load_from_db(&t, received.chat_id).await?;
+    assert_eq!(chat.blocked, Blocked::Yes);
+    Ok(())
+}
+
+/// Tests that a signature made with the key from the Autocrypt header of the same message
+/// does not authenticate the sender for [`Config::AutoAcceptRules`].
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_auto_accept_rules_fresh_autocrypt_key() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+
+    // Bob learns Alice's key, so his reply is encrypted and signed.
+    let alice_chat = alice.create_chat(&bob).await;
+    bob.recv_msg(&alice.send_text(alice_chat.id, "Hi").await)
+        .await;
+    let bob_chat = bob.create_chat(&alice).await;
+    let sent = bob.send_text(bob_chat.id, "Hello").await;
+
+    // Another device of Alice has never seen Bob's key, for it the key is fresh,
+    // just like the key of an attacker forging Bob's address.
+    let alice2 = tcm.alice().await;
+    alice2
+        .set_config(Config::AutoAcceptRules, Some("example.net"))
+        .await?;
+    let msg = alice2.recv_msg(&sent).await;
+    assert!(msg.get_showpadlock());
+    let chat = Chat::load_from_db(&alice2, msg.chat_id).await?;
+    assert!(chat.is_contact_request());
+    Ok(())
+}
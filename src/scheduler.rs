@@ -1,22 +1,25 @@
 use std::iter::{self, once};
 use std::num::NonZeroUsize;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use anyhow::{bail, Context as _, Error, Result};
 use async_channel::{self as channel, Receiver, Sender};
 use futures::future::try_join_all;
-use futures_lite::FutureExt;
 use tokio::sync::{oneshot, RwLock, RwLockWriteGuard};
 use tokio::task;
 
 use self::connectivity::ConnectivityStore;
+use self::watchdog::{is_stalled, watch};
 use crate::config::Config;
 use crate::contact::{ContactId, RecentlySeenLoop};
 use crate::context::Context;
-use crate::download::download_msg;
+use crate::download::{download_msg, DownloadState};
 use crate::ephemeral::{self, delete_expired_imap_messages};
 use crate::events::EventType;
-use crate::imap::{FolderMeaning, Imap};
+use crate::imap::scan_folders::get_configured_watched_folders;
+use crate::imap::{get_folder_meaning_by_name, FolderMeaning, Imap, ImapActionError};
+use crate::job::{self, JobKind};
 use crate::location;
 use crate::log::LogExt;
 use crate::message::MsgId;
@@ -25,6 +28,20 @@
 use crate::tools::{duration_to_str, maybe_add_time_based_warnings, time};
 
 pub(crate) mod connectivity;
+mod watchdog;
+
+/// Interval of retrying to select an additional folder which does not exist
+/// or could not be selected because of an error.
+const EXTRA_FOLDER_RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum number of IMAP connections used for additional folders.
+///
+/// If more folders are watched, the last connection polls the remaining ones
+/// instead of watching a single folder with IDLE.
+const MAX_EXTRA_FOLDER_CONNECTIONS: usize = 3;
+
+/// Interval of polling additional folders which share a connection.
+const EXTRA_FOLDER_POLL_INTERVAL: Duration = Duration::from_secs(60);
 
 /// State of the IO scheduler, as stored on the [`Context`].
 ///
@@ -48,17 +65,44 @@ pub(crate) async fn is_running(&self) -> bool {
     }
 
     /// Starts the scheduler if it is not yet started.
+    ///
+    /// If [`Config::Offline`] is set, the scheduler is only started
+    /// when the offline mode is turned off.
     pub(crate) async fn start(&self, context: Context) {
         let mut inner = self.inner.write().await;
         match *inner {
-            InnerSchedulerState::Started(_) => (),
-            InnerSchedulerState::Stopped => Self::do_start(inner, context).await,
+            InnerSchedulerState::Started(_) | InnerSchedulerState::Offline => (),
+            InnerSchedulerState::Stopped => {
+                if is_offline(&context).await {
+                    info!(context, "Offline mode is enabled, not starting IO.");
+                    *inner = InnerSchedulerState::Offline;
+                } else {
+                    Self::do_start(inner, context).await
+                }
+            }
             InnerSchedulerState::Paused {
                 ref mut started, ..
             } => *started = true,
         }
     }
 
+    /// Suspends or resumes the scheduler when [`Config::Offline`] is changed.
+    ///
+    /// Connections are closed cleanly when going offline.
+    /// Going online starts the scheduler if it was started before,
+    /// so new messages are fetched and queued messages are sent immediately.
+    pub(crate) async fn set_offline(&self, context: &Context, offline: bool) {
+        let inner = self.inner.write().await;
+        match (&*inner, offline) {
+            (InnerSchedulerState::Started(_), true) => {
+                Self::do_stop(inner, context, InnerSchedulerState::Offline).await
+            }
+            (InnerSchedulerState::Offline, false) => Self::do_start(inner, context.clone()).await,
+            // Paused scheduler checks the offline mode when it is resumed.
+            _ => (),
+        }
+    }
+
     /// Starts the scheduler if it is not yet started.
     async fn do_start(mut inner: RwLockWriteGuard<'_, InnerSchedulerState>, context: Context) {
         info!(context, "starting IO");
@@ -82,6 +126,7 @@ pub(crate) async fn stop(&self, context: &Context) {
                 Self::do_stop(inner, context, InnerSchedulerState::Stopped).await
             }
             InnerSchedulerState::Stopped => (),
+            InnerSchedulerState::Offline => *inner = InnerSchedulerState::Stopped,
             InnerSchedulerState::Paused {
                 ref mut started, ..
             } => *started = false,
@@ -116,7 +161,9 @@ async fn do_stop(
         let prev_state = std::mem::replace(&mut *inner, new_state);
         match prev_state {
             InnerSchedulerState::Started(scheduler) => scheduler.stop(context).await,
-            InnerSchedulerState::Stopped | InnerSchedulerState::Paused { .. } => (),
+            InnerSchedulerState::Stopped
+            | InnerSchedulerState::Offline
+            | InnerSchedulerState::Paused { .. } => (),
         }
     }
 
@@ -145,6 +192,12 @@ pub(crate) async fn pause<'a>(&'_ self, context: Context) -> Result<IoPausedGuar
                         pause_guards_count: NonZeroUsize::new(1).unwrap(),
                     };
                 }
+                InnerSchedulerState::Offline => {
+                    *inner = InnerSchedulerState::Paused {
+                        started: true,
+                        pause_guards_count: NonZeroUsize::new(1).unwrap(),
+                    };
+                }
                 InnerSchedulerState::Paused {
                     ref mut pause_guards_count,
                     ..
@@ -164,7 +217,7 @@ pub(crate) async fn pause<'a>(&'_ self, context: Context) -> Result<IoPausedGuar
                 InnerSchedulerState::Started(_) => {
                     warn!(&context, "IoPausedGuard resume: started instead of paused");
                 }
-                InnerSchedulerState::Stopped => {
+                InnerSchedulerState::Stopped | InnerSchedulerState::Offline => {
                     warn!(&context, "IoPausedGuard resume: stopped instead of paused");
                 }
                 InnerSchedulerState::Paused {
@@ -173,6 +226,9 @@ pub(crate) async fn pause<'a>(&'_ self, context: Context) -> Result<IoPausedGuar
                 } => {
                     if *pause_guards_count == NonZeroUsize::new(1).unwrap() {
                         match *started {
+                            true if is_offline(&context).await => {
+                                *inner = InnerSchedulerState::Offline
+                            }
                             true => SchedulerState::do_start(inner, context.clone()).await,
                             false => *inner = InnerSchedulerState::Stopped,
                         }
@@ -274,12 +330,24 @@ enum InnerSchedulerState {
     Started(Scheduler),
     #[default]
     Stopped,
+
+    /// Started, but suspended because [`Config::Offline`] is set.
+    Offline,
     Paused {
         started: bool,
         pause_guards_count: NonZeroUsize,
     },
 }
 
+/// Returns true if [`Config::Offline`] is set.
+async fn is_offline(context: &Context) -> bool {
+    context
+        .get_config_bool(Config::Offline)
+        .await
+        .log_err(context)
+        .unwrap_or_default()
+}
+
 /// Guard to make sure the IO Scheduler is resumed.
 ///
 /// Returned by [`SchedulerState::pause`].  To resume the IO scheduler simply drop this
@@ -301,6 +369,13 @@ fn drop(&mut self) {
 #[derive(Debug)]
 struct SchedBox {
     meaning: FolderMeaning,
+
+    /// Names of the watched folders if they are additional folders
+    /// set with [`Context::set_extra_folder_watched`].
+    ///
+    /// If there are several, the IMAP loop polls them.
+    extra_folders: Vec<String>,
+
     conn_state: ImapConnectionState,
 
     /// IMAP loop task handle.
@@ -311,7 +386,7 @@ struct SchedBox {
 #[derive(Debug)]
 pub(crate) struct Scheduler {
     inbox: SchedBox,
-    /// Optional boxes -- mvbox, sentbox.
+    /// Optional boxes -- mvbox, sentbox and additional folders.
     oboxes: Vec<SchedBox>,
     smtp: SmtpConnectionState,
     smtp_handle: task::JoinHandle<()>,
@@ -327,11 +402,12 @@ async fn download_msgs(context: &Context, imap: &mut Imap) -> Result<()> {
     let msg_ids = context
         .sql
         .query_map(
-            "SELECT msg_id FROM download",
-            (),
+            "SELECT msg_id, retries FROM download WHERE next_try<=?",
+            (time(),),
             |row| {
                 let msg_id: MsgId = row.get(0)?;
-                Ok(msg_id)
+                let retries: i64 = row.get(1)?;
+                Ok((msg_id, retries))
             },
             |rowids| {
                 rowids
@@ -341,9 +417,31 @@ async fn download_msgs(context: &Context, imap: &mut Imap) -> Result<()> {
         )
         .await?;
 
-    for msg_id in msg_ids {
+    for (msg_id, retries) in msg_ids {
         if let Err(err) = download_msg(context, msg_id, imap).await {
             warn!(context, "Failed to download message {msg_id}: {:#}.", err);
+            let retries = retries + 1;
+            if err
+                .downcast_ref::<ImapActionError>()
+                .map_or(false, |err| err.kind.is_temporary())
+            {
+                if retries <= JobKind::Download.max_retries() {
+                    // Retry on an iteration of the inbox loop after the delay.
+                    let next_try = time().saturating_add(JobKind::Download.retry_delay(retries));
+                    context
+                        .sql
+                        .execute(
+                            "UPDATE download SET retries=?, next_try=?, last_error=? WHERE msg_id=?",
+                            (retries, next_try, format!("{err:#}"), msg_id),
+                        )
+                        .await?;
+                    continue;
+                }
+                msg_id
+                    .update_download_state(context, DownloadState::Failure)
+                    .await?;
+            }
+            context.emit_job_failed(JobKind::Download, msg_id, &format!("{err:#}"));
         }
         context
             .sql
@@ -359,102 +457,126 @@ async fn inbox_loop(
     started: oneshot::Sender<()>,
     inbox_handlers: ImapConnectionHandlers,
 ) {
-    use futures::future::FutureExt;
-
     info!(ctx, "starting inbox loop");
     let ImapConnectionHandlers {
         mut connection,
         stop_receiver,
     } = inbox_handlers;
 
-    let ctx1 = ctx.clone();
-    let fut = async move {
-        let ctx = ctx1;
-        if let Err(()) = started.send(()) {
-            warn!(ctx, "inbox loop, missing started receiver");
-            return;
-        };
+    if let Err(()) = started.send(()) {
+        warn!(ctx, "inbox loop, missing started receiver");
+        return;
+    };
 
-        loop {
-            {
-                // Update quota no more than once a minute.
-                let quota_needs_update = {
-                    let quota = ctx.quota.read().await;
-                    quota
-                        .as_ref()
-                        .filter(|quota| quota.modified + 60 > time())
-                        .is_none()
-                };
-
-                if quota_needs_update {
-                    if let Err(err) = ctx.update_recent_quota(&mut connection).await {
-                        warn!(ctx, "Failed to update quota: {:#}.", err);
+    let connectivity = connection.connectivity.clone();
+    while !stop_requested(&stop_receiver) {
+        {
+            // Update quota no more than once a minute.
+            let quota_needs_update = {
+                let quota = ctx.quota.read().await;
+                quota
+                    .as_ref()
+                    .filter(|quota| quota.modified + 60 > time())
+                    .is_none()
+            };
+
+            if quota_needs_update {
+                if let Err(err) = watch(
+                    &ctx,
+                    &connectivity,
+                    "update_recent_quota",
+                    ctx.update_recent_quota(&mut connection),
+                )
+                .await
+                {
+                    warn!(ctx, "Failed to update quota: {:#}.", err);
+                    if is_stalled(&err) {
+                        connection.trigger_reconnect(&ctx);
                     }
                 }
             }
+        }
 
-            let resync_requested = ctx.resync_request.swap(false, Ordering::Relaxed);
-            if resync_requested {
-                if let Err(err) = connection.resync_folders(&ctx).await {
-                    warn!(ctx, "Failed to resync folders: {:#}.", err);
-                    ctx.resync_request.store(true, Ordering::Relaxed);
+        let resync_requested = ctx.resync_request.swap(false, Ordering::Relaxed);
+        if resync_requested {
+            if let Err(err) = watch(
+                &ctx,
+                &connectivity,
+                "resync_folders",
+                connection.resync_folders(&ctx),
+            )
+            .await
+            {
+                warn!(ctx, "Failed to resync folders: {:#}.", err);
+                ctx.resync_request.store(true, Ordering::Relaxed);
+                if is_stalled(&err) {
+                    connection.trigger_reconnect(&ctx);
                 }
             }
+        }
 
-            maybe_add_time_based_warnings(&ctx).await;
+        maybe_add_time_based_warnings(&ctx).await;
 
-            match ctx.get_config_i64(Config::LastHousekeeping).await {
-                Ok(last_housekeeping_time) => {
-                    let next_housekeeping_time =
-                        last_housekeeping_time.saturating_add(60 * 60 * 24);
-                    if next_housekeeping_time <= time() {
-                        sql::housekeeping(&ctx).await.log_err(&ctx).ok();
-                    }
+        match ctx.get_config_i64(Config::LastHousekeeping).await {
+            Ok(last_housekeeping_time) => {
+                let next_housekeeping_time = last_housekeeping_time.saturating_add(60 * 60 * 24);
+                if next_housekeeping_time <= time() {
+                    sql::housekeeping(&ctx).await.log_err(&ctx).ok();
                 }
-                Err(err) => {
-                    warn!(ctx, "Failed to get last housekeeping time: {}", err);
-                }
-            };
+            }
+            Err(err) => {
+                warn!(ctx, "Failed to get last housekeeping time: {}", err);
+            }
+        };
 
-            match ctx.get_config_bool(Config::FetchedExistingMsgs).await {
-                Ok(fetched_existing_msgs) => {
-                    if !fetched_existing_msgs {
-                        // Consider it done even if we fail.
-                        //
-                        // This operation is not critical enough to retry,
-                        // especially if the error is persistent.
-                        if let Err(err) =
-                            ctx.set_config_bool(Config::FetchedExistingMsgs, true).await
-                        {
-                            warn!(ctx, "Can't set Config::FetchedExistingMsgs: {:#}", err);
-                        }
+        match ctx.get_config_bool(Config::FetchedExistingMsgs).await {
+            Ok(fetched_existing_msgs) => {
+                if !fetched_existing_msgs {
+                    // Consider it done even if we fail.
+                    //
+                    // This operation is not critical enough to retry,
+                    // especially if the error is persistent.
+                    if let Err(err) = ctx.set_config_bool(Config::FetchedExistingMsgs, true).await {
+                        warn!(ctx, "Can't set Config::FetchedExistingMsgs: {:#}", err);
+                    }
 
-                        if let Err(err) = connection.fetch_existing_msgs(&ctx).await {
-                            warn!(ctx, "Failed to fetch existing messages: {:#}", err);
-                            connection.trigger_reconnect(&ctx);
-                        }
+                    if let Err(err) = watch(
+                        &ctx,
+                        &connectivity,
+                        "fetch_existing_msgs",
+                        connection.fetch_existing_msgs(&ctx),
+                    )
+                    .await
+                    {
+                        warn!(ctx, "Failed to fetch existing messages: {:#}", err);
+                        connection.trigger_reconnect(&ctx);
                     }
                 }
-                Err(err) => {
-                    warn!(ctx, "Can't get Config::FetchedExistingMsgs: {:#}", err);
-                }
             }
-
-            if let Err(err) = download_msgs(&ctx, &mut connection).await {
-                warn!(ctx, "Failed to download messages: {:#}", err);
+            Err(err) => {
+                warn!(ctx, "Can't get Config::FetchedExistingMsgs: {:#}", err);
             }
+        }
 
-            fetch_idle(&ctx, &mut connection, FolderMeaning::Inbox).await;
+        if let Err(err) = watch(
+            &ctx,
+            &connectivity,
+            "download_msgs",
+            download_msgs(&ctx, &mut connection),
+        )
+        .await
+        {
+            warn!(ctx, "Failed to download messages: {:#}", err);
+            if is_stalled(&err) {
+                connection.trigger_reconnect(&ctx);
+            }
         }
-    };
 
-    stop_receiver
-        .recv()
-        .map(|_| {
-            info!(ctx, "shutting down inbox loop");
-        })
-        .race(fut)
-        .await;
+        fetch_idle(&ctx, &mut connection, FolderMeaning::Inbox).await;
+    }
+
+    info!(ctx, "shutting down inbox loop");
+    connection.logout(&ctx).await;
 }
 
 /// Implement a single iteration of IMAP loop.
@@ -501,8 +623,8 @@ async fn fetch_idle(
     };
 
     // connect and fake idle if unable to connect
-    if let Err(err) = connection
-        .prepare(ctx)
+    let connectivity = connection.connectivity.clone();
+    if let Err(err) = watch(ctx, &connectivity, "prepare", connection.prepare(ctx))
         .await
         .context("prepare IMAP connection")
     {
@@ -515,28 +637,59 @@ async fn fetch_idle(
 
     if folder_config == Config::ConfiguredInboxFolder {
         if let Some(session) = connection.session.as_mut() {
-            session
-                .store_seen_flags_on_imap(ctx)
-                .await
-                .context("store_seen_flags_on_imap")
-                .log_err(ctx)
-                .ok();
+            if let Err(err) = watch(
+                ctx,
+                &connectivity,
+                "store_seen_flags_on_imap",
+                session.store_seen_flags_on_imap(ctx),
+            )
+            .await
+            .context("store_seen_flags_on_imap")
+            {
+                warn!(ctx, "{:#}", err);
+                if is_stalled(&err) {
+                    connection.trigger_reconnect(ctx);
+                    return InterruptInfo::new(false);
+                }
+            }
         } else {
             warn!(ctx, "No session even though we just prepared it");
         }
     }
 
     // Fetch the watched folder.
-    if let Err(err) = connection
-        .fetch_move_delete(ctx, &watch_folder, folder_meaning)
-        .await
-        .context("fetch_move_delete")
+    if let Err(err) = watch(
+        ctx,
+        &connectivity,
+        "fetch_move_delete",
+        connection.fetch_move_delete(ctx, &watch_folder, folder_meaning),
+    )
+    .await
+    .context("fetch_move_delete")
     {
         connection.trigger_reconnect(ctx);
         warn!(ctx, "{:#}", err);
         return InterruptInfo::new(false);
     }
 
+    if folder_config == Config::ConfiguredInboxFolder {
+        if let Err(err) = watch(
+            ctx,
+            &connectivity,
+            "sync_drafts",
+            connection.sync_drafts(ctx),
+        )
+        .await
+        .context("sync_drafts")
+        {
+            warn!(ctx, "{:#}", err);
+            if is_stalled(&err) {
+                connection.trigger_reconnect(ctx);
+                return InterruptInfo::new(false);
+            }
+        }
+    }
+
     // Mark expired messages for deletion. Marked messages will be deleted from the server
     // on the next iteration of `fetch_move_delete`. `delete_expired_imap_messages` is not
     // called right before `fetch_move_delete` because it is not well optimized and would
@@ -553,11 +706,23 @@ async fn fetch_idle(
     // be able to scan all folders before time is up if there are many of them.
     if folder_config == Config::ConfiguredInboxFolder {
         // Only scan on the Inbox thread in order to prevent parallel scans, which might lead to duplicate messages
-        match connection.scan_folders(ctx).await.context("scan_folders") {
+        match watch(
+            ctx,
+            &connectivity,
+            "scan_folders",
+            connection.scan_folders(ctx),
+        )
+        .await
+        .context("scan_folders")
+        {
             Err(err) => {
                 // Don't reconnect, if there is a problem with the connection we will realize this when IDLEing
                 // but maybe just one folder can't be selected or something
                 warn!(ctx, "{:#}", err);
+                if is_stalled(&err) {
+                    connection.trigger_reconnect(ctx);
+                    return InterruptInfo::new(false);
+                }
             }
             Ok(true) => {
                 // Fetch the watched folder again in case scanning other folder moved messages
@@ -566,10 +731,14 @@ async fn fetch_idle(
                 // In most cases this will select the watched folder and return because there are
                 // no new messages. We want to select the watched folder anyway before going IDLE
                 // there, so this does not take additional protocol round-trip.
-                if let Err(err) = connection
-                    .fetch_move_delete(ctx, &watch_folder, folder_meaning)
-                    .await
-                    .context("fetch_move_delete after scan_folders")
+                if let Err(err) = watch(
+                    ctx,
+                    &connectivity,
+                    "fetch_move_delete",
+                    connection.fetch_move_delete(ctx, &watch_folder, folder_meaning),
+                )
+                .await
+                .context("fetch_move_delete after scan_folders")
                 {
                     connection.trigger_reconnect(ctx);
                     warn!(ctx, "{:#}", err);
@@ -581,12 +750,21 @@ async fn fetch_idle(
     }
 
     // Synchronize Seen flags.
-    connection
-        .sync_seen_flags(ctx, &watch_folder)
-        .await
-        .context("sync_seen_flags")
-        .log_err(ctx)
-        .ok();
+    if let Err(err) = watch(
+        ctx,
+        &connectivity,
+        "sync_seen_flags",
+        connection.sync_seen_flags(ctx, &watch_folder),
+    )
+    .await
+    .context("sync_seen_flags")
+    {
+        warn!(ctx, "{:#}", err);
+        if is_stalled(&err) {
+            connection.trigger_reconnect(ctx);
+            return InterruptInfo::new(false);
+        }
+    }
 
     connection.connectivity.set_connected(ctx).await;
 
@@ -649,35 +827,275 @@ async fn simple_imap_loop(
     inbox_handlers: ImapConnectionHandlers,
     folder_meaning: FolderMeaning,
 ) {
-    use futures::future::FutureExt;
-
     info!(ctx, "starting simple loop for {}", folder_meaning);
     let ImapConnectionHandlers {
         mut connection,
         stop_receiver,
     } = inbox_handlers;
 
-    let ctx1 = ctx.clone();
+    if let Err(()) = started.send(()) {
+        warn!(&ctx, "simple imap loop, missing started receiver");
+        return;
+    }
+
+    while !stop_requested(&stop_receiver) {
+        fetch_idle(&ctx, &mut connection, folder_meaning).await;
+    }
+
+    info!(ctx, "shutting down simple loop");
+    connection.logout(&ctx).await;
+}
+
+/// IMAP loop watching an additional folder set with [`Context::set_extra_folder_watched`].
+async fn extra_folder_loop(
+    ctx: Context,
+    started: oneshot::Sender<()>,
+    handlers: ImapConnectionHandlers,
+    folder: String,
+    folder_meaning: FolderMeaning,
+) {
+    info!(ctx, "starting loop for additional folder {folder:?}");
+    let ImapConnectionHandlers {
+        mut connection,
+        stop_receiver,
+    } = handlers;
+
+    if let Err(()) = started.send(()) {
+        warn!(&ctx, "additional folder loop, missing started receiver");
+        return;
+    }
+
+    while !stop_requested(&stop_receiver) {
+        fetch_idle_extra_folder(&ctx, &mut connection, &folder, folder_meaning).await;
+    }
+
+    info!(ctx, "shutting down loop for additional folder {folder:?}");
+    connection.logout(&ctx).await;
+}
 
-    let fut = async move {
-        let ctx = ctx1;
-        if let Err(()) = started.send(()) {
-            warn!(&ctx, "simple imap loop, missing started receiver");
-            return;
+/// Implements a single iteration of the IMAP loop watching an additional folder.
+///
+/// Unlike [`fetch_idle`], the folder is not created if it does not exist on the server.
+/// Instead, selecting it is retried after [`EXTRA_FOLDER_RETRY_INTERVAL`].
+async fn fetch_idle_extra_folder(
+    ctx: &Context,
+    connection: &mut Imap,
+    folder: &str,
+    folder_meaning: FolderMeaning,
+) -> InterruptInfo {
+    let connectivity = connection.connectivity.clone();
+    if let Err(err) = watch(ctx, &connectivity, "prepare", connection.prepare(ctx))
+        .await
+        .context("prepare IMAP connection")
+    {
+        warn!(ctx, "{:#}", err);
+        connection.trigger_reconnect(ctx);
+        return wait_for_interrupt(connection, EXTRA_FOLDER_RETRY_INTERVAL).await;
+    }
+
+    match watch(
+        ctx,
+        &connectivity,
+        "select_extra_folder",
+        connection.select_extra_folder(ctx, folder),
+    )
+    .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            connection.connectivity.set_connected(ctx).await;
+            return wait_for_interrupt(connection, EXTRA_FOLDER_RETRY_INTERVAL).await;
         }
+        Err(err) => {
+            warn!(ctx, "{:#}", err);
+            connection.trigger_reconnect(ctx);
+            return wait_for_interrupt(connection, EXTRA_FOLDER_RETRY_INTERVAL).await;
+        }
+    }
 
-        loop {
-            fetch_idle(&ctx, &mut connection, folder_meaning).await;
+    if let Err(err) = watch(
+        ctx,
+        &connectivity,
+        "fetch_move_delete",
+        connection.fetch_move_delete(ctx, folder, folder_meaning),
+    )
+    .await
+    .context("fetch_move_delete")
+    {
+        connection.trigger_reconnect(ctx);
+        warn!(ctx, "{:#}", err);
+        return InterruptInfo::new(false);
+    }
+
+    if let Err(err) = watch(
+        ctx,
+        &connectivity,
+        "sync_seen_flags",
+        connection.sync_seen_flags(ctx, folder),
+    )
+    .await
+    .context("sync_seen_flags")
+    {
+        warn!(ctx, "{:#}", err);
+        if is_stalled(&err) {
+            connection.trigger_reconnect(ctx);
+            return InterruptInfo::new(false);
         }
+    }
+
+    connection.connectivity.set_connected(ctx).await;
+
+    let Some(session) = connection.session.take() else {
+        warn!(ctx, "No IMAP session, going to fake idle.");
+        return connection
+            .fake_idle(ctx, Some(folder.to_string()), folder_meaning)
+            .await;
     };
+    if !session.can_idle()
+        || ctx
+            .get_config_bool(Config::DisableIdle)
+            .await
+            .context("Failed to get disable_idle config")
+            .log_err(ctx)
+            .unwrap_or_default()
+    {
+        connection.session = Some(session);
+        return connection
+            .fake_idle(ctx, Some(folder.to_string()), folder_meaning)
+            .await;
+    }
+    match session
+        .idle(
+            ctx,
+            connection.idle_interrupt_receiver.clone(),
+            Some(folder.to_string()),
+        )
+        .await
+        .context("idle")
+    {
+        Ok((session, info)) => {
+            connection.session = Some(session);
+            info
+        }
+        Err(err) => {
+            connection.trigger_reconnect(ctx);
+            warn!(ctx, "{:#}", err);
+            InterruptInfo::new(false)
+        }
+    }
+}
+
+/// IMAP loop polling additional folders which do not get a connection of their own,
+/// see [`MAX_EXTRA_FOLDER_CONNECTIONS`].
+async fn polled_extra_folders_loop(
+    ctx: Context,
+    started: oneshot::Sender<()>,
+    handlers: ImapConnectionHandlers,
+    folders: Vec<String>,
+) {
+    info!(ctx, "starting loop polling additional folders {folders:?}");
+    let ImapConnectionHandlers {
+        mut connection,
+        stop_receiver,
+    } = handlers;
+
+    if let Err(()) = started.send(()) {
+        warn!(
+            &ctx,
+            "polling additional folders loop, missing started receiver"
+        );
+        return;
+    }
+
+    while !stop_requested(&stop_receiver) {
+        poll_extra_folders(&ctx, &mut connection, &folders).await;
+    }
+
+    info!(ctx, "shutting down loop polling additional folders");
+    connection.logout(&ctx).await;
+}
+
+/// Implements a single iteration of the IMAP loop polling additional folders.
+///
+/// Fetches messages from each folder in turn and then waits for
+/// [`EXTRA_FOLDER_POLL_INTERVAL`] or an interrupt.
+/// Folders which do not exist on the server are skipped.
+async fn poll_extra_folders(
+    ctx: &Context,
+    connection: &mut Imap,
+    folders: &[String],
+) -> InterruptInfo {
+    let connectivity = connection.connectivity.clone();
+    if let Err(err) = watch(ctx, &connectivity, "prepare", connection.prepare(ctx))
+        .await
+        .context("prepare IMAP connection")
+    {
+        warn!(ctx, "{:#}", err);
+        connection.trigger_reconnect(ctx);
+        return wait_for_interrupt(connection, EXTRA_FOLDER_POLL_INTERVAL).await;
+    }
+
+    for folder in folders {
+        match watch(
+            ctx,
+            &connectivity,
+            "select_extra_folder",
+            connection.select_extra_folder(ctx, folder),
+        )
+        .await
+        {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                warn!(ctx, "{:#}", err);
+                connection.trigger_reconnect(ctx);
+                return wait_for_interrupt(connection, EXTRA_FOLDER_POLL_INTERVAL).await;
+            }
+        }
+
+        let folder_meaning = get_folder_meaning_by_name(folder);
+        if let Err(err) = watch(
+            ctx,
+            &connectivity,
+            "fetch_move_delete",
+            connection.fetch_move_delete(ctx, folder, folder_meaning),
+        )
+        .await
+        .context("fetch_move_delete")
+        {
+            connection.trigger_reconnect(ctx);
+            warn!(ctx, "{:#}", err);
+            return InterruptInfo::new(false);
+        }
 
-    stop_receiver
-        .recv()
-        .map(|_| {
-            info!(ctx, "shutting down simple loop");
-        })
-        .race(fut)
-        .await;
+        if let Err(err) = watch(
+            ctx,
+            &connectivity,
+            "sync_seen_flags",
+            connection.sync_seen_flags(ctx, folder),
+        )
+        .await
+        .context("sync_seen_flags")
+        {
+            warn!(ctx, "{:#}", err);
+            if is_stalled(&err) {
+                connection.trigger_reconnect(ctx);
+                return InterruptInfo::new(false);
+            }
+        }
+    }
+
+    connection.connectivity.set_connected(ctx).await;
+    wait_for_interrupt(connection, EXTRA_FOLDER_POLL_INTERVAL).await
+}
+
+/// Waits for an interrupt of the IMAP loop, but at most for `timeout`.
+async fn wait_for_interrupt(connection: &Imap, timeout: Duration) -> InterruptInfo {
+    tokio::time::timeout(timeout, connection.idle_interrupt_receiver.recv())
+        .await
+        .ok()
+        .and_then(|info| info.ok())
+        .unwrap_or_default()
 }
 
 async fn smtp_loop(
@@ -685,8 +1103,6 @@ async fn smtp_loop(
     started: oneshot::Sender<()>,
     smtp_handlers: SmtpConnectionHandlers,
 ) {
-    use futures::future::FutureExt;
-
     info!(ctx, "starting smtp loop");
     let SmtpConnectionHandlers {
         mut connection,
@@ -694,75 +1110,101 @@ async fn smtp_loop(
         idle_interrupt_receiver,
     } = smtp_handlers;
 
-    let ctx1 = ctx.clone();
-    let fut = async move {
-        let ctx = ctx1;
-        if let Err(()) = started.send(()) {
-            warn!(&ctx, "smtp loop, missing started receiver");
-            return;
-        }
-
-        let mut timeout = None;
-        loop {
-            if let Err(err) = send_smtp_messages(&ctx, &mut connection).await {
-                warn!(ctx, "send_smtp_messages failed: {:#}", err);
-                timeout = Some(timeout.map_or(30, |timeout: u64| timeout.saturating_mul(3)))
-            } else {
-                let duration_until_can_send = ctx.ratelimit.read().await.until_can_send();
-                if !duration_until_can_send.is_zero() {
-                    info!(
-                        ctx,
-                        "smtp got rate limited, waiting for {} until can send again",
-                        duration_to_str(duration_until_can_send)
-                    );
-                    tokio::time::timeout(duration_until_can_send, async {
-                        idle_interrupt_receiver.recv().await.unwrap_or_default()
-                    })
-                    .await
-                    .unwrap_or_default();
-                    continue;
-                }
-                timeout = None;
-            }
+    if let Err(()) = started.send(()) {
+        warn!(&ctx, "smtp loop, missing started receiver");
+        return;
+    }
 
-            // Fake Idle
-            info!(ctx, "smtp fake idle - started");
-            match &connection.last_send_error {
-                None => connection.connectivity.set_connected(&ctx).await,
-                Some(err) => connection.connectivity.set_err(&ctx, err).await,
+    let connectivity = connection.connectivity.clone();
+    let mut timeout = None;
+    while !stop_requested(&stop_receiver) {
+        if let Err(err) = watch(
+            &ctx,
+            &connectivity,
+            "send_smtp_messages",
+            send_smtp_messages(&ctx, &mut connection),
+        )
+        .await
+        {
+            warn!(ctx, "send_smtp_messages failed: {:#}", err);
+            if is_stalled(&err) {
+                connection.disconnect();
             }
-
-            // If send_smtp_messages() failed, we set a timeout for the fake-idle so that
-            // sending is retried (at the latest) after the timeout. If sending fails
-            // again, we increase the timeout exponentially, in order not to do lots of
-            // unnecessary retries.
-            if let Some(timeout) = timeout {
+            timeout = Some(timeout.map_or(30, |timeout: u64| timeout.saturating_mul(3)))
+        } else {
+            let duration_until_can_send = ctx.ratelimit.read().await.until_can_send();
+            if !duration_until_can_send.is_zero() {
                 info!(
                     ctx,
-                    "smtp has messages to retry, planning to retry {} seconds later", timeout
+                    "smtp got rate limited, waiting for {} until can send again",
+                    duration_to_str(duration_until_can_send)
                 );
-                let duration = std::time::Duration::from_secs(timeout);
-                tokio::time::timeout(duration, async {
+                tokio::time::timeout(duration_until_can_send, async {
                     idle_interrupt_receiver.recv().await.unwrap_or_default()
                 })
                 .await
                 .unwrap_or_default();
-            } else {
-                info!(ctx, "smtp has no messages to retry, waiting for interrupt");
-                idle_interrupt_receiver.recv().await.unwrap_or_default();
-            };
+                continue;
+            }
+            timeout = None;
+        }
 
-            info!(ctx, "smtp fake idle - interrupted")
+        // Fake Idle
+        debug!(ctx, "smtp fake idle - started");
+        match &connection.last_send_error {
+            None => connection.connectivity.set_connected(&ctx).await,
+            Some(err) => connection.connectivity.set_err(&ctx, err).await,
         }
-    };
 
-    stop_receiver
-        .recv()
-        .map(|_| {
-            info!(ctx, "shutting down smtp loop");
-        })
-        .race(fut)
-        .await;
+        // Jobs postponed by their own backoff must be woken up when they are due.
+        let postponed_timeout = match job::next_postponed_job(&ctx).await {
+            Ok(next_try) => next_try.map(|next_try| {
+                u64::try_from(next_try.saturating_sub(time()))
+                    .unwrap_or_default()
+                    .max(1)
+            }),
+            Err(err) => {
+                warn!(ctx, "Failed to get next postponed job: {:#}", err);
+                None
+            }
+        };
+
+        // If send_smtp_messages() failed, we set a timeout for the fake-idle so that
+        // sending is retried (at the latest) after the timeout. If sending fails
+        // again, we increase the timeout exponentially, in order not to do lots of
+        // unnecessary retries.
+        if let Some(timeout) = timeout.or(postponed_timeout) {
+            info!(
+                ctx,
+                "smtp has messages to retry, planning to retry {} seconds later", timeout
+            );
+            let duration = std::time::Duration::from_secs(timeout);
+            tokio::time::timeout(duration, async {
+                idle_interrupt_receiver.recv().await.unwrap_or_default()
+            })
+            .await
+            .unwrap_or_default();
+        } else {
+            info!(ctx, "smtp has no messages to retry, waiting for interrupt");
+            idle_interrupt_receiver.recv().await.unwrap_or_default();
+        };
+
+        debug!(ctx, "smtp fake idle - interrupted")
+    }
+
+    info!(ctx, "shutting down smtp loop");
+    connection.disconnect();
+}
+
+/// Returns true if the loop owning `stop_receiver` has been asked to shut down.
+///
+/// Loops check this between operations instead of being cancelled at an arbitrary await point,
+/// so that in-flight IMAP and SMTP commands are completed before the connection is closed.
+fn stop_requested(stop_receiver: &Receiver<()>) -> bool {
+    match stop_receiver.try_recv() {
+        Ok(()) | Err(channel::TryRecvError::Closed) => true,
+        Err(channel::TryRecvError::Empty) => false,
+    }
 }
 
 impl Scheduler {
@@ -785,6 +1227,7 @@ pub async fn start(ctx: Context) -> Result<Self> {
         };
         let inbox = SchedBox {
             meaning: FolderMeaning::Inbox,
+            extra_folders: Vec::new(),
             conn_state,
             handle,
         };
@@ -804,6 +1247,7 @@ pub async fn start(ctx: Context) -> Result<Self> {
                 let handle = task::spawn(simple_imap_loop(ctx, start_send, handlers, meaning));
                 oboxes.push(SchedBox {
                     meaning,
+                    extra_folders: Vec::new(),
                     conn_state,
                     handle,
                 });
@@ -811,6 +1255,55 @@ pub async fn start(ctx: Context) -> Result<Self> {
             }
         }
 
+        let configured_folders = get_configured_watched_folders(&ctx).await?;
+        let mut extra_folders: Vec<String> = ctx
+            .get_extra_folders()
+            .await?
+            .into_iter()
+            .filter(|folder| !configured_folders.contains(folder))
+            .collect();
+        let polled_folders = if extra_folders.len() > MAX_EXTRA_FOLDER_CONNECTIONS {
+            extra_folders.split_off(MAX_EXTRA_FOLDER_CONNECTIONS - 1)
+        } else {
+            Vec::new()
+        };
+        for folder in extra_folders {
+            let meaning = get_folder_meaning_by_name(&folder);
+            let (conn_state, handlers) = ImapConnectionState::new(&ctx).await?;
+            let (start_send, start_recv) = oneshot::channel();
+            let handle = task::spawn(extra_folder_loop(
+                ctx.clone(),
+                start_send,
+                handlers,
+                folder.clone(),
+                meaning,
+            ));
+            oboxes.push(SchedBox {
+                meaning,
+                extra_folders: vec![folder],
+                conn_state,
+                handle,
+            });
+            start_recvs.push(start_recv);
+        }
+        if !polled_folders.is_empty() {
+            let (conn_state, handlers) = ImapConnectionState::new(&ctx).await?;
+            let (start_send, start_recv) = oneshot::channel();
+            let handle = task::spawn(polled_extra_folders_loop(
+                ctx.clone(),
+                start_send,
+                handlers,
+                polled_folders.clone(),
+            ));
+            oboxes.push(SchedBox {
+                meaning: FolderMeaning::Unknown,
+                extra_folders: polled_folders,
+                conn_state,
+                handle,
+            });
+            start_recvs.push(start_recv);
+        }
+
         let smtp_handle = {
             let ctx = ctx.clone();
             task::spawn(smtp_loop(ctx, smtp_start_send, smtp_handlers))
@@ -903,24 +1396,39 @@ pub(crate) async fn stop(self, context: &Context) {
         }
         self.smtp.stop().await.log_err(context).ok();
 
-        // Actually shutdown tasks.
+        // Wait for the tasks to finish their current operation and shut down.
         let timeout_duration = std::time::Duration::from_secs(30);
         for b in once(self.inbox).chain(self.oboxes) {
-            tokio::time::timeout(timeout_duration, b.handle)
-                .await
-                .log_err(context)
-                .ok();
+            join_or_abort(context, b.handle, timeout_duration).await;
         }
-        tokio::time::timeout(timeout_duration, self.smtp_handle)
-            .await
-            .log_err(context)
-            .ok();
+        join_or_abort(context, self.smtp_handle, timeout_duration).await;
         self.ephemeral_handle.abort();
         self.location_handle.abort();
         self.recently_seen_loop.abort();
     }
 }
 
+/// Waits for the task to finish and aborts it if it does not finish within `timeout_duration`.
+async fn join_or_abort(
+    context: &Context,
+    mut handle: task::JoinHandle<()>,
+    timeout_duration: std::time::Duration,
+) {
+    match tokio::time::timeout(timeout_duration, &mut handle).await {
+        Ok(res) => {
+            res.log_err(context).ok();
+        }
+        Err(_) => {
+            warn!(
+                context,
+                "Task did not shut down within {}, aborting it.",
+                duration_to_str(timeout_duration)
+            );
+            handle.abort();
+        }
+    }
+}
+
 /// Connection state logic shared between imap and smtp connections.
 #[derive(Debug)]
 struct ConnectionState {
@@ -934,12 +1442,17 @@ struct ConnectionState {
 
 impl ConnectionState {
     /// Shutdown this connection completely.
+    ///
+    /// The run loop finishes the operation it is currently performing,
+    /// IDLE is interrupted so it can be ended with a DONE command.
     async fn stop(&self) -> Result<()> {
         // Trigger shutdown of the run loop.
         self.stop_sender
             .send(())
             .await
             .context("failed to stop, missing receiver")?;
+        // Wake up the loop if it is idle so it notices the stop request.
+        self.interrupt(InterruptInfo::new(false));
         Ok(())
     }
 
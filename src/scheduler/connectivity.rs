@@ -8,10 +8,12 @@
 
 use crate::events::EventType;
 use crate::imap::{scan_folders::get_watched_folder_configs, FolderMeaning};
+use crate::push::PushState;
 use crate::quota::{QUOTA_ERROR_THRESHOLD_PERCENTAGE, QUOTA_WARN_THRESHOLD_PERCENTAGE};
 use crate::{context::Context, log::LogExt};
 use crate::{stock_str, tools};
 
+use super::watchdog::Stall;
 use super::InnerSchedulerState;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, EnumProperty, PartialOrd, Ord)]
@@ -115,7 +117,11 @@ fn all_work_done(&self) -> bool {
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct ConnectivityStore(Arc<Mutex<DetailedConnectivity>>);
+pub(crate) struct ConnectivityStore(
+    Arc<Mutex<DetailedConnectivity>>,
+    /// Last operation aborted by the watchdog.
+    Arc<std::sync::Mutex<Option<Stall>>>,
+);
 
 impl ConnectivityStore {
     async fn set(&self, context: &Context, v: DetailedConnectivity) {
@@ -141,6 +147,13 @@ pub(crate) async fn set_connected(&self, context: &Context) {
     pub(crate) async fn set_not_configured(&self, context: &Context) {
         self.set(context, DetailedConnectivity::NotConfigured).await;
     }
+    pub(crate) async fn set_stall(&self, context: &Context, stall: Stall) {
+        *self.1.lock().expect("Mutex is poisoned") = Some(stall);
+        context.emit_event(EventType::ConnectivityChanged);
+    }
+    pub(crate) fn get_stall(&self) -> Option<Stall> {
+        self.1.lock().expect("Mutex is poisoned").clone()
+    }
 
     async fn get_detailed(&self) -> DetailedConnectivity {
         self.0.lock().await.deref().clone()
@@ -209,6 +222,70 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// Returns a list item about the last operation of the connection
+/// aborted by the watchdog, or an empty string if there was none.
+fn stall_html(store: &ConnectivityStore) -> String {
+    match store.get_stall() {
+        Some(stall) => format!(
+            "<li><span class=\"yellow dot\"></span> {} stalled and was aborted at {}</li>",
+            stall.operation,
+            tools::timestamp_to_str(stall.timestamp)
+        ),
+        None => String::new(),
+    }
+}
+
+/// Start of the HTML page returned by [`Context::get_connectivity_html`],
+/// including the styles used by [`Context::get_connectivity_html_body`].
+pub(crate) const CONNECTIVITY_HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8" />
+    <meta name="viewport" content="initial-scale=1.0; user-scalable=no" />
+    <style>
+        ul {
+            list-style-type: none;
+            padding-left: 1em;
+        }
+        .dot {
+            height: 0.9em; width: 0.9em;
+            border: 1px solid #888;
+            border-radius: 50%;
+            display: inline-block;
+            position: relative; left: -0.1em; top: 0.1em;
+        }
+        .bar {
+            width: 90%;
+            border: 1px solid #888;
+            border-radius: .5em;
+            margin-top: .2em;
+            margin-bottom: 1em;
+            position: relative; left: -0.2em;
+        }
+        .progress {
+            min-width:1.8em;
+            height: 1em;
+            border-radius: .45em;
+            color: white;
+            text-align: center;
+            padding-bottom: 2px;
+        }
+        .red {
+            background-color: #f33b2d;
+        }
+        .green {
+            background-color: #34c759;
+        }
+        .yellow {
+            background-color: #fdc625;
+        }
+    </style>
+</head>
+<body>"#;
+
+/// End of the HTML page returned by [`Context::get_connectivity_html`].
+pub(crate) const CONNECTIVITY_HTML_TAIL: &str = "</body></html>\n";
+
 impl Context {
     /// Get the current connectivity, i.e. whether the device is connected to the IMAP server.
     /// One of:
@@ -257,52 +334,16 @@ pub async fn get_connectivity(&self) -> Connectivity {
     /// This comes as an HTML from the core so that we can easily improve it
     /// and the improvement instantly reaches all UIs.
     pub async fn get_connectivity_html(&self) -> Result<String> {
-        let mut ret = r#"<!DOCTYPE html>
-            <html>
-            <head>
-                <meta charset="UTF-8" />
-                <meta name="viewport" content="initial-scale=1.0; user-scalable=no" />
-                <style>
-                    ul {
-                        list-style-type: none;
-                        padding-left: 1em;
-                    }
-                    .dot {
-                        height: 0.9em; width: 0.9em;
-                        border: 1px solid #888;
-                        border-radius: 50%;
-                        display: inline-block;
-                        position: relative; left: -0.1em; top: 0.1em;
-                    }
-                    .bar {
-                        width: 90%;
-                        border: 1px solid #888;
-                        border-radius: .5em;
-                        margin-top: .2em;
-                        margin-bottom: 1em;
-                        position: relative; left: -0.2em;
-                    }
-                    .progress {
-                        min-width:1.8em;
-                        height: 1em;
-                        border-radius: .45em;
-                        color: white;
-                        text-align: center;
-                        padding-bottom: 2px;
-                    }
-                    .red {
-                        background-color: #f33b2d;
-                    }
-                    .green {
-                        background-color: #34c759;
-                    }
-                    .yellow {
-                        background-color: #fdc625;
-                    }
-                </style>
-            </head>
-            <body>"#
-            .to_string();
+        let body = self.get_connectivity_html_body().await?;
+        Ok(format!(
+            "{CONNECTIVITY_HTML_HEAD}{body}{CONNECTIVITY_HTML_TAIL}"
+        ))
+    }
+
+    /// Returns the content of the page returned by [`Context::get_connectivity_html`],
+    /// without the surrounding `<html>` and `<body>` elements.
+    pub(crate) async fn get_connectivity_html_body(&self) -> Result<String> {
+        let mut ret = String::new();
 
         // =============================================================================================
         //                              Get the states from the RwLock
@@ -313,7 +354,13 @@ pub async fn get_connectivity_html(&self) -> Result<String> {
             InnerSchedulerState::Started(ref sched) => (
                 sched
                     .boxes()
-                    .map(|b| (b.meaning, b.conn_state.state.connectivity.clone()))
+                    .map(|b| {
+                        (
+                            b.meaning,
+                            b.extra_folders.clone(),
+                            b.conn_state.state.connectivity.clone(),
+                        )
+                    })
                     .collect::<Vec<_>>(),
                 sched.smtp.state.connectivity.clone(),
             ),
@@ -333,24 +380,30 @@ pub async fn get_connectivity_html(&self) -> Result<String> {
         let watched_folders = get_watched_folder_configs(self).await?;
         let incoming_messages = stock_str::incoming_messages(self).await;
         ret += &format!("<h3>{incoming_messages}</h3><ul>");
-        for (folder, state) in &folders_states {
+        for (folder, extra_folders, state) in &folders_states {
             let mut folder_added = false;
 
-            if let Some(config) = folder.to_config().filter(|c| watched_folders.contains(c)) {
-                let f = self.get_config(config).await.log_err(self).ok().flatten();
-
-                if let Some(foldername) = f {
-                    let detailed = &state.get_detailed().await;
-                    ret += "<li>";
-                    ret += &*detailed.to_icon();
-                    ret += " <b>";
-                    ret += &*escaper::encode_minimal(&foldername);
-                    ret += ":</b> ";
-                    ret += &*escaper::encode_minimal(&detailed.to_string_imap(self).await);
-                    ret += "</li>";
-
-                    folder_added = true;
+            let foldernames: Vec<String> = if extra_folders.is_empty() {
+                match folder.to_config().filter(|c| watched_folders.contains(c)) {
+                    Some(config) => self.get_config(config).await.log_err(self).ok().flatten(),
+                    None => None,
                 }
+                .into_iter()
+                .collect()
+            } else {
+                extra_folders.clone()
+            };
+            for foldername in foldernames {
+                let detailed = &state.get_detailed().await;
+                ret += "<li>";
+                ret += &*detailed.to_icon();
+                ret += " <b>";
+                ret += &*escaper::encode_minimal(&foldername);
+                ret += ":</b> ";
+                ret += &*escaper::encode_minimal(&detailed.to_string_imap(self).await);
+                ret += "</li>";
+
+                folder_added = true;
             }
 
             if !folder_added && folder == &FolderMeaning::Inbox {
@@ -365,6 +418,7 @@ pub async fn get_connectivity_html(&self) -> Result<String> {
                     ret += "</li>";
                 }
             }
+            ret += &stall_html(state);
         }
         ret += "</ul>";
 
@@ -380,7 +434,9 @@ pub async fn get_connectivity_html(&self) -> Result<String> {
         ret += &*detailed.to_icon();
         ret += " ";
         ret += &*escaper::encode_minimal(&detailed.to_string_smtp(self).await);
-        ret += "</li></ul>";
+        ret += "</li>";
+        ret += &stall_html(&smtp);
+        ret += "</ul>";
 
         // =============================================================================================
         // Add e.g.
@@ -476,8 +532,42 @@ pub async fn get_connectivity_html(&self) -> Result<String> {
         ret += "</ul>";
 
         // =============================================================================================
+        // Add e.g.
+        //                              Push Notifications
+        //                                Connected
+        // =============================================================================================
+
+        let push_notifications = stock_str::push_notifications(self).await;
+        ret += &format!("<h3>{push_notifications}</h3><ul><li>");
+        let push_state = self.push_state.read().expect("RwLock is poisoned").clone();
+        ret += &match push_state {
+            PushState::NotRegistered => stock_str::push_notifications_disabled(self).await,
+            PushState::Registered => format!(
+                "<span class=\"green dot\"></span> {}",
+                stock_str::connected(self).await
+            ),
+            PushState::Error(err) => format!(
+                "<span class=\"red dot\"></span> {}",
+                escaper::encode_minimal(&stock_str::error(self, &err).await)
+            ),
+        };
+        ret += "</li></ul>";
+
+        // =============================================================================================
+        // Add e.g.
+        //                              Last Error
+        //                                Cannot login as "alice@example.org"
+        // =============================================================================================
+
+        let last_error = self.get_last_error();
+        if !last_error.is_empty() {
+            let last_error_title = stock_str::last_error(self).await;
+            ret += &format!(
+                "<h3>{last_error_title}</h3><ul><li>{}</li></ul>",
+                escaper::encode_minimal(&last_error)
+            );
+        }
 
-        ret += "</body></html>\n";
         Ok(ret)
     }
 
@@ -0,0 +1,124 @@
+//! # Watchdog for stalled IMAP and SMTP operations.
+//!
+//! Reads and writes on IMAP and SMTP sockets time out after 30 seconds,
+//! but an operation may still get stuck, e.g. if the TLS library hangs
+//! or the server keeps the connection alive without making progress.
+//! Such an operation would block the whole IMAP or SMTP loop.
+//!
+//! The scheduler wraps operations into [`watch`], which aborts operations
+//! running for more than [`STALL_TIMEOUT`] and records the stall
+//! in the [`ConnectivityStore`] of the connection, so it is shown
+//! in the connectivity report. The caller is expected to drop the connection
+//! if [`is_stalled`] returns true for the returned error,
+//! as the protocol state after aborting an operation is unknown.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::connectivity::ConnectivityStore;
+use crate::context::Context;
+use crate::tools::time;
+
+/// Time after which an IMAP or SMTP operation is considered stalled.
+///
+/// Fetching is done in batches, so even on a slow connection
+/// no operation is expected to run that long.
+pub(crate) const STALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Stalled operation, shown in the connectivity report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Stall {
+    /// Name of the aborted operation.
+    pub operation: &'static str,
+
+    /// Time when the operation was aborted, Unix time in seconds.
+    pub timestamp: i64,
+}
+
+/// Error returned by [`watch`] for an aborted operation.
+#[derive(Debug, thiserror::Error)]
+#[error("{operation} stalled for {} seconds and was aborted", .timeout.as_secs())]
+pub(crate) struct Stalled {
+    operation: &'static str,
+    timeout: Duration,
+}
+
+/// Runs an IMAP or SMTP operation, aborting it after [`STALL_TIMEOUT`].
+pub(crate) async fn watch<T>(
+    context: &Context,
+    connectivity: &ConnectivityStore,
+    operation: &'static str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    watch_with_timeout(context, connectivity, operation, STALL_TIMEOUT, fut).await
+}
+
+async fn watch_with_timeout<T>(
+    context: &Context,
+    connectivity: &ConnectivityStore,
+    operation: &'static str,
+    timeout: Duration,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(res) => res,
+        Err(_) => {
+            let err = Stalled { operation, timeout };
+            warn!(context, "{err}.");
+            connectivity
+                .set_stall(
+                    context,
+                    Stall {
+                        operation,
+                        timestamp: time(),
+                    },
+                )
+                .await;
+            Err(err.into())
+        }
+    }
+}
+
+/// Returns true if the error was caused by an operation aborted by [`watch`].
+pub(crate) fn is_stalled(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<Stalled>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context as _;
+
+    use super::*;
+    use crate::test_utils::TestContext;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_watch() -> Result<()> {
+        let t = TestContext::new().await;
+        let connectivity = ConnectivityStore::default();
+        let timeout = Duration::from_millis(100);
+
+        let res = watch_with_timeout(&t, &connectivity, "noop", timeout, async { Ok(42) }).await;
+        assert_eq!(res?, 42);
+        assert_eq!(connectivity.get_stall(), None);
+
+        let err = watch_with_timeout(&t, &connectivity, "hang", timeout, async {
+            std::future::pending::<Result<()>>().await
+        })
+        .await
+        .context("sync_seen_flags")
+        .unwrap_err();
+        assert!(is_stalled(&err));
+        assert_eq!(connectivity.get_stall().unwrap().operation, "hang");
+
+        let err = watch_with_timeout(&t, &connectivity, "fail", timeout, async {
+            Err::<(), _>(anyhow::anyhow!("Connection refused"))
+        })
+        .await
+        .unwrap_err();
+        assert!(!is_stalled(&err));
+        assert_eq!(connectivity.get_stall().unwrap().operation, "hang");
+        Ok(())
+    }
+}
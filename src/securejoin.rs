@@ -19,7 +19,7 @@
 use crate::mimeparser::{MimeMessage, SystemMessage};
 use crate::param::Param;
 use crate::peerstate::{Peerstate, PeerstateKeyType, PeerstateVerifiedStatus};
-use crate::qr::check_qr;
+use crate::qr::{check_qr, qr_to_invite_link, set_config_from_qr, Qr};
 use crate::stock_str;
 use crate::token;
 use crate::tools::time;
@@ -129,6 +129,50 @@ pub async fn get_securejoin_qr(context: &Context, group: Option<ChatId>) -> Resu
     Ok(qr)
 }
 
+/// Generates a Secure Join invite link, e.g. `https://i.delta.chat/#FINGERPRINT&a=...`.
+///
+/// The link contains the same data as the QR code generated by [`get_securejoin_qr`]
+/// and can be shared where QR codes cannot be scanned.
+/// As the data is in the URL fragment,
+/// it is not sent to the web server if the link is opened in a browser.
+pub async fn get_securejoin_link(context: &Context, group: Option<ChatId>) -> Result<String> {
+    let qr = get_securejoin_qr(context, group).await?;
+    qr_to_invite_link(&qr)
+}
+
+/// Withdraws a Secure Join QR code or invite link generated by this device.
+///
+/// Handshakes started by scanning a withdrawn code are ignored,
+/// so a code that was shared by accident cannot be used to join anymore.
+/// The code can be enabled again by [`revive_securejoin_qr`].
+/// Other devices of the account are informed using a sync message.
+pub async fn withdraw_securejoin_qr(context: &Context, qr: &str) -> Result<()> {
+    match check_qr(context, qr).await? {
+        Qr::WithdrawVerifyContact { .. } | Qr::WithdrawVerifyGroup { .. } => {
+            set_config_from_qr(context, qr).await
+        }
+        Qr::ReviveVerifyContact { .. } | Qr::ReviveVerifyGroup { .. } => {
+            info!(context, "Secure-join QR code is already withdrawn.");
+            Ok(())
+        }
+        _ => bail!("Not an own Secure Join QR code"),
+    }
+}
+
+/// Enables a Secure Join QR code or invite link withdrawn by [`withdraw_securejoin_qr`] again.
+pub async fn revive_securejoin_qr(context: &Context, qr: &str) -> Result<()> {
+    match check_qr(context, qr).await? {
+        Qr::ReviveVerifyContact { .. } | Qr::ReviveVerifyGroup { .. } => {
+            set_config_from_qr(context, qr).await
+        }
+        Qr::WithdrawVerifyContact { .. } | Qr::WithdrawVerifyGroup { .. } => {
+            info!(context, "Secure-join QR code is not withdrawn.");
+            Ok(())
+        }
+        _ => bail!("Not an own Secure Join QR code"),
+    }
+}
+
 async fn get_self_fingerprint(context: &Context) -> Option<Fingerprint> {
     match load_self_public_key(context).await {
         Ok(key) => Some(key.fingerprint()),
@@ -760,6 +804,49 @@ async fn mark_peer_as_verified(
     }
 }
 
+/// Marks the contact as verified after the user compared `fingerprint`
+/// with the one shown on the contact's device, e.g. over the phone.
+///
+/// The result is the same as for a successful Secure-Join with the contact.
+pub(crate) async fn mark_verified_manually(
+    context: &Context,
+    contact_id: ContactId,
+    fingerprint: Fingerprint,
+) -> Result<()> {
+    let contact = Contact::get_by_id(context, contact_id).await?;
+    let mut peerstate = Peerstate::from_addr(context, contact.get_addr())
+        .await?
+        .with_context(|| format!("No key known for {}", contact.get_addr()))?;
+    let which_key = if peerstate.public_key_fingerprint.as_ref() == Some(&fingerprint) {
+        PeerstateKeyType::PublicKey
+    } else if peerstate.gossip_key_fingerprint.as_ref() == Some(&fingerprint) {
+        PeerstateKeyType::GossipKey
+    } else {
+        bail!(
+            "Fingerprint {} does not match the key of {}",
+            fingerprint.hex(),
+            contact.get_addr()
+        );
+    };
+    peerstate.set_verified(
+        which_key,
+        fingerprint,
+        PeerstateVerifiedStatus::BidirectVerified,
+        contact.get_addr().to_owned(),
+    )?;
+    peerstate.prefer_encrypt = EncryptPreference::Mutual;
+    peerstate.save_to_db(&context.sql).await?;
+    info!(context, "Contact {contact_id} verified manually.");
+    context.emit_event(EventType::ContactsChanged(Some(contact_id)));
+    secure_connection_established(
+        context,
+        contact_id,
+        info_chat_id(context, contact_id).await?,
+    )
+    .await?;
+    Ok(())
+}
+
 /* ******************************************************************************
  * Tools: Misc.
  ******************************************************************************/
@@ -1420,4 +1507,42 @@ async fn test_adhoc_group_no_qr() -> Result<()> {
         assert!(get_securejoin_qr(&alice, Some(chat_id)).await.is_err());
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_withdraw_securejoin_qr() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let qr = get_securejoin_qr(&alice, None).await?;
+        let link = get_securejoin_link(&alice, None).await?;
+
+        // Withdrawing the invite link withdraws the QR code as well.
+        withdraw_securejoin_qr(&alice, &link).await?;
+        assert!(matches!(
+            check_qr(&alice, &qr).await?,
+            Qr::ReviveVerifyContact { .. }
+        ));
+        assert!(withdraw_securejoin_qr(&bob, &qr).await.is_err());
+
+        // Alice ignores the handshake started with the withdrawn code.
+        join_securejoin(&bob, &qr).await?;
+        let sent = bob.pop_sent_msg().await;
+        alice.recv_msg_opt(&sent).await;
+        assert!(alice
+            .pop_sent_msg_opt(std::time::Duration::from_secs(0))
+            .await
+            .is_none());
+
+        revive_securejoin_qr(&alice, &qr).await?;
+        join_securejoin(&bob, &qr).await?;
+        let sent = bob.pop_sent_msg().await;
+        alice.recv_msg_opt(&sent).await;
+        let sent = alice.pop_sent_msg().await;
+        let msg = bob.parse_msg(&sent).await;
+        assert_eq!(
+            msg.get_header(HeaderDef::SecureJoin).unwrap(),
+            "vc-auth-required"
+        );
+        Ok(())
+    }
 }
@@ -10,10 +10,13 @@
 use tokio::io::BufStream;
 use tokio::task;
 
+use crate::audit;
+use crate::chat;
 use crate::config::Config;
 use crate::contact::{Contact, ContactId};
 use crate::context::Context;
 use crate::events::EventType;
+use crate::job::JobKind;
 use crate::login_param::{CertificateChecks, LoginParam, ServerLoginParam};
 use crate::message::Message;
 use crate::message::{self, MsgId};
@@ -26,6 +29,8 @@
 use crate::scheduler::connectivity::ConnectivityStore;
 use crate::socks::Socks5Config;
 use crate::sql;
+use crate::stock_str;
+use crate::tools::time;
 
 /// SMTP write and read timeout.
 const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
@@ -335,6 +340,7 @@ pub async fn connect(
 
         self.transport = Some(transport);
         self.last_success = Some(SystemTime::now());
+        context.metrics.record_smtp_connect();
 
         context.emit_event(EventType::SmtpConnected(format!(
             "SMTP-LOGIN as {} ok",
@@ -511,6 +517,25 @@ pub(crate) async fn smtp_send(
     status
 }
 
+/// Renders a message which was queued without MIME body, see `chat::create_send_msg_job()`.
+///
+/// Such messages reference files outside the blobdir,
+/// which are only read now instead of being stored in the `smtp` table.
+/// The message is marked as failed if it cannot be rendered.
+pub(crate) async fn render_deferred_msg(context: &Context, msg_id: MsgId) -> Result<String> {
+    let mut msg = Message::load_from_db(context, msg_id).await?;
+    let attach_selfavatar = chat::shall_attach_selfavatar(context, msg.chat_id)
+        .await
+        .unwrap_or_default();
+    let rendered_msg = MimeFactory::from_msg(context, &msg, attach_selfavatar)
+        .await?
+        .render(context)
+        .await;
+    let rendered_msg =
+        chat::process_rendered_msg(context, &mut msg, rendered_msg, attach_selfavatar).await?;
+    Ok(rendered_msg.message)
+}
+
 /// Sends message identified by `smtp` table rowid over SMTP connection.
 ///
 /// Removes row if the message should not be retried, otherwise increments retry count.
@@ -552,14 +577,16 @@ pub(crate) async fn send_msg_to_smtp(
             },
         )
         .await?;
-    if retries > 6 {
+    if retries > JobKind::SendMsg.max_retries() {
+        let error = "Number of retries exceeded the limit.";
         let mut msg = Message::load_from_db(context, msg_id).await?;
-        message::set_msg_failed(context, &mut msg, "Number of retries exceeded the limit.").await?;
+        message::set_msg_failed(context, &mut msg, error).await?;
         context
             .sql
             .execute("DELETE FROM smtp WHERE id=?", (rowid,))
             .await
             .context("failed to remove message with exceeded retry limit from smtp table")?;
+        context.emit_job_failed(JobKind::SendMsg, msg_id, error);
         return Ok(());
     }
     info!(
@@ -567,6 +594,24 @@ pub(crate) async fn send_msg_to_smtp(
         "Try number {retries} to send message {msg_id} (entry {rowid}) over SMTP"
     );
 
+    let body = if body.is_empty() {
+        match render_deferred_msg(context, msg_id).await {
+            Ok(body) => body,
+            Err(err) => {
+                // The referenced file may have vanished, retrying would not help.
+                warn!(context, "Cannot render message {msg_id}: {err:#}.");
+                context
+                    .sql
+                    .execute("DELETE FROM smtp WHERE id=?", (rowid,))
+                    .await?;
+                context.emit_job_failed(JobKind::SendMsg, msg_id, &format!("{err:#}"));
+                return Ok(());
+            }
+        }
+    } else {
+        body
+    };
+
     let recipients_list = recipients
         .split(' ')
         .filter_map(
@@ -582,13 +627,49 @@ pub(crate) async fn send_msg_to_smtp(
 
     let status = smtp_send(context, &recipients_list, body.as_str(), smtp, msg_id).await;
 
+    let operation = match &status {
+        SendResult::Retry => format!(
+            "Sending failed, will retry: {}",
+            smtp.last_send_error.as_deref().unwrap_or_default()
+        ),
+        SendResult::Success => format!("Sent to {} recipients", recipients_list.len()),
+        SendResult::Failure(err) => format!("Sending failed: {err:#}"),
+    };
+    if let Err(err) = audit::log_msg(context, msg_id, &operation).await {
+        warn!(
+            context,
+            "Cannot record SMTP operation on {msg_id}: {err:#}."
+        );
+    }
+
     match status {
-        SendResult::Retry => {}
-        SendResult::Success | SendResult::Failure(_) => {
+        SendResult::Retry => {
+            context.metrics.record_job_retry();
+            let next_try = time().saturating_add(JobKind::SendMsg.retry_delay(retries));
+            context
+                .sql
+                .execute(
+                    "UPDATE smtp SET next_try=?, last_error=? WHERE id=?",
+                    (
+                        next_try,
+                        smtp.last_send_error.as_deref().unwrap_or_default(),
+                        rowid,
+                    ),
+                )
+                .await?;
+        }
+        SendResult::Success => {
+            context
+                .sql
+                .execute("DELETE FROM smtp WHERE id=?", (rowid,))
+                .await?;
+        }
+        SendResult::Failure(ref err) => {
             context
                 .sql
                 .execute("DELETE FROM smtp WHERE id=?", (rowid,))
                 .await?;
+            context.emit_job_failed(JobKind::SendMsg, msg_id, &format!("{err:#}"));
         }
     };
 
@@ -618,6 +699,40 @@ async fn send_mdns(context: &Context, connection: &mut Smtp) -> Result<()> {
     }
 }
 
+/// Marks queued messages as failed and removes them from the `smtp` table
+/// if their delivery deadline has passed, see [`Message::set_delivery_ttl`].
+pub(crate) async fn expire_smtp_messages(context: &Context) -> Result<()> {
+    let now = time();
+    let expired = context
+        .sql
+        .query_map(
+            "SELECT id, msg_id FROM smtp WHERE deadline>0 AND deadline<=?",
+            (now,),
+            |row| {
+                let rowid: i64 = row.get(0)?;
+                let msg_id: MsgId = row.get(1)?;
+                Ok((rowid, msg_id))
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for (rowid, msg_id) in expired {
+        let error = stock_str::msg_delivery_expired(context).await;
+        let mut msg = Message::load_from_db(context, msg_id).await?;
+        message::set_msg_failed(context, &mut msg, &error).await?;
+        context
+            .sql
+            .execute("DELETE FROM smtp WHERE id=?", (rowid,))
+            .await
+            .context("failed to remove expired message from smtp table")?;
+        context.emit_job_failed(JobKind::SendMsg, msg_id, &error);
+    }
+    Ok(())
+}
+
 /// Tries to send all messages currently in `smtp`, `smtp_status_updates` and `smtp_mdns` tables.
 ///
 /// Logs and ignores SMTP errors to ensure that a single SMTP message constantly failing to be sent
@@ -632,11 +747,17 @@ pub(crate) async fn send_smtp_messages(context: &Context, connection: &mut Smtp)
         true
     };
 
+    expire_smtp_messages(context)
+        .await
+        .context("failed to expire messages")?;
+
     let rowids = context
         .sql
         .query_map(
-            "SELECT id FROM smtp ORDER BY id ASC",
-            (),
+            "SELECT id FROM smtp
+             WHERE next_try<=? AND msg_id NOT IN (SELECT msg_id FROM undo_msgs)
+             ORDER BY id ASC",
+            (time(),),
             |row| {
                 let rowid: i64 = row.get(0)?;
                 Ok(rowid)
@@ -756,19 +877,37 @@ async fn send_mdn(context: &Context, smtp: &mut Smtp) -> Result<bool> {
     }
     info!(context, "Sending MDNs");
 
+    let max_retries = JobKind::SendMdn.max_retries();
+    let exceeded = context
+        .sql
+        .query_map(
+            "SELECT msg_id, last_error FROM smtp_mdns WHERE retries > ?",
+            (max_retries,),
+            |row| {
+                let msg_id: MsgId = row.get(0)?;
+                let last_error: String = row.get(1)?;
+                Ok((msg_id, last_error))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
     context
         .sql
-        .execute("DELETE FROM smtp_mdns WHERE retries > 6", [])
+        .execute("DELETE FROM smtp_mdns WHERE retries > ?", (max_retries,))
         .await?;
+    for (msg_id, last_error) in exceeded {
+        context.emit_job_failed(JobKind::SendMdn, msg_id, &last_error);
+    }
     let msg_row = match context
         .sql
         .query_row_optional(
-            "SELECT msg_id, from_id FROM smtp_mdns ORDER BY retries LIMIT 1",
-            [],
+            "SELECT msg_id, from_id, retries FROM smtp_mdns WHERE next_try<=? ORDER BY retries LIMIT 1",
+            (time(),),
             |row| {
                 let msg_id: MsgId = row.get(0)?;
                 let from_id: ContactId = row.get(1)?;
-                Ok((msg_id, from_id))
+                let retries: i64 = row.get(2)?;
+                Ok((msg_id, from_id, retries))
             },
         )
         .await?
@@ -776,7 +915,7 @@ async fn send_mdn(context: &Context, smtp: &mut Smtp) -> Result<bool> {
         Some(msg_row) => msg_row,
         None => return Ok(false),
     };
-    let (msg_id, contact_id) = msg_row;
+    let (msg_id, contact_id, retries) = msg_row;
 
     context
         .sql
@@ -788,6 +927,21 @@ async fn send_mdn(context: &Context, smtp: &mut Smtp) -> Result<bool> {
         .context("failed to update MDN retries count")?;
 
     let res = send_mdn_msg_id(context, msg_id, contact_id, smtp).await;
+    if let Ok(false) = res {
+        context.metrics.record_job_retry();
+        let next_try = time().saturating_add(JobKind::SendMdn.retry_delay(retries + 1));
+        context
+            .sql
+            .execute(
+                "UPDATE smtp_mdns SET next_try=?, last_error=? WHERE msg_id=?",
+                (
+                    next_try,
+                    smtp.last_send_error.as_deref().unwrap_or_default(),
+                    msg_id,
+                ),
+            )
+            .await?;
+    }
     if let Err(ref err) = res {
         // If there is an error, for example there is no message corresponding to the msg_id in the
         // database, do not try to send this MDN again.
@@ -799,6 +953,7 @@ async fn send_mdn(context: &Context, smtp: &mut Smtp) -> Result<bool> {
             .sql
             .execute("DELETE FROM smtp_mdns WHERE msg_id = ?", (msg_id,))
             .await?;
+        context.emit_job_failed(JobKind::SendMdn, msg_id, &format!("{err:#}"));
     }
     // If there's a temporary error, pretend there are no more MDNs to send. It's unlikely that
     // other MDNs could be sent successfully in case of connectivity problems.
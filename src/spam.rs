@@ -0,0 +1,375 @@
+//! # Spam classification.
+//!
+//! If [`Config::SpamFilter`] is enabled, the first message of a contact request
+//! is checked against simple heuristics.
+//! Contact requests classified as spam are only listed with
+//! [`crate::constants::DC_GCL_SPAM_ONLY`] and do not notify the user.
+//!
+//! A message is considered spam if it arrived in the Spam folder,
+//! if a server-side filter flagged it using `X-Spam-Flag` or `X-Spam-Status` headers
+//! or if its subject contains one of [`Config::SpamKeywords`].
+//!
+//! If [`Config::DeleteRequestsAfter`] is set, contact requests,
+//! spam or not, are deleted by housekeeping once no message arrived for the configured number of days.
+
+use anyhow::Result;
+
+use crate::chat::ChatId;
+use crate::config::Config;
+use crate::constants::{Blocked, DC_CHAT_ID_LAST_SPECIAL};
+use crate::context::Context;
+use crate::headerdef::HeaderDef;
+use crate::mimeparser::MimeMessage;
+use crate::scheduler::InterruptInfo;
+use crate::tools::time;
+
+/// Returns true if the message looks like spam.
+async fn is_spam(context: &Context, mime_parser: &MimeMessage, rfc724_mid: &str) -> Result<bool> {
+    if mime_parser.get_header(HeaderDef::SecureJoin).is_some() {
+        return Ok(false);
+    }
+
+    if let Some(spam_folder) = context.get_config(Config::ConfiguredSpamFolder).await? {
+        if context
+            .sql
+            .exists(
+                "SELECT COUNT(*) FROM imap WHERE rfc724_mid=? AND folder=?",
+                (rfc724_mid, spam_folder),
+            )
+            .await?
+        {
+            info!(context, "Message {rfc724_mid} is in the Spam folder.");
+            return Ok(true);
+        }
+    }
+
+    if has_spam_headers(mime_parser) {
+        info!(context, "Message {rfc724_mid} is flagged as spam.");
+        return Ok(true);
+    }
+
+    let keywords = context
+        .get_config(Config::SpamKeywords)
+        .await?
+        .unwrap_or_default();
+    if let Some(subject) = mime_parser.get_subject() {
+        if contains_keyword(&subject, &keywords) {
+            info!(context, "Subject of {rfc724_mid} contains a spam keyword.");
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Returns true if a server-side spam filter flagged the message.
+fn has_spam_headers(mime_parser: &MimeMessage) -> bool {
+    let flagged = |headerdef| {
+        mime_parser
+            .get_header(headerdef)
+            .and_then(|value| value.trim().get(..3))
+            .map_or(false, |value| value.eq_ignore_ascii_case("yes"))
+    };
+    flagged(HeaderDef::XSpamFlag) || flagged(HeaderDef::XSpamStatus)
+}
+
+/// Returns true if `text` contains one of the comma-separated `keywords` as whole words,
+/// ignoring case.
+fn contains_keyword(text: &str, keywords: &str) -> bool {
+    let text = text.to_lowercase();
+    let is_word_char = |c: Option<char>| c.map_or(false, char::is_alphanumeric);
+    keywords
+        .split(',')
+        .map(|keyword| keyword.trim().to_lowercase())
+        .filter(|keyword| !keyword.is_empty())
+        .any(|keyword| {
+            text.match_indices(&keyword).any(|(start, _)| {
+                let end = start + keyword.len();
+                !is_word_char(text.get(..start).and_then(|s| s.chars().next_back()))
+                    && !is_word_char(text.get(end..).and_then(|s| s.chars().next()))
+            })
+        })
+}
+
+/// Classifies the contact request `chat_id` as spam
+/// if the received message is its first message and looks like spam.
+///
+/// Returns true if the chat is classified as spam.
+pub(crate) async fn classify_contact_request(
+    context: &Context,
+    chat_id: ChatId,
+    mime_parser: &MimeMessage,
+    rfc724_mid: &str,
+) -> Result<bool> {
+    if !context.get_config_bool(Config::SpamFilter).await? {
+        return Ok(false);
+    }
+    if chat_id.get_msg_cnt(context).await? == 0 && is_spam(context, mime_parser, rfc724_mid).await?
+    {
+        chat_id.set_spam(context, true).await?;
+        return Ok(true);
+    }
+    context
+        .sql
+        .exists(
+            "SELECT COUNT(*) FROM chats WHERE id=? AND spam=1",
+            (chat_id,),
+        )
+        .await
+}
+
+/// Schedules moving the messages of `chat_id` out of the Spam folder.
+pub(crate) async fn move_out_of_spam_folder(context: &Context, chat_id: ChatId) -> Result<()> {
+    let Some(spam_folder) = context.get_config(Config::ConfiguredSpamFolder).await? else {
+        return Ok(());
+    };
+    let target_config = if context.get_config_bool(Config::OnlyFetchMvbox).await? {
+        Config::ConfiguredMvboxFolder
+    } else {
+        Config::ConfiguredInboxFolder
+    };
+    let Some(target) = context.get_config(target_config).await? else {
+        return Ok(());
+    };
+    let moved = context
+        .sql
+        .execute(
+            "UPDATE imap SET target=?
+             WHERE folder=? AND rfc724_mid IN (SELECT rfc724_mid FROM msgs WHERE chat_id=?)",
+            (target, spam_folder, chat_id),
+        )
+        .await?;
+    if moved > 0 {
+        // Messages are moved when the Spam folder is scanned, so do not wait for the debounce.
+        context.last_full_folder_scan.lock().await.take();
+        context
+            .scheduler
+            .interrupt_inbox(InterruptInfo::new(false))
+            .await;
+    }
+    Ok(())
+}
+
+/// Deletes contact requests which did not receive a message
+/// for [`Config::DeleteRequestsAfter`] days.
+///
+/// Returns the number of deleted chats.
+pub(crate) async fn delete_stale_requests(context: &Context) -> Result<usize> {
+    let days = context.get_config_int(Config::DeleteRequestsAfter).await?;
+    if days <= 0 {
+        return Ok(0);
+    }
+    let threshold = time() - i64::from(days) * 24 * 60 * 60;
+    let chat_ids = context
+        .sql
+        .query_map(
+            "SELECT c.id FROM chats c
+             WHERE c.id>? AND c.blocked=?
+             AND IFNULL((SELECT MAX(timestamp) FROM msgs WHERE chat_id=c.id), c.created_timestamp)<?",
+            (DC_CHAT_ID_LAST_SPECIAL, Blocked::Request, threshold),
+            |row| row.get::<_, ChatId>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    if chat_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let from_server = context
+        .get_config_bool(Config::DeleteRequestsFromServer)
+        .await?;
+    let target = context.get_delete_msgs_target().await?;
+    for &chat_id in &chat_ids {
+        if from_server {
+            context
+                .sql
+                .execute(
+                    "UPDATE imap SET target=?
+                     WHERE rfc724_mid IN (SELECT rfc724_mid FROM msgs WHERE chat_id=?)",
+                    (&target, chat_id),
+                )
+                .await?;
+        }
+        // Files of the deleted messages are removed later by the same housekeeping run.
+        chat_id.delete_from_db(context).await?;
+    }
+    context.emit_msgs_changed_without_ids();
+    info!(
+        context,
+        "Deleted {} contact requests older than {days} days.",
+        chat_ids.len()
+    );
+    Ok(chat_ids.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{Chat, ChatVisibility};
+    use crate::chatlist::{get_archived_cnt, Chatlist};
+    use crate::constants::{DC_GCL_ARCHIVED_ONLY, DC_GCL_SPAM_ONLY};
+    use crate::receive_imf::receive_imf;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_contains_keyword() {
+        assert!(contains_keyword("You WON a prize", "lottery, won "));
+        assert!(!contains_keyword("Wonderful", "lottery, won "));
+        assert!(!contains_keyword("Wonderful", "won"));
+        assert!(contains_keyword("Won!", "won"));
+        assert!(contains_keyword("Claim your FREE MONEY now", "free money"));
+        assert!(!contains_keyword("Carefree moneybox", "free money"));
+        assert!(!contains_keyword("Hello", ""));
+        assert!(!contains_keyword("Hello", " , "));
+    }
+
+    async fn receive(t: &TestContext, mid: &str, extra_header: &str) -> Result<Chat> {
+        let raw = format!(
+            "From: Spammer <spammer@example.net>\n\
+             To: alice@example.org\n\
+             Subject: Cheap lottery tickets\n\
+             Message-ID: <{mid}>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             {extra_header}\
+             \n\
+             Buy now!\n"
+        );
+        let received = receive_imf(t, raw.as_bytes(), false).await?.unwrap();
+        Chat::load_from_db(t, received.chat_id).await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spam_headers() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = receive(&t, "1@example.net", "X-Spam-Flag: YES\n").await?;
+        assert!(chat.is_contact_request());
+        assert!(!chat.is_spam());
+
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::SpamFilter, true).await?;
+        let chat = receive(&t, "1@example.net", "X-Spam-Status: Yes, score=7.1\n").await?;
+        assert!(chat.is_contact_request());
+        assert!(chat.is_spam());
+
+        let chatlist = Chatlist::try_load(&t, 0, None, None).await?;
+        assert_eq!(chatlist.len(), 0);
+        let chatlist = Chatlist::try_load(&t, DC_GCL_SPAM_ONLY, None, None).await?;
+        assert_eq!(chatlist.get_chat_id(0)?, chat.id);
+        assert_eq!(chat.id.get_fresh_msg_cnt(&t).await?, 0);
+        let chatlist = Chatlist::try_load(&t, 0, Some("Spammer"), None).await?;
+        assert_eq!(chatlist.len(), 0);
+        chat.id.set_visibility(&t, ChatVisibility::Archived).await?;
+        let chatlist = Chatlist::try_load(&t, DC_GCL_ARCHIVED_ONLY, None, None).await?;
+        assert_eq!(chatlist.len(), 0);
+        assert_eq!(get_archived_cnt(&t).await?, 0);
+        chat.id.set_visibility(&t, ChatVisibility::Normal).await?;
+
+        // Later messages do not change the classification.
+        let chat2 = receive(&t, "2@example.net", "").await?;
+        assert_eq!(chat2.id, chat.id);
+        assert!(chat2.is_spam());
+
+        chat.id.mark_not_spam(&t).await?;
+        let chat = Chat::load_from_db(&t, chat.id).await?;
+        assert!(chat.is_contact_request());
+        assert!(!chat.is_spam());
+        let chatlist = Chatlist::try_load(&t, 0, None, None).await?;
+        assert_eq!(chatlist.get_chat_id(0)?, chat.id);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spam_keywords() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::SpamFilter, true).await?;
+        let chat = receive(&t, "1@example.net", "").await?;
+        assert!(!chat.is_spam());
+
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::SpamFilter, true).await?;
+        t.set_config(Config::SpamKeywords, Some("viagra, LOTTERY"))
+            .await?;
+        let chat = receive(&t, "1@example.net", "").await?;
+        assert!(chat.is_spam());
+
+        chat.id.accept(&t).await?;
+        let chat = Chat::load_from_db(&t, chat.id).await?;
+        assert!(!chat.is_contact_request());
+        assert!(!chat.is_spam());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spam_folder() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::SpamFilter, true).await?;
+        t.set_config(Config::ConfiguredInboxFolder, Some("INBOX"))
+            .await?;
+        t.set_config(Config::ConfiguredSpamFolder, Some("Junk"))
+            .await?;
+        t.sql
+            .execute(
+                "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
+                 VALUES ('1@example.net', 'Junk', 1, 1, 'Junk')",
+                (),
+            )
+            .await?;
+        let chat = receive(&t, "1@example.net", "").await?;
+        assert!(chat.is_spam());
+
+        chat.id.mark_not_spam(&t).await?;
+        let target: String = t
+            .sql
+            .query_get_value(
+                "SELECT target FROM imap WHERE rfc724_mid='1@example.net'",
+                (),
+            )
+            .await?
+            .unwrap();
+        assert_eq!(target, "INBOX");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_delete_stale_requests() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        // The message date is in 2020, so the request is stale.
+        let chat = receive(&t, "1@example.net", "").await?;
+        assert!(chat.is_contact_request());
+        assert_eq!(delete_stale_requests(&t).await?, 0);
+
+        t.set_config(Config::DeleteRequestsAfter, Some("30"))
+            .await?;
+        t.set_config(Config::LastHousekeeping, Some("1000")).await?;
+        assert_eq!(delete_stale_requests(&t).await?, 1);
+        assert!(Chat::load_from_db(&t, chat.id).await.is_err());
+        // Deleting requests is part of housekeeping and must not schedule another run.
+        assert_eq!(t.get_config_int(Config::LastHousekeeping).await?, 1000);
+
+        t.set_config_bool(Config::DeleteRequestsFromServer, true)
+            .await?;
+        t.sql
+            .execute(
+                "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
+                 VALUES ('2@example.net', 'INBOX', 1, 1, 'INBOX')",
+                (),
+            )
+            .await?;
+        receive(&t, "2@example.net", "").await?;
+        assert_eq!(delete_stale_requests(&t).await?, 1);
+        let target: String = t
+            .sql
+            .query_get_value(
+                "SELECT target FROM imap WHERE rfc724_mid='2@example.net'",
+                (),
+            )
+            .await?
+            .unwrap();
+        assert_eq!(target, "");
+
+        // Accepted chats are kept.
+        let chat = receive(&t, "3@example.net", "").await?;
+        chat.id.accept(&t).await?;
+        assert_eq!(delete_stale_requests(&t).await?, 0);
+        Ok(())
+    }
+}
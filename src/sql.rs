@@ -3,11 +3,14 @@
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{bail, Context as _, Result};
 use rusqlite::{self, config::DbConfig, types::ValueRef, Connection, OpenFlags, Row};
 use tokio::sync::{Mutex, MutexGuard, RwLock};
 
+use crate::audit;
 use crate::blob::BlobObject;
 use crate::chat::{add_device_msg, update_device_icon, update_saved_messages_icon};
 use crate::config::Config;
@@ -15,13 +18,17 @@
 use crate::context::Context;
 use crate::debug_logging::set_debug_logging_xdc;
 use crate::ephemeral::start_ephemeral_timers;
+use crate::imap;
 use crate::imex::BLOBS_BACKUP_NAME;
 use crate::log::LogExt;
 use crate::message::{Message, MsgId, Viewtype};
+use crate::metrics::Metrics;
 use crate::param::{Param, Params};
 use crate::peerstate::{deduplicate_peerstates, Peerstate};
+use crate::spam;
 use crate::stock_str;
 use crate::tools::{delete_file, time};
+use crate::webhook::update_webhook;
 
 /// Extension to [`rusqlite::ToSql`] trait
 /// which also includes [`Send`] and [`Sync`].
@@ -47,6 +54,7 @@ pub(crate) fn params_iter(
     iter.iter().map(|item| item as &dyn crate::sql::ToSql)
 }
 
+mod integrity;
 mod migrations;
 mod pool;
 
@@ -72,6 +80,9 @@ pub struct Sql {
 
     /// Cache of `config` table.
     pub(crate) config_cache: RwLock<HashMap<String, Option<String>>>,
+
+    /// Metric counters, shared with the context.
+    pub(crate) metrics: Arc<Metrics>,
 }
 
 impl Sql {
@@ -83,6 +94,7 @@ pub fn new(dbfile: PathBuf) -> Sql {
             pool: Default::default(),
             is_encrypted: Default::default(),
             config_cache: Default::default(),
+            metrics: Default::default(),
         }
     }
 
@@ -300,6 +312,30 @@ pub async fn open(&self, context: &Context, passphrase: String) -> Result<()> {
                 set_debug_logging_xdc(context, Some(MsgId::new(xdc_id))).await?;
             }
 
+            if let Some(filter) = self.get_raw_config(Config::LogFilter.as_ref()).await? {
+                match filter.parse() {
+                    Ok(filter) => context.set_log_filter(filter),
+                    Err(err) => warn!(context, "Ignoring invalid log filter: {err:#}."),
+                }
+            }
+
+            update_webhook(context)
+                .await
+                .context("failed to start webhook")
+                .log_err(context)
+                .ok();
+
+            integrity::check_integrity(context)
+                .await
+                .context("failed to check database integrity")
+                .log_err(context)
+                .ok();
+
+            self.metrics.set_enabled(
+                self.get_raw_config_bool(Config::MetricsEnabled.as_ref())
+                    .await?,
+            );
+
             Ok(())
         }
     }
@@ -376,8 +412,10 @@ async fn call<'a, F, R>(&'a self, function: F) -> Result<R>
         let lock = self.pool.read().await;
         let pool = lock.as_ref().context("no SQL connection")?;
         let mut conn = pool.get().await?;
-        let res = tokio::task::block_in_place(move || function(&mut conn))?;
-        Ok(res)
+        let start = Instant::now();
+        let res = tokio::task::block_in_place(move || function(&mut conn));
+        self.metrics.record_sql(start.elapsed());
+        res
     }
 
     /// Allocates a connection and calls given function, assuming it does write queries, with the
@@ -717,6 +755,14 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
         warn!(context, "Can't set config: {e:#}.");
     }
 
+    // Delete stale contact requests first, so that their files are removed below.
+    if let Err(err) = spam::delete_stale_requests(context).await {
+        warn!(
+            context,
+            "Housekeeping: cannot delete stale contact requests: {err:#}."
+        );
+    }
+
     if let Err(err) = remove_unused_files(context).await {
         warn!(
             context,
@@ -724,6 +770,13 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
         );
     }
 
+    if let Err(err) = integrity::check_missing_files(context).await {
+        warn!(
+            context,
+            "Housekeeping: cannot check for missing files: {err:#}."
+        );
+    }
+
     if let Err(err) = start_ephemeral_timers(context).await {
         warn!(
             context,
@@ -742,6 +795,10 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
         warn!(context, "Failed to deduplicate peerstates: {:#}.", err)
     }
 
+    if let Err(err) = audit::prune(context).await {
+        warn!(context, "Housekeeping: Cannot prune audit trail: {err:#}.");
+    }
+
     // Try to clear the freelist to free some space on the disk. This
     // only works if auto_vacuum is enabled.
     match context
@@ -784,6 +841,17 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
         .log_err(context)
         .ok();
 
+    context
+        .sql
+        .execute(
+            "DELETE FROM imap_moves WHERE timestamp<?",
+            (time().saturating_sub(imap::MOVES_RETENTION),),
+        )
+        .await
+        .context("failed to remove old IMAP moves")
+        .log_err(context)
+        .ok();
+
     info!(context, "Housekeeping done.");
     Ok(())
 }
@@ -994,13 +1062,16 @@ async fn maybe_add_from_param(
 
 /// Removes from the database locally deleted messages that also don't
 /// have a server UID.
+///
+/// Messages whose deletion can still be undone are kept.
 async fn prune_tombstones(sql: &Sql) -> Result<()> {
     sql.execute(
         "DELETE FROM msgs
          WHERE chat_id=?
          AND NOT EXISTS (
          SELECT * FROM imap WHERE msgs.rfc724_mid=rfc724_mid AND target!=''
-         )",
+         )
+         AND id NOT IN (SELECT msg_id FROM undo_msgs)",
         (DC_CHAT_ID_TRASH,),
     )
     .await?;
@@ -0,0 +1,316 @@
+//! # Consistency check of the database.
+//!
+//! Long-lived databases may end up in states the code does not expect,
+//! e.g. because the app was killed in the middle of an operation
+//! or because of bugs in older versions.
+//! When the database is opened, such states are repaired where possible,
+//! so that they do not cause errors later.
+//! Checks that need to scan the blob directory are done during housekeeping instead,
+//! so that opening the database is not delayed.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::chat::ChatId;
+use crate::constants::{Chattype, DC_CHAT_ID_TRASH};
+use crate::contact::ContactId;
+use crate::context::Context;
+use crate::download::DownloadState;
+use crate::events::EventType;
+use crate::message::{MessageState, MsgId};
+use crate::param::{Param, Params};
+use crate::stock_str;
+
+/// Checks the database for inconsistent states and repairs them.
+///
+/// Emits [`EventType::DatabaseRepaired`] if anything was repaired.
+pub(crate) async fn check_integrity(context: &Context) -> Result<()> {
+    let chats = repair_memberless_chats(context).await?;
+    let orphans = delete_orphans(context).await?;
+    report_repaired(context, 0, chats, orphans);
+    Ok(())
+}
+
+/// Marks messages whose file is missing in the blob directory with an error.
+///
+/// Called during housekeeping as it reads the whole blob directory.
+/// Emits [`EventType::DatabaseRepaired`] if any message was marked.
+pub(crate) async fn check_missing_files(context: &Context) -> Result<()> {
+    let missing_files = mark_missing_files(context).await?;
+    report_repaired(context, missing_files, 0, 0);
+    Ok(())
+}
+
+fn report_repaired(context: &Context, missing_files: usize, chats: usize, orphans: usize) {
+    if missing_files + chats + orphans > 0 {
+        info!(
+            context,
+            "Database repaired: {missing_files} messages with missing files, {chats} chats without members, {orphans} orphaned entries."
+        );
+        context.emit_event(EventType::DatabaseRepaired {
+            missing_files,
+            chats,
+            orphans,
+        });
+        context.emit_msgs_changed_without_ids();
+    }
+}
+
+/// Sets an error on messages whose file does not exist in the blob directory anymore.
+///
+/// The messages are kept as their text may still be useful.
+async fn mark_missing_files(context: &Context) -> Result<usize> {
+    let mut blobs = HashSet::new();
+    let mut dir = match tokio::fs::read_dir(context.get_blobdir()).await {
+        Ok(dir) => dir,
+        Err(err) => {
+            warn!(context, "Cannot read blob directory: {err:#}.");
+            return Ok(0);
+        }
+    };
+    while let Some(entry) = dir.next_entry().await? {
+        blobs.insert(entry.file_name().to_string_lossy().into_owned());
+    }
+
+    let msg_ids = context
+        .sql
+        .query_map(
+            "SELECT id, param FROM msgs
+             WHERE chat_id!=? AND state!=? AND download_state=? AND IFNULL(error, '')=''
+             AND param GLOB '*f=$BLOBDIR/*'",
+            (
+                DC_CHAT_ID_TRASH,
+                MessageState::OutPreparing,
+                DownloadState::Done,
+            ),
+            |row| {
+                let msg_id: MsgId = row.get(0)?;
+                let param: String = row.get(1)?;
+                Ok((msg_id, param))
+            },
+            |rows| {
+                let mut msg_ids = Vec::new();
+                for row in rows {
+                    let (msg_id, param) = row?;
+                    let param: Params = param.parse().unwrap_or_default();
+                    let is_missing = param
+                        .get(Param::File)
+                        .and_then(|file| file.strip_prefix("$BLOBDIR/"))
+                        .map_or(false, |name| !blobs.contains(name));
+                    if is_missing {
+                        msg_ids.push(msg_id);
+                    }
+                }
+                Ok(msg_ids)
+            },
+        )
+        .await?;
+    let error = stock_str::file_missing(context).await;
+    for msg_id in &msg_ids {
+        warn!(context, "File of {msg_id} is missing.");
+        context
+            .sql
+            .execute("UPDATE msgs SET error=? WHERE id=?", (&error, msg_id))
+            .await?;
+    }
+    Ok(msg_ids.len())
+}
+
+/// Repairs 1:1 chats that have no members.
+///
+/// The contact is restored from the incoming messages,
+/// chats without any messages are deleted.
+async fn repair_memberless_chats(context: &Context) -> Result<usize> {
+    let chat_ids: Vec<ChatId> = context
+        .sql
+        .query_map(
+            "SELECT id FROM chats
+             WHERE id>9 AND type=?
+             AND NOT EXISTS (SELECT 1 FROM chats_contacts WHERE chat_id=chats.id)",
+            (Chattype::Single,),
+            |row| row.get(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    let mut repaired = 0;
+    for chat_id in chat_ids {
+        let contact_id: Option<ContactId> = context
+            .sql
+            .query_get_value(
+                "SELECT from_id FROM msgs WHERE chat_id=? AND from_id>?
+                 ORDER BY id DESC LIMIT 1",
+                (chat_id, ContactId::LAST_SPECIAL),
+            )
+            .await?;
+        if let Some(contact_id) = contact_id {
+            warn!(context, "Restoring {contact_id} as member of {chat_id}.");
+            context
+                .sql
+                .execute(
+                    "INSERT INTO chats_contacts (chat_id, contact_id) VALUES (?, ?)",
+                    (chat_id, contact_id),
+                )
+                .await?;
+            repaired += 1;
+        } else if !context
+            .sql
+            .exists("SELECT COUNT(*) FROM msgs WHERE chat_id=?", (chat_id,))
+            .await?
+        {
+            warn!(context, "Deleting {chat_id} without members and messages.");
+            context
+                .sql
+                .execute("DELETE FROM chats WHERE id=?", (chat_id,))
+                .await?;
+            repaired += 1;
+        } else {
+            warn!(
+                context,
+                "{chat_id} has no members, but there is no incoming message to restore them from."
+            );
+        }
+    }
+    Ok(repaired)
+}
+
+/// Deletes reactions and jobs referring to messages or contacts that do not exist.
+async fn delete_orphans(context: &Context) -> Result<usize> {
+    let reactions = context
+        .sql
+        .execute(
+            "DELETE FROM reactions
+             WHERE msg_id NOT IN (SELECT id FROM msgs)
+             OR contact_id NOT IN (SELECT id FROM contacts)",
+            (),
+        )
+        .await?;
+    let smtp = context
+        .sql
+        .execute(
+            "DELETE FROM smtp WHERE msg_id NOT IN (SELECT id FROM msgs)",
+            (),
+        )
+        .await?;
+    let downloads = context
+        .sql
+        .execute(
+            "DELETE FROM download WHERE msg_id NOT IN (SELECT id FROM msgs)",
+            (),
+        )
+        .await?;
+    Ok(reactions + smtp + downloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat;
+    use crate::message::{Message, Viewtype};
+    use crate::sql::housekeeping;
+    use crate::test_utils::{TestContext, TestContextManager};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_check_integrity_noop() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+        t.send_text(chat.id, "Hi").await;
+        check_integrity(&t).await?;
+        check_missing_files(&t).await?;
+        while let Ok(event) = t.evtracker.try_recv() {
+            assert!(!matches!(event.typ, EventType::DatabaseRepaired { .. }));
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_check_integrity() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+
+        // A 1:1 chat that lost its member.
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = bob.send_text(bob.create_chat(&alice).await.id, "Hi").await;
+        assert_eq!(alice.recv_msg(&sent).await.chat_id, alice_chat.id);
+        alice
+            .sql
+            .execute(
+                "DELETE FROM chats_contacts WHERE chat_id=?",
+                (alice_chat.id,),
+            )
+            .await?;
+
+        alice
+            .sql
+            .execute(
+                "INSERT INTO download (msg_id) VALUES (?)",
+                (MsgId::new(12345),),
+            )
+            .await?;
+
+        check_integrity(&alice).await?;
+        let event = alice
+            .evtracker
+            .get_matching(|evt| matches!(evt, EventType::DatabaseRepaired { .. }))
+            .await;
+        assert_eq!(
+            event,
+            EventType::DatabaseRepaired {
+                missing_files: 0,
+                chats: 1,
+                orphans: 1,
+            }
+        );
+        let contacts = chat::get_chat_contacts(&alice, alice_chat.id).await?;
+        assert_eq!(contacts.len(), 1);
+
+        // Nothing is left to repair.
+        check_integrity(&alice).await?;
+        while let Ok(event) = alice.evtracker.try_recv() {
+            assert!(!matches!(event.typ, EventType::DatabaseRepaired { .. }));
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_check_missing_files() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+
+        // A file deleted outside of the app.
+        let file = t.get_blobdir().join("hello.txt");
+        tokio::fs::write(&file, "hello").await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+        let msg_id = t.send_msg(chat.id, &mut msg).await.sender_msg_id;
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        tokio::fs::remove_file(msg.get_file(&t).unwrap()).await?;
+
+        // The blob directory is not scanned when opening the database.
+        check_integrity(&t).await?;
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.error(), None);
+
+        housekeeping(&t).await?;
+        let event = t
+            .evtracker
+            .get_matching(|evt| matches!(evt, EventType::DatabaseRepaired { .. }))
+            .await;
+        assert_eq!(
+            event,
+            EventType::DatabaseRepaired {
+                missing_files: 1,
+                chats: 0,
+                orphans: 0,
+            }
+        );
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.error(), Some(stock_str::file_missing(&t).await));
+        Ok(())
+    }
+}
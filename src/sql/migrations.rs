@@ -1,14 +1,19 @@
 //! Migrations module.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
 use anyhow::{Context as _, Result};
 
+use crate::chat::ChatId;
 use crate::config::Config;
-use crate::constants::ShowEmails;
+use crate::constants::{Chattype, ShowEmails, DC_CHAT_ID_LAST_SPECIAL};
+use crate::contact::ContactId;
 use crate::context::Context;
 use crate::imap;
+use crate::message::MessageState;
 use crate::provider::get_provider_by_domain;
 use crate::sql::Sql;
-use crate::tools::EmailAddress;
+use crate::tools::{remove_subject_prefix, EmailAddress};
 
 const DBVERSION: i32 = 68;
 const VERSION_CFG: &str = "dbversion";
@@ -750,6 +755,203 @@ pub async fn run(context: &Context, sql: &Sql) -> Result<(bool, bool, bool, bool
         .await?;
     }
 
+    if dbversion < 104 {
+        sql.execute_migration(
+            "ALTER TABLE smtp ADD COLUMN next_try INTEGER NOT NULL DEFAULT 0; -- timestamp of the next attempt
+             ALTER TABLE smtp ADD COLUMN last_error TEXT NOT NULL DEFAULT ''; -- error of the last failed attempt
+             ALTER TABLE smtp_mdns ADD COLUMN next_try INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE smtp_mdns ADD COLUMN last_error TEXT NOT NULL DEFAULT '';",
+            104,
+        )
+        .await?;
+    }
+
+    if dbversion < 105 {
+        sql.execute_migration(
+            "CREATE TABLE dkim_keys (
+               name TEXT PRIMARY KEY, -- selector._domainkey.domain
+               record TEXT NOT NULL, -- TXT record, empty if there is none
+               timestamp INTEGER NOT NULL
+             )",
+            105,
+        )
+        .await?;
+    }
+    if dbversion < 106 {
+        sql.execute_migration(
+            "ALTER TABLE chats ADD COLUMN spam INTEGER NOT NULL DEFAULT 0",
+            106,
+        )
+        .await?;
+    }
+    if dbversion < 107 {
+        sql.execute_migration(
+            "ALTER TABLE smtp ADD COLUMN deadline INTEGER NOT NULL DEFAULT 0; -- 0 = no deadline",
+            107,
+        )
+        .await?;
+    }
+    if dbversion < 108 {
+        sql.execute_migration(
+            "ALTER TABLE contacts ADD COLUMN last_seen_folder TEXT NOT NULL DEFAULT ''; -- folder of the last incoming message
+             ALTER TABLE contacts ADD COLUMN last_encrypted INTEGER NOT NULL DEFAULT 0; -- timestamp of the last encrypted message
+             ALTER TABLE contacts ADD COLUMN last_unencrypted INTEGER NOT NULL DEFAULT 0; -- timestamp of the last unencrypted message
+             ALTER TABLE contacts ADD COLUMN last_verified INTEGER NOT NULL DEFAULT 0; -- timestamp of the last message signed with a verified key",
+            108,
+        )
+        .await?;
+    }
+    if dbversion < 109 {
+        sql.execute_migration(
+            "CREATE TABLE undo_queue (
+               id INTEGER PRIMARY KEY AUTOINCREMENT,
+               action TEXT NOT NULL, -- JSON-serialized pending operation
+               deadline INTEGER NOT NULL -- time after which the operation is executed
+             );",
+            109,
+        )
+        .await?;
+    }
+    if dbversion < 110 {
+        // Speeds up message search filtered by sender.
+        sql.execute_migration(
+            "CREATE INDEX IF NOT EXISTS msgs_index9 ON msgs (from_id, timestamp);",
+            110,
+        )
+        .await?;
+    }
+    if dbversion < 111 {
+        sql.execute_migration(
+            "CREATE TABLE msgs_audit (
+               id INTEGER PRIMARY KEY AUTOINCREMENT,
+               rfc724_mid TEXT NOT NULL, -- Message-ID of the message the operation was done on
+               timestamp INTEGER NOT NULL,
+               operation TEXT NOT NULL -- human-readable description of the operation
+             );
+             CREATE INDEX msgs_audit_index1 ON msgs_audit (rfc724_mid);",
+            111,
+        )
+        .await?;
+    }
+    if dbversion < 112 {
+        sql.execute_migration(
+            "CREATE TABLE drafts_sync (
+               chat_id INTEGER PRIMARY KEY -- chat with a local draft change to be uploaded
+             );
+             CREATE TABLE imap_drafts (
+               rfc724_mid TEXT PRIMARY KEY, -- Message-ID of a draft version on the server
+               chat_id INTEGER NOT NULL
+             );
+             CREATE INDEX imap_drafts_index1 ON imap_drafts (chat_id);",
+            112,
+        )
+        .await?;
+    }
+    if dbversion < 113 {
+        sql.execute_migration(
+            "ALTER TABLE imap ADD COLUMN internaldate INTEGER NOT NULL DEFAULT 0; -- INTERNALDATE reported by the server
+             ALTER TABLE msgs ADD COLUMN timestamp_server INTEGER NOT NULL DEFAULT 0;",
+            113,
+        )
+        .await?;
+    }
+
+    if dbversion < 114 {
+        sql.execute_migration(
+            "ALTER TABLE msgs ADD COLUMN encrypted_at_rest INTEGER NOT NULL DEFAULT 0;",
+            114,
+        )
+        .await?;
+    }
+
+    if dbversion < 115 {
+        sql.execute_migration(
+            "ALTER TABLE contacts ADD COLUMN last_auto_reply INTEGER NOT NULL DEFAULT 0;",
+            115,
+        )
+        .await?;
+    }
+
+    if dbversion < 116 {
+        sql.execute_migration(
+            "ALTER TABLE contacts ADD COLUMN remote_content INTEGER NOT NULL DEFAULT 0;",
+            116,
+        )
+        .await?;
+    }
+    if dbversion < 117 {
+        sql.execute_migration(
+            "ALTER TABLE chats ADD COLUMN ui_settings TEXT NOT NULL DEFAULT '';",
+            117,
+        )
+        .await?;
+    }
+    if dbversion < 118 {
+        sql.execute_migration(
+            "ALTER TABLE chats ADD COLUMN last_error TEXT NOT NULL DEFAULT '';
+             ALTER TABLE contacts ADD COLUMN last_error TEXT NOT NULL DEFAULT '';",
+            118,
+        )
+        .await?;
+    }
+    if dbversion < 119 {
+        sql.execute_migration(
+            "ALTER TABLE undo_queue ADD COLUMN chat_id INTEGER NOT NULL DEFAULT 0; -- chat hidden until its deletion is executed
+             CREATE TABLE undo_msgs (
+               undo_id INTEGER NOT NULL, -- operation in undo_queue
+               msg_id INTEGER NOT NULL -- message kept until the operation is executed
+             );
+             CREATE INDEX undo_msgs_index1 ON undo_msgs (msg_id);",
+            119,
+        )
+        .await?;
+    }
+    if dbversion < 120 {
+        sql.execute_migration(
+            "CREATE TABLE imap_moves (
+               rfc724_mid TEXT NOT NULL, -- Message-ID of the moved message
+               target TEXT NOT NULL, -- folder the message was moved to
+               timestamp INTEGER NOT NULL, -- time of the last move
+               PRIMARY KEY(rfc724_mid, target)
+             );",
+            120,
+        )
+        .await?;
+    }
+    if dbversion < 121 {
+        sql.execute_migration(
+            "ALTER TABLE download ADD COLUMN retries INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE download ADD COLUMN next_try INTEGER NOT NULL DEFAULT 0; -- timestamp of the next attempt
+             ALTER TABLE download ADD COLUMN last_error TEXT NOT NULL DEFAULT ''; -- error of the last failed attempt",
+            121,
+        )
+        .await?;
+    }
+    if dbversion < 122 {
+        sql.execute_migration(
+            "ALTER TABLE msgs ADD COLUMN html TEXT NOT NULL DEFAULT ''; -- sanitized HTML, compressed",
+            122,
+        )
+        .await?;
+    }
+    if dbversion < 123 {
+        sql.transaction(merge_adhoc_groups).await?;
+        sql.set_db_version(123).await?;
+    }
+    if dbversion < 124 {
+        // Only the first part of a message is unique,
+        // all parts of a received message share the Message-ID.
+        // Parts of existing messages are numbered in the order of their IDs.
+        sql.execute_migration(
+            "ALTER TABLE msgs ADD COLUMN part INTEGER NOT NULL DEFAULT 0; -- index of the part in the received message
+             UPDATE msgs SET part=(SELECT COUNT(*) FROM msgs m WHERE m.rfc724_mid=msgs.rfc724_mid AND m.id<msgs.id)
+             WHERE rfc724_mid!='';
+             CREATE UNIQUE INDEX msgs_index10 ON msgs (rfc724_mid) WHERE part=0 AND rfc724_mid!='';",
+            124,
+        )
+        .await?;
+    }
+
     let new_version = sql
         .get_raw_config_int(VERSION_CFG)
         .await?
@@ -774,6 +976,91 @@ pub async fn run(context: &Context, sql: &Sql) -> Result<(bool, bool, bool, bool
     ))
 }
 
+/// Everything that must be equal for duplicate ad-hoc groups to be merged:
+/// the subject without `Re:`-like prefixes, all settings of the chat and the members.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct AdhocGroupKey {
+    name: String,
+    settings: (i64, i64, i64, i64, i64, i64, i64, String, String),
+    members: BTreeSet<ContactId>,
+}
+
+/// Merges ad-hoc groups into the oldest group having the same members and subject.
+///
+/// Such duplicates were created when messages without references to earlier messages
+/// were received, e.g. with a different order of recipients.
+/// New messages are assigned to the existing group by `lookup_adhoc_group()`.
+///
+/// Only groups with the same settings are merged, so that no setting is lost
+/// and messages are not moved out of blocked or muted chats.
+/// Groups with a draft or a pending undoable operation are not touched.
+fn merge_adhoc_groups(transaction: &mut rusqlite::Transaction) -> Result<()> {
+    let mut groups: BTreeMap<ChatId, AdhocGroupKey> = BTreeMap::new();
+    {
+        let mut stmt = transaction.prepare(
+            "SELECT c.id, IFNULL(c.name, ''),
+                    IFNULL(c.blocked, 0), IFNULL(c.archived, 0), IFNULL(c.muted_until, 0),
+                    IFNULL(c.ephemeral_timer, 0), IFNULL(c.protected, 0), c.spam,
+                    IFNULL(c.locations_send_until, 0), IFNULL(c.param, ''), c.ui_settings,
+                    cc.contact_id
+             FROM chats c
+             INNER JOIN chats_contacts cc ON cc.chat_id=c.id
+             WHERE c.type=? AND c.grpid='' AND c.id>?
+             AND NOT EXISTS (SELECT 1 FROM msgs m WHERE m.chat_id=c.id AND m.state=?)
+             AND NOT EXISTS (SELECT 1 FROM undo_queue u WHERE u.chat_id=c.id)",
+        )?;
+        let mut rows = stmt.query((
+            Chattype::Group,
+            DC_CHAT_ID_LAST_SPECIAL,
+            MessageState::OutDraft,
+        ))?;
+        while let Some(row) = rows.next()? {
+            let chat_id: ChatId = row.get(0)?;
+            let name: String = row.get(1)?;
+            let settings = (
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+                row.get(7)?,
+                row.get(8)?,
+                row.get(9)?,
+                row.get(10)?,
+            );
+            let contact_id: ContactId = row.get(11)?;
+            groups
+                .entry(chat_id)
+                .or_insert_with(|| AdhocGroupKey {
+                    name: remove_subject_prefix(&name),
+                    settings,
+                    members: BTreeSet::new(),
+                })
+                .members
+                .insert(contact_id);
+        }
+    }
+
+    // Chats are iterated in the order of their IDs, so the oldest one is kept.
+    let mut kept: HashMap<AdhocGroupKey, ChatId> = HashMap::new();
+    for (chat_id, key) in groups {
+        let Some(&keep_id) = kept.get(&key) else {
+            kept.insert(key, chat_id);
+            continue;
+        };
+        for table in ["msgs", "locations", "imap_drafts"] {
+            transaction.execute(
+                &format!("UPDATE {table} SET chat_id=? WHERE chat_id=?"),
+                (keep_id, chat_id),
+            )?;
+        }
+        transaction.execute("DELETE FROM drafts_sync WHERE chat_id=?", (chat_id,))?;
+        transaction.execute("DELETE FROM chats_contacts WHERE chat_id=?", (chat_id,))?;
+        transaction.execute("DELETE FROM chats WHERE id=?", (chat_id,))?;
+    }
+    Ok(())
+}
+
 impl Sql {
     async fn set_db_version(&self, version: i32) -> Result<()> {
         self.set_raw_config_int(VERSION_CFG, version).await?;
@@ -806,3 +1093,68 @@ async fn execute_migration(&self, query: &str, version: i32) -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{self, Chat, ChatVisibility, ProtectionStatus};
+    use crate::constants::Blocked;
+    use crate::contact::Contact;
+    use crate::message::{Message, Viewtype};
+    use crate::test_utils::TestContext;
+
+    async fn create_adhoc_group(
+        t: &TestContext,
+        name: &str,
+        member_ids: &[ContactId],
+    ) -> Result<ChatId> {
+        let chat_id = ChatId::create_multiuser_record(
+            t,
+            Chattype::Group,
+            "",
+            name,
+            Blocked::Not,
+            ProtectionStatus::Unprotected,
+            None,
+        )
+        .await?;
+        chat::add_to_chat_contacts_table(t, chat_id, member_ids).await?;
+        chat::add_info_msg(t, chat_id, name, 0).await?;
+        Ok(chat_id)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_merge_adhoc_groups() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t, "", "bob@example.net").await?;
+        let claire_id = Contact::create(&t, "", "claire@example.net").await?;
+        let dave_id = Contact::create(&t, "", "dave@example.net").await?;
+        let members = [ContactId::SELF, bob_id, claire_id];
+
+        let kept = create_adhoc_group(&t, "Hello", &members).await?;
+        let duplicate =
+            create_adhoc_group(&t, "Re: Hello", &[claire_id, ContactId::SELF, bob_id]).await?;
+        let other_subject = create_adhoc_group(&t, "Bye", &members).await?;
+        let other_members =
+            create_adhoc_group(&t, "Hello", &[ContactId::SELF, bob_id, dave_id]).await?;
+        let archived = create_adhoc_group(&t, "Hello", &members).await?;
+        archived
+            .set_visibility(&t, ChatVisibility::Archived)
+            .await?;
+        let with_draft = create_adhoc_group(&t, "Hello", &members).await?;
+        let mut draft = Message::new(Viewtype::Text);
+        draft.set_text("Draft".to_string());
+        with_draft.set_draft(&t, Some(&mut draft)).await?;
+
+        t.sql.transaction(merge_adhoc_groups).await?;
+
+        // The messages of the duplicate are moved to the oldest group.
+        assert_eq!(kept.get_msg_cnt(&t).await?, 2);
+        assert!(Chat::load_from_db(&t, duplicate).await.is_err());
+        assert_eq!(chat::get_chat_contacts(&t, kept).await?.len(), 3);
+        for chat_id in [other_subject, other_members, archived, with_draft] {
+            assert_eq!(chat_id.get_msg_cnt(&t).await?, 1);
+        }
+        Ok(())
+    }
+}
@@ -5,6 +5,8 @@
 
 use anyhow::{bail, Result};
 use humansize::{format_size, BINARY};
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
 use strum::EnumProperty as EnumPropertyTrait;
 use strum_macros::EnumProperty;
 use tokio::sync::RwLock;
@@ -12,7 +14,7 @@
 use crate::accounts::Accounts;
 use crate::blob::BlobObject;
 use crate::chat::{self, Chat, ChatId, ProtectionStatus};
-use crate::config::Config;
+use crate::config::{Config, InternalConfig};
 use crate::contact::{Contact, ContactId, Origin};
 use crate::context::Context;
 use crate::message::{Message, Viewtype};
@@ -22,8 +24,44 @@
 /// Storage for string translations.
 #[derive(Debug, Clone)]
 pub struct StockStrings {
+    /// Translations in the order of preference.
+    ///
+    /// If a string is not translated into the first language,
+    /// the next one is tried, falling back to the English default in the end.
+    translated_stockstrings: Arc<RwLock<Vec<StockTranslation>>>,
+}
+
+/// Translation of the stock strings into one language.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StockTranslation {
+    /// BCP 47 language tag, e.g. `de` or `pt-BR`.
+    ///
+    /// Selects the plural rules, see [`plural_category`].
+    pub lang: String,
+
     /// Map from stock string ID to the translation.
-    translated_stockstrings: Arc<RwLock<HashMap<usize, String>>>,
+    #[serde(default)]
+    pub strings: HashMap<u32, String>,
+
+    /// Map from stock string ID to the plural forms of the translation.
+    ///
+    /// Used for strings containing a count, e.g. `%1$s hours`.
+    /// The [`PluralCategory::Other`] form is used if the form for the category is missing.
+    #[serde(default)]
+    pub plurals: HashMap<u32, HashMap<PluralCategory, String>>,
+}
+
+/// Plural category of a count as defined by the Unicode CLDR.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluralCategory {
+    Zero,
+    One,
+    Two,
+    Few,
+    Many,
+    Other,
 }
 
 /// Stock strings
@@ -413,6 +451,58 @@ pub enum StockMessage {
 
     #[strum(props(fallback = "%1$s sent a message from another device."))]
     ChatProtectionDisabled = 171,
+
+    #[strum(props(fallback = "You unsubscribed from this mailing list."))]
+    UnsubscribedFromMailinglist = 172,
+
+    #[strum(props(fallback = "This message is damaged and could not be displayed completely."))]
+    DamagedMsgBody = 173,
+
+    #[strum(props(fallback = "Last Error"))]
+    LastError = 174,
+
+    #[strum(props(fallback = "Push Notifications"))]
+    PushNotifications = 175,
+
+    #[strum(props(fallback = "Push notifications are not enabled on this device."))]
+    PushNotificationsDisabled = 176,
+
+    #[strum(props(fallback = "You changed the group admins."))]
+    MsgYouChangedGrpAdmins = 177,
+
+    #[strum(props(fallback = "Group admins changed by %1$s."))]
+    MsgGrpAdminsChangedBy = 178,
+
+    #[strum(props(fallback = "New message"))]
+    NewMsgNotification = 179,
+
+    #[strum(props(
+        fallback = "%1$s does not offer IMAP access, only POP3 which is not supported by Delta Chat."
+    ))]
+    ConfigurePopOnly = 180,
+
+    #[strum(props(
+        fallback = "The sender address of this message could not be verified, it may be forged."
+    ))]
+    SenderNotVerified = 181,
+
+    #[strum(props(fallback = "Message could not be parsed completely: %1$s"))]
+    MsgNotParsedCompletely = 182,
+
+    #[strum(props(fallback = "Message expired before it could be delivered."))]
+    MsgDeliveryExpired = 183,
+
+    #[strum(props(fallback = "Looking up the configuration at %1$s."))]
+    ConfigureLookup = 184,
+
+    #[strum(props(fallback = "Looking up the DNS SRV records of %1$s."))]
+    ConfigureSrvLookup = 185,
+
+    #[strum(props(fallback = "No configuration found: %1$s"))]
+    ConfigureLookupFailed = 186,
+
+    #[strum(props(fallback = "The file of this message is missing."))]
+    FileMissing = 187,
 }
 
 impl StockMessage {
@@ -442,39 +532,317 @@ async fn translated(&self, id: StockMessage) -> String {
         self.translated_stockstrings
             .read()
             .await
-            .get(&(id as usize))
+            .iter()
+            .find_map(|translation| translation.strings.get(&(id as u32)))
             .map(AsRef::as_ref)
             .unwrap_or_else(|| id.fallback())
             .to_string()
     }
 
+    /// Returns the translation of a string containing `count`
+    /// in the plural form matching the count.
+    async fn translated_plural(&self, id: StockMessage, count: f64) -> String {
+        let translations = self.translated_stockstrings.read().await;
+        for translation in translations.iter() {
+            if let Some(forms) = translation.plurals.get(&(id as u32)) {
+                let category = plural_category(&translation.lang, count);
+                if let Some(form) = forms
+                    .get(&category)
+                    .or_else(|| forms.get(&PluralCategory::Other))
+                {
+                    return form.to_string();
+                }
+            }
+            if let Some(string) = translation.strings.get(&(id as u32)) {
+                return string.to_string();
+            }
+        }
+        id.fallback().to_string()
+    }
+
     async fn set_stock_translation(&self, id: StockMessage, stockstring: String) -> Result<()> {
-        if stockstring.contains("%1") && !id.fallback().contains("%1") {
-            bail!(
-                "translation {} contains invalid %1 placeholder, default is {}",
-                stockstring,
-                id.fallback()
-            );
+        check_placeholders(id, &stockstring)?;
+        let mut translations = self.translated_stockstrings.write().await;
+        if translations.is_empty() {
+            translations.push(StockTranslation::default());
         }
-        if stockstring.contains("%2") && !id.fallback().contains("%2") {
-            bail!(
-                "translation {} contains invalid %2 placeholder, default is {}",
-                stockstring,
-                id.fallback()
+        if let Some(translation) = translations.first_mut() {
+            translation.strings.insert(id as u32, stockstring);
+        }
+        Ok(())
+    }
+
+    async fn set_stock_translations(&self, translations: Vec<StockTranslation>) -> Result<()> {
+        for translation in &translations {
+            let strings = translation.strings.iter().chain(
+                translation
+                    .plurals
+                    .iter()
+                    .flat_map(|(id, forms)| forms.values().map(move |form| (id, form))),
             );
+            for (id, stockstring) in strings {
+                let Some(id) = StockMessage::from_u32(*id) else {
+                    bail!("Invalid stock message ID {id}");
+                };
+                check_placeholders(id, stockstring)?;
+            }
         }
-        self.translated_stockstrings
-            .write()
-            .await
-            .insert(id as usize, stockstring);
+        *self.translated_stockstrings.write().await = translations;
         Ok(())
     }
 }
 
+fn check_placeholders(id: StockMessage, stockstring: &str) -> Result<()> {
+    if stockstring.contains("%1") && !id.fallback().contains("%1") {
+        bail!(
+            "translation {} contains invalid %1 placeholder, default is {}",
+            stockstring,
+            id.fallback()
+        );
+    }
+    if stockstring.contains("%2") && !id.fallback().contains("%2") {
+        bail!(
+            "translation {} contains invalid %2 placeholder, default is {}",
+            stockstring,
+            id.fallback()
+        );
+    }
+    Ok(())
+}
+
+/// Plural rules of the Unicode CLDR for whole numbers, see
+/// <https://www.unicode.org/cldr/charts/latest/supplemental/language_plural_rules.html>.
+///
+/// Each entry lists the languages sharing a rule, as grouped in the CLDR `plurals.xml`.
+/// Regional variants with own rules, e.g. `pt-PT`, are listed with their full tag.
+const PLURAL_RULES: &[(&[&str], fn(u64) -> PluralCategory)] = {
+    use PluralCategory::{Few, Many, One, Other, Two, Zero};
+
+    &[
+        (
+            &[
+                "bm", "bo", "dz", "hnj", "id", "ig", "ii", "in", "ja", "jbo", "jv", "jw", "kde",
+                "kea", "km", "ko", "lkt", "lo", "ms", "my", "nqo", "osa", "sah", "ses", "sg", "su",
+                "th", "to", "tpi", "vi", "wo", "yo", "yue", "zh",
+            ],
+            |_| Other,
+        ),
+        (
+            &[
+                "am", "as", "bn", "doi", "fa", "ff", "gu", "hi", "hy", "kab", "kn", "pcm", "zu",
+            ],
+            |n| if n <= 1 { One } else { Other },
+        ),
+        (&["fr", "pt"], |n| match n {
+            0 | 1 => One,
+            _ if n % 1_000_000 == 0 => Many,
+            _ => Other,
+        }),
+        (
+            &["ca", "es", "it", "lld", "pt-pt", "scn", "vec"],
+            |n| match n {
+                1 => One,
+                _ if n != 0 && n % 1_000_000 == 0 => Many,
+                _ => Other,
+            },
+        ),
+        (&["da", "is", "mk"], |n| {
+            if n % 10 == 1 && n % 100 != 11 {
+                One
+            } else {
+                Other
+            }
+        }),
+        (&["ceb", "fil", "tl"], |n| match n % 10 {
+            4 | 6 | 9 => Other,
+            _ => One,
+        }),
+        (&["lv", "prg"], |n| {
+            if n % 10 == 0 || (11..=19).contains(&(n % 100)) {
+                Zero
+            } else if n % 10 == 1 {
+                One
+            } else {
+                Other
+            }
+        }),
+        (&["ksh"], |n| match n {
+            0 => Zero,
+            1 => One,
+            _ => Other,
+        }),
+        (&["lag"], |n| match n {
+            0 => Zero,
+            1 => One,
+            _ => Other,
+        }),
+        (
+            &[
+                "he", "iu", "naq", "sat", "se", "sma", "smi", "smj", "smn", "sms",
+            ],
+            |n| match n {
+                1 => One,
+                2 => Two,
+                _ => Other,
+            },
+        ),
+        (&["shi"], |n| match n {
+            0 | 1 => One,
+            2..=10 => Few,
+            _ => Other,
+        }),
+        (&["mo", "ro"], |n| match n {
+            1 => One,
+            _ if n == 0 || (1..=19).contains(&(n % 100)) => Few,
+            _ => Other,
+        }),
+        (&["bs", "hr", "sh", "sr"], |n| {
+            if n % 10 == 1 && n % 100 != 11 {
+                One
+            } else if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) {
+                Few
+            } else {
+                Other
+            }
+        }),
+        (&["dsb", "hsb", "sl"], |n| match n % 100 {
+            1 => One,
+            2 => Two,
+            3 | 4 => Few,
+            _ => Other,
+        }),
+        (&["cs", "sk"], |n| match n {
+            1 => One,
+            2..=4 => Few,
+            _ => Other,
+        }),
+        (&["pl"], |n| {
+            if n == 1 {
+                One
+            } else if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) {
+                Few
+            } else {
+                Many
+            }
+        }),
+        (&["be", "ru", "uk"], |n| {
+            if n % 10 == 1 && n % 100 != 11 {
+                One
+            } else if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) {
+                Few
+            } else {
+                Many
+            }
+        }),
+        (&["lt"], |n| {
+            if (11..=19).contains(&(n % 100)) {
+                Other
+            } else if n % 10 == 1 {
+                One
+            } else if n % 10 >= 2 {
+                Few
+            } else {
+                Other
+            }
+        }),
+        (&["gd"], |n| match n {
+            1 | 11 => One,
+            2 | 12 => Two,
+            3..=10 | 13..=19 => Few,
+            _ => Other,
+        }),
+        (&["br"], |n| {
+            let (n10, n100) = (n % 10, n % 100);
+            if n10 == 1 && ![11, 71, 91].contains(&n100) {
+                One
+            } else if n10 == 2 && ![12, 72, 92].contains(&n100) {
+                Two
+            } else if [3, 4, 9].contains(&n10)
+                && !(10..=19).contains(&n100)
+                && !(70..=79).contains(&n100)
+                && !(90..=99).contains(&n100)
+            {
+                Few
+            } else if n != 0 && n % 1_000_000 == 0 {
+                Many
+            } else {
+                Other
+            }
+        }),
+        (&["mt"], |n| match n {
+            1 => One,
+            2 => Two,
+            _ if n == 0 || (3..=10).contains(&(n % 100)) => Few,
+            _ if (11..=19).contains(&(n % 100)) => Many,
+            _ => Other,
+        }),
+        (&["ga"], |n| match n {
+            1 => One,
+            2 => Two,
+            3..=6 => Few,
+            7..=10 => Many,
+            _ => Other,
+        }),
+        (&["gv"], |n| match n % 10 {
+            1 => One,
+            2 => Two,
+            _ if n % 20 == 0 => Few,
+            _ => Other,
+        }),
+        (&["ar", "ars"], |n| match n {
+            0 => Zero,
+            1 => One,
+            2 => Two,
+            _ if (3..=10).contains(&(n % 100)) => Few,
+            _ if (11..=99).contains(&(n % 100)) => Many,
+            _ => Other,
+        }),
+        (&["cy"], |n| match n {
+            0 => Zero,
+            1 => One,
+            2 => Two,
+            3 => Few,
+            6 => Many,
+            _ => Other,
+        }),
+    ]
+};
+
+/// Returns the CLDR plural category of `count` in the language `lang`, see [`PLURAL_RULES`].
+///
+/// Only the rules for whole numbers are implemented, fractions are always [`PluralCategory::Other`].
+/// Languages not listed in [`PLURAL_RULES`] have a singular for 1 only, like English.
+pub fn plural_category(lang: &str, count: f64) -> PluralCategory {
+    if count.fract() != 0.0 || count < 0.0 {
+        return PluralCategory::Other;
+    }
+    let n = count as u64;
+    let lang = lang.replace('_', "-").to_ascii_lowercase();
+    let primary = lang.split('-').next().unwrap_or_default();
+    let rule = [lang.as_str(), primary].into_iter().find_map(|tag| {
+        PLURAL_RULES
+            .iter()
+            .find(|(langs, _)| langs.contains(&tag))
+            .map(|(_, rule)| rule)
+    });
+    match rule {
+        Some(rule) => rule(n),
+        None if n == 1 => PluralCategory::One,
+        None => PluralCategory::Other,
+    }
+}
+
 async fn translated(context: &Context, id: StockMessage) -> String {
     context.translated_stockstrings.translated(id).await
 }
 
+async fn translated_plural(context: &Context, id: StockMessage, count: f64) -> String {
+    context
+        .translated_stockstrings
+        .translated_plural(id, count)
+        .await
+}
+
 /// Helper trait only meant to be implemented for [`String`].
 trait StockStringMods: AsRef<str> + Sized {
     /// Substitutes the first replacement value if one is present.
@@ -599,6 +967,17 @@ pub(crate) async fn msg_grp_img_changed(context: &Context, by_contact: ContactId
     }
 }
 
+/// Stock string: `You changed the group admins.` or `Group admins changed by %1$s.`.
+pub(crate) async fn msg_grp_admins_changed(context: &Context, by_contact: ContactId) -> String {
+    if by_contact == ContactId::SELF {
+        translated(context, StockMessage::MsgYouChangedGrpAdmins).await
+    } else {
+        translated(context, StockMessage::MsgGrpAdminsChangedBy)
+            .await
+            .replace1(&by_contact.get_stock_name_n_addr(context).await)
+    }
+}
+
 /// Stock string: `I added member %1$s.`.
 ///
 /// The `added_member_addr` parameter should be an email address and is looked up in the
@@ -1063,6 +1442,16 @@ pub(crate) async fn chat_protection_disabled(context: &Context, contact_id: Cont
         .replace1(&contact_id.get_stock_name(context).await)
 }
 
+/// Stock string: `You unsubscribed from this mailing list.`
+pub(crate) async fn unsubscribed_from_mailinglist(context: &Context) -> String {
+    translated(context, StockMessage::UnsubscribedFromMailinglist).await
+}
+
+/// Stock string: `This message is damaged and could not be displayed completely.`
+pub(crate) async fn damaged_msg_body(context: &Context) -> String {
+    translated(context, StockMessage::DamagedMsgBody).await
+}
+
 /// Stock string: `Reply`.
 pub(crate) async fn reply_noun(context: &Context) -> String {
     translated(context, StockMessage::ReplyNoun).await
@@ -1081,17 +1470,17 @@ pub(crate) async fn delete_server_turned_off(context: &Context) -> String {
 /// Stock string: `Message deletion timer is set to %1$s minutes.`.
 pub(crate) async fn msg_ephemeral_timer_minutes(
     context: &Context,
-    minutes: &str,
+    minutes: f64,
     by_contact: ContactId,
 ) -> String {
     if by_contact == ContactId::SELF {
-        translated(context, StockMessage::MsgYouEphemeralTimerMinutes)
+        translated_plural(context, StockMessage::MsgYouEphemeralTimerMinutes, minutes)
             .await
-            .replace1(minutes)
+            .replace1(&minutes.to_string())
     } else {
-        translated(context, StockMessage::MsgEphemeralTimerMinutesBy)
+        translated_plural(context, StockMessage::MsgEphemeralTimerMinutesBy, minutes)
             .await
-            .replace1(minutes)
+            .replace1(&minutes.to_string())
             .replace2(&by_contact.get_stock_name_n_addr(context).await)
     }
 }
@@ -1099,17 +1488,17 @@ pub(crate) async fn msg_ephemeral_timer_minutes(
 /// Stock string: `Message deletion timer is set to %1$s hours.`.
 pub(crate) async fn msg_ephemeral_timer_hours(
     context: &Context,
-    hours: &str,
+    hours: f64,
     by_contact: ContactId,
 ) -> String {
     if by_contact == ContactId::SELF {
-        translated(context, StockMessage::MsgYouEphemeralTimerHours)
+        translated_plural(context, StockMessage::MsgYouEphemeralTimerHours, hours)
             .await
-            .replace1(hours)
+            .replace1(&hours.to_string())
     } else {
-        translated(context, StockMessage::MsgEphemeralTimerHoursBy)
+        translated_plural(context, StockMessage::MsgEphemeralTimerHoursBy, hours)
             .await
-            .replace1(hours)
+            .replace1(&hours.to_string())
             .replace2(&by_contact.get_stock_name_n_addr(context).await)
     }
 }
@@ -1117,17 +1506,17 @@ pub(crate) async fn msg_ephemeral_timer_hours(
 /// Stock string: `Message deletion timer is set to %1$s days.`.
 pub(crate) async fn msg_ephemeral_timer_days(
     context: &Context,
-    days: &str,
+    days: f64,
     by_contact: ContactId,
 ) -> String {
     if by_contact == ContactId::SELF {
-        translated(context, StockMessage::MsgYouEphemeralTimerDays)
+        translated_plural(context, StockMessage::MsgYouEphemeralTimerDays, days)
             .await
-            .replace1(days)
+            .replace1(&days.to_string())
     } else {
-        translated(context, StockMessage::MsgEphemeralTimerDaysBy)
+        translated_plural(context, StockMessage::MsgEphemeralTimerDaysBy, days)
             .await
-            .replace1(days)
+            .replace1(&days.to_string())
             .replace2(&by_contact.get_stock_name_n_addr(context).await)
     }
 }
@@ -1135,17 +1524,17 @@ pub(crate) async fn msg_ephemeral_timer_days(
 /// Stock string: `Message deletion timer is set to %1$s weeks.`.
 pub(crate) async fn msg_ephemeral_timer_weeks(
     context: &Context,
-    weeks: &str,
+    weeks: f64,
     by_contact: ContactId,
 ) -> String {
     if by_contact == ContactId::SELF {
-        translated(context, StockMessage::MsgYouEphemeralTimerWeeks)
+        translated_plural(context, StockMessage::MsgYouEphemeralTimerWeeks, weeks)
             .await
-            .replace1(weeks)
+            .replace1(&weeks.to_string())
     } else {
-        translated(context, StockMessage::MsgEphemeralTimerWeeksBy)
+        translated_plural(context, StockMessage::MsgEphemeralTimerWeeksBy, weeks)
             .await
-            .replace1(weeks)
+            .replace1(&weeks.to_string())
             .replace2(&by_contact.get_stock_name_n_addr(context).await)
     }
 }
@@ -1239,6 +1628,77 @@ pub(crate) async fn not_supported_by_provider(context: &Context) -> String {
     translated(context, StockMessage::NotSupportedByProvider).await
 }
 
+/// Stock string: `Last Error`.
+pub(crate) async fn last_error(context: &Context) -> String {
+    translated(context, StockMessage::LastError).await
+}
+
+/// Stock string: `Push Notifications`.
+pub(crate) async fn push_notifications(context: &Context) -> String {
+    translated(context, StockMessage::PushNotifications).await
+}
+
+/// Stock string: `Push notifications are not enabled on this device.`.
+pub(crate) async fn push_notifications_disabled(context: &Context) -> String {
+    translated(context, StockMessage::PushNotificationsDisabled).await
+}
+
+/// Stock string: `New message`.
+/// Used in notifications hiding the message content.
+pub(crate) async fn new_msg_notification(context: &Context) -> String {
+    translated(context, StockMessage::NewMsgNotification).await
+}
+
+/// Stock string: `%1$s does not offer IMAP access, only POP3 which is not supported by Delta Chat.`
+pub(crate) async fn configure_pop_only(context: &Context, domain: &str) -> String {
+    translated(context, StockMessage::ConfigurePopOnly)
+        .await
+        .replace1(domain)
+}
+
+/// Stock string: `The sender address of this message could not be verified, it may be forged.`
+pub(crate) async fn sender_not_verified(context: &Context) -> String {
+    translated(context, StockMessage::SenderNotVerified).await
+}
+
+/// Stock string: `Message could not be parsed completely: %1$s`.
+pub(crate) async fn msg_not_parsed_completely(context: &Context, error: &str) -> String {
+    translated(context, StockMessage::MsgNotParsedCompletely)
+        .await
+        .replace1(error)
+}
+
+/// Stock string: `Message expired before it could be delivered.`.
+pub(crate) async fn msg_delivery_expired(context: &Context) -> String {
+    translated(context, StockMessage::MsgDeliveryExpired).await
+}
+
+/// Stock string: `Looking up the configuration at %1$s.`.
+pub(crate) async fn configure_lookup(context: &Context, url: &str) -> String {
+    translated(context, StockMessage::ConfigureLookup)
+        .await
+        .replace1(url)
+}
+
+/// Stock string: `Looking up the DNS SRV records of %1$s.`.
+pub(crate) async fn configure_srv_lookup(context: &Context, domain: &str) -> String {
+    translated(context, StockMessage::ConfigureSrvLookup)
+        .await
+        .replace1(domain)
+}
+
+/// Stock string: `No configuration found: %1$s`.
+pub(crate) async fn configure_lookup_failed(context: &Context, error: &str) -> String {
+    translated(context, StockMessage::ConfigureLookupFailed)
+        .await
+        .replace1(error)
+}
+
+/// Stock string: `The file of this message is missing.`.
+pub(crate) async fn file_missing(context: &Context) -> String {
+    translated(context, StockMessage::FileMissing).await
+}
+
 /// Stock string: `Messages`.
 /// Used as a subtitle in quota context; can be plural always.
 pub(crate) async fn messages(context: &Context) -> String {
@@ -1316,6 +1776,17 @@ pub async fn set_stock_translation(&self, id: StockMessage, stockstring: String)
         Ok(())
     }
 
+    /// Replaces all stock string translations.
+    ///
+    /// `translations` are in the order of preference, e.g. `pt-BR` followed by `pt`.
+    /// Strings missing in all of them use the English default.
+    /// Fails without changing the translations if any of them is invalid.
+    pub async fn set_stock_translations(&self, translations: Vec<StockTranslation>) -> Result<()> {
+        self.translated_stockstrings
+            .set_stock_translations(translations)
+            .await
+    }
+
     /// Returns a stock message saying that protection status has changed.
     pub(crate) async fn stock_protection_msg(
         &self,
@@ -1343,9 +1814,13 @@ pub(crate) async fn update_device_chats(&self) -> Result<()> {
 
         // create saved-messages chat; we do this only once, if the user has deleted the chat,
         // he can recreate it manually (make sure we do not re-add it when configure() was called a second time)
-        if !self.sql.get_raw_config_bool("self-chat-added").await? {
+        if !self
+            .sql
+            .get_raw_config_bool(InternalConfig::SelfChatAdded.as_ref())
+            .await?
+        {
             self.sql
-                .set_raw_config_bool("self-chat-added", true)
+                .set_raw_config_bool(InternalConfig::SelfChatAdded.as_ref(), true)
                 .await?;
             ChatId::create_for_contact(self, ContactId::SELF).await?;
         }
@@ -1374,6 +1849,12 @@ pub async fn set_stock_translation(&self, id: StockMessage, stockstring: String)
             .await?;
         Ok(())
     }
+
+    /// Replaces all stock string translations for all accounts,
+    /// see [`Context::set_stock_translations`].
+    pub async fn set_stock_translations(&self, translations: Vec<StockTranslation>) -> Result<()> {
+        self.stockstrings.set_stock_translations(translations).await
+    }
 }
 
 #[cfg(test)]
@@ -1421,6 +1902,113 @@ async fn test_set_stock_translation_wrong_replacements() {
             .is_err());
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_set_stock_translations() -> Result<()> {
+        let t = TestContext::new().await;
+        let hours = StockMessage::MsgYouEphemeralTimerHours as u32;
+        let pt_br = StockTranslation {
+            lang: "pt-BR".to_string(),
+            strings: [(
+                StockMessage::NoMessages as u32,
+                "Nenhuma mensagem.".to_string(),
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let ru = StockTranslation {
+            lang: "ru".to_string(),
+            strings: [
+                (
+                    StockMessage::NoMessages as u32,
+                    "Нет сообщений.".to_string(),
+                ),
+                (StockMessage::SelfMsg as u32, "Я".to_string()),
+            ]
+            .into(),
+            plurals: [(
+                hours,
+                [
+                    (PluralCategory::One, "%1$s час".to_string()),
+                    (PluralCategory::Few, "%1$s часа".to_string()),
+                    (PluralCategory::Many, "%1$s часов".to_string()),
+                    (PluralCategory::Other, "%1$s часа (other)".to_string()),
+                ]
+                .into(),
+            )]
+            .into(),
+        };
+        t.set_stock_translations(vec![pt_br, ru]).await?;
+
+        // The first translation is preferred, the next ones are fallbacks.
+        assert_eq!(no_messages(&t).await, "Nenhuma mensagem.");
+        assert_eq!(translated(&t, StockMessage::SelfMsg).await, "Я".to_string());
+        assert_eq!(
+            translated(&t, StockMessage::Draft).await,
+            "Draft".to_string()
+        );
+
+        // Plural forms use the rules of the language of the translation.
+        assert_eq!(
+            msg_ephemeral_timer_hours(&t, 21.0, ContactId::SELF).await,
+            "21 час"
+        );
+        assert_eq!(
+            msg_ephemeral_timer_hours(&t, 3.0, ContactId::SELF).await,
+            "3 часа"
+        );
+        assert_eq!(
+            msg_ephemeral_timer_hours(&t, 11.0, ContactId::SELF).await,
+            "11 часов"
+        );
+        assert_eq!(
+            msg_ephemeral_timer_hours(&t, 1.5, ContactId::SELF).await,
+            "1.5 часа (other)"
+        );
+
+        // Invalid translations are rejected without changing the current ones.
+        let invalid = StockTranslation {
+            lang: "de".to_string(),
+            strings: [(StockMessage::NoMessages as u32, "%1$s".to_string())].into(),
+            ..Default::default()
+        };
+        assert!(t.set_stock_translations(vec![invalid]).await.is_err());
+        assert_eq!(no_messages(&t).await, "Nenhuma mensagem.");
+
+        t.set_stock_translations(Vec::new()).await?;
+        assert_eq!(no_messages(&t).await, "No messages.");
+        assert_eq!(
+            msg_ephemeral_timer_hours(&t, 2.0, ContactId::SELF).await,
+            "You set message deletion timer to 2 hours."
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plural_category() {
+        assert_eq!(plural_category("en", 1.0), PluralCategory::One);
+        assert_eq!(plural_category("en-US", 0.0), PluralCategory::Other);
+        assert_eq!(plural_category("fr", 0.0), PluralCategory::One);
+        assert_eq!(plural_category("ja", 1.0), PluralCategory::Other);
+        assert_eq!(plural_category("pl", 22.0), PluralCategory::Few);
+        assert_eq!(plural_category("pl", 12.0), PluralCategory::Many);
+        assert_eq!(plural_category("pl", 21.0), PluralCategory::Many);
+        assert_eq!(plural_category("uk", 101.0), PluralCategory::One);
+        assert_eq!(plural_category("cs", 3.0), PluralCategory::Few);
+        assert_eq!(plural_category("ar", 2.0), PluralCategory::Two);
+        assert_eq!(plural_category("ar", 105.0), PluralCategory::Few);
+        assert_eq!(plural_category("lv", 10.0), PluralCategory::Zero);
+        assert_eq!(plural_category("de", 2.5), PluralCategory::Other);
+        assert_eq!(plural_category("pt", 0.0), PluralCategory::One);
+        assert_eq!(plural_category("pt-BR", 1.0), PluralCategory::One);
+        assert_eq!(plural_category("pt_PT", 0.0), PluralCategory::Other);
+        assert_eq!(plural_category("pt-PT", 1.0), PluralCategory::One);
+        assert_eq!(plural_category("es", 1_000_000.0), PluralCategory::Many);
+        assert_eq!(plural_category("ru", 0.0), PluralCategory::Many);
+        assert_eq!(plural_category("is", 21.0), PluralCategory::One);
+        assert_eq!(plural_category("sl", 102.0), PluralCategory::Two);
+        assert_eq!(plural_category("xx", 1.0), PluralCategory::One);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_stock_str() {
         let t = TestContext::new().await;
@@ -7,13 +7,14 @@
 
 use crate::chat::{Chat, ChatId};
 use crate::config::Config;
-use crate::constants::Blocked;
-use crate::contact::ContactId;
+use crate::constants::{Blocked, Chattype, MAX_UI_SETTINGS_BYTES};
+use crate::contact::{Contact, ContactId, Origin};
 use crate::context::Context;
+use crate::events::EventType;
 use crate::message::{Message, MsgId, Viewtype};
 use crate::mimeparser::SystemMessage;
 use crate::param::Param;
-use crate::sync::SyncData::{AddQrToken, DeleteQrToken};
+use crate::sync::SyncData::{AddQrToken, DeleteQrToken, UiSettings};
 use crate::token::Namespace;
 use crate::tools::time;
 use crate::{chat, stock_str, token};
@@ -25,10 +26,64 @@ pub(crate) struct QrTokenData {
     pub(crate) grpid: Option<String>,
 }
 
+/// Chat as identified on all devices.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum SyncChat {
+    /// Group, mailing list or broadcast list with the given ID.
+    Group(String),
+
+    /// 1:1 chat with the given address.
+    Contact(String),
+}
+
+impl SyncChat {
+    /// Returns how the chat is identified on other devices,
+    /// `None` if it cannot be synchronized, e.g. for the device chat.
+    pub(crate) async fn from_chat_id(context: &Context, chat_id: ChatId) -> Result<Option<Self>> {
+        let chat = Chat::load_from_db(context, chat_id).await?;
+        if !chat.grpid.is_empty() {
+            return Ok(Some(Self::Group(chat.grpid)));
+        }
+        if chat.typ != Chattype::Single || chat.is_device_talk() {
+            return Ok(None);
+        }
+        let Some(contact_id) = chat::get_chat_contacts(context, chat_id).await?.pop() else {
+            return Ok(None);
+        };
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        Ok(Some(Self::Contact(contact.get_addr().to_string())))
+    }
+
+    /// Looks up the chat on this device.
+    async fn lookup(&self, context: &Context) -> Result<Option<ChatId>> {
+        match self {
+            Self::Group(grpid) => Ok(chat::get_chat_id_by_grpid(context, grpid)
+                .await?
+                .map(|(chat_id, _, _)| chat_id)),
+            Self::Contact(addr) => {
+                match Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await? {
+                    Some(contact_id) => ChatId::lookup_by_contact(context, contact_id).await,
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct UiSettingsData {
+    /// Chat the settings belong to, `None` for the settings of the account.
+    pub(crate) chat: Option<SyncChat>,
+
+    /// The settings, empty if removed.
+    pub(crate) settings: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) enum SyncData {
     AddQrToken(QrTokenData),
     DeleteQrToken(QrTokenData),
+    UiSettings(UiSettingsData),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -250,6 +305,30 @@ pub(crate) async fn execute_sync_items(&self, items: &SyncItems) -> Result<()> {
                     token::delete(self, Namespace::InviteNumber, &token.invitenumber).await?;
                     token::delete(self, Namespace::Auth, &token.auth).await?;
                 }
+                UiSettings(data) => {
+                    if data.settings.len() > MAX_UI_SETTINGS_BYTES {
+                        warn!(self, "Ignoring too long UI settings.");
+                        continue;
+                    }
+                    match &data.chat {
+                        None => {
+                            self.sql
+                                .set_raw_config(
+                                    Config::UiSettings.as_ref(),
+                                    Some(data.settings.as_str())
+                                        .filter(|settings| !settings.is_empty()),
+                                )
+                                .await?;
+                            self.emit_event(EventType::ConfigChanged(Config::UiSettings));
+                        }
+                        Some(chat) => match chat.lookup(self).await? {
+                            Some(chat_id) => {
+                                chat_id.set_ui_settings_ex(self, &data.settings).await?
+                            }
+                            None => warn!(self, "Ignoring UI settings for unknown chat {chat:?}."),
+                        },
+                    }
+                }
             }
         }
         Ok(())
@@ -491,4 +570,51 @@ async fn test_send_sync_msg() -> Result<()> {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_sync_ui_settings() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let alice2 = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        alice.set_config_bool(Config::SendSyncMsgs, true).await?;
+        let chat_id = alice.create_chat(&bob).await.id;
+        let chat_id2 = alice2.create_chat(&bob).await.id;
+
+        alice
+            .set_config(Config::UiSettings, Some("wallpaper=dark"))
+            .await?;
+        alice2.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(
+            alice2.get_config(Config::UiSettings).await?.as_deref(),
+            Some("wallpaper=dark")
+        );
+
+        chat_id
+            .set_ui_settings(&alice, Some("wallpaper=beach"))
+            .await?;
+        assert_eq!(
+            chat_id.get_ui_settings(&alice).await?.as_deref(),
+            Some("wallpaper=beach")
+        );
+        alice2.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(
+            chat_id2.get_ui_settings(&alice2).await?.as_deref(),
+            Some("wallpaper=beach")
+        );
+
+        chat_id.set_ui_settings(&alice, None).await?;
+        alice2.recv_msg(&alice.pop_sent_msg().await).await;
+        assert_eq!(chat_id2.get_ui_settings(&alice2).await?, None);
+
+        let too_long = "x".repeat(MAX_UI_SETTINGS_BYTES + 1);
+        assert!(chat_id
+            .set_ui_settings(&alice, Some(&too_long))
+            .await
+            .is_err());
+        assert!(alice
+            .set_config(Config::UiSettings, Some(&too_long))
+            .await
+            .is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,192 @@
+//! # Synthetic databases for benchmarks.
+//!
+//! [`populate`] fills a database with generated contacts, chats and messages
+//! directly via SQL, which is much faster than receiving messages.
+//! It is used by the `large_db` benchmark to measure the chatlist,
+//! message list and search queries on databases of realistic size.
+//!
+//! Warning: this is only here for benchmarks, not part of the public API.
+
+use anyhow::Result;
+
+use crate::constants::{Blocked, Chattype};
+use crate::contact::{ContactId, Origin};
+use crate::context::Context;
+use crate::message::{MessageState, Viewtype};
+use crate::tools::time;
+
+/// Words the generated message texts are made of.
+const WORDS: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+];
+
+/// Size of a synthetic database.
+#[derive(Debug, Clone)]
+pub struct SyntheticDb {
+    /// Number of contacts, each having a one-to-one chat.
+    pub contacts: usize,
+
+    /// Number of groups, each having up to 10 members.
+    pub groups: usize,
+
+    /// Number of messages, distributed evenly over all chats.
+    pub messages: usize,
+}
+
+impl Default for SyntheticDb {
+    fn default() -> Self {
+        Self {
+            contacts: 1000,
+            groups: 100,
+            messages: 100_000,
+        }
+    }
+}
+
+/// Adds generated contacts, chats and messages to the database.
+///
+/// Every fifth message is outgoing, the others are incoming and seen.
+/// Message timestamps are one second apart and end at the current time.
+pub async fn populate(context: &Context, size: &SyntheticDb) -> Result<()> {
+    let size = size.clone();
+    let now = time();
+    context
+        .sql
+        .transaction(move |transaction| {
+            let mut contact_ids = Vec::with_capacity(size.contacts);
+            // Chat IDs with their members.
+            let mut chats: Vec<(i64, Vec<i64>)> = Vec::with_capacity(size.contacts + size.groups);
+
+            let mut stmt = transaction
+                .prepare("INSERT INTO contacts (name, addr, origin) VALUES (?, ?, ?)")?;
+            for i in 0..size.contacts {
+                stmt.execute((
+                    format!("Contact {i}"),
+                    format!("contact{i}@example.org"),
+                    Origin::IncomingReplyTo,
+                ))?;
+                contact_ids.push(transaction.last_insert_rowid());
+            }
+
+            let mut chat_stmt = transaction.prepare(
+                "INSERT INTO chats (type, name, grpid, blocked, created_timestamp)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            let mut member_stmt = transaction
+                .prepare("INSERT INTO chats_contacts (chat_id, contact_id) VALUES (?, ?)")?;
+            for (i, &contact_id) in contact_ids.iter().enumerate() {
+                chat_stmt.execute((
+                    Chattype::Single,
+                    format!("Contact {i}"),
+                    "",
+                    Blocked::Not,
+                    now,
+                ))?;
+                let chat_id = transaction.last_insert_rowid();
+                member_stmt.execute((chat_id, contact_id))?;
+                chats.push((chat_id, vec![contact_id]));
+            }
+            for i in 0..size.groups {
+                chat_stmt.execute((
+                    Chattype::Group,
+                    format!("Group {i}"),
+                    format!("synthetic-group-{i}"),
+                    Blocked::Not,
+                    now,
+                ))?;
+                let chat_id = transaction.last_insert_rowid();
+                member_stmt.execute((chat_id, ContactId::SELF))?;
+                let members: Vec<i64> = contact_ids
+                    .iter()
+                    .skip(i)
+                    .step_by(size.groups)
+                    .take(10)
+                    .copied()
+                    .collect();
+                for &contact_id in &members {
+                    member_stmt.execute((chat_id, contact_id))?;
+                }
+                chats.push((chat_id, members));
+            }
+            if chats.is_empty() {
+                return Ok(());
+            }
+
+            let mut msg_stmt = transaction.prepare(
+                "INSERT INTO msgs (rfc724_mid, chat_id, from_id, to_id, timestamp,
+                                   timestamp_sent, timestamp_rcvd, type, state, txt)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            let self_id = i64::from(ContactId::SELF.to_u32());
+            let word = |n: usize| WORDS.get(n % WORDS.len()).copied().unwrap_or_default();
+            let messages = i64::try_from(size.messages)?;
+            let chat_msgs = chats.iter().cycle().enumerate().take(size.messages);
+            for ((i, (chat_id, members)), timestamp) in chat_msgs.zip(now - messages + 1..) {
+                let member = members
+                    .get(i / chats.len() % members.len().max(1))
+                    .copied()
+                    .unwrap_or(self_id);
+                let (from_id, to_id, state) = if i % 5 == 0 {
+                    (self_id, member, MessageState::OutDelivered)
+                } else {
+                    (member, self_id, MessageState::InSeen)
+                };
+                msg_stmt.execute((
+                    format!("synthetic.{i}@example.org"),
+                    chat_id,
+                    from_id,
+                    to_id,
+                    timestamp,
+                    timestamp,
+                    timestamp,
+                    Viewtype::Text,
+                    state,
+                    format!("{} {} {i}", word(i), word(i / WORDS.len())),
+                ))?;
+            }
+            Ok(())
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{self, ChatItem};
+    use crate::chatlist::Chatlist;
+    use crate::test_utils::TestContext;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_populate() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let size = SyntheticDb {
+            contacts: 3,
+            groups: 1,
+            messages: 40,
+        };
+        populate(&t, &size).await?;
+
+        let chatlist = Chatlist::try_load(&t, 0, None, None).await?;
+        assert_eq!(chatlist.len(), 4);
+        let chat_id = chatlist.get_chat_id(0)?;
+        let msgs = chat::get_chat_msgs(&t, chat_id).await?;
+        let msgs_cnt = msgs
+            .iter()
+            .filter(|item| matches!(item, ChatItem::Message { .. }))
+            .count();
+        assert_eq!(msgs_cnt, 10);
+        assert!(!t.search_msgs(None, "lorem").await?.is_empty());
+        Ok(())
+    }
+}
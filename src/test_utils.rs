@@ -16,6 +16,7 @@
 use once_cell::sync::Lazy;
 use pretty_assertions::assert_eq;
 use rand::Rng;
+use regex::Regex;
 use tempfile::{tempdir, TempDir};
 use tokio::runtime::Handle;
 use tokio::sync::RwLock;
@@ -27,8 +28,7 @@
 };
 use crate::chatlist::Chatlist;
 use crate::config::Config;
-use crate::constants::{Blocked, Chattype};
-use crate::constants::{DC_GCL_NO_SPECIALS, DC_MSG_ID_DAYMARKER};
+use crate::constants::{Blocked, Chattype, DC_GCL_NO_SPECIALS};
 use crate::contact::{Contact, ContactAddress, ContactId, Modifier, Origin};
 use crate::context::Context;
 use crate::e2ee::EncryptHelper;
@@ -40,9 +40,13 @@
 use crate::pgp::KeyPair;
 use crate::receive_imf::receive_imf;
 use crate::securejoin::{get_securejoin_qr, join_securejoin};
+use crate::smtp::render_deferred_msg;
 use crate::stock_str::StockStrings;
 use crate::tools::EmailAddress;
 
+pub mod imap_server;
+pub mod smtp_server;
+
 #[allow(non_upper_case_globals)]
 pub const AVATAR_900x900_BYTES: &[u8] = include_bytes!("../test-data/image/avatar900x900.png");
 
@@ -472,6 +476,13 @@ pub async fn pop_sent_msg_opt(&self, timeout: Duration) -> Option<SentMessage<'_
                 return None;
             }
         };
+        let payload = if payload.is_empty() {
+            render_deferred_msg(&self.ctx, msg_id)
+                .await
+                .expect("failed to render message")
+        } else {
+            payload
+        };
         self.ctx
             .sql
             .execute("DELETE FROM smtp WHERE id=?;", (rowid,))
@@ -665,24 +676,8 @@ pub async fn send_msg(&self, chat_id: ChatId, msg: &mut Message) -> SentMessage<
     }
 
     pub async fn golden_test_chat(&self, chat_id: ChatId, filename: &str) {
-        let filename = Path::new("test-data/golden/").join(filename);
-
         let actual = self.display_chat(chat_id).await;
-
-        // We're using `unwrap_or_default()` here so that if the file doesn't exist,
-        // it can be created using `write` below.
-        let expected = fs::read(&filename).await.unwrap_or_default();
-        let expected = String::from_utf8(expected).unwrap().replace("\r\n", "\n");
-        if (std::env::var("UPDATE_GOLDEN_TESTS") == Ok("1".to_string())) && actual != expected {
-            fs::write(&filename, &actual)
-                .await
-                .unwrap_or_else(|e| panic!("Error writing {filename:?}: {e}"));
-        } else {
-            assert_eq!(
-                actual, expected,
-                "To update the expected value, run `UPDATE_GOLDEN_TESTS=1 cargo test`"
-            );
-        }
+        golden_test(&actual, filename).await;
     }
 
     /// Prints out the entire chat to stdout.
@@ -707,7 +702,7 @@ async fn display_chat(&self, chat_id: ChatId) -> String {
             .into_iter()
             .map(|x| match x {
                 ChatItem::Message { msg_id } => msg_id,
-                ChatItem::DayMarker { .. } => MsgId::new(DC_MSG_ID_DAYMARKER),
+                ChatItem::DayMarker { .. } => MsgId::DAYMARKER,
             })
             .collect();
 
@@ -753,7 +748,7 @@ async fn display_chat(&self, chat_id: ChatId) -> String {
 
         let mut lines_out = 0;
         for msg_id in msglist {
-            if msg_id == MsgId::new(DC_MSG_ID_DAYMARKER) {
+            if msg_id == MsgId::DAYMARKER {
                 writeln!(res,
                 "--------------------------------------------------------------------------------"
             )
@@ -888,6 +883,13 @@ pub fn recipient(&self) -> EmailAddress {
         EmailAddress::new(rcpt).expect("failed to parse email address")
     }
 
+    /// Returns true if the message was destined for `addr`.
+    pub fn is_sent_to(&self, addr: &str) -> bool {
+        self.recipients
+            .split(' ')
+            .any(|rcpt| rcpt.eq_ignore_ascii_case(addr))
+    }
+
     /// The raw message payload.
     pub fn payload(&self) -> &str {
         &self.payload
@@ -898,6 +900,97 @@ pub async fn load_from_db(&self) -> Message {
             .await
             .unwrap()
     }
+
+    /// Compares the payload with a golden file in `test-data/golden/`.
+    ///
+    /// Message-IDs, dates, group IDs, MIME boundaries and encrypted data
+    /// are replaced with placeholders before comparing, see [`normalize_mime`].
+    pub async fn golden_test_mime(&self, filename: &str) {
+        golden_test(&normalize_mime(&self.payload), filename).await;
+    }
+}
+
+/// Compares `actual` with the contents of a golden file in `test-data/golden/`.
+///
+/// If `UPDATE_GOLDEN_TESTS=1` is set, the file is updated instead.
+async fn golden_test(actual: &str, filename: &str) {
+    let filename = Path::new("test-data/golden/").join(filename);
+
+    // We're using `unwrap_or_default()` here so that if the file doesn't exist,
+    // it can be created using `write` below.
+    let expected = fs::read(&filename).await.unwrap_or_default();
+    let expected = String::from_utf8(expected).unwrap().replace("\r\n", "\n");
+    if (std::env::var("UPDATE_GOLDEN_TESTS") == Ok("1".to_string())) && actual != expected {
+        fs::write(&filename, actual)
+            .await
+            .unwrap_or_else(|e| panic!("Error writing {filename:?}: {e}"));
+    } else {
+        assert_eq!(
+            actual, expected,
+            "To update the expected value, run `UPDATE_GOLDEN_TESTS=1 cargo test`"
+        );
+    }
+}
+
+/// Replaces the parts of a MIME message which differ between test runs with placeholders.
+///
+/// Message-IDs are numbered in the order of their first appearance,
+/// so that references between messages are still visible.
+fn normalize_mime(payload: &str) -> String {
+    static BOUNDARY: Lazy<Regex> = Lazy::new(|| Regex::new(r#"boundary="([^"]+)""#).unwrap());
+    static MESSAGE_ID: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^<>\s]+>").unwrap());
+
+    let mut payload = payload.replace("\r\n", "\n");
+    let boundaries: Vec<String> = BOUNDARY
+        .captures_iter(&payload)
+        .map(|caps| caps[1].to_string())
+        .collect();
+    for (i, boundary) in boundaries.iter().enumerate() {
+        payload = payload.replace(boundary.as_str(), &format!("BOUNDARY-{}", i + 1));
+    }
+
+    let mut message_ids: Vec<String> = Vec::new();
+    let mut in_message_id_header = false;
+    let mut in_pgp_message = false;
+    let mut res = String::new();
+    for line in payload.lines() {
+        if in_pgp_message {
+            if line.starts_with("-----END PGP MESSAGE-----") {
+                in_pgp_message = false;
+                writeln!(res, "ENCRYPTED\n{line}").unwrap();
+            }
+            continue;
+        }
+        in_pgp_message = line.starts_with("-----BEGIN PGP MESSAGE-----");
+
+        let header = line.split_once(':').map(|(name, _)| name.to_lowercase());
+        if !line.starts_with([' ', '\t']) {
+            in_message_id_header = matches!(
+                header.as_deref(),
+                Some("message-id" | "in-reply-to" | "references")
+            );
+        }
+        let line = match header.as_deref() {
+            Some("date") => "Date: DATE".to_string(),
+            Some("chat-group-id") => "Chat-Group-ID: GROUP-ID".to_string(),
+            _ if in_message_id_header => MESSAGE_ID
+                .replace_all(line, |caps: &regex::Captures| {
+                    let message_id = caps[0].to_string();
+                    let i = match message_ids.iter().position(|id| *id == message_id) {
+                        Some(i) => i,
+                        None => {
+                            message_ids.push(message_id);
+                            message_ids.len() - 1
+                        }
+                    };
+                    format!("<MSGID-{}>", i + 1)
+                })
+                .into_owned(),
+            _ => line.to_string(),
+        };
+        writeln!(res, "{line}").unwrap();
+    }
+    res
 }
 
 /// Load a pre-generated keypair for alice@example.org from disk.
@@ -1091,7 +1184,9 @@ fn print_event(event: &Event) {
             "{}",
             green.paint(format!("Received LOCATION_CHANGED(contact={contact:?})"))
         ),
-        EventType::ConfigureProgress { progress, comment } => {
+        EventType::ConfigureProgress {
+            progress, comment, ..
+        } => {
             if let Some(comment) = comment {
                 format!(
                     "{}",
@@ -1230,6 +1325,30 @@ async fn test_with_both() {
         // panic!("Both fail");
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_golden_test_mime() {
+        let alice = TestContext::new_alice().await;
+        let sent = SentMessage {
+            payload: "Date: Mon, 01 Jan 2024 00:00:00 +0000\r\n\
+                      Message-ID: <Mr.abc@example.org>\r\n\
+                      References: <Mr.def@example.org>\r\n <Mr.abc@example.org>\r\n\
+                      Chat-Group-ID: abcdefghijk\r\n\
+                      From: <alice@example.org>\r\n\
+                      Content-Type: multipart/mixed; boundary=\"xyz123\"\r\n\
+                      \r\n\
+                      --xyz123\r\n\
+                      Content-Type: text/plain\r\n\
+                      \r\n\
+                      Hello\r\n\
+                      --xyz123--\r\n"
+                .to_string(),
+            recipients: "bob@example.net".to_string(),
+            sender_msg_id: MsgId::new(0),
+            sender_context: &alice,
+        };
+        sent.golden_test_mime("test_golden_test_mime").await;
+    }
+
     /// Checks that dropping the `TestContext` after the runtime does not panic,
     /// e.g. that `TestContext::drop` does not assume the runtime still exists.
     #[test]
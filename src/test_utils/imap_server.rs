@@ -0,0 +1,1026 @@
+//! # In-process IMAP server for tests.
+//!
+//! [`ImapServer`] listens on a random localhost port and implements the subset
+//! of IMAP4rev1 used by [`Imap`](crate::imap::Imap), keeping folders and messages in memory.
+//! Tests can script the announced capabilities, drop the connection
+//! when a command is received, reset the UIDVALIDITY of a folder
+//! and delay all responses to reproduce the behaviour of real servers.
+//!
+//! TLS, CONDSTORE and partial fetches are not implemented.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::TimeZone;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::config::{Config, InternalConfig};
+use crate::constants::DC_FOLDERS_CONFIGURED_VERSION;
+use crate::context::Context;
+use crate::provider::Socket;
+use crate::tools::time;
+
+const USER: &str = "user";
+const PASSWORD: &str = "password";
+
+/// Message stored on the [`ImapServer`].
+#[derive(Debug, Clone)]
+pub struct ServerMessage {
+    pub uid: u32,
+    pub flags: BTreeSet<String>,
+    pub internal_date: i64,
+    pub body: Vec<u8>,
+}
+
+impl ServerMessage {
+    fn split(&self) -> (&[u8], &[u8]) {
+        let body = &self.body[..];
+        let end = if let Some(pos) = find(body, b"\r\n\r\n") {
+            pos + 4
+        } else if let Some(pos) = find(body, b"\n\n") {
+            pos + 2
+        } else {
+            body.len()
+        };
+        body.split_at(end)
+    }
+
+    /// Returns a body section such as `HEADER.FIELDS (MESSAGE-ID FROM)`.
+    fn section(&self, section: &str) -> Vec<u8> {
+        let (header, text) = self.split();
+        let upper = section.to_uppercase();
+        if upper.is_empty() {
+            self.body.clone()
+        } else if upper == "HEADER" {
+            header.to_vec()
+        } else if upper == "TEXT" {
+            text.to_vec()
+        } else if let Some(fields) = upper.strip_prefix("HEADER.FIELDS") {
+            let (exclude, fields) = match fields.strip_prefix(".NOT") {
+                Some(fields) => (true, fields),
+                None => (false, fields),
+            };
+            let fields: Vec<&str> = fields
+                .trim()
+                .trim_start_matches('(')
+                .trim_end_matches(')')
+                .split_whitespace()
+                .collect();
+            let mut res = Vec::new();
+            for field in header_fields(header) {
+                let name = String::from_utf8_lossy(field)
+                    .split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_uppercase();
+                if fields.contains(&name.as_str()) != exclude {
+                    res.extend_from_slice(field);
+                }
+            }
+            res.extend_from_slice(b"\r\n");
+            res
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Folder {
+    uid_validity: u32,
+    uid_next: u32,
+    attributes: Vec<String>,
+    messages: Vec<ServerMessage>,
+}
+
+impl Folder {
+    fn new(uid_validity: u32, attributes: &[&str]) -> Self {
+        Self {
+            uid_validity,
+            uid_next: 1,
+            attributes: attributes.iter().map(|a| a.to_string()).collect(),
+            messages: Vec::new(),
+        }
+    }
+
+    fn append(&mut self, body: Vec<u8>, flags: BTreeSet<String>) -> u32 {
+        let uid = self.uid_next;
+        self.uid_next += 1;
+        self.messages.push(ServerMessage {
+            uid,
+            flags,
+            internal_date: time(),
+            body,
+        });
+        uid
+    }
+
+    /// Returns the indices of the messages in a sequence set.
+    fn matching(&self, set: &str, uid: bool) -> Vec<usize> {
+        let max = if uid {
+            self.messages.last().map_or(0, |msg| msg.uid)
+        } else {
+            u32::try_from(self.messages.len()).unwrap_or(u32::MAX)
+        };
+        let ranges = parse_set(set, max);
+        (0..self.messages.len())
+            .filter(|&i| {
+                let n = if uid {
+                    self.messages[i].uid
+                } else {
+                    u32::try_from(i + 1).unwrap_or(u32::MAX)
+                };
+                ranges.iter().any(|&(start, end)| start <= n && n <= end)
+            })
+            .collect()
+    }
+
+    /// Removes messages flagged as `\Deleted`, returning their sequence numbers
+    /// in the order EXPUNGE responses are to be sent.
+    fn expunge(&mut self, indices: Option<&[usize]>) -> Vec<usize> {
+        let mut expunged: Vec<usize> = (0..self.messages.len())
+            .filter(|i| indices.map_or(true, |indices| indices.contains(i)))
+            .filter(|&i| self.messages[i].flags.contains("\\Deleted"))
+            .collect();
+        expunged.reverse();
+        for &i in &expunged {
+            self.messages.remove(i);
+        }
+        expunged.into_iter().map(|i| i + 1).collect()
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    capabilities: Vec<String>,
+    folders: BTreeMap<String, Folder>,
+    next_uid_validity: u32,
+
+    /// Commands on which the connection is dropped once without a response.
+    disconnect_on: Vec<String>,
+    latency: Duration,
+    connections: usize,
+    commands: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+
+    /// Incremented on every change of a folder to wake up IDLE.
+    changes: watch::Sender<u64>,
+}
+
+/// State of a client connection.
+#[derive(Debug, Default)]
+struct Session {
+    authenticated: bool,
+    selected: Option<String>,
+    read_only: bool,
+}
+
+/// Result of a command.
+enum Status {
+    Ok(&'static str),
+    No(&'static str),
+    Bad(&'static str),
+}
+
+/// In-process IMAP server.
+///
+/// The server is stopped when dropped.
+#[derive(Debug)]
+pub struct ImapServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl ImapServer {
+    /// Starts a server with an empty INBOX announcing `IDLE` and `MOVE`.
+    pub async fn new() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let mut folders = BTreeMap::new();
+        folders.insert("INBOX".to_string(), Folder::new(1, &[]));
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                capabilities: vec!["IDLE".to_string(), "MOVE".to_string()],
+                folders,
+                next_uid_validity: 2,
+                disconnect_on: Vec::new(),
+                latency: Duration::ZERO,
+                connections: 0,
+                commands: Vec::new(),
+            }),
+            changes: watch::channel(0).0,
+        });
+        let task = tokio::spawn({
+            let shared = Arc::clone(&shared);
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    shared.state.lock().unwrap().connections += 1;
+                    tokio::spawn(handle_connection(stream, Arc::clone(&shared)));
+                }
+            }
+        });
+        Ok(Self { addr, shared, task })
+    }
+
+    /// Configures the context to use this server with the folders already configured.
+    pub async fn configure(&self, context: &Context) -> Result<()> {
+        context
+            .set_config(Config::ConfiguredMailServer, Some("127.0.0.1"))
+            .await?;
+        context
+            .set_config(
+                Config::ConfiguredMailPort,
+                Some(&self.addr.port().to_string()),
+            )
+            .await?;
+        context
+            .set_config(
+                Config::ConfiguredMailSecurity,
+                Some(&(Socket::Plain as i32).to_string()),
+            )
+            .await?;
+        context
+            .set_config(Config::ConfiguredMailUser, Some(USER))
+            .await?;
+        context
+            .set_config(Config::ConfiguredMailPw, Some(PASSWORD))
+            .await?;
+        context
+            .set_config(Config::ConfiguredInboxFolder, Some("INBOX"))
+            .await?;
+        context
+            .sql
+            .set_raw_config_int(
+                InternalConfig::FoldersConfigured.as_ref(),
+                DC_FOLDERS_CONFIGURED_VERSION,
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut State) -> T) -> T {
+        let res = f(&mut self.shared.state.lock().unwrap());
+        self.shared.changes.send_modify(|n| *n += 1);
+        res
+    }
+
+    /// Sets the capabilities announced in addition to `IMAP4rev1`.
+    pub fn set_capabilities(&self, capabilities: &[&str]) {
+        self.with_state(|state| {
+            state.capabilities = capabilities.iter().map(|c| c.to_string()).collect()
+        });
+    }
+
+    /// Creates a folder with the given special-use attributes, e.g. `\Sent`.
+    pub fn add_folder(&self, name: &str, attributes: &[&str]) {
+        self.with_state(|state| {
+            let uid_validity = state.next_uid_validity;
+            state.next_uid_validity += 1;
+            state
+                .folders
+                .insert(name.to_string(), Folder::new(uid_validity, attributes));
+        });
+    }
+
+    /// Delivers a message to a folder, returning its UID.
+    pub fn add_message(&self, folder: &str, body: &[u8]) -> u32 {
+        self.with_state(|state| {
+            state
+                .folders
+                .get_mut(folder)
+                .expect("no such folder")
+                .append(body.to_vec(), BTreeSet::new())
+        })
+    }
+
+    /// Returns the messages in a folder.
+    pub fn messages(&self, folder: &str) -> Vec<ServerMessage> {
+        let state = self.shared.state.lock().unwrap();
+        state
+            .folders
+            .get(folder)
+            .map(|folder| folder.messages.clone())
+            .unwrap_or_default()
+    }
+
+    /// Drops the connection without a response when the command is received next time,
+    /// e.g. `UID FETCH`.
+    pub fn disconnect_on(&self, command: &str) {
+        self.with_state(|state| state.disconnect_on.push(command.to_uppercase()));
+    }
+
+    /// Changes the UIDVALIDITY of a folder and assigns new UIDs to its messages,
+    /// like servers do when a mailbox is restored from backup.
+    pub fn reset_uid_validity(&self, folder: &str) {
+        self.with_state(|state| {
+            let uid_validity = state.next_uid_validity;
+            state.next_uid_validity += 1;
+            let folder = state.folders.get_mut(folder).expect("no such folder");
+            folder.uid_validity = uid_validity;
+            folder.uid_next = 1;
+            for msg in &mut folder.messages {
+                msg.uid = folder.uid_next;
+                folder.uid_next += 1;
+            }
+        });
+    }
+
+    /// Delays every response by the given time.
+    pub fn set_latency(&self, latency: Duration) {
+        self.with_state(|state| state.latency = latency);
+    }
+
+    /// Returns the localhost port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Returns the number of accepted connections.
+    pub fn connections(&self) -> usize {
+        self.shared.state.lock().unwrap().connections
+    }
+
+    /// Returns the names of the commands received so far, e.g. `UID MOVE`.
+    pub fn commands(&self) -> Vec<String> {
+        self.shared.state.lock().unwrap().commands.clone()
+    }
+}
+
+impl Drop for ImapServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream, shared: Arc<Shared>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut session = Session::default();
+    writer
+        .write_all(b"* OK IMAP4rev1 test server ready\r\n")
+        .await?;
+
+    while let Some(args) = read_command(&mut reader, &mut writer).await? {
+        let (tag, command, args) = split_command(&args);
+        let (latency, disconnect) = {
+            let mut state = shared.state.lock().unwrap();
+            state.commands.push(command.clone());
+            let disconnect = state.disconnect_on.iter().position(|c| *c == command);
+            if let Some(i) = disconnect {
+                state.disconnect_on.remove(i);
+            }
+            (state.latency, disconnect.is_some())
+        };
+        if disconnect {
+            break;
+        }
+        tokio::time::sleep(latency).await;
+
+        if command == "IDLE" && session.selected.is_some() {
+            if !idle(&mut reader, &mut writer, &shared, &session, &tag).await? {
+                break;
+            }
+            continue;
+        }
+
+        let mut out = Vec::new();
+        let status = {
+            let mut state = shared.state.lock().unwrap();
+            state.handle(&mut session, &command, args, &mut out)
+        };
+        let (status, text) = match status {
+            Status::Ok(text) => ("OK", text),
+            Status::No(text) => ("NO", text),
+            Status::Bad(text) => ("BAD", text),
+        };
+        out.extend_from_slice(format!("{tag} {status} {text}\r\n").as_bytes());
+        writer.write_all(&out).await?;
+        shared.changes.send_modify(|n| *n += 1);
+        if command == "LOGOUT" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Waits for `DONE`, reporting new messages in the selected folder.
+///
+/// Returns false if the connection was closed.
+async fn idle(
+    reader: &mut BufReader<OwnedReadHalf>,
+    writer: &mut OwnedWriteHalf,
+    shared: &Shared,
+    session: &Session,
+    tag: &str,
+) -> Result<bool> {
+    let exists = |shared: &Shared| {
+        let state = shared.state.lock().unwrap();
+        session
+            .selected
+            .as_ref()
+            .and_then(|folder| state.folders.get(folder))
+            .map_or(0, |folder| folder.messages.len())
+    };
+    let mut changes = shared.changes.subscribe();
+    let mut last_exists = exists(shared);
+    writer.write_all(b"+ idling\r\n").await?;
+
+    let mut line = Vec::new();
+    let done = reader.read_until(b'\n', &mut line);
+    tokio::pin!(done);
+    loop {
+        tokio::select! {
+            res = &mut done => {
+                if res? == 0 {
+                    return Ok(false);
+                }
+                writer
+                    .write_all(format!("{tag} OK IDLE terminated\r\n").as_bytes())
+                    .await?;
+                return Ok(true);
+            }
+            Ok(()) = changes.changed() => {
+                let exists = exists(shared);
+                if exists > last_exists {
+                    writer
+                        .write_all(format!("* {exists} EXISTS\r\n").as_bytes())
+                        .await?;
+                }
+                last_exists = exists;
+            }
+        }
+    }
+}
+
+impl State {
+    fn handle(
+        &mut self,
+        session: &mut Session,
+        command: &str,
+        args: &[Vec<u8>],
+        out: &mut Vec<u8>,
+    ) -> Status {
+        let arg = |i: usize| {
+            String::from_utf8_lossy(args.get(i).map_or(&[][..], |a| a.as_slice())).into_owned()
+        };
+        match command {
+            "CAPABILITY" => {
+                push_line(
+                    out,
+                    format!("* CAPABILITY IMAP4rev1 {}", self.capabilities.join(" ")),
+                );
+                return Status::Ok("CAPABILITY completed");
+            }
+            "NOOP" => return Status::Ok("NOOP completed"),
+            "LOGOUT" => {
+                push_line(out, "* BYE Logging out".to_string());
+                return Status::Ok("LOGOUT completed");
+            }
+            "LOGIN" => {
+                if arg(0) == USER && arg(1) == PASSWORD {
+                    session.authenticated = true;
+                    return Status::Ok("LOGIN completed");
+                }
+                return Status::No("[AUTHENTICATIONFAILED] Invalid credentials");
+            }
+            _ if !session.authenticated => return Status::Bad("Not authenticated"),
+            _ => {}
+        }
+
+        match command {
+            "ID" if self.has_capability("ID") => {
+                push_line(out, r#"* ID ("name" "test server")"#.to_string());
+                Status::Ok("ID completed")
+            }
+            "ENABLE" => {
+                push_line(out, "* ENABLED".to_string());
+                Status::Ok("ENABLE completed")
+            }
+            "SELECT" | "EXAMINE" => {
+                let name = folder_name(&arg(0));
+                let Some(folder) = self.folders.get(&name) else {
+                    session.selected = None;
+                    return Status::No("[NONEXISTENT] Mailbox does not exist");
+                };
+                push_line(
+                    out,
+                    r"* FLAGS (\Answered \Flagged \Deleted \Seen \Draft)".to_string(),
+                );
+                push_line(out, format!("* {} EXISTS", folder.messages.len()));
+                push_line(out, "* 0 RECENT".to_string());
+                push_line(
+                    out,
+                    format!("* OK [UIDVALIDITY {}] UIDs valid", folder.uid_validity),
+                );
+                push_line(
+                    out,
+                    format!("* OK [UIDNEXT {}] Predicted next UID", folder.uid_next),
+                );
+                session.selected = Some(name);
+                session.read_only = command == "EXAMINE";
+                if session.read_only {
+                    Status::Ok("[READ-ONLY] EXAMINE completed")
+                } else {
+                    Status::Ok("[READ-WRITE] SELECT completed")
+                }
+            }
+            "CREATE" => {
+                let name = folder_name(&arg(0));
+                if self.folders.contains_key(&name) {
+                    return Status::No("[ALREADYEXISTS] Mailbox already exists");
+                }
+                let uid_validity = self.next_uid_validity;
+                self.next_uid_validity += 1;
+                self.folders.insert(name, Folder::new(uid_validity, &[]));
+                Status::Ok("CREATE completed")
+            }
+            "SUBSCRIBE" | "UNSUBSCRIBE" => Status::Ok("SUBSCRIBE completed"),
+            "LIST" => {
+                let pattern = arg(1);
+                for (name, folder) in &self.folders {
+                    if pattern == "*" || pattern == "%" || folder_name(&pattern) == *name {
+                        push_line(
+                            out,
+                            format!("* LIST ({}) \"/\" \"{name}\"", folder.attributes.join(" ")),
+                        );
+                    }
+                }
+                Status::Ok("LIST completed")
+            }
+            "STATUS" => {
+                let name = folder_name(&arg(0));
+                let Some(folder) = self.folders.get(&name) else {
+                    return Status::No("[NONEXISTENT] Mailbox does not exist");
+                };
+                let items: Vec<String> = list_items(&arg(1))
+                    .into_iter()
+                    .filter_map(|item| match item.to_uppercase().as_str() {
+                        "MESSAGES" => Some(format!("MESSAGES {}", folder.messages.len())),
+                        "UIDNEXT" => Some(format!("UIDNEXT {}", folder.uid_next)),
+                        "UIDVALIDITY" => Some(format!("UIDVALIDITY {}", folder.uid_validity)),
+                        "UNSEEN" => Some(format!(
+                            "UNSEEN {}",
+                            folder
+                                .messages
+                                .iter()
+                                .filter(|msg| !msg.flags.contains("\\Seen"))
+                                .count()
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                push_line(out, format!("* STATUS \"{name}\" ({})", items.join(" ")));
+                Status::Ok("STATUS completed")
+            }
+            "APPEND" => {
+                let name = folder_name(&arg(0));
+                let flags = args
+                    .get(1)
+                    .filter(|arg| arg.starts_with(b"("))
+                    .map(|arg| list_items(&String::from_utf8_lossy(arg)))
+                    .unwrap_or_default();
+                let Some(folder) = self.folders.get_mut(&name) else {
+                    return Status::No("[TRYCREATE] Mailbox does not exist");
+                };
+                let Some(body) = args.last() else {
+                    return Status::Bad("Missing message");
+                };
+                folder.append(body.clone(), flags.into_iter().collect());
+                Status::Ok("APPEND completed")
+            }
+            _ => self.handle_selected(session, command, args, out),
+        }
+    }
+
+    /// Handles commands which require a selected folder.
+    fn handle_selected(
+        &mut self,
+        session: &mut Session,
+        command: &str,
+        args: &[Vec<u8>],
+        out: &mut Vec<u8>,
+    ) -> Status {
+        let arg = |i: usize| {
+            String::from_utf8_lossy(args.get(i).map_or(&[][..], |a| a.as_slice())).into_owned()
+        };
+        let uid = command.starts_with("UID ");
+        let can_move = self.has_capability("MOVE");
+        let target_exists = self.folders.contains_key(&folder_name(&arg(1)));
+        let Some(selected) = session.selected.clone() else {
+            return Status::Bad("No mailbox selected");
+        };
+        let Some(folder) = self.folders.get_mut(&selected) else {
+            return Status::No("[NONEXISTENT] Mailbox was deleted");
+        };
+
+        match command.trim_start_matches("UID ") {
+            "CLOSE" => {
+                if !session.read_only {
+                    folder.expunge(None);
+                }
+                session.selected = None;
+                Status::Ok("CLOSE completed")
+            }
+            "EXPUNGE" => {
+                let indices = uid.then(|| folder.matching(&arg(0), true));
+                for seq in folder.expunge(indices.as_deref()) {
+                    push_line(out, format!("* {seq} EXPUNGE"));
+                }
+                Status::Ok("EXPUNGE completed")
+            }
+            "FETCH" => {
+                let items = list_items(&arg(1));
+                for i in folder.matching(&arg(0), uid) {
+                    let msg = &mut folder.messages[i];
+                    let mut fetched: Vec<Vec<u8>> = Vec::new();
+                    if uid && !items.iter().any(|item| item.eq_ignore_ascii_case("UID")) {
+                        fetched.push(format!("UID {}", msg.uid).into_bytes());
+                    }
+                    for item in &items {
+                        let upper = item.to_uppercase();
+                        if upper.starts_with("BODY") {
+                            if !upper.starts_with("BODY.PEEK") && !session.read_only {
+                                msg.flags.insert("\\Seen".to_string());
+                            }
+                            let (section, partial) = item
+                                .split_once('[')
+                                .and_then(|(_, section)| section.rsplit_once(']'))
+                                .unwrap_or_default();
+                            let mut data = msg.section(section);
+                            // Partial fetch `BODY[]<offset.length>`.
+                            let mut origin = String::new();
+                            if let Some((offset, length)) = partial
+                                .strip_prefix('<')
+                                .and_then(|partial| partial.strip_suffix('>'))
+                                .and_then(|partial| partial.split_once('.'))
+                            {
+                                let offset: usize = offset.parse().unwrap_or_default();
+                                let length: usize = length.parse().unwrap_or_default();
+                                data = data.into_iter().skip(offset).take(length).collect();
+                                origin = format!("<{offset}>");
+                            }
+                            let mut res = format!("BODY[{section}]{origin} {{{}}}\r\n", data.len())
+                                .into_bytes();
+                            res.extend_from_slice(&data);
+                            fetched.push(res);
+                            continue;
+                        }
+                        let res = match upper.as_str() {
+                            "UID" => format!("UID {}", msg.uid),
+                            "FLAGS" => format!("FLAGS ({})", join_flags(&msg.flags)),
+                            "RFC822.SIZE" => format!("RFC822.SIZE {}", msg.body.len()),
+                            "INTERNALDATE" => format!(
+                                "INTERNALDATE \"{}\"",
+                                chrono::Utc
+                                    .timestamp_opt(msg.internal_date, 0)
+                                    .single()
+                                    .map(|date| date.format("%d-%b-%Y %H:%M:%S +0000").to_string())
+                                    .unwrap_or_default()
+                            ),
+                            _ => continue,
+                        };
+                        fetched.push(res.into_bytes());
+                    }
+                    out.extend_from_slice(format!("* {} FETCH (", i + 1).as_bytes());
+                    out.extend_from_slice(&fetched.join(&b' '));
+                    out.extend_from_slice(b")\r\n");
+                }
+                Status::Ok("FETCH completed")
+            }
+            "STORE" => {
+                if session.read_only {
+                    return Status::No("Mailbox is read-only");
+                }
+                let operation = arg(1).to_uppercase();
+                let flags = list_items(&arg(2));
+                for i in folder.matching(&arg(0), uid) {
+                    let msg = &mut folder.messages[i];
+                    if operation.starts_with('+') {
+                        msg.flags.extend(flags.iter().cloned());
+                    } else if operation.starts_with('-') {
+                        msg.flags.retain(|flag| !flags.contains(flag));
+                    } else {
+                        msg.flags = flags.iter().cloned().collect();
+                    }
+                    if !operation.ends_with(".SILENT") {
+                        push_line(
+                            out,
+                            format!(
+                                "* {} FETCH (UID {} FLAGS ({}))",
+                                i + 1,
+                                msg.uid,
+                                join_flags(&msg.flags)
+                            ),
+                        );
+                    }
+                }
+                Status::Ok("STORE completed")
+            }
+            "SEARCH" => {
+                let criteria: Vec<String> = (0..args.len()).map(arg).collect();
+                let found: Vec<String> = folder
+                    .messages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, msg)| matches_search(msg, &criteria))
+                    .map(|(i, msg)| {
+                        if uid {
+                            msg.uid
+                        } else {
+                            u32::try_from(i + 1).unwrap_or(u32::MAX)
+                        }
+                        .to_string()
+                    })
+                    .collect();
+                push_line(
+                    out,
+                    format!("* SEARCH {}", found.join(" "))
+                        .trim_end()
+                        .to_string(),
+                );
+                Status::Ok("SEARCH completed")
+            }
+            "COPY" | "MOVE" => {
+                if command.ends_with("MOVE") && !can_move {
+                    return Status::Bad("Unknown command");
+                }
+                if !target_exists {
+                    return Status::No("[TRYCREATE] Mailbox does not exist");
+                }
+                let indices = folder.matching(&arg(0), uid);
+                let msgs: Vec<ServerMessage> = indices
+                    .iter()
+                    .map(|&i| folder.messages[i].clone())
+                    .collect();
+                if command.ends_with("MOVE") {
+                    for &i in indices.iter().rev() {
+                        folder.messages.remove(i);
+                        push_line(out, format!("* {} EXPUNGE", i + 1));
+                    }
+                }
+                if let Some(target) = self.folders.get_mut(&folder_name(&arg(1))) {
+                    for msg in msgs {
+                        target.append(msg.body, msg.flags);
+                    }
+                }
+                Status::Ok("COPY completed")
+            }
+            _ => Status::Bad("Unknown command"),
+        }
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(capability))
+    }
+}
+
+/// Returns true if the message matches the search criteria.
+///
+/// Only `ALL`, `FROM`, `TO` and `HEADER` are supported, other criteria match all messages.
+fn matches_search(msg: &ServerMessage, criteria: &[String]) -> bool {
+    let header = String::from_utf8_lossy(msg.split().0).to_lowercase();
+    let has_field = |name: &str, value: &str| {
+        header.lines().any(|line| {
+            line.split_once(':').map_or(false, |(n, v)| {
+                n.trim() == name.to_lowercase() && v.contains(&value.to_lowercase())
+            })
+        })
+    };
+    let mut criteria = criteria.iter();
+    while let Some(criterion) = criteria.next() {
+        let matches = match criterion.to_uppercase().as_str() {
+            "FROM" | "TO" => {
+                let value = criteria.next().map_or("", |v| v.as_str());
+                has_field(&criterion.to_lowercase(), value)
+            }
+            "UID" => {
+                let set = criteria.next().map_or("", |v| v.as_str());
+                parse_set(set, u32::MAX)
+                    .iter()
+                    .any(|&(start, end)| start <= msg.uid && msg.uid <= end)
+            }
+            "HEADER" => {
+                let name = criteria.next().map_or("", |v| v.as_str());
+                let value = criteria.next().map_or("", |v| v.as_str());
+                has_field(name, value)
+            }
+            _ => true,
+        };
+        if !matches {
+            return false;
+        }
+    }
+    true
+}
+
+/// Reads a command, acknowledging literals with a continuation request.
+///
+/// Returns the tokens of the command including the literals
+/// or `None` if the connection was closed.
+async fn read_command(
+    reader: &mut BufReader<OwnedReadHalf>,
+    writer: &mut OwnedWriteHalf,
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let mut tokens = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or(&line);
+        if let Some((head, len)) = split_literal(line) {
+            tokens.extend(tokenize(head));
+            writer.write_all(b"+ Ready for literal data\r\n").await?;
+            let mut literal = vec![0; len];
+            reader.read_exact(&mut literal).await?;
+            tokens.push(literal);
+        } else {
+            tokens.extend(tokenize(line));
+            return Ok(Some(tokens));
+        }
+    }
+}
+
+/// Splits tokens into the tag, the command name and its arguments.
+fn split_command(tokens: &[Vec<u8>]) -> (String, String, &[Vec<u8>]) {
+    let token = |i: usize| {
+        tokens
+            .get(i)
+            .map(|t| String::from_utf8_lossy(t).to_uppercase())
+            .unwrap_or_default()
+    };
+    let tag = tokens
+        .first()
+        .map(|t| String::from_utf8_lossy(t).into_owned())
+        .unwrap_or_default();
+    let command = token(1);
+    if command == "UID" {
+        (
+            tag,
+            format!("UID {}", token(2)),
+            tokens.get(3..).unwrap_or_default(),
+        )
+    } else {
+        (tag, command, tokens.get(2..).unwrap_or_default())
+    }
+}
+
+/// Splits a `{123}` literal length from the end of a line.
+fn split_literal(line: &[u8]) -> Option<(&[u8], usize)> {
+    let line = line.strip_suffix(b"}")?;
+    let start = line.iter().rposition(|&b| b == b'{')?;
+    let len = std::str::from_utf8(&line[start + 1..]).ok()?.parse().ok()?;
+    Some((&line[..start], len))
+}
+
+/// Splits a line into atoms and quoted strings.
+///
+/// Parenthesized lists and atoms with bracketed sections such as
+/// `BODY.PEEK[HEADER.FIELDS (FROM)]` are returned as single tokens.
+fn tokenize(line: &[u8]) -> Vec<Vec<u8>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < line.len() {
+        match line[i] {
+            b' ' => i += 1,
+            b'"' => {
+                let mut token = Vec::new();
+                i += 1;
+                while i < line.len() && line[i] != b'"' {
+                    if line[i] == b'\\' {
+                        i += 1;
+                    }
+                    if let Some(&b) = line.get(i) {
+                        token.push(b);
+                    }
+                    i += 1;
+                }
+                i += 1;
+                tokens.push(token);
+            }
+            _ => {
+                let start = i;
+                let mut depth = 0usize;
+                while i < line.len() && (depth > 0 || line[i] != b' ') {
+                    match line[i] {
+                        b'(' | b'[' => depth += 1,
+                        b')' | b']' => depth = depth.saturating_sub(1),
+                        b'"' => {
+                            i += 1;
+                            while i < line.len() && line[i] != b'"' {
+                                if line[i] == b'\\' {
+                                    i += 1;
+                                }
+                                i += 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                tokens.push(line[start..i.min(line.len())].to_vec());
+            }
+        }
+    }
+    tokens
+}
+
+/// Returns the items of a parenthesized list or a single item.
+fn list_items(list: &str) -> Vec<String> {
+    let list = list.strip_prefix('(').unwrap_or(list);
+    let list = list.strip_suffix(')').unwrap_or(list);
+    tokenize(list.as_bytes())
+        .into_iter()
+        .map(|item| String::from_utf8_lossy(&item).into_owned())
+        .collect()
+}
+
+/// Parses a sequence set such as `1:3,5,7:*` into inclusive ranges.
+///
+/// `*` stands for `max`, ranges may be given in any order.
+fn parse_set(set: &str, max: u32) -> Vec<(u32, u32)> {
+    let parse = |n: &str| {
+        if n == "*" {
+            Some(max)
+        } else {
+            n.parse().ok()
+        }
+    };
+    set.split(',')
+        .filter_map(|range| {
+            let (start, end) = match range.split_once(':') {
+                Some((start, end)) => (parse(start)?, parse(end)?),
+                None => (parse(range)?, parse(range)?),
+            };
+            Some((start.min(end), start.max(end)))
+        })
+        .collect()
+}
+
+fn folder_name(name: &str) -> String {
+    if name.eq_ignore_ascii_case("INBOX") {
+        "INBOX".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn join_flags(flags: &BTreeSet<String>) -> String {
+    flags.iter().cloned().collect::<Vec<_>>().join(" ")
+}
+
+fn push_line(out: &mut Vec<u8>, line: String) {
+    out.extend_from_slice(line.as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Splits a header into fields including their continuation lines.
+fn header_fields(header: &[u8]) -> Vec<&[u8]> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    while pos < header.len() {
+        let end = header[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(header.len(), |i| pos + i + 1);
+        let line = &header[pos..end];
+        let is_continuation = line.starts_with(b" ") || line.starts_with(b"\t");
+        if !is_continuation && pos > start {
+            fields.push(&header[start..pos]);
+            start = pos;
+        }
+        pos = end;
+    }
+    if pos > start {
+        fields.push(&header[start..pos]);
+    }
+    fields
+        .into_iter()
+        .filter(|field| *field != b"\r\n" && *field != b"\n")
+        .collect()
+}
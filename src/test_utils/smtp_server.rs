@@ -0,0 +1,238 @@
+//! # In-process SMTP server for tests.
+//!
+//! [`SmtpServer`] listens on a random localhost port, accepts mail
+//! for any recipient and keeps it in memory, see [`SmtpServer::mails`].
+//! Like [`ImapServer`](super::imap_server::ImapServer), it can drop the connection
+//! when a command is received to test reconnection.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use base64::Engine as _;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::provider::Socket;
+
+const USER: &str = "user";
+const PASSWORD: &str = "password";
+
+/// Mail received by the [`SmtpServer`].
+#[derive(Debug, Clone)]
+pub struct ReceivedMail {
+    pub from: String,
+    pub recipients: Vec<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    mails: Vec<ReceivedMail>,
+
+    /// Commands on which the connection is dropped once without a response.
+    disconnect_on: Vec<String>,
+    connections: usize,
+}
+
+/// In-process SMTP server.
+///
+/// The server is stopped when dropped.
+#[derive(Debug)]
+pub struct SmtpServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl SmtpServer {
+    /// Starts a server.
+    pub async fn new() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let task = tokio::spawn({
+            let state = Arc::clone(&state);
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    state.lock().unwrap().connections += 1;
+                    tokio::spawn(handle_connection(stream, Arc::clone(&state)));
+                }
+            }
+        });
+        Ok(Self { addr, state, task })
+    }
+
+    /// Configures the context to send mail using this server.
+    pub async fn configure(&self, context: &Context) -> Result<()> {
+        context
+            .set_config(Config::ConfiguredSendServer, Some("127.0.0.1"))
+            .await?;
+        context
+            .set_config(
+                Config::ConfiguredSendPort,
+                Some(&self.addr.port().to_string()),
+            )
+            .await?;
+        context
+            .set_config(
+                Config::ConfiguredSendSecurity,
+                Some(&(Socket::Plain as i32).to_string()),
+            )
+            .await?;
+        context
+            .set_config(Config::ConfiguredSendUser, Some(USER))
+            .await?;
+        context
+            .set_config(Config::ConfiguredSendPw, Some(PASSWORD))
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the mails received so far.
+    pub fn mails(&self) -> Vec<ReceivedMail> {
+        self.state.lock().unwrap().mails.clone()
+    }
+
+    /// Drops the connection without a response when the command is received next time,
+    /// e.g. `DATA`.
+    pub fn disconnect_on(&self, command: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .disconnect_on
+            .push(command.to_uppercase());
+    }
+
+    /// Returns the number of accepted connections.
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+}
+
+impl Drop for SmtpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<State>>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut authenticated = false;
+    let mut from = String::new();
+    let mut recipients = Vec::new();
+    writer
+        .write_all(b"220 localhost ESMTP test server\r\n")
+        .await?;
+
+    while let Some(line) = lines.next_line().await? {
+        let (command, arg) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+        let command = command.to_uppercase();
+        {
+            let mut state = state.lock().unwrap();
+            if let Some(i) = state.disconnect_on.iter().position(|c| *c == command) {
+                state.disconnect_on.remove(i);
+                break;
+            }
+        }
+
+        let reply = match command.as_str() {
+            "EHLO" => {
+                "250-localhost\r\n250-8BITMIME\r\n250-SMTPUTF8\r\n250 AUTH PLAIN LOGIN".to_string()
+            }
+            "HELO" | "NOOP" => "250 2.0.0 Ok".to_string(),
+            "AUTH" => {
+                let mut args = arg.split_whitespace();
+                let mechanism = args.next().unwrap_or_default().to_uppercase();
+                let mut initial_response = args.next().map(|arg| arg.to_string());
+                let credentials = match mechanism.as_str() {
+                    "PLAIN" => {
+                        if initial_response.is_none() {
+                            writer.write_all(b"334 \r\n").await?;
+                            initial_response = lines.next_line().await?;
+                        }
+                        let decoded = decode(initial_response.as_deref().unwrap_or_default());
+                        let mut parts = decoded.split('\0').skip(1);
+                        parts
+                            .next()
+                            .zip(parts.next())
+                            .map(|(user, password)| (user.to_string(), password.to_string()))
+                    }
+                    "LOGIN" => {
+                        writer.write_all(b"334 VXNlcm5hbWU6\r\n").await?;
+                        let user = lines.next_line().await?.unwrap_or_default();
+                        writer.write_all(b"334 UGFzc3dvcmQ6\r\n").await?;
+                        let password = lines.next_line().await?.unwrap_or_default();
+                        Some((decode(&user), decode(&password)))
+                    }
+                    _ => None,
+                };
+                if credentials == Some((USER.to_string(), PASSWORD.to_string())) {
+                    authenticated = true;
+                    "235 2.7.0 Authentication successful".to_string()
+                } else {
+                    "535 5.7.8 Authentication credentials invalid".to_string()
+                }
+            }
+            "MAIL" if authenticated => {
+                from = address(arg);
+                recipients.clear();
+                "250 2.1.0 Ok".to_string()
+            }
+            "RCPT" if authenticated => {
+                recipients.push(address(arg));
+                "250 2.1.5 Ok".to_string()
+            }
+            "DATA" if authenticated && !recipients.is_empty() => {
+                writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await?;
+                let mut body = Vec::new();
+                while let Some(line) = lines.next_line().await? {
+                    if line == "." {
+                        break;
+                    }
+                    body.extend_from_slice(line.strip_prefix('.').unwrap_or(&line).as_bytes());
+                    body.extend_from_slice(b"\r\n");
+                }
+                state.lock().unwrap().mails.push(ReceivedMail {
+                    from: from.clone(),
+                    recipients: std::mem::take(&mut recipients),
+                    body,
+                });
+                "250 2.0.0 Ok: queued".to_string()
+            }
+            "MAIL" | "RCPT" | "DATA" => "503 5.5.1 Bad sequence of commands".to_string(),
+            "RSET" => {
+                recipients.clear();
+                "250 2.0.0 Ok".to_string()
+            }
+            "QUIT" => {
+                writer.write_all(b"221 2.0.0 Bye\r\n").await?;
+                break;
+            }
+            _ => "502 5.5.2 Command not recognized".to_string(),
+        };
+        writer.write_all(format!("{reply}\r\n").as_bytes()).await?;
+    }
+    Ok(())
+}
+
+fn decode(s: &str) -> String {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(s.trim())
+        .unwrap_or_default();
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Extracts the address from a `FROM:<addr>` or `TO:<addr>` argument.
+fn address(arg: &str) -> String {
+    arg.split_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map_or("", |(addr, _)| addr)
+        .to_string()
+}
@@ -1,2 +1,4 @@
 mod aeap;
+mod imap;
+mod smtp;
 mod verified_chats;
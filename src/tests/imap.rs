@@ -0,0 +1,506 @@
+//! Tests of IMAP fetching, moving and reconnecting
+//! against the [`ImapServer`] simulator.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use base64::Engine as _;
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::download::{download_msg, DownloadState};
+use crate::events::EventType;
+use crate::imap::{FolderMeaning, Imap, ImapActionError, ImapErrorKind};
+use crate::message::{self, Message, Viewtype};
+use crate::test_utils::imap_server::ImapServer;
+use crate::test_utils::TestContextManager;
+
+async fn new_imap(context: &Context) -> Result<Imap> {
+    let (_sender, receiver) = async_channel::bounded(1);
+    Imap::new_configured(context, receiver).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_fetch_after_disconnect() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let mut imap = new_imap(&bob).await?;
+
+    // The first SELECT only records UIDNEXT, existing messages are not fetched.
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+
+    let chat_id = alice.create_chat(&bob).await.id;
+    let sent = alice.send_text(chat_id, "Hello over IMAP").await;
+    server.add_message("INBOX", sent.payload().as_bytes());
+    server.disconnect_on("UID FETCH");
+    assert!(imap
+        .fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await
+        .is_err());
+
+    imap.trigger_reconnect(&bob);
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert_eq!(bob.get_last_msg().await.text, "Hello over IMAP");
+    assert_eq!(server.connections(), 2);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_move_to_mvbox() -> Result<()> {
+    for (capabilities, expected_command) in [(&["MOVE"][..], "UID MOVE"), (&[][..], "UID COPY")] {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        let server = ImapServer::new().await?;
+        server.set_capabilities(capabilities);
+        server.add_folder("DeltaChat", &[]);
+        server.configure(&bob).await?;
+        bob.set_config(Config::MvboxMove, Some("1")).await?;
+        bob.set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await?;
+        let mut imap = new_imap(&bob).await?;
+        imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+            .await?;
+        imap.fetch_move_delete(&bob, "DeltaChat", FolderMeaning::Mvbox)
+            .await?;
+
+        let chat_id = alice.create_chat(&bob).await.id;
+        let sent = alice.send_text(chat_id, "Moved").await;
+        server.add_message("INBOX", sent.payload().as_bytes());
+        imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+            .await?;
+        assert!(server.messages("INBOX").is_empty());
+        assert_eq!(server.messages("DeltaChat").len(), 1);
+        assert!(server
+            .commands()
+            .iter()
+            .any(|command| command == expected_command));
+
+        imap.fetch_move_delete(&bob, "DeltaChat", FolderMeaning::Mvbox)
+            .await?;
+        assert_eq!(bob.get_last_msg().await.text, "Moved");
+    }
+    Ok(())
+}
+
+/// Tests that a message moved back to the Inbox by another client is not moved again.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_move_loop() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.set_capabilities(&["MOVE"]);
+    server.add_folder("DeltaChat", &[]);
+    server.configure(&bob).await?;
+    bob.set_config(Config::MvboxMove, Some("1")).await?;
+    bob.set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+        .await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+
+    let chat_id = alice.create_chat(&bob).await.id;
+    let sent = alice.send_text(chat_id, "Moved").await;
+    server.add_message("INBOX", sent.payload().as_bytes());
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert!(server.messages("INBOX").is_empty());
+
+    // Another client moves the message back before it is fetched from the DeltaChat folder.
+    server.add_message("INBOX", sent.payload().as_bytes());
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert_eq!(server.messages("INBOX").len(), 1);
+    assert_eq!(server.messages("DeltaChat").len(), 1);
+    assert_eq!(bob.get_last_msg().await.text, "Moved");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_uid_validity_reset() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+
+    let chat_id = alice.create_chat(&bob).await.id;
+    let sent = alice.send_text(chat_id, "First").await;
+    server.add_message("INBOX", sent.payload().as_bytes());
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    let msg = bob.get_last_msg().await;
+    assert_eq!(msg.text, "First");
+
+    server.reset_uid_validity("INBOX");
+    imap.trigger_reconnect(&bob);
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    imap.resync_folder_uids(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    let row: Option<(u32, u32)> = bob
+        .sql
+        .query_row_optional(
+            "SELECT uid, uidvalidity FROM imap WHERE rfc724_mid=?",
+            (&msg.rfc724_mid,),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .await?;
+    assert_eq!(row, Some((1, 2)));
+
+    // Messages arriving after the reset are fetched, the old one is not fetched again.
+    let sent = alice.send_text(chat_id, "Second").await;
+    server.add_message("INBOX", sent.payload().as_bytes());
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert_eq!(bob.get_last_msg().await.text, "Second");
+    let msgs_cnt: usize = bob
+        .sql
+        .count(
+            "SELECT COUNT(*) FROM msgs WHERE rfc724_mid=?",
+            (&msg.rfc724_mid,),
+        )
+        .await?;
+    assert_eq!(msgs_cnt, 1);
+    Ok(())
+}
+
+/// Tests that a deletion still pending when the server resets UIDVALIDITY
+/// is not forgotten, but executed with the new UID after the resync.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_uid_validity_reset_pending_delete() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+
+    let chat_id = alice.create_chat(&bob).await.id;
+    let kept = alice.send_text(chat_id, "Kept").await;
+    server.add_message("INBOX", kept.payload().as_bytes());
+    let deleted = alice.send_text(chat_id, "Deleted").await;
+    server.add_message("INBOX", deleted.payload().as_bytes());
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    let msg = bob.get_last_msg().await;
+    assert_eq!(msg.text, "Deleted");
+
+    // The message is deleted locally, but the server resets UIDVALIDITY
+    // before the deletion is executed.
+    message::delete_msgs(&bob, &[msg.id]).await?;
+    server.reset_uid_validity("INBOX");
+    imap.trigger_reconnect(&bob);
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert_eq!(server.messages("INBOX").len(), 2);
+    let target: Option<String> = bob
+        .sql
+        .query_get_value(
+            "SELECT target FROM imap WHERE rfc724_mid=? AND uidvalidity=1",
+            (&msg.rfc724_mid,),
+        )
+        .await?;
+    assert_eq!(target.as_deref(), Some(""));
+    assert!(
+        !bob.sql
+            .exists(
+                "SELECT COUNT(*) FROM imap WHERE target=folder AND uidvalidity=1",
+                (),
+            )
+            .await?
+    );
+
+    // The resync re-associates the deletion with the new UID.
+    imap.resync_folder_uids(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    let server_msgs = server.messages("INBOX");
+    assert_eq!(server_msgs.len(), 1);
+    assert_eq!(server_msgs[0].body, kept.payload().as_bytes());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_imap_latency() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    server.set_latency(Duration::from_millis(100));
+    let mut imap = new_imap(&bob).await?;
+
+    // CAPABILITY and LOGIN are delayed.
+    let start = Instant::now();
+    imap.prepare(&bob).await?;
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_extra_folder() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    bob.set_extra_folder_watched("Lists", true).await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.prepare(&bob).await?;
+
+    // Additional folders are not created.
+    assert!(!imap.select_extra_folder(&bob, "Lists").await?);
+    assert!(!server.commands().iter().any(|command| command == "CREATE"));
+
+    server.add_folder("Lists", &[]);
+    assert!(imap.select_extra_folder(&bob, "Lists").await?);
+    imap.fetch_move_delete(&bob, "Lists", FolderMeaning::Unknown)
+        .await?;
+    let chat_id = alice.create_chat(&bob).await.id;
+    let sent = alice.send_text(chat_id, "Sorted by sieve").await;
+    server.add_message("Lists", sent.payload().as_bytes());
+    imap.fetch_move_delete(&bob, "Lists", FolderMeaning::Unknown)
+        .await?;
+    assert_eq!(bob.get_last_msg().await.text, "Sorted by sieve");
+    Ok(())
+}
+
+/// Tests that after a long offline period messages are downloaded over a second connection
+/// while the main connection prefetches them in chunks.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_fetch_many_msgs_over_second_connection() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert_eq!(server.connections(), 1);
+
+    let msg_cnt = 501;
+    for i in 0..msg_cnt {
+        let raw = format!(
+            "From: alice@example.org\r\n\
+             To: bob@example.net\r\n\
+             Subject: Message {i}\r\n\
+             Message-ID: <many{i}@example.org>\r\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\r\n\
+             Chat-Version: 1.0\r\n\
+             \r\n\
+             Message {i}\r\n"
+        );
+        server.add_message("INBOX", raw.as_bytes());
+    }
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert_eq!(server.connections(), 2);
+    assert!(server
+        .commands()
+        .iter()
+        .any(|command| command == "UID SEARCH"));
+    assert!(server.commands().iter().any(|command| command == "LOGOUT"));
+    for i in [0, msg_cnt - 1] {
+        assert!(
+            message::rfc724_mid_exists(&bob, &format!("many{i}@example.org"))
+                .await?
+                .is_some()
+        );
+    }
+    assert_eq!(
+        bob.get_last_msg().await.text,
+        format!("Message {}", msg_cnt - 1)
+    );
+    Ok(())
+}
+
+/// Tests that the mail history is prefetched in chunks
+/// and the progress is reported for each message.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_import_history_progress() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let msg_cnt = 3;
+    for i in 0..msg_cnt {
+        let raw = format!(
+            "From: alice@example.org\r\n\
+             To: bob@example.net\r\n\
+             Subject: Message {i}\r\n\
+             Message-ID: <history{i}@example.org>\r\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\r\n\
+             Chat-Version: 1.0\r\n\
+             \r\n\
+             Message {i}\r\n"
+        );
+        server.add_message("INBOX", raw.as_bytes());
+    }
+    bob.set_config(Config::ImportHistoryDays, Some("30"))
+        .await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.prepare(&bob).await?;
+    imap.fetch_new_messages(&bob, "INBOX", FolderMeaning::Inbox, true)
+        .await?;
+    assert!(server
+        .commands()
+        .iter()
+        .any(|command| command == "UID SEARCH"));
+    for fetched in 1..=msg_cnt {
+        let event = bob
+            .evtracker
+            .get_matching(|evt| matches!(evt, EventType::FetchProgress { .. }))
+            .await;
+        assert_eq!(
+            event,
+            EventType::FetchProgress {
+                fetched,
+                total: msg_cnt
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Tests that large messages are fetched in chunks
+/// and their attachments are decoded directly into blob files.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_fetch_large_msg() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+
+    let data: Vec<u8> = (0..4_000_000u32).map(|i| (i % 251) as u8).collect();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
+    let wrapped = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect::<Vec<_>>()
+        .join("\r\n");
+    let raw = format!(
+        "From: alice@example.org\r\n\
+         To: bob@example.net\r\n\
+         Subject: Large file\r\n\
+         Message-ID: <large@example.org>\r\n\
+         Date: Sun, 22 Mar 2020 22:37:57 +0000\r\n\
+         Chat-Version: 1.0\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+         \r\n\
+         --outer\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         \r\n\
+         Here is the file.\r\n\
+         --outer\r\n\
+         Content-Type: application/octet-stream; name=\"data.bin\"\r\n\
+         Content-Disposition: attachment; filename=\"data.bin\"\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {wrapped}\r\n\
+         --outer--\r\n"
+    );
+    server.add_message("INBOX", raw.as_bytes());
+    let fetch_count = || {
+        server
+            .commands()
+            .iter()
+            .filter(|command| *command == "UID FETCH")
+            .count()
+    };
+    let fetches_before = fetch_count();
+    imap.fetch_move_delete(&bob, "INBOX", FolderMeaning::Inbox)
+        .await?;
+    assert!(fetch_count() - fetches_before > raw.len() / (1024 * 1024));
+
+    let msg = bob.get_last_msg().await;
+    assert_eq!(msg.get_viewtype(), Viewtype::File);
+    assert_eq!(msg.get_filename().as_deref(), Some("data.bin"));
+    assert_eq!(tokio::fs::read(msg.get_file(&bob).unwrap()).await?, data);
+    Ok(())
+}
+
+/// Tests that a download is kept queued while the UID of the message is unknown
+/// and only fails if the message does not exist on the server.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_download_unknown_uid() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    let mut imap = new_imap(&bob).await?;
+
+    let chat_id = alice.create_chat(&bob).await.id;
+    let sent = alice.send_text(chat_id, "Partial").await;
+    let msg = bob.recv_msg(&sent).await;
+    msg.id
+        .update_download_state(&bob, DownloadState::Available)
+        .await?;
+    bob.sql
+        .execute(
+            "INSERT INTO imap (rfc724_mid, folder, uid, uidvalidity, target)
+             VALUES (?, 'INBOX', 0, 1, 'INBOX')",
+            (&msg.rfc724_mid,),
+        )
+        .await?;
+
+    let err = download_msg(&bob, msg.id, &mut imap).await.unwrap_err();
+    let err = err.downcast_ref::<ImapActionError>().unwrap();
+    assert_eq!(err.kind, ImapErrorKind::Pending);
+    let msg = Message::load_from_db(&bob, msg.id).await?;
+    assert_eq!(msg.download_state(), DownloadState::Available);
+
+    bob.sql
+        .execute(
+            "UPDATE imap SET uid=1 WHERE rfc724_mid=?",
+            (&msg.rfc724_mid,),
+        )
+        .await?;
+    assert!(download_msg(&bob, msg.id, &mut imap).await.is_err());
+    let msg = Message::load_from_db(&bob, msg.id).await?;
+    assert_eq!(msg.download_state(), DownloadState::Failure);
+    Ok(())
+}
+
+/// Tests that a device set up for an account already used by Delta Chat
+/// sends copies to self in `BccSelf::MultiDevice` mode.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_discover_other_devices() -> Result<()> {
+    let mut tcm = TestContextManager::new();
+    let alice = tcm.alice().await;
+    let bob = tcm.bob().await;
+    let server = ImapServer::new().await?;
+    server.configure(&bob).await?;
+    bob.set_config(Config::BccSelf, Some("2")).await?;
+    let mut imap = new_imap(&bob).await?;
+    imap.prepare(&bob).await?;
+
+    // The DeltaChat folder is created by this device.
+    imap.configure_folders(&bob, true).await?;
+    assert!(!bob.should_send_bcc_self().await?);
+
+    // Another device moved a message to the DeltaChat folder.
+    let chat_id = alice.create_chat(&bob).await.id;
+    let sent = alice.send_text(chat_id, "Moved by another device").await;
+    server.add_message("DeltaChat", sent.payload().as_bytes());
+    imap.configure_folders(&bob, true).await?;
+    assert!(bob.should_send_bcc_self().await?);
+    Ok(())
+}
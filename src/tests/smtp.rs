@@ -0,0 +1,38 @@
+//! Tests of SMTP sending and reconnecting against the [`SmtpServer`] simulator.
+
+use anyhow::Result;
+use async_smtp::EmailAddress;
+
+use crate::smtp::Smtp;
+use crate::test_utils::smtp_server::SmtpServer;
+use crate::test_utils::TestContext;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_after_disconnect() -> Result<()> {
+    let alice = TestContext::new_alice().await;
+    let server = SmtpServer::new().await?;
+    server.configure(&alice).await?;
+    let recipients = [EmailAddress::new("bob@example.net".to_string())?];
+    let mut smtp = Smtp::new();
+    smtp.connect_configured(&alice).await?;
+    smtp.send(&alice, &recipients, b"Subject: First\r\n\r\nHello\r\n")
+        .await?;
+    let mails = server.mails();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].from, "alice@example.org");
+    assert_eq!(mails[0].recipients, vec!["bob@example.net".to_string()]);
+    assert!(String::from_utf8_lossy(&mails[0].body).contains("Subject: First"));
+
+    server.disconnect_on("DATA");
+    assert!(smtp
+        .send(&alice, &recipients, b"Subject: Second\r\n\r\nHello\r\n")
+        .await
+        .is_err());
+    smtp.disconnect();
+    smtp.connect_configured(&alice).await?;
+    smtp.send(&alice, &recipients, b"Subject: Second\r\n\r\nHello\r\n")
+        .await?;
+    assert_eq!(server.mails().len(), 2);
+    assert_eq!(server.connections(), 2);
+    Ok(())
+}
@@ -5,7 +5,7 @@
 
 use std::borrow::Cow;
 use std::fmt;
-use std::io::{Cursor, Write};
+use std::io::{BufRead, Cursor, Read, Seek, Write};
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::str::from_utf8;
@@ -15,6 +15,9 @@
 use base64::Engine as _;
 use chrono::{Local, NaiveDateTime, NaiveTime, TimeZone};
 use futures::{StreamExt, TryStreamExt};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, ImageFormat};
 use mailparse::dateparse;
 use mailparse::headers::Headers;
 use mailparse::MailHeaderMap;
@@ -319,6 +322,45 @@ pub fn get_filemeta(buf: &[u8]) -> Result<(u32, u32)> {
     Ok(dimensions)
 }
 
+/// Returns the dimensions of the image file at `path`.
+///
+/// Unlike [`get_filemeta`], only the beginning of the file is read.
+pub(crate) fn get_filemeta_from_path(path: &Path) -> Result<(u32, u32)> {
+    let image = image::io::Reader::open(path)?.with_guessed_format()?;
+    let dimensions = image.into_dimensions()?;
+    Ok(dimensions)
+}
+
+/// Returns true if the given image has more than one frame,
+/// i.e. it is an animated GIF, an APNG or an animated WebP.
+///
+/// Only the first frames of GIFs and the headers of other formats are read.
+pub(crate) fn is_animated_image<R: BufRead + Seek>(mut reader: R) -> Result<bool> {
+    let format = image::io::Reader::new(&mut reader)
+        .with_guessed_format()?
+        .format();
+    reader.rewind()?;
+    let animated = match format {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader)?.into_frames().take(2).count() > 1,
+        Some(ImageFormat::Png) => PngDecoder::new(reader)?.is_apng(),
+        Some(ImageFormat::WebP) => {
+            // Extended WebP files start with a `VP8X` chunk containing the animation flag,
+            // see <https://developers.google.com/speed/webp/docs/riff_container>.
+            let mut header = [0u8; 21];
+            reader.read_exact(&mut header)?;
+            header.get(12..16) == Some(b"VP8X".as_slice())
+                && header.get(20).map_or(false, |flags| flags & 0x02 != 0)
+        }
+        _ => false,
+    };
+    Ok(animated)
+}
+
+/// Returns true if the image file at `path` is animated, see [`is_animated_image`].
+pub(crate) fn is_animated_image_from_path(path: &Path) -> Result<bool> {
+    is_animated_image(std::io::BufReader::new(std::fs::File::open(path)?))
+}
+
 /// Expand paths relative to $BLOBDIR into absolute paths.
 ///
 /// If `path` starts with "$BLOBDIR", replaces it with the blobdir path.
@@ -1283,4 +1325,23 @@ fn test_remove_subject_prefix() {
         assert_eq!(remove_subject_prefix("Fwd: Subject"), "Subject");
         assert_eq!(remove_subject_prefix("Fw: Subject"), "Subject");
     }
+
+    #[test]
+    fn test_is_animated_image() -> Result<()> {
+        let gif = include_bytes!("../test-data/image/image100x50.gif");
+        assert!(!is_animated_image(Cursor::new(gif))?);
+        let png = include_bytes!("../test-data/image/logo.png");
+        assert!(!is_animated_image(Cursor::new(png))?);
+        let jpg = include_bytes!("../test-data/image/avatar1000x1000.jpg");
+        assert!(!is_animated_image(Cursor::new(jpg))?);
+
+        let mut animated_gif = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut animated_gif);
+            let frame = || image::Frame::new(image::RgbaImage::new(2, 2));
+            encoder.encode_frames(vec![frame(), frame()])?;
+        }
+        assert!(is_animated_image(Cursor::new(&animated_gif))?);
+        Ok(())
+    }
 }
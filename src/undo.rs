@@ -0,0 +1,394 @@
+//! # Undo window for destructive operations.
+//!
+//! If [`Config::UndoTimeout`] is set, deleting messages and chats
+//! and removing group members is not executed at once.
+//! Instead the operation is queued and can be reversed with [`undo`]
+//! until the timeout expires; only then it is propagated to IMAP and SMTP.
+//!
+//! Deleted messages are moved to the trash chat without removing their content,
+//! they are recorded in the `undo_msgs` table so that they are not pruned
+//! and their unsent SMTP jobs are not sent until the timeout expires.
+//! Deleted chats are hidden from the chatlist while their deletion is pending;
+//! a new message arriving in such a chat cancels the deletion.
+//! Removing a group member takes effect when the timeout expires.
+//! Expired operations are executed by the ephemeral loop.
+
+use std::collections::BTreeSet;
+
+use anyhow::{Context as _, Result};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::{self, ChatId};
+use crate::config::Config;
+use crate::constants::DC_CHAT_ID_TRASH;
+use crate::contact::ContactId;
+use crate::context::Context;
+use crate::events::EventType;
+use crate::log::LogExt;
+use crate::message::{self, Message, MsgId};
+use crate::scheduler::InterruptInfo;
+use crate::tools::time;
+
+/// Operation waiting in the undo queue.
+#[derive(Debug, Serialize, Deserialize)]
+enum PendingAction {
+    /// Messages to delete together with the chats they were in.
+    DeleteMsgs(Vec<(MsgId, ChatId)>),
+
+    /// Chat to delete.
+    DeleteChat(ChatId),
+
+    /// Group member to remove.
+    RemoveMember(ChatId, ContactId),
+}
+
+/// Returns the time until which an operation started now can be undone,
+/// `None` if undoing is disabled.
+async fn undo_deadline(context: &Context) -> Result<Option<i64>> {
+    let timeout = context.get_config_i64(Config::UndoTimeout).await?;
+    Ok(Some(time().saturating_add(timeout)).filter(|_| timeout > 0))
+}
+
+/// Queues deletion of messages if undoing is enabled and hides the messages.
+///
+/// Returns false if the messages should be deleted at once.
+pub(crate) async fn defer_delete_msgs(context: &Context, msg_ids: &[MsgId]) -> Result<bool> {
+    let Some(deadline) = undo_deadline(context).await? else {
+        return Ok(false);
+    };
+    let mut msgs = Vec::new();
+    for &msg_id in msg_ids {
+        let msg = Message::load_from_db(context, msg_id).await?;
+        if !msg.chat_id.is_trash() {
+            msgs.push((msg_id, msg.chat_id));
+        }
+    }
+    let action = serde_json::to_string(&PendingAction::DeleteMsgs(msgs.clone()))?;
+    context
+        .sql
+        .transaction(move |transaction| {
+            transaction.execute(
+                "INSERT INTO undo_queue (action, deadline) VALUES (?, ?)",
+                (action, deadline),
+            )?;
+            let undo_id = transaction.last_insert_rowid();
+            for (msg_id, _) in &msgs {
+                transaction.execute(
+                    "UPDATE msgs SET chat_id=? WHERE id=?",
+                    (DC_CHAT_ID_TRASH, msg_id),
+                )?;
+                transaction.execute(
+                    "INSERT INTO undo_msgs (undo_id, msg_id) VALUES (?, ?)",
+                    (undo_id, msg_id),
+                )?;
+            }
+            Ok(())
+        })
+        .await?;
+
+    let mut modified_chat_ids = BTreeSet::new();
+    for (msg_id, chat_id) in msgs {
+        context.emit_event(EventType::MsgDeleted { chat_id, msg_id });
+        modified_chat_ids.insert(chat_id);
+    }
+    for chat_id in modified_chat_ids {
+        context.emit_msgs_changed(chat_id, MsgId::new(0));
+    }
+    context.scheduler.interrupt_ephemeral_task().await;
+    Ok(true)
+}
+
+/// Queues deletion of a chat if undoing is enabled and hides the chat.
+///
+/// Returns false if the chat should be deleted at once.
+pub(crate) async fn defer_delete_chat(context: &Context, chat_id: ChatId) -> Result<bool> {
+    let Some(deadline) = undo_deadline(context).await? else {
+        return Ok(false);
+    };
+    let action = serde_json::to_string(&PendingAction::DeleteChat(chat_id))?;
+    context
+        .sql
+        .execute(
+            "INSERT INTO undo_queue (action, deadline, chat_id) VALUES (?, ?, ?)",
+            (action, deadline, chat_id),
+        )
+        .await?;
+    context.emit_msgs_changed_without_ids();
+    context.scheduler.interrupt_ephemeral_task().await;
+    Ok(true)
+}
+
+/// Queues removal of a group member if undoing is enabled.
+///
+/// Returns false if the member should be removed at once.
+pub(crate) async fn defer_remove_member(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: ContactId,
+) -> Result<bool> {
+    let Some(deadline) = undo_deadline(context).await? else {
+        return Ok(false);
+    };
+    let action = serde_json::to_string(&PendingAction::RemoveMember(chat_id, contact_id))?;
+    context
+        .sql
+        .execute(
+            "INSERT INTO undo_queue (action, deadline) VALUES (?, ?)",
+            (action, deadline),
+        )
+        .await?;
+    context.scheduler.interrupt_ephemeral_task().await;
+    Ok(true)
+}
+
+/// Removes the latest operation matching the condition from the queue and returns it.
+async fn pop_action(
+    context: &Context,
+    condition: &'static str,
+    now: i64,
+) -> Result<Option<PendingAction>> {
+    let action = context
+        .sql
+        .transaction(move |transaction| {
+            let row = transaction
+                .query_row(
+                    &format!("SELECT id, action FROM undo_queue WHERE {condition} LIMIT 1"),
+                    (now,),
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            let Some((id, action)) = row else {
+                return Ok(None);
+            };
+            transaction.execute("DELETE FROM undo_queue WHERE id=?", (id,))?;
+            transaction.execute("DELETE FROM undo_msgs WHERE undo_id=?", (id,))?;
+            Ok(Some(action))
+        })
+        .await?;
+    match action {
+        Some(action) => Ok(Some(
+            serde_json::from_str(&action).context("Failed to parse pending operation")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Reverses the latest deletion of messages or chats or removal of a group member
+/// that is still pending, see [`Config::UndoTimeout`].
+///
+/// Returns false if there is no operation that can be undone.
+pub async fn undo(context: &Context) -> Result<bool> {
+    let Some(action) = pop_action(context, "deadline>? ORDER BY id DESC", time()).await? else {
+        return Ok(false);
+    };
+    match action {
+        PendingAction::DeleteMsgs(msgs) => {
+            let restored = msgs.clone();
+            context
+                .sql
+                .transaction(move |transaction| {
+                    for (msg_id, chat_id) in &restored {
+                        transaction.execute(
+                            "UPDATE msgs SET chat_id=? WHERE id=? AND chat_id=?",
+                            (chat_id, msg_id, DC_CHAT_ID_TRASH),
+                        )?;
+                    }
+                    Ok(())
+                })
+                .await?;
+            context.invalidate_fresh_msg_count();
+            for (msg_id, chat_id) in msgs {
+                context.emit_msgs_changed(chat_id, msg_id);
+            }
+            context
+                .scheduler
+                .interrupt_smtp(InterruptInfo::new(false))
+                .await;
+        }
+        PendingAction::DeleteChat(_) => {
+            context.emit_msgs_changed_without_ids();
+        }
+        PendingAction::RemoveMember(chat_id, _) => {
+            context.emit_event(EventType::ChatModified(chat_id));
+        }
+    }
+    Ok(true)
+}
+
+/// Cancels a pending deletion of the chat, called when a new message arrives in it.
+pub(crate) async fn cancel_chat_deletion(context: &Context, chat_id: ChatId) -> Result<()> {
+    let cancelled = context
+        .sql
+        .execute("DELETE FROM undo_queue WHERE chat_id=?", (chat_id,))
+        .await?;
+    if cancelled > 0 {
+        info!(
+            context,
+            "New message in {chat_id}, cancelling its deletion."
+        );
+    }
+    Ok(())
+}
+
+/// Returns the time at which the next pending operation expires.
+pub(crate) async fn next_deadline(context: &Context) -> Result<Option<i64>> {
+    context
+        .sql
+        .query_get_value("SELECT MIN(deadline) FROM undo_queue", ())
+        .await
+}
+
+/// Executes pending operations that can no longer be undone.
+pub(crate) async fn execute_expired(context: &Context, now: i64) -> Result<()> {
+    while let Some(action) = pop_action(context, "deadline<=? ORDER BY id", now).await? {
+        let res = match action {
+            PendingAction::DeleteMsgs(msgs) => {
+                let msg_ids: Vec<MsgId> = msgs.into_iter().map(|(msg_id, _)| msg_id).collect();
+                message::delete_msgs_ex(context, &msg_ids, false).await
+            }
+            PendingAction::DeleteChat(chat_id) => chat_id.delete_ex(context, false).await,
+            PendingAction::RemoveMember(chat_id, contact_id) => {
+                chat::remove_contact_from_chat_ex(context, chat_id, contact_id, false).await
+            }
+        };
+        res.context("Failed to execute pending operation")
+            .log_err(context)
+            .ok();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{
+        add_contact_to_chat, create_group_chat, get_chat_contacts, Chat, ProtectionStatus,
+    };
+    use crate::chatlist::Chatlist;
+    use crate::test_utils::TestContextManager;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_undo_delete_msgs() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        alice.set_config(Config::UndoTimeout, Some("60")).await?;
+        let chat_id = alice.create_chat(&bob).await.id;
+        let sent = alice.send_text(chat_id, "hi").await;
+        let msg_id = sent.sender_msg_id;
+
+        message::delete_msgs(&alice, &[msg_id]).await?;
+        assert!(Message::load_from_db(&alice, msg_id)
+            .await?
+            .chat_id
+            .is_trash());
+        let undo_msgs_cnt = || async {
+            alice
+                .sql
+                .count("SELECT COUNT(*) FROM undo_msgs WHERE msg_id=?", (msg_id,))
+                .await
+        };
+        assert_eq!(undo_msgs_cnt().await?, 1);
+
+        assert!(undo(&alice).await?);
+        let msg = Message::load_from_db(&alice, msg_id).await?;
+        assert_eq!(msg.chat_id, chat_id);
+        assert_eq!(msg.get_text(), "hi");
+        assert_eq!(undo_msgs_cnt().await?, 0);
+        // The retry state of the SMTP job is kept.
+        let next_try: i64 = alice
+            .sql
+            .query_get_value("SELECT next_try FROM smtp WHERE msg_id=?", (msg_id,))
+            .await?
+            .unwrap();
+        assert_eq!(next_try, 0);
+        assert!(!undo(&alice).await?);
+
+        // Without undo the deletion is executed when the timeout expires.
+        message::delete_msgs(&alice, &[msg_id]).await?;
+        execute_expired(&alice, time()).await?;
+        assert!(Message::load_from_db(&alice, msg_id)
+            .await?
+            .chat_id
+            .is_trash());
+        execute_expired(&alice, time() + 60).await?;
+        assert_eq!(next_deadline(&alice).await?, None);
+        let msg = Message::load_from_db(&alice, msg_id).await?;
+        assert!(msg.chat_id.is_trash());
+        assert_eq!(msg.get_text(), "");
+        assert_eq!(
+            alice
+                .sql
+                .count("SELECT COUNT(*) FROM smtp WHERE msg_id=?", (msg_id,))
+                .await?,
+            0
+        );
+        assert!(!undo(&alice).await?);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_undo_delete_chat_and_remove_member() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        alice.set_config(Config::UndoTimeout, Some("60")).await?;
+        let chat_id = create_group_chat(&alice, ProtectionStatus::Unprotected, "Group").await?;
+        let bob_id = alice.add_or_lookup_contact(&bob).await.id;
+        add_contact_to_chat(&alice, chat_id, bob_id).await?;
+
+        chat_id.delete(&alice).await?;
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 0);
+        assert!(undo(&alice).await?);
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 1);
+
+        chat::remove_contact_from_chat(&alice, chat_id, bob_id).await?;
+        assert!(get_chat_contacts(&alice, chat_id).await?.contains(&bob_id));
+        assert!(undo(&alice).await?);
+        execute_expired(&alice, time() + 60).await?;
+        assert!(get_chat_contacts(&alice, chat_id).await?.contains(&bob_id));
+
+        // Operations are undone in reverse order.
+        chat::remove_contact_from_chat(&alice, chat_id, bob_id).await?;
+        chat_id.delete(&alice).await?;
+        assert!(undo(&alice).await?);
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 1);
+        execute_expired(&alice, time() + 60).await?;
+        assert!(!get_chat_contacts(&alice, chat_id).await?.contains(&bob_id));
+        assert!(!undo(&alice).await?);
+
+        chat_id.delete(&alice).await?;
+        execute_expired(&alice, time() + 60).await?;
+        assert!(Chat::load_from_db(&alice, chat_id).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_new_msg_cancels_chat_deletion() -> Result<()> {
+        let mut tcm = TestContextManager::new();
+        let alice = tcm.alice().await;
+        let bob = tcm.bob().await;
+        alice.set_config(Config::UndoTimeout, Some("60")).await?;
+        let chat_id = alice.create_chat(&bob).await.id;
+        alice.send_text(chat_id, "Hi").await;
+
+        chat_id.delete(&alice).await?;
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 0);
+        let sent = bob
+            .send_text(bob.create_chat(&alice).await.id, "Hello")
+            .await;
+        let msg = alice.recv_msg(&sent).await;
+        assert_eq!(msg.chat_id, chat_id);
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 1);
+
+        execute_expired(&alice, time() + 60).await?;
+        assert!(Chat::load_from_db(&alice, chat_id).await.is_ok());
+        assert_eq!(
+            Message::load_from_db(&alice, msg.id).await?.get_text(),
+            "Hello"
+        );
+        assert!(!undo(&alice).await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! # Webhook notifications.
+//!
+//! If [`Config::WebhookUrl`] is set, selected events are sent
+//! as a JSON `POST` request to this URL.
+//! The body is signed with HMAC-SHA256 using [`Config::WebhookSecret`] as the key,
+//! the signature is sent hex-encoded in the `X-Deltachat-Signature` header as `sha256=<hex>`.
+//! Failed requests are retried with exponential backoff.
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::{ensure, Context as _, Result};
+use async_channel::{self as channel, Receiver, Sender};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::task;
+
+use crate::config::Config;
+use crate::context::{Context, InnerContext};
+use crate::events::EventType;
+use crate::net::ensure_online;
+use crate::net::http::get_client;
+use crate::socks::Socks5Config;
+use crate::tools::time;
+
+/// Maximum number of attempts to deliver a single notification.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled for each further retry.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Event types which can be sent to a webhook.
+#[derive(Debug, Display, EnumString, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A message was received, see [`EventType::IncomingMsg`].
+    IncomingMsg,
+
+    /// Secure-join protocol finished successfully.
+    SecurejoinDone,
+
+    /// Configuration finished, successfully or not.
+    ConfigureDone,
+}
+
+impl WebhookEvent {
+    /// Parses a comma-separated list of webhook event types.
+    pub(crate) fn parse_list(s: &str) -> Result<Vec<WebhookEvent>> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                name.parse()
+                    .with_context(|| format!("Unknown webhook event {name:?}"))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Webhook {
+    /// Event types to send.
+    events: Vec<WebhookEvent>,
+
+    /// Channel to the background task sending the notifications.
+    sender: Sender<String>,
+
+    /// Handle to the background task.
+    _loop_handle: task::JoinHandle<()>,
+}
+
+impl Webhook {
+    /// Queues a notification if the event is one of the selected types.
+    pub(crate) fn notify(&self, account_id: u32, event: &EventType) {
+        if let Some((kind, data)) = webhook_payload(event) {
+            if self.events.contains(&kind) {
+                let body = json!({
+                    "event": kind.to_string(),
+                    "account_id": account_id,
+                    "timestamp": time(),
+                    "data": data,
+                });
+                self.sender.try_send(body.to_string()).ok();
+            }
+        }
+    }
+}
+
+/// Returns the webhook event type and payload data for an event,
+/// `None` if the event is never sent to webhooks.
+fn webhook_payload(event: &EventType) -> Option<(WebhookEvent, serde_json::Value)> {
+    match event {
+        EventType::IncomingMsg { chat_id, msg_id } => Some((
+            WebhookEvent::IncomingMsg,
+            json!({ "chat_id": chat_id.to_u32(), "msg_id": msg_id.to_u32() }),
+        )),
+        EventType::SecurejoinInviterProgress {
+            contact_id,
+            progress: 1000,
+        }
+        | EventType::SecurejoinJoinerProgress {
+            contact_id,
+            progress: 1000,
+        } => Some((
+            WebhookEvent::SecurejoinDone,
+            json!({ "contact_id": contact_id.to_u32() }),
+        )),
+        EventType::ConfigureProgress {
+            progress: progress @ (0 | 1000),
+            comment,
+            ..
+        } => Some((
+            WebhookEvent::ConfigureDone,
+            json!({ "success": *progress == 1000, "comment": comment }),
+        )),
+        _ => None,
+    }
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of `body` using `secret` as the key.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Sends a single notification, retrying on network errors,
+/// server errors and rate limiting.
+///
+/// Notifications are dropped while the account is offline, see [`Config::Offline`].
+async fn send_notification(context: &Context, url: &str, secret: &str, body: String) -> Result<()> {
+    let signature = format!("sha256={}", sign(secret, body.as_bytes()));
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        ensure_online(context).await?;
+        let socks5_config = Socks5Config::from_context(context).await?;
+        let res = get_client(socks5_config)?
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Deltachat-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        let retry = match res {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                let status = response.status();
+                ensure!(
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+                    "Webhook returned {status}"
+                );
+                format!("Webhook returned {status}")
+            }
+            Err(err) => format!("{err:#}"),
+        };
+        ensure!(
+            attempt < MAX_ATTEMPTS,
+            "Giving up after {attempt} attempts: {retry}"
+        );
+        info!(
+            context,
+            "Webhook attempt {attempt} failed, retrying in {}s: {retry}.",
+            delay.as_secs()
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Sends all notifications put into the channel, one after another.
+///
+/// The loop only holds a weak reference to the context,
+/// so it does not keep the context alive and exits once the context is dropped.
+async fn webhook_loop(
+    context: Weak<InnerContext>,
+    url: String,
+    secret: String,
+    receiver: Receiver<String>,
+) {
+    while let Ok(body) = receiver.recv().await {
+        let Some(inner) = context.upgrade() else {
+            break;
+        };
+        let context = Context { inner };
+        if let Err(err) = send_notification(&context, &url, &secret, body).await {
+            warn!(context, "Failed to send webhook notification: {err:#}.");
+        }
+    }
+}
+
+/// (Re)starts the webhook according to the current configuration,
+/// stops it if [`Config::WebhookUrl`] is not set.
+pub(crate) async fn update_webhook(context: &Context) -> Result<()> {
+    let webhook = match context.get_config(Config::WebhookUrl).await? {
+        Some(url) if !url.is_empty() => {
+            let secret = context
+                .get_config(Config::WebhookSecret)
+                .await?
+                .unwrap_or_default();
+            ensure!(!secret.is_empty(), "Webhook secret is not set");
+            let events = WebhookEvent::parse_list(
+                &context
+                    .get_config(Config::WebhookEvents)
+                    .await?
+                    .unwrap_or_default(),
+            )?;
+            let (sender, receiver) = channel::bounded(1000);
+            let loop_handle = task::spawn(webhook_loop(
+                Arc::downgrade(&context.inner),
+                url,
+                secret,
+                receiver,
+            ));
+            Some(Webhook {
+                events,
+                sender,
+                _loop_handle: loop_handle,
+            })
+        }
+        _ => None,
+    };
+    // Dropping the old sender lets the old loop finish queued notifications and exit.
+    *context.webhook.write().expect("RwLock is poisoned") = webhook;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::ChatId;
+    use crate::contact::ContactId;
+    use crate::message::MsgId;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_sign() {
+        // Test case 2 of RFC 4231.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let (kind, data) = webhook_payload(&EventType::IncomingMsg {
+            chat_id: ChatId::new(10),
+            msg_id: MsgId::new(11),
+        })
+        .unwrap();
+        assert_eq!(kind, WebhookEvent::IncomingMsg);
+        assert_eq!(data, json!({"chat_id": 10, "msg_id": 11}));
+
+        assert!(webhook_payload(&EventType::SecurejoinJoinerProgress {
+            contact_id: ContactId::new(12),
+            progress: 400,
+        })
+        .is_none());
+        let (kind, _) = webhook_payload(&EventType::SecurejoinJoinerProgress {
+            contact_id: ContactId::new(12),
+            progress: 1000,
+        })
+        .unwrap();
+        assert_eq!(kind, WebhookEvent::SecurejoinDone);
+
+        let (kind, data) = webhook_payload(&EventType::ConfigureProgress {
+            progress: 0,
+            comment: Some("wrong password".to_string()),
+            step: None,
+        })
+        .unwrap();
+        assert_eq!(kind, WebhookEvent::ConfigureDone);
+        assert_eq!(data, json!({"success": false, "comment": "wrong password"}));
+        assert!(webhook_payload(&EventType::Info("hi".to_string())).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_webhook_config() -> Result<()> {
+        let t = TestContext::new().await;
+        assert!(t.webhook.read().unwrap().is_none());
+
+        assert!(t
+            .set_config(Config::WebhookUrl, Some("http://example.org/hook"))
+            .await
+            .is_err());
+        assert!(t
+            .set_config(Config::WebhookEvents, Some("incoming_msg,foo"))
+            .await
+            .is_err());
+
+        t.set_config(Config::WebhookEvents, Some("incoming_msg"))
+            .await?;
+        assert!(t
+            .set_config(Config::WebhookUrl, Some("https://example.org/hook"))
+            .await
+            .is_err());
+        assert!(t.webhook.read().unwrap().is_none());
+        t.set_config(Config::WebhookSecret, Some("secret")).await?;
+        t.set_config(Config::WebhookUrl, Some("https://example.org/hook"))
+            .await?;
+        assert!(t.set_config(Config::WebhookSecret, Some("")).await.is_err());
+        assert_eq!(
+            t.webhook.read().unwrap().as_ref().unwrap().events,
+            vec![WebhookEvent::IncomingMsg]
+        );
+
+        t.set_config(Config::WebhookUrl, None).await?;
+        assert!(t.webhook.read().unwrap().is_none());
+        t.set_config(Config::WebhookSecret, None).await?;
+        Ok(())
+    }
+}